@@ -0,0 +1,265 @@
+use std::fmt::Write;
+use std::time::Duration;
+
+use crate::report::{csv_field, json_str, QUANTILES};
+use crate::{Format, Report};
+
+/// The totals of several reports side by side, e.g. of the same workload run
+/// against different implementations. Every report can come with metrics
+/// that latencies don't capture, like memory use, which get a column each.
+#[derive(Debug, Clone)]
+pub struct Comparison {
+    title: String,
+    entries: Vec<Entry>,
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    report: Report,
+    metrics: Vec<(String, u64)>,
+}
+
+impl Comparison {
+    pub fn new(title: impl Into<String>) -> Self {
+        Self {
+            title: title.into(),
+            entries: vec![],
+        }
+    }
+
+    /// Adds a row per operation of `report`, which is labeled by its title.
+    pub fn add(&mut self, report: Report, metrics: &[(&str, u64)]) {
+        let metrics = metrics
+            .iter()
+            .map(|(name, v)| (name.to_string(), *v))
+            .collect();
+        self.entries.push(Entry { report, metrics });
+    }
+
+    // Every metric name, in the order they first appear.
+    fn metric_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = vec![];
+        for e in &self.entries {
+            for (name, _) in &e.metrics {
+                if !names.contains(&name.as_str()) {
+                    names.push(name);
+                }
+            }
+        }
+        names
+    }
+
+    pub fn render(&self, format: Format) -> String {
+        match format {
+            Format::Text => self.render_text(),
+            Format::Csv => self.render_csv(),
+            Format::Json => self.render_json(),
+        }
+    }
+
+    fn render_text(&self) -> String {
+        let names = self.metric_names();
+        let mut out = String::new();
+        writeln!(out, "{}", self.title).unwrap();
+
+        let mut header = format!("{:<32} {:<8} {:>10} {:>14}", "run", "op", "count", "ops/s");
+        for (name, _) in QUANTILES {
+            write!(header, " {:>10}", name).unwrap();
+        }
+        write!(header, " {:>10}", "max").unwrap();
+        for name in &names {
+            write!(header, " {:>14}", name).unwrap();
+        }
+        writeln!(out, "{}", header).unwrap();
+
+        for e in &self.entries {
+            for (op, hist) in e.report.totals() {
+                write!(
+                    out,
+                    "{:<32} {:<8} {:>10} {:>14.1}",
+                    e.report.title(),
+                    op,
+                    hist.count(),
+                    e.report.throughput(&hist)
+                )
+                .unwrap();
+                for (_, q) in QUANTILES {
+                    let d = Duration::from_nanos(hist.value_at_quantile(q));
+                    write!(out, " {:>10}", format!("{:?}", d)).unwrap();
+                }
+                write!(
+                    out,
+                    " {:>10}",
+                    format!("{:?}", Duration::from_nanos(hist.max()))
+                )
+                .unwrap();
+                for name in &names {
+                    match e.metric(name) {
+                        Some(v) => write!(out, " {:>14}", v).unwrap(),
+                        None => write!(out, " {:>14}", "-").unwrap(),
+                    }
+                }
+                out.push('\n');
+            }
+        }
+        out
+    }
+
+    fn render_csv(&self) -> String {
+        let names = self.metric_names();
+        let mut out = String::from("run,op,count,ops_per_sec");
+        for (name, _) in QUANTILES {
+            write!(out, ",{}_ns", name).unwrap();
+        }
+        out.push_str(",max_ns");
+        for name in &names {
+            write!(out, ",{}", csv_field(name)).unwrap();
+        }
+        out.push('\n');
+
+        for e in &self.entries {
+            for (op, hist) in e.report.totals() {
+                write!(
+                    out,
+                    "{},{},{},{:.1}",
+                    csv_field(e.report.title()),
+                    csv_field(op),
+                    hist.count(),
+                    e.report.throughput(&hist)
+                )
+                .unwrap();
+                for (_, q) in QUANTILES {
+                    write!(out, ",{}", hist.value_at_quantile(q)).unwrap();
+                }
+                write!(out, ",{}", hist.max()).unwrap();
+                // Missing metrics are left empty.
+                for name in &names {
+                    out.push(',');
+                    if let Some(v) = e.metric(name) {
+                        write!(out, "{}", v).unwrap();
+                    }
+                }
+                out.push('\n');
+            }
+        }
+        out
+    }
+
+    fn render_json(&self) -> String {
+        let runs: Vec<String> = self
+            .entries
+            .iter()
+            .map(|e| {
+                let metrics: Vec<String> = e
+                    .metrics
+                    .iter()
+                    .map(|(name, v)| format!("{}:{}", json_str(name), v))
+                    .collect();
+                let totals: Vec<String> = e
+                    .report
+                    .totals()
+                    .iter()
+                    .map(|(op, hist)| {
+                        let mut s = format!(
+                            "{{\"op\":{},\"count\":{},\"ops_per_sec\":{:.1}",
+                            json_str(op),
+                            hist.count(),
+                            e.report.throughput(hist)
+                        );
+                        for (name, q) in QUANTILES {
+                            write!(s, ",\"{}_ns\":{}", name, hist.value_at_quantile(q)).unwrap();
+                        }
+                        write!(s, ",\"max_ns\":{}}}", hist.max()).unwrap();
+                        s
+                    })
+                    .collect();
+                format!(
+                    "{{\"run\":{},\"elapsed_ns\":{},\"metrics\":{{{}}},\"totals\":[{}]}}",
+                    json_str(e.report.title()),
+                    e.report.elapsed().as_nanos(),
+                    metrics.join(","),
+                    totals.join(",")
+                )
+            })
+            .collect();
+
+        format!(
+            "{{\"title\":{},\"runs\":[{}]}}\n",
+            json_str(&self.title),
+            runs.join(",")
+        )
+    }
+}
+
+impl Entry {
+    fn metric(&self, name: &str) -> Option<u64> {
+        self.metrics
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| *v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Recorder;
+
+    fn report(title: &str, nanos: u64) -> Report {
+        let mut r = Recorder::new("t");
+        for _ in 0..10 {
+            r.record("push", Duration::from_nanos(nanos));
+            r.record("pop", Duration::from_nanos(nanos * 2));
+        }
+        Report::new(title, Duration::from_secs(1), vec![r])
+    }
+
+    fn sample() -> Comparison {
+        let mut cmp = Comparison::new("stacks");
+        cmp.add(report("epoch", 10), &[("peak_bytes", 640)]);
+        cmp.add(
+            report("seize, batched", 20),
+            &[("end_bytes", 0), ("peak_bytes", 128)],
+        );
+        cmp
+    }
+
+    #[test]
+    fn text_has_a_row_per_report_and_op() {
+        let text = sample().render(Format::Text);
+        let lines: Vec<&str> = text.lines().collect();
+
+        assert_eq!(lines[0], "stacks");
+        assert!(lines[1].ends_with("peak_bytes      end_bytes"));
+        assert_eq!(lines.len(), 2 + 4);
+        assert!(lines[2].starts_with("epoch"));
+        assert!(lines[2].ends_with("640              -"));
+        assert!(lines[5].starts_with("seize, batched"));
+    }
+
+    #[test]
+    fn csv_leaves_missing_metrics_empty() {
+        let csv = sample().render(Format::Csv);
+        let lines: Vec<&str> = csv.lines().collect();
+
+        assert_eq!(
+            lines[0],
+            "run,op,count,ops_per_sec,p50_ns,p90_ns,p99_ns,p999_ns,max_ns,peak_bytes,end_bytes"
+        );
+        assert_eq!(lines[1], "epoch,pop,10,10.0,20,20,20,20,20,640,");
+        assert_eq!(
+            lines[4],
+            "\"seize, batched\",push,10,10.0,20,20,20,20,20,128,0"
+        );
+    }
+
+    #[test]
+    fn json_structure() {
+        let json = sample().render(Format::Json);
+
+        assert!(json.starts_with(
+            "{\"title\":\"stacks\",\"runs\":[{\"run\":\"epoch\",\"elapsed_ns\":1000000000,\"metrics\":{\"peak_bytes\":640},\"totals\":[{\"op\":\"pop\""
+        ));
+        assert!(json.contains("\"metrics\":{\"end_bytes\":0,\"peak_bytes\":128}"));
+    }
+}
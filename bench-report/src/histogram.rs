@@ -0,0 +1,210 @@
+//! An HDR-style histogram: values are grouped into buckets whose width grows
+//! with the magnitude of the value, so that every recorded value is kept with
+//! a bounded relative error (below 1/64) no matter how large it is.
+//!
+//! Values below `SUB_BUCKETS` each get their own slot. Above that, every power
+//! of two range is split into `SUB_BUCKETS / 2` equally wide slots.
+
+const SUB_BUCKET_BITS: u32 = 7;
+const SUB_BUCKETS: u64 = 1 << SUB_BUCKET_BITS;
+const HALF_SUB_BUCKETS: u64 = SUB_BUCKETS / 2;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Histogram {
+    counts: Vec<u64>,
+    total: u64,
+    sum: u128,
+    min: u64,
+    max: u64,
+}
+
+fn index_of(v: u64) -> usize {
+    if v < SUB_BUCKETS {
+        return v as usize;
+    }
+
+    // Shift the value so that it lands in [HALF_SUB_BUCKETS, SUB_BUCKETS).
+    let msb = 63 - v.leading_zeros();
+    let shift = msb - (SUB_BUCKET_BITS - 1);
+    let sub = v >> shift;
+    (SUB_BUCKETS + (shift as u64 - 1) * HALF_SUB_BUCKETS + (sub - HALF_SUB_BUCKETS)) as usize
+}
+
+// Returns the smallest and largest values that map to the slot.
+fn range_of(idx: usize) -> (u64, u64) {
+    let idx = idx as u64;
+    if idx < SUB_BUCKETS {
+        return (idx, idx);
+    }
+
+    let shift = (idx - SUB_BUCKETS) / HALF_SUB_BUCKETS + 1;
+    let sub = (idx - SUB_BUCKETS) % HALF_SUB_BUCKETS + HALF_SUB_BUCKETS;
+    let low = sub << shift;
+    (low, low + ((1 << shift) - 1))
+}
+
+impl Histogram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, v: u64) {
+        self.record_n(v, 1);
+    }
+
+    pub fn record_n(&mut self, v: u64, n: u64) {
+        if n == 0 {
+            return;
+        }
+
+        let idx = index_of(v);
+        if idx >= self.counts.len() {
+            self.counts.resize(idx + 1, 0);
+        }
+        self.counts[idx] += n;
+
+        if self.total == 0 || v < self.min {
+            self.min = v;
+        }
+        self.max = self.max.max(v);
+        self.total += n;
+        self.sum += v as u128 * n as u128;
+    }
+
+    /// Adds all the values recorded in `other` to this histogram.
+    pub fn merge(&mut self, other: &Histogram) {
+        if other.total == 0 {
+            return;
+        }
+        if other.counts.len() > self.counts.len() {
+            self.counts.resize(other.counts.len(), 0);
+        }
+        for (c, o) in self.counts.iter_mut().zip(&other.counts) {
+            *c += o;
+        }
+
+        if self.total == 0 || other.min < self.min {
+            self.min = other.min;
+        }
+        self.max = self.max.max(other.max);
+        self.total += other.total;
+        self.sum += other.sum;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.total
+    }
+
+    pub fn min(&self) -> u64 {
+        self.min
+    }
+
+    pub fn max(&self) -> u64 {
+        self.max
+    }
+
+    pub fn mean(&self) -> f64 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        self.sum as f64 / self.total as f64
+    }
+
+    /// Returns the value below or at which `q` (in `[0, 1]`) of the recorded
+    /// values fall. Like HDR histograms, the highest value of the matching
+    /// slot is returned, so the result is never below the exact quantile.
+    pub fn value_at_quantile(&self, q: f64) -> u64 {
+        if self.total == 0 {
+            return 0;
+        }
+
+        let q = q.clamp(0.0, 1.0);
+        let rank = ((q * self.total as f64).ceil() as u64).max(1);
+
+        let mut seen = 0;
+        for (idx, &c) in self.counts.iter().enumerate() {
+            seen += c;
+            if seen >= rank {
+                return range_of(idx).1.clamp(self.min, self.max);
+            }
+        }
+        self.max
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn index_and_range_agree() {
+        for v in (0..100_000).chain([u64::MAX / 3, u64::MAX - 1, u64::MAX]) {
+            let (low, high) = range_of(index_of(v));
+            assert!(low <= v && v <= high, "{} not in [{}, {}]", v, low, high);
+        }
+    }
+
+    #[test]
+    fn relative_error_is_bounded() {
+        for v in [128, 1_000, 123_456, 98_765_432, u64::MAX / 7] {
+            let (low, high) = range_of(index_of(v));
+            assert!((high - low) as f64 / low as f64 <= 1.0 / 64.0);
+        }
+    }
+
+    #[test]
+    fn small_values_are_exact() {
+        let mut h = Histogram::new();
+        for v in [3, 1, 2, 5, 4] {
+            h.record(v);
+        }
+
+        assert_eq!(h.count(), 5);
+        assert_eq!(h.min(), 1);
+        assert_eq!(h.max(), 5);
+        assert_eq!(h.mean(), 3.0);
+        assert_eq!(h.value_at_quantile(0.0), 1);
+        assert_eq!(h.value_at_quantile(0.5), 3);
+        assert_eq!(h.value_at_quantile(1.0), 5);
+    }
+
+    #[test]
+    fn quantiles_of_uniform_values() {
+        let mut h = Histogram::new();
+        for v in 1..=10_000 {
+            h.record(v);
+        }
+
+        for (q, exact) in [(0.5, 5_000.0), (0.9, 9_000.0), (0.99, 9_900.0)] {
+            let got = h.value_at_quantile(q) as f64;
+            assert!(got >= exact && got <= exact * (1.0 + 1.0 / 64.0), "{}", got);
+        }
+        assert_eq!(h.value_at_quantile(1.0), 10_000);
+    }
+
+    #[test]
+    fn merge_matches_recording_everything_in_one() {
+        let mut a = Histogram::new();
+        let mut b = Histogram::new();
+        let mut all = Histogram::new();
+        for v in 0..1_000 {
+            if v % 3 == 0 {
+                a.record(v * 17);
+            } else {
+                b.record(v * 17);
+            }
+            all.record(v * 17);
+        }
+
+        a.merge(&b);
+        assert_eq!(a, all);
+    }
+
+    #[test]
+    fn empty_histogram() {
+        let h = Histogram::new();
+        assert_eq!(h.count(), 0);
+        assert_eq!(h.mean(), 0.0);
+        assert_eq!(h.value_at_quantile(0.99), 0);
+    }
+}
@@ -0,0 +1,15 @@
+//! Latency reporting for the stress binaries in this workspace.
+//!
+//! Every worker thread records how long each operation took into its own
+//! `Recorder`, so recording doesn't need any synchronization. Once the
+//! workload is done, the recorders are collected into a `Report` which prints
+//! percentiles, throughput and a per-thread breakdown as text, CSV or JSON.
+//! A `Comparison` puts the totals of several reports side by side.
+mod compare;
+pub use compare::Comparison;
+
+mod histogram;
+pub use histogram::Histogram;
+
+mod report;
+pub use report::{Format, Recorder, Report};
@@ -0,0 +1,355 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::Histogram;
+
+pub(crate) const QUANTILES: [(&str, f64); 4] =
+    [("p50", 0.5), ("p90", 0.9), ("p99", 0.99), ("p999", 0.999)];
+
+/// Records per-operation latencies for a single thread.
+#[derive(Debug, Clone)]
+pub struct Recorder {
+    label: String,
+    ops: BTreeMap<String, Histogram>,
+}
+
+impl Recorder {
+    pub fn new(label: impl Into<String>) -> Self {
+        Self {
+            label: label.into(),
+            ops: BTreeMap::new(),
+        }
+    }
+
+    pub fn record(&mut self, op: &str, latency: Duration) {
+        let nanos = latency.as_nanos().min(u64::MAX as u128) as u64;
+        match self.ops.get_mut(op) {
+            Some(h) => h.record(nanos),
+            None => {
+                let mut h = Histogram::new();
+                h.record(nanos);
+                self.ops.insert(op.to_owned(), h);
+            }
+        }
+    }
+
+    /// Runs `f` and records how long it took under `op`.
+    pub fn time<R>(&mut self, op: &str, f: impl FnOnce() -> R) -> R {
+        let start = Instant::now();
+        let res = f();
+        self.record(op, start.elapsed());
+        res
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Csv,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" => Ok(Format::Text),
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("unknown report format: {}", s)),
+        }
+    }
+}
+
+/// The recorders of every thread that took part in a workload, along with
+/// how long the workload took in total.
+#[derive(Debug, Clone)]
+pub struct Report {
+    title: String,
+    elapsed: Duration,
+    threads: Vec<Recorder>,
+}
+
+// A row of the report: one operation, either for a single thread or for all
+// threads combined (`thread` is None then).
+struct Row<'a> {
+    thread: Option<&'a str>,
+    op: &'a str,
+    hist: &'a Histogram,
+}
+
+impl Report {
+    pub fn new(title: impl Into<String>, elapsed: Duration, threads: Vec<Recorder>) -> Self {
+        Self {
+            title: title.into(),
+            elapsed,
+            threads,
+        }
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// Combined histograms of every operation across all threads.
+    pub fn totals(&self) -> BTreeMap<&str, Histogram> {
+        let mut totals: BTreeMap<&str, Histogram> = BTreeMap::new();
+        for t in &self.threads {
+            for (op, h) in &t.ops {
+                totals.entry(op).or_default().merge(h);
+            }
+        }
+        totals
+    }
+
+    /// Operations per second over the whole workload.
+    pub fn throughput(&self, hist: &Histogram) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs == 0.0 {
+            return 0.0;
+        }
+        hist.count() as f64 / secs
+    }
+
+    pub fn render(&self, format: Format) -> String {
+        let totals = self.totals();
+        let mut rows: Vec<Row> = totals
+            .iter()
+            .map(|(op, hist)| Row {
+                thread: None,
+                op,
+                hist,
+            })
+            .collect();
+        for t in &self.threads {
+            rows.extend(t.ops.iter().map(|(op, hist)| Row {
+                thread: Some(&t.label),
+                op,
+                hist,
+            }));
+        }
+
+        match format {
+            Format::Text => self.render_text(&rows),
+            Format::Csv => self.render_csv(&rows),
+            Format::Json => self.render_json(&rows),
+        }
+    }
+
+    fn render_text(&self, rows: &[Row]) -> String {
+        let mut out = String::new();
+        writeln!(out, "{} (elapsed {:?})", self.title, self.elapsed).unwrap();
+
+        let mut header = format!(
+            "{:<24} {:<8} {:>10} {:>14}",
+            "thread", "op", "count", "ops/s"
+        );
+        for (name, _) in QUANTILES {
+            write!(header, " {:>10}", name).unwrap();
+        }
+        write!(header, " {:>10}", "max").unwrap();
+        writeln!(out, "{}", header).unwrap();
+
+        for row in rows {
+            write!(
+                out,
+                "{:<24} {:<8} {:>10} {:>14.1}",
+                row.thread.unwrap_or("all"),
+                row.op,
+                row.hist.count(),
+                self.throughput(row.hist)
+            )
+            .unwrap();
+            for (_, q) in QUANTILES {
+                let d = Duration::from_nanos(row.hist.value_at_quantile(q));
+                write!(out, " {:>10}", format!("{:?}", d)).unwrap();
+            }
+            let max = Duration::from_nanos(row.hist.max());
+            writeln!(out, " {:>10}", format!("{:?}", max)).unwrap();
+        }
+        out
+    }
+
+    fn render_csv(&self, rows: &[Row]) -> String {
+        let mut out = String::from("thread,op,count,ops_per_sec");
+        for (name, _) in QUANTILES {
+            write!(out, ",{}_ns", name).unwrap();
+        }
+        out.push_str(",max_ns\n");
+
+        for row in rows {
+            write!(
+                out,
+                "{},{},{},{:.1}",
+                csv_field(row.thread.unwrap_or("all")),
+                csv_field(row.op),
+                row.hist.count(),
+                self.throughput(row.hist)
+            )
+            .unwrap();
+            for (_, q) in QUANTILES {
+                write!(out, ",{}", row.hist.value_at_quantile(q)).unwrap();
+            }
+            writeln!(out, ",{}", row.hist.max()).unwrap();
+        }
+        out
+    }
+
+    fn render_json(&self, rows: &[Row]) -> String {
+        let row_json = |row: &Row| {
+            let mut s = format!(
+                "{{\"op\":{},\"count\":{},\"ops_per_sec\":{:.1}",
+                json_str(row.op),
+                row.hist.count(),
+                self.throughput(row.hist)
+            );
+            for (name, q) in QUANTILES {
+                write!(s, ",\"{}_ns\":{}", name, row.hist.value_at_quantile(q)).unwrap();
+            }
+            write!(s, ",\"max_ns\":{}}}", row.hist.max()).unwrap();
+            s
+        };
+
+        let totals: Vec<String> = rows
+            .iter()
+            .filter(|r| r.thread.is_none())
+            .map(row_json)
+            .collect();
+
+        let threads: Vec<String> = self
+            .threads
+            .iter()
+            .map(|t| {
+                let ops: Vec<String> = rows
+                    .iter()
+                    .filter(|r| r.thread == Some(t.label.as_str()))
+                    .map(row_json)
+                    .collect();
+                format!(
+                    "{{\"thread\":{},\"ops\":[{}]}}",
+                    json_str(&t.label),
+                    ops.join(",")
+                )
+            })
+            .collect();
+
+        format!(
+            "{{\"title\":{},\"elapsed_ns\":{},\"totals\":[{}],\"threads\":[{}]}}\n",
+            json_str(&self.title),
+            self.elapsed.as_nanos(),
+            totals.join(","),
+            threads.join(",")
+        )
+    }
+}
+
+pub(crate) fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_owned()
+    }
+}
+
+pub(crate) fn json_str(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_report() -> Report {
+        let mut a = Recorder::new("pusher-0");
+        let mut b = Recorder::new("popper, 1");
+        for i in 1..=100 {
+            a.record("push", Duration::from_nanos(i));
+            b.record("pop", Duration::from_nanos(i * 2));
+        }
+        b.record("push", Duration::from_nanos(7));
+
+        Report::new("stack", Duration::from_secs(2), vec![a, b])
+    }
+
+    #[test]
+    fn totals_merge_threads() {
+        let report = sample_report();
+        let totals = report.totals();
+
+        assert_eq!(totals["push"].count(), 101);
+        assert_eq!(totals["pop"].count(), 100);
+        assert_eq!(report.throughput(&totals["pop"]), 50.0);
+    }
+
+    #[test]
+    fn time_records_under_op() {
+        let mut r = Recorder::new("t");
+        assert_eq!(r.time("work", || 42), 42);
+        assert_eq!(r.ops["work"].count(), 1);
+    }
+
+    #[test]
+    fn text_has_totals_and_per_thread_rows() {
+        let text = sample_report().render(Format::Text);
+        let lines: Vec<&str> = text.lines().collect();
+
+        assert!(lines[0].starts_with("stack"));
+        assert!(lines[1].contains("p999"));
+        // 2 total rows, 1 row for pusher-0 and 2 for the popper.
+        assert_eq!(lines.len(), 2 + 2 + 1 + 2);
+        assert!(lines[2].starts_with("all"));
+    }
+
+    #[test]
+    fn csv_quotes_fields() {
+        let csv = sample_report().render(Format::Csv);
+        let mut lines = csv.lines();
+
+        assert_eq!(
+            lines.next().unwrap(),
+            "thread,op,count,ops_per_sec,p50_ns,p90_ns,p99_ns,p999_ns,max_ns"
+        );
+        assert_eq!(
+            lines.next().unwrap(),
+            "all,pop,100,50.0,100,181,199,200,200"
+        );
+        assert!(csv.contains("\"popper, 1\",pop,100"));
+    }
+
+    #[test]
+    fn json_structure() {
+        let json = sample_report().render(Format::Json);
+
+        assert!(json.starts_with(
+            "{\"title\":\"stack\",\"elapsed_ns\":2000000000,\"totals\":[{\"op\":\"pop\""
+        ));
+        assert!(json.contains("{\"thread\":\"pusher-0\",\"ops\":[{\"op\":\"push\",\"count\":100,"));
+        assert_eq!(json_str("a\"b\\\n"), "\"a\\\"b\\\\\\n\"");
+    }
+
+    #[test]
+    fn format_from_str() {
+        assert_eq!("JSON".parse(), Ok(Format::Json));
+        assert_eq!("csv".parse(), Ok(Format::Csv));
+        assert!("xml".parse::<Format>().is_err());
+    }
+}
@@ -0,0 +1,362 @@
+//! Cooperative cancellation shared by the crates in this workspace.
+//!
+//! A `CancellationToken` is a flag that can be flipped once. Work that can be
+//! cancelled checks `is_cancelled()` periodically, blocks on
+//! `cancelled_wait()`, awaits `cancelled()`, or has an `on_cancel` callback
+//! wake it up however it's blocked. Child tokens are cancelled together with
+//! their parent, but cancelling a child leaves the parent alone.
+//! Clones of a token share the same flag.
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    node: Arc<Node>,
+}
+
+#[derive(Default)]
+struct Node {
+    cancelled: AtomicBool,
+    state: Mutex<State>,
+    // Notifies the threads blocked in cancelled_wait.
+    cond: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    // Weak, so that dropped children don't stay alive as long as the parent.
+    children: Vec<Weak<Node>>,
+    // Tasks waiting in `cancelled().await`, keyed by an id that the future
+    // uses to deregister itself when dropped.
+    wakers: HashMap<u64, Waker>,
+    // Registered with on_cancel, keyed like the wakers.
+    callbacks: HashMap<u64, Box<dyn FnOnce() + Send>>,
+    next_id: u64,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a token which is cancelled when this one is. If this token is
+    /// already cancelled, so is the child.
+    pub fn child_token(&self) -> CancellationToken {
+        let child = CancellationToken::new();
+
+        // `cancel` flips the flag before taking the children out, so checking
+        // it under the lock means the child is either seen by `cancel` or
+        // sees the flag itself.
+        let mut state = self.node.state.lock().unwrap();
+        if self.is_cancelled() {
+            drop(state);
+            child.cancel();
+        } else {
+            state.children.retain(|c| c.strong_count() > 0);
+            state.children.push(Arc::downgrade(&child.node));
+        }
+
+        child
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.node.cancelled.load(Ordering::Acquire)
+    }
+
+    /// Cancels this token and all of its descendants. Calling it more than
+    /// once has no effect.
+    pub fn cancel(&self) {
+        self.node.cancel();
+    }
+
+    /// Blocks the current thread until the token is cancelled.
+    pub fn cancelled_wait(&self) {
+        let mut state = self.node.state.lock().unwrap();
+        while !self.is_cancelled() {
+            state = self.node.cond.wait(state).unwrap();
+        }
+    }
+
+    /// Blocks the current thread until the token is cancelled or `timeout`
+    /// elapses. Returns true if the token was cancelled.
+    pub fn cancelled_wait_timeout(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.node.state.lock().unwrap();
+
+        while !self.is_cancelled() {
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            state = self
+                .node
+                .cond
+                .wait_timeout(state, deadline - now)
+                .unwrap()
+                .0;
+        }
+        true
+    }
+
+    /// Calls `f` once the token is cancelled, or right away if it already
+    /// is. `f` is called by the thread that cancels the token, unless the
+    /// returned guard is dropped first.
+    pub fn on_cancel(&self, f: impl FnOnce() + Send + 'static) -> OnCancel<'_> {
+        let mut state = self.node.state.lock().unwrap();
+        // Checked under the lock, like in child_token.
+        if self.is_cancelled() {
+            drop(state);
+            f();
+            return OnCancel {
+                token: self,
+                id: None,
+            };
+        }
+        let id = state.next_id();
+        state.callbacks.insert(id, Box::new(f));
+        OnCancel {
+            token: self,
+            id: Some(id),
+        }
+    }
+
+    /// Returns a future which completes once the token is cancelled.
+    pub fn cancelled(&self) -> WaitForCancellation<'_> {
+        WaitForCancellation {
+            token: self,
+            waker_id: None,
+        }
+    }
+}
+
+impl State {
+    fn next_id(&mut self) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+}
+
+impl Node {
+    fn cancel(&self) {
+        if self.cancelled.swap(true, Ordering::AcqRel) {
+            return;
+        }
+
+        let (children, wakers, callbacks) = {
+            let mut state = self.state.lock().unwrap();
+            (
+                std::mem::take(&mut state.children),
+                std::mem::take(&mut state.wakers),
+                std::mem::take(&mut state.callbacks),
+            )
+        };
+        self.cond.notify_all();
+
+        // Waking, calling back and cancelling the children without holding
+        // our lock, so that woken tasks can poll again right away.
+        for (_, waker) in wakers {
+            waker.wake();
+        }
+        for (_, f) in callbacks {
+            f();
+        }
+        for child in children.iter().filter_map(Weak::upgrade) {
+            child.cancel();
+        }
+    }
+}
+
+pub struct WaitForCancellation<'a> {
+    token: &'a CancellationToken,
+    waker_id: Option<u64>,
+}
+
+impl Future for WaitForCancellation<'_> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.token.is_cancelled() {
+            return Poll::Ready(());
+        }
+
+        let node = &self.token.node;
+        let mut state = node.state.lock().unwrap();
+
+        // Checking again under the lock, because `cancel` might have taken
+        // the wakers out between the first check and acquiring the lock.
+        if self.token.is_cancelled() {
+            return Poll::Ready(());
+        }
+
+        let id = match self.waker_id {
+            Some(id) => id,
+            None => state.next_id(),
+        };
+        state.wakers.insert(id, cx.waker().clone());
+        drop(state);
+
+        self.waker_id = Some(id);
+        Poll::Pending
+    }
+}
+
+impl Drop for WaitForCancellation<'_> {
+    fn drop(&mut self) {
+        if let Some(id) = self.waker_id {
+            self.token.node.state.lock().unwrap().wakers.remove(&id);
+        }
+    }
+}
+
+/// Deregisters the callback of `CancellationToken::on_cancel` when dropped.
+pub struct OnCancel<'a> {
+    token: &'a CancellationToken,
+    // None if the callback was already called.
+    id: Option<u64>,
+}
+
+impl Drop for OnCancel<'_> {
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            self.token.node.state.lock().unwrap().callbacks.remove(&id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn cancel_is_visible_to_clones() {
+        let token = CancellationToken::new();
+        let cloned = token.clone();
+        assert!(!cloned.is_cancelled());
+
+        token.cancel();
+        assert!(cloned.is_cancelled());
+
+        // Cancelling twice is fine.
+        cloned.cancel();
+        assert!(token.is_cancelled());
+    }
+
+    #[test]
+    fn parent_cancels_children_transitively() {
+        let parent = CancellationToken::new();
+        let child = parent.child_token();
+        let grandchild = child.child_token();
+
+        parent.cancel();
+        assert!(child.is_cancelled());
+        assert!(grandchild.is_cancelled());
+    }
+
+    #[test]
+    fn child_does_not_cancel_parent() {
+        let parent = CancellationToken::new();
+        let child = parent.child_token();
+        let sibling = parent.child_token();
+
+        child.cancel();
+        assert!(!parent.is_cancelled());
+        assert!(!sibling.is_cancelled());
+    }
+
+    #[test]
+    fn child_of_cancelled_token_starts_cancelled() {
+        let parent = CancellationToken::new();
+        parent.cancel();
+        assert!(parent.child_token().is_cancelled());
+    }
+
+    #[test]
+    fn dropped_children_are_pruned() {
+        let parent = CancellationToken::new();
+        for _ in 0..100 {
+            drop(parent.child_token());
+        }
+        let _child = parent.child_token();
+        assert_eq!(parent.node.state.lock().unwrap().children.len(), 1);
+    }
+
+    #[test]
+    fn cancelled_wait_blocks_until_cancel() {
+        let token = CancellationToken::new();
+
+        thread::scope(|s| {
+            let waiters: Vec<_> = (0..4)
+                .map(|_| {
+                    let child = token.child_token();
+                    s.spawn(move || child.cancelled_wait())
+                })
+                .collect();
+
+            thread::sleep(Duration::from_millis(20));
+            token.cancel();
+
+            for w in waiters {
+                w.join().unwrap();
+            }
+        });
+    }
+
+    #[test]
+    fn cancelled_wait_timeout() {
+        let token = CancellationToken::new();
+        assert!(!token.cancelled_wait_timeout(Duration::from_millis(10)));
+
+        token.cancel();
+        assert!(token.cancelled_wait_timeout(Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn on_cancel_callbacks() {
+        let token = CancellationToken::new();
+        let child = token.child_token();
+        let (tx, rx) = std::sync::mpsc::channel();
+
+        let tx2 = tx.clone();
+        let _called = child.on_cancel(move || tx2.send("child").unwrap());
+        let dropped = token.on_cancel(|| panic!("called after the guard was dropped"));
+        drop(dropped);
+        assert!(token.node.state.lock().unwrap().callbacks.is_empty());
+
+        token.cancel();
+        assert_eq!(rx.try_recv(), Ok("child"));
+        let _late = token.on_cancel(move || tx.send("late").unwrap());
+        assert_eq!(rx.try_recv(), Ok("late"));
+    }
+
+    #[tokio::test]
+    async fn cancelled_future_completes_on_cancel() {
+        let token = CancellationToken::new();
+        let child = token.child_token();
+
+        let handle = tokio::spawn(async move { child.cancelled().await });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!handle.is_finished());
+
+        token.cancel();
+        handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn dropped_future_deregisters_waker() {
+        let token = CancellationToken::new();
+
+        tokio::select! {
+            _ = token.cancelled() => unreachable!(),
+            _ = tokio::time::sleep(Duration::from_millis(10)) => (),
+        }
+
+        assert!(token.node.state.lock().unwrap().wakers.is_empty());
+    }
+}
@@ -0,0 +1,108 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::thread;
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use cskiplist::SkipMap;
+
+const KEYS: u64 = 10_000;
+const OPS_PER_THREAD: u64 = 10_000;
+
+// One write per `WRITE_EVERY` ops, the rest are lookups.
+const WRITE_EVERY: u64 = 10;
+
+fn key(thread: u64, i: u64) -> u64 {
+    (i * 7919 + thread * 104_729) % KEYS
+}
+
+fn skipmap(threads: u64) {
+    let map = SkipMap::new();
+    for k in (0..KEYS).step_by(2) {
+        map.insert(k, k);
+    }
+    thread::scope(|s| {
+        for t in 0..threads {
+            let map = &map;
+            s.spawn(move || {
+                for i in 0..OPS_PER_THREAD {
+                    let k = key(t, i);
+                    if i % WRITE_EVERY == 0 {
+                        if !map.remove(&k) {
+                            map.insert(k, k);
+                        }
+                    } else {
+                        let guard = map.guard();
+                        black_box(map.get(&k, &guard));
+                    }
+                }
+            });
+        }
+    });
+}
+
+fn mutex_btreemap(threads: u64) {
+    let map = Mutex::new(BTreeMap::new());
+    for k in (0..KEYS).step_by(2) {
+        map.lock().unwrap().insert(k, k);
+    }
+    thread::scope(|s| {
+        for t in 0..threads {
+            let map = &map;
+            s.spawn(move || {
+                for i in 0..OPS_PER_THREAD {
+                    let k = key(t, i);
+                    let mut map = map.lock().unwrap();
+                    if i % WRITE_EVERY == 0 {
+                        if map.remove(&k).is_none() {
+                            map.insert(k, k);
+                        }
+                    } else {
+                        black_box(map.get(&k));
+                    }
+                }
+            });
+        }
+    });
+}
+
+pub fn vs_btreemap_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("read_mostly");
+    group.sample_size(20);
+    for threads in [1, 2, 4, 8] {
+        group.bench_with_input(BenchmarkId::new("SkipMap", threads), &threads, |b, &t| {
+            b.iter(|| skipmap(t))
+        });
+        group.bench_with_input(
+            BenchmarkId::new("Mutex<BTreeMap>", threads),
+            &threads,
+            |b, &t| b.iter(|| mutex_btreemap(t)),
+        );
+    }
+    group.finish();
+
+    // Range scans hold the guard for the whole scan, while the mutex blocks
+    // every writer for that long.
+    let map = SkipMap::new();
+    let btree = Mutex::new(BTreeMap::new());
+    for k in 0..KEYS {
+        map.insert(k, k);
+        btree.lock().unwrap().insert(k, k);
+    }
+    let mut group = c.benchmark_group("range_scan_1000");
+    group.bench_function("SkipMap", |b| {
+        b.iter(|| {
+            let guard = map.guard();
+            black_box(map.range(4000..5000, &guard).map(|(_, v)| v).sum::<u64>())
+        })
+    });
+    group.bench_function("Mutex<BTreeMap>", |b| {
+        b.iter(|| {
+            let map = btree.lock().unwrap();
+            black_box(map.range(4000..5000).map(|(_, v)| v).sum::<u64>())
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, vs_btreemap_benchmark);
+criterion_main!(benches);
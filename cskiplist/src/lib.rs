@@ -0,0 +1,543 @@
+//! An ordered map on top of a lock-free skip list.
+//!
+//! Every node is linked into level 0, and into each level above with a
+//! probability of 1/2 per level, so searches skip most of the nodes on the
+//! way down. Reads never write to shared memory. Inserts and removals only
+//! use CAS, following Herlihy and Shavit's lock-free skip list: a node is
+//! removed by first marking its next pointers (the lowest bit), top level
+//! first. Marking level 0 is what removes it logically, and whoever succeeds
+//! at that owns the removal. Any thread that runs into a marked node on its
+//! way helps unlinking it.
+//!
+//! Nodes are reclaimed with seize. A node can only be retired once it's
+//! unlinked from every level, which might not be done by the thread that
+//! removed it: an insert can still be linking the upper levels of a node that
+//! is already being removed. So nodes count the levels they're linked into,
+//! plus one while their insert is in progress, and whoever drops the count to
+//! zero retires the node.
+use std::borrow::Borrow;
+use std::cell::Cell;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::ops::{Bound, RangeBounds};
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+use seize::{reclaim, Collector, Guard, Linked};
+
+const MAX_HEIGHT: usize = 16;
+
+type Next<K, V> = AtomicPtr<Linked<Node<K, V>>>;
+
+pub struct SkipMap<K, V> {
+    // The next pointers of the head, one per level.
+    head: Box<[Next<K, V>]>,
+    collector: Collector,
+    len: AtomicUsize,
+}
+
+struct Node<K, V> {
+    key: K,
+    value: V,
+    // The levels the node is linked into, plus one while it's being inserted.
+    links: AtomicUsize,
+    next: Box<[Next<K, V>]>,
+}
+
+// Where a key is, or would be, on every level. A pred is represented by its
+// next pointers, so the head and the nodes can be treated the same.
+struct Position<'g, K, V> {
+    preds: [&'g [Next<K, V>]; MAX_HEIGHT],
+    succs: [*mut Linked<Node<K, V>>; MAX_HEIGHT],
+}
+
+unsafe impl<K: Send + Sync, V: Send + Sync> Send for SkipMap<K, V> {}
+unsafe impl<K: Send + Sync, V: Send + Sync> Sync for SkipMap<K, V> {}
+
+fn is_marked<T>(ptr: *mut T) -> bool {
+    ptr.addr() & 1 == 1
+}
+
+fn marked<T>(ptr: *mut T) -> *mut T {
+    ptr.map_addr(|addr| addr | 1)
+}
+
+fn unmarked<T>(ptr: *mut T) -> *mut T {
+    ptr.map_addr(|addr| addr & !1)
+}
+
+// A geometric distribution: height h has a probability of 1/2^h.
+fn random_height() -> usize {
+    thread_local! {
+        static STATE: Cell<u64> = Cell::new(RandomState::new().build_hasher().finish() | 1);
+    }
+
+    // xorshift64, which is plenty for deciding heights.
+    let x = STATE.with(|state| {
+        let mut x = state.get();
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        state.set(x);
+        x
+    });
+    (x.trailing_zeros() as usize + 1).min(MAX_HEIGHT)
+}
+
+impl<K, V> SkipMap<K, V> {
+    pub fn new() -> Self {
+        Self {
+            head: (0..MAX_HEIGHT).map(|_| AtomicPtr::default()).collect(),
+            collector: Collector::new(),
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    /// Enters the collector of this map. References returned by `get` and
+    /// `range` are valid for as long as the guard is held.
+    pub fn guard(&self) -> Guard<'_> {
+        self.collector.enter()
+    }
+
+    /// The number of entries. It's only a snapshot while other threads are
+    /// inserting or removing entries.
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn check_guard(&self, guard: &Guard<'_>) {
+        let ours = guard
+            .collector()
+            .is_some_and(|c| Collector::ptr_eq(c, &self.collector));
+        assert!(ours, "guard belongs to a different map");
+    }
+
+    // Drops a link of the node, and retires it if it was the last one.
+    unsafe fn release(&self, node: *mut Linked<Node<K, V>>, guard: &Guard<'_>) {
+        let n = &*node;
+        if n.links.fetch_sub(1, Ordering::AcqRel) == 1 {
+            guard.retire(node, reclaim::boxed::<Node<K, V>>);
+        }
+    }
+}
+
+impl<K: Ord, V> SkipMap<K, V> {
+    /// Inserts the entry if there's no entry for `key` yet. Returns false
+    /// (and drops the arguments) otherwise.
+    pub fn insert(&self, key: K, value: V) -> bool {
+        let guard = self.collector.enter();
+        let height = random_height();
+        let node = self.collector.link_boxed(Node {
+            key,
+            value,
+            // The insert's own link, dropped at the end.
+            links: AtomicUsize::new(1),
+            next: (0..height).map(|_| AtomicPtr::default()).collect(),
+        });
+        // SAFETY: the node isn't retired before we drop our link.
+        let n = unsafe { &*node };
+
+        // Linking level 0 inserts the entry.
+        let mut pos = loop {
+            let pos = self.find(&n.key, &guard);
+            let succ = pos.succs[0];
+            if !succ.is_null() && unsafe { &(&*succ).key } == &n.key {
+                // SAFETY: the node was never shared.
+                unsafe { drop(Box::from_raw(node)) };
+                return false;
+            }
+
+            for (level, next) in n.next.iter().enumerate() {
+                next.store(pos.succs[level], Ordering::Relaxed);
+            }
+            n.links.fetch_add(1, Ordering::Relaxed);
+            match pos.preds[0][0].compare_exchange(succ, node, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => break pos,
+                Err(_) => {
+                    n.links.fetch_sub(1, Ordering::Relaxed);
+                }
+            }
+        };
+        self.len.fetch_add(1, Ordering::Relaxed);
+
+        // The upper levels only speed up searches, so we give up on them as
+        // soon as the node is being removed.
+        'levels: for level in 1..height {
+            loop {
+                let succ = pos.succs[level];
+                let next = n.next[level].load(Ordering::Acquire);
+                if is_marked(next) {
+                    break 'levels;
+                }
+                if next != succ
+                    && n.next[level]
+                        .compare_exchange(next, succ, Ordering::AcqRel, Ordering::Acquire)
+                        .is_err()
+                {
+                    // It can only have been marked in the meantime.
+                    break 'levels;
+                }
+
+                // Counted before it's linked, as it can be unlinked right away.
+                n.links.fetch_add(1, Ordering::Relaxed);
+                let linked = pos.preds[level][level]
+                    .compare_exchange(succ, node, Ordering::AcqRel, Ordering::Acquire)
+                    .is_ok();
+                if linked {
+                    break;
+                }
+                n.links.fetch_sub(1, Ordering::Relaxed);
+
+                pos = self.find(&n.key, &guard);
+                if pos.succs[0] != node {
+                    break 'levels;
+                }
+            }
+        }
+
+        unsafe { self.release(node, &guard) };
+        true
+    }
+
+    /// Removes the entry for `key`. Returns false if there was none, or if
+    /// another thread removed it first.
+    pub fn remove<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let guard = self.collector.enter();
+        let pos = self.find(key, &guard);
+        let node = pos.succs[0];
+        if node.is_null() || unsafe { &*node }.key.borrow() != key {
+            return false;
+        }
+        let n = unsafe { &*node };
+
+        // Top-down, so the node disappears from the fast lanes first and an
+        // insert still linking it notices.
+        for next in n.next[1..].iter().rev() {
+            let mut cur = next.load(Ordering::Acquire);
+            while !is_marked(cur) {
+                match next.compare_exchange(cur, marked(cur), Ordering::AcqRel, Ordering::Acquire) {
+                    Ok(_) => break,
+                    Err(actual) => cur = actual,
+                }
+            }
+        }
+
+        let mut cur = n.next[0].load(Ordering::Acquire);
+        loop {
+            if is_marked(cur) {
+                // Someone else removed it.
+                return false;
+            }
+            match n.next[0].compare_exchange(cur, marked(cur), Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => break,
+                Err(actual) => cur = actual,
+            }
+        }
+        self.len.fetch_sub(1, Ordering::Relaxed);
+
+        // Unlinks the node from every level it's linked into by now.
+        self.find(key, &guard);
+        true
+    }
+
+    pub fn get<'g, Q>(&'g self, key: &Q, guard: &'g Guard<'_>) -> Option<&'g V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.check_guard(guard);
+        let node = self.seek(Bound::Included(key), guard)?;
+        (node.key.borrow() == key).then_some(&node.value)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let guard = self.collector.enter();
+        self.get(key, &guard).is_some()
+    }
+
+    /// Iterates over the entries in `range` in order. Entries inserted or
+    /// removed while iterating may or may not be seen, but the ones that are
+    /// stay valid for as long as the guard is held.
+    pub fn range<'g, R>(&'g self, range: R, guard: &'g Guard<'_>) -> Range<'g, K, V, R>
+    where
+        R: RangeBounds<K>,
+    {
+        self.check_guard(guard);
+        let next = self.seek(range.start_bound(), guard);
+        Range { guard, next, range }
+    }
+
+    pub fn iter<'g>(&'g self, guard: &'g Guard<'_>) -> Range<'g, K, V, std::ops::RangeFull> {
+        self.range(.., guard)
+    }
+
+    // Returns the first node at level 0 that's within `start` and isn't
+    // removed. Unlike find, it never writes to the list.
+    fn seek<'g, Q>(&'g self, start: Bound<&Q>, guard: &'g Guard<'_>) -> Option<&'g Node<K, V>>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let before_start = |key: &K| match start {
+            Bound::Included(start) => key.borrow() < start,
+            Bound::Excluded(start) => key.borrow() <= start,
+            Bound::Unbounded => false,
+        };
+
+        let mut pred: &[Next<K, V>] = &self.head;
+        let mut curr = ptr::null_mut();
+        for level in (0..MAX_HEIGHT).rev() {
+            curr = unmarked(guard.protect(&pred[level], Ordering::Acquire));
+            while let Some(node) = unsafe { curr.as_ref() } {
+                if !before_start(&node.key) {
+                    break;
+                }
+                pred = &node.next;
+                curr = unmarked(guard.protect(&node.next[level], Ordering::Acquire));
+            }
+        }
+
+        // Skip the nodes that are removed but still linked.
+        let mut node: &Node<K, V> = unsafe { curr.as_ref() }?;
+        loop {
+            let next = guard.protect(&node.next[0], Ordering::Acquire);
+            if !is_marked(next) {
+                return Some(node);
+            }
+            node = unsafe { unmarked(next).as_ref() }?;
+        }
+    }
+
+    // Finds the preds and succs of `key` on every level, where succs[0] is
+    // the first node with a key that isn't less than `key`. Marked nodes on
+    // the way are unlinked.
+    fn find<'g, Q>(&'g self, key: &Q, guard: &'g Guard<'_>) -> Position<'g, K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        'retry: loop {
+            let mut pos = Position {
+                preds: [&*self.head; MAX_HEIGHT],
+                succs: [ptr::null_mut(); MAX_HEIGHT],
+            };
+            let mut pred: &[Next<K, V>] = &self.head;
+
+            for level in (0..MAX_HEIGHT).rev() {
+                let mut curr = guard.protect(&pred[level], Ordering::Acquire);
+                if is_marked(curr) {
+                    // pred was removed since we got to it.
+                    continue 'retry;
+                }
+
+                while let Some(node) = unsafe { curr.as_ref() } {
+                    let succ = guard.protect(&node.next[level], Ordering::Acquire);
+                    if is_marked(succ) {
+                        // Help unlinking the removed node on this level.
+                        let unlinked = pred[level]
+                            .compare_exchange(
+                                curr,
+                                unmarked(succ),
+                                Ordering::AcqRel,
+                                Ordering::Acquire,
+                            )
+                            .is_ok();
+                        if !unlinked {
+                            continue 'retry;
+                        }
+                        unsafe { self.release(curr, guard) };
+                        curr = unmarked(succ);
+                        continue;
+                    }
+
+                    if node.key.borrow() >= key {
+                        break;
+                    }
+                    pred = &node.next;
+                    curr = succ;
+                }
+
+                pos.preds[level] = pred;
+                pos.succs[level] = curr;
+            }
+            return pos;
+        }
+    }
+}
+
+impl<K, V> Default for SkipMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> Drop for SkipMap<K, V> {
+    fn drop(&mut self) {
+        // No insert is in progress, so every node has exactly as many links as
+        // levels it's linked into. A node is freed on the last of them, and
+        // the lower levels never lead to it again.
+        for level in (0..MAX_HEIGHT).rev() {
+            let mut curr = unmarked(*self.head[level].get_mut());
+            while !curr.is_null() {
+                unsafe {
+                    let node = &*curr;
+                    let next = unmarked(node.next[level].load(Ordering::Relaxed));
+                    if node.links.fetch_sub(1, Ordering::Relaxed) == 1 {
+                        drop(Box::from_raw(curr));
+                    }
+                    curr = next;
+                }
+            }
+        }
+    }
+}
+
+pub struct Range<'g, K, V, R> {
+    guard: &'g Guard<'g>,
+    next: Option<&'g Node<K, V>>,
+    range: R,
+}
+
+impl<'g, K: Ord, V, R: RangeBounds<K>> Iterator for Range<'g, K, V, R> {
+    type Item = (&'g K, &'g V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.next.take()?;
+        let past_end = match self.range.end_bound() {
+            Bound::Included(end) => &node.key > end,
+            Bound::Excluded(end) => &node.key >= end,
+            Bound::Unbounded => false,
+        };
+        if past_end {
+            return None;
+        }
+
+        // Skip the nodes that are removed but still linked.
+        let mut next = unmarked(self.guard.protect(&node.next[0], Ordering::Acquire));
+        while let Some(n) = unsafe { next.as_ref() } {
+            let after = self.guard.protect(&n.next[0], Ordering::Acquire);
+            if !is_marked(after) {
+                break;
+            }
+            next = unmarked(after);
+        }
+        self.next = unsafe { next.as_ref() }.map(|n| &**n);
+        Some((&node.key, &node.value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn insert_get_remove() {
+        let map = SkipMap::new();
+        assert!(map.is_empty());
+
+        assert!(map.insert(2, "two"));
+        assert!(map.insert(1, "one"));
+        assert!(!map.insert(1, "uno"));
+        assert_eq!(map.len(), 2);
+
+        let guard = map.guard();
+        assert_eq!(map.get(&1, &guard), Some(&"one"));
+        assert_eq!(map.get(&3, &guard), None);
+
+        assert!(map.remove(&1));
+        assert!(!map.remove(&1));
+        assert_eq!(map.get(&1, &guard), None);
+        assert!(map.contains_key(&2));
+        assert_eq!(map.len(), 1);
+    }
+
+    #[test]
+    fn borrowed_keys() {
+        let map = SkipMap::new();
+        map.insert("key".to_owned(), 1);
+
+        let guard = map.guard();
+        assert_eq!(map.get("key", &guard), Some(&1));
+        assert!(map.remove("key"));
+    }
+
+    #[test]
+    fn iterates_in_order() {
+        let map = SkipMap::new();
+        for i in [5, 3, 9, 1, 7, 2, 8, 6, 4, 0] {
+            map.insert(i, i * 10);
+        }
+        map.remove(&6);
+
+        let guard = map.guard();
+        let keys: Vec<_> = map.iter(&guard).map(|(k, _)| *k).collect();
+        assert_eq!(keys, [0, 1, 2, 3, 4, 5, 7, 8, 9]);
+
+        let entries: Vec<_> = map.range(3..6, &guard).collect();
+        assert_eq!(entries, [(&3, &30), (&4, &40), (&5, &50)]);
+        let keys: Vec<_> = map.range(5..=7, &guard).map(|(k, _)| *k).collect();
+        assert_eq!(keys, [5, 7]);
+        let keys: Vec<_> = map
+            .range((Bound::Excluded(7), Bound::Unbounded), &guard)
+            .map(|(k, _)| *k)
+            .collect();
+        assert_eq!(keys, [8, 9]);
+        assert_eq!(map.range(10.., &guard).count(), 0);
+    }
+
+    #[test]
+    fn many_keys_get_tall_towers() {
+        let map = SkipMap::new();
+        for i in (0..10_000).rev() {
+            map.insert(i, ());
+        }
+        assert_eq!(map.len(), 10_000);
+
+        let guard = map.guard();
+        assert!(map.iter(&guard).map(|(k, _)| *k).eq(0..10_000));
+        let tall = map.head[8].load(Ordering::Relaxed);
+        assert!(!tall.is_null());
+    }
+
+    #[test]
+    fn drops_every_value_once() {
+        let counter = Arc::new(());
+        let map = SkipMap::new();
+        for i in 0..1000 {
+            map.insert(i, counter.clone());
+        }
+        for i in (0..1000).step_by(3) {
+            map.remove(&i);
+        }
+        // Removed values are dropped once seize reclaims their nodes, which
+        // might not have happened yet.
+        let alive = Arc::strong_count(&counter) - 1;
+        assert!((map.len()..=1000).contains(&alive));
+
+        drop(map);
+        assert_eq!(Arc::strong_count(&counter), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "guard belongs to a different map")]
+    fn guard_of_another_map() {
+        let map = SkipMap::<u8, u8>::new();
+        let other = SkipMap::<u8, u8>::new();
+        map.get(&1, &other.guard());
+    }
+}
@@ -0,0 +1,86 @@
+//! Several threads insert and remove overlapping keys while readers scan the
+//! map. Every key is owned by one thread at a time for removals, so the final
+//! contents are known exactly.
+use std::collections::BTreeSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+use cskiplist::SkipMap;
+
+const WRITERS: u64 = 4;
+const KEYS_PER_WRITER: u64 = 2_000;
+
+#[test]
+fn writers_and_scanners() {
+    let map = SkipMap::new();
+    let done = AtomicBool::new(false);
+
+    thread::scope(|s| {
+        for _ in 0..2 {
+            s.spawn(|| {
+                while !done.load(Ordering::Relaxed) {
+                    let guard = map.guard();
+                    let keys: Vec<u64> = map.iter(&guard).map(|(k, _)| *k).collect();
+                    assert!(keys.windows(2).all(|w| w[0] < w[1]), "scan out of order");
+                    for (k, v) in map.range(100..200, &guard) {
+                        assert!((100..200).contains(k));
+                        assert_eq!(*v, k * 2);
+                    }
+                }
+            });
+        }
+
+        let writers: Vec<_> = (0..WRITERS)
+            .map(|w| {
+                let map = &map;
+                s.spawn(move || {
+                    // Keys interleave across writers, so they all contend on
+                    // the same parts of the list.
+                    let keys = (0..KEYS_PER_WRITER).map(|i| i * WRITERS + w);
+                    for k in keys.clone() {
+                        // The keys below 64 are also inserted by everyone
+                        // else, so only one of them wins.
+                        let inserted = map.insert(k, k * 2);
+                        assert!(inserted || k < 64);
+                        map.insert(k % 64, k % 64 * 2);
+                    }
+                    for k in keys.filter(|k| k % 2 == 1 && *k >= 64) {
+                        assert!(map.remove(&k));
+                        assert!(!map.contains_key(&k));
+                    }
+                })
+            })
+            .collect();
+        for w in writers {
+            w.join().unwrap();
+        }
+        done.store(true, Ordering::Relaxed);
+    });
+
+    let expected: BTreeSet<u64> = (0..WRITERS * KEYS_PER_WRITER)
+        .filter(|k| k % 2 == 0 || *k < 64)
+        .collect();
+    let guard = map.guard();
+    let keys: BTreeSet<u64> = map.iter(&guard).map(|(k, _)| *k).collect();
+    assert_eq!(keys, expected);
+    assert_eq!(map.len(), expected.len());
+}
+
+#[test]
+fn concurrent_removes_of_the_same_key() {
+    for _ in 0..200 {
+        let map = SkipMap::new();
+        for k in 0..64 {
+            map.insert(k, ());
+        }
+
+        let removed: u64 = thread::scope(|s| {
+            let removers: Vec<_> = (0..4)
+                .map(|_| s.spawn(|| (0..64).filter(|k| map.remove(k)).count() as u64))
+                .collect();
+            removers.into_iter().map(|r| r.join().unwrap()).sum()
+        });
+        assert_eq!(removed, 64);
+        assert!(map.is_empty());
+    }
+}
@@ -0,0 +1,130 @@
+use std::collections::{LinkedList, VecDeque};
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use data_structures::arena::{ArenaQueue, ArenaStack};
+use data_structures::queue::Queue;
+use data_structures::stack::{Stack, Stack2};
+
+// Small enough to stay in L1/L2, and large enough to spill out of them.
+const SIZES: [u64; 2] = [1_000, 100_000];
+
+// The number of push/pop pairs in the churn benchmarks.
+const CHURN_OPS: u64 = 10_000;
+
+// Gives every stack and queue the same API so the workloads below can be
+// shared between them.
+trait Collection: Default {
+    fn put(&mut self, val: u64);
+    fn take(&mut self) -> Option<u64>;
+}
+
+macro_rules! collection {
+    ($ty:ty, $new:expr, $put:ident, $take:ident) => {
+        impl Collection for Wrap<$ty> {
+            fn put(&mut self, val: u64) {
+                self.0.$put(val)
+            }
+            fn take(&mut self) -> Option<u64> {
+                self.0.$take()
+            }
+        }
+
+        impl Default for Wrap<$ty> {
+            fn default() -> Self {
+                Wrap($new)
+            }
+        }
+    };
+}
+
+// Local wrapper, since the traits can't be implemented for foreign types.
+struct Wrap<T>(T);
+
+collection!(Stack<u64>, Stack::new(), push, pop);
+collection!(Stack2<u64>, Stack2::new(), push, pop);
+collection!(ArenaStack<u64>, ArenaStack::new(), push, pop);
+collection!(Vec<u64>, Vec::new(), push, pop);
+
+collection!(Queue<u64>, Queue::new(), push, pop);
+collection!(ArenaQueue<u64>, ArenaQueue::new(), push, pop);
+collection!(VecDeque<u64>, VecDeque::new(), push_back, pop_front);
+collection!(LinkedList<u64>, LinkedList::new(), push_back, pop_front);
+
+// Pushes n elements and pops all of them again.
+fn fill_drain<C: Collection>(n: u64) -> u64 {
+    let mut c = C::default();
+    for i in 0..n {
+        c.put(i);
+    }
+    let mut sum = 0;
+    while let Some(v) = c.take() {
+        sum += v;
+    }
+    sum
+}
+
+// Keeps n elements in the collection while pushing and popping one at a
+// time. The node-based collections allocate and free a node for every pair,
+// the arena ones reuse the slot they just freed.
+fn churn<C: Collection>(c: &mut C) -> u64 {
+    let mut sum = 0;
+    for i in 0..CHURN_OPS {
+        c.put(i);
+        sum += c.take().unwrap();
+    }
+    sum
+}
+
+fn prefilled<C: Collection>(n: u64) -> C {
+    let mut c = C::default();
+    for i in 0..n {
+        c.put(i);
+    }
+    c
+}
+
+macro_rules! bench_group {
+    ($c:expr, $group:expr, $($name:expr => $ty:ty),+ $(,)?) => {{
+        let mut group = $c.benchmark_group(concat!($group, "/fill_drain"));
+        for n in SIZES {
+            $(
+                group.bench_with_input(BenchmarkId::new($name, n), &n, |b, &n| {
+                    b.iter(|| black_box(fill_drain::<Wrap<$ty>>(n)))
+                });
+            )+
+        }
+        group.finish();
+
+        let mut group = $c.benchmark_group(concat!($group, "/churn"));
+        for n in SIZES {
+            $(
+                let mut c: Wrap<$ty> = prefilled(n);
+                group.bench_with_input(BenchmarkId::new($name, n), &n, |b, _| {
+                    b.iter(|| black_box(churn(&mut c)))
+                });
+            )+
+        }
+        group.finish();
+    }};
+}
+
+pub fn stacks_benchmark(c: &mut Criterion) {
+    bench_group!(c, "stack",
+        "Stack" => Stack<u64>,
+        "Stack2" => Stack2<u64>,
+        "ArenaStack" => ArenaStack<u64>,
+        "Vec" => Vec<u64>,
+    );
+}
+
+pub fn queues_benchmark(c: &mut Criterion) {
+    bench_group!(c, "queue",
+        "Queue" => Queue<u64>,
+        "ArenaQueue" => ArenaQueue<u64>,
+        "VecDeque" => VecDeque<u64>,
+        "LinkedList" => LinkedList<u64>,
+    );
+}
+
+criterion_group!(benches, stacks_benchmark, queues_benchmark);
+criterion_main!(benches);
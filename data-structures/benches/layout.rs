@@ -0,0 +1,91 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use data_structures::layout::{Particle, ParticlesAos, ParticlesSoa};
+
+// From well inside L1 to well outside the last level cache, at 32 bytes per
+// particle.
+const SIZES: [usize; 3] = [1_000, 100_000, 1_000_000];
+
+fn particles(n: usize) -> impl Iterator<Item = Particle> {
+    (0..n).map(|i| {
+        let f = i as f32;
+        Particle {
+            position: [f, f, f],
+            velocity: [1.0, 0.5, -1.0],
+            mass: 1.0 + f % 7.0,
+            charge: f % 3.0 - 1.0,
+        }
+    })
+}
+
+// A fixed pseudo-random order, so whole-record reads can't be prefetched.
+fn shuffled_indices(n: usize) -> Vec<usize> {
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    let mut indices: Vec<usize> = (0..n).collect();
+    for i in (1..n).rev() {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        indices.swap(i, (state % (i as u64 + 1)) as usize);
+    }
+    indices
+}
+
+// Uses every field, so the compiler can't skip loading any of them.
+fn sum_fields(p: Particle) -> f32 {
+    p.position.iter().chain(&p.velocity).sum::<f32>() + p.mass + p.charge
+}
+
+pub fn layout_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("layout/total_mass");
+    for n in SIZES {
+        let aos: ParticlesAos = particles(n).collect();
+        let soa: ParticlesSoa = particles(n).collect();
+        group.bench_with_input(BenchmarkId::new("AoS", n), &n, |b, _| {
+            b.iter(|| black_box(aos.total_mass()))
+        });
+        group.bench_with_input(BenchmarkId::new("SoA", n), &n, |b, _| {
+            b.iter(|| black_box(soa.total_mass()))
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("layout/step");
+    for n in SIZES {
+        let mut aos: ParticlesAos = particles(n).collect();
+        let mut soa: ParticlesSoa = particles(n).collect();
+        group.bench_with_input(BenchmarkId::new("AoS", n), &n, |b, _| {
+            b.iter(|| aos.step(black_box(0.01)))
+        });
+        group.bench_with_input(BenchmarkId::new("SoA", n), &n, |b, _| {
+            b.iter(|| soa.step(black_box(0.01)))
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("layout/random_records");
+    for n in SIZES {
+        let aos: ParticlesAos = particles(n).collect();
+        let soa: ParticlesSoa = particles(n).collect();
+        let indices = shuffled_indices(n);
+        group.bench_with_input(BenchmarkId::new("AoS", n), &n, |b, _| {
+            b.iter(|| {
+                indices
+                    .iter()
+                    .map(|&i| sum_fields(aos.get(i).unwrap()))
+                    .sum::<f32>()
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("SoA", n), &n, |b, _| {
+            b.iter(|| {
+                indices
+                    .iter()
+                    .map(|&i| sum_fields(soa.get(i).unwrap()))
+                    .sum::<f32>()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, layout_benchmark);
+criterion_main!(benches);
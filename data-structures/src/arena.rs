@@ -0,0 +1,235 @@
+//! A stack and a queue that keep their nodes in a Vec and link them by index
+//! instead of by pointer.
+//!
+//! Popped slots go on a free list and are reused by the next push, so a
+//! structure that's pushed to and popped from all the time stays in the same
+//! few cache lines instead of allocating a new node somewhere on the heap
+//! for every push.
+
+struct Arena<T> {
+    slots: Vec<Slot<T>>,
+    // Head of the list of free slots, linked through Slot::Free.
+    free: Option<usize>,
+    len: usize,
+}
+
+enum Slot<T> {
+    Occupied(T),
+    Free(Option<usize>),
+}
+
+impl<T> Arena<T> {
+    fn with_capacity(capacity: usize) -> Self {
+        Self {
+            slots: Vec::with_capacity(capacity),
+            free: None,
+            len: 0,
+        }
+    }
+
+    fn alloc(&mut self, val: T) -> usize {
+        self.len += 1;
+        match self.free {
+            Some(idx) => {
+                let slot = std::mem::replace(&mut self.slots[idx], Slot::Occupied(val));
+                self.free = match slot {
+                    Slot::Free(next) => next,
+                    Slot::Occupied(_) => unreachable!("occupied slot on the free list"),
+                };
+                idx
+            }
+            None => {
+                self.slots.push(Slot::Occupied(val));
+                self.slots.len() - 1
+            }
+        }
+    }
+
+    fn dealloc(&mut self, idx: usize) -> T {
+        self.len -= 1;
+        let slot = std::mem::replace(&mut self.slots[idx], Slot::Free(self.free));
+        self.free = Some(idx);
+        match slot {
+            Slot::Occupied(val) => val,
+            Slot::Free(_) => unreachable!("freed slot {} twice", idx),
+        }
+    }
+
+    fn get(&self, idx: usize) -> &T {
+        match &self.slots[idx] {
+            Slot::Occupied(val) => val,
+            Slot::Free(_) => unreachable!("slot {} is free", idx),
+        }
+    }
+
+    fn get_mut(&mut self, idx: usize) -> &mut T {
+        match &mut self.slots[idx] {
+            Slot::Occupied(val) => val,
+            Slot::Free(_) => unreachable!("slot {} is free", idx),
+        }
+    }
+}
+
+struct Node<T> {
+    val: T,
+    next: Option<usize>,
+}
+
+pub struct ArenaStack<T> {
+    arena: Arena<Node<T>>,
+    head: Option<usize>,
+}
+
+impl<T> ArenaStack<T> {
+    pub fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            arena: Arena::with_capacity(capacity),
+            head: None,
+        }
+    }
+
+    pub fn push(&mut self, val: T) {
+        let next = self.head;
+        self.head = Some(self.arena.alloc(Node { val, next }));
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        let head = self.arena.dealloc(self.head?);
+        self.head = head.next;
+        Some(head.val)
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.head.map(|idx| &self.arena.get(idx).val)
+    }
+
+    pub fn len(&self) -> usize {
+        self.arena.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.is_none()
+    }
+}
+
+impl<T> Default for ArenaStack<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct ArenaQueue<T> {
+    arena: Arena<Node<T>>,
+    head: Option<usize>,
+    tail: Option<usize>,
+}
+
+impl<T> ArenaQueue<T> {
+    pub fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            arena: Arena::with_capacity(capacity),
+            head: None,
+            tail: None,
+        }
+    }
+
+    pub fn push(&mut self, val: T) {
+        let idx = self.arena.alloc(Node { val, next: None });
+        match self.tail {
+            None => self.head = Some(idx),
+            Some(tail) => self.arena.get_mut(tail).next = Some(idx),
+        }
+        self.tail = Some(idx);
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        let head = self.arena.dealloc(self.head?);
+        self.head = head.next;
+        if self.head.is_none() {
+            self.tail = None;
+        }
+        Some(head.val)
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.head.map(|idx| &self.arena.get(idx).val)
+    }
+
+    pub fn len(&self) -> usize {
+        self.arena.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.is_none()
+    }
+}
+
+impl<T> Default for ArenaQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn popped_slots_are_reused() {
+        let mut stack = ArenaStack::new();
+        for i in 0..10 {
+            stack.push(i);
+        }
+        for _ in 0..5 {
+            stack.pop();
+        }
+        for i in 0..5 {
+            stack.push(i);
+        }
+        assert_eq!(stack.arena.slots.len(), 10);
+        assert_eq!(stack.len(), 10);
+
+        let mut queue = ArenaQueue::new();
+        for i in 0..1000 {
+            queue.push(i);
+            assert_eq!(queue.pop(), Some(i));
+        }
+        assert_eq!(queue.arena.slots.len(), 1);
+    }
+
+    #[test]
+    fn peek_and_len() {
+        let mut queue = ArenaQueue::new();
+        assert_eq!(queue.peek(), None);
+        queue.push("a");
+        queue.push("b");
+        assert_eq!(queue.peek(), Some(&"a"));
+        assert_eq!(queue.len(), 2);
+
+        let mut stack = ArenaStack::new();
+        stack.push("a");
+        stack.push("b");
+        assert_eq!(stack.peek(), Some(&"b"));
+        assert_eq!(stack.len(), 2);
+    }
+
+    #[test]
+    fn drops_remaining_elements() {
+        let val = std::rc::Rc::new(());
+        let mut queue = ArenaQueue::new();
+        for _ in 0..3 {
+            queue.push(std::rc::Rc::clone(&val));
+        }
+        queue.pop();
+        drop(queue);
+        assert_eq!(std::rc::Rc::strong_count(&val), 1);
+    }
+}
@@ -0,0 +1,286 @@
+//! A bit vector that answers rank and select queries, the building block of
+//! succinct data structures.
+//!
+//! `rank1(i)` counts the ones before position `i` and `select1(k)` finds the
+//! position of the k-th one (and likewise for zeros). Both are answered from
+//! counts that are computed once, when the vector is built:
+//!
+//! - every superblock of 512 bits stores the number of ones before it,
+//! - every 64-bit word stores the number of ones between the start of its
+//!   superblock and itself, which fits into a u16.
+//!
+//! So rank is a lookup in each plus a popcount. Select binary searches the
+//! superblocks, but only between two samples: the superblocks of every
+//! 4096th one (or zero). When the bits are spread evenly that's a handful of
+//! superblocks, otherwise the search is logarithmic. Within a superblock at
+//! most 8 words are scanned. The counts take about 40% of the space of the
+//! bits themselves.
+
+const WORD: usize = 64;
+const WORDS_PER_SUPER: usize = 8;
+const SUPER: usize = WORD * WORDS_PER_SUPER;
+const SAMPLE: usize = 4096;
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct BitVec {
+    words: Vec<u64>,
+    len: usize,
+    // Ones before every superblock, plus the total at the end.
+    supers: Vec<u64>,
+    // Ones between the start of the superblock and every word.
+    blocks: Vec<u16>,
+    // The superblocks of every SAMPLE-th one and zero.
+    ones_samples: Vec<u32>,
+    zeros_samples: Vec<u32>,
+}
+
+impl BitVec {
+    /// Takes the first `len` bits of `words`, least significant bit first.
+    /// Panics if `words` holds less than `len` bits.
+    pub fn from_words(mut words: Vec<u64>, len: usize) -> Self {
+        assert!(words.len() * WORD >= len, "fewer than {} bits", len);
+        words.truncate(len.div_ceil(WORD));
+        // Clearing the bits past the end, so they don't count as ones.
+        if !len.is_multiple_of(WORD) {
+            *words.last_mut().unwrap() &= (1 << (len % WORD)) - 1;
+        }
+
+        let mut bv = Self {
+            words,
+            len,
+            ..Self::default()
+        };
+        bv.build();
+        bv
+    }
+
+    fn build(&mut self) {
+        let mut ones = 0;
+        for (s, chunk) in self.words.chunks(WORDS_PER_SUPER).enumerate() {
+            self.supers.push(ones);
+            let chunk_ones: u64 = chunk.iter().map(|w| w.count_ones() as u64).sum();
+            let zeros_before = (s * SUPER) as u64 - ones;
+            let chunk_zeros = (chunk.len() * WORD) as u64 - chunk_ones;
+            push_samples(&mut self.ones_samples, s, ones..ones + chunk_ones);
+            push_samples(
+                &mut self.zeros_samples,
+                s,
+                zeros_before..zeros_before + chunk_zeros,
+            );
+
+            let mut in_super = 0;
+            for word in chunk {
+                self.blocks.push(in_super);
+                in_super += word.count_ones() as u16;
+            }
+            ones += in_super as u64;
+        }
+        self.supers.push(ones);
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, i: usize) -> Option<bool> {
+        if i >= self.len {
+            return None;
+        }
+        Some(self.words[i / WORD] >> (i % WORD) & 1 == 1)
+    }
+
+    pub fn count_ones(&self) -> usize {
+        *self.supers.last().unwrap_or(&0) as usize
+    }
+
+    pub fn count_zeros(&self) -> usize {
+        self.len - self.count_ones()
+    }
+
+    /// The number of ones before position `i`. Panics if `i > len`.
+    pub fn rank1(&self, i: usize) -> usize {
+        assert!(i <= self.len, "rank of {} out of {}", i, self.len);
+        if i == self.len {
+            return self.count_ones();
+        }
+        let w = i / WORD;
+        let in_word = self.words[w] & ((1 << (i % WORD)) - 1);
+        self.supers[i / SUPER] as usize + self.blocks[w] as usize + in_word.count_ones() as usize
+    }
+
+    /// The number of zeros before position `i`. Panics if `i > len`.
+    pub fn rank0(&self, i: usize) -> usize {
+        i - self.rank1(i)
+    }
+
+    /// The position of the k-th one, counting from 0.
+    pub fn select1(&self, k: usize) -> Option<usize> {
+        if k >= self.count_ones() {
+            return None;
+        }
+        let k = k as u64;
+        let s = self.find_super(&self.ones_samples, k, |s| self.supers[s]);
+
+        // The last word of the superblock with at most k ones before it.
+        let words = s * WORDS_PER_SUPER..self.blocks.len().min((s + 1) * WORDS_PER_SUPER);
+        let k = k - self.supers[s];
+        let w = words.rev().find(|&w| self.blocks[w] as u64 <= k).unwrap();
+        let k = k - self.blocks[w] as u64;
+        Some(w * WORD + select_in_word(self.words[w], k as u32))
+    }
+
+    /// The position of the k-th zero, counting from 0.
+    pub fn select0(&self, k: usize) -> Option<usize> {
+        if k >= self.count_zeros() {
+            return None;
+        }
+        let k = k as u64;
+        let zeros_before_super = |s: usize| (s * SUPER) as u64 - self.supers[s];
+        let s = self.find_super(&self.zeros_samples, k, zeros_before_super);
+
+        let first = s * WORDS_PER_SUPER;
+        let words = first..self.blocks.len().min(first + WORDS_PER_SUPER);
+        let k = k - zeros_before_super(s);
+        let zeros_before_word = |w: usize| ((w - first) * WORD) as u64 - self.blocks[w] as u64;
+        let w = words.rev().find(|&w| zeros_before_word(w) <= k).unwrap();
+        let k = k - zeros_before_word(w);
+        // The padding of the last word is never reached, k is in range.
+        Some(w * WORD + select_in_word(!self.words[w], k as u32))
+    }
+
+    // The last superblock with at most k ones (or zeros) before it, where
+    // `before` counts them.
+    fn find_super(&self, samples: &[u32], k: u64, before: impl Fn(usize) -> u64) -> usize {
+        let sample = k as usize / SAMPLE;
+        let lo = samples[sample] as usize;
+        let hi = samples
+            .get(sample + 1)
+            .map_or(self.supers.len() - 1, |&s| s as usize + 1);
+
+        let mut range = lo..hi;
+        // Binary search for the first superblock with more than k before it.
+        while range.start < range.end {
+            let mid = range.start + (range.end - range.start) / 2;
+            if before(mid) <= k {
+                range.start = mid + 1;
+            } else {
+                range.end = mid;
+            }
+        }
+        range.start - 1
+    }
+}
+
+impl FromIterator<bool> for BitVec {
+    fn from_iter<I: IntoIterator<Item = bool>>(iter: I) -> Self {
+        let mut words = vec![];
+        let mut len: usize = 0;
+        for bit in iter {
+            if len.is_multiple_of(WORD) {
+                words.push(0);
+            }
+            if bit {
+                *words.last_mut().unwrap() |= 1 << (len % WORD);
+            }
+            len += 1;
+        }
+        Self::from_words(words, len)
+    }
+}
+
+// Adds superblock s to the samples of the ones (or zeros) in `range`, which
+// are the ones in the superblock.
+fn push_samples(samples: &mut Vec<u32>, s: usize, range: std::ops::Range<u64>) {
+    while range.contains(&((samples.len() * SAMPLE) as u64)) {
+        samples.push(s as u32);
+    }
+}
+
+// The position of the k-th one in word, which must have more than k ones.
+fn select_in_word(mut word: u64, k: u32) -> usize {
+    for _ in 0..k {
+        // Clearing the lowest one.
+        word &= word - 1;
+    }
+    word.trailing_zeros() as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+
+    // Checks every query against a scan of the plain bits.
+    fn check(bits: &[bool]) {
+        let bv: BitVec = bits.iter().copied().collect();
+        assert_eq!(bv.len(), bits.len());
+
+        let mut ones = vec![];
+        let mut zeros = vec![];
+        for (i, &bit) in bits.iter().enumerate() {
+            assert_eq!(bv.get(i), Some(bit));
+            assert_eq!(bv.rank1(i), ones.len(), "rank1({})", i);
+            assert_eq!(bv.rank0(i), zeros.len(), "rank0({})", i);
+            if bit {
+                ones.push(i);
+            } else {
+                zeros.push(i);
+            }
+        }
+        assert_eq!(bv.get(bits.len()), None);
+        assert_eq!(bv.rank1(bits.len()), ones.len());
+        assert_eq!(bv.count_ones(), ones.len());
+        assert_eq!(bv.count_zeros(), zeros.len());
+
+        for (k, &i) in ones.iter().enumerate() {
+            assert_eq!(bv.select1(k), Some(i), "select1({})", k);
+        }
+        for (k, &i) in zeros.iter().enumerate() {
+            assert_eq!(bv.select0(k), Some(i), "select0({})", k);
+        }
+        assert_eq!(bv.select1(ones.len()), None);
+        assert_eq!(bv.select0(zeros.len()), None);
+    }
+
+    #[test]
+    fn random_bits_match_naive_scan() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+        for density in [0.001, 0.1, 0.5, 0.9, 0.999] {
+            for len in [0, 1, 63, 64, 65, 511, 512, 513, 10_000, 100_000] {
+                let bits: Vec<bool> = (0..len).map(|_| rng.gen_bool(density)).collect();
+                check(&bits);
+            }
+        }
+    }
+
+    #[test]
+    fn uniform_and_clustered_bits() {
+        check(&[true; 20_000]);
+        check(&[false; 20_000]);
+
+        // Long runs put many samples into a single superblock, and leave
+        // long gaps between others.
+        let mut bits = vec![false; 50_000];
+        bits[..9000].fill(true);
+        bits[49_000..].fill(true);
+        bits[25_000] = true;
+        check(&bits);
+    }
+
+    #[test]
+    fn from_words_ignores_bits_past_the_end() {
+        let bv = BitVec::from_words(vec![u64::MAX, u64::MAX], 70);
+        assert_eq!(bv.count_ones(), 70);
+        assert_eq!(bv.select1(69), Some(69));
+        assert_eq!(bv.select1(70), None);
+        assert_eq!(bv.select0(0), None);
+
+        let bv = BitVec::from_words(vec![0b1010, 0, 0], 64);
+        assert_eq!(bv.select1(1), Some(3));
+        assert_eq!(bv.rank0(64), 62);
+    }
+}
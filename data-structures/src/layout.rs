@@ -0,0 +1,157 @@
+//! The same collection of records laid out as an array of structs (AoS) and
+//! as a struct of arrays (SoA).
+//!
+//! With AoS a pass over a single field still drags every other field of the
+//! records through the cache: a Particle is 32 bytes, so only two masses fit
+//! in a 64-byte cache line. With SoA the masses are contiguous and 16 fit in
+//! a line, which also lets the compiler vectorize the loop. The flip side is
+//! reading whole records, which touches one cache line with AoS but one per
+//! field with SoA. `benches/layout.rs` measures both.
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Particle {
+    pub position: [f32; 3],
+    pub velocity: [f32; 3],
+    pub mass: f32,
+    pub charge: f32,
+}
+
+/// Array of structs.
+#[derive(Debug, Default)]
+pub struct ParticlesAos {
+    particles: Vec<Particle>,
+}
+
+/// Struct of arrays: the i-th particle is made of the i-th element of every
+/// Vec.
+#[derive(Debug, Default)]
+pub struct ParticlesSoa {
+    positions: Vec<[f32; 3]>,
+    velocities: Vec<[f32; 3]>,
+    masses: Vec<f32>,
+    charges: Vec<f32>,
+}
+
+impl ParticlesAos {
+    pub fn push(&mut self, p: Particle) {
+        self.particles.push(p);
+    }
+
+    pub fn len(&self) -> usize {
+        self.particles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.particles.is_empty()
+    }
+
+    pub fn get(&self, i: usize) -> Option<Particle> {
+        self.particles.get(i).copied()
+    }
+
+    /// Reads one field of every record.
+    pub fn total_mass(&self) -> f32 {
+        self.particles.iter().map(|p| p.mass).sum()
+    }
+
+    /// Reads two fields and writes one of every record.
+    pub fn step(&mut self, dt: f32) {
+        for p in &mut self.particles {
+            advance(&mut p.position, &p.velocity, dt);
+        }
+    }
+}
+
+impl ParticlesSoa {
+    pub fn push(&mut self, p: Particle) {
+        self.positions.push(p.position);
+        self.velocities.push(p.velocity);
+        self.masses.push(p.mass);
+        self.charges.push(p.charge);
+    }
+
+    pub fn len(&self) -> usize {
+        self.masses.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.masses.is_empty()
+    }
+
+    pub fn get(&self, i: usize) -> Option<Particle> {
+        Some(Particle {
+            position: *self.positions.get(i)?,
+            velocity: self.velocities[i],
+            mass: self.masses[i],
+            charge: self.charges[i],
+        })
+    }
+
+    pub fn total_mass(&self) -> f32 {
+        self.masses.iter().sum()
+    }
+
+    pub fn step(&mut self, dt: f32) {
+        for (position, velocity) in self.positions.iter_mut().zip(&self.velocities) {
+            advance(position, velocity, dt);
+        }
+    }
+}
+
+fn advance(position: &mut [f32; 3], velocity: &[f32; 3], dt: f32) {
+    for (x, v) in position.iter_mut().zip(velocity) {
+        *x += v * dt;
+    }
+}
+
+impl FromIterator<Particle> for ParticlesAos {
+    fn from_iter<I: IntoIterator<Item = Particle>>(iter: I) -> Self {
+        Self {
+            particles: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl FromIterator<Particle> for ParticlesSoa {
+    fn from_iter<I: IntoIterator<Item = Particle>>(iter: I) -> Self {
+        let mut soa = Self::default();
+        iter.into_iter().for_each(|p| soa.push(p));
+        soa
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn particles(n: usize) -> impl Iterator<Item = Particle> {
+        (0..n).map(|i| {
+            let f = i as f32;
+            Particle {
+                position: [f, f + 1.0, f + 2.0],
+                velocity: [1.0, -1.0, 0.5],
+                mass: f,
+                charge: -f,
+            }
+        })
+    }
+
+    #[test]
+    fn layouts_agree() {
+        let mut aos: ParticlesAos = particles(100).collect();
+        let mut soa: ParticlesSoa = particles(100).collect();
+        assert_eq!(aos.len(), 100);
+        assert_eq!(soa.len(), 100);
+        assert_eq!(aos.total_mass(), soa.total_mass());
+        assert_eq!(aos.total_mass(), (0..100).sum::<usize>() as f32);
+
+        aos.step(2.0);
+        soa.step(2.0);
+        for i in 0..100 {
+            assert_eq!(aos.get(i), soa.get(i));
+        }
+        assert_eq!(soa.get(0).unwrap().position, [2.0, -1.0, 3.0]);
+        assert_eq!(aos.get(100), None);
+        assert_eq!(soa.get(100), None);
+    }
+}
@@ -1,3 +1,11 @@
-mod stack;
+pub mod stack;
 
-mod queue;
+pub mod queue;
+
+pub mod arena;
+
+pub mod layout;
+
+pub mod sync;
+
+pub mod bitvec;
@@ -11,6 +11,12 @@ pub struct Queue<T: Debug + Default> {
     tail: OptNode<T>,
 }
 
+// SAFETY: the Rcs only ever point to nodes of the same queue and are never
+// handed out, so moving the queue to another thread moves every reference
+// count along with it. This is what lets sync::SharedQueue put it behind a
+// Mutex.
+unsafe impl<T: Debug + Default + Send> Send for Queue<T> {}
+
 #[derive(Debug)]
 struct Node<T: Debug> {
     id: usize,
@@ -18,6 +24,8 @@ struct Node<T: Debug> {
     prev: OptNode<T>,
 }
 
+// Only in debug builds, so the benchmarks measure the queue and not stdout.
+#[cfg(debug_assertions)]
 impl<T: Debug> Drop for Node<T> {
     fn drop(&mut self) {
         println!("Node dropped = {:?}", self);
@@ -123,6 +131,12 @@ impl<T: Debug + Default> Queue<T> {
     }
 }
 
+impl<T: Debug + Default> Default for Queue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
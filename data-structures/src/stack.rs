@@ -98,6 +98,18 @@ impl<T> Stack2<T> {
     }
 }
 
+impl<T> Default for Stack<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Default for Stack2<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<T> Entry<T> {
     pub fn new(val: T) -> Self {
         Self { val, prev: None }
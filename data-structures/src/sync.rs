@@ -0,0 +1,368 @@
+//! Thread-safe versions of the single-threaded structures in this crate.
+//!
+//! The wrappers are cheap to clone and every clone refers to the same
+//! structure. Locking is an implementation detail, the guards never leave
+//! the methods so callers can't hold on to them by accident.
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::queue::Queue;
+use crate::stack::Stack;
+
+pub struct SharedStack<T> {
+    inner: Arc<Mutex<Stack<T>>>,
+}
+
+impl<T> SharedStack<T> {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Stack::new())),
+        }
+    }
+
+    pub fn push(&self, val: T) {
+        self.lock().push(val);
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        self.lock().pop()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Stack<T>> {
+        // Stack operations can't leave it in an inconsistent state, so a
+        // panic in another thread doesn't need to be propagated.
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl<T> Clone for SharedStack<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl<T> Default for SharedStack<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct SharedQueue<T: Debug + Default> {
+    inner: Arc<Mutex<Queue<T>>>,
+}
+
+impl<T: Debug + Default> SharedQueue<T> {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Queue::new())),
+        }
+    }
+
+    pub fn push(&self, val: T) {
+        self.lock().push(val);
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        self.lock().pop()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Queue<T>> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl<T: Debug + Default> Clone for SharedQueue<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl<T: Debug + Default> Default for SharedQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// The same behavioral tests are run against every stack and queue in the
+// workspace, so they all agree on things like popping from an empty
+// structure and ordering.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    // Adapts the different APIs (&mut self vs &self, blocking pop) to the
+    // one the suites are written against. The methods are named differently
+    // so inherent methods don't shadow them.
+    trait Container {
+        fn new() -> Self;
+        fn put(&mut self, val: i32);
+        fn take(&mut self) -> Option<i32>;
+    }
+
+    impl Container for Stack<i32> {
+        fn new() -> Self {
+            Stack::new()
+        }
+        fn put(&mut self, val: i32) {
+            Stack::push(self, val)
+        }
+        fn take(&mut self) -> Option<i32> {
+            Stack::pop(self)
+        }
+    }
+
+    impl Container for crate::stack::Stack2<i32> {
+        fn new() -> Self {
+            crate::stack::Stack2::new()
+        }
+        fn put(&mut self, val: i32) {
+            crate::stack::Stack2::push(self, val)
+        }
+        fn take(&mut self) -> Option<i32> {
+            crate::stack::Stack2::pop(self)
+        }
+    }
+
+    impl Container for SharedStack<i32> {
+        fn new() -> Self {
+            SharedStack::new()
+        }
+        fn put(&mut self, val: i32) {
+            SharedStack::push(self, val)
+        }
+        fn take(&mut self) -> Option<i32> {
+            SharedStack::pop(self)
+        }
+    }
+
+    impl Container for treiber_stack::Stack<i32> {
+        fn new() -> Self {
+            treiber_stack::Stack::new()
+        }
+        fn put(&mut self, val: i32) {
+            treiber_stack::Stack::push(self, val)
+        }
+        fn take(&mut self) -> Option<i32> {
+            treiber_stack::Stack::pop(self)
+        }
+    }
+
+    impl Container for Queue<i32> {
+        fn new() -> Self {
+            Queue::new()
+        }
+        fn put(&mut self, val: i32) {
+            Queue::push(self, val)
+        }
+        fn take(&mut self) -> Option<i32> {
+            Queue::pop(self)
+        }
+    }
+
+    impl Container for SharedQueue<i32> {
+        fn new() -> Self {
+            SharedQueue::new()
+        }
+        fn put(&mut self, val: i32) {
+            SharedQueue::push(self, val)
+        }
+        fn take(&mut self) -> Option<i32> {
+            SharedQueue::pop(self)
+        }
+    }
+
+    impl Container for crate::arena::ArenaStack<i32> {
+        fn new() -> Self {
+            crate::arena::ArenaStack::new()
+        }
+        fn put(&mut self, val: i32) {
+            crate::arena::ArenaStack::push(self, val)
+        }
+        fn take(&mut self) -> Option<i32> {
+            crate::arena::ArenaStack::pop(self)
+        }
+    }
+
+    impl Container for crate::arena::ArenaQueue<i32> {
+        fn new() -> Self {
+            crate::arena::ArenaQueue::new()
+        }
+        fn put(&mut self, val: i32) {
+            crate::arena::ArenaQueue::push(self, val)
+        }
+        fn take(&mut self) -> Option<i32> {
+            crate::arena::ArenaQueue::pop(self)
+        }
+    }
+
+    impl Container for michael_scott_q::Queue<i32> {
+        fn new() -> Self {
+            michael_scott_q::Queue::new()
+        }
+        fn put(&mut self, val: i32) {
+            michael_scott_q::Queue::push(self, val)
+        }
+        fn take(&mut self) -> Option<i32> {
+            // pop spins until there's an element, which is fine here since
+            // nothing else has access to the queue.
+            if self.is_empty() {
+                None
+            } else {
+                Some(michael_scott_q::Queue::pop(self))
+            }
+        }
+    }
+
+    fn drain<C: Container>(c: &mut C) -> Vec<i32> {
+        std::iter::from_fn(|| c.take()).collect()
+    }
+
+    macro_rules! common_suite {
+        ($ty:ty) => {
+            #[test]
+            fn pop_from_empty_is_none() {
+                let mut c = <$ty as Container>::new();
+                assert_eq!(c.take(), None);
+                assert_eq!(c.take(), None);
+            }
+
+            #[test]
+            fn pop_after_drained_is_none() {
+                let mut c = <$ty as Container>::new();
+                c.put(1);
+                c.put(2);
+                assert_eq!(drain(&mut c).len(), 2);
+                assert_eq!(c.take(), None);
+            }
+
+            #[test]
+            fn reusable_after_drained() {
+                let mut c = <$ty as Container>::new();
+                c.put(1);
+                assert_eq!(c.take(), Some(1));
+                assert_eq!(c.take(), None);
+
+                c.put(2);
+                assert_eq!(c.take(), Some(2));
+                assert_eq!(c.take(), None);
+            }
+        };
+    }
+
+    macro_rules! lifo_suite {
+        ($name:ident, $ty:ty) => {
+            mod $name {
+                use super::*;
+
+                common_suite!($ty);
+
+                #[test]
+                fn last_in_first_out() {
+                    let mut c = <$ty as Container>::new();
+                    for i in 0..100 {
+                        c.put(i);
+                    }
+                    assert_eq!(drain(&mut c), (0..100).rev().collect::<Vec<_>>());
+                }
+
+                #[test]
+                fn interleaved_push_pop() {
+                    let mut c = <$ty as Container>::new();
+                    c.put(1);
+                    c.put(2);
+                    assert_eq!(c.take(), Some(2));
+                    c.put(3);
+                    assert_eq!(drain(&mut c), vec![3, 1]);
+                }
+            }
+        };
+    }
+
+    macro_rules! fifo_suite {
+        ($name:ident, $ty:ty) => {
+            mod $name {
+                use super::*;
+
+                common_suite!($ty);
+
+                #[test]
+                fn first_in_first_out() {
+                    let mut c = <$ty as Container>::new();
+                    for i in 0..100 {
+                        c.put(i);
+                    }
+                    assert_eq!(drain(&mut c), (0..100).collect::<Vec<_>>());
+                }
+
+                #[test]
+                fn interleaved_push_pop() {
+                    let mut c = <$ty as Container>::new();
+                    c.put(1);
+                    c.put(2);
+                    assert_eq!(c.take(), Some(1));
+                    c.put(3);
+                    assert_eq!(drain(&mut c), vec![2, 3]);
+                }
+            }
+        };
+    }
+
+    lifo_suite!(stack, Stack<i32>);
+    lifo_suite!(stack2, crate::stack::Stack2<i32>);
+    lifo_suite!(shared_stack, SharedStack<i32>);
+    lifo_suite!(treiber, treiber_stack::Stack<i32>);
+    lifo_suite!(arena_stack, crate::arena::ArenaStack<i32>);
+
+    fifo_suite!(queue, Queue<i32>);
+    fifo_suite!(shared_queue, SharedQueue<i32>);
+    fifo_suite!(michael_scott, michael_scott_q::Queue<i32>);
+    fifo_suite!(arena_queue, crate::arena::ArenaQueue<i32>);
+
+    #[test]
+    fn shared_stack_clones_share_elements() {
+        let stack = SharedStack::new();
+        let handles: Vec<_> = (0..4)
+            .map(|t| {
+                let stack = stack.clone();
+                thread::spawn(move || {
+                    for i in 0..1000 {
+                        stack.push(t * 1000 + i);
+                    }
+                })
+            })
+            .collect();
+        handles.into_iter().for_each(|h| h.join().unwrap());
+
+        let mut popped: Vec<_> = std::iter::from_fn(|| stack.pop()).collect();
+        popped.sort();
+        assert_eq!(popped, (0..4000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn shared_queue_keeps_per_producer_order() {
+        let queue = SharedQueue::new();
+        let handles: Vec<_> = (0..4)
+            .map(|t| {
+                let queue = queue.clone();
+                thread::spawn(move || {
+                    for i in 0..1000 {
+                        queue.push((t, i));
+                    }
+                })
+            })
+            .collect();
+        handles.into_iter().for_each(|h| h.join().unwrap());
+
+        let mut last_seen = [None; 4];
+        while let Some((t, i)) = queue.pop() {
+            assert!(last_seen[t].is_none_or(|last| last < i));
+            last_seen[t] = Some(i);
+        }
+        assert_eq!(last_seen, [Some(999); 4]);
+    }
+}
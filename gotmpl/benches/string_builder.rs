@@ -1,8 +1,10 @@
 use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
 use gotmpl::enum_parser::{parse, parse_cap};
-use gotmpl::flexi_parser::{parse as fparse, parse_ref as fparse_ref};
+use gotmpl::flexi_parser::{parse as fparse, parse_ref as fparse_ref, render_borrowed, Template};
 use gotmpl::simple_parser::parse as simple_parse;
+use handlebars::Handlebars;
 use std::collections::HashMap;
+use tera::{Context, Tera};
 
 pub fn string_builder_benchmark(c: &mut Criterion) {
     let tmpl = std::fs::read_to_string("templates/large.tmpl").unwrap();
@@ -58,8 +60,108 @@ pub fn string_builder_benchmark(c: &mut Criterion) {
         },
     );
 
+    // Tokenized once, outside of the measured loop.
+    group.bench_with_input(
+        BenchmarkId::new("flexi_parser/template_render", "large_tmpl"),
+        &(Template::compile(&tmpl).unwrap(), data.clone()),
+        |b, (template, data)| {
+            b.iter(|| template.render(black_box(data)));
+        },
+    );
+
+    let borrowed: HashMap<&str, &str> = data.iter().map(|(k, v)| (&**k, &**v)).collect();
+    group.bench_with_input(
+        BenchmarkId::new("flexi_parser/render_borrowed", "large_tmpl"),
+        &(tmpl.as_str(), borrowed),
+        |b, (tmpl, data)| {
+            b.iter(|| render_borrowed(black_box(tmpl), black_box(data)));
+        },
+    );
+
+    group.finish();
+}
+
+// The same template through this crate, handlebars and tera, which parse
+// `{{ name1 }}` alike. Compiling and rendering are measured separately, as
+// a template is usually compiled once and rendered many times.
+pub fn engines_benchmark(c: &mut Criterion) {
+    let tmpl = std::fs::read_to_string("templates/large.tmpl").unwrap();
+    let data: HashMap<String, String> = (1..=3)
+        .flat_map(|i| {
+            [
+                (format!("name{}", i), format!("A{}", i)),
+                (format!("surname{}", i), format!("M{}", i)),
+            ]
+        })
+        .collect();
+
+    let mut group = c.benchmark_group("engines");
+
+    group.bench_with_input(
+        BenchmarkId::new("gotmpl/compile", "large_tmpl"),
+        &tmpl,
+        |b, tmpl| {
+            b.iter(|| Template::compile(black_box(tmpl)).unwrap());
+        },
+    );
+    group.bench_with_input(
+        BenchmarkId::new("handlebars/compile", "large_tmpl"),
+        &tmpl,
+        |b, tmpl| {
+            b.iter(|| {
+                let mut hbs = Handlebars::new();
+                hbs.register_template_string("large", black_box(tmpl))
+                    .unwrap();
+                hbs
+            });
+        },
+    );
+    group.bench_with_input(
+        BenchmarkId::new("tera/compile", "large_tmpl"),
+        &tmpl,
+        |b, tmpl| {
+            b.iter(|| {
+                let mut tera = Tera::default();
+                tera.add_raw_template("large", black_box(tmpl)).unwrap();
+                tera
+            });
+        },
+    );
+
+    group.bench_with_input(
+        BenchmarkId::new("gotmpl/render", "large_tmpl"),
+        &(Template::compile(&tmpl).unwrap(), data.clone()),
+        |b, (template, data)| {
+            b.iter(|| template.render(black_box(data)).unwrap());
+        },
+    );
+
+    let mut hbs = Handlebars::new();
+    // Neither of the others escapes HTML here.
+    hbs.register_escape_fn(handlebars::no_escape);
+    hbs.register_template_string("large", &tmpl).unwrap();
+    group.bench_with_input(
+        BenchmarkId::new("handlebars/render", "large_tmpl"),
+        &(hbs, data.clone()),
+        |b, (hbs, data)| {
+            b.iter(|| hbs.render("large", black_box(data)).unwrap());
+        },
+    );
+
+    // Templates whose names don't end with .html aren't escaped.
+    let mut tera = Tera::default();
+    tera.add_raw_template("large", &tmpl).unwrap();
+    let context = Context::from_serialize(&data).unwrap();
+    group.bench_with_input(
+        BenchmarkId::new("tera/render", "large_tmpl"),
+        &(tera, context),
+        |b, (tera, context)| {
+            b.iter(|| tera.render("large", black_box(context)).unwrap());
+        },
+    );
+
     group.finish();
 }
 
-criterion_group!(benches, string_builder_benchmark);
+criterion_group!(benches, string_builder_benchmark, engines_benchmark);
 criterion_main!(benches);
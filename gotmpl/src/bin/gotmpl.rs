@@ -0,0 +1,119 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::ExitCode;
+
+use serde_json::Value as Json;
+
+use gotmpl::flexi_parser::{lint, parse_with_json, Template};
+
+const USAGE: &str = "usage: gotmpl [--front-matter] TEMPLATE DATA [-o OUTPUT]
+       gotmpl --check TEMPLATE...";
+
+// Usage: gotmpl [--front-matter] TEMPLATE DATA [-o OUTPUT]
+//        gotmpl --check TEMPLATE...
+//
+// Renders the template file TEMPLATE with the fields of DATA, a JSON object,
+// or a YAML mapping if its name ends with .yaml or .yml. The output goes to
+// stdout, or to OUTPUT.
+//
+// With --front-matter, TEMPLATE can start with a front matter that declares
+// defaults and required keys, see `Template::compile_with_front_matter`.
+//
+// With --check, lints the TEMPLATE files instead, printing the warnings as
+// `path:line:column: warning`.
+//
+// Exits with 1 if the template doesn't render or has warnings, 2 on bad
+// usage and 3 if a file can't be read or written or the data can't be
+// parsed.
+fn main() -> ExitCode {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let front_matter = args.first().is_some_and(|a| a == "--front-matter");
+    if front_matter {
+        args.remove(0);
+    }
+    let (tmpl, data, output) = match args.as_slice() {
+        [check, ..] if front_matter && check == "--check" => {
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+        [check, paths @ ..] if check == "--check" && !paths.is_empty() => return check_all(paths),
+        [tmpl, data] => (tmpl, data, None),
+        [tmpl, data, o, output] if o == "-o" => (tmpl, data, Some(output)),
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    let (tmpl, data) = match read(tmpl).and_then(|tmpl| Ok((tmpl, load(data)?))) {
+        Ok(read) => read,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::from(3);
+        }
+    };
+
+    let rendered = if front_matter {
+        Template::compile_with_front_matter(&tmpl).and_then(|t| t.render_json(&data))
+    } else {
+        parse_with_json(tmpl, &data)
+    };
+    let rendered = match rendered {
+        Ok(rendered) => rendered,
+        Err(e) => {
+            eprintln!("couldn't render template: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let written = match output {
+        Some(path) => fs::write(path, rendered).map_err(|e| (path.as_str(), e)),
+        None => io::stdout()
+            .write_all(rendered.as_bytes())
+            .map_err(|e| ("stdout", e)),
+    };
+    if let Err((path, e)) = written {
+        eprintln!("couldn't write {}: {}", path, e);
+        return ExitCode::from(3);
+    }
+    ExitCode::SUCCESS
+}
+
+fn check_all(paths: &[String]) -> ExitCode {
+    let mut code = ExitCode::SUCCESS;
+    for path in paths {
+        let tmpl = match read(path) {
+            Ok(tmpl) => tmpl,
+            Err(e) => {
+                eprintln!("{}", e);
+                code = ExitCode::from(3);
+                continue;
+            }
+        };
+        for w in lint(&tmpl) {
+            println!("{}:{}:{}: {}", path, w.line, w.column, w.lint);
+            if code == ExitCode::SUCCESS {
+                code = ExitCode::FAILURE;
+            }
+        }
+    }
+    code
+}
+
+fn read(path: &str) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|e| format!("couldn't read {}: {}", path, e))
+}
+
+fn load(path: &str) -> Result<Json, String> {
+    let src = read(path)?;
+    let yaml = Path::new(path)
+        .extension()
+        .is_some_and(|ext| ext == "yaml" || ext == "yml");
+    let data = if yaml {
+        serde_norway::from_str(&src).map_err(|e| e.to_string())
+    } else {
+        serde_json::from_str(&src).map_err(|e| e.to_string())
+    };
+    data.map_err(|e| format!("couldn't parse {}: {}", path, e))
+}
@@ -1,5 +1,8 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
 
+use crate::MissingKeyPolicy;
+
 #[derive(Debug)]
 enum Token<'a> {
     String(&'a str),
@@ -7,23 +10,43 @@ enum Token<'a> {
 }
 
 trait StringFromTokens {
-    fn build(&self, tokens: &[Token], data: &HashMap<String, String>) -> String;
+    fn build(
+        &self,
+        tokens: &[Token],
+        data: &HashMap<String, String>,
+        missing: &MissingKeyPolicy,
+    ) -> String;
+}
+
+// The value of the pattern `p`, panicking if it's missing and `missing` says
+// so.
+fn lookup<'a>(
+    p: &str,
+    data: &'a HashMap<String, String>,
+    missing: &'a MissingKeyPolicy,
+) -> Cow<'a, str> {
+    match data.get(p) {
+        Some(s) => Cow::Borrowed(s),
+        None => missing
+            .render(p)
+            .unwrap_or_else(|| panic!("couldn't find data corresponding to key: {}", p)),
+    }
 }
 
 struct SimpleStringBuilder;
 
 impl StringFromTokens for SimpleStringBuilder {
-    fn build(&self, tokens: &[Token], data: &HashMap<String, String>) -> String {
+    fn build(
+        &self,
+        tokens: &[Token],
+        data: &HashMap<String, String>,
+        missing: &MissingKeyPolicy,
+    ) -> String {
         let mut result = String::new();
         for token in tokens.iter() {
             match token {
                 Token::String(s) => result.push_str(s),
-                Token::Pattern(p) => {
-                    let s = data.get(*p).unwrap_or_else(|| {
-                        panic!("couldn't find data corresponding to key: {}", p)
-                    });
-                    result.push_str(s);
-                }
+                Token::Pattern(p) => result.push_str(&lookup(p, data, missing)),
             }
         }
         result
@@ -33,35 +56,35 @@ impl StringFromTokens for SimpleStringBuilder {
 struct CapacityStringBuilder;
 
 impl CapacityStringBuilder {
-    fn cap(&self, tokens: &[Token], data: &HashMap<String, String>) -> usize {
+    fn cap(
+        &self,
+        tokens: &[Token],
+        data: &HashMap<String, String>,
+        missing: &MissingKeyPolicy,
+    ) -> usize {
         tokens
             .iter()
             .map(|tkn| match tkn {
                 Token::String(s) => s.len(),
-                Token::Pattern(p) => {
-                    let s = data.get(*p).unwrap_or_else(|| {
-                        panic!("couldn't find data corresponding to key: {}", p)
-                    });
-                    s.len()
-                }
+                Token::Pattern(p) => lookup(p, data, missing).len(),
             })
             .sum()
     }
 }
 
 impl StringFromTokens for CapacityStringBuilder {
-    fn build(&self, tokens: &[Token], data: &HashMap<String, String>) -> String {
-        let cap = self.cap(tokens, data);
+    fn build(
+        &self,
+        tokens: &[Token],
+        data: &HashMap<String, String>,
+        missing: &MissingKeyPolicy,
+    ) -> String {
+        let cap = self.cap(tokens, data, missing);
         let mut result = String::with_capacity(cap);
         for token in tokens.iter() {
             match token {
                 Token::String(s) => result.push_str(s),
-                Token::Pattern(p) => {
-                    let s = data.get(*p).unwrap_or_else(|| {
-                        panic!("couldn't find data corresponding to key: {}", p)
-                    });
-                    result.push_str(s);
-                }
+                Token::Pattern(p) => result.push_str(&lookup(p, data, missing)),
             }
         }
         result
@@ -78,11 +101,23 @@ pub fn parse_cap(template: String, data: HashMap<String, String>) -> String {
     parser.parse()
 }
 
+/// Like `parse`, but only panics on a missing key if `missing` says so.
+pub fn parse_with_policy(
+    template: String,
+    data: HashMap<String, String>,
+    missing: &MissingKeyPolicy,
+) -> String {
+    let mut parser = Parser::new(template, data);
+    parser.missing = missing.clone();
+    parser.parse()
+}
+
 struct Parser<'a, S: StringFromTokens> {
     data: HashMap<String, String>,
     tmpl: String,
     tokens: Vec<Token<'a>>,
     str_builder: S,
+    missing: MissingKeyPolicy,
 }
 
 impl<'a> Parser<'a, SimpleStringBuilder> {
@@ -92,6 +127,7 @@ impl<'a> Parser<'a, SimpleStringBuilder> {
             tmpl,
             tokens: vec![],
             str_builder: SimpleStringBuilder,
+            missing: MissingKeyPolicy::Error,
         }
     }
 }
@@ -106,6 +142,7 @@ where
             tmpl,
             tokens: vec![],
             str_builder: s,
+            missing: MissingKeyPolicy::Error,
         }
     }
 
@@ -152,7 +189,8 @@ where
     }
 
     fn build(&self) -> String {
-        self.str_builder.build(&self.tokens, &self.data)
+        self.str_builder
+            .build(&self.tokens, &self.data, &self.missing)
     }
 }
 
@@ -194,4 +232,28 @@ mod tests {
         let result = parse_cap(tmpl, data);
         assert_eq!("Hello, Amin!", result);
     }
+
+    #[test]
+    fn missing_key_policies() {
+        let tmpl = "Hello, {{ name }}{{surname}}!";
+        let data = HashMap::from([("name".to_string(), "Amin".to_string())]);
+        let render = |policy| parse_with_policy(tmpl.to_owned(), data.clone(), &policy);
+
+        assert_eq!(render(MissingKeyPolicy::Empty), "Hello, Amin!");
+        assert_eq!(
+            render(MissingKeyPolicy::KeepPlaceholder),
+            "Hello, Amin{{ surname }}!"
+        );
+        assert_eq!(
+            render(MissingKeyPolicy::Default("?".to_owned())),
+            "Hello, Amin?!"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "couldn't find data corresponding to key: surname")]
+    fn missing_key_panics_by_default() {
+        let data = HashMap::from([("name".to_string(), "Amin".to_string())]);
+        parse_cap("{{ name }} {{ surname }}".to_owned(), data);
+    }
 }
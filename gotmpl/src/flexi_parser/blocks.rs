@@ -0,0 +1,300 @@
+//! Conditional blocks: `{{#if key}} ... {{else}} ... {{/if}}`.
+//!
+//! The first branch is rendered if the key is truthy, the optional `else`
+//! branch otherwise. A key is truthy if it's in the data and its value is
+//! neither empty nor `false`, or a list that isn't empty. Blocks can be
+//! nested, and nothing in a branch that isn't taken is resolved, so it may
+//! refer to missing keys.
+//!
+//! Iteration blocks, `{{#each key}} ... {{/each}}`, and tables,
+//! `{{#table key}} ... {{/table}}`, are rendered by `parse_values`, the only
+//! parser whose data can hold lists. Here they're only tracked so that they
+//! can be skipped in branches that aren't taken.
+use std::fmt;
+
+use super::tokens::Token;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlockError {
+    UnexpectedElse,
+    UnexpectedEndIf,
+    UnexpectedEndEach,
+    UnexpectedEndTable,
+    DuplicateElse,
+    /// The template ended with `open` blocks still open.
+    Unclosed {
+        open: usize,
+    },
+    NotAList {
+        key: String,
+    },
+    /// A table row holds a tag of another block.
+    BlockInTable,
+}
+
+impl fmt::Display for BlockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockError::UnexpectedElse => write!(f, "{{{{else}}}} outside of {{{{#if}}}}"),
+            BlockError::UnexpectedEndIf => write!(f, "{{{{/if}}}} without {{{{#if}}}}"),
+            BlockError::DuplicateElse => write!(f, "{{{{#if}}}} with more than one {{{{else}}}}"),
+            BlockError::UnexpectedEndEach => write!(f, "{{{{/each}}}} without {{{{#each}}}}"),
+            BlockError::UnexpectedEndTable => write!(f, "{{{{/table}}}} without {{{{#table}}}}"),
+            BlockError::Unclosed { open } => {
+                write!(f, "{} {{{{#if}}}} not closed with {{{{/if}}}}", open)
+            }
+            BlockError::NotAList { key } => write!(f, "not a list: {}", key),
+            BlockError::BlockInTable => {
+                write!(f, "a table row can only hold text and placeholders")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BlockError {}
+
+// The parsers report errors as Strings.
+impl From<BlockError> for String {
+    fn from(e: BlockError) -> Self {
+        e.to_string()
+    }
+}
+
+pub(crate) fn is_truthy(value: Option<&str>) -> bool {
+    !matches!(value, None | Some("") | Some("false"))
+}
+
+/// Tracks the open blocks of a single render.
+#[derive(Debug, Default)]
+pub(crate) struct Blocks {
+    stack: Vec<Block>,
+}
+
+#[derive(Debug)]
+struct Block {
+    // Whether the branch we're in is rendered, taking the enclosing blocks
+    // into account.
+    rendering: bool,
+    // Whether the enclosing blocks are rendered.
+    outer: bool,
+    in_else: bool,
+    kind: Kind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    If,
+    // Each and table blocks are never rendered here.
+    Each,
+    Table,
+}
+
+impl Blocks {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn rendering(&self) -> bool {
+        self.stack.last().is_none_or(|b| b.rendering)
+    }
+
+    /// Returns whether `tkn` is to be rendered: false for the tags of blocks,
+    /// and for everything in a branch that isn't taken. `truthy` is only
+    /// called for the keys of blocks that are reached. Reaching an each
+    /// or table block fails, the data can't hold lists.
+    pub(crate) fn step<T: AsRef<str>>(
+        &mut self,
+        tkn: &Token<T>,
+        truthy: impl FnOnce(&T) -> bool,
+    ) -> Result<bool, BlockError> {
+        match tkn {
+            Token::If(key) => {
+                let outer = self.rendering();
+                self.stack.push(Block {
+                    rendering: outer && truthy(key),
+                    outer,
+                    in_else: false,
+                    kind: Kind::If,
+                });
+            }
+            Token::Each(key) | Token::Table(key) => {
+                if self.rendering() {
+                    let key = key.as_ref().to_owned();
+                    return Err(BlockError::NotAList { key });
+                }
+                let kind = match tkn {
+                    Token::Each(_) => Kind::Each,
+                    _ => Kind::Table,
+                };
+                self.stack.push(Block {
+                    rendering: false,
+                    outer: false,
+                    in_else: false,
+                    kind,
+                });
+            }
+            Token::Else => {
+                let block = self
+                    .stack
+                    .last_mut()
+                    .filter(|b| b.kind == Kind::If)
+                    .ok_or(BlockError::UnexpectedElse)?;
+                if block.in_else {
+                    return Err(BlockError::DuplicateElse);
+                }
+                block.in_else = true;
+                block.rendering = block.outer && !block.rendering;
+            }
+            Token::EndIf => self.close(Kind::If, BlockError::UnexpectedEndIf)?,
+            Token::EndEach => self.close(Kind::Each, BlockError::UnexpectedEndEach)?,
+            Token::EndTable => self.close(Kind::Table, BlockError::UnexpectedEndTable)?,
+            Token::Text(_) | Token::Placeholder(_) => return Ok(self.rendering()),
+        }
+        Ok(false)
+    }
+
+    fn close(&mut self, kind: Kind, err: BlockError) -> Result<(), BlockError> {
+        match self.stack.last() {
+            Some(block) if block.kind == kind => {
+                self.stack.pop();
+                Ok(())
+            }
+            _ => Err(err),
+        }
+    }
+
+    /// Fails if blocks are left open at the end of the template.
+    pub(crate) fn finish(self) -> Result<(), BlockError> {
+        match self.stack.len() {
+            0 => Ok(()),
+            open => Err(BlockError::Unclosed { open }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Renders the texts of tkns, with the truthiness of keys given by their
+    // names.
+    fn render(tkns: &[Token<&str>]) -> Result<String, BlockError> {
+        let mut blocks = Blocks::new();
+        let mut out = String::new();
+        for tkn in tkns {
+            if blocks.step(tkn, |key| key.starts_with("yes"))? {
+                if let Token::Text(t) = tkn {
+                    out.push_str(t);
+                }
+            }
+        }
+        blocks.finish()?;
+        Ok(out)
+    }
+
+    #[test]
+    fn branches() {
+        use Token::*;
+
+        let tkns = [If("yes"), Text("a"), Else, Text("b"), EndIf];
+        assert_eq!(render(&tkns), Ok("a".to_owned()));
+        let tkns = [If("no"), Text("a"), Else, Text("b"), EndIf, Text("c")];
+        assert_eq!(render(&tkns), Ok("bc".to_owned()));
+        let tkns = [If("no"), Text("a"), EndIf];
+        assert_eq!(render(&tkns), Ok("".to_owned()));
+    }
+
+    #[test]
+    fn nested_blocks_inside_untaken_branches_stay_hidden() {
+        use Token::*;
+
+        let tkns = [
+            If("no"),
+            If("yes"),
+            Text("a"),
+            Else,
+            Text("b"),
+            EndIf,
+            Else,
+            If("yes2"),
+            Text("c"),
+            EndIf,
+            EndIf,
+        ];
+        assert_eq!(render(&tkns), Ok("c".to_owned()));
+    }
+
+    #[test]
+    fn keys_of_unreached_blocks_are_not_looked_up() {
+        let mut blocks = Blocks::new();
+        assert_eq!(blocks.step(&Token::If("a"), |_| false), Ok(false));
+        assert_eq!(
+            blocks.step(&Token::If("b"), |_| panic!("looked up")),
+            Ok(false)
+        );
+    }
+
+    #[test]
+    fn mismatched_tags() {
+        use Token::*;
+
+        assert_eq!(render(&[Else]), Err(BlockError::UnexpectedElse));
+        assert_eq!(render(&[EndIf]), Err(BlockError::UnexpectedEndIf));
+        assert_eq!(
+            render(&[If("a"), Else, Else, EndIf]),
+            Err(BlockError::DuplicateElse)
+        );
+        assert_eq!(
+            render(&[If("a"), If("b"), EndIf]),
+            Err(BlockError::Unclosed { open: 1 })
+        );
+        assert_eq!(
+            render(&[If("a"), EndEach]),
+            Err(BlockError::UnexpectedEndEach)
+        );
+    }
+
+    #[test]
+    fn each_blocks_are_skipped_in_untaken_branches() {
+        use Token::*;
+
+        let tkns = [If("no"), Each("l"), Text("a"), EndEach, EndIf, Text("b")];
+        assert_eq!(render(&tkns), Ok("b".to_owned()));
+
+        let tkns = [If("no"), Each("l"), EndIf, EndEach];
+        assert_eq!(render(&tkns), Err(BlockError::UnexpectedEndIf));
+
+        let key = "l".to_owned();
+        let tkns = [If("yes"), Each("l"), EndEach, EndIf];
+        assert_eq!(render(&tkns), Err(BlockError::NotAList { key }));
+    }
+
+    #[test]
+    fn table_blocks_are_skipped_in_untaken_branches() {
+        use Token::*;
+
+        let tkns = [If("no"), Table("l"), Text("a"), EndTable, EndIf, Text("b")];
+        assert_eq!(render(&tkns), Ok("b".to_owned()));
+
+        let tkns = [If("no"), Table("l"), EndEach, EndIf];
+        assert_eq!(render(&tkns), Err(BlockError::UnexpectedEndEach));
+        assert_eq!(render(&[EndTable]), Err(BlockError::UnexpectedEndTable));
+    }
+
+    #[test]
+    fn truthiness() {
+        assert!(is_truthy(Some("yes")));
+        assert!(is_truthy(Some("0")));
+        assert!(!is_truthy(Some("")));
+        assert!(!is_truthy(Some("false")));
+        assert!(!is_truthy(None));
+    }
+
+    #[test]
+    fn error_message() {
+        let e: String = BlockError::Unclosed { open: 2 }.into();
+        assert_eq!(e, "2 {{#if}} not closed with {{/if}}");
+        let e: String = BlockError::UnexpectedEndEach.into();
+        assert_eq!(e, "{{/each}} without {{#each}}");
+    }
+}
@@ -0,0 +1,107 @@
+//! Rendering that borrows both the template and the values, and doesn't
+//! allocate unless it has to: the tokens are slices of the template, and the
+//! output is only built up once it consists of more than one piece. A
+//! template without placeholders, or one that renders to a single value,
+//! comes back borrowed.
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use super::blocks::{is_truthy, Blocks};
+use super::tokens::{Iter, Limits, Token};
+use super::{split_default, Result};
+
+/// Like `parse_ref`, but borrowing everything. See the module docs.
+pub fn render_borrowed<'a>(tmpl: &'a str, data: &'a HashMap<&str, &str>) -> Result<Cow<'a, str>> {
+    let mut blocks = Blocks::new();
+    let mut parsed = Cow::Borrowed("");
+
+    for tkn in Iter::new(tmpl, Limits::new()) {
+        let tkn = tkn?;
+        let truthy = |k: &&str| is_truthy(data.get(*k).copied());
+        if !blocks.step(&tkn, truthy)? {
+            continue;
+        }
+        let resolved = match tkn {
+            Token::Text(t) => t,
+            Token::Placeholder(p) => {
+                let (key, default) = split_default(p)?;
+                data.get(key)
+                    .copied()
+                    .or(default)
+                    .ok_or(format!("couldn't find data corresponding to key: {}", key))?
+            }
+            // The tags of blocks render as nothing.
+            _ => "",
+        };
+        if resolved.is_empty() {
+            continue;
+        }
+        if parsed.is_empty() {
+            parsed = Cow::Borrowed(resolved);
+        } else {
+            parsed.to_mut().push_str(resolved);
+        }
+    }
+    blocks.finish()?;
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flexi_parser::parse_ref;
+
+    #[test]
+    fn renders_like_parse_ref() {
+        let tmpl = std::fs::read_to_string("templates/large.tmpl").unwrap();
+        let expected = std::fs::read_to_string("templates/large.parsed").unwrap();
+        let data = HashMap::from([
+            ("name1", "A1"),
+            ("name2", "A2"),
+            ("name3", "A3"),
+            ("surname1", "M1"),
+            ("surname2", "M2"),
+            ("surname3", "M3"),
+        ]);
+
+        assert_eq!(render_borrowed(&tmpl, &data).as_deref(), Ok(&*expected));
+
+        let owned = data
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        assert_eq!(parse_ref(tmpl, owned), Ok(expected));
+    }
+
+    #[test]
+    fn single_pieces_are_borrowed() {
+        let data = HashMap::from([("name", "Amin"), ("admin", "")]);
+
+        let rendered = render_borrowed("no placeholders", &data).unwrap();
+        assert!(matches!(rendered, Cow::Borrowed("no placeholders")));
+
+        let rendered = render_borrowed("{{#if admin}}admin: {{/if}}{{ name }}", &data).unwrap();
+        assert!(matches!(rendered, Cow::Borrowed("Amin")));
+
+        let rendered = render_borrowed("Hi {{ name }}", &data).unwrap();
+        assert!(matches!(rendered, Cow::Owned(_)));
+        assert_eq!(rendered, "Hi Amin");
+    }
+
+    #[test]
+    fn errors() {
+        let data = HashMap::new();
+        assert_eq!(
+            render_borrowed("{{ name }}", &data),
+            Err("couldn't find data corresponding to key: name".to_owned())
+        );
+        assert_eq!(
+            render_borrowed("{{ name | default:\"Guest\" }}", &data).as_deref(),
+            Ok("Guest")
+        );
+        assert_eq!(
+            render_borrowed("{{#if a}}", &data),
+            Err("1 {{#if}} not closed with {{/if}}".to_owned())
+        );
+    }
+}
@@ -0,0 +1,62 @@
+//! Rendering one template for many data sets, like an email for each of its
+//! recipients. The template is compiled once and the data sets are rendered
+//! in parallel.
+use std::collections::HashMap;
+
+use rayon::prelude::*;
+
+use super::{Result, Template};
+
+/// Renders `tmpl` for each of the data sets, in their order. Fails if the
+/// template doesn't compile or if any of the renders fails, the error names
+/// the index of a data set it failed for.
+pub fn render_many<I>(tmpl: &str, data: I) -> Result<Vec<String>>
+where
+    I: IntoIterator<Item = HashMap<String, String>>,
+{
+    let tmpl = Template::compile(tmpl)?;
+    let data: Vec<_> = data.into_iter().collect();
+    data.into_par_iter()
+        .enumerate()
+        .map(|(i, data)| {
+            tmpl.render(&data)
+                .map_err(|e| format!("couldn't render data set {}: {}", i, e))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recipient(name: &str) -> HashMap<String, String> {
+        HashMap::from([("name".to_owned(), name.to_owned())])
+    }
+
+    #[test]
+    fn renders_every_data_set_in_order() {
+        let names: Vec<_> = (0..1000).map(|i| format!("r{}", i)).collect();
+        let rendered = render_many("Dear {{ name }},", names.iter().map(|n| recipient(n)));
+
+        let expected: Vec<_> = names.iter().map(|n| format!("Dear {},", n)).collect();
+        assert_eq!(rendered, Ok(expected));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            render_many("{{ name ", [recipient("a")]),
+            Err("missing closing delimiter: }}".to_owned())
+        );
+
+        let data = [recipient("a"), HashMap::new(), recipient("c")];
+        assert_eq!(
+            render_many("{{ name }}", data),
+            Err(
+                "couldn't render data set 1: couldn't find data corresponding to key: name"
+                    .to_owned()
+            )
+        );
+        assert_eq!(render_many("{{ name }}", []), Ok(vec![]));
+    }
+}
@@ -0,0 +1,928 @@
+//! Templates that call functions of their own: an `Engine` holds helpers,
+//! which are called by name from placeholders at render time, and partials,
+//! named templates that other templates include.
+//!
+//! Calls look like the ones the sandbox checks: a placeholder with more than
+//! one word is a call, like `{{ shout name "!!!" }}`, where the first word
+//! names the helper and the rest are its arguments. A quoted argument is
+//! passed as is, any other one is a data key and passes its value. An
+//! argument in parentheses is a call itself, as in `{{ shout (lower name) }}`,
+//! and `|` passes the result of one call as the last argument of the next
+//! one: `{{ lower name | shout "!" }}` calls `shout "!" (lower name)`. A
+//! single word is a data key.
+//!
+//! One helper is built in: `{{ plural count "item" "items" }}` renders the
+//! form of a word that goes with `count`. Which form that is depends on the
+//! language, so the rule that picks it can be replaced with `plural_rule`,
+//! along with the number of forms. A helper registered as `plural` replaces
+//! the built-in one.
+//!
+//! `{{> header }}` renders the partial `header` in its place, with the same
+//! data. Partials can include other partials, but not themselves, not even
+//! through others, and `max_include_depth` bounds how deeply they're nested.
+//!
+//! Partials are also the layouts other templates extend. A layout marks the
+//! parts of it that can be replaced as named blocks, like
+//! `{{#block content}}default{{/block}}`, whose default is rendered unless
+//! the template that extends the layout replaces it. A template that starts
+//! with `{{#extends layout}}` renders as the layout, with each block it
+//! defines replacing the one of the same name:
+//!
+//! ```text
+//! {{#extends page}}
+//! {{#block title}}Hi {{ name }}{{/block}}
+//! ```
+//!
+//! Besides blocks, such a template can only hold whitespace. Layouts can
+//! extend other layouts, and the blocks of the template furthest down the
+//! chain win.
+//!
+//! With `sandbox`, every template, partial and layout is checked against a
+//! `Sandbox` before it's rendered, which then has to allow every helper that
+//! may be called, the built-in `plural` included.
+//!
+//! Applications that look their templates up by name add them with
+//! `add_template`, which checks them right away, and render them with
+//! `render`. Added templates are partials as well, so they can include and
+//! extend each other.
+use std::collections::HashMap;
+use std::fmt;
+use std::iter::Peekable;
+
+use super::blocks::Blocks;
+use super::sandbox::{Sandbox, Words};
+use super::tokens::{block_key, Iter, Limits, Token, TokenError};
+use super::{resolve_token, truthy, Result};
+
+type Helper = dyn Fn(&[String]) -> Result<String> + Send + Sync;
+
+// Picks the index of the plural form for a count.
+type PluralRule = dyn Fn(i64) -> usize + Send + Sync;
+
+// The blocks that replace the ones of a layout, by their name.
+type Overrides<'t> = HashMap<&'t str, Vec<Token<&'t str>>>;
+
+#[derive(Default)]
+pub struct Engine {
+    helpers: HashMap<String, Box<Helper>>,
+    partials: HashMap<String, String>,
+    // Unlimited if None.
+    max_include_depth: Option<usize>,
+    // The English rule if None.
+    plural_rule: Option<Box<PluralRule>>,
+    // Nothing is checked if None.
+    sandbox: Option<Sandbox>,
+}
+
+/// Why partials or layouts couldn't be rendered. The chains of names start
+/// with the outermost partial or layout, and end with the one that's
+/// included or extended again.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IncludeError {
+    Cycle { chain: Vec<String> },
+    LayoutCycle { chain: Vec<String> },
+    TooDeep { limit: usize },
+}
+
+impl fmt::Display for IncludeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IncludeError::Cycle { chain } => {
+                write!(f, "partial includes itself: {}", chain.join(" > "))
+            }
+            IncludeError::LayoutCycle { chain } => {
+                write!(f, "layout extends itself: {}", chain.join(" > "))
+            }
+            IncludeError::TooDeep { limit } => {
+                write!(f, "partials are nested deeper than {} levels", limit)
+            }
+        }
+    }
+}
+
+impl std::error::Error for IncludeError {}
+
+// The parsers report errors as Strings.
+impl From<IncludeError> for String {
+    fn from(e: IncludeError) -> Self {
+        e.to_string()
+    }
+}
+
+// The chain of names of an IncludeError.
+fn chain(names: &[&str]) -> Vec<String> {
+    names.iter().map(|&name| name.to_owned()).collect()
+}
+
+impl fmt::Debug for Engine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Engine")
+            .field("helpers", &self.helpers.keys().collect::<Vec<_>>())
+            .field("partials", &self.partials.keys().collect::<Vec<_>>())
+            .field("max_include_depth", &self.max_include_depth)
+            .field("sandbox", &self.sandbox)
+            .finish()
+    }
+}
+
+impl Engine {
+    /// Creates an engine without helpers and partials.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes `helper` callable as `name`. It's given the rendered arguments,
+    /// and an error it returns fails the render. A helper registered under
+    /// the same name before is replaced.
+    pub fn register_helper(
+        mut self,
+        name: impl Into<String>,
+        helper: impl Fn(&[String]) -> Result<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.helpers.insert(name.into(), Box::new(helper));
+        self
+    }
+
+    /// Makes `tmpl` includable as `{{> name }}`. A partial registered under
+    /// the same name before is replaced.
+    pub fn register_partial(mut self, name: impl Into<String>, tmpl: impl Into<String>) -> Self {
+        self.partials.insert(name.into(), tmpl.into());
+        self
+    }
+
+    /// Makes the built-in `plural` helper pick its form with `rule`, which is
+    /// given the count and returns the index of the form, starting at 0 for
+    /// the first one after the count. By default, that's the first form for
+    /// 1 and the second one for anything else, as in English.
+    ///
+    /// For Polish, with three forms, like `{{ plural n "plik" "pliki" "plików" }}`:
+    ///
+    /// ```ignore
+    /// engine.plural_rule(|n| match (n % 10, n % 100) {
+    ///     _ if n == 1 => 0,
+    ///     (2..=4, rem) if !(12..=14).contains(&rem) => 1,
+    ///     _ => 2,
+    /// })
+    /// ```
+    pub fn plural_rule(mut self, rule: impl Fn(i64) -> usize + Send + Sync + 'static) -> Self {
+        self.plural_rule = Some(Box::new(rule));
+        self
+    }
+
+    /// How many partials may be rendered inside each other, counting a
+    /// template rendered by name with `render` as one of them. Rendering
+    /// fails with `IncludeError::TooDeep` beyond that. Unlimited by default,
+    /// as only cycles can nest partials without bound, and they're errors
+    /// anyway.
+    pub fn max_include_depth(mut self, max: usize) -> Self {
+        self.max_include_depth = Some(max);
+        self
+    }
+
+    /// Checks every template, partial and layout against `sandbox` before
+    /// rendering it, and fails with the `SandboxError` of the first
+    /// violation. The includes and the block tags aren't calls, but the
+    /// helpers are.
+    pub fn sandbox(mut self, sandbox: Sandbox) -> Self {
+        self.sandbox = Some(sandbox);
+        self
+    }
+
+    /// Adds `tmpl` under `name`, replacing the template or partial that was
+    /// registered under it before. Fails if `tmpl` can't be tokenized, and
+    /// leaves the engine as it was then.
+    pub fn add_template(&mut self, name: impl Into<String>, tmpl: impl Into<String>) -> Result<()> {
+        let tmpl = tmpl.into();
+        Iter::new(&tmpl, Limits::new()).try_for_each(|tkn| tkn.map(drop))?;
+        self.partials.insert(name.into(), tmpl);
+        Ok(())
+    }
+
+    /// Removes the template or partial `name`, and returns whether there was
+    /// one.
+    pub fn remove_template(&mut self, name: &str) -> bool {
+        self.partials.remove(name).is_some()
+    }
+
+    /// Renders the template or partial `name` like `parse` does.
+    pub fn render(&self, name: &str, data: &HashMap<String, String>) -> Result<String> {
+        let (name, tmpl) = self
+            .partials
+            .get_key_value(name)
+            .ok_or_else(|| format!("unknown template: {}", name))?;
+        let mut parsed = String::new();
+        self.render_into(tmpl, data, &mut vec![name.as_str()], &mut parsed)?;
+        Ok(parsed)
+    }
+
+    /// Like `parse_ref`, but placeholders can call the helpers and include
+    /// the partials.
+    pub fn parse(&self, tmpl: String, data: &HashMap<String, String>) -> Result<String> {
+        let mut parsed = String::new();
+        self.render_into(&tmpl, data, &mut Vec::new(), &mut parsed)?;
+        Ok(parsed)
+    }
+
+    // `included` holds the names of the partials that are being rendered,
+    // the innermost one last.
+    fn render_into<'e>(
+        &'e self,
+        tmpl: &str,
+        data: &HashMap<String, String>,
+        included: &mut Vec<&'e str>,
+        parsed: &mut String,
+    ) -> Result<()> {
+        self.check(tmpl)?;
+        let mut tokens = Iter::new(tmpl, Limits::new());
+        let (layout, leading) = layout_of(&mut tokens)?;
+        let Some(mut layout) = layout else {
+            let tokens = leading.into_iter().map(Ok).chain(tokens);
+            return self.render_tokens(tokens, data, &Overrides::new(), included, parsed);
+        };
+
+        // The layouts extended so far, to tell cycles.
+        let mut extended_by = Vec::new();
+        let mut overrides = Overrides::new();
+        loop {
+            for (name, body) in blocks_of(tokens)? {
+                overrides.entry(name).or_insert(body);
+            }
+            let (name, tmpl) = self
+                .partials
+                .get_key_value(layout)
+                .ok_or_else(|| format!("unknown partial: {}", layout))?;
+            let cycle = extended_by.contains(&name.as_str());
+            extended_by.push(name.as_str());
+            if cycle {
+                let chain = chain(&extended_by);
+                return Err(IncludeError::LayoutCycle { chain }.into());
+            }
+
+            self.check(tmpl)?;
+            tokens = Iter::new(tmpl, Limits::new());
+            match layout_of(&mut tokens)? {
+                (Some(next), _) => layout = next,
+                (None, leading) => {
+                    let tokens = leading.into_iter().map(Ok).chain(tokens);
+                    return self.render_tokens(tokens, data, &overrides, included, parsed);
+                }
+            }
+        }
+    }
+
+    fn render_tokens<'e, 't>(
+        &'e self,
+        mut tokens: impl Iterator<Item = std::result::Result<Token<&'t str>, TokenError>>,
+        data: &HashMap<String, String>,
+        overrides: &Overrides<'t>,
+        included: &mut Vec<&'e str>,
+        parsed: &mut String,
+    ) -> Result<()> {
+        let mut blocks = Blocks::new();
+        // The named blocks whose default is being rendered.
+        let mut defaults = 0;
+
+        while let Some(tkn) = tokens.next() {
+            let tkn = tkn?;
+            if !blocks.step(&tkn, |k| truthy(k, data))? {
+                continue;
+            }
+            match tkn {
+                Token::Placeholder(p) if p.starts_with('>') => {
+                    self.include(p[1..].trim_start(), data, included, parsed)?
+                }
+                Token::Placeholder(p) if block_key(p, "#block").is_some() => {
+                    let name = block_key(p, "#block").unwrap_or_default();
+                    let Some(body) = overrides.get(name) else {
+                        defaults += 1;
+                        continue;
+                    };
+                    skip_block(&mut tokens)?;
+                    let body = body.iter().cloned().map(Ok);
+                    self.render_tokens(body, data, overrides, included, parsed)?;
+                }
+                Token::Placeholder("/block") => {
+                    if defaults == 0 {
+                        return Err("{{/block}} without {{#block}}".to_owned());
+                    }
+                    defaults -= 1;
+                }
+                Token::Placeholder(p) if block_key(p, "#extends").is_some() => {
+                    return Err("{{#extends}} has to come first".to_owned())
+                }
+                Token::Placeholder(p) if p.contains(char::is_whitespace) => {
+                    parsed.push_str(&self.call(p, data)?)
+                }
+                _ => parsed.push_str(resolve_token(&tkn, data)?),
+            }
+        }
+        if defaults > 0 {
+            return Err("{{#block}} without {{/block}}".to_owned());
+        }
+        blocks.finish()?;
+        Ok(())
+    }
+
+    fn include<'e>(
+        &'e self,
+        name: &str,
+        data: &HashMap<String, String>,
+        included: &mut Vec<&'e str>,
+        parsed: &mut String,
+    ) -> Result<()> {
+        let (name, tmpl) = self
+            .partials
+            .get_key_value(name)
+            .ok_or_else(|| format!("unknown partial: {}", name))?;
+        if let Some(limit) = self.max_include_depth.filter(|&max| included.len() >= max) {
+            return Err(IncludeError::TooDeep { limit }.into());
+        }
+        let cycle = included.contains(&name.as_str());
+        included.push(name);
+        if cycle {
+            let chain = chain(included);
+            return Err(IncludeError::Cycle { chain }.into());
+        }
+        self.render_into(tmpl, data, included, parsed)?;
+        included.pop();
+        Ok(())
+    }
+
+    fn check(&self, tmpl: &str) -> Result<()> {
+        match &self.sandbox {
+            Some(sandbox) => Ok(sandbox.check(tmpl)?),
+            None => Ok(()),
+        }
+    }
+
+    // The built-in helper.
+    fn plural(&self, args: &[String]) -> Result<String> {
+        let [count, forms @ ..] = args else {
+            return Err("plural takes a count and its forms".to_owned());
+        };
+        let n: i64 = count
+            .trim()
+            .parse()
+            .map_err(|_| format!("plural: not a count: {}", count))?;
+        let idx = match &self.plural_rule {
+            Some(rule) => rule(n),
+            None => usize::from(n != 1),
+        };
+        forms
+            .get(idx)
+            .cloned()
+            .ok_or_else(|| format!("plural: no form {} for the count {}", idx, n))
+    }
+
+    fn call(&self, placeholder: &str, data: &HashMap<String, String>) -> Result<String> {
+        let mut call = Call {
+            engine: self,
+            data,
+            words: Words::new(placeholder).map(|(_, w)| w).peekable(),
+        };
+        let result = call.pipeline()?;
+        match call.words.next() {
+            None => Ok(result),
+            Some(_) => Err(format!("unbalanced parentheses in: {}", placeholder)),
+        }
+    }
+}
+
+// The layout a template extends, if its first tag is `{{#extends layout}}`.
+// Otherwise the tokens read up to its first tag, which are to be rendered.
+fn layout_of<'t>(tokens: &mut Iter<'t>) -> Result<(Option<&'t str>, Vec<Token<&'t str>>)> {
+    let mut leading = Vec::new();
+    for tkn in tokens {
+        match tkn? {
+            Token::Text(t) if t.trim().is_empty() => leading.push(Token::Text(t)),
+            Token::Placeholder(p) if block_key(p, "#extends").is_some() => {
+                return Ok((block_key(p, "#extends"), Vec::new()))
+            }
+            tkn => {
+                leading.push(tkn);
+                break;
+            }
+        }
+    }
+    Ok((None, leading))
+}
+
+// Collects the blocks of a template that extends a layout, nested ones
+// included. The body of a block holds the tags of the blocks nested in it.
+fn blocks_of<'t>(
+    tokens: impl Iterator<Item = std::result::Result<Token<&'t str>, TokenError>>,
+) -> Result<Overrides<'t>> {
+    let mut blocks = Overrides::new();
+    let mut open: Vec<(&str, Vec<Token<&str>>)> = Vec::new();
+
+    for tkn in tokens {
+        let tkn = tkn?;
+        match tkn {
+            Token::Placeholder(p) if block_key(p, "#block").is_some() => {
+                for (_, body) in &mut open {
+                    body.push(tkn.clone());
+                }
+                open.push((block_key(p, "#block").unwrap_or_default(), Vec::new()));
+            }
+            Token::Placeholder("/block") => {
+                let (name, body) = open
+                    .pop()
+                    .ok_or("{{/block}} without {{#block}}".to_owned())?;
+                for (_, body) in &mut open {
+                    body.push(tkn.clone());
+                }
+                if blocks.insert(name, body).is_some() {
+                    return Err(format!("duplicate block: {}", name));
+                }
+            }
+            Token::Text(t) if open.is_empty() && t.trim().is_empty() => {}
+            _ if open.is_empty() => {
+                return Err("a template that extends a layout can only hold blocks".to_owned())
+            }
+            _ => {
+                for (_, body) in &mut open {
+                    body.push(tkn.clone());
+                }
+            }
+        }
+    }
+    if !open.is_empty() {
+        return Err("{{#block}} without {{/block}}".to_owned());
+    }
+    Ok(blocks)
+}
+
+// Skips the default of a block that's replaced, up to its `{{/block}}`.
+fn skip_block<'t>(
+    tokens: &mut impl Iterator<Item = std::result::Result<Token<&'t str>, TokenError>>,
+) -> Result<()> {
+    let mut depth = 1;
+    for tkn in tokens {
+        match tkn? {
+            Token::Placeholder(p) if block_key(p, "#block").is_some() => depth += 1,
+            Token::Placeholder("/block") => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(());
+                }
+            }
+            _ => {}
+        }
+    }
+    Err("{{#block}} without {{/block}}".to_owned())
+}
+
+// Evaluates the words of a single placeholder.
+struct Call<'e, I: Iterator> {
+    engine: &'e Engine,
+    data: &'e HashMap<String, String>,
+    words: Peekable<I>,
+}
+
+impl<'e, 'w, I: Iterator<Item = &'w str>> Call<'e, I> {
+    fn pipeline(&mut self) -> Result<String> {
+        let mut result = self.command(None)?;
+        while self.words.next_if_eq(&"|").is_some() {
+            result = self.command(Some(result))?;
+        }
+        Ok(result)
+    }
+
+    fn command(&mut self, piped: Option<String>) -> Result<String> {
+        let name = match self.words.next() {
+            Some(name) if !["(", ")", "|"].contains(&name) && !name.starts_with('"') => name,
+            _ => return Err("missing helper name".to_owned()),
+        };
+        let helper = self.engine.helpers.get(name);
+        if helper.is_none() && name != "plural" {
+            return Err(format!("unknown helper: {}", name));
+        }
+
+        let mut args = Vec::new();
+        while let Some(&word) = self.words.peek() {
+            match word {
+                ")" | "|" => break,
+                "(" => {
+                    self.words.next();
+                    args.push(self.pipeline()?);
+                    if self.words.next() != Some(")") {
+                        return Err("missing closing parenthesis".to_owned());
+                    }
+                }
+                _ => {
+                    self.words.next();
+                    args.push(self.arg(word)?);
+                }
+            }
+        }
+        args.extend(piped);
+        match helper {
+            Some(helper) => helper(&args),
+            None => self.engine.plural(&args),
+        }
+    }
+
+    fn arg(&self, word: &str) -> Result<String> {
+        if let Some(quoted) = word.strip_prefix('"') {
+            return quoted
+                .strip_suffix('"')
+                .map(str::to_owned)
+                .ok_or_else(|| format!("unterminated string: {}", word));
+        }
+        self.data
+            .get(word)
+            .cloned()
+            .ok_or(format!("couldn't find data corresponding to key: {}", word))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn engine() -> Engine {
+        Engine::new()
+            .register_helper("shout", |args| Ok(args.concat().to_uppercase()))
+            .register_helper("lower", |args| Ok(args.concat().to_lowercase()))
+            .register_helper("fail", |_| Err("failed on purpose".to_owned()))
+    }
+
+    fn data() -> HashMap<String, String> {
+        HashMap::from([("name".to_owned(), "Amin".to_owned())])
+    }
+
+    #[test]
+    fn helpers_are_called_with_their_arguments() {
+        let tmpl = r#"{{ shout name "!!!" }} {{ name }}"#.to_owned();
+        assert_eq!(engine().parse(tmpl, &data()), Ok("AMIN!!! Amin".to_owned()));
+    }
+
+    #[test]
+    fn nested_calls_and_pipes() {
+        let tmpl = r#"{{ shout (lower name) "-" }} {{ lower name | shout "(a | b) " }}"#;
+        assert_eq!(
+            engine().parse(tmpl.to_owned(), &data()),
+            Ok("AMIN- (A | B) AMIN".to_owned())
+        );
+
+        let tmpl = r#"{{ shout ("x" }}"#;
+        assert_eq!(
+            engine().parse(tmpl.to_owned(), &data()),
+            Err("missing helper name".to_owned())
+        );
+        let tmpl = r#"{{ shout (lower "x" }}"#;
+        assert_eq!(
+            engine().parse(tmpl.to_owned(), &data()),
+            Err("missing closing parenthesis".to_owned())
+        );
+        let tmpl = r#"{{ shout "x") }}"#;
+        assert_eq!(
+            engine().parse(tmpl.to_owned(), &data()),
+            Err(r#"unbalanced parentheses in: shout "x")"#.to_owned())
+        );
+    }
+
+    #[test]
+    fn failed_calls_fail_the_render() {
+        let cases = [
+            ("{{ whisper name }}", "unknown helper: whisper"),
+            (
+                "{{ shout surname }}",
+                "couldn't find data corresponding to key: surname",
+            ),
+            ("{{ name | fail }}", "unknown helper: name"),
+            (r#"{{ shout "open }}"#, r#"unterminated string: "open"#),
+            ("{{ shout (fail) }}", "failed on purpose"),
+        ];
+        for (tmpl, expected) in cases {
+            assert_eq!(
+                engine().parse(tmpl.to_owned(), &data()),
+                Err(expected.to_owned()),
+                "{}",
+                tmpl
+            );
+        }
+    }
+
+    #[test]
+    fn plural_forms() {
+        let tmpl = r#"{{ n }} {{ plural n "file" "files" }}"#;
+        let render = |engine: &Engine, n: i64| {
+            let data = HashMap::from([("n".to_owned(), n.to_string())]);
+            engine.parse(tmpl.to_owned(), &data)
+        };
+        let engine = engine();
+        assert_eq!(render(&engine, 1), Ok("1 file".to_owned()));
+        assert_eq!(render(&engine, 0), Ok("0 files".to_owned()));
+        assert_eq!(render(&engine, 21), Ok("21 files".to_owned()));
+
+        let tmpl = r#"{{ n }} {{ plural n "plik" "pliki" "plików" }}"#;
+        let polish = engine.plural_rule(|n| match (n % 10, n % 100) {
+            _ if n == 1 => 0,
+            (2..=4, rem) if !(12..=14).contains(&rem) => 1,
+            _ => 2,
+        });
+        let rendered: Vec<_> = [1, 2, 5, 12, 22]
+            .into_iter()
+            .map(|n| {
+                let data = HashMap::from([("n".to_owned(), n.to_string())]);
+                polish.parse(tmpl.to_owned(), &data).unwrap()
+            })
+            .collect();
+        assert_eq!(
+            rendered,
+            ["1 plik", "2 pliki", "5 plików", "12 plików", "22 pliki"]
+        );
+    }
+
+    #[test]
+    fn plural_errors() {
+        let cases = [
+            ("{{ plural name }}", "plural: not a count: Amin"),
+            (
+                r#"{{ plural "2" "a" }}"#,
+                "plural: no form 1 for the count 2",
+            ),
+            (r#"{{ plural ("1") }}"#, "missing helper name"),
+        ];
+        for (tmpl, expected) in cases {
+            assert_eq!(
+                engine().parse(tmpl.to_owned(), &data()),
+                Err(expected.to_owned()),
+                "{}",
+                tmpl
+            );
+        }
+
+        // Registered helpers win.
+        let engine = engine().register_helper("plural", |_| Ok("mine".to_owned()));
+        assert_eq!(
+            engine.parse(r#"{{ plural "x" }}"#.to_owned(), &data()),
+            Ok("mine".to_owned())
+        );
+    }
+
+    #[test]
+    fn partials_are_rendered_with_the_same_data() {
+        let engine = engine()
+            .register_partial("header", "# {{ shout name }}\n{{> line }}")
+            .register_partial("line", "{{#if name}}---{{/if}}\n");
+
+        let tmpl = "{{> header }}Hi {{ name }}\n{{>line}}";
+        assert_eq!(
+            engine.parse(tmpl.to_owned(), &data()),
+            Ok("# AMIN\n---\nHi Amin\n---\n".to_owned())
+        );
+        assert_eq!(
+            engine.parse("{{> footer }}".to_owned(), &data()),
+            Err("unknown partial: footer".to_owned())
+        );
+    }
+
+    #[test]
+    fn partials_cant_include_themselves() {
+        let engine = engine()
+            .register_partial("a", "{{> b }}")
+            .register_partial("b", "{{#if name}}{{> a }}{{/if}}")
+            .register_partial("c", "{{> c }}");
+
+        assert_eq!(
+            engine.parse("{{> a }}".to_owned(), &data()),
+            Err("partial includes itself: a > b > a".to_owned())
+        );
+        assert_eq!(
+            engine.parse("{{> c }}".to_owned(), &data()),
+            Err("partial includes itself: c > c".to_owned())
+        );
+        // Only a cycle that's rendered is one.
+        assert_eq!(
+            engine.parse("{{> a }}".to_owned(), &HashMap::new()),
+            Ok("".to_owned())
+        );
+    }
+
+    #[test]
+    fn include_depth_is_limited() {
+        let engine = Engine::new()
+            .register_partial("a", "a{{> b }}")
+            .register_partial("b", "b{{> c }}")
+            .register_partial("c", "c")
+            .max_include_depth(3);
+
+        assert_eq!(
+            engine.parse("{{> a }}".to_owned(), &data()),
+            Ok("abc".to_owned())
+        );
+        assert_eq!(engine.render("a", &data()), Ok("abc".to_owned()));
+
+        let engine = engine.max_include_depth(2);
+        let e = String::from(IncludeError::TooDeep { limit: 2 });
+        assert_eq!(engine.parse("{{> a }}".to_owned(), &data()), Err(e.clone()));
+        assert_eq!(engine.render("b", &data()), Ok("bc".to_owned()));
+        assert_eq!(engine.render("a", &data()), Err(e));
+    }
+
+    #[test]
+    fn cycles_are_include_errors() {
+        let chain = vec!["a".to_owned(), "a".to_owned()];
+        assert_eq!(
+            String::from(IncludeError::Cycle {
+                chain: chain.clone()
+            }),
+            "partial includes itself: a > a"
+        );
+        assert_eq!(
+            IncludeError::LayoutCycle { chain }.to_string(),
+            "layout extends itself: a > a"
+        );
+    }
+
+    #[test]
+    fn partials_need_balanced_blocks() {
+        let engine = Engine::new().register_partial("open", "{{#if name}}");
+        assert_eq!(
+            engine.parse("{{> open }}{{/if}}".to_owned(), &data()),
+            Err("1 {{#if}} not closed with {{/if}}".to_owned())
+        );
+    }
+
+    fn layouts() -> Engine {
+        engine()
+            .register_partial(
+                "page",
+                "<title>{{#block title}}Untitled{{/block}}</title>\n\
+                 {{#block body}}<p>{{#block content}}Nothing{{/block}}</p>{{/block}}",
+            )
+            .register_partial(
+                "profile",
+                "{{#extends page}}\n{{#block title}}{{ shout name }}{{/block}}",
+            )
+    }
+
+    #[test]
+    fn blocks_replace_the_ones_of_the_layout() {
+        let engine = layouts();
+        assert_eq!(
+            engine.parse("{{> page }}".to_owned(), &data()),
+            Ok("<title>Untitled</title>\n<p>Nothing</p>".to_owned())
+        );
+
+        let tmpl = "{{#extends page}}\n{{#block content}}Hi {{ name }}{{/block}}\n";
+        assert_eq!(
+            engine.parse(tmpl.to_owned(), &data()),
+            Ok("<title>Untitled</title>\n<p>Hi Amin</p>".to_owned())
+        );
+    }
+
+    #[test]
+    fn layouts_extend_layouts() {
+        let engine = layouts();
+        let tmpl = "{{#extends profile}}{{#block content}}{{> line }}{{/block}}";
+        let engine = engine.register_partial("line", "---");
+        assert_eq!(
+            engine.parse(tmpl.to_owned(), &data()),
+            Ok("<title>AMIN</title>\n<p>---</p>".to_owned())
+        );
+
+        // The blocks further down the chain win, nested ones included.
+        let tmpl = "{{#extends profile}}\
+                    {{#block title}}Profile{{/block}}\
+                    {{#block body}}[{{#block content}}{{ name }}{{/block}}]{{/block}}";
+        assert_eq!(
+            engine.parse(tmpl.to_owned(), &data()),
+            Ok("<title>Profile</title>\n[Amin]".to_owned())
+        );
+    }
+
+    #[test]
+    fn layout_errors() {
+        let engine = layouts()
+            .register_partial("a", "{{#extends b}}")
+            .register_partial("b", "{{#extends a}}")
+            .register_partial("open", "{{#block a}}");
+
+        let cases = [
+            ("{{#extends a}}", "layout extends itself: a > b > a"),
+            ("{{#extends none}}", "unknown partial: none"),
+            (
+                "{{#extends page}}text",
+                "a template that extends a layout can only hold blocks",
+            ),
+            (
+                "{{#extends page}}{{ name }}",
+                "a template that extends a layout can only hold blocks",
+            ),
+            (
+                "{{#extends page}}{{#block a}}{{/block}}{{#block a}}{{/block}}",
+                "duplicate block: a",
+            ),
+            (
+                "{{#extends page}}{{#block a}}",
+                "{{#block}} without {{/block}}",
+            ),
+            (
+                "{{#extends page}}{{/block}}",
+                "{{/block}} without {{#block}}",
+            ),
+            (
+                "{{#extends open}}{{#block a}}{{/block}}",
+                "{{#block}} without {{/block}}",
+            ),
+            ("{{> open }}", "{{#block}} without {{/block}}"),
+            ("{{/block}}", "{{/block}} without {{#block}}"),
+            ("Hi {{#extends page}}", "{{#extends}} has to come first"),
+        ];
+        for (tmpl, expected) in cases {
+            assert_eq!(
+                engine.parse(tmpl.to_owned(), &data()),
+                Err(expected.to_owned()),
+                "{}",
+                tmpl
+            );
+        }
+    }
+
+    #[test]
+    fn templates_are_rendered_by_name() {
+        let mut engine = engine();
+        engine.add_template("hi", "Hi {{ shout name }}").unwrap();
+        engine.add_template("page", "{{> hi }}!").unwrap();
+        assert_eq!(engine.render("page", &data()), Ok("Hi AMIN!".to_owned()));
+
+        // Replacing and removing a template invalidates it for the others.
+        engine.add_template("hi", "Bye").unwrap();
+        assert_eq!(engine.render("page", &data()), Ok("Bye!".to_owned()));
+        assert!(engine.remove_template("hi"));
+        assert!(!engine.remove_template("hi"));
+        assert_eq!(
+            engine.render("page", &data()),
+            Err("unknown partial: hi".to_owned())
+        );
+        assert_eq!(
+            engine.render("hi", &data()),
+            Err("unknown template: hi".to_owned())
+        );
+    }
+
+    #[test]
+    fn added_templates_are_checked() {
+        let mut engine = engine();
+        engine.add_template("ok", "fine").unwrap();
+        assert_eq!(
+            engine.add_template("ok", "{{ name "),
+            Err("missing closing delimiter: }}".to_owned())
+        );
+        assert_eq!(engine.render("ok", &data()), Ok("fine".to_owned()));
+
+        engine.add_template("loop", "{{> loop }}").unwrap();
+        assert_eq!(
+            engine.render("loop", &data()),
+            Err("partial includes itself: loop > loop".to_owned())
+        );
+    }
+
+    #[test]
+    fn sandbox_refuses_helpers_it_doesnt_allow() {
+        let engine = engine()
+            .register_partial("hi", "Hi {{ lower name }}")
+            .sandbox(Sandbox::new().allow_function("shout"));
+        assert_eq!(
+            engine.parse("{{ shout name }}".to_owned(), &data()),
+            Ok("AMIN".to_owned())
+        );
+
+        let denied = [
+            ("{{ shout (lower name) }}", "function is not allowed: lower"),
+            (
+                r#"{{ plural 1 "a" "b" }}"#,
+                "function is not allowed: plural",
+            ),
+            // Partials and layouts are checked when they're rendered.
+            ("{{> hi }}", "function is not allowed: lower"),
+        ];
+        for (tmpl, expected) in denied {
+            let e = engine.parse(tmpl.to_owned(), &data()).unwrap_err();
+            assert!(e.ends_with(expected), "{}: {}", tmpl, e);
+        }
+
+        let engine = layouts().sandbox(Sandbox::new());
+        assert_eq!(
+            engine.parse("{{#extends profile}}".to_owned(), &data()),
+            Err("line 2, column 20: function is not allowed: shout".to_owned())
+        );
+        let engine = layouts().sandbox(Sandbox::new().allow_function("shout"));
+        assert_eq!(
+            engine.parse("{{#extends profile}}".to_owned(), &data()),
+            Ok("<title>AMIN</title>\n<p>Nothing</p>".to_owned())
+        );
+    }
+
+    #[test]
+    fn calls_in_untaken_branches_are_skipped() {
+        let tmpl = "{{#if missing}}{{ fail }}{{ fail name }}{{else}}{{ shout name }}{{/if}}";
+        assert_eq!(
+            engine().parse(tmpl.to_owned(), &data()),
+            Ok("AMIN".to_owned())
+        );
+    }
+}
@@ -0,0 +1,133 @@
+//! Helpers for fixed-width output that count grapheme clusters rather than
+//! chars, so that they never split an emoji or a letter from its accents.
+//! They're meant to be registered with an `Engine`:
+//!
+//! ```ignore
+//! let engine = Engine::new()
+//!     .register_helper("truncate", truncate)
+//!     .register_helper("pad", pad);
+//! ```
+//!
+//! The text comes last, so it can be piped in from another call, as in
+//! `{{ truncate "10" name | pad "12" }}`. Widths are quoted, as any other
+//! word would be taken for a data key.
+use unicode_segmentation::UnicodeSegmentation;
+
+use super::Result;
+
+/// `truncate "width" ["ellipsis"] text` cuts `text` down to `width` grapheme
+/// clusters. If it has to be cut, it ends in the ellipsis, which counts
+/// towards the width.
+pub fn truncate(args: &[String]) -> Result<String> {
+    let (width, ellipsis, text) = match args {
+        [width, text] => (width, "", text),
+        [width, ellipsis, text] => (width, ellipsis.as_str(), text),
+        _ => return Err("truncate takes a width, an optional ellipsis and the text".to_owned()),
+    };
+    let width = parse_width("truncate", width)?;
+
+    let graphemes: Vec<_> = text.graphemes(true).collect();
+    if graphemes.len() <= width {
+        return Ok(text.clone());
+    }
+    let ellipsis: Vec<_> = ellipsis.graphemes(true).take(width).collect();
+    let kept = width - ellipsis.len();
+    Ok(graphemes[..kept].concat() + &ellipsis.concat())
+}
+
+/// `pad "width" text` pads `text` with spaces to `width` grapheme clusters,
+/// after it, or before it with `">width"`, as in tables. Longer text is left
+/// as it is.
+pub fn pad(args: &[String]) -> Result<String> {
+    let [width, text] = args else {
+        return Err("pad takes a width and the text".to_owned());
+    };
+    let (right, width) = match width.strip_prefix('>') {
+        Some(width) => (true, width),
+        None => (false, width.as_str()),
+    };
+    let width = parse_width("pad", width)?;
+
+    let fill = " ".repeat(width.saturating_sub(text.graphemes(true).count()));
+    Ok(if right {
+        fill + text
+    } else {
+        text.clone() + &fill
+    })
+}
+
+fn parse_width(helper: &str, width: &str) -> Result<usize> {
+    width
+        .trim()
+        .parse()
+        .map_err(|_| format!("{}: not a width: {}", helper, width))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flexi_parser::Engine;
+    use std::collections::HashMap;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|&arg| arg.to_owned()).collect()
+    }
+
+    #[test]
+    fn truncate_keeps_graphemes_whole() {
+        // A family emoji is a single grapheme of several chars, as is an e
+        // with a combining accent.
+        let text = "👨‍👩‍👧e\u{301}abc";
+        assert_eq!(truncate(&args(&["3", text])), Ok("👨‍👩‍👧e\u{301}a".to_owned()));
+        assert_eq!(truncate(&args(&["1", text])), Ok("👨‍👩‍👧".to_owned()));
+        assert_eq!(
+            truncate(&args(&["4", "…", text])),
+            Ok("👨‍👩‍👧e\u{301}a…".to_owned())
+        );
+        assert_eq!(truncate(&args(&["5", "…", text])), Ok(text.to_owned()));
+        assert_eq!(truncate(&args(&["1", "...", text])), Ok(".".to_owned()));
+        assert_eq!(truncate(&args(&["0", text])), Ok("".to_owned()));
+    }
+
+    #[test]
+    fn pad_counts_graphemes() {
+        let text = "e\u{301}😀";
+        assert_eq!(pad(&args(&["4", text])), Ok("e\u{301}😀  ".to_owned()));
+        assert_eq!(pad(&args(&[">4", text])), Ok("  e\u{301}😀".to_owned()));
+        assert_eq!(pad(&args(&["1", text])), Ok(text.to_owned()));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            truncate(&args(&["x", "a"])),
+            Err("truncate: not a width: x".to_owned())
+        );
+        assert_eq!(
+            truncate(&args(&["1"])),
+            Err("truncate takes a width, an optional ellipsis and the text".to_owned())
+        );
+        assert_eq!(
+            pad(&args(&["-1", "a"])),
+            Err("pad: not a width: -1".to_owned())
+        );
+        assert_eq!(
+            pad(&args(&["1", "a", "b"])),
+            Err("pad takes a width and the text".to_owned())
+        );
+    }
+
+    #[test]
+    fn as_helpers() {
+        let engine = Engine::new()
+            .register_helper("truncate", truncate)
+            .register_helper("pad", pad);
+        let data = HashMap::from([("name".to_owned(), "Zoë 🦀 Rustacean".to_owned())]);
+
+        let tmpl = r#"[{{ truncate "6" "…" name | pad "7" }}] [{{ pad ">4" "ab" }}]"#;
+        assert_eq!(
+            engine.parse(tmpl.to_owned(), &data),
+            Ok("[Zoë 🦀… ] [  ab]".to_owned())
+        );
+    }
+}
@@ -0,0 +1,176 @@
+//! The front matter of a template file, a block between `---` lines at its
+//! very start that declares the defaults of keys and the keys the data has to
+//! hold:
+//!
+//! ```text
+//! ---
+//! # Comments take whole lines.
+//! defaults:
+//!   greeting: Hello
+//!   sign_off: "Best,"
+//! required: [name, email]
+//! ---
+//! {{ greeting }} {{ name }}, ...
+//! ```
+//!
+//! It's a small subset of YAML: the two keys above, a mapping of plain or
+//! quoted strings under `defaults`, and a list of keys under `required`,
+//! either as `[a, b]` or as `- a` lines.
+use std::collections::HashMap;
+
+use super::Result;
+
+const DELIMITER: &str = "---";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(super) struct FrontMatter {
+    pub(super) defaults: HashMap<String, String>,
+    pub(super) required: Vec<String>,
+}
+
+// What the indented lines belong to.
+enum Section {
+    None,
+    Defaults,
+    Required,
+}
+
+/// Splits the front matter off `tmpl`, returning it and the rest of the
+/// template. Templates without one get an empty front matter.
+pub(super) fn split(tmpl: &str) -> Result<(FrontMatter, &str)> {
+    let mut lines = tmpl.split_inclusive('\n');
+    let Some(first) = lines.next().filter(|&l| trim_newline(l) == DELIMITER) else {
+        return Ok((FrontMatter::default(), tmpl));
+    };
+
+    let mut front = FrontMatter::default();
+    let mut section = Section::None;
+    // Where the line after the current one starts.
+    let mut offset = first.len();
+    // The delimiter is line 1.
+    for (line, src) in (2..).zip(lines) {
+        offset += src.len();
+        let src = trim_newline(src);
+        if src == DELIMITER {
+            return Ok((front, &tmpl[offset..]));
+        }
+        let trimmed = src.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let err = |msg: &str| format!("front matter, line {}: {}: {}", line, msg, trimmed);
+
+        if !src.starts_with(char::is_whitespace) {
+            let Some((key, value)) = src.split_once(':') else {
+                return Err(err("expected a key"));
+            };
+            let value = value.trim();
+            section = match (key.trim_end(), value) {
+                ("defaults", "") => Section::Defaults,
+                ("required", "") => Section::Required,
+                ("required", list) => {
+                    let Some(list) = list.strip_prefix('[').and_then(|l| l.strip_suffix(']'))
+                    else {
+                        return Err(err("required is not a list"));
+                    };
+                    let keys = list.split(',').map(str::trim).filter(|k| !k.is_empty());
+                    front.required.extend(keys.map(unquote));
+                    Section::None
+                }
+                ("defaults", _) => return Err(err("defaults is not a mapping")),
+                _ => return Err(err("unknown key")),
+            };
+            continue;
+        }
+
+        match section {
+            Section::Defaults => match trimmed.split_once(':') {
+                Some((key, value)) => {
+                    front
+                        .defaults
+                        .insert(unquote(key.trim_end()), unquote(value.trim()));
+                }
+                None => return Err(err("expected a default")),
+            },
+            Section::Required => match trimmed.strip_prefix('-') {
+                Some(key) => front.required.push(unquote(key.trim())),
+                None => return Err(err("expected a required key")),
+            },
+            Section::None => return Err(err("unexpected indentation")),
+        }
+    }
+    Err("front matter isn't closed by ---".to_owned())
+}
+
+fn trim_newline(line: &str) -> &str {
+    line.trim_end_matches(['\n', '\r'])
+}
+
+fn unquote(s: &str) -> String {
+    ['"', '\'']
+        .iter()
+        .find_map(|&q| s.strip_prefix(q).and_then(|s| s.strip_suffix(q)))
+        .unwrap_or(s)
+        .to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_and_required_keys() {
+        let tmpl = "---\n# Greets.\ndefaults:\n  greeting: Hello\n  \"sign off\": 'Best, A'\n\n\
+                    required: [name, \"email\"]\n---\r\nHi {{ name }}\n---\n";
+        let (front, rest) = split(tmpl).unwrap();
+        assert_eq!(rest, "Hi {{ name }}\n---\n");
+        assert_eq!(
+            front.defaults,
+            HashMap::from([
+                ("greeting".to_owned(), "Hello".to_owned()),
+                ("sign off".to_owned(), "Best, A".to_owned()),
+            ])
+        );
+        assert_eq!(front.required, ["name", "email"]);
+
+        let (front, rest) = split("---\nrequired:\n  - a\n  - b\n---").unwrap();
+        assert_eq!(rest, "");
+        assert_eq!(front.required, ["a", "b"]);
+    }
+
+    #[test]
+    fn templates_without_front_matter() {
+        for tmpl in ["", "Hi {{ name }}", "--- Hi\n---\n", "\n---\n---\n"] {
+            assert_eq!(split(tmpl), Ok((FrontMatter::default(), tmpl)));
+        }
+    }
+
+    #[test]
+    fn malformed_front_matter() {
+        let err = |tmpl| split(tmpl).unwrap_err();
+        assert_eq!(
+            err("---\na: b\n"),
+            "front matter, line 2: unknown key: a: b"
+        );
+        assert_eq!(
+            err("---\ndefaults: x\n---\n"),
+            "front matter, line 2: defaults is not a mapping: defaults: x"
+        );
+        assert_eq!(
+            err("---\nrequired: a\n---\n"),
+            "front matter, line 2: required is not a list: required: a"
+        );
+        assert_eq!(
+            err("---\nrequired:\n  a\n---\n"),
+            "front matter, line 3: expected a required key: a"
+        );
+        assert_eq!(
+            err("---\n  a: b\n---\n"),
+            "front matter, line 2: unexpected indentation: a: b"
+        );
+        assert_eq!(
+            err("---\ndefaults:\n  a: b\n"),
+            "front matter isn't closed by ---"
+        );
+    }
+}
@@ -0,0 +1,143 @@
+//! JSON data, and any data that serializes to it, for `parse_values`.
+//!
+//! Objects become maps and arrays become lists, so they're reached with
+//! dotted paths and `{{#each}}` blocks. Numbers, bools and null become the
+//! values of the same name, see `Value` for how they're rendered and which
+//! of them are falsy. Numbers that don't fit an `i64` or `f64` are kept as
+//! they're written.
+use std::collections::HashMap;
+
+use serde::Serialize;
+use serde_json::Value as Json;
+
+use super::{parse_values, Result, Template, Value};
+
+impl From<Json> for Value {
+    fn from(json: Json) -> Self {
+        match json {
+            Json::Null => Value::Null,
+            Json::Bool(b) => Value::Bool(b),
+            Json::Number(n) => match (n.as_i64(), n.as_f64()) {
+                (Some(i), _) => Value::Int(i),
+                (None, Some(x)) if n.is_f64() => Value::Float(x),
+                _ => Value::Str(n.to_string()),
+            },
+            Json::String(s) => Value::Str(s),
+            Json::Array(items) => Value::List(items.into_iter().map(Value::from).collect()),
+            Json::Object(fields) => Value::Map(
+                fields
+                    .into_iter()
+                    .map(|(k, v)| (k, Value::from(v)))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// Like `parse_values`, with the fields of the JSON object `data` as the
+/// data.
+pub fn parse_with_json(tmpl: String, data: &Json) -> Result<String> {
+    parse_values(tmpl, &fields(data)?)
+}
+
+impl Template {
+    /// Like `render_values`, with the fields of the JSON object `data` as
+    /// the data.
+    pub fn render_json(&self, data: &Json) -> Result<String> {
+        self.render_values(fields(data)?)
+    }
+}
+
+fn fields(data: &Json) -> Result<HashMap<String, Value>> {
+    let Json::Object(fields) = data else {
+        return Err(format!("data has to be a JSON object, not: {}", data));
+    };
+    Ok(fields
+        .iter()
+        .map(|(k, v)| (k.clone(), Value::from(v.clone())))
+        .collect())
+}
+
+/// Like `parse_with_json`, with `data` serialized to JSON first. It has to
+/// serialize to an object, as structs and maps do.
+pub fn render(tmpl: String, data: &impl Serialize) -> Result<String> {
+    let json = serde_json::to_value(data).map_err(|e| format!("can't serialize data: {}", e))?;
+    parse_with_json(tmpl, &json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn objects_arrays_and_scalars() {
+        let data = json!({
+            "user": {"name": "Amin", "age": 30, "admin": false, "nick": null, "posts": 0},
+            "scores": [1.5, 2, u64::MAX],
+        });
+
+        let tmpl = "{{ user.name }} ({{ user.age }}){{#if user.admin}} admin{{/if}}\
+                    {{#if user.nick}} aka {{ user.nick }}{{/if}}\
+                    {{#if user.posts}} posting{{/if}}:\
+                    {{#each scores}} {{ this }}{{/each}}";
+        let result = parse_with_json(tmpl.to_owned(), &data);
+        assert_eq!(
+            result,
+            Ok("Amin (30): 1.5 2 18446744073709551615".to_owned())
+        );
+    }
+
+    #[test]
+    fn data_has_to_be_an_object() {
+        let result = parse_with_json("{{ this }}".to_owned(), &json!([1, 2]));
+        assert_eq!(
+            result,
+            Err("data has to be a JSON object, not: [1,2]".to_owned())
+        );
+    }
+
+    #[test]
+    fn templates_with_front_matter() {
+        let tmpl = "---\ndefaults:\n  greeting: Hello\nrequired: [users]\n---\n\
+                    {{ greeting }}{{#each users}} {{ this.name }}{{/each}}!";
+        let template = Template::compile_with_front_matter(tmpl).unwrap();
+
+        let data = json!({"users": [{"name": "Amin"}, {"name": "Sara"}]});
+        assert_eq!(
+            template.render_json(&data),
+            Ok("Hello Amin Sara!".to_owned())
+        );
+        let data = json!({"greeting": "Hi", "users": []});
+        assert_eq!(template.render_json(&data), Ok("Hi!".to_owned()));
+        assert_eq!(
+            template.render_json(&json!({})),
+            Err("missing required key: users".to_owned())
+        );
+    }
+
+    #[test]
+    fn render_serializable_data() {
+        #[derive(Serialize)]
+        struct Employee<'a> {
+            name: &'a str,
+            departments: Vec<&'a str>,
+        }
+
+        let data = Employee {
+            name: "Sara",
+            departments: vec!["Sales", "Engineering"],
+        };
+        let tmpl = "{{ name }} works in:{{#each departments}} {{ this }}{{/each}}";
+        assert_eq!(
+            render(tmpl.to_owned(), &data),
+            Ok("Sara works in: Sales Engineering".to_owned())
+        );
+
+        let result = render("".to_owned(), &"just a string");
+        assert_eq!(
+            result,
+            Err("data has to be a JSON object, not: \"just a string\"".to_owned())
+        );
+    }
+}
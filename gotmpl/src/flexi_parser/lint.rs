@@ -0,0 +1,327 @@
+//! Finds what's likely a mistake in a template without rendering it, for
+//! checking template files before they're deployed, as `gotmpl --check`
+//! does.
+//!
+//! Unlike a render, linting looks at every branch, and keeps going after the
+//! first problem. Only an error of the tokenizer, like a missing closing
+//! delimiter, stops it, as nothing after it can be told apart.
+use std::fmt;
+
+use super::blocks::BlockError;
+use super::sandbox::line_and_column;
+use super::tokens::{block_key, Iter, Limits, Token, TokenError};
+
+/// What `lint` warns about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Lint {
+    /// `{{ }}`, which has no key.
+    EmptyPlaceholder,
+    /// Text like `{name}`, which looks like a placeholder with single braces.
+    SingleBraces { text: String },
+    /// A `}}` in text, that doesn't close anything.
+    StrayClosingDelimiter,
+    /// A tag of a block without its counterpart, or a block that's never
+    /// closed. Named blocks, `{{#block name}}`, count as well.
+    Block(BlockError),
+    /// `{{/block}}` without `{{#block name}}`.
+    StrayEndBlock,
+    /// Spaces or tabs at the end of a line.
+    TrailingWhitespace,
+    /// The template can't be tokenized past this point.
+    Invalid(TokenError),
+}
+
+/// A lint at the part of the template it's about. Lines and columns start at
+/// 1, and columns count characters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintWarning {
+    pub lint: Lint,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Lint::EmptyPlaceholder => write!(f, "placeholder without a key"),
+            Lint::SingleBraces { text } => {
+                write!(
+                    f,
+                    "looks like a placeholder, but has single braces: {}",
+                    text
+                )
+            }
+            Lint::StrayClosingDelimiter => write!(f, "}}}} without {{{{"),
+            Lint::Block(e) => write!(f, "{}", e),
+            Lint::StrayEndBlock => write!(f, "{{{{/block}}}} without {{{{#block}}}}"),
+            Lint::TrailingWhitespace => write!(f, "trailing whitespace"),
+            Lint::Invalid(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl fmt::Display for LintWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.lint
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    If,
+    Each,
+    Table,
+    Named,
+}
+
+// An open block, and where it's opened.
+struct Open {
+    kind: Kind,
+    at: usize,
+    has_else: bool,
+}
+
+/// Returns the lints of `tmpl`, in the order they appear.
+pub fn lint(tmpl: &str) -> Vec<LintWarning> {
+    // With their offsets into tmpl.
+    let mut lints = Vec::new();
+    let mut open: Vec<Open> = Vec::new();
+    // Where the last token ends.
+    let mut end = 0;
+
+    for tkn in Iter::new(tmpl, Limits::new()).spanned() {
+        let (tkn, span) = match tkn {
+            Ok(tkn) => tkn,
+            Err(e) => {
+                // The error is about what follows the last token.
+                lints.push((end, Lint::Invalid(e)));
+                break;
+            }
+        };
+        end = span.end;
+        let kind = match tkn {
+            Token::Text(_) => {
+                lint_text(&tmpl[span.clone()], span.start, &mut lints);
+                continue;
+            }
+            Token::Placeholder("") => {
+                lints.push((span.start, Lint::EmptyPlaceholder));
+                continue;
+            }
+            Token::Placeholder(p) if block_key(p, "#block").is_some() => Kind::Named,
+            Token::Placeholder("/block") => {
+                close(&mut open, Kind::Named, span.start, &mut lints);
+                continue;
+            }
+            Token::Placeholder(_) => continue,
+            Token::If(_) => Kind::If,
+            Token::Each(_) => Kind::Each,
+            Token::Table(_) => Kind::Table,
+            Token::Else => {
+                let err = match open.last_mut() {
+                    Some(block) if block.kind == Kind::If && !block.has_else => {
+                        block.has_else = true;
+                        continue;
+                    }
+                    Some(block) if block.kind == Kind::If => BlockError::DuplicateElse,
+                    _ => BlockError::UnexpectedElse,
+                };
+                lints.push((span.start, Lint::Block(err)));
+                continue;
+            }
+            Token::EndIf => {
+                close(&mut open, Kind::If, span.start, &mut lints);
+                continue;
+            }
+            Token::EndEach => {
+                close(&mut open, Kind::Each, span.start, &mut lints);
+                continue;
+            }
+            Token::EndTable => {
+                close(&mut open, Kind::Table, span.start, &mut lints);
+                continue;
+            }
+        };
+        open.push(Open {
+            kind,
+            at: span.start,
+            has_else: false,
+        });
+    }
+    for block in open {
+        lints.push((block.at, Lint::Block(BlockError::Unclosed { open: 1 })));
+    }
+    trailing_whitespace(tmpl, &mut lints);
+
+    lints.sort_by_key(|&(at, _)| at);
+    lints
+        .into_iter()
+        .map(|(at, lint)| {
+            let (line, column) = line_and_column(tmpl, at);
+            LintWarning { lint, line, column }
+        })
+        .collect()
+}
+
+// Closes the innermost block if it's of `kind`. A tag that closes nothing
+// is left alone, so that it doesn't throw off the tags after it.
+fn close(open: &mut Vec<Open>, kind: Kind, at: usize, lints: &mut Vec<(usize, Lint)>) {
+    if open.last().is_some_and(|block| block.kind == kind) {
+        open.pop();
+        return;
+    }
+    let err = match kind {
+        Kind::If => BlockError::UnexpectedEndIf,
+        Kind::Each => BlockError::UnexpectedEndEach,
+        Kind::Table => BlockError::UnexpectedEndTable,
+        // BlockError only knows the blocks of the parsers.
+        Kind::Named => return lints.push((at, Lint::StrayEndBlock)),
+    };
+    lints.push((at, Lint::Block(err)));
+}
+
+// Lints the source of a text token, which starts at `offset`.
+fn lint_text(source: &str, offset: usize, lints: &mut Vec<(usize, Lint)>) {
+    // A `{{` in text is escaped, and so is the placeholder it opens.
+    let mut escaped = Vec::new();
+    for (at, _) in source.match_indices("{{") {
+        if let Some(len) = source[at..].find("}}") {
+            escaped.push(at..at + len + 2);
+        }
+    }
+    let is_escaped = |at: usize| escaped.iter().any(|e| e.contains(&at));
+
+    for (at, _) in source.match_indices("}}") {
+        if !is_escaped(at) {
+            lints.push((offset + at, Lint::StrayClosingDelimiter));
+        }
+    }
+
+    let bytes = source.as_bytes();
+    for (at, _) in source.match_indices('{') {
+        let doubled = |i: Option<usize>| i.and_then(|i| bytes.get(i)) == Some(&b'{');
+        if doubled(at.checked_sub(1)) || doubled(Some(at + 1)) {
+            continue;
+        }
+        let Some(len) = source[at..].find('}') else {
+            continue;
+        };
+        let key = source[at + 1..at + len].trim();
+        let is_key = !key.is_empty()
+            && key
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '_' || c == '.');
+        if is_key && !source[at + len..].starts_with("}}") {
+            let text = source[at..=at + len].to_owned();
+            lints.push((offset + at, Lint::SingleBraces { text }));
+        }
+    }
+}
+
+fn trailing_whitespace(tmpl: &str, lints: &mut Vec<(usize, Lint)>) {
+    let mut start = 0;
+    for line in tmpl.split_inclusive('\n') {
+        let content = line.trim_end_matches(['\n', '\r']);
+        let trimmed = content.trim_end_matches([' ', '\t']);
+        if trimmed.len() < content.len() {
+            lints.push((start + trimmed.len(), Lint::TrailingWhitespace));
+        }
+        start += line.len();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lints(tmpl: &str) -> Vec<(usize, usize, Lint)> {
+        lint(tmpl)
+            .into_iter()
+            .map(|w| (w.line, w.column, w.lint))
+            .collect()
+    }
+
+    #[test]
+    fn clean_templates() {
+        let tmpl = "Hi {{ name }},\n{{#if admin}}{a: 1} {{else}}{}{{/if}}\n\\{{ x }}";
+        assert_eq!(lints(tmpl), vec![]);
+    }
+
+    #[test]
+    fn placeholders_and_braces() {
+        let tmpl = "{{ }} {name} {{ a }} b }} {{ c }}\n{ d.e }";
+        assert_eq!(
+            lints(tmpl),
+            vec![
+                (1, 1, Lint::EmptyPlaceholder),
+                (
+                    1,
+                    7,
+                    Lint::SingleBraces {
+                        text: "{name}".to_owned()
+                    }
+                ),
+                (1, 24, Lint::StrayClosingDelimiter),
+                (
+                    2,
+                    1,
+                    Lint::SingleBraces {
+                        text: "{ d.e }".to_owned()
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn unbalanced_blocks() {
+        let tmpl = "{{/if}}{{#if a}}{{else}}{{else}}{{#each l}}\n{{/table}}{{/each}}\
+                    {{#block b}}{{/if}}{{/block}}{{/block}}";
+        use BlockError::*;
+        assert_eq!(
+            lints(tmpl),
+            vec![
+                (1, 1, Lint::Block(UnexpectedEndIf)),
+                (1, 8, Lint::Block(Unclosed { open: 1 })),
+                (1, 25, Lint::Block(DuplicateElse)),
+                (2, 1, Lint::Block(UnexpectedEndTable)),
+                (2, 32, Lint::Block(UnexpectedEndIf)),
+                (2, 49, Lint::StrayEndBlock),
+            ]
+        );
+    }
+
+    #[test]
+    fn trailing_whitespace_and_invalid_templates() {
+        let tmpl = "a  \r\n\t\n{{ b }} \n{{ c";
+        assert_eq!(
+            lints(tmpl),
+            vec![
+                (1, 2, Lint::TrailingWhitespace),
+                (2, 1, Lint::TrailingWhitespace),
+                (3, 8, Lint::Invalid(TokenError::MissingClosingDelimiter)),
+                (3, 8, Lint::TrailingWhitespace),
+            ]
+        );
+    }
+
+    #[test]
+    fn display() {
+        let w = LintWarning {
+            lint: Lint::SingleBraces {
+                text: "{a}".to_owned(),
+            },
+            line: 1,
+            column: 2,
+        };
+        assert_eq!(
+            w.to_string(),
+            "line 1, column 2: looks like a placeholder, but has single braces: {a}"
+        );
+        assert_eq!(Lint::StrayClosingDelimiter.to_string(), "}} without {{");
+    }
+}
@@ -1,10 +1,90 @@
 mod tokens;
+pub use tokens::{Limits, TokenError};
 use tokens::{Token, Tokens};
 
+#[cfg(feature = "sources")]
+mod sources;
+#[cfg(feature = "sources")]
+pub use sources::Sources;
+
+#[cfg(feature = "serde")]
+mod json;
+#[cfg(feature = "serde")]
+pub use json::{parse_with_json, render};
+
+#[cfg(feature = "rayon")]
+mod bulk;
+#[cfg(feature = "rayon")]
+pub use bulk::render_many;
+
+#[cfg(feature = "unicode")]
+mod filters;
+#[cfg(feature = "unicode")]
+pub use filters::{pad, truncate};
+
+mod blocks;
+pub use blocks::BlockError;
+use blocks::{is_truthy, Blocks};
+
+mod borrowed;
+pub use borrowed::render_borrowed;
+
+mod engine;
+pub use engine::{Engine, IncludeError};
+
+mod front_matter;
+
+mod lint;
+pub use lint::{lint, Lint, LintWarning};
+
+mod sandbox;
+pub use sandbox::{Sandbox, SandboxError, Violation};
+
+mod stream;
+pub use stream::{render_fmt_to, render_to};
+
+mod table;
+
+mod template;
+pub use template::Template;
+
+mod template_test;
+pub use template_test::{Failure, TemplateTest, TestReport};
+
+mod validate;
+pub use validate::{validate, MissingKey};
+
+mod value;
+pub use value::Value;
+use value::{field, Resolver};
+
+use std::borrow::Cow;
 use std::collections::HashMap;
 
+use crate::MissingKeyPolicy;
+
 type Result<T> = std::result::Result<T, String>;
 
+/// Replaces the placeholders of `tmpl` with their values in `data`.
+///
+/// What's between `{{#if key}}` and `{{/if}}` is only rendered if `key` is
+/// in `data` and its value is neither empty nor `false`, and what's after an
+/// optional `{{else}}` only if it's not. This holds for all the parsers here.
+///
+/// A `{{` that's preceded by a backslash is output as is, without the
+/// backslash: `\{{ name }}` renders `{{ name }}`. Two backslashes render a
+/// single one, followed by the placeholder.
+///
+/// `{{ name | default:"Guest" }}` renders `Guest` if `name` isn't in `data`,
+/// instead of failing. The default is everything between the first and the
+/// last quote, so it can hold quotes itself.
+///
+/// `{{! ... }}` is a comment, which renders as nothing. It ends at the first
+/// `}}`.
+///
+/// `{{- ` strips the whitespace before a tag, newlines included, and ` -}}`
+/// the whitespace after it, so that blocks on lines of their own don't leave
+/// blank lines behind: `{{- #if admin }}`.
 pub fn parse(tmpl: String, data: HashMap<String, String>) -> Result<String> {
     // let tokens = Tokens::from(tmpl);
     // let parsed = String::new();
@@ -22,25 +102,240 @@ pub fn parse(tmpl: String, data: HashMap<String, String>) -> Result<String> {
     //         },
     //     })
     let tokens = Tokens::from(tmpl);
+    let mut blocks = Blocks::new();
     let mut parsed = String::new();
 
     for tkn in tokens.into_iter() {
         let tkn = tkn?;
+        if !blocks.step(&tkn, |k| truthy(k, &data))? {
+            continue;
+        }
         let resolved = resolve_token(&tkn, &data)?;
         parsed.push_str(&resolved);
     }
+    blocks.finish()?;
     Ok(parsed)
 }
 
 pub fn parse_ref(tmpl: String, data: HashMap<String, String>) -> Result<String> {
-    let tokens = Tokens::from(tmpl);
+    parse_with_limits(tmpl, data, Limits::new())
+}
+
+/// Like `parse_ref`, but fails once the template exceeds `limits`. Meant for
+/// templates from untrusted sources.
+pub fn parse_with_limits(
+    tmpl: String,
+    data: HashMap<String, String>,
+    limits: Limits,
+) -> Result<String> {
+    render_ref(tmpl, &data, limits, &MissingKeyPolicy::Error)
+}
+
+/// Like `parse_ref`, but a placeholder whose key isn't in `data` renders as
+/// `missing` says, and only fails with `MissingKeyPolicy::Error`. The keys of
+/// `{{#if}}` blocks aren't affected, a missing one is falsy anyway.
+pub fn parse_with_policy(
+    tmpl: String,
+    data: HashMap<String, String>,
+    missing: &MissingKeyPolicy,
+) -> Result<String> {
+    render_ref(tmpl, &data, Limits::new(), missing)
+}
+
+fn render_ref(
+    tmpl: String,
+    data: &HashMap<String, String>,
+    limits: Limits,
+    missing: &MissingKeyPolicy,
+) -> Result<String> {
+    let tokens = Tokens::from(tmpl).with_limits(limits);
+    let mut blocks = Blocks::new();
     let mut parsed = String::new();
 
     for tkn in tokens.iter() {
         let tkn = tkn?;
-        let resolved = resolve_token(&tkn, &data)?;
+        if !blocks.step(&tkn, |k| truthy(k, data))? {
+            continue;
+        }
+        let resolved = resolve_or(&tkn, data, missing)?;
         parsed.push_str(&resolved);
     }
+    blocks.finish()?;
+    Ok(parsed)
+}
+
+/// Like `parse_ref`, but fails before rendering anything if the template
+/// violates `sandbox`. Meant for templates written by untrusted users.
+pub fn parse_sandboxed(
+    tmpl: String,
+    data: HashMap<String, String>,
+    sandbox: &Sandbox,
+) -> Result<String> {
+    sandbox.check(&tmpl)?;
+    parse_ref(tmpl, data)
+}
+
+/// Like `parse_ref`, but the data can hold lazy values, which are only
+/// evaluated if their placeholder is rendered, and lists, which are rendered
+/// by `{{#each key}} ... {{/each}}`, or as a table with aligned columns by
+/// `{{#table key}} ... {{/table}}`. See `Value` for the order lazy values are
+/// evaluated in, and for the dotted paths into maps.
+pub fn parse_values(tmpl: String, data: &HashMap<String, Value>) -> Result<String> {
+    let tokens = Tokens::from(tmpl);
+    // The bodies of each blocks are rendered repeatedly.
+    let tkns = tokens.iter().collect::<std::result::Result<Vec<_>, _>>()?;
+    render_tokens(&tkns, data)
+}
+
+// Renders tkns with data like parse_values.
+fn render_tokens(tkns: &[Token<&str>], data: &HashMap<String, Value>) -> Result<String> {
+    let mut resolver = Resolver::new(data);
+    let mut parsed = String::new();
+
+    render_values(tkns, None, &mut resolver, &mut parsed)?;
+    Ok(parsed)
+}
+
+// Renders tkns for parse_values. `elem` is the element of the innermost each
+// block and its index, if there is one.
+fn render_values<'a>(
+    tkns: &[Token<&str>],
+    elem: Option<(&'a Value, usize)>,
+    resolver: &mut Resolver<'a>,
+    parsed: &mut String,
+) -> Result<()> {
+    let lookup = |resolver: &Resolver<'a>, key: &str| match (key, elem) {
+        ("this", Some((value, _))) => Ok(value),
+        (_, Some((value, _))) => match key.strip_prefix("this.") {
+            Some(path) => field(value, path)
+                .ok_or(format!("couldn't find data corresponding to key: {}", key)),
+            None => resolver.value(key),
+        },
+        _ => resolver.value(key),
+    };
+    let index = |key: &str| match (key, elem) {
+        ("@index", Some((_, idx))) => Some(idx),
+        _ => None,
+    };
+
+    let mut blocks = Blocks::new();
+    let mut i = 0;
+    while i < tkns.len() {
+        let tkn = &tkns[i];
+        i += 1;
+
+        if let Token::Each(key) | Token::Table(key) = *tkn {
+            let table = matches!(tkn, Token::Table(_));
+            let end = i + end_of_block(&tkns[i..], table)?;
+            if blocks.rendering() {
+                let Value::List(items) = lookup(resolver, key)? else {
+                    let key = key.to_owned();
+                    return Err(BlockError::NotAList { key }.into());
+                };
+                if table {
+                    let cells = table::cells(&tkns[i..end])?;
+                    let mut rows = Vec::with_capacity(items.len());
+                    for (idx, item) in items.iter().enumerate() {
+                        // Each value renders like a placeholder of an each
+                        // block.
+                        let row = table::keys(&cells)
+                            .map(|key| {
+                                let mut value = String::new();
+                                let tkn = [Token::Placeholder(key)];
+                                render_values(&tkn, Some((item, idx)), resolver, &mut value)?;
+                                Ok(value)
+                            })
+                            .collect::<Result<Vec<_>>>()?;
+                        rows.push(row);
+                    }
+                    table::render(&cells, &rows, parsed);
+                } else {
+                    for (idx, item) in items.iter().enumerate() {
+                        render_values(&tkns[i..end], Some((item, idx)), resolver, parsed)?;
+                    }
+                }
+            }
+            i = end + 1;
+            continue;
+        }
+
+        let truthy = |k: &&str| {
+            index(k).is_some() || lookup(resolver, k).is_ok_and(|v| resolver.is_truthy(v))
+        };
+        if !blocks.step(tkn, truthy)? {
+            continue;
+        }
+        match *tkn {
+            Token::Text(t) => parsed.push_str(t),
+            Token::Placeholder(k) => {
+                let (k, default) = split_default(k)?;
+                match (index(k), lookup(resolver, k), default) {
+                    (Some(idx), _, _) => parsed.push_str(&idx.to_string()),
+                    (None, Err(_), Some(default)) => parsed.push_str(default),
+                    (None, value, _) => parsed.push_str(resolver.render(value?)?),
+                }
+            }
+            // Blocks::step doesn't render the tags, each and table blocks are
+            // handled above.
+            _ => (),
+        }
+    }
+    blocks.finish()?;
+    Ok(())
+}
+
+// The index of the /each that closes an each block whose body starts at
+// tkns[0], or of the /table that closes a table block. Fails if the template
+// ends before it.
+fn end_of_block(tkns: &[Token<&str>], table: bool) -> Result<usize> {
+    let mut depth = 0;
+    for (i, tkn) in tkns.iter().enumerate() {
+        let (open, close) = match tkn {
+            Token::Each(_) => (!table, false),
+            Token::EndEach => (false, !table),
+            Token::Table(_) => (table, false),
+            Token::EndTable => (false, table),
+            _ => (false, false),
+        };
+        if open {
+            depth += 1;
+        } else if close && depth == 0 {
+            return Ok(i);
+        } else if close {
+            depth -= 1;
+        }
+    }
+    Err(BlockError::Unclosed { open: depth + 1 }.into())
+}
+
+/// Like `parse`, but placeholders can also read from the built-in `env` and
+/// `file` sources, as far as `sources` allows it. Fails before rendering
+/// anything if the template violates the sandbox of `sources`.
+#[cfg(feature = "sources")]
+pub fn parse_with_sources(
+    tmpl: String,
+    data: HashMap<String, String>,
+    sources: &Sources,
+) -> Result<String> {
+    sources.check(&tmpl)?;
+    let tokens = Tokens::from(tmpl);
+    let mut blocks = Blocks::new();
+    let mut parsed = String::new();
+
+    for tkn in tokens.iter() {
+        let tkn = tkn?;
+        if !blocks.step(&tkn, |k| truthy(k, &data))? {
+            continue;
+        }
+        match &tkn {
+            Token::Placeholder(k) => match sources.resolve(k) {
+                Some(resolved) => parsed.push_str(&resolved?),
+                None => parsed.push_str(resolve_token(&tkn, &data)?),
+            },
+            _ => parsed.push_str(resolve_token(&tkn, &data)?),
+        }
+    }
+    blocks.finish()?;
     Ok(parsed)
 }
 
@@ -52,14 +347,56 @@ where
     match tkn {
         Token::Text(k) => Ok(k.as_ref()),
         Token::Placeholder(k) => {
-            let k = k.as_ref();
+            let (k, default) = split_default(k.as_ref())?;
             data.get(k)
                 .map(|v| v.as_str())
+                .or(default)
                 .ok_or(format!("couldn't find data corresponding to key: {}", k))
         }
+        // The tags of blocks render as nothing.
+        _ => Ok(""),
+    }
+}
+
+// Splits `key | default:"text"` into the key and the default. Placeholders
+// without a default are the key as a whole.
+fn split_default(p: &str) -> Result<(&str, Option<&str>)> {
+    let Some((key, rest)) = p.split_once('|') else {
+        return Ok((p, None));
+    };
+    let Some(default) = rest.trim_start().strip_prefix("default:") else {
+        return Ok((p, None));
+    };
+    let default = default.trim();
+    match default.strip_prefix('"').and_then(|d| d.strip_suffix('"')) {
+        Some(default) => Ok((key.trim_end(), Some(default))),
+        None => Err(format!("default is not a quoted string: {}", p)),
+    }
+}
+
+// Like resolve_token, but a missing key is up to `missing`.
+fn resolve_or<'a, T>(
+    tkn: &'a Token<T>,
+    data: &'a HashMap<String, String>,
+    missing: &'a MissingKeyPolicy,
+) -> Result<Cow<'a, str>>
+where
+    T: AsRef<str> + 'a,
+{
+    match resolve_token(tkn, data) {
+        Ok(resolved) => Ok(Cow::Borrowed(resolved)),
+        Err(e) => match tkn {
+            // A malformed default stays an error.
+            Token::Placeholder(k) => missing.render(split_default(k.as_ref())?.0).ok_or(e),
+            _ => Err(e),
+        },
     }
 }
 
+fn truthy<T: AsRef<str>>(key: &T, data: &HashMap<String, String>) -> bool {
+    is_truthy(data.get(key.as_ref()).map(String::as_str))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -135,6 +472,383 @@ mod tests {
         assert_eq!("Hello, Amin!", result);
     }
 
+    #[cfg(feature = "sources")]
+    #[test]
+    fn parse_with_sources_mixes_data_and_sources() {
+        std::env::set_var("GOTMPL_PARSE_TEST_USER", "amin");
+        let tmpl = String::from("Hello, {{ name }} ({{ env \"GOTMPL_PARSE_TEST_USER\" }})!");
+        let data = HashMap::from([("name".to_string(), "Amin".to_string())]);
+        let sources = Sources::new().allow_env("GOTMPL_PARSE_TEST_USER");
+
+        let result = parse_with_sources(tmpl.clone(), data.clone(), &sources).unwrap();
+        assert_eq!("Hello, Amin (amin)!", result);
+
+        let result = parse_with_sources(tmpl, data, &Sources::new());
+        assert_eq!(
+            Err("environment variable is not allowed: GOTMPL_PARSE_TEST_USER".to_owned()),
+            result
+        );
+    }
+
+    #[cfg(feature = "sources")]
+    #[test]
+    fn parse_with_sources_enforces_the_sandbox() {
+        std::env::set_var("GOTMPL_PARSE_SANDBOX_TEST", "secret");
+        let tmpl = String::from("{{ env \"GOTMPL_PARSE_SANDBOX_TEST\" }}");
+        let sources = Sources::new().allow_env("GOTMPL_PARSE_SANDBOX_TEST");
+
+        let allowed = sources
+            .clone()
+            .sandbox(Sandbox::new().allow_function("env"));
+        let result = parse_with_sources(tmpl.clone(), HashMap::new(), &allowed);
+        assert_eq!(Ok("secret".to_owned()), result);
+
+        let denied = sources.sandbox(Sandbox::new().allow_function("file"));
+        let result = parse_with_sources(tmpl, HashMap::new(), &denied);
+        assert_eq!(
+            Err("line 1, column 4: function is not allowed: env".to_owned()),
+            result
+        );
+    }
+
+    #[test]
+    fn parse_with_limits_reports_exceeded_limit() {
+        let data = HashMap::from([("name".to_string(), "Amin".to_string())]);
+
+        let tmpl = "{{ name }}".repeat(100);
+        let result = parse_with_limits(tmpl.clone(), data.clone(), Limits::new().max_tokens(200));
+        assert_eq!(Ok("Amin".repeat(100)), result);
+
+        let result = parse_with_limits(tmpl, data, Limits::new().max_tokens(50));
+        assert_eq!(Err("template has more than 50 tokens".to_owned()), result);
+    }
+
+    #[test]
+    fn inline_defaults() {
+        let data = HashMap::from([("name".to_string(), "Amin".to_string())]);
+        let render = |tmpl: &str| parse(tmpl.to_owned(), data.clone());
+
+        assert_eq!(
+            render(r#"Hi {{ name | default:"Guest" }} {{ surname|default: "Doe" }}"#),
+            Ok("Hi Amin Doe".to_owned())
+        );
+        assert_eq!(
+            render(r#"{{ title | default:"" }}{{ nick | default:"the "great"" }}"#),
+            Ok("the \"great\"".to_owned())
+        );
+        assert_eq!(
+            render(r#"{{ nick | default:Guest }}"#),
+            Err("default is not a quoted string: nick | default:Guest".to_owned())
+        );
+        // Not a default, so it's all the key.
+        assert_eq!(
+            render("{{ a | b }}"),
+            Err("couldn't find data corresponding to key: a | b".to_owned())
+        );
+    }
+
+    #[test]
+    fn inline_defaults_win_over_the_policy() {
+        let tmpl = r#"{{ a | default:"x" }} {{ b }}"#.to_owned();
+        let result = parse_with_policy(tmpl, HashMap::new(), &MissingKeyPolicy::KeepPlaceholder);
+        assert_eq!(Ok("x {{ b }}".to_owned()), result);
+
+        let tmpl = r#"{{ a | default:x }}"#.to_owned();
+        let result = parse_with_policy(tmpl, HashMap::new(), &MissingKeyPolicy::Empty);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn parse_with_policy_for_missing_keys() {
+        let tmpl = "Hi {{ name }}{{#if admin}}, admin{{/if}}!".to_owned();
+        let data = HashMap::new();
+
+        let result = parse_with_policy(tmpl.clone(), data.clone(), &MissingKeyPolicy::Error);
+        assert_eq!(
+            Err("couldn't find data corresponding to key: name".to_owned()),
+            result
+        );
+        let result = parse_with_policy(tmpl.clone(), data.clone(), &MissingKeyPolicy::Empty);
+        assert_eq!(Ok("Hi !".to_owned()), result);
+        let policy = MissingKeyPolicy::Default("there".to_owned());
+        let result = parse_with_policy(tmpl, data, &policy);
+        assert_eq!(Ok("Hi there!".to_owned()), result);
+    }
+
+    #[test]
+    fn parse_sandboxed_checks_before_rendering() {
+        let data = HashMap::from([("name".to_string(), "Amin".to_string())]);
+        let sandbox = Sandbox::new().max_operations(0);
+
+        let result = parse_sandboxed("Hello, {{ name }}!".to_owned(), data.clone(), &sandbox);
+        assert_eq!(Ok("Hello, Amin!".to_owned()), result);
+
+        let result = parse_sandboxed("{{ name }} {{ upper name }}".to_owned(), data, &sandbox);
+        assert_eq!(
+            Err("line 1, column 15: template performs more than 0 operations".to_owned()),
+            result
+        );
+    }
+
+    #[test]
+    fn parse_values_only_evaluates_rendered_keys() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        let order = Rc::new(RefCell::new(Vec::new()));
+        let lazy = |key: &'static str| {
+            let order = Rc::clone(&order);
+            Value::lazy(move || {
+                order.borrow_mut().push(key);
+                key.to_uppercase().into()
+            })
+        };
+        let data = HashMap::from([
+            ("greeting".to_owned(), Value::from("Hello")),
+            ("first".to_owned(), lazy("first")),
+            ("last".to_owned(), lazy("last")),
+            ("unused".to_owned(), lazy("unused")),
+        ]);
+
+        let tmpl = "{{ greeting }}, {{ last }} {{ first }} {{ last }}!".to_owned();
+        let result = parse_values(tmpl, &data).unwrap();
+        assert_eq!("Hello, LAST FIRST LAST!", result);
+        assert_eq!(*order.borrow(), ["last", "first"]);
+
+        // Nothing after a missing key is evaluated.
+        order.borrow_mut().clear();
+        let result = parse_values("{{ missing }}{{ first }}".to_owned(), &data);
+        assert!(result.is_err());
+        assert!(order.borrow().is_empty());
+    }
+
+    #[test]
+    fn conditional_blocks() {
+        let tmpl = "{{#if admin}}Hi admin {{ name }}{{else}}Hi {{ name }}{{/if}}!".to_owned();
+        let data = HashMap::from([("name".to_string(), "Amin".to_string())]);
+        assert_eq!(parse(tmpl.clone(), data.clone()), Ok("Hi Amin!".to_owned()));
+
+        let mut admin = data.clone();
+        admin.insert("admin".to_owned(), "yes".to_owned());
+        assert_eq!(
+            parse_ref(tmpl.clone(), admin),
+            Ok("Hi admin Amin!".to_owned())
+        );
+
+        admin = data;
+        admin.insert("admin".to_owned(), "false".to_owned());
+        assert_eq!(parse_ref(tmpl, admin), Ok("Hi Amin!".to_owned()));
+    }
+
+    #[test]
+    fn escaped_delimiters_are_output_as_is() {
+        let tmpl = r"Use \{{ name }} for {{ name }}, C:\\{{ dir }}".to_owned();
+        let data = HashMap::from([
+            ("name".to_string(), "Amin".to_string()),
+            ("dir".to_string(), "tmp".to_string()),
+        ]);
+        let expected = r"Use {{ name }} for Amin, C:\tmp".to_owned();
+        assert_eq!(parse(tmpl.clone(), data.clone()), Ok(expected.clone()));
+        assert_eq!(parse_ref(tmpl, data), Ok(expected));
+    }
+
+    #[test]
+    fn comments_render_as_nothing() {
+        let data = HashMap::from([("name".to_string(), "Amin".to_string())]);
+        let tmpl = "Hi {{! greeting, see #12 }}{{ name }}{{!{{ missing }}!".to_owned();
+        assert_eq!(parse(tmpl.clone(), data.clone()), Ok("Hi Amin!".to_owned()));
+        assert_eq!(parse_ref(tmpl, data), Ok("Hi Amin!".to_owned()));
+    }
+
+    #[test]
+    fn untaken_branches_may_refer_to_missing_keys() {
+        let tmpl = "{{#if missing}}{{ missing }}{{/if}}done".to_owned();
+        assert_eq!(parse(tmpl, HashMap::new()), Ok("done".to_owned()));
+    }
+
+    #[test]
+    fn unbalanced_blocks() {
+        let result = parse_ref("{{#if a}}a".to_owned(), HashMap::new());
+        assert_eq!(result, Err("1 {{#if}} not closed with {{/if}}".to_owned()));
+
+        let result = parse("a{{/if}}".to_owned(), HashMap::new());
+        assert_eq!(result, Err("{{/if}} without {{#if}}".to_owned()));
+    }
+
+    #[test]
+    fn parse_values_evaluates_conditions_lazily() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        let calls = Rc::new(Cell::new(0));
+        let counter = Rc::clone(&calls);
+        let data = HashMap::from([
+            (
+                "flag".to_owned(),
+                Value::lazy(move || {
+                    counter.set(counter.get() + 1);
+                    "on".into()
+                }),
+            ),
+            ("unused".to_owned(), Value::lazy(|| panic!("evaluated"))),
+        ]);
+
+        let tmpl = "{{#if flag}}{{ flag }}{{/if}}{{#if flag}}{{else}}{{#if unused}}{{/if}}{{/if}}";
+        assert_eq!(parse_values(tmpl.to_owned(), &data), Ok("on".to_owned()));
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[test]
+    fn each_blocks() {
+        let data = HashMap::from([
+            ("title".to_owned(), Value::from("Team")),
+            ("names".to_owned(), Value::from(vec!["Amin", "Sara"])),
+            ("empty".to_owned(), Value::List(vec![])),
+        ]);
+
+        let tmpl = "{{ title }}:{{#each names}} {{ @index }}.{{ this }} of {{ title }}{{/each}}\
+                    {{#if empty}}!{{else}}{{#each empty}}?{{/each}}.{{/if}}";
+        let result = parse_values(tmpl.to_owned(), &data);
+        assert_eq!(
+            result,
+            Ok("Team: 0.Amin of Team 1.Sara of Team.".to_owned())
+        );
+    }
+
+    #[test]
+    fn each_blocks_with_inline_defaults() {
+        let people = Value::List(vec![
+            Value::from(HashMap::from([("name", "Amin"), ("role", "admin")])),
+            Value::from(HashMap::from([("name", "Sara")])),
+        ]);
+        let data = HashMap::from([("people".to_owned(), people)]);
+
+        let tmpl = r#"{{#each people}}{{ this.name }}:{{ this.role | default:"user" }} {{/each}}"#;
+        let result = parse_values(tmpl.to_owned(), &data);
+        assert_eq!(result, Ok("Amin:admin Sara:user ".to_owned()));
+    }
+
+    #[test]
+    fn trim_markers_leave_no_blank_lines() {
+        let data = HashMap::from([
+            ("names".to_owned(), Value::from(vec!["Amin", "Sara"])),
+            ("admin".to_owned(), Value::from("yes")),
+        ]);
+        let tmpl = "Team:\n{{- #each names }}\n- {{ this }}\n{{- /each }}\n{{- #if admin }}\n(admin)\n{{- /if }}\n";
+        let result = parse_values(tmpl.to_owned(), &data);
+        assert_eq!(result, Ok("Team:\n- Amin\n- Sara\n(admin)\n".to_owned()));
+    }
+
+    #[test]
+    fn nested_each_blocks_refer_to_the_innermost_element() {
+        let rows = Value::List(vec![
+            Value::from(vec!["a", "b"]),
+            Value::from(vec!["c"]),
+            Value::List(vec![]),
+        ]);
+        let data = HashMap::from([("rows".to_owned(), rows)]);
+
+        let tmpl = "{{#each rows}}[{{#if this}}{{#each this}}{{ @index }}{{ this }}{{/each}}{{else}}-{{/if}}]{{/each}}";
+        let result = parse_values(tmpl.to_owned(), &data);
+        assert_eq!(result, Ok("[0a1b][0c][-]".to_owned()));
+    }
+
+    #[test]
+    fn dotted_paths_into_maps() {
+        let user = |name: &str, city: &str| {
+            let address = HashMap::from([("city", city)]);
+            Value::Map(HashMap::from([
+                ("name".to_owned(), Value::from(name)),
+                ("address".to_owned(), Value::from(address)),
+            ]))
+        };
+        let data = HashMap::from([
+            ("user".to_owned(), user("Amin", "Tehran")),
+            (
+                "team".to_owned(),
+                Value::List(vec![user("Sara", "Paris"), user("Omid", "")]),
+            ),
+        ]);
+
+        let tmpl = "{{ user.name }} from {{ user.address.city }}, with \
+                    {{#each team}}{{ this.name }}{{#if this.address.city}} \
+                    ({{ this.address.city }}){{/if}}, {{/each}}lead {{ team.0.name }}";
+        let result = parse_values(tmpl.to_owned(), &data);
+        assert_eq!(
+            result,
+            Ok("Amin from Tehran, with Sara (Paris), Omid, lead Sara".to_owned())
+        );
+
+        let result = parse_values("{{#each team}}{{ this.age }}{{/each}}".to_owned(), &data);
+        assert_eq!(
+            result,
+            Err("couldn't find data corresponding to key: this.age".to_owned())
+        );
+    }
+
+    #[test]
+    fn each_needs_a_list() {
+        let data = HashMap::from([
+            ("name".to_owned(), Value::from("Amin")),
+            ("list".to_owned(), Value::from(vec!["a"])),
+        ]);
+
+        let result = parse_values("{{#each name}}{{/each}}".to_owned(), &data);
+        assert_eq!(result, Err("not a list: name".to_owned()));
+        let result = parse_values("{{ list }}".to_owned(), &data);
+        assert_eq!(
+            result,
+            Err("a list can only be rendered by {{#each}}".to_owned())
+        );
+        let result = parse_values("{{#each list}}{{ this }}".to_owned(), &data);
+        assert_eq!(result, Err("1 {{#if}} not closed with {{/if}}".to_owned()));
+
+        // String data has no lists at all.
+        let data = HashMap::from([("name".to_string(), "Amin".to_string())]);
+        let result = parse("{{#each name}}{{/each}}".to_owned(), data);
+        assert_eq!(result, Err("not a list: name".to_owned()));
+    }
+
+    #[test]
+    fn table_blocks() {
+        let user =
+            |name: &str, age: &str| Value::from(HashMap::from([("name", name), ("age", age)]));
+        let data = HashMap::from([
+            ("unit".to_owned(), Value::from("y")),
+            (
+                "users".to_owned(),
+                Value::List(vec![
+                    user("Amin", "34"),
+                    user("Sara", "7"),
+                    user("Jo", "101"),
+                ]),
+            ),
+        ]);
+
+        let tmpl = "Users:\n{{#table users}}{{ @index }}. {{ this.name }} {{ >this.age }}{{ unit }}\n{{/table}}";
+        let result = parse_values(tmpl.to_owned(), &data);
+        assert_eq!(
+            result,
+            Ok("Users:\n0. Amin  34y\n1. Sara   7y\n2. Jo   101y\n".to_owned())
+        );
+
+        let tmpl = "{{#each users}}{{#table this}}{{ this }}{{/table}}{{/each}}";
+        let result = parse_values(tmpl.to_owned(), &data);
+        assert_eq!(result, Err("not a list: this".to_owned()));
+        let tmpl = "{{#table users}}{{#if this.name}}{{/if}}{{/table}}";
+        let result = parse_values(tmpl.to_owned(), &data);
+        assert_eq!(
+            result,
+            Err("a table row can only hold text and placeholders".to_owned())
+        );
+        let result = parse_values("{{#table users}}{{/each}}".to_owned(), &data);
+        assert_eq!(result, Err("1 {{#if}} not closed with {{/if}}".to_owned()));
+
+        // Tables are skipped in untaken branches of the other parsers.
+        let tmpl = "{{#if no}}{{#table users}}{{ this }}{{/table}}{{/if}}done".to_owned();
+        assert_eq!(parse(tmpl, HashMap::new()), Ok("done".to_owned()));
+    }
+
     #[test]
     fn parse_ref_large_template() {
         let tmpl = std::fs::read_to_string("templates/large.tmpl").unwrap();
@@ -0,0 +1,326 @@
+//! Checks a template against a sandbox before it's rendered, for templates
+//! written by untrusted users.
+//!
+//! A placeholder with more than one word is a function call, like
+//! `{{ env "HOME" }}`: the first word is the function and the rest are its
+//! arguments. An argument in parentheses is a call itself, as in
+//! `{{ upper (env "USER") }}`, and `|` pipes the result of one call into the
+//! next one. A single word is a data key.
+//!
+//! The sandbox bounds how deeply calls are nested, how many operations (calls
+//! and pipes) a render may perform, and which functions may be called at all.
+//! The check is static, so a template that passes it can't run into the
+//! limits halfway through rendering.
+//!
+//! It's enforced by everything that calls functions: `Engine::sandbox`
+//! checks every template, partial and layout before rendering it, so its
+//! helpers and the built-in `plural` have to be allowed, and
+//! `Sources::sandbox` does the same for the `env` and `file` sources.
+use std::collections::HashSet;
+use std::fmt;
+
+use super::tokens::{Iter, Limits, Token};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sandbox {
+    max_depth: usize,
+    max_operations: usize,
+    functions: HashSet<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    TooDeep { limit: usize },
+    TooManyOperations { limit: usize },
+    FunctionNotAllowed { name: String },
+}
+
+/// A violation of the sandbox, at the word of the template that caused it.
+/// Lines and columns start at 1, and columns count characters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SandboxError {
+    pub violation: Violation,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::TooDeep { limit } => {
+                write!(f, "calls are nested deeper than {} levels", limit)
+            }
+            Violation::TooManyOperations { limit } => {
+                write!(f, "template performs more than {} operations", limit)
+            }
+            Violation::FunctionNotAllowed { name } => {
+                write!(f, "function is not allowed: {}", name)
+            }
+        }
+    }
+}
+
+impl fmt::Display for SandboxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.violation
+        )
+    }
+}
+
+impl std::error::Error for SandboxError {}
+
+// The parsers report errors as Strings.
+impl From<SandboxError> for String {
+    fn from(e: SandboxError) -> Self {
+        e.to_string()
+    }
+}
+
+impl Default for Sandbox {
+    fn default() -> Self {
+        Self {
+            max_depth: usize::MAX,
+            max_operations: usize::MAX,
+            functions: HashSet::new(),
+        }
+    }
+}
+
+impl Sandbox {
+    /// Creates a sandbox without limits, which doesn't allow calling any
+    /// function.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How deeply calls may be nested. A call has a depth of 1, and every
+    /// pair of parentheses around it adds another level.
+    pub fn max_depth(mut self, max: usize) -> Self {
+        self.max_depth = max;
+        self
+    }
+
+    /// How many calls and pipes the whole template may contain.
+    pub fn max_operations(mut self, max: usize) -> Self {
+        self.max_operations = max;
+        self
+    }
+
+    pub fn allow_function(mut self, name: impl Into<String>) -> Self {
+        self.functions.insert(name.into());
+        self
+    }
+
+    /// Returns the first violation of the sandbox in `tmpl`. Errors of the
+    /// template itself, like a missing closing delimiter, are left to the
+    /// render: checking just stops there.
+    pub fn check(&self, tmpl: &str) -> Result<(), SandboxError> {
+        let mut operations = 0;
+
+        for tkn in Iter::new(tmpl, Limits::new()) {
+            let placeholder = match tkn {
+                Ok(Token::Placeholder(p)) => p,
+                // The keys of blocks are never calls.
+                Ok(_) => continue,
+                Err(_) => break,
+            };
+            // The placeholder is a slice of tmpl.
+            let offset = placeholder.as_ptr() as usize - tmpl.as_ptr() as usize;
+
+            if let Some((at, violation)) = self.check_placeholder(placeholder, &mut operations) {
+                let (line, column) = line_and_column(tmpl, offset + at);
+                return Err(SandboxError {
+                    violation,
+                    line,
+                    column,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    // Returns the first violation in placeholder, with its offset.
+    fn check_placeholder(
+        &self,
+        placeholder: &str,
+        operations: &mut usize,
+    ) -> Option<(usize, Violation)> {
+        if !placeholder.contains(char::is_whitespace) {
+            // A data key.
+            return None;
+        }
+        if placeholder.starts_with(['>', '#', '/']) {
+            // The includes and the block tags of the engine.
+            return None;
+        }
+
+        let mut parens = 0;
+        // Whether the next word is the name of a function.
+        let mut expect_call = true;
+
+        for (at, word) in Words::new(placeholder) {
+            match word {
+                "(" => {
+                    parens += 1;
+                    expect_call = true;
+                }
+                // Unbalanced parentheses are for the render to report.
+                ")" => parens = usize::saturating_sub(parens, 1),
+                "|" => {
+                    *operations += 1;
+                    if *operations > self.max_operations {
+                        return Some((at, self.too_many_operations()));
+                    }
+                    expect_call = true;
+                }
+                name if expect_call => {
+                    expect_call = false;
+                    *operations += 1;
+                    if parens + 1 > self.max_depth {
+                        let limit = self.max_depth;
+                        return Some((at, Violation::TooDeep { limit }));
+                    }
+                    if *operations > self.max_operations {
+                        return Some((at, self.too_many_operations()));
+                    }
+                    if !self.functions.contains(name) {
+                        let name = name.to_owned();
+                        return Some((at, Violation::FunctionNotAllowed { name }));
+                    }
+                }
+                _ => (),
+            }
+        }
+        None
+    }
+
+    fn too_many_operations(&self) -> Violation {
+        Violation::TooManyOperations {
+            limit: self.max_operations,
+        }
+    }
+}
+
+pub(super) fn line_and_column(tmpl: &str, offset: usize) -> (usize, usize) {
+    let before = &tmpl[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+// Splits a placeholder into words and their offsets. Parentheses and pipes
+// are words of their own, and a quoted string is a single word even if it
+// contains any of them.
+pub(super) struct Words<'a> {
+    s: &'a str,
+    idx: usize,
+}
+
+impl<'a> Words<'a> {
+    pub(super) fn new(s: &'a str) -> Self {
+        Self { s, idx: 0 }
+    }
+}
+
+impl<'a> Iterator for Words<'a> {
+    type Item = (usize, &'a str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rest = &self.s[self.idx..];
+        let start = self.idx + rest.find(|c: char| !c.is_whitespace())?;
+        let rest = &self.s[start..];
+
+        let len = match rest.as_bytes()[0] {
+            b'(' | b')' | b'|' => 1,
+            // An unterminated string runs until the end.
+            b'"' => rest[1..].find('"').map_or(rest.len(), |i| i + 2),
+            _ => rest
+                .find(|c: char| c.is_whitespace() || "()|\"".contains(c))
+                .unwrap_or(rest.len()),
+        };
+        self.idx = start + len;
+        Some((start, &self.s[start..self.idx]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn violation(sandbox: &Sandbox, tmpl: &str) -> Option<(Violation, usize, usize)> {
+        let e = sandbox.check(tmpl).err()?;
+        Some((e.violation, e.line, e.column))
+    }
+
+    #[test]
+    fn words() {
+        let words: Vec<_> = Words::new(r#"upper (env "A (B)")|trim "#).collect();
+        assert_eq!(
+            words,
+            [
+                (0, "upper"),
+                (6, "("),
+                (7, "env"),
+                (11, r#""A (B)""#),
+                (18, ")"),
+                (19, "|"),
+                (20, "trim"),
+            ]
+        );
+    }
+
+    #[test]
+    fn only_allowed_functions_are_called() {
+        let sandbox = Sandbox::new().allow_function("env");
+        assert_eq!(sandbox.check(r#"{{ name }} {{ env "HOME" }}"#), Ok(()));
+
+        assert_eq!(
+            violation(&sandbox, "Hi {{ name }},\n  {{ env \"HOME\" | file }}"),
+            Some((
+                Violation::FunctionNotAllowed {
+                    name: "file".to_owned()
+                },
+                2,
+                19
+            ))
+        );
+    }
+
+    #[test]
+    fn nested_calls_are_bounded() {
+        let sandbox = Sandbox::new().allow_function("f").max_depth(2);
+        assert_eq!(sandbox.check("{{ f (f 1) }}"), Ok(()));
+        assert_eq!(
+            violation(&sandbox, "{{ f (f (f 1)) }}"),
+            Some((Violation::TooDeep { limit: 2 }, 1, 10))
+        );
+    }
+
+    #[test]
+    fn operations_are_counted_over_the_whole_template() {
+        let sandbox = Sandbox::new().allow_function("f").max_operations(5);
+        // Data keys aren't operations.
+        assert_eq!(
+            sandbox.check("{{ a }}{{ f 1 | f }}{{ b }}{{ f (f 1) }}"),
+            Ok(())
+        );
+        assert_eq!(
+            violation(&sandbox, "{{ f 1 | f }}{{ f 1 | f }}"),
+            Some((Violation::TooManyOperations { limit: 5 }, 1, 23))
+        );
+    }
+
+    #[test]
+    fn error_message() {
+        let e: String = Sandbox::new()
+            .check("\n\n  {{ env \"A\" }}")
+            .unwrap_err()
+            .into();
+        assert_eq!(e, "line 3, column 6: function is not allowed: env");
+    }
+}
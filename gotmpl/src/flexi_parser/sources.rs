@@ -0,0 +1,211 @@
+//! Built-in data sources which let templates read values that aren't in the
+//! data map: `{{ env "HOME" }}` and `{{ file "banner.txt" }}`.
+//!
+//! Both are sandboxed. Only environment variables that were explicitly
+//! allowed can be read, and files are only read from inside the configured
+//! root directory, up to a maximum size. On top of that, a `Sandbox` can
+//! restrict which of the sources templates may call at all.
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::{Result, Sandbox};
+
+const DEFAULT_MAX_FILE_SIZE: u64 = 64 * 1024;
+
+#[derive(Debug, Clone)]
+pub struct Sources {
+    env_allowlist: HashSet<String>,
+    file_root: Option<PathBuf>,
+    max_file_size: u64,
+    sandbox: Option<Sandbox>,
+}
+
+impl Default for Sources {
+    fn default() -> Self {
+        Self {
+            env_allowlist: HashSet::new(),
+            file_root: None,
+            max_file_size: DEFAULT_MAX_FILE_SIZE,
+            sandbox: None,
+        }
+    }
+}
+
+impl Sources {
+    /// Creates sources that don't allow reading anything.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn allow_env(mut self, name: impl Into<String>) -> Self {
+        self.env_allowlist.insert(name.into());
+        self
+    }
+
+    /// Allows `file` to read files inside `root` and its subdirectories.
+    pub fn file_root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.file_root = Some(root.into());
+        self
+    }
+
+    pub fn max_file_size(mut self, max: u64) -> Self {
+        self.max_file_size = max;
+        self
+    }
+
+    /// Checks templates against `sandbox` before they're rendered, so that
+    /// only the sources it allows as functions, `env` or `file`, can be
+    /// called.
+    pub fn sandbox(mut self, sandbox: Sandbox) -> Self {
+        self.sandbox = Some(sandbox);
+        self
+    }
+
+    pub(crate) fn check(&self, tmpl: &str) -> Result<()> {
+        match &self.sandbox {
+            Some(sandbox) => Ok(sandbox.check(tmpl)?),
+            None => Ok(()),
+        }
+    }
+
+    /// Resolves a placeholder if it's a call to one of the sources. Returns
+    /// None when the placeholder is a regular data key.
+    pub(crate) fn resolve(&self, placeholder: &str) -> Option<Result<String>> {
+        let (name, arg) = placeholder.split_once(char::is_whitespace)?;
+        let resolve = match name {
+            "env" => Sources::env,
+            "file" => Sources::file,
+            _ => return None,
+        };
+
+        Some(parse_quoted(arg.trim()).and_then(|arg| resolve(self, arg)))
+    }
+
+    fn env(&self, var: &str) -> Result<String> {
+        if !self.env_allowlist.contains(var) {
+            return Err(format!("environment variable is not allowed: {}", var));
+        }
+        std::env::var(var).map_err(|e| format!("couldn't read environment variable {}: {}", var, e))
+    }
+
+    fn file(&self, path: &str) -> Result<String> {
+        let root = self
+            .file_root
+            .as_ref()
+            .ok_or_else(|| "reading files is not allowed".to_owned())?;
+
+        let full = sandboxed_path(root, Path::new(path))?;
+        let len = fs::metadata(&full)
+            .map_err(|e| format!("couldn't read file {}: {}", path, e))?
+            .len();
+        if len > self.max_file_size {
+            return Err(format!(
+                "file {} is larger than the maximum of {} bytes",
+                path, self.max_file_size
+            ));
+        }
+
+        fs::read_to_string(&full).map_err(|e| format!("couldn't read file {}: {}", path, e))
+    }
+}
+
+// Canonicalizing both paths resolves `..` and symlinks, so the check can't be
+// tricked into reading outside of root.
+fn sandboxed_path(root: &Path, path: &Path) -> Result<PathBuf> {
+    let root = root
+        .canonicalize()
+        .map_err(|e| format!("invalid file root {}: {}", root.display(), e))?;
+    let full = root
+        .join(path)
+        .canonicalize()
+        .map_err(|e| format!("couldn't read file {}: {}", path.display(), e))?;
+
+    if !full.starts_with(&root) {
+        return Err(format!(
+            "file is outside of the allowed root: {}",
+            path.display()
+        ));
+    }
+    Ok(full)
+}
+
+fn parse_quoted(arg: &str) -> Result<&str> {
+    arg.strip_prefix('"')
+        .and_then(|a| a.strip_suffix('"'))
+        .filter(|a| !a.is_empty() && !a.contains('"'))
+        .ok_or_else(|| format!("expected a single quoted argument, got: {}", arg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regular_keys_are_not_resolved() {
+        let sources = Sources::new().allow_env("HOME");
+        assert!(sources.resolve("name").is_none());
+        assert!(sources.resolve("user name").is_none());
+    }
+
+    #[test]
+    fn env_allowlist() {
+        std::env::set_var("GOTMPL_SOURCES_TEST", "value");
+        let sources = Sources::new().allow_env("GOTMPL_SOURCES_TEST");
+
+        assert_eq!(
+            sources.resolve("env \"GOTMPL_SOURCES_TEST\""),
+            Some(Ok("value".to_owned()))
+        );
+        assert_eq!(
+            sources.resolve("env \"PATH\""),
+            Some(Err("environment variable is not allowed: PATH".to_owned()))
+        );
+    }
+
+    #[test]
+    fn argument_must_be_quoted() {
+        let sources = Sources::new();
+        assert_eq!(
+            sources.resolve("env HOME"),
+            Some(Err(
+                "expected a single quoted argument, got: HOME".to_owned()
+            ))
+        );
+        assert!(sources.resolve("file \"a\" \"b\"").unwrap().is_err());
+    }
+
+    #[test]
+    fn file_sandbox() {
+        let root = std::env::temp_dir().join("gotmpl-sources-test");
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("sub/banner.txt"), "Welcome!").unwrap();
+        fs::write(root.join("big.txt"), "0123456789").unwrap();
+
+        assert_eq!(
+            Sources::new().resolve("file \"sub/banner.txt\""),
+            Some(Err("reading files is not allowed".to_owned()))
+        );
+
+        let sources = Sources::new().file_root(&root).max_file_size(5);
+        assert_eq!(
+            sources.resolve("file \"sub/banner.txt\""),
+            Some(Err(
+                "file sub/banner.txt is larger than the maximum of 5 bytes".to_owned()
+            ))
+        );
+
+        let sources = Sources::new().file_root(root.join("sub"));
+        assert_eq!(
+            sources.resolve("file \"banner.txt\""),
+            Some(Ok("Welcome!".to_owned()))
+        );
+        assert_eq!(
+            sources.resolve("file \"../big.txt\""),
+            Some(Err(
+                "file is outside of the allowed root: ../big.txt".to_owned()
+            ))
+        );
+        assert!(sources.resolve("file \"missing.txt\"").unwrap().is_err());
+    }
+}
@@ -0,0 +1,128 @@
+//! Rendering straight into a writer, for templates too large to be worth
+//! building the whole output in memory first. Every token is written as soon
+//! as it's resolved, and the template is borrowed rather than copied.
+//!
+//! Nothing can be taken back once written, so a render that fails halfway
+//! (a missing key, or an unclosed block at the end) leaves the output up to
+//! the error in the writer.
+use std::collections::HashMap;
+use std::{fmt, io};
+
+use super::blocks::Blocks;
+use super::tokens::{Iter, Limits};
+use super::{resolve_token, truthy, Result};
+
+/// Like `parse_ref`, but writes the output to `writer`. Every token is a
+/// separate write, so unbuffered writers like sockets and files are best
+/// wrapped in a `BufWriter`.
+pub fn render_to<W: io::Write>(
+    tmpl: &str,
+    data: &HashMap<String, String>,
+    mut writer: W,
+) -> Result<()> {
+    render_with(tmpl, data, |s| {
+        writer
+            .write_all(s.as_bytes())
+            .map_err(|e| format!("couldn't write output: {}", e))
+    })
+}
+
+/// Like `render_to`, but for writers of text, like a `String` or a
+/// `fmt::Formatter`.
+pub fn render_fmt_to<W: fmt::Write>(
+    tmpl: &str,
+    data: &HashMap<String, String>,
+    mut writer: W,
+) -> Result<()> {
+    render_with(tmpl, data, |s| {
+        writer
+            .write_str(s)
+            .map_err(|_| "couldn't write output".to_owned())
+    })
+}
+
+fn render_with(
+    tmpl: &str,
+    data: &HashMap<String, String>,
+    mut write: impl FnMut(&str) -> Result<()>,
+) -> Result<()> {
+    let mut blocks = Blocks::new();
+
+    for tkn in Iter::new(tmpl, Limits::new()) {
+        let tkn = tkn?;
+        if !blocks.step(&tkn, |k| truthy(k, data))? {
+            continue;
+        }
+        let resolved = resolve_token(&tkn, data)?;
+        if !resolved.is_empty() {
+            write(resolved)?;
+        }
+    }
+    blocks.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flexi_parser::parse_ref;
+
+    fn data() -> HashMap<String, String> {
+        HashMap::from([
+            ("name".to_owned(), "Amin".to_owned()),
+            ("admin".to_owned(), "yes".to_owned()),
+        ])
+    }
+
+    #[test]
+    fn writes_what_parse_returns() {
+        let tmpl = "Hello {{#if admin}}admin {{/if}}{{ name }}, \\{{ escaped }}!";
+        let expected = parse_ref(tmpl.to_owned(), data()).unwrap();
+
+        let mut bytes = Vec::new();
+        render_to(tmpl, &data(), &mut bytes).unwrap();
+        assert_eq!(String::from_utf8(bytes).unwrap(), expected);
+
+        let mut text = String::new();
+        render_fmt_to(tmpl, &data(), &mut text).unwrap();
+        assert_eq!(text, expected);
+    }
+
+    #[test]
+    fn output_up_to_the_error_is_written() {
+        let mut text = String::new();
+        let err = render_fmt_to("Hi {{ name }} {{ missing }}!", &data(), &mut text);
+        assert_eq!(
+            err,
+            Err("couldn't find data corresponding to key: missing".to_owned())
+        );
+        assert_eq!(text, "Hi Amin ");
+    }
+
+    // Accepts a limited number of bytes, like a full disk.
+    struct Full(usize);
+
+    impl io::Write for Full {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.0 == 0 {
+                return Err(io::ErrorKind::WriteZero.into());
+            }
+            let n = buf.len().min(self.0);
+            self.0 -= n;
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn write_errors() {
+        assert_eq!(render_to("Hi {{ name }}", &data(), Full(7)), Ok(()));
+        assert_eq!(
+            render_to("Hi {{ name }}", &data(), Full(6)),
+            Err("couldn't write output: write zero".to_owned())
+        );
+    }
+}
@@ -0,0 +1,180 @@
+//! Tables: `{{#table key}} ... {{/table}}` renders the list under `key` like
+//! an each block, once per element, but pads every placeholder to the widest
+//! value of its column so that the columns line up. The placeholders are the
+//! columns, in order, and the text between them separates the columns:
+//!
+//! ```text
+//! {{#table users}}{{ this.name }} | {{ >this.age }}
+//! {{/table}}
+//! ```
+//!
+//! A `>` in front of the key aligns the column to the right, for numbers. The
+//! last column isn't padded if only whitespace follows it, so lines don't end
+//! in spaces. A row can only hold text and placeholders, no other blocks.
+//!
+//! Rendering takes two passes: all the values are rendered first, to measure
+//! the columns, and then the rows are written out.
+use std::fmt::Write;
+
+use super::blocks::BlockError;
+use super::tokens::Token;
+
+/// A piece of the row of a table.
+#[derive(Debug, PartialEq)]
+pub(super) enum Cell<'t> {
+    Text(&'t str),
+    Column { key: &'t str, right: bool },
+}
+
+/// Splits the body of a table block into its cells.
+pub(super) fn cells<'t>(body: &[Token<&'t str>]) -> Result<Vec<Cell<'t>>, BlockError> {
+    body.iter()
+        .map(|tkn| match *tkn {
+            Token::Text(t) => Ok(Cell::Text(t)),
+            Token::Placeholder(p) => Ok(match p.strip_prefix('>') {
+                Some(key) => Cell::Column {
+                    key: key.trim_start(),
+                    right: true,
+                },
+                None => Cell::Column {
+                    key: p,
+                    right: false,
+                },
+            }),
+            _ => Err(BlockError::BlockInTable),
+        })
+        .collect()
+}
+
+/// The keys of the columns, in order.
+pub(super) fn keys<'c>(cells: &'c [Cell<'_>]) -> impl Iterator<Item = &'c str> {
+    cells.iter().filter_map(|cell| match *cell {
+        Cell::Column { key, .. } => Some(key),
+        Cell::Text(_) => None,
+    })
+}
+
+/// Writes out `rows`, which hold the rendered values of the columns of
+/// `cells`.
+pub(super) fn render(cells: &[Cell<'_>], rows: &[Vec<String>], out: &mut String) {
+    let mut widths = vec![0; keys(cells).count()];
+    for row in rows {
+        for (width, value) in widths.iter_mut().zip(row) {
+            *width = (*width).max(value.chars().count());
+        }
+    }
+
+    let last = cells
+        .iter()
+        .rposition(|cell| matches!(cell, Cell::Column { .. }));
+    let trailing = last.is_some_and(|last| {
+        cells[last + 1..]
+            .iter()
+            .all(|cell| matches!(cell, Cell::Text(t) if t.trim().is_empty()))
+    });
+
+    for row in rows {
+        let mut values = row.iter().zip(&widths);
+        for (i, cell) in cells.iter().enumerate() {
+            let right = match *cell {
+                Cell::Text(t) => {
+                    out.push_str(t);
+                    continue;
+                }
+                Cell::Column { right, .. } => right,
+            };
+            let Some((value, &width)) = values.next() else {
+                break;
+            };
+            // Writing to a String doesn't fail.
+            let _ = if right {
+                write!(out, "{:>1$}", value, width)
+            } else if trailing && Some(i) == last {
+                write!(out, "{}", value)
+            } else {
+                write!(out, "{:<1$}", value, width)
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn cells_of_a_row() {
+        use Token::*;
+
+        let body = [
+            Text("- "),
+            Placeholder("name"),
+            Text(" "),
+            Placeholder("> age"),
+        ];
+        let expected = vec![
+            Cell::Text("- "),
+            Cell::Column {
+                key: "name",
+                right: false,
+            },
+            Cell::Text(" "),
+            Cell::Column {
+                key: "age",
+                right: true,
+            },
+        ];
+        assert_eq!(cells(&body), Ok(expected));
+        assert_eq!(
+            cells(&[Text(""), If("a"), EndIf]),
+            Err(BlockError::BlockInTable)
+        );
+    }
+
+    #[test]
+    fn columns_line_up() {
+        let cells = [
+            Cell::Text("| "),
+            Cell::Column {
+                key: "name",
+                right: false,
+            },
+            Cell::Text(" | "),
+            Cell::Column {
+                key: "age",
+                right: true,
+            },
+            Cell::Text(" |\n"),
+        ];
+        let rows = [row(&["Amin", "7"]), row(&["Sara", "123"]), row(&["Jo", ""])];
+
+        let mut out = String::new();
+        render(&cells, &rows, &mut out);
+        assert_eq!(out, "| Amin |   7 |\n| Sara | 123 |\n| Jo   |     |\n");
+    }
+
+    #[test]
+    fn last_column_is_not_padded_at_the_end_of_a_line() {
+        let cells = [
+            Cell::Column {
+                key: "name",
+                right: false,
+            },
+            Cell::Text("  "),
+            Cell::Column {
+                key: "city",
+                right: false,
+            },
+            Cell::Text("\n"),
+        ];
+        let rows = [row(&["Amin", "Tehran"]), row(&["Sara", "Paris"])];
+
+        let mut out = String::new();
+        render(&cells, &rows, &mut out);
+        assert_eq!(out, "Amin  Tehran\nSara  Paris\n");
+    }
+}
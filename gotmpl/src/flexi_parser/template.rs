@@ -0,0 +1,249 @@
+//! Templates that are tokenized once and rendered many times. The parse
+//! functions tokenize the template on every call, which dominates the cost
+//! of rendering short templates with little data.
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use super::blocks::{is_truthy, Blocks};
+use super::front_matter::{self, FrontMatter};
+use super::tokens::{Limits, Token, Tokens};
+use super::{render_tokens, resolve_or, split_default, Result, Value};
+use crate::MissingKeyPolicy;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    tokens: Vec<Token<String>>,
+    // The length of the text between the placeholders, which the output of
+    // a render is at least.
+    text_len: usize,
+    missing: MissingKeyPolicy,
+    front: FrontMatter,
+}
+
+impl Template {
+    /// Tokenizes `tmpl`. Fails on the errors `parse` would fail on before
+    /// rendering anything, like a missing closing delimiter.
+    pub fn compile(tmpl: &str) -> Result<Template> {
+        Self::compile_with_limits(tmpl, Limits::new())
+    }
+
+    /// Like `compile`, but `tmpl` can start with a front matter between
+    /// `---` lines, which declares defaults for keys missing from the data
+    /// and keys the data has to hold:
+    ///
+    /// ```text
+    /// ---
+    /// defaults:
+    ///   greeting: Hello
+    /// required: [name]
+    /// ---
+    /// {{ greeting }}, {{ name }}!
+    /// ```
+    ///
+    /// The front matter isn't part of the output.
+    pub fn compile_with_front_matter(tmpl: &str) -> Result<Template> {
+        let (front, tmpl) = front_matter::split(tmpl)?;
+        let mut template = Self::compile(tmpl)?;
+        template.front = front;
+        Ok(template)
+    }
+
+    /// Like `compile`, but fails once the template exceeds `limits`.
+    pub fn compile_with_limits(tmpl: &str, limits: Limits) -> Result<Template> {
+        let tokens = Tokens::from(tmpl.to_owned())
+            .with_limits(limits)
+            .into_iter()
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        let text_len = tokens
+            .iter()
+            .map(|tkn| match tkn {
+                Token::Text(t) => t.len(),
+                _ => 0,
+            })
+            .sum();
+        Ok(Template {
+            tokens,
+            text_len,
+            missing: MissingKeyPolicy::Error,
+            front: FrontMatter::default(),
+        })
+    }
+
+    /// The defaults of the front matter, which are rendered for keys the
+    /// data doesn't hold.
+    pub fn defaults(&self) -> &HashMap<String, String> {
+        &self.front.defaults
+    }
+
+    /// The keys the front matter requires the data to hold.
+    pub fn required(&self) -> &[String] {
+        &self.front.required
+    }
+
+    /// What placeholders whose key isn't in the data render as, see
+    /// `parse_with_policy`. Missing keys are errors by default.
+    pub fn missing_keys(mut self, missing: MissingKeyPolicy) -> Self {
+        self.missing = missing;
+        self
+    }
+
+    /// Renders the template like `parse` does, and can be called any number
+    /// of times. Fails if `data` lacks a key the front matter requires,
+    /// whether or not it has a default.
+    pub fn render(&self, data: &HashMap<String, String>) -> Result<String> {
+        if let Some(key) = self.required().iter().find(|&k| !data.contains_key(k)) {
+            return Err(format!("missing required key: {}", key));
+        }
+        let lookup = |key: &str| data.get(key).or_else(|| self.defaults().get(key));
+
+        let mut blocks = Blocks::new();
+        let mut parsed = String::with_capacity(self.text_len);
+
+        for tkn in &self.tokens {
+            if !blocks.step(tkn, |k| is_truthy(lookup(k).map(String::as_str)))? {
+                continue;
+            }
+            parsed.push_str(&self.resolve(tkn, data)?);
+        }
+        blocks.finish()?;
+        Ok(parsed)
+    }
+
+    /// Like `render`, but renders `data` like `parse_values` does, so it can
+    /// hold lists and maps. The defaults of the front matter are added to
+    /// `data` as strings for the keys it doesn't hold. Missing keys are
+    /// always errors.
+    pub fn render_values(&self, mut data: HashMap<String, Value>) -> Result<String> {
+        if let Some(key) = self.required().iter().find(|&k| !data.contains_key(k)) {
+            return Err(format!("missing required key: {}", key));
+        }
+        for (key, default) in self.defaults() {
+            data.entry(key.clone())
+                .or_insert_with(|| Value::Str(default.clone()));
+        }
+
+        let tkns: Vec<_> = self.tokens.iter().map(Token::as_deref).collect();
+        render_tokens(&tkns, &data)
+    }
+
+    // Resolves tkn against the data, and falls back to the defaults of the
+    // front matter for keys the data doesn't hold.
+    fn resolve<'a>(
+        &'a self,
+        tkn: &'a Token<String>,
+        data: &'a HashMap<String, String>,
+    ) -> Result<Cow<'a, str>> {
+        let default = match tkn {
+            Token::Placeholder(p) if !self.defaults().is_empty() => {
+                let (key, _) = split_default(p)?;
+                self.defaults().get(key).filter(|_| !data.contains_key(key))
+            }
+            _ => None,
+        };
+        match default {
+            Some(value) => Ok(Cow::Borrowed(value)),
+            None => resolve_or(tkn, data, &self.missing),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_like_parse() {
+        let tmpl = std::fs::read_to_string("templates/large.tmpl").unwrap();
+        let expected = std::fs::read_to_string("templates/large.parsed").unwrap();
+        let data: HashMap<_, _> = (1..=3)
+            .flat_map(|i| {
+                [
+                    (format!("name{}", i), format!("A{}", i)),
+                    (format!("surname{}", i), format!("M{}", i)),
+                ]
+            })
+            .collect();
+
+        let template = Template::compile(&tmpl).unwrap();
+        assert_eq!(template.render(&data), Ok(expected.clone()));
+        assert_eq!(template.render(&data), Ok(expected));
+    }
+
+    #[test]
+    fn data_can_change_between_renders() {
+        let template = Template::compile("{{#if admin}}Hi admin {{/if}}{{ name }}!").unwrap();
+        let mut data = HashMap::from([("name".to_owned(), "Amin".to_owned())]);
+        assert_eq!(template.render(&data), Ok("Amin!".to_owned()));
+
+        data.insert("admin".to_owned(), "yes".to_owned());
+        assert_eq!(template.render(&data), Ok("Hi admin Amin!".to_owned()));
+
+        assert_eq!(
+            template.render(&HashMap::new()),
+            Err("couldn't find data corresponding to key: name".to_owned())
+        );
+    }
+
+    #[test]
+    fn missing_keys() {
+        let template = Template::compile("{{ name }} {{ surname }}")
+            .unwrap()
+            .missing_keys(MissingKeyPolicy::KeepPlaceholder);
+        let data = HashMap::from([("name".to_owned(), "Amin".to_owned())]);
+        assert_eq!(template.render(&data), Ok("Amin {{ surname }}".to_owned()));
+    }
+
+    #[test]
+    fn compile_errors() {
+        assert_eq!(
+            Template::compile("Hello {{ name"),
+            Err("missing closing delimiter: }}".to_owned())
+        );
+        assert_eq!(
+            Template::compile_with_limits("{{ a }}{{ b }}", Limits::new().max_tokens(2)),
+            Err("template has more than 2 tokens".to_owned())
+        );
+    }
+
+    #[test]
+    fn front_matter() {
+        let tmpl = "---\ndefaults:\n  greeting: Hello\n  name: you\nrequired: [email]\n---\n\
+                    {{ greeting }}, {{ name }} <{{ email }}>";
+        let template = Template::compile_with_front_matter(tmpl).unwrap();
+        assert_eq!(template.required(), ["email"]);
+        assert_eq!(template.defaults().len(), 2);
+
+        let mut data = HashMap::from([("email".to_owned(), "a@b.c".to_owned())]);
+        assert_eq!(template.render(&data), Ok("Hello, you <a@b.c>".to_owned()));
+        data.insert("name".to_owned(), "Amin".to_owned());
+        assert_eq!(template.render(&data), Ok("Hello, Amin <a@b.c>".to_owned()));
+
+        data.remove("email");
+        assert_eq!(
+            template.render(&data),
+            Err("missing required key: email".to_owned())
+        );
+        assert_eq!(
+            Template::compile_with_front_matter("---\ndefaults:\n  a\n---\n"),
+            Err("front matter, line 3: expected a default: a".to_owned())
+        );
+    }
+
+    #[test]
+    fn front_matter_is_opt_in() {
+        let tmpl = "---\nrequired: [name]\n---\nHi";
+        let template = Template::compile(tmpl).unwrap();
+        assert!(template.required().is_empty());
+        assert_eq!(template.render(&HashMap::new()), Ok(tmpl.to_owned()));
+    }
+
+    #[test]
+    fn front_matter_defaults_in_blocks() {
+        let tmpl = "---\ndefaults:\n  admin: yes\n---\n{{#if admin}}Hi admin{{/if}}";
+        let template = Template::compile_with_front_matter(tmpl).unwrap();
+        assert_eq!(template.render(&HashMap::new()), Ok("Hi admin".to_owned()));
+
+        let data = HashMap::from([("admin".to_owned(), "false".to_owned())]);
+        assert_eq!(template.render(&data), Ok("".to_owned()));
+    }
+}
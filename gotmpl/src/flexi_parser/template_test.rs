@@ -0,0 +1,380 @@
+//! Assertions about a template, for the test suites of the crates that ship
+//! templates.
+//!
+//! ```ignore
+//! TemplateTest::new(include_str!("welcome.tmpl"))
+//!     .requires("name")
+//!     .forbids("sk_live_")
+//!     .renders([("name", "Amin")], "Welcome, Amin!")
+//!     .check()
+//!     .unwrap();
+//! ```
+//!
+//! All the assertions are checked, and every failure ends up in the report.
+//! The samples are rendered with `parse_values`, and a key counts as covered
+//! once any sample rendered it or used it as the condition of a block.
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::rc::Rc;
+
+use super::tokens::{Token, Tokens};
+use super::{parse_values, Value};
+
+#[derive(Debug, Clone, Default)]
+pub struct TemplateTest {
+    tmpl: String,
+    required: Vec<String>,
+    forbidden: Vec<String>,
+    samples: Vec<Sample>,
+    require_coverage: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Sample {
+    data: HashMap<String, String>,
+    expected: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Failure {
+    /// The template itself can't be tokenized.
+    InvalidTemplate {
+        error: String,
+    },
+    MissingPlaceholder {
+        key: String,
+    },
+    /// The raw string is in the template, or in the output of a sample if
+    /// `sample` is set.
+    Forbidden {
+        raw: String,
+        sample: Option<usize>,
+    },
+    RenderFailed {
+        sample: usize,
+        error: String,
+    },
+    UnexpectedOutput {
+        sample: usize,
+        expected: String,
+        actual: String,
+    },
+    /// Only with `require_coverage`.
+    Uncovered {
+        key: String,
+    },
+}
+
+/// The outcome of `TemplateTest::run`. Samples are numbered from 0, in the
+/// order they were added.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TestReport {
+    /// The keys the template refers to.
+    pub placeholders: BTreeSet<String>,
+    /// The keys no sample rendered.
+    pub uncovered: BTreeSet<String>,
+    pub failures: Vec<Failure>,
+}
+
+impl TemplateTest {
+    pub fn new(tmpl: impl Into<String>) -> Self {
+        Self {
+            tmpl: tmpl.into(),
+            ..Self::default()
+        }
+    }
+
+    /// The template has to refer to `key`, in a placeholder or in the
+    /// condition of a block.
+    pub fn requires(mut self, key: impl Into<String>) -> Self {
+        self.required.push(key.into());
+        self
+    }
+
+    /// Neither the template nor the output of any sample may contain `raw`.
+    pub fn forbids(mut self, raw: impl Into<String>) -> Self {
+        self.forbidden.push(raw.into());
+        self
+    }
+
+    /// Rendering `data` has to produce `expected`.
+    pub fn renders<K, V>(mut self, data: impl IntoIterator<Item = (K, V)>, expected: &str) -> Self
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.samples.push(Sample {
+            data: data
+                .into_iter()
+                .map(|(k, v)| (k.into(), v.into()))
+                .collect(),
+            expected: expected.to_owned(),
+        });
+        self
+    }
+
+    /// Every key of the template has to be rendered by at least one sample.
+    pub fn require_coverage(mut self) -> Self {
+        self.require_coverage = true;
+        self
+    }
+
+    pub fn run(&self) -> TestReport {
+        let mut report = TestReport::default();
+
+        for tkn in Tokens::from(self.tmpl.clone()).iter() {
+            match tkn {
+                // The element of an each block and its index aren't data.
+                Ok(Token::Placeholder("this" | "@index")) => (),
+                Ok(
+                    Token::Placeholder(key) | Token::If(key) | Token::Each(key) | Token::Table(key),
+                ) => {
+                    report.placeholders.insert(key.to_owned());
+                }
+                Ok(_) => (),
+                Err(e) => {
+                    let error = e.to_string();
+                    report.failures.push(Failure::InvalidTemplate { error });
+                    return report;
+                }
+            }
+        }
+
+        for key in &self.required {
+            if !report.placeholders.contains(key) {
+                let key = key.clone();
+                report.failures.push(Failure::MissingPlaceholder { key });
+            }
+        }
+        for raw in &self.forbidden {
+            if self.tmpl.contains(raw.as_str()) {
+                let raw = raw.clone();
+                report
+                    .failures
+                    .push(Failure::Forbidden { raw, sample: None });
+            }
+        }
+
+        let rendered = Rc::new(RefCell::new(BTreeSet::new()));
+        for (i, sample) in self.samples.iter().enumerate() {
+            let data = recording(&sample.data, &rendered);
+            let actual = match parse_values(self.tmpl.clone(), &data) {
+                Ok(actual) => actual,
+                Err(error) => {
+                    report
+                        .failures
+                        .push(Failure::RenderFailed { sample: i, error });
+                    continue;
+                }
+            };
+
+            for raw in &self.forbidden {
+                if actual.contains(raw.as_str()) {
+                    let raw = raw.clone();
+                    report.failures.push(Failure::Forbidden {
+                        raw,
+                        sample: Some(i),
+                    });
+                }
+            }
+            if actual != sample.expected {
+                report.failures.push(Failure::UnexpectedOutput {
+                    sample: i,
+                    expected: sample.expected.clone(),
+                    actual,
+                });
+            }
+        }
+
+        let rendered = rendered.borrow();
+        report.uncovered = report.placeholders.difference(&rendered).cloned().collect();
+        if self.require_coverage {
+            for key in &report.uncovered {
+                let key = key.clone();
+                report.failures.push(Failure::Uncovered { key });
+            }
+        }
+        report
+    }
+
+    /// Like `run`, but returns the report as an error if anything failed.
+    pub fn check(&self) -> Result<TestReport, TestReport> {
+        let report = self.run();
+        if report.is_ok() {
+            Ok(report)
+        } else {
+            Err(report)
+        }
+    }
+}
+
+impl TestReport {
+    pub fn is_ok(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+// Lazy values that note down the keys they're evaluated for.
+fn recording(
+    data: &HashMap<String, String>,
+    rendered: &Rc<RefCell<BTreeSet<String>>>,
+) -> HashMap<String, Value> {
+    data.iter()
+        .map(|(k, v)| {
+            let (key, val) = (k.clone(), v.clone());
+            let rendered = Rc::clone(rendered);
+            let value = Value::lazy(move || {
+                rendered.borrow_mut().insert(key.clone());
+                val.as_str().into()
+            });
+            (k.clone(), value)
+        })
+        .collect()
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::InvalidTemplate { error } => write!(f, "invalid template: {}", error),
+            Failure::MissingPlaceholder { key } => write!(f, "missing placeholder: {}", key),
+            Failure::Forbidden { raw, sample: None } => {
+                write!(f, "template contains forbidden string: {:?}", raw)
+            }
+            Failure::Forbidden {
+                raw,
+                sample: Some(i),
+            } => write!(
+                f,
+                "sample {}: output contains forbidden string: {:?}",
+                i, raw
+            ),
+            Failure::RenderFailed { sample, error } => {
+                write!(f, "sample {}: rendering failed: {}", sample, error)
+            }
+            Failure::UnexpectedOutput {
+                sample,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "sample {}: expected output {:?}, got {:?}",
+                sample, expected, actual
+            ),
+            Failure::Uncovered { key } => write!(f, "no sample renders: {}", key),
+        }
+    }
+}
+
+impl fmt::Display for TestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_ok() {
+            return write!(f, "template test passed");
+        }
+        write!(f, "template test failed:")?;
+        for failure in &self.failures {
+            write!(f, "\n  {}", failure)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for TestReport {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TMPL: &str = "Hi {{ name }}{{#if vip}}, have a {{ gift }}{{/if}}!";
+
+    #[test]
+    fn passing_test() {
+        let report = TemplateTest::new(TMPL)
+            .requires("name")
+            .forbids("secret")
+            .renders([("name", "Amin")], "Hi Amin!")
+            .renders(
+                [("name", "Sara"), ("vip", "yes"), ("gift", "cake")],
+                "Hi Sara, have a cake!",
+            )
+            .require_coverage()
+            .check()
+            .unwrap();
+
+        let keys = ["gift", "name", "vip"].map(str::to_owned);
+        assert_eq!(report.placeholders, BTreeSet::from(keys));
+        assert!(report.uncovered.is_empty());
+    }
+
+    #[test]
+    fn every_failure_is_reported() {
+        let report = TemplateTest::new(TMPL)
+            .requires("surname")
+            .forbids("cake")
+            .renders([("name", "Sara"), ("vip", "1"), ("gift", "cake")], "Hi!")
+            .renders([("vip", "1")], "")
+            .require_coverage()
+            .check()
+            .unwrap_err();
+
+        assert_eq!(
+            report.failures,
+            vec![
+                Failure::MissingPlaceholder {
+                    key: "surname".to_owned()
+                },
+                Failure::Forbidden {
+                    raw: "cake".to_owned(),
+                    sample: Some(0)
+                },
+                Failure::UnexpectedOutput {
+                    sample: 0,
+                    expected: "Hi!".to_owned(),
+                    actual: "Hi Sara, have a cake!".to_owned(),
+                },
+                Failure::RenderFailed {
+                    sample: 1,
+                    error: "couldn't find data corresponding to key: name".to_owned()
+                },
+            ]
+        );
+        // Every key was rendered by the first sample.
+        assert!(report.uncovered.is_empty());
+    }
+
+    #[test]
+    fn coverage() {
+        let test = TemplateTest::new(TMPL).renders([("name", "Amin")], "Hi Amin!");
+        let report = test.run();
+        assert!(report.is_ok());
+        // Missing keys don't count, even as conditions.
+        let keys = ["gift", "vip"].map(str::to_owned);
+        assert_eq!(report.uncovered, BTreeSet::from(keys.clone()));
+
+        let report = test.require_coverage().run();
+        let uncovered = keys.map(|key| Failure::Uncovered { key });
+        assert_eq!(report.failures, uncovered);
+    }
+
+    #[test]
+    fn forbidden_strings_in_the_template() {
+        let report = TemplateTest::new("token: sk_live_123")
+            .forbids("sk_live_")
+            .run();
+        assert_eq!(
+            report.to_string(),
+            "template test failed:\n  template contains forbidden string: \"sk_live_\""
+        );
+    }
+
+    #[test]
+    fn invalid_template() {
+        let report = TemplateTest::new("{{ name").requires("name").run();
+        assert_eq!(
+            report.failures,
+            vec![Failure::InvalidTemplate {
+                error: "missing closing delimiter: }}".to_owned()
+            }]
+        );
+    }
+}
@@ -1,37 +1,283 @@
 mod iter;
-use iter::Iter;
+pub(super) use iter::Iter;
 
 mod into_iter;
 use into_iter::IntoIter;
 
-use super::Result;
+use std::fmt;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Token<T> {
     Text(T),
     Placeholder(T),
+    /// `{{#if key}}`, holding the key.
+    If(T),
+    /// `{{else}}`
+    Else,
+    /// `{{/if}}`
+    EndIf,
+    /// `{{#each key}}`, holding the key.
+    Each(T),
+    /// `{{/each}}`
+    EndEach,
+    /// `{{#table key}}`, holding the key.
+    Table(T),
+    /// `{{/table}}`
+    EndTable,
+}
+
+impl<'a> Token<&'a str> {
+    // Tells the tags of blocks apart from plain placeholders.
+    fn placeholder(p: &'a str) -> Result<Self, TokenError> {
+        match p {
+            "else" => Ok(Token::Else),
+            "/if" => Ok(Token::EndIf),
+            "/each" => Ok(Token::EndEach),
+            "/table" => Ok(Token::EndTable),
+            "#if" => Err(TokenError::MissingConditionKey),
+            "#each" => Err(TokenError::MissingListKey),
+            "#table" => Err(TokenError::MissingTableKey),
+            _ => {
+                if let Some(key) = block_key(p, "#if") {
+                    Ok(Token::If(key))
+                } else if let Some(key) = block_key(p, "#each") {
+                    Ok(Token::Each(key))
+                } else if let Some(key) = block_key(p, "#table") {
+                    Ok(Token::Table(key))
+                } else {
+                    Ok(Token::Placeholder(p))
+                }
+            }
+        }
+    }
+
+    fn to_owned(&self) -> Token<String> {
+        match *self {
+            Token::Text(t) => Token::Text(t.to_owned()),
+            Token::Placeholder(p) => Token::Placeholder(p.to_owned()),
+            Token::If(k) => Token::If(k.to_owned()),
+            Token::Else => Token::Else,
+            Token::EndIf => Token::EndIf,
+            Token::Each(k) => Token::Each(k.to_owned()),
+            Token::EndEach => Token::EndEach,
+            Token::Table(k) => Token::Table(k.to_owned()),
+            Token::EndTable => Token::EndTable,
+        }
+    }
+}
+
+impl Token<String> {
+    // The token borrowing its strings, as the tokens of `Tokens::iter` do.
+    pub(super) fn as_deref(&self) -> Token<&str> {
+        match self {
+            Token::Text(t) => Token::Text(t),
+            Token::Placeholder(p) => Token::Placeholder(p),
+            Token::If(k) => Token::If(k),
+            Token::Else => Token::Else,
+            Token::EndIf => Token::EndIf,
+            Token::Each(k) => Token::Each(k),
+            Token::EndEach => Token::EndEach,
+            Token::Table(k) => Token::Table(k),
+            Token::EndTable => Token::EndTable,
+        }
+    }
+}
+
+// What ends a text token.
+pub(super) enum TextEnd {
+    // The text runs to the end of the template.
+    Rest,
+    // The text ends at `end`, and a placeholder starts at `at`.
+    Placeholder { end: usize, at: usize },
+    // The text ends at `end`, at the backslash of an escaped `{{`.
+    Escape { end: usize },
+}
+
+// Finds the end of the text that starts at `cur`. `{{` is only looked for
+// from `from` on, as the text may start with an escaped one.
+//
+// `\\{{` opens a placeholder that follows a single backslash, `\{{` outputs
+// `{{`.
+pub(super) fn text_end(tmpl: &str, cur: usize, from: usize) -> TextEnd {
+    let Some(idx) = tmpl[from..].find("{{") else {
+        return TextEnd::Rest;
+    };
+    let idx = from + idx;
+    let text = &tmpl[cur..idx];
+    if text.ends_with("\\\\") {
+        TextEnd::Placeholder {
+            end: idx - 1,
+            at: idx,
+        }
+    } else if text.ends_with('\\') {
+        TextEnd::Escape { end: idx - 1 }
+    } else {
+        TextEnd::Placeholder { end: idx, at: idx }
+    }
+}
+
+// `{{! ... }}` is a comment, which renders as nothing and isn't returned by
+// the iterators. It ends at the first `}}`.
+fn is_comment(placeholder: &str) -> bool {
+    placeholder.trim_start().starts_with('!')
+}
+
+// `{{- ` trims the whitespace (newlines included) before a tag, and ` -}}`
+// the whitespace after it. The markers need whitespace next to them, so that
+// `{{-1}}` is still a plain placeholder.
+fn trims_before(placeholder: &str) -> bool {
+    placeholder
+        .strip_prefix('-')
+        .is_some_and(|rest| rest.starts_with(char::is_whitespace))
+}
+
+fn trims_after(placeholder: &str) -> bool {
+    placeholder
+        .strip_suffix('-')
+        .is_some_and(|rest| rest.ends_with(char::is_whitespace))
+}
+
+// The placeholder without its trim markers.
+fn strip_markers(mut placeholder: &str) -> &str {
+    if trims_before(placeholder) {
+        placeholder = &placeholder[1..];
+    }
+    if trims_after(placeholder) {
+        placeholder = &placeholder[..placeholder.len() - 1];
+    }
+    placeholder
+}
+
+fn trimmed(text: &str, start: bool, end: bool) -> &str {
+    let text = if start { text.trim_start() } else { text };
+    if end {
+        text.trim_end()
+    } else {
+        text
+    }
+}
+
+// The key of a `{{tag key}}` placeholder.
+pub(super) fn block_key<'a>(p: &'a str, tag: &str) -> Option<&'a str> {
+    let key = p.strip_prefix(tag)?;
+    key.starts_with(char::is_whitespace)
+        .then(|| key.trim_start())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenError {
+    MissingClosingDelimiter,
+    TooManyTokens { limit: usize },
+    TooDeep { limit: usize },
+    MissingConditionKey,
+    MissingListKey,
+    MissingTableKey,
+}
+
+impl fmt::Display for TokenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenError::MissingClosingDelimiter => write!(f, "missing closing delimiter: }}}}"),
+            TokenError::TooManyTokens { limit } => {
+                write!(f, "template has more than {} tokens", limit)
+            }
+            TokenError::TooDeep { limit } => {
+                write!(f, "template is nested deeper than {} levels", limit)
+            }
+            TokenError::MissingConditionKey => write!(f, "missing key after {{{{#if"),
+            TokenError::MissingListKey => write!(f, "missing key after {{{{#each"),
+            TokenError::MissingTableKey => write!(f, "missing key after {{{{#table"),
+        }
+    }
+}
+
+impl std::error::Error for TokenError {}
+
+// The parsers report errors as Strings.
+impl From<TokenError> for String {
+    fn from(e: TokenError) -> Self {
+        e.to_string()
+    }
+}
+
+/// Bounds the work done to tokenize a template, for templates that come from
+/// untrusted sources. Nothing is limited by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    max_tokens: usize,
+    max_depth: usize,
+}
+
+impl Limits {
+    pub fn new() -> Self {
+        Limits {
+            max_tokens: usize::MAX,
+            max_depth: usize::MAX,
+        }
+    }
+
+    /// The number of tokens (text and placeholders) after which tokenizing
+    /// fails with `TokenError::TooManyTokens`.
+    pub fn max_tokens(mut self, max: usize) -> Self {
+        self.max_tokens = max;
+        self
+    }
+
+    /// How deep `{{` may be nested before tokenizing fails with
+    /// `TokenError::TooDeep`. A plain placeholder has a depth of 1, and every
+    /// `{{` inside it adds another level.
+    pub fn max_depth(mut self, max: usize) -> Self {
+        self.max_depth = max;
+        self
+    }
+
+    fn check_depth(&self, placeholder: &str) -> Result<(), TokenError> {
+        // Counting stops as soon as the limit is exceeded.
+        let nested = placeholder.matches("{{").take(self.max_depth).count();
+        if nested >= self.max_depth {
+            return Err(TokenError::TooDeep {
+                limit: self.max_depth,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits::new()
+    }
 }
 
 pub struct Tokens {
     tmpl: String,
+    limits: Limits,
 }
 
 impl Tokens {
     pub fn from(tmpl: String) -> Self {
-        Tokens { tmpl }
+        Tokens {
+            tmpl,
+            limits: Limits::new(),
+        }
+    }
+
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
     }
 
     pub fn iter(&self) -> Iter {
-        Iter::new(&self.tmpl)
+        Iter::new(&self.tmpl, self.limits)
     }
 
     pub fn into_iter(&self) -> IntoIter {
-        IntoIter::new(self.tmpl.clone())
+        IntoIter::new(self.tmpl.clone(), self.limits)
     }
 }
 
 impl IntoIterator for Tokens {
-    type Item = Result<Token<String>>;
+    type Item = Result<Token<String>, TokenError>;
     type IntoIter = IntoIter;
 
     fn into_iter(self) -> Self::IntoIter {
@@ -40,7 +286,7 @@ impl IntoIterator for Tokens {
 }
 
 impl<'a> IntoIterator for &'a Tokens {
-    type Item = Result<Token<&'a str>>;
+    type Item = Result<Token<&'a str>, TokenError>;
     type IntoIter = Iter<'a>;
 
     fn into_iter(self) -> Self::IntoIter {
@@ -51,6 +297,7 @@ impl<'a> IntoIterator for &'a Tokens {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     #[test]
     fn iter() {
@@ -97,4 +344,253 @@ mod tests {
 
         assert_eq!(expected, actual);
     }
+
+    #[test]
+    fn limits_apply_to_both_iterators() {
+        let tokens = Tokens::from("{{ a }}{{ b }}{{ c }}".to_owned())
+            .with_limits(Limits::new().max_tokens(2));
+
+        let expected = TokenError::TooManyTokens { limit: 2 };
+        assert_eq!(tokens.iter().last(), Some(Err(expected.clone())));
+        assert_eq!(tokens.into_iter().last(), Some(Err(expected)));
+    }
+
+    #[test]
+    fn check_depth() {
+        let limits = Limits::new().max_depth(2);
+
+        assert_eq!(limits.check_depth(" name "), Ok(()));
+        assert_eq!(limits.check_depth(" {{ name "), Ok(()));
+        assert_eq!(
+            limits.check_depth(" {{ {{ name "),
+            Err(TokenError::TooDeep { limit: 2 })
+        );
+        assert_eq!(
+            Limits::new().max_depth(0).check_depth("name"),
+            Err(TokenError::TooDeep { limit: 0 })
+        );
+    }
+
+    #[test]
+    fn conditional_tags() {
+        let tokens =
+            Tokens::from("{{#if a }}x{{ else }}{{ elsewhere }}{{/if}}{{#iffy}}".to_owned());
+
+        let expected = vec![
+            Token::Text(""),
+            Token::If("a"),
+            Token::Text("x"),
+            Token::Else,
+            Token::Text(""),
+            Token::Placeholder("elsewhere"),
+            Token::Text(""),
+            Token::EndIf,
+            Token::Text(""),
+            Token::Placeholder("#iffy"),
+        ];
+        let actual: Vec<_> = tokens.iter().map(Result::unwrap).collect();
+        assert_eq!(expected, actual);
+
+        let owned: Vec<_> = tokens.into_iter().map(Result::unwrap).collect();
+        assert_eq!(
+            owned,
+            expected.iter().map(Token::to_owned).collect::<Vec<_>>()
+        );
+
+        let tokens = Tokens::from("{{ #if }}".to_owned());
+        assert_eq!(
+            tokens.iter().last(),
+            Some(Err(TokenError::MissingConditionKey))
+        );
+    }
+
+    #[test]
+    fn each_tags() {
+        let tokens = Tokens::from("{{#each items}}{{ this }}{{/each}}{{#eachother}}".to_owned());
+
+        let expected = vec![
+            Token::Text(""),
+            Token::Each("items"),
+            Token::Text(""),
+            Token::Placeholder("this"),
+            Token::Text(""),
+            Token::EndEach,
+            Token::Text(""),
+            Token::Placeholder("#eachother"),
+        ];
+        let actual: Vec<_> = tokens.iter().map(Result::unwrap).collect();
+        assert_eq!(expected, actual);
+
+        let tokens = Tokens::from("{{#each}}".to_owned());
+        assert_eq!(tokens.iter().last(), Some(Err(TokenError::MissingListKey)));
+    }
+
+    #[test]
+    fn table_tags() {
+        let tokens = Tokens::from("{{#table rows}}{{ >this.n }}{{/table}}{{#tables}}".to_owned());
+
+        let expected = vec![
+            Token::Text(""),
+            Token::Table("rows"),
+            Token::Text(""),
+            Token::Placeholder(">this.n"),
+            Token::Text(""),
+            Token::EndTable,
+            Token::Text(""),
+            Token::Placeholder("#tables"),
+        ];
+        let actual: Vec<_> = tokens.iter().map(Result::unwrap).collect();
+        assert_eq!(expected, actual);
+
+        let tokens = Tokens::from("{{ #table }}".to_owned());
+        assert_eq!(tokens.iter().last(), Some(Err(TokenError::MissingTableKey)));
+    }
+
+    #[test]
+    fn escaped_delimiters() {
+        let tokens = Tokens::from(r"a \{{ b }} \\{{ c }}\{{\{{d}}".to_owned());
+
+        let expected = vec![
+            Token::Text("a "),
+            Token::Text("{{ b }} \\"),
+            Token::Placeholder("c"),
+            Token::Text(""),
+            Token::Text("{{"),
+            Token::Text("{{d}}"),
+        ];
+        let actual: Vec<_> = tokens.iter().map(Result::unwrap).collect();
+        assert_eq!(expected, actual);
+
+        let owned: Vec<_> = tokens.into_iter().map(Result::unwrap).collect();
+        assert_eq!(
+            owned,
+            expected.iter().map(Token::to_owned).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn comments_are_dropped() {
+        let tokens = Tokens::from("a {{! note {{ name }} b{{!}}{{ c }}".to_owned());
+
+        let expected = vec![
+            Token::Text("a "),
+            Token::Text(" b"),
+            Token::Text(""),
+            Token::Placeholder("c"),
+        ];
+        let actual: Vec<_> = tokens.iter().map(Result::unwrap).collect();
+        assert_eq!(expected, actual);
+
+        let owned: Vec<_> = tokens.into_iter().map(Result::unwrap).collect();
+        assert_eq!(
+            owned,
+            expected.iter().map(Token::to_owned).collect::<Vec<_>>()
+        );
+
+        // Escaped, it's text.
+        let tokens = Tokens::from(r"\{{! note }}".to_owned());
+        let actual: Vec<_> = tokens.iter().map(Result::unwrap).collect();
+        assert_eq!(actual, vec![Token::Text(""), Token::Text("{{! note }}")]);
+    }
+
+    #[test]
+    fn trim_markers() {
+        let tmpl = "<ul>\n  {{- #each items -}}\n  <li>{{ this }}</li>\n  {{- /each }}\n</ul> {{-1}} {{- ! x -}} .";
+        let tokens = Tokens::from(tmpl.to_owned());
+
+        let expected = vec![
+            Token::Text("<ul>"),
+            Token::Each("items"),
+            Token::Text("<li>"),
+            Token::Placeholder("this"),
+            Token::Text("</li>"),
+            Token::EndEach,
+            Token::Text("\n</ul> "),
+            Token::Placeholder("-1"),
+            Token::Text(""),
+            Token::Text("."),
+        ];
+        let actual: Vec<_> = tokens.iter().map(Result::unwrap).collect();
+        assert_eq!(expected, actual);
+
+        let owned: Vec<_> = tokens.into_iter().map(Result::unwrap).collect();
+        assert_eq!(
+            owned,
+            expected.iter().map(Token::to_owned).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn error_message() {
+        let e: String = TokenError::MissingClosingDelimiter.into();
+        assert_eq!(e, "missing closing delimiter: }}");
+    }
+
+    // Templates made mostly of the characters the tokenizer looks at, and
+    // multi-byte ones to slice next to.
+    fn delimiter_heavy() -> impl Strategy<Value = String> {
+        "([{}\\\\!#/|\" -]|é|😀|\n|if|each|else|a){0,48}"
+    }
+
+    // Checks the tokens of tmpl and their spans, which have to cover it.
+    fn check_tokens(tmpl: &str) {
+        let tokens: Vec<_> = Iter::new(tmpl, Limits::new()).spanned().collect();
+        let owned: Vec<_> = IntoIter::new(tmpl.to_owned(), Limits::new())
+            .spanned()
+            .collect();
+        assert_eq!(owned.len(), tokens.len());
+
+        let mut end = 0;
+        for (tkn, owned) in tokens.iter().zip(&owned) {
+            let ((tkn, span), (owned, owned_span)) = match (tkn, owned) {
+                (Ok(tkn), Ok(owned)) => (tkn, owned),
+                (Err(e), Err(owned)) => {
+                    assert_eq!(e, owned);
+                    return;
+                }
+                _ => panic!("iterators disagree: {:?} {:?}", tkn, owned),
+            };
+            assert_eq!(&tkn.to_owned(), owned);
+            assert_eq!(span, owned_span);
+
+            assert!(skipped(&tmpl[end..span.start]), "gap before {:?}", tkn);
+            end = span.end;
+            let source = &tmpl[span.clone()];
+            match tkn {
+                Token::Text(t) => assert!(source.contains(t), "{:?} in {:?}", t, source),
+                _ => assert!(source.starts_with("{{") && source.ends_with("}}")),
+            }
+        }
+        assert!(skipped(&tmpl[end..]), "rest {:?}", &tmpl[end..]);
+    }
+
+    // Whether gap is only made of what the spans leave out: comments and the
+    // backslashes escaping the placeholders after them.
+    fn skipped(mut gap: &str) -> bool {
+        loop {
+            gap = gap.trim_start_matches('\\');
+            if gap.is_empty() {
+                return true;
+            }
+            let Some(end) = gap.strip_prefix("{{").and_then(|g| g.find("}}")) else {
+                return false;
+            };
+            if !is_comment(strip_markers(&gap[2..end + 2])) {
+                return false;
+            }
+            gap = &gap[end + 4..];
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn tokenizing_arbitrary_strings(tmpl in any::<String>()) {
+            check_tokens(&tmpl);
+        }
+
+        #[test]
+        fn tokenizing_delimiter_heavy_strings(tmpl in delimiter_heavy()) {
+            check_tokens(&tmpl);
+        }
+    }
 }
@@ -1,50 +1,123 @@
-use super::Token;
+use std::iter::FusedIterator;
+use std::ops::Range;
+
+use super::{
+    is_comment, strip_markers, text_end, trimmed, trims_after, trims_before, Limits, TextEnd,
+    Token, TokenError,
+};
 
 pub struct IntoIter {
     cur_idx: usize,
-    next: Option<Result<Token<String>, String>>,
+    next: Option<Result<Token<String>, TokenError>>,
     tmpl: String,
+    limits: Limits,
+    // Where the escaped `{{` that the current text starts with ends, the
+    // search for the next placeholder starts there.
+    escaped_end: usize,
+    // Tokens returned so far.
+    count: usize,
+    // Set once None or an error was returned, nothing is returned after that.
+    done: bool,
+    // Set after a tag that ends with ` -}}`, the next text starts after the
+    // whitespace that follows it.
+    trim_next: bool,
+    // The part of the template the last token returned was read from, and
+    // the one of the placeholder in `next`.
+    span: Range<usize>,
+    next_span: Range<usize>,
 }
 
 impl IntoIter {
-    pub fn new(tmpl: String) -> IntoIter {
+    pub fn new(tmpl: String, limits: Limits) -> IntoIter {
         IntoIter {
             cur_idx: 0,
             next: None,
             tmpl,
+            limits,
+            escaped_end: 0,
+            count: 0,
+            done: false,
+            trim_next: false,
+            span: 0..0,
+            next_span: 0..0,
         }
     }
 
-    fn set_next_placeholder(&mut self, at: usize) -> Result<(), String> {
+    /// The byte range of the template the last token returned was read from.
+    /// A placeholder's includes its delimiters, and a text's includes the
+    /// whitespace trimmed off it and the backslash of the escaped `{{` it
+    /// may end at.
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+
+    /// Returns the tokens along with their spans, see `span`.
+    pub fn spanned(
+        mut self,
+    ) -> impl Iterator<Item = Result<(Token<String>, Range<usize>), TokenError>> {
+        std::iter::from_fn(move || {
+            let tkn = self.next()?;
+            Some(tkn.map(|tkn| (tkn, self.span())))
+        })
+    }
+
+    fn set_next_placeholder(&mut self, at: usize) -> Result<(), TokenError> {
         let tmpl = &self.tmpl[at..];
 
         let delim_end = match tmpl.find("}}") {
             None => {
                 // There is a problem with template, therefore should stop iterating.
                 self.stop_iter();
-                return Err("missing closing delimiter: }}".to_owned());
+                return Err(TokenError::MissingClosingDelimiter);
             }
             Some(idx) => idx,
         };
 
-        self.next = Some(Ok(Token::Placeholder(tmpl[2..delim_end].trim().to_owned())));
+        let placeholder = &tmpl[2..delim_end];
+        self.trim_next = trims_after(placeholder);
+        let placeholder = strip_markers(placeholder);
+        if is_comment(placeholder) {
+            // Dropped, the text after it is the next token.
+            self.cur_idx = at + delim_end + 2;
+            return Ok(());
+        }
+        if let Err(e) = self.limits.check_depth(placeholder) {
+            self.stop_iter();
+            return Err(e);
+        }
+
+        let token = match Token::placeholder(placeholder.trim()) {
+            Ok(token) => token.to_owned(),
+            Err(e) => {
+                self.stop_iter();
+                return Err(e);
+            }
+        };
+
+        self.next = Some(Ok(token));
+        self.next_span = at..at + delim_end + 2;
         // Setting current to index after the second closing '}'.
         self.cur_idx = at + delim_end + 2;
         Ok(())
     }
 
+    // The text between start and end, trimmed as the tags around it say.
+    fn text(&mut self, start: usize, end: usize, trim_end: bool) -> String {
+        let trim_start = std::mem::take(&mut self.trim_next);
+        trimmed(&self.tmpl[start..end], trim_start, trim_end).to_owned()
+    }
+
     fn stop_iter(&mut self) {
         // Setting current index to the end of template, so that
         // there is nothing left to iterate through.
         self.cur_idx = self.tmpl.len();
     }
-}
-
-impl Iterator for IntoIter {
-    type Item = Result<Token<String>, String>;
 
-    fn next(&mut self) -> Option<Self::Item> {
+    // Returns the next token, without checking the limits on the number of
+    // tokens or whether the iterator is done.
+    fn advance(&mut self) -> Option<Result<Token<String>, TokenError>> {
         if self.next.is_some() {
+            self.span = self.next_span.clone();
             return self.next.take();
         }
 
@@ -52,9 +125,12 @@ impl Iterator for IntoIter {
             return None;
         }
 
-        match self.tmpl[self.cur_idx..].find("{{") {
-            None => {
-                let next = Ok(Token::Text(self.tmpl[self.cur_idx..].to_owned()));
+        let from = self.cur_idx.max(self.escaped_end);
+        match text_end(&self.tmpl, self.cur_idx, from) {
+            TextEnd::Rest => {
+                self.span = self.cur_idx..self.tmpl.len();
+                let text = self.text(self.cur_idx, self.tmpl.len(), false);
+                let next = Ok(Token::Text(text));
 
                 // No more to iterate through after this. Calling stop_iter
                 // has a side-effect of setting cur_idx to a new value, thus
@@ -64,15 +140,24 @@ impl Iterator for IntoIter {
 
                 Some(next)
             }
-            Some(mut idx) => {
-                // idx is relative to cur_idx because we used find
-                // on tmpl[cur_idx..] earlier.
-                idx = idx + self.cur_idx;
-                let cur = Token::Text(self.tmpl[self.cur_idx..idx].to_owned());
+            TextEnd::Escape { end } => {
+                self.span = self.cur_idx..end + 1;
+                // Leaving out the backslash, the next text starts with the
+                // `{{`.
+                let cur = Token::Text(self.text(self.cur_idx, end, false));
+                self.cur_idx = end + 1;
+                self.escaped_end = end + 3;
+                Some(Ok(cur))
+            }
+            TextEnd::Placeholder { end, at } => {
+                let span = self.cur_idx..end;
+                let trim = trims_before(&self.tmpl[at + 2..]);
+                let cur = Token::Text(self.text(self.cur_idx, end, trim));
 
-                if let Err(e) = self.set_next_placeholder(idx) {
+                if let Err(e) = self.set_next_placeholder(at) {
                     return Some(Err(e));
                 }
+                self.span = span;
 
                 Some(Ok(cur))
             }
@@ -80,16 +165,69 @@ impl Iterator for IntoIter {
     }
 }
 
+impl Iterator for IntoIter {
+    type Item = Result<Token<String>, TokenError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let next = self.advance();
+        match next {
+            Some(Ok(_)) if self.count == self.limits.max_tokens => {
+                self.done = true;
+                Some(Err(TokenError::TooManyTokens {
+                    limit: self.limits.max_tokens,
+                }))
+            }
+            Some(Ok(_)) => {
+                self.count += 1;
+                next
+            }
+            None | Some(Err(_)) => {
+                self.done = true;
+                next
+            }
+        }
+    }
+}
+
+impl FusedIterator for IntoIter {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn spans() {
+        let tmpl = "Hi {{- name }}\\{{ a }} {{! c }}{{#if x -}}\n!{{/if}}".to_owned();
+        let spanned: Vec<_> = IntoIter::new(tmpl.clone(), Limits::new())
+            .spanned()
+            .map(|tkn| tkn.map(|(_, span)| &tmpl[span]))
+            .collect();
+
+        assert_eq!(
+            spanned,
+            [
+                Ok("Hi "),
+                Ok("{{- name }}"),
+                Ok("\\"),
+                Ok("{{ a }} "),
+                Ok(""),
+                Ok("{{#if x -}}"),
+                Ok("\n!"),
+                Ok("{{/if}}"),
+            ]
+        );
+    }
+
     #[test]
     fn should_iterate_correctly() {
         let tmpl = String::from("Hello {{ name }} {{surname}}, Welcome!");
 
-        let tokens = IntoIter::new(tmpl);
-        let actual: Vec<Result<Token<String>, String>> = tokens.collect();
+        let tokens = IntoIter::new(tmpl, Limits::new());
+        let actual: Vec<Result<Token<String>, TokenError>> = tokens.collect();
 
         let expected = vec![
             Ok(Token::Text("Hello ".to_owned())),
@@ -106,7 +244,7 @@ mod tests {
     fn error_when_no_closing_delim() {
         let tmpl = String::from("Hello {{ name }} {{ surnamne  Welcome!");
 
-        let mut tokens = IntoIter::new(tmpl);
+        let mut tokens = IntoIter::new(tmpl, Limits::new());
 
         assert_eq!(tokens.next(), Some(Ok(Token::Text("Hello ".to_owned()))));
         assert_eq!(
@@ -115,8 +253,32 @@ mod tests {
         );
         assert_eq!(
             tokens.next(),
-            Some(Err("missing closing delimiter: }}".to_owned()))
+            Some(Err(TokenError::MissingClosingDelimiter))
         );
         assert_eq!(tokens.next(), None);
     }
+
+    #[test]
+    fn fused_after_error() {
+        let tmpl = String::from("{{ a }}{{ b }}");
+        let mut tokens = IntoIter::new(tmpl, Limits::new().max_depth(0));
+
+        assert_eq!(tokens.next(), Some(Err(TokenError::TooDeep { limit: 0 })));
+        for _ in 0..3 {
+            assert_eq!(tokens.next(), None);
+        }
+    }
+
+    #[test]
+    fn too_many_tokens() {
+        let tmpl = "{{ a }}".repeat(1_000_000);
+        let tokens = IntoIter::new(tmpl, Limits::new().max_tokens(10));
+
+        let actual: Vec<_> = tokens.collect();
+        assert_eq!(actual.len(), 11);
+        assert_eq!(
+            actual.last(),
+            Some(&Err(TokenError::TooManyTokens { limit: 10 }))
+        );
+    }
 }
@@ -1,50 +1,123 @@
-use super::Token;
+use std::iter::FusedIterator;
+use std::ops::Range;
+
+use super::{
+    is_comment, strip_markers, text_end, trimmed, trims_after, trims_before, Limits, TextEnd,
+    Token, TokenError,
+};
 
 pub struct Iter<'a> {
     cur_idx: usize,
-    next: Option<Result<Token<&'a str>, String>>,
+    next: Option<Result<Token<&'a str>, TokenError>>,
     tmpl: &'a str,
+    limits: Limits,
+    // Where the escaped `{{` that the current text starts with ends, the
+    // search for the next placeholder starts there.
+    escaped_end: usize,
+    // Tokens returned so far.
+    count: usize,
+    // Set once None or an error was returned, nothing is returned after that.
+    done: bool,
+    // Set after a tag that ends with ` -}}`, the next text starts after the
+    // whitespace that follows it.
+    trim_next: bool,
+    // The part of the template the last token returned was read from, and
+    // the one of the placeholder in `next`.
+    span: Range<usize>,
+    next_span: Range<usize>,
 }
 
 impl<'a> Iter<'a> {
-    pub fn new(tmpl: &'a str) -> Iter<'a> {
+    pub fn new(tmpl: &'a str, limits: Limits) -> Iter<'a> {
         Iter {
             cur_idx: 0,
             next: None,
-            tmpl: tmpl,
+            tmpl,
+            limits,
+            escaped_end: 0,
+            count: 0,
+            done: false,
+            trim_next: false,
+            span: 0..0,
+            next_span: 0..0,
         }
     }
 
-    fn set_next_placeholder(&mut self, at: usize) -> Result<(), String> {
+    /// The byte range of the template the last token returned was read from.
+    /// A placeholder's includes its delimiters, and a text's includes the
+    /// whitespace trimmed off it and the backslash of the escaped `{{` it
+    /// may end at.
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+
+    /// Returns the tokens along with their spans, see `span`.
+    pub fn spanned(
+        mut self,
+    ) -> impl Iterator<Item = Result<(Token<&'a str>, Range<usize>), TokenError>> {
+        std::iter::from_fn(move || {
+            let tkn = self.next()?;
+            Some(tkn.map(|tkn| (tkn, self.span())))
+        })
+    }
+
+    fn set_next_placeholder(&mut self, at: usize) -> Result<(), TokenError> {
         let tmpl = &self.tmpl[at..];
 
         let delim_end = match tmpl.find("}}") {
             None => {
                 // There is a problem with template, therefore should stop iterating.
                 self.stop_iter();
-                return Err("missing closing delimiter: }}".to_owned());
+                return Err(TokenError::MissingClosingDelimiter);
             }
             Some(idx) => idx,
         };
 
-        self.next = Some(Ok(Token::Placeholder(tmpl[2..delim_end].trim())));
+        let placeholder = &tmpl[2..delim_end];
+        self.trim_next = trims_after(placeholder);
+        let placeholder = strip_markers(placeholder);
+        if is_comment(placeholder) {
+            // Dropped, the text after it is the next token.
+            self.cur_idx = at + delim_end + 2;
+            return Ok(());
+        }
+        if let Err(e) = self.limits.check_depth(placeholder) {
+            self.stop_iter();
+            return Err(e);
+        }
+
+        let token = match Token::placeholder(placeholder.trim()) {
+            Ok(token) => token,
+            Err(e) => {
+                self.stop_iter();
+                return Err(e);
+            }
+        };
+
+        self.next = Some(Ok(token));
+        self.next_span = at..at + delim_end + 2;
         // Setting current to index after the second closing '}'.
         self.cur_idx = at + delim_end + 2;
         Ok(())
     }
 
+    // The text between start and end, trimmed as the tags around it say.
+    fn text(&mut self, start: usize, end: usize, trim_end: bool) -> &'a str {
+        let trim_start = std::mem::take(&mut self.trim_next);
+        trimmed(&self.tmpl[start..end], trim_start, trim_end)
+    }
+
     fn stop_iter(&mut self) {
         // Setting current index to the end of template, so that
         // there is nothing left to iterate through.
         self.cur_idx = self.tmpl.len();
     }
-}
 
-impl<'a> Iterator for Iter<'a> {
-    type Item = Result<Token<&'a str>, String>;
-
-    fn next(&mut self) -> Option<Self::Item> {
+    // Returns the next token, without checking the limits on the number of
+    // tokens or whether the iterator is done.
+    fn advance(&mut self) -> Option<Result<Token<&'a str>, TokenError>> {
         if self.next.is_some() {
+            self.span = self.next_span.clone();
             return self.next.take();
         }
 
@@ -52,9 +125,11 @@ impl<'a> Iterator for Iter<'a> {
             return None;
         }
 
-        match self.tmpl[self.cur_idx..].find("{{") {
-            None => {
-                let next = Ok(Token::Text(&self.tmpl[self.cur_idx..]));
+        let from = self.cur_idx.max(self.escaped_end);
+        match text_end(self.tmpl, self.cur_idx, from) {
+            TextEnd::Rest => {
+                self.span = self.cur_idx..self.tmpl.len();
+                let next = Ok(Token::Text(self.text(self.cur_idx, self.tmpl.len(), false)));
 
                 // No more to iterate through after this. Calling stop_iter
                 // has a side-effect of setting cur_idx to a new value, thus
@@ -64,15 +139,24 @@ impl<'a> Iterator for Iter<'a> {
 
                 Some(next)
             }
-            Some(mut idx) => {
-                // idx is relative to cur_idx because we used find
-                // on tmpl[cur_idx..] earlier.
-                idx = idx + self.cur_idx;
-                let cur = Token::Text(&self.tmpl[self.cur_idx..idx]);
+            TextEnd::Escape { end } => {
+                self.span = self.cur_idx..end + 1;
+                // Leaving out the backslash, the next text starts with the
+                // `{{`.
+                let cur = Token::Text(self.text(self.cur_idx, end, false));
+                self.cur_idx = end + 1;
+                self.escaped_end = end + 3;
+                Some(Ok(cur))
+            }
+            TextEnd::Placeholder { end, at } => {
+                let span = self.cur_idx..end;
+                let trim = trims_before(&self.tmpl[at + 2..]);
+                let cur = Token::Text(self.text(self.cur_idx, end, trim));
 
-                if let Err(e) = self.set_next_placeholder(idx) {
+                if let Err(e) = self.set_next_placeholder(at) {
                     return Some(Err(e));
                 }
+                self.span = span;
 
                 Some(Ok(cur))
             }
@@ -80,16 +164,77 @@ impl<'a> Iterator for Iter<'a> {
     }
 }
 
+impl<'a> Iterator for Iter<'a> {
+    type Item = Result<Token<&'a str>, TokenError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let next = self.advance();
+        match next {
+            Some(Ok(_)) if self.count == self.limits.max_tokens => {
+                self.done = true;
+                Some(Err(TokenError::TooManyTokens {
+                    limit: self.limits.max_tokens,
+                }))
+            }
+            Some(Ok(_)) => {
+                self.count += 1;
+                next
+            }
+            None | Some(Err(_)) => {
+                self.done = true;
+                next
+            }
+        }
+    }
+}
+
+impl FusedIterator for Iter<'_> {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn spans() {
+        let tmpl = "Hi {{- name }}\\{{ a }} {{! c }}{{#if x -}}\n!{{/if}}";
+        let spanned: Vec<_> = Iter::new(tmpl, Limits::new()).spanned().collect();
+
+        assert_eq!(
+            spanned,
+            [
+                Ok((Token::Text("Hi"), 0..3)),
+                Ok((Token::Placeholder("name"), 3..14)),
+                Ok((Token::Text(""), 14..15)),
+                Ok((Token::Text("{{ a }} "), 15..23)),
+                Ok((Token::Text(""), 31..31)),
+                Ok((Token::If("x"), 31..42)),
+                Ok((Token::Text("!"), 42..44)),
+                Ok((Token::EndIf, 44..51)),
+            ]
+        );
+
+        // The span of the last token, also after an error.
+        let mut tokens = Iter::new("a {{ b }} {{ c", Limits::new());
+        tokens.next();
+        assert_eq!(tokens.next(), Some(Ok(Token::Placeholder("b"))));
+        assert_eq!(tokens.span(), 2..9);
+        assert_eq!(
+            tokens.next(),
+            Some(Err(TokenError::MissingClosingDelimiter))
+        );
+        assert_eq!(tokens.span(), 2..9);
+    }
+
     #[test]
     fn should_iterate_correctly() {
         let tmpl = String::from("Hello {{ name }} {{surname}}, Welcome!");
 
-        let tokens = Iter::new(&tmpl);
-        let actual: Vec<Result<Token<&str>, String>> = tokens.collect();
+        let tokens = Iter::new(&tmpl, Limits::new());
+        let actual: Vec<Result<Token<&str>, TokenError>> = tokens.collect();
 
         let expected = vec![
             Ok(Token::Text("Hello ")),
@@ -106,14 +251,89 @@ mod tests {
     fn error_when_no_closing_delim() {
         let tmpl = String::from("Hello {{ name }} {{ surnamne  Welcome!");
 
-        let mut tokens = Iter::new(&tmpl);
+        let mut tokens = Iter::new(&tmpl, Limits::new());
 
         assert_eq!(tokens.next(), Some(Ok(Token::Text("Hello "))));
         assert_eq!(tokens.next(), Some(Ok(Token::Placeholder("name"))));
         assert_eq!(
             tokens.next(),
-            Some(Err("missing closing delimiter: }}".to_owned()))
+            Some(Err(TokenError::MissingClosingDelimiter))
         );
         assert_eq!(tokens.next(), None);
     }
+
+    #[test]
+    fn fused_after_error() {
+        let tmpl = String::from("{{ a }} {{ b");
+        let mut tokens = Iter::new(&tmpl, Limits::new());
+
+        assert_eq!(tokens.next(), Some(Ok(Token::Text(""))));
+        assert_eq!(tokens.next(), Some(Ok(Token::Placeholder("a"))));
+        assert_eq!(
+            tokens.next(),
+            Some(Err(TokenError::MissingClosingDelimiter))
+        );
+        for _ in 0..3 {
+            assert_eq!(tokens.next(), None);
+        }
+    }
+
+    #[test]
+    fn fused_after_end() {
+        let tmpl = String::from("Hello");
+        let mut tokens = Iter::new(&tmpl, Limits::new());
+
+        assert_eq!(tokens.next(), Some(Ok(Token::Text("Hello"))));
+        for _ in 0..3 {
+            assert_eq!(tokens.next(), None);
+        }
+    }
+
+    #[test]
+    fn too_many_tokens() {
+        let tmpl = "{{ a }}".repeat(1_000_000);
+        let mut tokens = Iter::new(&tmpl, Limits::new().max_tokens(1000));
+
+        let ok = tokens.by_ref().take_while(Result::is_ok).count();
+        assert_eq!(ok, 1000);
+        assert_eq!(tokens.next(), None);
+
+        // The error is returned in place of the first token over the limit.
+        let last = Iter::new(&tmpl, Limits::new().max_tokens(1000)).last();
+        assert_eq!(last, Some(Err(TokenError::TooManyTokens { limit: 1000 })));
+    }
+
+    #[test]
+    fn exactly_max_tokens_is_fine() {
+        let tmpl = String::from("a {{ b }} c");
+        let tokens = Iter::new(&tmpl, Limits::new().max_tokens(3));
+
+        assert!(tokens.map(|t| t.unwrap()).eq([
+            Token::Text("a "),
+            Token::Placeholder("b"),
+            Token::Text(" c"),
+        ]));
+    }
+
+    #[test]
+    fn millions_of_opening_delimiters() {
+        // Without a closing delimiter, the whole template is a single broken
+        // placeholder.
+        let tmpl = "{{".repeat(1_000_000);
+        let actual: Vec<_> = Iter::new(&tmpl, Limits::new()).collect();
+        assert_eq!(actual, vec![Err(TokenError::MissingClosingDelimiter)]);
+
+        // With one, it's nested a million levels deep.
+        let tmpl = "{{".repeat(1_000_000) + "}}";
+        let actual: Vec<_> = Iter::new(&tmpl, Limits::new().max_depth(16)).collect();
+        assert_eq!(actual, vec![Err(TokenError::TooDeep { limit: 16 })]);
+    }
+
+    #[test]
+    fn nested_within_max_depth() {
+        let tmpl = String::from("{{ a {{ b }}");
+        let mut tokens = Iter::new(&tmpl, Limits::new().max_depth(2));
+
+        assert_eq!(tokens.nth(1), Some(Ok(Token::Placeholder("a {{ b"))));
+    }
 }
@@ -0,0 +1,120 @@
+//! Checks that a template doesn't refer to keys that are missing from its
+//! data, reporting all of them at once rather than the first one the render
+//! fails on.
+//!
+//! The check is strict: placeholders in branches that wouldn't be rendered
+//! with this data count as well, as they would be with other data. The keys
+//! of conditional blocks don't, a missing key is just falsy, and neither do
+//! placeholders with a default.
+use std::collections::HashMap;
+use std::fmt;
+
+use super::sandbox::line_and_column;
+use super::split_default;
+use super::tokens::{Iter, Limits, Token};
+
+/// A placeholder whose key is missing from the data, at the `{{` that opens
+/// it. Lines and columns start at 1, and columns count characters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingKey {
+    pub key: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for MissingKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: couldn't find data corresponding to key: {}",
+            self.line, self.column, self.key
+        )
+    }
+}
+
+impl std::error::Error for MissingKey {}
+
+/// Returns every placeholder of `tmpl` whose key is missing from `data`, in
+/// the order they appear. Errors of the template itself, like a missing
+/// closing delimiter, are left to the render: checking just stops there.
+pub fn validate(tmpl: &str, data: &HashMap<String, String>) -> Result<(), Vec<MissingKey>> {
+    let mut missing = Vec::new();
+
+    for tkn in Iter::new(tmpl, Limits::new()).spanned() {
+        let (placeholder, span) = match tkn {
+            Ok((Token::Placeholder(p), span)) => (p, span),
+            Ok(_) => continue,
+            Err(_) => break,
+        };
+        let Ok((key, None)) = split_default(placeholder) else {
+            continue;
+        };
+        if !data.contains_key(key) {
+            let (line, column) = line_and_column(tmpl, span.start);
+            missing.push(MissingKey {
+                key: key.to_owned(),
+                line,
+                column,
+            });
+        }
+    }
+
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(missing)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn missing(key: &str, line: usize, column: usize) -> MissingKey {
+        MissingKey {
+            key: key.to_owned(),
+            line,
+            column,
+        }
+    }
+
+    #[test]
+    fn reports_every_missing_key() {
+        let tmpl = "Hi {{ name }} {{ surname }},\n\
+                    {{#if admin}}you're {{ role }}{{else}}{{ name }}{{/if}}\n\
+                    {{ city | default:\"?\" }} – {{ country }}";
+        let data = HashMap::from([("name".to_owned(), "Amin".to_owned())]);
+
+        assert_eq!(
+            validate(tmpl, &data),
+            Err(vec![
+                missing("surname", 1, 15),
+                missing("role", 2, 21),
+                missing("country", 3, 28),
+            ])
+        );
+
+        let data = HashMap::from(
+            ["name", "surname", "role", "country"].map(|k| (k.to_owned(), String::new())),
+        );
+        assert_eq!(validate(tmpl, &data), Ok(()));
+    }
+
+    #[test]
+    fn stops_at_errors_of_the_template() {
+        let data = HashMap::new();
+        assert_eq!(
+            validate("{{ a }}{{ b", &data),
+            Err(vec![missing("a", 1, 1)])
+        );
+        assert_eq!(validate("{{ b", &data), Ok(()));
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(
+            missing("name", 2, 5).to_string(),
+            "line 2, column 5: couldn't find data corresponding to key: name"
+        );
+    }
+}
@@ -0,0 +1,428 @@
+//! Values of the data map for `parse_values`. Besides plain strings, a value
+//! can be a closure that's only called if its placeholder is actually
+//! rendered, which lets expensive lookups be wired into the data without
+//! paying for keys the template doesn't use.
+//!
+//! Evaluation is deterministic: placeholders are rendered strictly from left
+//! to right, so lazy values are evaluated in the order their keys first
+//! appear in the template, and none are evaluated after rendering failed.
+//! Each lazy value is evaluated at most once per render, no matter how often
+//! its key appears.
+//!
+//! Lists are rendered by `{{#each key}} ... {{/each}}` blocks, once per
+//! element. Within the block, `{{ this }}` is the element and `{{ @index }}`
+//! its index, starting at 0, and in nested blocks they refer to the innermost
+//! one. `{{#table key}} ... {{/table}}` renders them the same way, as rows
+//! of a table with aligned columns. Lists can't be lazy, but their elements
+//! can.
+//!
+//! Maps nest values under names, and placeholders reach into them with dotted
+//! paths: `{{ user.address.city }}`, or `{{ this.name }}` in an each block.
+//! A number in a path is an index into a list, as in `{{ users.0.name }}`.
+//! A key of the data that contains dots itself is found as well, it's looked
+//! up as is before the path is followed.
+//!
+//! Numbers, bools and null are rendered through `Display`, null as nothing.
+//! In conditional blocks, `false`, null and zero are falsy, as are empty
+//! strings, lists and maps and the string `"false"`.
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fmt;
+
+use super::blocks::is_truthy;
+use super::Result;
+
+pub enum Value {
+    Str(String),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Null,
+    /// Called when the placeholder is rendered. Returning another lazy value
+    /// is fine, it's evaluated right away as well.
+    Lazy(Box<dyn Fn() -> Value>),
+    List(Vec<Value>),
+    Map(HashMap<String, Value>),
+}
+
+impl Value {
+    pub fn lazy(f: impl Fn() -> Value + 'static) -> Self {
+        Value::Lazy(Box::new(f))
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::Str(s)
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(items: Vec<T>) -> Self {
+        Value::List(items.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<K: Into<String>, V: Into<Value>> From<HashMap<K, V>> for Value {
+    fn from(fields: HashMap<K, V>) -> Self {
+        Value::Map(
+            fields
+                .into_iter()
+                .map(|(k, v)| (k.into(), v.into()))
+                .collect(),
+        )
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::Str(s.to_owned())
+    }
+}
+
+impl From<i64> for Value {
+    fn from(i: i64) -> Self {
+        Value::Int(i)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(f: f64) -> Self {
+        Value::Float(f)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+/// `None` is null.
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Value::Null, Into::into)
+    }
+}
+
+/// Lazy values are evaluated for display. Lists and maps, which placeholders
+/// can't render, are displayed as their elements separated by commas, the
+/// fields of maps as `name: value` in the order of their names.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Str(s) => f.write_str(s),
+            Value::Int(i) => write!(f, "{}", i),
+            Value::Float(x) => write!(f, "{}", x),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Null => Ok(()),
+            Value::Lazy(lazy) => write!(f, "{}", lazy()),
+            Value::List(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                Ok(())
+            }
+            Value::Map(fields) => {
+                let mut fields: Vec<_> = fields.iter().collect();
+                fields.sort_by_key(|(name, _)| *name);
+                for (i, (name, value)) in fields.into_iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}: {}", name, value)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Str(s) => f.debug_tuple("Str").field(s).finish(),
+            Value::Int(i) => f.debug_tuple("Int").field(i).finish(),
+            Value::Float(x) => f.debug_tuple("Float").field(x).finish(),
+            Value::Bool(b) => f.debug_tuple("Bool").field(b).finish(),
+            Value::Null => f.write_str("Null"),
+            Value::Lazy(_) => f.write_str("Lazy(..)"),
+            Value::List(items) => f.debug_tuple("List").field(items).finish(),
+            Value::Map(fields) => f.debug_tuple("Map").field(fields).finish(),
+        }
+    }
+}
+
+/// Resolves placeholders for a single render, remembering what the lazy
+/// values evaluated to.
+pub(crate) struct Resolver<'a> {
+    data: &'a HashMap<String, Value>,
+    // By the address of the value, which may be an element of a list. Holds
+    // what lazy values evaluated to, and the scalars that aren't strings.
+    evaluated: HashMap<*const Value, String>,
+}
+
+impl<'a> Resolver<'a> {
+    pub(crate) fn new(data: &'a HashMap<String, Value>) -> Self {
+        Self {
+            data,
+            evaluated: HashMap::new(),
+        }
+    }
+
+    // parse_values looks up the values itself, as `this` isn't a key.
+    #[cfg(test)]
+    fn resolve(&mut self, key: &str) -> Result<&str> {
+        let value = self.value(key)?;
+        self.render(value)
+    }
+
+    /// Looks up `key`, which may be a dotted path.
+    pub(crate) fn value(&self, key: &str) -> Result<&'a Value> {
+        let found = match self.data.get(key) {
+            Some(value) => Some(value),
+            None => key
+                .split_once('.')
+                .and_then(|(first, rest)| field(self.data.get(first)?, rest)),
+        };
+        found.ok_or(format!("couldn't find data corresponding to key: {}", key))
+    }
+
+    pub(crate) fn render(&mut self, value: &'a Value) -> Result<&str> {
+        match value {
+            Value::Str(s) => Ok(s),
+            Value::Lazy(f) => match self.evaluated.entry(value as *const Value) {
+                Entry::Occupied(e) => Ok(e.into_mut()),
+                Entry::Vacant(e) => Ok(e.insert(force(f())?)),
+            },
+            Value::Int(_) | Value::Float(_) | Value::Bool(_) | Value::Null => Ok(self
+                .evaluated
+                .entry(value as *const Value)
+                .or_insert_with(|| value.to_string())),
+            Value::List(_) => Err("a list can only be rendered by {{#each}}".to_owned()),
+            Value::Map(_) => Err("a map can't be rendered, only its fields".to_owned()),
+        }
+    }
+
+    /// Whether `value` makes a conditional block render. A lazy value is
+    /// evaluated for that, just like when it's rendered.
+    pub(crate) fn is_truthy(&mut self, value: &'a Value) -> bool {
+        match value {
+            Value::Int(i) => *i != 0,
+            Value::Float(x) => *x != 0.0 && !x.is_nan(),
+            Value::Bool(b) => *b,
+            Value::Null => false,
+            Value::List(items) => !items.is_empty(),
+            Value::Map(fields) => !fields.is_empty(),
+            _ => is_truthy(self.render(value).ok()),
+        }
+    }
+}
+
+/// Follows the dotted `path` from `value`.
+pub(crate) fn field<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(value, |value, name| match value {
+        Value::Map(fields) => fields.get(name),
+        Value::List(items) => items.get(name.parse::<usize>().ok()?),
+        _ => None,
+    })
+}
+
+fn force(mut value: Value) -> Result<String> {
+    loop {
+        match value {
+            Value::Str(s) => return Ok(s),
+            Value::Lazy(f) => value = f(),
+            Value::Int(_) | Value::Float(_) | Value::Bool(_) | Value::Null => {
+                return Ok(value.to_string())
+            }
+            Value::List(_) | Value::Map(_) => {
+                return Err("lazy values can't be lists or maps".to_owned())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    // A lazy value that counts how often it's evaluated.
+    fn counted(s: &'static str) -> (Value, Rc<Cell<usize>>) {
+        let calls = Rc::new(Cell::new(0));
+        let counter = Rc::clone(&calls);
+        let value = Value::lazy(move || {
+            counter.set(counter.get() + 1);
+            s.into()
+        });
+        (value, calls)
+    }
+
+    #[test]
+    fn lazy_values_are_memoized() {
+        let (value, calls) = counted("Amin");
+        let data = HashMap::from([("name".to_owned(), value)]);
+
+        let mut resolver = Resolver::new(&data);
+        assert_eq!(resolver.resolve("name"), Ok("Amin"));
+        assert_eq!(resolver.resolve("name"), Ok("Amin"));
+        assert_eq!(calls.get(), 1);
+
+        // But only for one render.
+        let mut resolver = Resolver::new(&data);
+        assert_eq!(resolver.resolve("name"), Ok("Amin"));
+        assert_eq!(calls.get(), 2);
+    }
+
+    #[test]
+    fn nested_lazy_values_are_forced() {
+        let value = Value::lazy(|| Value::lazy(|| "deep".into()));
+        let data = HashMap::from([("key".to_owned(), value)]);
+
+        assert_eq!(Resolver::new(&data).resolve("key"), Ok("deep"));
+    }
+
+    #[test]
+    fn missing_key() {
+        let data = HashMap::new();
+        assert_eq!(
+            Resolver::new(&data).resolve("name"),
+            Err("couldn't find data corresponding to key: name".to_owned())
+        );
+    }
+
+    #[test]
+    fn lazy_list_elements_are_memoized_separately() {
+        let (a, a_calls) = counted("a");
+        let (b, b_calls) = counted("b");
+        let data = HashMap::from([("list".to_owned(), Value::List(vec![a, b]))]);
+
+        let mut resolver = Resolver::new(&data);
+        let Value::List(items) = resolver.value("list").unwrap() else {
+            unreachable!()
+        };
+        for _ in 0..2 {
+            assert_eq!(resolver.render(&items[0]), Ok("a"));
+            assert_eq!(resolver.render(&items[1]), Ok("b"));
+        }
+        assert_eq!((a_calls.get(), b_calls.get()), (1, 1));
+
+        assert!(resolver.resolve("list").is_err());
+        assert!(resolver.is_truthy(&data["list"]));
+    }
+
+    #[test]
+    fn lazy_values_cannot_be_lists() {
+        let data = HashMap::from([("key".to_owned(), Value::lazy(|| vec!["a"].into()))]);
+        assert_eq!(
+            Resolver::new(&data).resolve("key"),
+            Err("lazy values can't be lists or maps".to_owned())
+        );
+    }
+
+    #[test]
+    fn dotted_paths() {
+        let address = HashMap::from([("city", "Tehran")]);
+        let user = Value::Map(HashMap::from([
+            ("name".to_owned(), Value::from("Amin")),
+            ("address".to_owned(), Value::from(address)),
+            ("tags".to_owned(), Value::from(vec!["a", "b"])),
+        ]));
+        let data = HashMap::from([
+            ("user".to_owned(), user),
+            ("user.name".to_owned(), Value::from("flat")),
+        ]);
+
+        let mut resolver = Resolver::new(&data);
+        assert_eq!(resolver.resolve("user.address.city"), Ok("Tehran"));
+        assert_eq!(resolver.resolve("user.tags.1"), Ok("b"));
+        // Keys with dots win over paths.
+        assert_eq!(resolver.resolve("user.name"), Ok("flat"));
+
+        for missing in [
+            "user.age",
+            "user.tags.2",
+            "user.tags.x",
+            "user.address.city.x",
+        ] {
+            let err = format!("couldn't find data corresponding to key: {}", missing);
+            assert_eq!(resolver.resolve(missing), Err(err));
+        }
+        assert_eq!(
+            resolver.resolve("user.address"),
+            Err("a map can't be rendered, only its fields".to_owned())
+        );
+        assert!(resolver.is_truthy(&data["user"]));
+        assert!(!resolver.is_truthy(&Value::Map(HashMap::new())));
+    }
+
+    #[test]
+    fn scalars() {
+        let data = HashMap::from([
+            ("int".to_owned(), Value::from(-3)),
+            ("float".to_owned(), Value::from(1.5)),
+            ("bool".to_owned(), Value::from(true)),
+            ("null".to_owned(), Value::from(None::<i64>)),
+            ("lazy".to_owned(), Value::lazy(|| 0.into())),
+        ]);
+
+        let mut resolver = Resolver::new(&data);
+        let rendered: Vec<_> = ["int", "float", "bool", "null", "lazy"]
+            .into_iter()
+            .map(|key| resolver.resolve(key).unwrap().to_owned())
+            .collect();
+        assert_eq!(rendered, ["-3", "1.5", "true", "", "0"]);
+    }
+
+    #[test]
+    fn truthiness() {
+        let truthy = [
+            Value::from(1),
+            Value::from(-0.5),
+            Value::from(true),
+            Value::from("0"),
+            Value::from(vec![Value::Null]),
+        ];
+        let data = HashMap::new();
+        let mut resolver = Resolver::new(&data);
+        for value in &truthy {
+            assert!(resolver.is_truthy(value), "{:?}", value);
+        }
+        let falsy = [
+            Value::from(0),
+            Value::from(0.0),
+            Value::from(f64::NAN),
+            Value::from(false),
+            Value::Null,
+            Value::from(""),
+            Value::from("false"),
+            Value::List(vec![]),
+        ];
+        for value in &falsy {
+            assert!(!resolver.is_truthy(value), "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn display() {
+        let value = Value::from(HashMap::from([
+            (
+                "b",
+                Value::from(vec![Value::from(1), Value::Null, Value::from(false)]),
+            ),
+            ("a", Value::lazy(|| 2.5.into())),
+        ]));
+        assert_eq!(value.to_string(), "a: 2.5, b: 1, , false");
+    }
+
+    #[test]
+    fn debug_hides_closures() {
+        assert_eq!(format!("{:?}", Value::from("a")), "Str(\"a\")");
+        assert_eq!(format!("{:?}", Value::lazy(|| "a".into())), "Lazy(..)");
+    }
+}
@@ -1,3 +1,6 @@
 pub mod enum_parser;
 pub mod flexi_parser;
 pub mod simple_parser;
+
+mod missing_key;
+pub use missing_key::MissingKeyPolicy;
@@ -0,0 +1,48 @@
+//! What the parsers render for a placeholder whose key isn't in the data.
+use std::borrow::Cow;
+
+/// By default a missing key is an error: `simple_parser` and `enum_parser`
+/// panic, and `flexi_parser` returns an error. The other policies let a
+/// render degrade instead, e.g. in production where a half-filled template
+/// beats no output at all.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum MissingKeyPolicy {
+    #[default]
+    Error,
+    /// Renders nothing.
+    Empty,
+    /// Renders the placeholder, as `{{ key }}`, so the gap is visible.
+    KeepPlaceholder,
+    /// Renders the given text.
+    Default(String),
+}
+
+impl MissingKeyPolicy {
+    /// What a placeholder of the missing `key` renders as, or None for
+    /// `Error`.
+    pub(crate) fn render<'a>(&'a self, key: &str) -> Option<Cow<'a, str>> {
+        match self {
+            MissingKeyPolicy::Error => None,
+            MissingKeyPolicy::Empty => Some(Cow::Borrowed("")),
+            MissingKeyPolicy::KeepPlaceholder => Some(Cow::Owned(format!("{{{{ {} }}}}", key))),
+            MissingKeyPolicy::Default(text) => Some(Cow::Borrowed(text)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders() {
+        assert_eq!(MissingKeyPolicy::Error.render("name"), None);
+        assert_eq!(MissingKeyPolicy::Empty.render("name").as_deref(), Some(""));
+        assert_eq!(
+            MissingKeyPolicy::KeepPlaceholder.render("name").as_deref(),
+            Some("{{ name }}")
+        );
+        let policy = MissingKeyPolicy::Default("n/a".to_owned());
+        assert_eq!(policy.render("name").as_deref(), Some("n/a"));
+    }
+}
@@ -1,7 +1,18 @@
 use std::collections::HashMap;
 
+use crate::MissingKeyPolicy;
+
 pub fn parse(template: String, data: HashMap<String, String>) -> String {
-    let parser = Parser::new(template, data);
+    parse_with_policy(template, data, &MissingKeyPolicy::Error)
+}
+
+/// Like `parse`, but only panics on a missing key if `missing` says so.
+pub fn parse_with_policy(
+    template: String,
+    data: HashMap<String, String>,
+    missing: &MissingKeyPolicy,
+) -> String {
+    let parser = Parser::new(template, data, missing.clone());
     parser.parse()
 }
 
@@ -9,11 +20,12 @@ struct Parser {
     data: HashMap<String, String>,
     tmpl: String,
     result: String,
+    missing: MissingKeyPolicy,
 }
 
 // Can the parser be extracted as a general algorithm implementation.
 impl Parser {
-    fn new(tmpl: String, data: HashMap<String, String>) -> Self {
+    fn new(tmpl: String, data: HashMap<String, String>, missing: MissingKeyPolicy) -> Self {
         // Capacity here is simply an estimation. We predict that the result
         // string is equal or greater in length than the template itself.
         let result_cap = tmpl.len();
@@ -21,6 +33,7 @@ impl Parser {
             data,
             tmpl,
             result: String::with_capacity(result_cap),
+            missing,
         }
     }
 
@@ -59,11 +72,16 @@ impl Parser {
         let delim_end = tmpl.find("}}").expect("missing closing delimiters: }}");
         let key = tmpl[2..delim_end].trim();
 
-        let val = self
-            .data
-            .get(key)
-            .unwrap_or_else(|| panic!("couldn't find data corresponding to key: {}", key));
-        self.result.push_str(val);
+        match self.data.get(key) {
+            Some(val) => self.result.push_str(val),
+            None => {
+                let val = self
+                    .missing
+                    .render(key)
+                    .unwrap_or_else(|| panic!("couldn't find data corresponding to key: {}", key));
+                self.result.push_str(&val);
+            }
+        }
 
         // returning index after the second closing '}'.
         at + delim_end + 2
@@ -99,4 +117,27 @@ mod tests {
         let result = parse(tmpl, data);
         assert_eq!(expected, result);
     }
+
+    #[test]
+    #[should_panic(expected = "couldn't find data corresponding to key: name")]
+    fn missing_key_panics_by_default() {
+        parse("Hello, {{ name }}!".to_owned(), HashMap::new());
+    }
+
+    #[test]
+    fn missing_key_policies() {
+        let tmpl = "Hello, {{ name }}{{surname}}!";
+        let data = HashMap::from([("name".to_string(), "Amin".to_string())]);
+        let render = |policy| parse_with_policy(tmpl.to_owned(), data.clone(), &policy);
+
+        assert_eq!(render(MissingKeyPolicy::Empty), "Hello, Amin!");
+        assert_eq!(
+            render(MissingKeyPolicy::KeepPlaceholder),
+            "Hello, Amin{{ surname }}!"
+        );
+        assert_eq!(
+            render(MissingKeyPolicy::Default("?".to_owned())),
+            "Hello, Amin?!"
+        );
+    }
 }
@@ -0,0 +1,129 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+// A file in the temp dir that is removed at the end of the test.
+struct TempFile(PathBuf);
+
+impl TempFile {
+    fn new(name: &str, content: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("gotmpl-{}-{}", std::process::id(), name));
+        fs::write(&path, content).unwrap();
+        Self(path)
+    }
+
+    fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+fn gotmpl(args: &[&Path]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_gotmpl"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn stderr(out: &Output) -> String {
+    String::from_utf8_lossy(&out.stderr).into_owned()
+}
+
+#[test]
+fn renders_json_and_yaml_data() {
+    let tmpl = TempFile::new("render.tmpl", "Hello, {{ name }}!");
+    let json = TempFile::new("render.json", r#"{"name": "Amin"}"#);
+    let yaml = TempFile::new("render.yaml", "name: Amin\n");
+
+    for data in [&json, &yaml] {
+        let out = gotmpl(&[tmpl.path(), data.path()]);
+        assert_eq!(out.status.code(), Some(0), "{}", stderr(&out));
+        assert_eq!(out.stdout, b"Hello, Amin!");
+    }
+}
+
+#[test]
+fn render_errors_exit_with_1() {
+    let tmpl = TempFile::new("missing.tmpl", "Hello, {{ name }}!");
+    let data = TempFile::new("missing.json", "{}");
+
+    let out = gotmpl(&[tmpl.path(), data.path()]);
+    assert_eq!(out.status.code(), Some(1));
+    assert!(out.stdout.is_empty());
+    assert_eq!(
+        stderr(&out),
+        "couldn't render template: couldn't find data corresponding to key: name\n"
+    );
+}
+
+#[test]
+fn front_matter_is_opt_in() {
+    let tmpl = TempFile::new(
+        "front.tmpl",
+        "---\ndefaults:\n  greeting: Hello\nrequired: [name]\n---\n{{ greeting }}, {{ name }}!",
+    );
+    let data = TempFile::new("front.json", r#"{"name": "Amin"}"#);
+    let flag = Path::new("--front-matter");
+
+    let out = gotmpl(&[flag, tmpl.path(), data.path()]);
+    assert_eq!(out.status.code(), Some(0), "{}", stderr(&out));
+    assert_eq!(out.stdout, b"Hello, Amin!");
+
+    let empty = TempFile::new("front-empty.json", "{}");
+    let out = gotmpl(&[flag, tmpl.path(), empty.path()]);
+    assert_eq!(out.status.code(), Some(1));
+    assert_eq!(
+        stderr(&out),
+        "couldn't render template: missing required key: name\n"
+    );
+
+    // Without the flag the front matter is just text.
+    let out = gotmpl(&[tmpl.path(), data.path()]);
+    assert_eq!(out.status.code(), Some(1));
+}
+
+#[test]
+fn bad_usage_exits_with_2() {
+    let tmpl = TempFile::new("usage.tmpl", "Hi");
+
+    let flag = Path::new("--front-matter");
+    let check = Path::new("--check");
+    for args in [
+        &[][..],
+        &[tmpl.path()],
+        &[check],
+        &[flag, check, tmpl.path()],
+    ] {
+        let out = gotmpl(args);
+        assert_eq!(out.status.code(), Some(2), "{:?}", args);
+        assert!(stderr(&out).starts_with("usage: gotmpl"), "{:?}", args);
+    }
+}
+
+#[test]
+fn unreadable_data_exits_with_3() {
+    let tmpl = TempFile::new("unreadable.tmpl", "Hi");
+    let missing = std::env::temp_dir().join("gotmpl-no-such-data.json");
+
+    let out = gotmpl(&[tmpl.path(), &missing]);
+    assert_eq!(out.status.code(), Some(3));
+    assert!(
+        stderr(&out).starts_with("couldn't read"),
+        "{}",
+        stderr(&out)
+    );
+
+    let data = TempFile::new("unparsable.json", "{");
+    let out = gotmpl(&[tmpl.path(), data.path()]);
+    assert_eq!(out.status.code(), Some(3));
+    assert!(
+        stderr(&out).starts_with("couldn't parse"),
+        "{}",
+        stderr(&out)
+    );
+}
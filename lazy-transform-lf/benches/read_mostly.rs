@@ -0,0 +1,45 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use lazy_transform_lf::LazyTransform;
+
+fn transform(src: &String) -> String {
+    format!("{} - extended!!!", src)
+}
+
+pub fn read_mostly_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("read_mostly");
+
+    let lt = LazyTransform::new(transform);
+    lt.set_source("value".to_owned()).unwrap();
+    group.bench_function("get/last_read", |b| {
+        b.iter(|| black_box(lt.guard().get().map(String::len)));
+    });
+
+    // Each thread only remembers its last read of a single LazyTransform, so
+    // alternating between two of them sends every get through the full path.
+    let other = LazyTransform::new(transform);
+    other.set_source("value".to_owned()).unwrap();
+    group.bench_function("get/full_path", |b| {
+        b.iter(|| {
+            black_box(lt.guard().get().map(String::len));
+            black_box(other.guard().get().map(String::len));
+        });
+    });
+
+    // Sources are set rarely compared to reads, so most reads still hit the
+    // fast path.
+    let mut i = 0;
+    group.bench_function("get/one_write_per_1000_reads", |b| {
+        b.iter(|| {
+            i += 1;
+            if i % 1000 == 0 {
+                lt.set_source(format!("value {}", i)).unwrap();
+            }
+            black_box(lt.guard().get().map(String::len))
+        });
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, read_mostly_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,123 @@
+use std::sync::RwLock;
+use std::thread;
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use lazy_transform_lf::LazyTransform;
+
+const OPS_PER_THREAD: u64 = 10_000;
+
+// Expensive enough that running it less often matters.
+fn transform(src: &u64) -> Vec<u64> {
+    (0..64).map(|i| src.wrapping_mul(i)).collect()
+}
+
+// The straightforward alternative: a source waiting to be transformed and
+// the last value behind one lock. Readers that find a source transform it
+// under the write lock, so they're as lazy as LazyTransform.
+struct Locked {
+    state: RwLock<(Option<u64>, Option<Vec<u64>>)>,
+}
+
+impl Locked {
+    fn new() -> Self {
+        Self {
+            state: RwLock::new((None, None)),
+        }
+    }
+
+    fn set_source(&self, src: u64) {
+        self.state.write().unwrap().0 = Some(src);
+    }
+
+    fn get_len(&self) -> Option<usize> {
+        {
+            let state = self.state.read().unwrap();
+            if state.0.is_none() {
+                return state.1.as_ref().map(Vec::len);
+            }
+        }
+        let mut state = self.state.write().unwrap();
+        if let Some(src) = state.0.take() {
+            state.1 = Some(transform(&src));
+        }
+        state.1.as_ref().map(Vec::len)
+    }
+}
+
+// Every thread reads, and sets a source once per `write_every` ops.
+fn lazy_transform(threads: u64, write_every: u64) {
+    let lt = LazyTransform::new(transform);
+    lt.set_source(0).unwrap();
+    thread::scope(|s| {
+        for t in 0..threads {
+            let lt = &lt;
+            s.spawn(move || {
+                for i in 0..OPS_PER_THREAD {
+                    if i % write_every == t % write_every {
+                        lt.set_source(i).unwrap();
+                    }
+                    black_box(lt.guard().get().map(Vec::len));
+                }
+            });
+        }
+    });
+}
+
+fn rwlock(threads: u64, write_every: u64) {
+    let locked = Locked::new();
+    locked.set_source(0);
+    thread::scope(|s| {
+        for t in 0..threads {
+            let locked = &locked;
+            s.spawn(move || {
+                for i in 0..OPS_PER_THREAD {
+                    if i % write_every == t % write_every {
+                        locked.set_source(i);
+                    }
+                    black_box(locked.get_len());
+                }
+            });
+        }
+    });
+}
+
+pub fn fresh_value_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("fresh_value");
+
+    let lt = LazyTransform::new(transform);
+    lt.set_source(1).unwrap();
+    group.bench_function("LazyTransform", |b| {
+        b.iter(|| black_box(lt.guard().get().map(Vec::len)))
+    });
+
+    let locked = Locked::new();
+    locked.set_source(1);
+    group.bench_function("RwLock", |b| b.iter(|| black_box(locked.get_len())));
+
+    group.finish();
+}
+
+// Both skip the sources that were replaced before anyone read them. What
+// differs is that readers of LazyTransform never wait for each other, while
+// a reader of the RwLock waits for every transform and set_source, and every
+// read still writes to the lock.
+pub fn workload_benchmark(c: &mut Criterion) {
+    for (name, write_every) in [("read_heavy", 1000), ("write_heavy", 2)] {
+        let mut group = c.benchmark_group(name);
+        group.sample_size(20);
+        for threads in [1, 2, 4, 8] {
+            group.bench_with_input(
+                BenchmarkId::new("LazyTransform", threads),
+                &threads,
+                |b, &t| b.iter(|| lazy_transform(t, write_every)),
+            );
+            group.bench_with_input(BenchmarkId::new("RwLock", threads), &threads, |b, &t| {
+                b.iter(|| rwlock(t, write_every))
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, fresh_value_benchmark, workload_benchmark);
+criterion_main!(benches);
@@ -2,18 +2,80 @@
 // set_source gets a source which can be passed to transformFn to get the
 // new value which should be cached and served in get_transformed. The
 // calculation should not happen until get_transformed is called.
-use std::fmt::Debug;
-use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+use std::cell::Cell;
+use std::convert::Infallible;
+use std::fmt;
+use std::ptr;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use seize::{reclaim, Guard, Linked};
+
+pub use seize::Collector;
+
+pub use map::{GuardedMapped, Mapped, Versioned};
+pub use multi::{GuardedMultiSourceLazyTransform, MultiSourceLazyTransform, Sources};
+#[cfg(feature = "serde")]
+pub use snapshot::Snapshot;
+use sync::{protect, AtomicBool, AtomicPtr, AtomicUsize};
+use waiters::Waiters;
+pub use watch::{Changed, Watcher};
+
+mod map;
+mod multi;
+#[cfg(feature = "serde")]
+mod snapshot;
+mod sync;
+mod waiters;
+mod watch;
+
+// Every LazyTransform gets a unique id, so entries in LAST_READ can never be
+// mistaken for ones of another instance, even if it reuses the address of a
+// dropped one. It's a std atomic even under loom, whose atomics can't be
+// created in a static.
+static NEXT_ID: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+#[cfg(not(loom))]
+thread_local! {
+    // The value context this thread read last through the full path of get,
+    // as (id of the LazyTransform, seq of the value, value context). Only a
+    // single entry is kept, so a thread reading several LazyTransforms in
+    // turn always takes the full path.
+    static LAST_READ: Cell<(usize, usize, *const ())> =
+        const { Cell::new((usize::MAX, 0, ptr::null())) };
+}
 
-use seize::{reclaim, Collector, Guard, Linked};
+// Loom runs its threads on a single OS thread, so they need a thread local of
+// loom's to not share the entry.
+#[cfg(loom)]
+loom::thread_local! {
+    static LAST_READ: Cell<(usize, usize, *const ())> = Cell::new((usize::MAX, 0, ptr::null()));
+}
 
-// TODO: source and val can be of different types.
-pub struct LazyTransform<F, T: Debug> {
-    collector: Collector,
+pub struct LazyTransform<F, S, T> {
+    id: usize,
+    // Possibly shared with other instances, see with_collector.
+    collector: Arc<Collector>,
     transform: F,
+    error_policy: ErrorPolicy,
+    source_policy: SourcePolicy,
+    flight_policy: FlightPolicy,
+    // Set while a getter transforms with FlightPolicy::SingleFlight.
+    in_flight: AtomicBool,
     seq_counter: AtomicUsize,
     val_ctx: AtomicPtr<Linked<ValueContext<T>>>,
-    src_ctx: AtomicPtr<Linked<SourceContext<T>>>,
+    src_ctx: AtomicPtr<Linked<SourceContext<S>>>,
+    // Readers blocked in get_or_wait.
+    waiters: Waiters,
+    // Writers blocked in set_source until the pending source is taken, see
+    // SourcePolicy::BlockIfPending.
+    producers: Waiters,
+    // Hands committed values over to storage, see with_persist.
+    persist: Option<Box<Persist<T>>>,
+    // The seq of the value that was persisted last. Locked while the
+    // callback runs, so values are persisted one at a time and in order.
+    persisted: Mutex<Option<usize>>,
 
     // Metrics.
     // Incremented when the attempt to set source context through
@@ -25,48 +87,203 @@ pub struct LazyTransform<F, T: Debug> {
     // Incremented when someone has already inserted source context with a
     // higher sequence numebr than the one we tried to insert.
     set_source_comp_exch_failure_outdated: AtomicUsize,
+    // Incremented when the source policy turns a source down.
+    set_source_rejected: AtomicUsize,
+    // Incremented every time the transform is called, whether it succeeds or not.
+    transforms_performed: AtomicUsize,
+    // Incremented when a transformed value is thrown away because a value
+    // of a newer source was already stored.
+    transforms_wasted: AtomicUsize,
+    // Incremented when a cancellable transform gives up on a stale source.
+    transforms_cancelled: AtomicUsize,
+}
+
+type Persist<T> = dyn Fn(usize, &T) + Send + Sync;
+
+/// A snapshot of the counters a LazyTransform keeps about its own operation.
+/// The counters are updated independently of each other, so a snapshot taken
+/// while other threads are busy isn't guaranteed to be consistent.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Metrics {
+    /// Sources stored by `set_source`.
+    pub set_source_successes: usize,
+    /// Times `set_source` lost a race against an older source and retried.
+    pub set_source_retryable_failures: usize,
+    /// Sources dropped by `set_source` because a newer one was already set.
+    pub set_source_outdated_failures: usize,
+    /// Sources handed back by `set_source` because of the `SourcePolicy`.
+    pub set_source_rejections: usize,
+    /// Calls to the transform, including the ones that failed.
+    pub transforms_performed: usize,
+    /// Transformed values dropped because a newer value was already stored.
+    pub transforms_wasted: usize,
+    /// Cancellable transforms that gave up because their source was stale.
+    pub transforms_cancelled: usize,
 }
 
-struct ValueContext<T: Debug> {
+struct ValueContext<T> {
     seq: usize,
-    val: T,
+    // Shared with the callers of get_arc and get_shared, which can hold on to
+    // it after the context is reclaimed.
+    val: Arc<T>,
 }
 
-struct SourceContext<T: Debug> {
+struct SourceContext<S> {
     seq: usize,
-    source: Option<T>,
+    // The source is shared with the markers that replace it once it's taken,
+    // so it's still around for invalidate to transform it again.
+    source: Arc<S>,
+    // False once a getter has taken the responsibility of transforming it.
+    pending: bool,
+    // The seq of the set_source that provided the source. It's lower than
+    // seq once invalidate has put the source back, see set_source for why
+    // that matters.
+    source_seq: usize,
 }
 
-impl<T> ValueContext<T>
-where
-    T: Debug,
-{
+/// Decides what happens to a source when a fallible transform of it fails.
+/// Either way, the last successfully transformed value keeps being served.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// The source is only transformed again once a new source is set, or
+    /// `invalidate` is called.
+    #[default]
+    Discard,
+    /// The source is put back (unless a newer one was set in the meantime), so
+    /// the next `try_get` attempts the transform again.
+    Retry,
+}
+
+/// Decides what `set_source` does when the last source hasn't been
+/// transformed yet, for producers that are faster than the readers.
+///
+/// The check is made once, before the source is stored, so concurrent
+/// `set_source` calls can still replace each other's sources.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SourcePolicy {
+    /// The new source replaces the pending one, which is never transformed.
+    #[default]
+    Latest,
+    /// The new source is handed back with `SetSourceError::Pending`.
+    RejectIfPending,
+    /// `set_source` blocks until a reader has taken the pending source, or
+    /// hands the new one back with `SetSourceError::TimedOut` after the
+    /// timeout.
+    BlockIfPending(Duration),
+}
+
+/// Decides whether getters transform sources concurrently.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FlightPolicy {
+    /// A getter that finds a new source transforms it, even while another
+    /// getter is still transforming an older one. Getters that find the new
+    /// source already taken return the previous value right away.
+    #[default]
+    Concurrent,
+    /// Only one transform runs at a time. Getters that find a transform in
+    /// flight wait for it and return its value, and a newer source is only
+    /// transformed once it's done. That saves the transforms whose values
+    /// would be replaced right away, at the cost of latency. The guard of a
+    /// waiting getter delays reclamation, like in `get_or_wait`.
+    SingleFlight,
+}
+
+/// Returned by `set_source` when the `SourcePolicy` turned a source down. The
+/// source is handed back, so the caller can try again later or drop it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SetSourceError<S> {
+    /// The last source was still pending.
+    Pending(S),
+    /// The last source was still pending when the timeout passed.
+    TimedOut(S),
+}
+
+impl<S> SetSourceError<S> {
+    pub fn into_source(self) -> S {
+        match self {
+            SetSourceError::Pending(source) | SetSourceError::TimedOut(source) => source,
+        }
+    }
+}
+
+impl<S> fmt::Display for SetSourceError<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SetSourceError::Pending(_) => write!(f, "the last source is still pending"),
+            SetSourceError::TimedOut(_) => {
+                write!(f, "timed out waiting for the last source to be taken")
+            }
+        }
+    }
+}
+
+impl<S: fmt::Debug> std::error::Error for SetSourceError<S> {}
+
+/// Handed to the transform of a LazyTransform created with `new_cancellable`,
+/// so that a long transform can check whether it's still worth finishing.
+///
+/// This isn't a `cancel_token::CancellationToken`: nobody cancels a
+/// transform, it's its source that gets outdated by a newer seq. Reading
+/// `seq_counter` tells that for free, while a token per transform would cost
+/// an allocation, and `set_source` would have to find and cancel the tokens
+/// of the transforms still running on older sources.
+pub struct Staleness<'a> {
+    seq_counter: &'a AtomicUsize,
+    // The seq of the source that's being transformed.
+    seq: usize,
+}
+
+impl Staleness<'_> {
+    /// Returns true once a newer source was set or the source was
+    /// invalidated. The value of the source that's being transformed would
+    /// then be replaced right away, so the transform might as well give up.
+    pub fn is_stale(&self) -> bool {
+        self.seq_counter.load(Ordering::Acquire) > self.seq
+    }
+}
+
+impl<T> ValueContext<T> {
     fn new(seq: usize, val: T) -> Self {
+        Self::shared(seq, Arc::new(val))
+    }
+
+    fn shared(seq: usize, val: Arc<T>) -> Self {
         Self { seq, val }
     }
 }
 
-impl<T> SourceContext<T>
-where
-    T: Debug,
-{
-    fn new(seq: usize, source: Option<T>) -> Self {
-        Self { seq, source }
+impl<S> SourceContext<S> {
+    // What take_source leaves behind in place of this context.
+    fn marker(&self) -> Self {
+        Self {
+            seq: self.seq,
+            source: Arc::clone(&self.source),
+            pending: false,
+            source_seq: self.source_seq,
+        }
+    }
+
+    fn new(seq: usize, source: Arc<S>, pending: bool) -> Self {
+        Self {
+            seq,
+            source,
+            pending,
+            source_seq: seq,
+        }
     }
 }
 
-impl<T: Debug> Drop for ValueContext<T> {
+#[cfg(feature = "tracing")]
+impl<T> Drop for ValueContext<T> {
     fn drop(&mut self) {
-        println!("dropping value context with seq={}, value={:?}", self.seq, self.val);
+        tracing::trace!(seq = self.seq, "dropping value context");
     }
 }
 
-impl<F, T> Drop for LazyTransform<F, T>
-where
-    T: Debug,
-{
+impl<F, S, T> Drop for LazyTransform<F, S, T> {
     fn drop(&mut self) {
-        println!("dropping lazy transform");
+        #[cfg(feature = "tracing")]
+        tracing::trace!("dropping lazy transform");
         // SAFETY: because we have a &mut to self, it's safe to drop
         // everything immediate as Rust guarantees that no one else
         // will have a reference to self. And because of this, we won't
@@ -76,51 +293,240 @@ where
 
         // Ordering is irrelevant here because Atomics are loaded immediately
         // anyways due to the special guard that we use here.
-        let val_ctx = guard.protect(&self.val_ctx, Ordering::Relaxed);
-        let src_ctx = guard.protect(&self.src_ctx, Ordering::Relaxed);
+        let val_ctx = protect(&guard, &self.val_ctx, Ordering::Relaxed);
+        let src_ctx = protect(&guard, &self.src_ctx, Ordering::Relaxed);
 
         if !val_ctx.is_null() {
             unsafe {
-                guard.retire(val_ctx, reclaim::boxed::<ValueContext<T>>);
+                retire_val(&guard, val_ctx);
             }
         }
         if !src_ctx.is_null() {
             unsafe {
-                guard.retire(src_ctx, reclaim::boxed::<SourceContext<T>>);
+                retire_src(&guard, src_ctx);
             }
         }
     }
 }
 
-impl<F, T> LazyTransform<F, T>
-where
-    T: Debug,
-    F: Fn(&T) -> T,
-{
-    pub fn new(transform: F) -> Self {
+impl<F, S, T> LazyTransform<F, S, T> {
+    fn with_transform(transform: F) -> Self {
         Self {
-            collector: Collector::new(),
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            collector: Arc::new(sync::new_collector()),
             transform,
+            error_policy: ErrorPolicy::default(),
+            source_policy: SourcePolicy::default(),
+            flight_policy: FlightPolicy::default(),
+            in_flight: AtomicBool::new(false),
             seq_counter: AtomicUsize::new(0),
             val_ctx: AtomicPtr::default(),
             src_ctx: AtomicPtr::default(),
+            waiters: Waiters::new(),
+            producers: Waiters::new(),
+            persist: None,
+            persisted: Mutex::new(None),
             set_source_comp_exch_success: AtomicUsize::new(0),
             set_source_comp_exch_failure_retryable: AtomicUsize::new(0),
             set_source_comp_exch_failure_outdated: AtomicUsize::new(0),
+            set_source_rejected: AtomicUsize::new(0),
+            transforms_performed: AtomicUsize::new(0),
+            transforms_wasted: AtomicUsize::new(0),
+            transforms_cancelled: AtomicUsize::new(0),
+        }
+    }
+
+    /// Sets what happens to a source whose fallible transform failed.
+    pub fn with_error_policy(mut self, policy: ErrorPolicy) -> Self {
+        self.error_policy = policy;
+        self
+    }
+
+    /// Sets what `set_source` does while the last source is still pending.
+    pub fn with_source_policy(mut self, policy: SourcePolicy) -> Self {
+        self.source_policy = policy;
+        self
+    }
+
+    /// Sets whether getters transform sources concurrently.
+    pub fn with_flight_policy(mut self, policy: FlightPolicy) -> Self {
+        self.flight_policy = policy;
+        self
+    }
+
+    /// Makes this instance retire its sources and values into `collector`
+    /// instead of a collector of its own. Sharing one collector saves memory
+    /// when there are many instances, but retired values can then outlive
+    /// the instance, hence the `'static` bounds.
+    pub fn with_collector(mut self, collector: Arc<Collector>) -> Self
+    where
+        S: 'static,
+        T: 'static,
+    {
+        // Only the contexts of with_initial, with_initial_value and eager
+        // were linked into the old collector, and they were never shared.
+        unsafe {
+            relink(&self.src_ctx, &collector);
+            relink(&self.val_ctx, &collector);
+        }
+        self.collector = collector;
+        self
+    }
+
+    /// Starts out with `source`, as if it was set by a `set_source` before
+    /// any other. Its value gets sequence number 0.
+    ///
+    /// # Panics
+    ///
+    /// If called twice.
+    pub fn with_initial(self, source: S) -> Self {
+        assert!(
+            self.src_ctx.load(Ordering::Relaxed).is_null(),
+            "the initial source was already set"
+        );
+        // The value of with_initial_value has seq 0, and has to be replaced
+        // by the one of the source.
+        let seq = if self.val_ctx.load(Ordering::Relaxed).is_null() {
+            0
+        } else {
+            1
+        };
+        self.seq_counter.store(seq, Ordering::Relaxed);
+        let src_ctx = SourceContext::new(seq, Arc::new(source), true);
+        self.src_ctx
+            .store(self.collector.link_boxed(src_ctx), Ordering::Relaxed);
+        self
+    }
+
+    /// Starts out serving `value`, e.g. the last good value from before a
+    /// restart, until the first source is transformed. The value gets
+    /// sequence number 0 and counts as persisted, see `with_persist`. An
+    /// initial source of `with_initial` still replaces it on the first `get`.
+    ///
+    /// # Panics
+    ///
+    /// If there's a value already.
+    pub fn with_initial_value(mut self, value: T) -> Self {
+        assert!(
+            self.val_ctx.load(Ordering::Relaxed).is_null(),
+            "the initial value was already set"
+        );
+        let src_ctx = self.src_ctx.load(Ordering::Relaxed);
+        if !src_ctx.is_null() {
+            // SAFETY: nothing has been shared yet, we own self.
+            let ctx = unsafe { &mut *src_ctx };
+            ctx.seq = 1;
+            ctx.source_seq = 1;
+            self.seq_counter.store(1, Ordering::Relaxed);
+        }
+        let val_ctx = ValueContext::new(0, value);
+        self.val_ctx
+            .store(self.collector.link_boxed(val_ctx), Ordering::Relaxed);
+        *self
+            .persisted
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner) = Some(0);
+        self
+    }
+
+    /// Sets a callback that saves values, so they can be served by
+    /// `with_initial_value` after a restart. It's given the sequence number
+    /// and the value. Storing a value never calls it; that's left to `persist`
+    /// and `persist_next`, usually on a background thread, so getters don't
+    /// pay for slow storage.
+    pub fn with_persist(mut self, persist: impl Fn(usize, &T) + Send + Sync + 'static) -> Self {
+        self.persist = Some(Box::new(persist));
+        self
+    }
+
+    /// Hands the current value to the callback of `with_persist`, unless it
+    /// was handed over already, and returns its sequence number. None if
+    /// there's nothing new to persist, or no callback.
+    pub fn persist(&self) -> Option<usize> {
+        let persist = self.persist.as_ref()?;
+        let mut persisted = self
+            .persisted
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        // The guard isn't held while the callback runs, so that slow storage
+        // doesn't delay reclamation.
+        let (seq, val) = {
+            let guard = self.collector.enter();
+            let val_ctx = protect(&guard, &self.val_ctx, Ordering::Acquire);
+            let val_ctx = unsafe { val_ctx.as_ref() }?;
+            (val_ctx.seq, Arc::clone(&val_ctx.val))
+        };
+        if persisted.is_some_and(|p| p >= seq) {
+            return None;
+        }
+        persist(seq, &val);
+        *persisted = Some(seq);
+        Some(seq)
+    }
+
+    /// Like `persist`, but if there's nothing new to persist, blocks until a
+    /// new value is stored or `timeout` has passed. Returns None right away
+    /// without a callback.
+    pub fn persist_next(&self, timeout: Duration) -> Option<usize> {
+        self.persist.as_ref()?;
+        let deadline = Instant::now() + timeout;
+        // Registered before checking, so a value stored right after isn't
+        // missed.
+        let registration = self.waiters.register();
+        loop {
+            let seen = registration.generation();
+            if let Some(seq) = self.persist() {
+                return Some(seq);
+            }
+            if !registration.wait(seen, deadline) {
+                return None;
+            }
+        }
+    }
+
+    /// Stores a new source, to be transformed by the next `get`. Fails only
+    /// if the `SourcePolicy` turns the source down, which `Latest` never
+    /// does.
+    pub fn set_source(&self, source: S) -> Result<(), SetSourceError<S>> {
+        let SourcePolicy::BlockIfPending(timeout) = self.source_policy else {
+            return self.store_source(source);
+        };
+
+        let deadline = Instant::now() + timeout;
+        // Registered before the first check, so a take right after it isn't
+        // missed.
+        let registration = self.producers.register();
+        let mut source = source;
+        loop {
+            let seen = registration.generation();
+            match self.store_source(source) {
+                Err(SetSourceError::Pending(rejected)) => source = rejected,
+                res => return res,
+            }
+            if !registration.wait(seen, deadline) {
+                return Err(SetSourceError::TimedOut(source));
+            }
         }
     }
 
-    pub fn set_source(&self, source: T) {
+    // Does the work of set_source. With any policy but Latest, a pending
+    // source is reported instead of replaced.
+    fn store_source(&self, source: S) -> Result<(), SetSourceError<S>> {
+        if self.source_policy != SourcePolicy::Latest && self.has_pending_source() {
+            self.set_source_rejected.fetch_add(1, Ordering::Relaxed);
+            return Err(SetSourceError::Pending(source));
+        }
+
         // TODO: should Ordering be Relaxed?
-        let new_seq = self.seq_counter.fetch_add(1, Ordering::AcqRel) + 1;
+        let mut new_seq = self.seq_counter.fetch_add(1, Ordering::AcqRel) + 1;
 
         // Make the heap allocation once outside the loop.
-        let new_src = self
-            .collector
-            .link_boxed(SourceContext::new(new_seq, Some(source)));
+        let new_src =
+            self.collector
+                .link_boxed(SourceContext::new(new_seq, Arc::new(source), true));
 
         let guard = self.collector.enter();
-        let mut cur_src = guard.protect(&self.src_ctx, Ordering::Acquire);
+        let mut cur_src = protect(&guard, &self.src_ctx, Ordering::Acquire);
 
         loop {
             // Ordering for failure is set to Acquire because in case of success, cur
@@ -142,9 +548,10 @@ where
                     // On the first call to set_source, cur is still empty, so we should
                     // make sure it's not null before retiring.
                     if !cur.is_null() {
-                        self.collector
-                            .retire(cur, reclaim::boxed::<SourceContext<T>>);
+                        retire_src(&guard, cur);
                     }
+                    // Waiting readers can now do the transform themselves.
+                    self.waiters.notify();
                     break;
                 },
                 Err(cur) => {
@@ -161,11 +568,31 @@ where
                     // Impossible for two threads to acquire the same sequence number.
                     assert_ne!(new_seq, cur_ref.seq);
 
+                    #[cfg(feature = "tracing")]
+                    tracing::trace!(
+                        seq = new_seq,
+                        current = cur_ref.seq,
+                        "set_source CAS failed"
+                    );
+
                     if new_seq > cur_ref.seq {
                         self.set_source_comp_exch_failure_retryable
                             .fetch_add(1, Ordering::Relaxed);
                         // We have the latest data, so we should over-write.
                         cur_src = cur;
+                    } else if new_seq > cur_ref.source_seq {
+                        self.set_source_comp_exch_failure_retryable
+                            .fetch_add(1, Ordering::Relaxed);
+                        // An invalidate that started after us has put back an older source
+                        // than ours, so our source is still the latest one. It needs
+                        // a seq higher than the invalidation's though, or its value would
+                        // lose against the one transformed from the old source.
+                        new_seq = self.seq_counter.fetch_add(1, Ordering::AcqRel) + 1;
+                        // SAFETY: we're still the sole owner of new_src.
+                        let new_src_ref = unsafe { &mut *new_src };
+                        new_src_ref.seq = new_seq;
+                        new_src_ref.source_seq = new_seq;
+                        cur_src = cur;
                     } else {
                         self.set_source_comp_exch_failure_outdated
                             .fetch_add(1, Ordering::Relaxed);
@@ -173,71 +600,335 @@ where
                         // SAFETY: because we're the sole owner of this allocation, and we
                         // haven't stored it anywhere, it's safe to retire at any time.
                         unsafe {
-                            self.collector
-                                .retire(new_src, reclaim::boxed::<SourceContext<T>>);
+                            retire_src(&guard, new_src);
                         }
                         break;
                     }
                 }
             }
         }
+        Ok(())
+    }
+
+    fn has_pending_source(&self) -> bool {
+        let guard = self.collector.enter();
+        let src_ctx = protect(&guard, &self.src_ctx, Ordering::Acquire);
+        !src_ctx.is_null() && unsafe { &*src_ctx }.pending
+    }
+
+    /// Makes the next `get` transform the last source again, as if it was set
+    /// once more. This is for transforms that depend on more than the source,
+    /// when that other state has changed. Until then, the current value is
+    /// still served like after `set_source`. Returns false if there's no
+    /// source to transform yet.
+    pub fn invalidate(&self) -> bool {
+        // Taking a seq of our own orders the invalidation with set_source
+        // calls, and makes the fast path of get stop serving the current value.
+        let new_seq = self.seq_counter.fetch_add(1, Ordering::AcqRel) + 1;
+
+        let guard = self.collector.enter();
+        let mut cur_src = protect(&guard, &self.src_ctx, Ordering::Acquire);
+
+        loop {
+            if cur_src.is_null() {
+                return false;
+            }
+
+            let cur_ref = unsafe { &*cur_src };
+            if cur_ref.seq > new_seq {
+                // A newer source was set in the meantime, which will be
+                // transformed anyway.
+                return true;
+            }
+
+            let new_src = self.collector.link_boxed(SourceContext {
+                seq: new_seq,
+                source: Arc::clone(&cur_ref.source),
+                pending: true,
+                source_seq: cur_ref.source_seq,
+            });
+            match self.src_ctx.compare_exchange(
+                cur_src,
+                new_src,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(cur) => {
+                    unsafe { retire_src(&guard, cur) };
+                    self.waiters.notify();
+                    return true;
+                }
+                Err(cur) => {
+                    // new_src was never shared, so it can be retired right away.
+                    unsafe { retire_src(&guard, new_src) };
+                    cur_src = cur;
+                }
+            }
+        }
+    }
+
+    /// Removes the current value and returns it, for values that should be
+    /// consumed only once. Of concurrent calls, only one gets the value. The
+    /// last source is transformed again by the next `get`, as after
+    /// `invalidate`, unless a concurrent `get` already did that and its value
+    /// was taken instead.
+    pub fn take_value(&self) -> Option<Arc<T>> {
+        // The invalidation bumps seq_counter before the value is retired,
+        // which the fast path of get relies on.
+        self.invalidate();
+
+        let guard = self.collector.enter();
+        let val_ctx = self.val_ctx.swap(ptr::null_mut(), Ordering::AcqRel);
+        let val_ctx_ref = unsafe { val_ctx.as_ref() }?;
+        let val = Arc::clone(&val_ctx_ref.val);
+        unsafe { retire_val(&guard, val_ctx) };
+        Some(val)
     }
 
-    pub fn guard(&self) -> GuardedLazyTransform<'_, F, T> {
+    pub fn guard(&self) -> GuardedLazyTransform<'_, F, S, T> {
         let guard = self.collector.enter();
         GuardedLazyTransform { guard, lt: self }
     }
 
-    pub fn get<'g>(&self, guard: &'g Guard<'g>) -> Option<&'g T> {
-        let cur_src_ctx = guard.protect(&self.src_ctx, Ordering::Acquire);
-        if cur_src_ctx.is_null() {
-            return None;
+    pub fn metrics(&self) -> Metrics {
+        Metrics {
+            set_source_successes: self.set_source_comp_exch_success.load(Ordering::Relaxed),
+            set_source_retryable_failures: self
+                .set_source_comp_exch_failure_retryable
+                .load(Ordering::Relaxed),
+            set_source_outdated_failures: self
+                .set_source_comp_exch_failure_outdated
+                .load(Ordering::Relaxed),
+            set_source_rejections: self.set_source_rejected.load(Ordering::Relaxed),
+            transforms_performed: self.transforms_performed.load(Ordering::Relaxed),
+            transforms_wasted: self.transforms_wasted.load(Ordering::Relaxed),
+            transforms_cancelled: self.transforms_cancelled.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Returns a watcher that's notified whenever a new source is set, so
+    /// that callers can react to new values instead of polling `get`.
+    pub fn subscribe(&self) -> Watcher<'_, F, S, T> {
+        Watcher::new(self)
+    }
+
+    // The sequence number of the latest source, whether it's been transformed
+    // or not. Getters that take the source leave a marker with the same seq
+    // behind, and outdated sources are never stored, so it only increases.
+    fn source_seq(&self) -> usize {
+        let guard = self.collector.enter();
+        let src_ctx = protect(&guard, &self.src_ctx, Ordering::Acquire);
+        if src_ctx.is_null() {
+            return 0;
         }
+        unsafe { &*src_ctx }.seq
+    }
+
+    // Shared by all the getters which only differ in how the transform is
+    // called. The value is stored in the Arc it's returned in. Errors from
+    // `transform` are returned only to this caller, and the current value
+    // stays untouched. Ok(None) means that the transform was cancelled.
+    fn get_with<'g, E>(
+        &self,
+        guard: &'g Guard<'g>,
+        transform: impl FnOnce(&S, &Staleness<'_>) -> Result<Option<Arc<T>>, E>,
+    ) -> Result<Option<&'g ValueContext<T>>, E> {
+        loop {
+            if let Some(val) = self.last_read(guard) {
+                return Ok(Some(val));
+            }
 
-        let src_ref = unsafe { &(*cur_src_ctx).source };
-        if src_ref.is_some() {
-            match self.do_transform(guard, cur_src_ctx) {
-                Some(val) => return Some(val),
-                None => (),
+            // Without a source there can still be a restored value.
+            let cur_src_ctx = protect(guard, &self.src_ctx, Ordering::Acquire);
+            let pending = !cur_src_ctx.is_null() && unsafe { &*cur_src_ctx }.pending;
+
+            if self.flight_policy == FlightPolicy::SingleFlight
+                && (pending || self.in_flight.load(Ordering::Acquire))
+            {
+                let Some(_flight) = self.start_flight() else {
+                    // Its value, or the chance to transform the newer source.
+                    self.wait_for_flight();
+                    continue;
+                };
+                // The source may have been transformed before we got here.
+                let cur_src_ctx = protect(guard, &self.src_ctx, Ordering::Acquire);
+                if !cur_src_ctx.is_null() && unsafe { &*cur_src_ctx }.pending {
+                    if let Some(val) = self.do_transform(guard, cur_src_ctx, transform)? {
+                        return Ok(Some(val));
+                    }
+                }
+            } else if pending {
+                if let Some(val) = self.do_transform(guard, cur_src_ctx, transform)? {
+                    return Ok(Some(val));
+                }
             }
+            break;
         }
 
-        let val_ctx = guard.protect(&self.val_ctx, Ordering::Acquire);
+        let val_ctx = protect(guard, &self.val_ctx, Ordering::Acquire);
         if val_ctx.is_null() {
+            return Ok(None);
+        }
+
+        let val_ctx = unsafe { &*val_ctx };
+        LAST_READ.with(|last| last.set((self.id, val_ctx.seq, val_ctx as *const _ as *const ())));
+        Ok(Some(val_ctx))
+    }
+
+    // Returns None if another getter's transform is in flight. Dropping the
+    // returned flight ends it, even if the transform panics.
+    fn start_flight(&self) -> Option<Flight<'_>> {
+        self.in_flight
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Relaxed)
+            .ok()?;
+        Some(Flight {
+            in_flight: &self.in_flight,
+            waiters: &self.waiters,
+        })
+    }
+
+    fn wait_for_flight(&self) {
+        // Registered before checking, so the end of the flight isn't missed.
+        let registration = self.waiters.register();
+        loop {
+            let seen = registration.generation();
+            if !self.in_flight.load(Ordering::SeqCst) {
+                return;
+            }
+            registration.wait_forever(seen);
+        }
+    }
+
+    // The fast path of get. If the value this thread read last is for the
+    // latest seq handed out by set_source, there's no newer source that could
+    // be transformed, so get would return the same value again. Checking that
+    // takes a single load of seq_counter, without touching src_ctx or val_ctx.
+    //
+    // The value can't have been reclaimed either: replacing it requires a
+    // value with a higher seq, which means seq_counter was bumped before it.
+    // We read seq_counter after the guard was entered, so if we didn't see the
+    // bump, the value is retired (if at all) after that and stays around until
+    // the guard is dropped.
+    fn last_read<'g>(&self, _guard: &'g Guard<'g>) -> Option<&'g ValueContext<T>> {
+        let (id, seq, val_ctx) = LAST_READ.with(Cell::get);
+        if id != self.id || seq != self.seq_counter.load(Ordering::Acquire) {
             return None;
         }
-        unsafe { Some(&(**val_ctx).val) }
+
+        let val_ctx = val_ctx as *const Linked<ValueContext<T>>;
+        unsafe { Some(&*val_ctx) }
     }
 
-    fn do_transform<'g>(
+    fn do_transform<'g, E>(
         &self,
         guard: &'g Guard<'g>,
-        cur_src_ctx: *mut Linked<SourceContext<T>>,
-    ) -> Option<&'g T> {
-        match self.take_source(guard, cur_src_ctx) {
-            None => None,
-            Some(cur_src) => {
-                // We need to extract the seq again because we might end up with a different
-                // sequence number than the one we started due to the retry loop.
-                let (seq, src) = unsafe {
-                    let src = &(*cur_src);
-                    (src.seq, src.source.as_ref().unwrap())
-                };
+        cur_src_ctx: *mut Linked<SourceContext<S>>,
+        transform: impl FnOnce(&S, &Staleness<'_>) -> Result<Option<Arc<T>>, E>,
+    ) -> Result<Option<&'g ValueContext<T>>, E> {
+        let (cur_src, taken_marker) = match self.take_source(guard, cur_src_ctx) {
+            None => return Ok(None),
+            Some(taken) => taken,
+        };
+
+        // We need to extract the seq again because we might end up with a different
+        // sequence number than the one we started due to the retry loop.
+        let (seq, src) = unsafe {
+            let src = &(*cur_src);
+            (src.seq, &*src.source)
+        };
+
+        // Perform the potentially expensive calculation.
+        self.transforms_performed.fetch_add(1, Ordering::Relaxed);
+        let staleness = Staleness {
+            seq_counter: &self.seq_counter,
+            seq,
+        };
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("transform", seq).entered();
+        #[cfg(feature = "tracing")]
+        let started = Instant::now();
+        #[cfg(feature = "tracing")]
+        tracing::debug!("transform started");
+
+        let res = transform(src, &staleness);
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            elapsed_us = started.elapsed().as_micros() as u64,
+            outcome = match &res {
+                Ok(Some(_)) => "value",
+                Ok(None) => "cancelled",
+                Err(_) => "error",
+            },
+            "transform finished"
+        );
+
+        match res {
+            Ok(Some(new_val)) => {
+                // It's safe to retire the cur_src here even though src is still
+                // borrowed. Retiring through the guard delays the reclamation until
+                // the guard is dropped.
+                unsafe { retire_src(guard, cur_src) };
+                Ok(Some(self.store_val(guard, seq, new_val)))
+            }
+            Ok(None) => {
+                // The source is stale, so there's no point in putting it
+                // back. The newer one replaces the marker once it's set.
+                self.transforms_cancelled.fetch_add(1, Ordering::Relaxed);
+                unsafe { retire_src(guard, cur_src) };
+                Ok(None)
+            }
+            Err(e) => {
+                match self.error_policy {
+                    ErrorPolicy::Discard => unsafe { retire_src(guard, cur_src) },
+                    ErrorPolicy::Retry => self.restore_source(guard, cur_src, taken_marker),
+                }
+                Err(e)
+            }
+        }
+    }
 
-                // Perform the potentially expensive calculation.
-                let new_val = (self.transform)(src);
-                Some(self.store_val(guard, seq, new_val))
+    // Puts a source that was taken by take_source back in place of the marker
+    // that was left for it, so it's transformed again by the next getter.
+    fn restore_source(
+        &self,
+        guard: &Guard<'_>,
+        taken: *mut Linked<SourceContext<S>>,
+        marker: *mut Linked<SourceContext<S>>,
+    ) {
+        // The marker isn't pending, so getters never replace it. If it's gone,
+        // set_source replaced it with a newer source (and retired the marker)
+        // which makes the source we took obsolete.
+        //
+        // Republishing `taken` is safe because it was never retired, and
+        // threads that still have it loaded from before will see exactly the
+        // same (immutable) context.
+        match self
+            .src_ctx
+            .compare_exchange(marker, taken, Ordering::AcqRel, Ordering::Acquire)
+        {
+            Ok(_) => {
+                unsafe { retire_src(guard, marker) };
+                self.waiters.notify();
             }
+            Err(_) => unsafe { retire_src(guard, taken) },
         }
     }
 
+    // Replaces the current source with a marker that has the same seq and
+    // source but isn't pending, which makes us responsible for transforming
+    // it. On success, the taken source and the marker are returned. The
+    // caller is responsible for retiring the taken source, which lets it put
+    // the source back if needed.
+    #[allow(clippy::type_complexity)]
     fn take_source<'g>(
         &self,
         guard: &'g Guard<'g>,
-        mut cur_src_ctx: *mut Linked<SourceContext<T>>,
-    ) -> Option<*mut Linked<SourceContext<T>>> {
-        let seq = unsafe { &(*cur_src_ctx) }.seq;
-        let new_src_ctx = self.collector.link_boxed(SourceContext::new(seq, None));
+        mut cur_src_ctx: *mut Linked<SourceContext<S>>,
+    ) -> Option<(*mut Linked<SourceContext<S>>, *mut Linked<SourceContext<S>>)> {
+        let new_src_ctx = self
+            .collector
+            .link_boxed(unsafe { (*cur_src_ctx).marker() });
+        let mut seq = unsafe { (&*new_src_ctx).seq };
 
         loop {
             match self.src_ctx.compare_exchange(
@@ -249,22 +940,21 @@ where
                 Ok(cur_src) => {
                     // Eventually, cur_src_ctx must be deallocated because CAS was successful
                     // so no new threads will have access to it anymore, thus safe to retire.
+                    // That's left to the caller though, because a failed transform might
+                    // want to put it back.
                     //
                     // cur_src is guaranteed to be the cur_src_ctx. We should prefer to use cur_src
                     // because we're in a loop and this CAS could be retried with a different cur_src_ctx
                     // so in every iteration we need to get the most up-to-date value.
-
-                    // It's safe to retire the cur_src here even though we're returning a reference
-                    // to it to the caller. The reason is that we're calling retire on guard which
-                    // ensures that that retirement happens after the guard is dropped.
-                    unsafe { guard.retire(cur_src, reclaim::boxed::<SourceContext<T>>) };
-
-                    return Some(cur_src);
+                    //
+                    // The source isn't pending anymore, so blocked producers can go.
+                    self.producers.notify();
+                    return Some((cur_src, new_src_ctx));
                 }
                 Err(cur_src) => {
-                    let (cur_seq, cur_source) = unsafe {
+                    let (cur_seq, cur_pending) = unsafe {
                         let cur = &(*cur_src);
-                        (cur.seq, cur.source.as_ref())
+                        (cur.seq, cur.pending)
                     };
 
                     // It's not possible to have our_seq > their_seq which means that
@@ -278,30 +968,28 @@ where
                         // It means that there's newer source from set_source and we
                         // should update the new allocation with the new sequence number
                         // and retry the CAS.
-                        if cur_source.is_some() {
+                        if cur_pending {
                             // We're the sole owner of new_src_ctx, so it's safe to get a
                             // mutable reference to it.
-                            unsafe { &mut (**new_src_ctx) }.seq = cur_seq;
+                            unsafe { **new_src_ctx = (*cur_src).marker() };
+                            seq = cur_seq;
                             cur_src_ctx = cur_src;
-
                         } else {
                             // Means that there is a newer source, but some other thread
                             // has already take the responsibility of performing the transform.
                             // We should retire our allocation and proceed to reading the
                             // current val.
-                            unsafe {
-                                guard.retire(new_src_ctx, reclaim::boxed::<SourceContext<T>>)
-                            };
+                            unsafe { retire_src(guard, new_src_ctx) };
                             return None;
                         }
                     } else {
-                        // Means that the source _must_ be None. which means someone
+                        // Means that the source _must_ not be pending, which means someone
                         // else is already taking care of it. We can proceed to load the val.
                         // We should retire our allocation for new_src_ctx.
                         // The thread with successful CAS should take care of retiring the
                         // cur_src_ctx at the end.
-                        assert!(cur_source.is_none());
-                        unsafe { guard.retire(new_src_ctx, reclaim::boxed::<SourceContext<T>>) };
+                        assert!(!cur_pending);
+                        unsafe { retire_src(guard, new_src_ctx) };
                         return None;
                     }
                 }
@@ -312,18 +1000,21 @@ where
     // Try to store the new value that we acquired from calling transform.
     // If there's already a more up-to-date value, that will be returned
     // instead and our allocation for the new value is retired.
-    fn store_val<'g>(&self, guard: &'g Guard<'_>, new_seq: usize, new_val: T) -> &'g T {
+    fn store_val<'g>(
+        &self,
+        guard: &'g Guard<'_>,
+        new_seq: usize,
+        new_val: Arc<T>,
+    ) -> &'g ValueContext<T> {
         let new_val_ctx = self
             .collector
-            .link_boxed(ValueContext::new(new_seq, new_val));
+            .link_boxed(ValueContext::shared(new_seq, new_val));
 
-        let mut cur_val_ctx = guard.protect(&self.val_ctx, Ordering::Acquire);
+        let mut cur_val_ctx = protect(guard, &self.val_ctx, Ordering::Acquire);
 
         if !cur_val_ctx.is_null() {
-            let (cur_seq, cur_val) = unsafe {
-                let cur = &(*cur_val_ctx);
-                (cur.seq, &cur.val)
-            };
+            let cur = unsafe { &*cur_val_ctx };
+            let cur_seq = cur.seq;
 
             assert_ne!(new_seq, cur_seq);
 
@@ -331,9 +1022,10 @@ where
             // transform, someone else has already done the calcuation with a newer source.
             // So we can retire new_val_ctx.
             if new_seq < cur_seq {
+                self.transforms_wasted.fetch_add(1, Ordering::Relaxed);
                 // Using guard to delay retiring until the guard is dropped.
-                unsafe { guard.retire(new_val_ctx, reclaim::boxed::<ValueContext<T>>) };
-                return cur_val;
+                unsafe { retire_val(guard, new_val_ctx) };
+                return cur;
             }
         }
 
@@ -350,29 +1042,39 @@ where
                     // We've successfully stored the value we calculated, so we can retire cur_val_ctx.
                     // cur_val_ctx would be null the first time we do the transform and attempt to store it.
                     if !cur_val_ctx.is_null() {
-                        unsafe { guard.retire(cur_val_ctx, reclaim::boxed::<ValueContext<T>>) };
+                        unsafe { retire_val(guard, cur_val_ctx) };
                     }
+                    self.waiters.notify();
 
-                    return unsafe { &(*new_val_ctx).val };
+                    return unsafe { &*new_val_ctx };
                 }
                 Err(cur_val) => {
-                    let old_seq = unsafe { &(*cur_val) }.seq;
+                    // take_value removed the value in the meantime.
+                    let Some(old) = (unsafe { cur_val.as_ref() }) else {
+                        cur_val_ctx = cur_val;
+                        continue;
+                    };
+                    let old_seq = old.seq;
 
                     // `new_seq == old_seq` is impossible because there's no way that two threads
                     // can take on the responsibility of calculating the value with same seq.
                     assert_ne!(new_seq, old_seq);
 
+                    #[cfg(feature = "tracing")]
+                    tracing::trace!(seq = new_seq, current = old_seq, "storing value CAS failed");
+
                     if new_seq > old_seq {
                         // We have value with newer sequence number and coming here
                         // means that someone else with older value managed to do the CAS
                         // first so we should retry.
                         cur_val_ctx = cur_val;
                     } else {
+                        self.transforms_wasted.fetch_add(1, Ordering::Relaxed);
                         // Someone with newer value already succeeded so we can retire our
                         // new_val. And then return the current value.
-                        unsafe { guard.retire(new_val_ctx, reclaim::boxed::<ValueContext<T>>) };
+                        unsafe { retire_val(guard, new_val_ctx) };
 
-                        return unsafe { &(**cur_val).val };
+                        return old;
                     }
                 }
             }
@@ -380,117 +1082,422 @@ where
     }
 }
 
-pub struct GuardedLazyTransform<'a, F, T: Debug> {
-    guard: Guard<'a>,
-    lt: &'a LazyTransform<F, T>,
-}
-
-impl<F, T> GuardedLazyTransform<'_, F, T>
+impl<F, S, T> LazyTransform<F, S, T>
 where
-    T: Debug,
-    F: Fn(&T) -> T,
+    F: Fn(&S) -> T,
 {
-    pub fn get(&self) -> Option<&T> {
-        self.lt.get(&self.guard)
+    pub fn new(transform: F) -> Self {
+        Self::with_transform(transform)
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    use rand::Rng;
-    use std::collections::HashSet;
-    use std::thread;
-    use std::time::Duration;
 
-    const CONC_CALL_COUNT: usize = 1_000_000;
+    pub fn get<'g>(&self, guard: &'g Guard<'g>) -> Option<&'g T> {
+        self.get_ctx(guard).map(|ctx| &*ctx.val)
+    }
 
-    fn string_transform(s: &String) -> String {
-        format!("{} - extended!!!", s)
+    /// Like `get`, but also returns the sequence number of the source the
+    /// value was transformed from. It only increases, so callers can tell
+    /// whether the value changed between two reads.
+    pub fn get_versioned<'g>(&self, guard: &'g Guard<'g>) -> Option<(usize, &'g T)> {
+        self.get_ctx(guard).map(|ctx| (ctx.seq, &*ctx.val))
     }
 
-    #[test]
-    fn drop_empty_lazy_transform() {
-        let lt = LazyTransform::new(string_transform);
+    /// Like `get_versioned`, but returns None if the value is still the one
+    /// with sequence number `seq`, or an older one.
+    pub fn get_if_newer_than<'g>(
+        &self,
+        guard: &'g Guard<'g>,
+        seq: usize,
+    ) -> Option<(usize, &'g T)> {
+        self.get_versioned(guard).filter(|(s, _)| *s > seq)
+    }
 
-        drop(lt);
+    /// Like `get`, but returns a clone of the value, so no guard has to be
+    /// held while it's used.
+    pub fn get_cloned(&self) -> Option<T>
+    where
+        T: Clone,
+    {
+        let guard = self.collector.enter();
+        self.get(&guard).cloned()
     }
-    #[test]
-    fn set_source_first_call() {
-        let lt = LazyTransform::new(string_transform);
 
-        lt.set_source("input".to_string());
+    /// Like `get_cloned`, but shares the value instead of cloning it. The
+    /// value stays alive as long as the returned Arc, even if a newer one
+    /// replaces it in the meantime.
+    pub fn get_arc(&self) -> Option<Arc<T>> {
+        let guard = self.collector.enter();
+        self.get_ctx(&guard).map(|ctx| Arc::clone(&ctx.val))
     }
 
-    #[test]
-    fn set_source_many_concurrent_calls() {
-        let lt = LazyTransform::new(|src: &(String, usize)| (src.0.to_owned(), src.1));
+    /// Derives a value from this one with `map`. It's computed lazily as
+    /// well, and only once per value of this LazyTransform, no matter how
+    /// often it's read. Derived values can be mapped again.
+    pub fn map<G, U>(&self, map: G) -> Mapped<'_, Self, G, U>
+    where
+        G: Fn(&T) -> U,
+    {
+        Mapped::new(self, map)
+    }
 
-        thread::scope(|s| {
-            for _ in 0..20 {
-                s.spawn(|| {
-                    for i in 0..CONC_CALL_COUNT {
-                        lt.set_source((format!("{:?}", thread::current().id()), i));
-                    }
+    /// Transforms the source of `with_initial` right away, so the first
+    /// `get` doesn't have to. Does nothing without an initial source.
+    pub fn eager(self) -> Self {
+        {
+            let guard = self.collector.enter();
+            let src_ctx = protect(&guard, &self.src_ctx, Ordering::Acquire);
+            // Not get, which would leave an entry in LAST_READ for the value
+            // that with_collector may still move.
+            if !src_ctx.is_null() && unsafe { &*src_ctx }.pending {
+                let res: Result<_, Infallible> = self.do_transform(&guard, src_ctx, |src, _| {
+                    Ok(Some(Arc::new((self.transform)(src))))
                 });
+                let Ok(_) = res;
             }
-        });
+        }
+        self
+    }
 
-        let src_ctx = lt.src_ctx.load(Ordering::Relaxed);
-        println!("{:?}", unsafe { &(*src_ctx) }.source);
+    fn get_ctx<'g>(&self, guard: &'g Guard<'g>) -> Option<&'g ValueContext<T>> {
+        let res: Result<_, Infallible> =
+            self.get_with(guard, |src, _| Ok(Some(Arc::new((self.transform)(src)))));
+        match res {
+            Ok(val) => val,
+        }
+    }
 
-        let success_count = lt.set_source_comp_exch_success.load(Ordering::Relaxed);
-        println!(
-            "set_source compare_exchange success count = {}",
-            success_count
-        );
+    /// Like `get`, but if there's no value yet, blocks until one is available
+    /// or `timeout` has passed. Note that reclamation is delayed for as long
+    /// as `guard` is held, including the time spent waiting.
+    pub fn get_or_wait<'g>(&self, guard: &'g Guard<'g>, timeout: Duration) -> Option<&'g T> {
+        let deadline = Instant::now() + timeout;
+        let registration = self.waiters.register();
 
-        let failure_retryable_count = lt
-            .set_source_comp_exch_failure_outdated
-            .load(Ordering::Relaxed);
-        println!(
-            "set_source compare_exchange failure retryable count = {}",
-            failure_retryable_count
-        );
+        loop {
+            let seen = registration.generation();
+            if let Some(val) = self.get(guard) {
+                return Some(val);
+            }
+            if !registration.wait(seen, deadline) {
+                return None;
+            }
+        }
+    }
+}
 
-        let failure_outdated_count = lt
-            .set_source_comp_exch_failure_outdated
-            .load(Ordering::Relaxed);
-        println!(
-            "set_source compare_exchange failure outdated count ={}",
-            failure_outdated_count
-        );
+impl<F, S, T, E> LazyTransform<F, S, T>
+where
+    F: Fn(&S) -> Result<T, E>,
+{
+    /// Creates a LazyTransform whose transform can fail, the values are read
+    /// with `try_get`.
+    pub fn new_fallible(transform: F) -> Self {
+        Self::with_transform(transform)
     }
 
-    #[test]
-    fn get_first_call() {
-        let lt = LazyTransform::new(string_transform);
+    /// Like `get`, for transforms that can fail. If the transform of a new
+    /// source fails, the error is returned to the caller that ran it, and the
+    /// source is handled according to the `ErrorPolicy`. Other callers keep
+    /// getting the last successfully transformed value.
+    pub fn try_get<'g>(&self, guard: &'g Guard<'g>) -> Result<Option<&'g T>, E> {
+        Ok(self.try_get_ctx(guard)?.map(|ctx| &*ctx.val))
+    }
 
-        let glt = lt.guard();
-        let val = glt.get();
-        assert!(val.is_none());
+    /// Like `try_get`, but shares the value instead of borrowing it, see
+    /// `get_arc`.
+    pub fn try_get_arc(&self) -> Result<Option<Arc<T>>, E> {
+        let guard = self.collector.enter();
+        Ok(self.try_get_ctx(&guard)?.map(|ctx| Arc::clone(&ctx.val)))
     }
 
-    #[test]
-    fn get_should_return_some_after_set_source() {
-        let lt = LazyTransform::new(string_transform);
+    fn try_get_ctx<'g>(&self, guard: &'g Guard<'g>) -> Result<Option<&'g ValueContext<T>>, E> {
+        self.get_with(guard, |src, _| {
+            (self.transform)(src).map(|v| Some(Arc::new(v)))
+        })
+    }
+}
+
+impl<F, S, T> LazyTransform<F, S, T>
+where
+    F: Fn(&S, &Staleness<'_>) -> Option<T>,
+{
+    /// Creates a LazyTransform whose transform can give up on a source once
+    /// it's stale, by returning None. Values are read with `get_cancellable`.
+    pub fn new_cancellable(transform: F) -> Self {
+        Self::with_transform(transform)
+    }
+
+    /// Like `get`, for transforms that can be cancelled. If the transform run
+    /// by this call is cancelled, nothing is stored and the current value is
+    /// returned, just like when another caller is already transforming the
+    /// newest source. The newer source is transformed by a later call.
+    pub fn get_cancellable<'g>(&self, guard: &'g Guard<'g>) -> Option<&'g T> {
+        self.get_cancellable_ctx(guard).map(|ctx| &*ctx.val)
+    }
+
+    /// Like `get_cancellable`, but shares the value instead of borrowing it,
+    /// see `get_arc`.
+    pub fn get_cancellable_arc(&self) -> Option<Arc<T>> {
+        let guard = self.collector.enter();
+        self.get_cancellable_ctx(&guard)
+            .map(|ctx| Arc::clone(&ctx.val))
+    }
+
+    fn get_cancellable_ctx<'g>(&self, guard: &'g Guard<'g>) -> Option<&'g ValueContext<T>> {
+        let res: Result<_, Infallible> = self.get_with(guard, |src, staleness| {
+            Ok((self.transform)(src, staleness).map(Arc::new))
+        });
+        match res {
+            Ok(val) => val,
+        }
+    }
+}
+
+impl<F, S, T> LazyTransform<F, S, T>
+where
+    F: Fn(&S) -> Arc<T>,
+{
+    /// Creates a LazyTransform whose transform returns its values in an Arc,
+    /// which is stored as is. Values are read with `get_shared`, which hands
+    /// out clones of that Arc, so a transform that returns an Arc it also
+    /// keeps elsewhere shares the value with the readers without copying it.
+    pub fn new_shared(transform: F) -> Self {
+        Self::with_transform(transform)
+    }
+
+    /// Like `get_arc`, for transforms that return Arcs. No guard is needed,
+    /// so the value can be held across await points or for as long as the
+    /// caller likes.
+    pub fn get_shared(&self) -> Option<Arc<T>> {
+        self.get_shared_versioned().map(|(_, val)| val)
+    }
+
+    /// Like `get_shared`, but also returns the sequence number of the source
+    /// the value was transformed from, see `get_versioned`.
+    pub fn get_shared_versioned(&self) -> Option<(usize, Arc<T>)> {
+        let guard = self.collector.enter();
+        let res: Result<_, Infallible> =
+            self.get_with(&guard, |src, _| Ok(Some((self.transform)(src))));
+        match res {
+            Ok(val) => val.map(|ctx| (ctx.seq, Arc::clone(&ctx.val))),
+        }
+    }
+
+    /// Like `get_or_wait`, for transforms that return Arcs. Unlike
+    /// `get_or_wait`, no guard is held while waiting, so reclamation of
+    /// older values isn't held up.
+    pub fn get_shared_or_wait(&self, timeout: Duration) -> Option<Arc<T>> {
+        let deadline = Instant::now() + timeout;
+        let registration = self.waiters.register();
+
+        loop {
+            let seen = registration.generation();
+            if let Some(val) = self.get_shared() {
+                return Some(val);
+            }
+            if !registration.wait(seen, deadline) {
+                return None;
+            }
+        }
+    }
+}
+
+impl<F, S, T> Versioned for LazyTransform<F, S, T>
+where
+    F: Fn(&S) -> T,
+{
+    type Value = T;
+
+    fn collector(&self) -> &Collector {
+        &self.collector
+    }
+
+    fn get_versioned<'g>(&'g self, guard: &'g Guard<'g>) -> Option<(usize, &'g T)> {
+        LazyTransform::get_versioned(self, guard)
+    }
+}
+
+// Every context is retired through these, so retirements can be traced.
+unsafe fn retire_src<S>(guard: &Guard<'_>, src_ctx: *mut Linked<SourceContext<S>>) {
+    #[cfg(feature = "tracing")]
+    {
+        let seq = (&*src_ctx).seq;
+        tracing::trace!(seq = seq, "retiring source context");
+    }
+    guard.retire(src_ctx, reclaim::boxed::<SourceContext<S>>);
+}
+
+unsafe fn retire_val<T>(guard: &Guard<'_>, val_ctx: *mut Linked<ValueContext<T>>) {
+    #[cfg(feature = "tracing")]
+    {
+        let seq = (&*val_ctx).seq;
+        tracing::trace!(seq = seq, "retiring value context");
+    }
+    guard.retire(val_ctx, reclaim::boxed::<ValueContext<T>>);
+}
+
+// Moves a context into another collector.
+//
+// SAFETY: the context must not be shared with anyone.
+unsafe fn relink<X>(ptr: &AtomicPtr<Linked<X>>, collector: &Collector) {
+    let old = ptr.load(Ordering::Relaxed);
+    if !old.is_null() {
+        let ctx = Linked::into_inner(*Box::from_raw(old));
+        ptr.store(collector.link_boxed(ctx), Ordering::Relaxed);
+    }
+}
+
+struct Flight<'a> {
+    in_flight: &'a AtomicBool,
+    waiters: &'a Waiters,
+}
+
+impl Drop for Flight<'_> {
+    fn drop(&mut self) {
+        self.in_flight.store(false, Ordering::SeqCst);
+        self.waiters.notify();
+    }
+}
+
+pub struct GuardedLazyTransform<'a, F, S, T> {
+    guard: Guard<'a>,
+    lt: &'a LazyTransform<F, S, T>,
+}
+
+impl<F, S, T> GuardedLazyTransform<'_, F, S, T>
+where
+    F: Fn(&S) -> T,
+{
+    pub fn get(&self) -> Option<&T> {
+        self.lt.get(&self.guard)
+    }
+
+    pub fn get_or_wait(&self, timeout: Duration) -> Option<&T> {
+        self.lt.get_or_wait(&self.guard, timeout)
+    }
+
+    pub fn get_versioned(&self) -> Option<(usize, &T)> {
+        self.lt.get_versioned(&self.guard)
+    }
+
+    pub fn get_if_newer_than(&self, seq: usize) -> Option<(usize, &T)> {
+        self.lt.get_if_newer_than(&self.guard, seq)
+    }
+}
+
+impl<F, S, T, E> GuardedLazyTransform<'_, F, S, T>
+where
+    F: Fn(&S) -> Result<T, E>,
+{
+    pub fn try_get(&self) -> Result<Option<&T>, E> {
+        self.lt.try_get(&self.guard)
+    }
+}
+
+impl<F, S, T> GuardedLazyTransform<'_, F, S, T>
+where
+    F: Fn(&S, &Staleness<'_>) -> Option<T>,
+{
+    pub fn get_cancellable(&self) -> Option<&T> {
+        self.lt.get_cancellable(&self.guard)
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+
+    use rand::Rng;
+    use std::collections::HashSet;
+    use std::sync::{mpsc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    const CONC_CALL_COUNT: usize = 1_000_000;
+
+    fn string_transform(s: &String) -> String {
+        format!("{} - extended!!!", s)
+    }
+
+    #[test]
+    fn drop_empty_lazy_transform() {
+        let lt = LazyTransform::new(string_transform);
+
+        drop(lt);
+    }
+    #[test]
+    fn set_source_first_call() {
+        let lt = LazyTransform::new(string_transform);
+
+        lt.set_source("input".to_string()).unwrap();
+    }
+
+    #[test]
+    fn set_source_many_concurrent_calls() {
+        let lt = LazyTransform::new(|src: &(String, usize)| (src.0.to_owned(), src.1));
+
+        thread::scope(|s| {
+            for _ in 0..20 {
+                s.spawn(|| {
+                    for i in 0..CONC_CALL_COUNT {
+                        lt.set_source((format!("{:?}", thread::current().id()), i))
+                            .unwrap();
+                    }
+                });
+            }
+        });
+
+        let src_ctx = lt.src_ctx.load(Ordering::Relaxed);
+        println!("{:?}", unsafe { &(*src_ctx) }.source);
+
+        let success_count = lt.set_source_comp_exch_success.load(Ordering::Relaxed);
+        println!(
+            "set_source compare_exchange success count = {}",
+            success_count
+        );
+
+        let failure_retryable_count = lt
+            .set_source_comp_exch_failure_outdated
+            .load(Ordering::Relaxed);
+        println!(
+            "set_source compare_exchange failure retryable count = {}",
+            failure_retryable_count
+        );
+
+        let failure_outdated_count = lt
+            .set_source_comp_exch_failure_outdated
+            .load(Ordering::Relaxed);
+        println!(
+            "set_source compare_exchange failure outdated count ={}",
+            failure_outdated_count
+        );
+    }
+
+    #[test]
+    fn get_first_call() {
+        let lt = LazyTransform::new(string_transform);
+
+        let glt = lt.guard();
+        let val = glt.get();
+        assert!(val.is_none());
+    }
+
+    #[test]
+    fn get_should_return_some_after_set_source() {
+        let lt = LazyTransform::new(string_transform);
 
         thread::scope(|s| {
             s.spawn(|| {
                 rand_sleep(30, 200);
-                lt.set_source("value".to_owned());
+                lt.set_source("value".to_owned()).unwrap();
             });
 
             for _ in 0..3 {
-                s.spawn(|| {
-                    loop {
-                        let glt = lt.guard();
-                        let val = glt.get();
-                        if let Some(val) = val {
-                            assert_eq!(val, "value - extended!!!");
-                            break;
-                        }
+                s.spawn(|| loop {
+                    let glt = lt.guard();
+                    let val = glt.get();
+                    if let Some(val) = val {
+                        assert_eq!(val, "value - extended!!!");
+                        break;
                     }
                 });
             }
@@ -505,8 +1512,8 @@ mod tests {
             let glt = lt.guard();
             assert!(glt.get().is_none());
         }
-        
-        lt.set_source("old source".to_owned());
+
+        lt.set_source("old source".to_owned()).unwrap();
 
         {
             let glt = lt.guard();
@@ -514,7 +1521,7 @@ mod tests {
         }
 
         thread::sleep(Duration::from_millis(100));
-        lt.set_source("new source".to_owned());
+        lt.set_source("new source".to_owned()).unwrap();
 
         thread::sleep(Duration::from_millis(100));
         {
@@ -546,10 +1553,11 @@ mod tests {
                 if i % 3 == 0 {
                     s.spawn(|| {
                         for i in 0..20 {
-                        let mut rng = rand::thread_rng();
+                            let mut rng = rand::thread_rng();
                             let dur = rng.gen_range(50..200);
                             thread::sleep(Duration::from_millis(dur));
-                            lt.set_source((format!("{:?}", thread::current().id()), i));
+                            lt.set_source((format!("{:?}", thread::current().id()), i))
+                                .unwrap();
                         }
                     });
                 }
@@ -591,6 +1599,960 @@ mod tests {
         });
     }
 
+    #[test]
+    fn get_or_wait_times_out_without_source() {
+        let lt = LazyTransform::new(string_transform);
+
+        let start = std::time::Instant::now();
+        assert!(lt.guard().get_or_wait(Duration::from_millis(50)).is_none());
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[test]
+    fn get_or_wait_returns_existing_value_immediately() {
+        let lt = LazyTransform::new(string_transform);
+        lt.set_source("value".to_owned()).unwrap();
+
+        let glt = lt.guard();
+        assert_eq!(
+            glt.get_or_wait(Duration::from_secs(10)).unwrap(),
+            "value - extended!!!"
+        );
+    }
+
+    #[test]
+    fn get_or_wait_wakes_up_on_set_source() {
+        let lt = LazyTransform::new(string_transform);
+
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    let glt = lt.guard();
+                    let val = glt.get_or_wait(Duration::from_secs(10));
+                    assert_eq!(val.unwrap(), "value - extended!!!");
+                });
+            }
+
+            rand_sleep(30, 100);
+            lt.set_source("value".to_owned()).unwrap();
+        });
+    }
+
+    #[test]
+    fn get_or_wait_wakes_up_on_store_val() {
+        // The transform is slow enough that the readers that didn't take the
+        // source have to wait for the one that did.
+        let lt = LazyTransform::new(|s: &String| {
+            thread::sleep(Duration::from_millis(50));
+            s.to_uppercase()
+        });
+        lt.set_source("value".to_owned()).unwrap();
+
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    let glt = lt.guard();
+                    let val = glt.get_or_wait(Duration::from_secs(10));
+                    assert_eq!(val.unwrap(), "VALUE");
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn watcher_sees_new_sources() {
+        let lt = LazyTransform::new(string_transform);
+        lt.set_source("first".to_owned()).unwrap();
+
+        let mut watcher = lt.subscribe();
+        assert!(!watcher.has_changed());
+
+        lt.set_source("second".to_owned()).unwrap();
+        assert!(watcher.has_changed());
+        assert_eq!(watcher.mark_seen(), 2);
+        assert!(!watcher.has_changed());
+
+        // Transforming the source isn't a change of its own.
+        assert_eq!(lt.guard().get().unwrap(), "second - extended!!!");
+        assert!(!watcher.has_changed());
+    }
+
+    #[test]
+    fn watcher_wait_changed() {
+        let lt = LazyTransform::new(string_transform);
+        let mut watcher = lt.subscribe();
+        assert_eq!(watcher.seen(), 0);
+
+        assert_eq!(watcher.wait_changed(Duration::from_millis(20)), None);
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                rand_sleep(30, 100);
+                lt.set_source("value".to_owned()).unwrap();
+            });
+
+            assert_eq!(watcher.wait_changed(Duration::from_secs(10)), Some(1));
+        });
+        assert_eq!(lt.guard().get().unwrap(), "value - extended!!!");
+    }
+
+    #[tokio::test]
+    async fn watcher_changed_can_be_awaited() {
+        let lt = std::sync::Arc::new(LazyTransform::new(string_transform));
+
+        let reader = tokio::spawn({
+            let lt = lt.clone();
+            async move {
+                let mut watcher = lt.subscribe();
+                let mut seen = vec![];
+                while seen.len() < 3 {
+                    let seq = watcher.changed().await;
+                    let val = lt.guard().get().unwrap().clone();
+                    seen.push((seq, val));
+                }
+                seen
+            }
+        });
+
+        // Let the reader subscribe and start waiting before the first change.
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        for i in 1..=3 {
+            lt.set_source(format!("value {}", i)).unwrap();
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+
+        let seen = tokio::time::timeout(Duration::from_secs(10), reader)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            seen,
+            (1..=3)
+                .map(|i| (i, format!("value {} - extended!!!", i)))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn metrics_count_sources_and_transforms() {
+        let lt = LazyTransform::new(string_transform);
+        assert_eq!(lt.metrics(), Metrics::default());
+
+        lt.set_source("first".to_owned()).unwrap();
+        lt.set_source("second".to_owned()).unwrap();
+        assert!(lt.guard().get().is_some());
+        // The value is cached, so this doesn't transform again.
+        assert!(lt.guard().get().is_some());
+
+        assert_eq!(
+            lt.metrics(),
+            Metrics {
+                set_source_successes: 2,
+                transforms_performed: 1,
+                ..Metrics::default()
+            }
+        );
+    }
+
+    #[test]
+    fn metrics_count_failed_transforms_as_performed() {
+        let lt = LazyTransform::new_fallible(fallible_transform);
+
+        lt.set_source("nope".to_owned()).unwrap();
+        assert!(lt.guard().try_get().is_err());
+        lt.set_source("1".to_owned()).unwrap();
+        assert!(lt.guard().try_get().is_ok());
+
+        let metrics = lt.metrics();
+        assert_eq!(metrics.transforms_performed, 2);
+        assert_eq!(metrics.transforms_wasted, 0);
+    }
+
+    #[test]
+    fn metrics_under_contention_add_up() {
+        let lt = LazyTransform::new(|src: &usize| *src);
+
+        thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    for i in 0..10_000 {
+                        lt.set_source(i).unwrap();
+                        lt.guard().get();
+                    }
+                });
+            }
+        });
+
+        let metrics = lt.metrics();
+        // Every source is either stored or dropped for being outdated.
+        assert_eq!(
+            metrics.set_source_successes + metrics.set_source_outdated_failures,
+            80_000
+        );
+        // Only stored sources can be transformed, and a source is transformed
+        // at most once.
+        assert!(metrics.transforms_performed <= metrics.set_source_successes);
+        assert!(metrics.transforms_wasted <= metrics.transforms_performed);
+    }
+
+    #[test]
+    fn values_dont_need_to_implement_debug() {
+        struct Opaque(usize);
+
+        let lt = LazyTransform::new(|src: &usize| Opaque(src * 2));
+        lt.set_source(21).unwrap();
+        assert_eq!(lt.guard().get().unwrap().0, 42);
+    }
+
+    #[test]
+    fn cancellable_transform_gives_up_on_stale_source() {
+        let (started_tx, started_rx) = mpsc::channel();
+        let lt = LazyTransform::new_cancellable(|src: &usize, staleness: &Staleness| {
+            if *src == 1 {
+                // Stands in for a long transform that checks for staleness
+                // every now and then.
+                started_tx.send(()).unwrap();
+                while !staleness.is_stale() {
+                    thread::yield_now();
+                }
+                return None;
+            }
+            Some(*src * 10)
+        });
+
+        lt.set_source(1).unwrap();
+        thread::scope(|s| {
+            let getter = s.spawn(|| lt.guard().get_cancellable().copied());
+            started_rx.recv().unwrap();
+            lt.set_source(2).unwrap();
+            // Nothing was stored for the cancelled source.
+            assert_eq!(getter.join().unwrap(), None);
+        });
+
+        assert_eq!(lt.guard().get_cancellable(), Some(&20));
+        let metrics = lt.metrics();
+        assert_eq!(metrics.transforms_performed, 2);
+        assert_eq!(metrics.transforms_cancelled, 1);
+    }
+
+    #[test]
+    fn invalidate_makes_transform_stale() {
+        let (started_tx, started_rx) = mpsc::channel();
+        let (resume_tx, resume_rx) = mpsc::channel::<()>();
+        let resume_rx = Mutex::new(resume_rx);
+        let lt = LazyTransform::new_cancellable(|src: &usize, staleness: &Staleness| {
+            started_tx.send(()).unwrap();
+            resume_rx.lock().unwrap().recv().unwrap();
+            if staleness.is_stale() {
+                None
+            } else {
+                Some(*src)
+            }
+        });
+
+        lt.set_source(7).unwrap();
+        thread::scope(|s| {
+            let getter = s.spawn(|| lt.guard().get_cancellable().copied());
+            started_rx.recv().unwrap();
+            assert!(lt.invalidate());
+            resume_tx.send(()).unwrap();
+            assert_eq!(getter.join().unwrap(), None);
+        });
+
+        // The invalidated source is transformed again.
+        resume_tx.send(()).unwrap();
+        assert_eq!(lt.guard().get_cancellable(), Some(&7));
+        assert_eq!(lt.metrics().transforms_cancelled, 1);
+    }
+
+    #[test]
+    fn get_cloned_and_get_arc() {
+        let lt = LazyTransform::new(string_transform);
+        assert_eq!(lt.get_cloned(), None);
+        assert!(lt.get_arc().is_none());
+
+        lt.set_source("first".to_owned()).unwrap();
+        let cloned = lt.get_cloned().unwrap();
+        let shared = lt.get_arc().unwrap();
+        assert_eq!(cloned, "first - extended!!!");
+        assert_eq!(*shared, "first - extended!!!");
+        // Both come from the same transform.
+        assert_eq!(lt.metrics().transforms_performed, 1);
+
+        // The shared value outlives its replacement and the LazyTransform.
+        lt.set_source("second".to_owned()).unwrap();
+        assert_eq!(*lt.get_arc().unwrap(), "second - extended!!!");
+        drop(lt);
+        assert_eq!(*shared, "first - extended!!!");
+        assert_eq!(Arc::strong_count(&shared), 1);
+    }
+
+    #[tokio::test]
+    async fn get_arc_across_await_points() {
+        let lt = Arc::new(LazyTransform::new(|src: &usize| src * 2));
+        lt.set_source(21).unwrap();
+
+        let val = lt.get_arc().unwrap();
+        tokio::task::yield_now().await;
+        lt.set_source(50).unwrap();
+        tokio::task::yield_now().await;
+        assert_eq!(*val, 42);
+        assert_eq!(lt.get_cloned(), Some(100));
+    }
+
+    #[test]
+    fn shared_values_are_stored_without_copying() {
+        let cache: Mutex<Vec<Arc<String>>> = Mutex::new(vec![]);
+        let lt = LazyTransform::new_shared(|src: &String| {
+            let val = Arc::new(format!("{} - shared", src));
+            cache.lock().unwrap().push(Arc::clone(&val));
+            val
+        });
+        assert_eq!(lt.get_shared(), None);
+
+        lt.set_source("first".to_owned()).unwrap();
+        let (seq, first) = lt.get_shared_versioned().unwrap();
+        assert_eq!(seq, 1);
+        assert!(Arc::ptr_eq(&first, &cache.lock().unwrap()[0]));
+        assert!(Arc::ptr_eq(&first, &lt.get_shared().unwrap()));
+        assert_eq!(lt.metrics().transforms_performed, 1);
+
+        lt.set_source("second".to_owned()).unwrap();
+        assert_eq!(*lt.get_shared().unwrap(), "second - shared");
+        drop(lt);
+        // Held by us and the cache only.
+        assert_eq!(*first, "first - shared");
+        assert_eq!(Arc::strong_count(&first), 2);
+    }
+
+    #[test]
+    fn get_shared_or_wait_wakes_up_on_new_value() {
+        let lt = LazyTransform::new_shared(|src: &usize| Arc::new(src + 1));
+        assert_eq!(lt.get_shared_or_wait(Duration::from_millis(10)), None);
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(20));
+                lt.set_source(1).unwrap();
+            });
+            let val = lt.get_shared_or_wait(Duration::from_secs(5));
+            assert_eq!(val.as_deref(), Some(&2));
+        });
+    }
+
+    #[test]
+    fn arcs_of_fallible_and_cancellable_transforms() {
+        let lt = LazyTransform::new_fallible(
+            |src: &i32| if *src < 0 { Err("negative") } else { Ok(*src) },
+        );
+        assert_eq!(lt.try_get_arc(), Ok(None));
+        lt.set_source(-1).unwrap();
+        assert_eq!(lt.try_get_arc(), Err("negative"));
+        lt.set_source(3).unwrap();
+        assert_eq!(lt.try_get_arc(), Ok(Some(Arc::new(3))));
+
+        let lt = LazyTransform::new_cancellable(|src: &i32, _: &Staleness<'_>| Some(src * 2));
+        assert_eq!(lt.get_cancellable_arc(), None);
+        lt.set_source(4).unwrap();
+        assert_eq!(lt.get_cancellable_arc().as_deref(), Some(&8));
+    }
+
+    #[test]
+    fn get_versioned_tracks_changes() {
+        let lt = LazyTransform::new(string_transform);
+        assert_eq!(lt.guard().get_versioned(), None);
+        assert_eq!(lt.guard().get_if_newer_than(0), None);
+
+        lt.set_source("first".to_owned()).unwrap();
+        let guard = lt.guard();
+        let (seq, val) = guard.get_versioned().unwrap();
+        assert_eq!(val, "first - extended!!!");
+        // Same seq when read again, including through the fast path.
+        assert_eq!(guard.get_versioned().unwrap().0, seq);
+        assert_eq!(guard.get_if_newer_than(seq), None);
+        assert!(guard.get_if_newer_than(seq - 1).is_some());
+        drop(guard);
+
+        lt.set_source("second".to_owned()).unwrap();
+        let guard = lt.guard();
+        let (newer, val) = guard.get_if_newer_than(seq).unwrap();
+        assert!(newer > seq);
+        assert_eq!(val, "second - extended!!!");
+
+        // Invalidating produces a new version of the same source.
+        drop(guard);
+        lt.invalidate();
+        assert!(lt.guard().get_if_newer_than(newer).is_some());
+    }
+
+    #[test]
+    fn instances_can_share_a_collector() {
+        let collector = Arc::new(Collector::new());
+        let counted = Arc::new(());
+        let lts: Vec<_> = (0..4)
+            .map(|i| {
+                let counted = Arc::clone(&counted);
+                LazyTransform::new(move |src: &usize| (src * i, Arc::clone(&counted)))
+                    .with_collector(Arc::clone(&collector))
+            })
+            .collect();
+        assert_eq!(Arc::strong_count(&collector), 5);
+
+        thread::scope(|s| {
+            for lt in &lts {
+                s.spawn(move || {
+                    for src in 0..100 {
+                        lt.set_source(src).unwrap();
+                        lt.guard().get();
+                    }
+                });
+            }
+        });
+        let vals: Vec<_> = lts.iter().map(|lt| lt.guard().get().unwrap().0).collect();
+        assert_eq!(vals, [0, 99, 198, 297]);
+
+        // Values retired into the shared collector are still dropped once
+        // it's gone.
+        drop(lts);
+        assert_eq!(Arc::strong_count(&collector), 1);
+        drop(collector);
+        assert_eq!(Arc::strong_count(&counted), 1);
+    }
+
+    #[test]
+    fn fast_path_never_hides_a_new_source() {
+        let lt = LazyTransform::new(|src: &usize| *src);
+
+        for i in 0..10_000 {
+            lt.set_source(i).unwrap();
+            // The first get takes the full path, the second one is served
+            // from the last read of this thread.
+            assert_eq!(lt.guard().get(), Some(&i));
+            assert_eq!(lt.guard().get(), Some(&i));
+        }
+    }
+
+    #[test]
+    fn fast_path_keeps_instances_apart() {
+        let first = LazyTransform::new(|src: &usize| *src);
+        let second = LazyTransform::new(|src: &usize| *src + 100);
+        first.set_source(1).unwrap();
+        second.set_source(1).unwrap();
+
+        for _ in 0..3 {
+            assert_eq!(first.guard().get(), Some(&1));
+            assert_eq!(second.guard().get(), Some(&101));
+        }
+
+        // A new instance might reuse the memory of a dropped one, its last
+        // read must not be served by it.
+        drop(first);
+        let third = LazyTransform::new(|src: &usize| *src + 1000);
+        assert_eq!(third.guard().get(), None);
+        third.set_source(1).unwrap();
+        assert_eq!(third.guard().get(), Some(&1001));
+    }
+
+    #[test]
+    fn fast_path_reads_are_never_older_than_completed_set_source() {
+        // Readers may not see a source while set_source is still running, but
+        // once it has returned, get must not return anything older.
+        let lt = LazyTransform::new(|src: &usize| *src);
+        let published = AtomicUsize::new(0);
+        const SOURCES: usize = 100_000;
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                for i in 1..=SOURCES {
+                    lt.set_source(i).unwrap();
+                    published.store(i, Ordering::Release);
+                }
+            });
+
+            for _ in 0..4 {
+                s.spawn(|| loop {
+                    let min = published.load(Ordering::Acquire);
+                    let val = lt.guard().get().copied().unwrap_or(0);
+                    assert!(val >= min, "read {} after {} was set", val, min);
+                    if val == SOURCES {
+                        break;
+                    }
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn invalidate_transforms_last_source_again() {
+        let factor = AtomicUsize::new(1);
+        let lt = LazyTransform::new(|src: &usize| src * factor.load(Ordering::Relaxed));
+
+        lt.set_source(7).unwrap();
+        assert_eq!(lt.guard().get(), Some(&7));
+
+        // The value is cached until it's invalidated.
+        factor.store(3, Ordering::Relaxed);
+        assert_eq!(lt.guard().get(), Some(&7));
+
+        assert!(lt.invalidate());
+        assert_eq!(lt.guard().get(), Some(&21));
+        assert_eq!(lt.guard().get(), Some(&21));
+        assert_eq!(lt.metrics().transforms_performed, 2);
+    }
+
+    #[test]
+    fn invalidate_without_source() {
+        let lt = LazyTransform::new(string_transform);
+
+        assert!(!lt.invalidate());
+        assert!(lt.guard().get().is_none());
+
+        lt.set_source("value".to_owned()).unwrap();
+        assert_eq!(lt.guard().get().unwrap(), "value - extended!!!");
+    }
+
+    #[test]
+    fn invalidate_before_transform_and_after_new_source() {
+        let lt = LazyTransform::new(|src: &usize| *src);
+
+        // Invalidating a source that wasn't transformed yet is harmless.
+        lt.set_source(1).unwrap();
+        assert!(lt.invalidate());
+        assert_eq!(lt.guard().get(), Some(&1));
+        assert_eq!(lt.metrics().transforms_performed, 1);
+
+        // A source set after invalidating wins.
+        assert!(lt.invalidate());
+        lt.set_source(2).unwrap();
+        assert_eq!(lt.guard().get(), Some(&2));
+    }
+
+    #[test]
+    fn invalidate_concurrently_with_set_source() {
+        let lt = LazyTransform::new(|src: &usize| *src);
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                for i in 1..=10_000 {
+                    lt.set_source(i).unwrap();
+                }
+            });
+            s.spawn(|| {
+                for _ in 0..10_000 {
+                    lt.invalidate();
+                    lt.guard().get();
+                }
+            });
+        });
+
+        // Invalidations never bring back an older source.
+        assert_eq!(lt.guard().get(), Some(&10_000));
+    }
+
+    fn fallible_transform(s: &String) -> Result<usize, String> {
+        s.parse().map_err(|_| format!("not a number: {}", s))
+    }
+
+    #[test]
+    fn try_get_keeps_last_good_value_on_error() {
+        let lt = LazyTransform::new_fallible(fallible_transform);
+
+        lt.set_source("42".to_owned()).unwrap();
+        assert_eq!(lt.guard().try_get(), Ok(Some(&42)));
+
+        lt.set_source("forty-two".to_owned()).unwrap();
+        assert_eq!(
+            lt.guard().try_get(),
+            Err("not a number: forty-two".to_owned())
+        );
+
+        // The failed source was discarded, so the next caller just sees the
+        // last good value.
+        assert_eq!(lt.guard().try_get(), Ok(Some(&42)));
+
+        lt.set_source("7".to_owned()).unwrap();
+        assert_eq!(lt.guard().try_get(), Ok(Some(&7)));
+    }
+
+    #[test]
+    fn try_get_before_any_success_returns_none() {
+        let lt = LazyTransform::new_fallible(fallible_transform);
+
+        lt.set_source("nope".to_owned()).unwrap();
+        assert!(lt.guard().try_get().is_err());
+        assert_eq!(lt.guard().try_get(), Ok(None));
+    }
+
+    #[test]
+    fn retry_policy_transforms_failed_source_again() {
+        let attempts = AtomicUsize::new(0);
+        let lt = LazyTransform::new_fallible(|s: &String| {
+            // Fails the first two attempts, like a flaky backend would.
+            if attempts.fetch_add(1, Ordering::Relaxed) < 2 {
+                Err("flaky")
+            } else {
+                Ok(s.len())
+            }
+        })
+        .with_error_policy(ErrorPolicy::Retry);
+
+        lt.set_source("four".to_owned()).unwrap();
+        assert_eq!(lt.guard().try_get(), Err("flaky"));
+        assert_eq!(lt.guard().try_get(), Err("flaky"));
+        assert_eq!(lt.guard().try_get(), Ok(Some(&4)));
+
+        // Once transformed, the source is consumed and not retried anymore.
+        assert_eq!(lt.guard().try_get(), Ok(Some(&4)));
+        assert_eq!(attempts.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn retry_policy_concurrent_getters_see_one_success() {
+        let attempts = AtomicUsize::new(0);
+        let lt = LazyTransform::new_fallible(|s: &String| {
+            // Every other attempt fails, starting with the first one.
+            if attempts.fetch_add(1, Ordering::Relaxed) % 2 == 1 {
+                Ok(s.to_uppercase())
+            } else {
+                Err(())
+            }
+        })
+        .with_error_policy(ErrorPolicy::Retry);
+
+        lt.set_source("value".to_owned()).unwrap();
+
+        thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| loop {
+                    if let Ok(Some(val)) = lt.guard().try_get() {
+                        assert_eq!(val, "VALUE");
+                        break;
+                    }
+                });
+            }
+        });
+
+        // Every failed attempt put the source back for exactly one retry, and
+        // the first success consumed it.
+        assert_eq!(attempts.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn take_value_consumes_the_value_once() {
+        let lt = LazyTransform::new(|s: &String| s.to_uppercase());
+        assert_eq!(lt.take_value(), None);
+
+        lt.set_source("value".to_owned()).unwrap();
+        assert_eq!(lt.get_cloned().as_deref(), Some("VALUE"));
+        assert_eq!(
+            lt.take_value().as_deref().map(String::as_str),
+            Some("VALUE")
+        );
+        assert_eq!(lt.take_value(), None);
+
+        // The next get transforms the last source again.
+        assert_eq!(lt.get_cloned().as_deref(), Some("VALUE"));
+        assert_eq!(lt.metrics().transforms_performed, 2);
+    }
+
+    #[test]
+    fn take_value_races_with_getters() {
+        const ROUNDS: usize = 1_000;
+        let lt = LazyTransform::new(|s: &usize| *s);
+        let taken = AtomicUsize::new(0);
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                for i in 1..=ROUNDS {
+                    lt.set_source(i).unwrap();
+                }
+            });
+            for _ in 0..2 {
+                s.spawn(|| {
+                    for _ in 0..ROUNDS {
+                        lt.guard().get();
+                    }
+                });
+            }
+            for _ in 0..2 {
+                s.spawn(|| {
+                    for _ in 0..ROUNDS {
+                        if lt.take_value().is_some() {
+                            taken.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                });
+            }
+        });
+
+        assert!(taken.load(Ordering::Relaxed) > 0);
+        lt.take_value();
+        assert_eq!(lt.get_cloned(), Some(ROUNDS));
+    }
+
+    #[test]
+    fn reject_if_pending_hands_the_source_back() {
+        let lt =
+            LazyTransform::new(|s: &u32| s * 10).with_source_policy(SourcePolicy::RejectIfPending);
+
+        lt.set_source(1).unwrap();
+        assert_eq!(lt.set_source(2), Err(SetSourceError::Pending(2)));
+        assert_eq!(lt.get_cloned(), Some(10));
+
+        // Once taken, the source isn't pending anymore.
+        lt.set_source(3).unwrap();
+        assert_eq!(lt.get_cloned(), Some(30));
+
+        // Neither is an invalidated one, until it's transformed again.
+        assert!(lt.invalidate());
+        assert_eq!(
+            lt.set_source(4).map_err(SetSourceError::into_source),
+            Err(4)
+        );
+        assert_eq!(lt.metrics().set_source_rejections, 2);
+    }
+
+    #[test]
+    fn block_if_pending_waits_for_a_reader() {
+        let lt = LazyTransform::new(|s: &u32| s * 10)
+            .with_source_policy(SourcePolicy::BlockIfPending(Duration::from_secs(10)));
+        lt.set_source(1).unwrap();
+
+        thread::scope(|s| {
+            let producer = s.spawn(|| lt.set_source(2));
+            thread::sleep(Duration::from_millis(20));
+            assert!(!producer.is_finished());
+
+            assert_eq!(lt.get_cloned(), Some(10));
+            producer.join().unwrap().unwrap();
+        });
+        assert_eq!(lt.get_cloned(), Some(20));
+    }
+
+    #[test]
+    fn block_if_pending_times_out() {
+        let lt = LazyTransform::new(|s: &u32| s * 10)
+            .with_source_policy(SourcePolicy::BlockIfPending(Duration::from_millis(10)));
+
+        lt.set_source(1).unwrap();
+        assert_eq!(lt.set_source(2), Err(SetSourceError::TimedOut(2)));
+        assert_eq!(lt.get_cloned(), Some(10));
+    }
+
+    // Collects the messages of all events.
+    #[cfg(feature = "tracing")]
+    struct Messages(Mutex<Vec<String>>);
+
+    #[cfg(feature = "tracing")]
+    impl tracing::Subscriber for Messages {
+        fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+            true
+        }
+        fn new_span(&self, _: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+            tracing::span::Id::from_u64(1)
+        }
+        fn record(&self, _: &tracing::span::Id, _: &tracing::span::Record<'_>) {}
+        fn record_follows_from(&self, _: &tracing::span::Id, _: &tracing::span::Id) {}
+        fn event(&self, event: &tracing::Event<'_>) {
+            struct Message<'a>(&'a mut String);
+            impl tracing::field::Visit for Message<'_> {
+                fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn fmt::Debug) {
+                    if field.name() == "message" {
+                        *self.0 = format!("{:?}", value);
+                    }
+                }
+            }
+            let mut message = String::new();
+            event.record(&mut Message(&mut message));
+            self.0.lock().unwrap().push(message);
+        }
+        fn enter(&self, _: &tracing::span::Id) {}
+        fn exit(&self, _: &tracing::span::Id) {}
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn transforms_and_retirements_are_traced() {
+        let messages = std::sync::Arc::new(Messages(Mutex::new(vec![])));
+        tracing::subscriber::with_default(std::sync::Arc::clone(&messages), || {
+            let lt = LazyTransform::new(string_transform);
+            lt.set_source("a".to_owned()).unwrap();
+            lt.get_cloned();
+            lt.set_source("b".to_owned()).unwrap();
+            lt.get_cloned();
+        });
+
+        let messages = messages.0.lock().unwrap();
+        let count = |m: &str| messages.iter().filter(|msg| *msg == m).count();
+        assert_eq!(count("transform started"), 2);
+        assert_eq!(count("transform finished"), 2);
+        // The first value, both sources and their markers, and on drop the
+        // second value and the last marker.
+        assert_eq!(count("retiring value context"), 2);
+        assert!(count("retiring source context") >= 3);
+    }
+
+    #[test]
+    fn initial_source_is_transformed_lazily() {
+        let lt = LazyTransform::new(|s: &u32| s * 10).with_initial(1);
+        assert_eq!(lt.metrics().transforms_performed, 0);
+        assert_eq!(lt.guard().get_versioned(), Some((0, &10)));
+
+        lt.set_source(2).unwrap();
+        assert_eq!(lt.guard().get_versioned(), Some((1, &20)));
+        assert_eq!(lt.metrics().transforms_performed, 2);
+    }
+
+    #[test]
+    fn eager_transforms_initial_source_up_front() {
+        let lt = LazyTransform::new(string_transform)
+            .with_initial("value".to_owned())
+            .eager()
+            // Moves the initial source and its value along.
+            .with_collector(Arc::new(Collector::new()));
+        assert_eq!(lt.metrics().transforms_performed, 1);
+
+        assert_eq!(lt.get_cloned(), Some(string_transform(&"value".to_owned())));
+        assert_eq!(lt.metrics().transforms_performed, 1);
+
+        // Nothing to prime.
+        let lt = LazyTransform::new(string_transform).eager();
+        assert_eq!(lt.get_cloned(), None);
+    }
+
+    #[test]
+    fn single_flight_getters_wait_for_the_value() {
+        const GETTERS: usize = 8;
+        let barrier = std::sync::Barrier::new(GETTERS);
+        let lt = LazyTransform::new(|s: &String| {
+            thread::sleep(Duration::from_millis(20));
+            s.to_uppercase()
+        })
+        .with_flight_policy(FlightPolicy::SingleFlight);
+        lt.set_source("value".to_owned()).unwrap();
+
+        thread::scope(|s| {
+            for _ in 0..GETTERS {
+                s.spawn(|| {
+                    barrier.wait();
+                    // With Concurrent, all but the first getter get None.
+                    assert_eq!(lt.get_cloned().as_deref(), Some("VALUE"));
+                });
+            }
+        });
+        assert_eq!(lt.metrics().transforms_performed, 1);
+    }
+
+    #[test]
+    fn single_flight_runs_one_transform_at_a_time() {
+        let active = AtomicUsize::new(0);
+        let lt = LazyTransform::new(|s: &usize| {
+            assert_eq!(active.fetch_add(1, Ordering::SeqCst), 0);
+            rand_sleep(0, 2);
+            active.fetch_sub(1, Ordering::SeqCst);
+            *s
+        })
+        .with_flight_policy(FlightPolicy::SingleFlight);
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                for i in 1..=100 {
+                    lt.set_source(i).unwrap();
+                    rand_sleep(0, 1);
+                }
+            });
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..100 {
+                        lt.get_cloned();
+                    }
+                });
+            }
+        });
+        assert_eq!(lt.get_cloned(), Some(100));
+    }
+
+    #[test]
+    fn single_flight_survives_a_panicking_transform() {
+        let lt = LazyTransform::new(|s: &usize| {
+            assert_ne!(*s, 0);
+            *s
+        })
+        .with_flight_policy(FlightPolicy::SingleFlight);
+        lt.set_source(0).unwrap();
+        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| lt.get_cloned()));
+        assert!(res.is_err());
+
+        // Getters don't wait for the aborted flight.
+        lt.set_source(1).unwrap();
+        assert_eq!(lt.get_cloned(), Some(1));
+    }
+
+    #[test]
+    fn initial_value_is_served_until_a_source_is_transformed() {
+        let lt = LazyTransform::new(|s: &u32| s * 10).with_initial_value(1);
+        assert_eq!(lt.guard().get_versioned(), Some((0, &1)));
+        assert_eq!(lt.metrics().transforms_performed, 0);
+
+        lt.set_source(2).unwrap();
+        assert_eq!(lt.guard().get_versioned(), Some((1, &20)));
+
+        // The initial source wins over the initial value, in either order.
+        let lt = LazyTransform::new(|s: &u32| s * 10)
+            .with_initial(3)
+            .with_initial_value(1);
+        assert_eq!(lt.guard().get_versioned(), Some((1, &30)));
+        let lt = LazyTransform::new(|s: &u32| s * 10)
+            .with_initial_value(1)
+            .with_initial(3);
+        assert_eq!(lt.guard().get_versioned(), Some((1, &30)));
+    }
+
+    #[test]
+    fn persist_hands_over_new_values() {
+        let saved = Arc::new(Mutex::new(vec![]));
+        let lt = LazyTransform::new(|s: &u32| s * 10).with_persist({
+            let saved = Arc::clone(&saved);
+            move |seq, val: &u32| saved.lock().unwrap().push((seq, *val))
+        });
+        assert_eq!(lt.persist(), None);
+
+        lt.set_source(1).unwrap();
+        // Not a value yet.
+        assert_eq!(lt.persist(), None);
+        lt.get_cloned();
+        assert_eq!(lt.persist(), Some(1));
+        assert_eq!(lt.persist(), None);
+
+        thread::scope(|s| {
+            s.spawn(|| while lt.persist_next(Duration::from_secs(5)) != Some(3) {});
+            for i in 2..=3 {
+                thread::sleep(Duration::from_millis(10));
+                lt.set_source(i).unwrap();
+                lt.get_cloned();
+            }
+        });
+        let saved = saved.lock().unwrap();
+        assert_eq!(saved.first(), Some(&(1, 10)));
+        assert_eq!(saved.last(), Some(&(3, 30)));
+        assert!(saved.windows(2).all(|w| w[0].0 < w[1].0));
+    }
+
+    #[test]
+    fn initial_value_counts_as_persisted() {
+        let lt = LazyTransform::new(|s: &u32| s * 10)
+            .with_persist(|_, _| {})
+            .with_initial_value(1);
+        assert_eq!(lt.persist(), None);
+        assert_eq!(lt.persist_next(Duration::from_millis(10)), None);
+
+        // Without a callback, there's nothing to wait for.
+        let lt = LazyTransform::new(|s: &u32| s * 10);
+        lt.set_source(1).unwrap();
+        lt.get_cloned();
+        assert_eq!(lt.persist_next(Duration::from_secs(60)), None);
+    }
+
     fn rand_sleep(min: u64, max: u64) {
         let mut rng = rand::thread_rng();
         let dur = rng.gen_range(min..max);
@@ -0,0 +1,259 @@
+// Values derived from a LazyTransform, or from another derived value, with
+// `map`. A Mapped caches the result of its function together with the seq of
+// the parent value it was computed from, and only calls the function again
+// once the parent's seq has changed. Just like the transform, the function
+// runs in the first get that sees the new parent value.
+//
+// Unlike LazyTransform, there's no protocol that makes a single reader
+// responsible for the computation: readers that see a new parent value at
+// the same time all call the function, and the first one to store its
+// result wins. Results are linked into the parent's collector, so the
+// parent's guards protect them as well.
+use std::sync::atomic::Ordering;
+
+use seize::{reclaim, Collector, Guard, Linked};
+
+use crate::sync::{protect, AtomicPtr};
+use crate::ValueContext;
+
+/// A lazily computed value with a sequence number that increases whenever
+/// the value changes. This is what `map` derives values from.
+pub trait Versioned {
+    type Value;
+
+    /// The collector whose guards `get_versioned` takes.
+    fn collector(&self) -> &Collector;
+
+    fn get_versioned<'g>(&'g self, guard: &'g Guard<'g>) -> Option<(usize, &'g Self::Value)>;
+}
+
+pub struct Mapped<'a, P, G, U> {
+    parent: &'a P,
+    map: G,
+    cache: AtomicPtr<Linked<ValueContext<U>>>,
+}
+
+impl<P, G, U> Drop for Mapped<'_, P, G, U> {
+    fn drop(&mut self) {
+        // SAFETY: the values handed out by get borrow self, so none are left.
+        let guard = unsafe { Guard::unprotected() };
+        let cache = protect(&guard, &self.cache, Ordering::Relaxed);
+        if !cache.is_null() {
+            unsafe { guard.retire(cache, reclaim::boxed::<ValueContext<U>>) };
+        }
+    }
+}
+
+impl<'a, P, G, U> Mapped<'a, P, G, U>
+where
+    P: Versioned,
+    G: Fn(&P::Value) -> U,
+{
+    pub(crate) fn new(parent: &'a P, map: G) -> Self {
+        Self {
+            parent,
+            map,
+            cache: AtomicPtr::default(),
+        }
+    }
+
+    pub fn guard(&self) -> GuardedMapped<'_, P, G, U> {
+        let guard = self.parent.collector().enter();
+        GuardedMapped {
+            guard,
+            mapped: self,
+        }
+    }
+
+    /// Returns None until the parent has a value. `guard` has to be one of
+    /// the parent's, e.g. from `guard`.
+    pub fn get<'g>(&'g self, guard: &'g Guard<'g>) -> Option<&'g U> {
+        self.get_ctx(guard).map(|ctx| &*ctx.val)
+    }
+
+    /// Like `get`, but also returns the sequence number of the parent value
+    /// the value was computed from.
+    pub fn get_versioned<'g>(&'g self, guard: &'g Guard<'g>) -> Option<(usize, &'g U)> {
+        self.get_ctx(guard).map(|ctx| (ctx.seq, &*ctx.val))
+    }
+
+    pub fn get_cloned(&self) -> Option<U>
+    where
+        U: Clone,
+    {
+        let guard = self.parent.collector().enter();
+        self.get(&guard).cloned()
+    }
+
+    /// Derives a value from this one, see `LazyTransform::map`.
+    pub fn map<H, V>(&self, map: H) -> Mapped<'_, Self, H, V>
+    where
+        H: Fn(&U) -> V,
+    {
+        Mapped::new(self, map)
+    }
+
+    fn get_ctx<'g>(&'g self, guard: &'g Guard<'g>) -> Option<&'g ValueContext<U>> {
+        let (seq, parent_val) = self.parent.get_versioned(guard)?;
+
+        let mut cur = protect(guard, &self.cache, Ordering::Acquire);
+        // A newer result than ours can only come from a reader that saw a
+        // newer parent value, which is just as good.
+        if let Some(cur) = unsafe { cur.as_ref() }.filter(|cur| cur.seq >= seq) {
+            return Some(cur);
+        }
+
+        let new = self
+            .parent
+            .collector()
+            .link_boxed(ValueContext::new(seq, (self.map)(parent_val)));
+        loop {
+            match self
+                .cache
+                .compare_exchange(cur, new, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => {
+                    if !cur.is_null() {
+                        unsafe { guard.retire(cur, reclaim::boxed::<ValueContext<U>>) };
+                    }
+                    return Some(unsafe { &*new });
+                }
+                Err(actual) => {
+                    // The cache is never reset, so a failed CAS means it has
+                    // a value.
+                    let actual_ref = unsafe { &*actual };
+                    if actual_ref.seq >= seq {
+                        // SAFETY: new was never shared.
+                        unsafe { guard.retire(new, reclaim::boxed::<ValueContext<U>>) };
+                        return Some(actual_ref);
+                    }
+                    cur = actual;
+                }
+            }
+        }
+    }
+}
+
+impl<P, G, U> Versioned for Mapped<'_, P, G, U>
+where
+    P: Versioned,
+    G: Fn(&P::Value) -> U,
+{
+    type Value = U;
+
+    fn collector(&self) -> &Collector {
+        self.parent.collector()
+    }
+
+    fn get_versioned<'g>(&'g self, guard: &'g Guard<'g>) -> Option<(usize, &'g U)> {
+        Mapped::get_versioned(self, guard)
+    }
+}
+
+pub struct GuardedMapped<'a, P, G, U> {
+    guard: Guard<'a>,
+    mapped: &'a Mapped<'a, P, G, U>,
+}
+
+impl<P, G, U> GuardedMapped<'_, P, G, U>
+where
+    P: Versioned,
+    G: Fn(&P::Value) -> U,
+{
+    pub fn get(&self) -> Option<&U> {
+        self.mapped.get(&self.guard)
+    }
+
+    pub fn get_versioned(&self) -> Option<(usize, &U)> {
+        self.mapped.get_versioned(&self.guard)
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use crate::LazyTransform;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn recomputes_only_when_the_parent_changes() {
+        let calls = AtomicUsize::new(0);
+        let lt = LazyTransform::new(|s: &u32| s * 2);
+        let mapped = lt.map(|v: &u32| {
+            calls.fetch_add(1, Ordering::Relaxed);
+            v + 1
+        });
+        assert_eq!(mapped.guard().get(), None);
+
+        lt.set_source(1).unwrap();
+        assert_eq!(mapped.guard().get(), Some(&3));
+        assert_eq!(mapped.guard().get(), Some(&3));
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+
+        // Only the value computed from the latest source is seen.
+        lt.set_source(2).unwrap();
+        lt.set_source(3).unwrap();
+        assert_eq!(mapped.guard().get_versioned(), Some((3, &7)));
+        assert_eq!(mapped.get_cloned(), Some(7));
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn maps_can_be_chained() {
+        let lt = LazyTransform::new(|s: &&str| s.to_uppercase());
+        let len = lt.map(|s: &String| s.len());
+        let doubled = len.map(|n: &usize| n * 2);
+        let description = lt.map(|s: &String| format!("<{}>", s));
+
+        lt.set_source("abc").unwrap();
+        assert_eq!(doubled.get_cloned(), Some(6));
+        assert_eq!(description.get_cloned().as_deref(), Some("<ABC>"));
+
+        lt.set_source("abcd").unwrap();
+        let guard = doubled.guard();
+        assert_eq!(guard.get_versioned(), Some((2, &8)));
+    }
+
+    #[test]
+    fn concurrent_readers_see_consistent_values() {
+        const ROUNDS: usize = 1_000;
+        let lt = LazyTransform::new(|s: &usize| *s);
+        let mapped = lt.map(|v: &usize| (*v, v * 3));
+        lt.set_source(0).unwrap();
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                for i in 1..=ROUNDS {
+                    lt.set_source(i).unwrap();
+                }
+            });
+            for _ in 0..3 {
+                s.spawn(|| {
+                    let mut last_seq = 0;
+                    for _ in 0..ROUNDS {
+                        let guard = mapped.guard();
+                        let (seq, &(v, tripled)) = guard.get_versioned().unwrap();
+                        assert_eq!(tripled, v * 3);
+                        assert!(seq >= last_seq);
+                        last_seq = seq;
+                    }
+                });
+            }
+        });
+
+        assert_eq!(mapped.get_cloned(), Some((ROUNDS, ROUNDS * 3)));
+    }
+
+    #[test]
+    fn drops_cached_value_with_the_map() {
+        let val = Arc::new(());
+        let lt = LazyTransform::new(|_: &()| ());
+        let mapped = lt.map(|_: &()| Arc::clone(&val));
+        lt.set_source(()).unwrap();
+        mapped.get_cloned();
+
+        drop(mapped);
+        assert_eq!(Arc::strong_count(&val), 1);
+    }
+}
@@ -0,0 +1,349 @@
+// A LazyTransform fed by several named sources. Each set_source replaces the
+// source of one key, and the transform gets a snapshot of the newest source
+// of every key.
+//
+// The snapshots are immutable, and a new one is swapped in with a CAS for
+// every set_source, so concurrent set_source calls for different keys never
+// lose each other's sources. Each source carries the seq of the set_source
+// that provided it, so the seqs of a snapshot form a vector clock of it.
+//
+// Laziness is left to an inner LazyTransform with () as its source: once a
+// snapshot is stored, setting its source marks the value as outdated. The
+// transform then reads whatever snapshot is current when it runs, which
+// includes every source stored before the set_source that triggered it.
+use std::borrow::Borrow;
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use seize::{reclaim, Collector, Guard, Linked};
+
+use crate::{LazyTransform, Mapped, Metrics, ValueContext, Versioned};
+
+pub struct MultiSourceLazyTransform<K, V, F, T> {
+    inner: LazyTransform<F, (), T>,
+    sources: AtomicPtr<Linked<Sources<K, V>>>,
+    seq_counter: AtomicUsize,
+}
+
+/// The newest source of every key, as seen by the transform.
+pub struct Sources<K, V> {
+    entries: BTreeMap<K, Entry<V>>,
+}
+
+struct Entry<V> {
+    seq: usize,
+    // Shared between the snapshots, which are copied on every set_source.
+    value: Arc<V>,
+}
+
+impl<V> Clone for Entry<V> {
+    fn clone(&self) -> Self {
+        Self {
+            seq: self.seq,
+            value: Arc::clone(&self.value),
+        }
+    }
+}
+
+impl<K: Ord, V> Sources<K, V> {
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.entries.get(key).map(|e| &*e.value)
+    }
+
+    /// The seq of the set_source that provided the source of `key`. Seqs are
+    /// shared by all keys, so a source with a higher seq was set later.
+    pub fn seq<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.entries.get(key).map(|e| e.seq)
+    }
+
+    /// The sources in the order of their keys.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.entries.iter().map(|(k, e)| (k, &*e.value))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl<K, V, F, T> Drop for MultiSourceLazyTransform<K, V, F, T> {
+    fn drop(&mut self) {
+        // SAFETY: nobody else has a reference to self anymore.
+        let guard = unsafe { Guard::unprotected() };
+        let sources = guard.protect(&self.sources, Ordering::Relaxed);
+        if !sources.is_null() {
+            unsafe { guard.retire(sources, reclaim::boxed::<Sources<K, V>>) };
+        }
+    }
+}
+
+impl<K, V, F, T> MultiSourceLazyTransform<K, V, F, T>
+where
+    K: Ord + Clone,
+    F: Fn(&Sources<K, V>) -> T,
+{
+    pub fn new(transform: F) -> Self {
+        Self {
+            inner: LazyTransform::with_transform(transform),
+            sources: AtomicPtr::default(),
+            seq_counter: AtomicUsize::new(0),
+        }
+    }
+
+    /// See `LazyTransform::with_collector`.
+    pub fn with_collector(mut self, collector: Arc<Collector>) -> Self
+    where
+        K: 'static,
+        V: 'static,
+        T: 'static,
+    {
+        // Nothing was linked into the old collector yet.
+        self.inner.collector = collector;
+        self
+    }
+
+    /// Replaces the source of `key`, leaving the other keys alone.
+    pub fn set_source(&self, key: K, source: V) {
+        let seq = self.seq_counter.fetch_add(1, Ordering::AcqRel) + 1;
+        let new_entry = Entry {
+            seq,
+            value: Arc::new(source),
+        };
+
+        let collector = &self.inner.collector;
+        let guard = collector.enter();
+        let mut cur = guard.protect(&self.sources, Ordering::Acquire);
+
+        loop {
+            let mut entries = match unsafe { cur.as_ref() } {
+                Some(cur) => cur.entries.clone(),
+                None => BTreeMap::new(),
+            };
+            if entries.get(&key).is_some_and(|e| e.seq > seq) {
+                // A newer source of the same key made it in first.
+                return;
+            }
+            entries.insert(key.clone(), new_entry.clone());
+
+            let new = collector.link_boxed(Sources { entries });
+            match self
+                .sources
+                .compare_exchange(cur, new, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => {
+                    if !cur.is_null() {
+                        unsafe { guard.retire(cur, reclaim::boxed::<Sources<K, V>>) };
+                    }
+                    break;
+                }
+                Err(actual) => {
+                    // SAFETY: new was never shared.
+                    unsafe { drop(Box::from_raw(new)) };
+                    cur = actual;
+                }
+            }
+        }
+
+        // Only now that the snapshot has the new source, the value can be
+        // marked as outdated. The inner policy is Latest, which never turns a
+        // source down.
+        let _ = self.inner.set_source(());
+    }
+
+    /// Makes the next `get` transform the current snapshot again.
+    pub fn invalidate(&self) -> bool {
+        self.inner.invalidate()
+    }
+
+    pub fn guard(&self) -> GuardedMultiSourceLazyTransform<'_, K, V, F, T> {
+        let guard = self.inner.collector.enter();
+        GuardedMultiSourceLazyTransform { guard, mt: self }
+    }
+
+    pub fn get<'g>(&self, guard: &'g Guard<'g>) -> Option<&'g T> {
+        self.get_ctx(guard).map(|ctx| &*ctx.val)
+    }
+
+    /// Like `get`, but also returns a sequence number that increases with
+    /// every new value, see `LazyTransform::get_versioned`. It's unrelated to
+    /// the seqs of the sources.
+    pub fn get_versioned<'g>(&self, guard: &'g Guard<'g>) -> Option<(usize, &'g T)> {
+        self.get_ctx(guard).map(|ctx| (ctx.seq, &*ctx.val))
+    }
+
+    pub fn get_cloned(&self) -> Option<T>
+    where
+        T: Clone,
+    {
+        let guard = self.inner.collector.enter();
+        self.get(&guard).cloned()
+    }
+
+    pub fn get_arc(&self) -> Option<Arc<T>> {
+        let guard = self.inner.collector.enter();
+        self.get_ctx(&guard).map(|ctx| Arc::clone(&ctx.val))
+    }
+
+    pub fn metrics(&self) -> Metrics {
+        self.inner.metrics()
+    }
+
+    /// Derives a value from this one, see `LazyTransform::map`.
+    pub fn map<G, U>(&self, map: G) -> Mapped<'_, Self, G, U>
+    where
+        G: Fn(&T) -> U,
+    {
+        Mapped::new(self, map)
+    }
+
+    fn get_ctx<'g>(&self, guard: &'g Guard<'g>) -> Option<&'g ValueContext<T>> {
+        let res: Result<_, Infallible> = self.inner.get_with(guard, |_, _| {
+            let sources = guard.protect(&self.sources, Ordering::Acquire);
+            // The inner source is only set after a snapshot was stored.
+            let sources: &Sources<K, V> = unsafe { &*sources };
+            Ok(Some(Arc::new((self.inner.transform)(sources))))
+        });
+        match res {
+            Ok(val) => val,
+        }
+    }
+}
+
+impl<K, V, F, T> Versioned for MultiSourceLazyTransform<K, V, F, T>
+where
+    K: Ord + Clone,
+    F: Fn(&Sources<K, V>) -> T,
+{
+    type Value = T;
+
+    fn collector(&self) -> &Collector {
+        &self.inner.collector
+    }
+
+    fn get_versioned<'g>(&'g self, guard: &'g Guard<'g>) -> Option<(usize, &'g T)> {
+        MultiSourceLazyTransform::get_versioned(self, guard)
+    }
+}
+
+pub struct GuardedMultiSourceLazyTransform<'a, K, V, F, T> {
+    guard: Guard<'a>,
+    mt: &'a MultiSourceLazyTransform<K, V, F, T>,
+}
+
+impl<K, V, F, T> GuardedMultiSourceLazyTransform<'_, K, V, F, T>
+where
+    K: Ord + Clone,
+    F: Fn(&Sources<K, V>) -> T,
+{
+    pub fn get(&self) -> Option<&T> {
+        self.mt.get(&self.guard)
+    }
+
+    pub fn get_versioned(&self) -> Option<(usize, &T)> {
+        self.mt.get_versioned(&self.guard)
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn sum(sources: &Sources<&'static str, usize>) -> usize {
+        sources.iter().map(|(_, v)| v).sum()
+    }
+
+    #[test]
+    fn transforms_newest_source_of_every_key() {
+        let mt = MultiSourceLazyTransform::new(sum);
+        assert_eq!(mt.guard().get(), None);
+
+        mt.set_source("a", 1);
+        mt.set_source("b", 10);
+        assert_eq!(mt.guard().get(), Some(&11));
+
+        mt.set_source("a", 2);
+        assert_eq!(mt.guard().get(), Some(&12));
+        assert_eq!(mt.get_cloned(), Some(12));
+        assert_eq!(mt.metrics().transforms_performed, 2);
+    }
+
+    #[test]
+    fn seqs_form_a_vector_clock() {
+        let mt = MultiSourceLazyTransform::new(|sources: &Sources<&str, ()>| {
+            (sources.seq("a"), sources.seq("b"), sources.len())
+        });
+
+        mt.set_source("a", ());
+        mt.set_source("b", ());
+        mt.set_source("a", ());
+        assert_eq!(mt.get_cloned(), Some((Some(3), Some(2), 2)));
+    }
+
+    #[test]
+    fn values_can_be_mapped() {
+        let mt = MultiSourceLazyTransform::new(sum);
+        let doubled = mt.map(|total: &usize| total * 2);
+        assert_eq!(doubled.get_cloned(), None);
+
+        mt.set_source("a", 1);
+        mt.set_source("b", 10);
+        assert_eq!(doubled.get_cloned(), Some(22));
+        let (seq, _) = mt.guard().get_versioned().unwrap();
+
+        mt.set_source("b", 20);
+        let guard = doubled.guard();
+        let (new_seq, val) = guard.get_versioned().unwrap();
+        assert!(new_seq > seq);
+        assert_eq!(val, &42);
+    }
+
+    #[test]
+    fn concurrent_sources_of_different_keys_are_all_kept() {
+        const KEYS: usize = 8;
+        const ROUNDS: usize = 200;
+        let mt = MultiSourceLazyTransform::new(|sources: &Sources<usize, usize>| {
+            sources.iter().map(|(_, v)| v).sum::<usize>()
+        });
+
+        thread::scope(|s| {
+            for key in 0..KEYS {
+                let mt = &mt;
+                s.spawn(move || {
+                    for round in 1..=ROUNDS {
+                        mt.set_source(key, round);
+                        mt.guard().get();
+                    }
+                });
+            }
+        });
+
+        assert_eq!(mt.get_cloned(), Some(KEYS * ROUNDS));
+    }
+
+    #[test]
+    fn drops_sources_with_the_transform() {
+        let source = Arc::new(());
+        let mt = MultiSourceLazyTransform::new(|sources: &Sources<u8, Arc<()>>| sources.len());
+        mt.set_source(1, Arc::clone(&source));
+        mt.set_source(2, Arc::clone(&source));
+        assert_eq!(mt.get_arc().as_deref(), Some(&2));
+
+        drop(mt);
+        assert_eq!(Arc::strong_count(&source), 1);
+    }
+}
@@ -0,0 +1,93 @@
+// Saving the last value across restarts. A restored value is served like a
+// transformed one until a newer source is transformed, so the first get after
+// a restart doesn't have to run the transform.
+//
+// Seqs only mean something within an instance, but the seq of a restored
+// value has to stay comparable to the seqs of the sources set after it. So
+// restore moves the seq counter past it, as if as many sources had been set.
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::sync::protect;
+use crate::LazyTransform;
+
+/// The last value of a LazyTransform, as saved by `snapshot`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot<T> {
+    pub seq: u64,
+    pub value: T,
+}
+
+impl<F, S, T> LazyTransform<F, S, T> {
+    /// Returns the last transformed value, without transforming a pending
+    /// source first. None if there's no value yet.
+    pub fn snapshot(&self) -> Option<Snapshot<T>>
+    where
+        T: Clone,
+    {
+        let guard = self.collector.enter();
+        let val_ctx = protect(&guard, &self.val_ctx, Ordering::Acquire);
+        let val_ctx = unsafe { val_ctx.as_ref() }?;
+        Some(Snapshot {
+            seq: val_ctx.seq as u64,
+            value: T::clone(&val_ctx.val),
+        })
+    }
+
+    /// Serves the value of `snapshot` until a source set after this call is
+    /// transformed. Meant to be called right after creating the instance:
+    /// returns false and drops the value if a source with the same or a
+    /// higher seq was already set.
+    pub fn restore(&self, snapshot: Snapshot<T>) -> bool {
+        let seq = snapshot.seq as usize;
+        if self.seq_counter.fetch_max(seq, Ordering::AcqRel) >= seq {
+            return false;
+        }
+
+        // No source can have our seq: the ones set before had lower seqs,
+        // and the ones set from now on get higher ones.
+        let guard = self.collector.enter();
+        self.store_val(&guard, seq, Arc::new(snapshot.value));
+        true
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restored_value_is_served_without_transform() {
+        let lt = LazyTransform::new(|s: &String| s.len());
+        assert_eq!(lt.snapshot(), None);
+        lt.set_source("abc".to_owned()).unwrap();
+        lt.set_source("abcd".to_owned()).unwrap();
+        lt.get_cloned();
+
+        let saved = serde_json::to_string(&lt.snapshot().unwrap()).unwrap();
+        drop(lt);
+
+        let lt = LazyTransform::new(|_: &String| -> usize { panic!("transformed") });
+        let snapshot: Snapshot<usize> = serde_json::from_str(&saved).unwrap();
+        assert_eq!(snapshot, Snapshot { seq: 2, value: 4 });
+        assert!(lt.restore(snapshot));
+        assert_eq!(lt.guard().get_versioned(), Some((2, &4)));
+        assert_eq!(lt.metrics().transforms_performed, 0);
+    }
+
+    #[test]
+    fn newer_sources_replace_restored_value() {
+        let lt = LazyTransform::new(|s: &u32| s * 10);
+        assert!(lt.restore(Snapshot { seq: 7, value: 1 }));
+        assert_eq!(lt.get_cloned(), Some(1));
+
+        lt.set_source(2).unwrap();
+        assert_eq!(lt.guard().get_versioned(), Some((8, &20)));
+
+        // Too old by now.
+        assert!(!lt.restore(Snapshot { seq: 8, value: 3 }));
+        assert_eq!(lt.get_cloned(), Some(20));
+    }
+}
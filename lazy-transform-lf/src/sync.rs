@@ -0,0 +1,40 @@
+// The atomics of the CAS protocol between set_source, take_source and
+// store_val. Building with `--cfg loom` swaps them for loom's, so that
+// tests/loom.rs can model-check their interleavings.
+//
+// seize keeps using std atomics internally, which loom doesn't see. That's
+// fine as long as the collector never depends on the interleaving, so under
+// loom it's created without epoch tracking: protecting a pointer is then a
+// plain load, and nothing retired while a guard is held is reclaimed before
+// the guard is dropped.
+use std::sync::atomic::Ordering;
+
+use seize::{Collector, Guard, Linked};
+
+#[cfg(loom)]
+pub(crate) use loom::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize};
+#[cfg(not(loom))]
+pub(crate) use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize};
+
+pub(crate) fn new_collector() -> Collector {
+    #[cfg(loom)]
+    return Collector::new().epoch_frequency(None);
+    #[cfg(not(loom))]
+    return Collector::new();
+}
+
+// Guard::protect for the atomics above.
+pub(crate) fn protect<T>(
+    guard: &Guard<'_>,
+    ptr: &AtomicPtr<Linked<T>>,
+    ordering: Ordering,
+) -> *mut Linked<T> {
+    // Without epoch tracking, that's all Guard::protect does as well.
+    #[cfg(loom)]
+    {
+        let _ = guard;
+        ptr.load(ordering)
+    }
+    #[cfg(not(loom))]
+    guard.protect(ptr, ordering)
+}
@@ -0,0 +1,122 @@
+// Parks readers (threads or tasks) until a LazyTransform has something new
+// to offer. The hot paths (set_source and store_val) only pay for an atomic
+// load and a fence unless somebody is actually waiting.
+use std::mem;
+use std::sync::atomic::{fence, AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::task::Waker;
+use std::time::Instant;
+
+pub(crate) struct Waiters {
+    count: AtomicUsize,
+    state: Mutex<State>,
+    cond: Condvar,
+}
+
+struct State {
+    // Bumped on every notification that happens while someone is waiting, so
+    // waiters can tell a real wakeup from a spurious one.
+    generation: u64,
+    // Tasks are woken once and have to register again if they're still
+    // interested.
+    wakers: Vec<Waker>,
+}
+
+// Keeps the waiter counted until it's dropped, even if the reader panics
+// (e.g. in the transform) halfway through.
+pub(crate) struct Registration<'a> {
+    waiters: &'a Waiters,
+}
+
+impl Waiters {
+    pub(crate) fn new() -> Self {
+        Self {
+            count: AtomicUsize::new(0),
+            state: Mutex::new(State {
+                generation: 0,
+                wakers: Vec::new(),
+            }),
+            cond: Condvar::new(),
+        }
+    }
+
+    // Must be called before checking for a value. Together with the fence in
+    // notify, either the notifier sees the waiter or the waiter sees whatever
+    // was published before the notification.
+    pub(crate) fn register(&self) -> Registration<'_> {
+        self.count.fetch_add(1, Ordering::SeqCst);
+        Registration { waiters: self }
+    }
+
+    pub(crate) fn notify(&self) {
+        fence(Ordering::SeqCst);
+        if self.count.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+
+        let wakers = {
+            let mut state = self.lock();
+            state.generation += 1;
+            mem::take(&mut state.wakers)
+        };
+        self.cond.notify_all();
+        wakers.into_iter().for_each(Waker::wake);
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+}
+
+impl Registration<'_> {
+    pub(crate) fn generation(&self) -> u64 {
+        self.waiters.lock().generation
+    }
+
+    // Blocks until there was a notification after `seen` was read. Returns
+    // false if the deadline passed first.
+    pub(crate) fn wait(&self, seen: u64, deadline: Instant) -> bool {
+        let mut state = self.waiters.lock();
+        while state.generation == seen {
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            state = self
+                .waiters
+                .cond
+                .wait_timeout(state, deadline - now)
+                .unwrap()
+                .0;
+        }
+        true
+    }
+
+    // Like wait, without a deadline.
+    pub(crate) fn wait_forever(&self, seen: u64) {
+        let mut state = self.waiters.lock();
+        while state.generation == seen {
+            state = self.waiters.cond.wait(state).unwrap();
+        }
+    }
+
+    // The async counterpart of wait. `ready` is checked under the lock, so a
+    // notification either happens before it and is observed by `ready`, or
+    // after it and wakes `waker`. Returns the result of `ready`.
+    pub(crate) fn wake_on_notify(&self, waker: &Waker, ready: impl FnOnce() -> bool) -> bool {
+        let mut state = self.waiters.lock();
+        if ready() {
+            return true;
+        }
+        if !state.wakers.iter().any(|w| w.will_wake(waker)) {
+            state.wakers.push(waker.clone());
+        }
+        false
+    }
+}
+
+impl Drop for Registration<'_> {
+    fn drop(&mut self) {
+        self.waiters.count.fetch_sub(1, Ordering::SeqCst);
+    }
+}
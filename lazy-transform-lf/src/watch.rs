@@ -0,0 +1,94 @@
+// Change notifications for LazyTransform. A Watcher remembers the sequence
+// number of the last source it has seen, and reports when a newer one was
+// set. Values are still transformed lazily, so after a change the watcher's
+// owner calls `get` to get (and possibly compute) the new value.
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use crate::waiters::Registration;
+use crate::LazyTransform;
+
+pub struct Watcher<'a, F, S, T> {
+    lt: &'a LazyTransform<F, S, T>,
+    seen: usize,
+}
+
+impl<'a, F, S, T> Watcher<'a, F, S, T> {
+    pub(crate) fn new(lt: &'a LazyTransform<F, S, T>) -> Self {
+        Self {
+            lt,
+            seen: lt.source_seq(),
+        }
+    }
+
+    /// The sequence number of the last source this watcher has seen. It's 0
+    /// if there was no source when it subscribed.
+    pub fn seen(&self) -> usize {
+        self.seen
+    }
+
+    /// Returns true if a newer source has been set since the last change
+    /// this watcher has seen. Doesn't mark it as seen.
+    pub fn has_changed(&self) -> bool {
+        self.lt.source_seq() > self.seen
+    }
+
+    /// Marks the current source as seen and returns its sequence number.
+    pub fn mark_seen(&mut self) -> usize {
+        self.seen = self.seen.max(self.lt.source_seq());
+        self.seen
+    }
+
+    /// Blocks until a newer source is set and returns its sequence number,
+    /// or None if `timeout` passes first.
+    pub fn wait_changed(&mut self, timeout: Duration) -> Option<usize> {
+        let deadline = Instant::now() + timeout;
+        let registration = self.lt.waiters.register();
+
+        loop {
+            let generation = registration.generation();
+            if self.has_changed() {
+                return Some(self.mark_seen());
+            }
+            if !registration.wait(generation, deadline) {
+                return None;
+            }
+        }
+    }
+
+    /// Returns a future that resolves to the sequence number of the next
+    /// source that's set, or right away if that has already happened.
+    pub fn changed(&mut self) -> Changed<'_, 'a, F, S, T> {
+        Changed {
+            watcher: self,
+            registration: None,
+        }
+    }
+}
+
+pub struct Changed<'w, 'a, F, S, T> {
+    watcher: &'w mut Watcher<'a, F, S, T>,
+    // Registered on the first poll and dropped with the future, so notifiers
+    // only take the lock while somebody is interested.
+    registration: Option<Registration<'a>>,
+}
+
+impl<F, S, T> Future for Changed<'_, '_, F, S, T> {
+    type Output = usize;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let lt = this.watcher.lt;
+        let registration = this
+            .registration
+            .get_or_insert_with(|| lt.waiters.register());
+
+        if registration.wake_on_notify(cx.waker(), || this.watcher.has_changed()) {
+            Poll::Ready(this.watcher.mark_seen())
+        } else {
+            Poll::Pending
+        }
+    }
+}
@@ -0,0 +1,113 @@
+//! Model checks the CAS protocol between set_source, take_source and
+//! store_val with loom, which runs every interleaving of the atomic
+//! operations and so proves that the asserts on the seqs hold, instead of
+//! hoping a stress test happens to hit the bad interleavings.
+//!
+//! The atomics are only swapped for loom's under `--cfg loom`:
+//!
+//!     RUSTFLAGS="--cfg loom" cargo test -p lazy-transform-lf --release --test loom
+#![cfg(loom)]
+
+use loom::sync::Arc;
+use loom::{model, thread};
+
+use lazy_transform_lf::LazyTransform;
+
+type Lt = LazyTransform<fn(&usize) -> usize, usize, usize>;
+
+fn times_ten(source: &usize) -> usize {
+    source * 10
+}
+
+fn get(lt: &Lt) -> Option<usize> {
+    lt.guard().get().copied()
+}
+
+#[test]
+fn set_source_races_get() {
+    model(|| {
+        let lt: Arc<Lt> = Arc::new(LazyTransform::new(times_ten));
+        lt.set_source(1).unwrap();
+
+        let setter = thread::spawn({
+            let lt = Arc::clone(&lt);
+            move || lt.set_source(2)
+        });
+        // Either source may be transformed, but there's always a value.
+        let seen = get(&lt);
+        assert!(matches!(seen, Some(10) | Some(20)), "{:?}", seen);
+        setter.join().unwrap().unwrap();
+
+        assert_eq!(get(&lt), Some(20));
+    });
+}
+
+#[test]
+fn getters_race_on_take_source() {
+    model(|| {
+        let lt: Arc<Lt> = Arc::new(LazyTransform::new(times_ten));
+        lt.set_source(1).unwrap();
+
+        let getter = thread::spawn({
+            let lt = Arc::clone(&lt);
+            move || get(&lt)
+        });
+        let seen = get(&lt);
+        let other = getter.join().unwrap();
+
+        // The loser of take_source reads the value, which isn't stored yet
+        // if the winner is still transforming.
+        assert!(matches!(seen, None | Some(10)), "{:?}", seen);
+        assert!(matches!(other, None | Some(10)), "{:?}", other);
+        assert!(seen.is_some() || other.is_some());
+        assert_eq!(lt.metrics().transforms_performed, 1);
+        assert_eq!(get(&lt), Some(10));
+    });
+}
+
+#[test]
+fn concurrent_set_sources_keep_the_newest() {
+    model(|| {
+        let lt: Arc<Lt> = Arc::new(LazyTransform::new(times_ten));
+
+        let setter = thread::spawn({
+            let lt = Arc::clone(&lt);
+            move || {
+                lt.set_source(1).unwrap();
+                get(&lt)
+            }
+        });
+        lt.set_source(2).unwrap();
+        let seen = get(&lt);
+        let other = setter.join().unwrap();
+        // As with racing getters, one of them may find the other still
+        // transforming.
+        assert!(matches!(seen, None | Some(10) | Some(20)), "{:?}", seen);
+        assert!(matches!(other, None | Some(10) | Some(20)), "{:?}", other);
+
+        // Whichever source got the higher seq wins, and once it's stored
+        // nothing older replaces it.
+        let (seq, &val) = lt.guard().get_versioned().unwrap();
+        assert_eq!(seq, 2);
+        assert_eq!(get(&lt), Some(val));
+    });
+}
+
+#[test]
+fn invalidate_races_set_source() {
+    model(|| {
+        let lt: Arc<Lt> = Arc::new(LazyTransform::new(times_ten));
+        lt.set_source(1).unwrap();
+        assert_eq!(get(&lt), Some(10));
+
+        let setter = thread::spawn({
+            let lt = Arc::clone(&lt);
+            move || lt.set_source(2)
+        });
+        assert!(lt.invalidate());
+        setter.join().unwrap().unwrap();
+
+        // An invalidation must never bring back the source it replaced.
+        assert_eq!(get(&lt), Some(20));
+    });
+}
@@ -0,0 +1,192 @@
+//! A handle on the value of a `ManualFuture` that any number of tasks can
+//! await. Every clone of a `Completion` resolves to (a clone of) the same
+//! value, so a test can hand one completer's result to several tasks:
+//!
+//! ```ignore
+//! let (completion, completer) = Completion::pending();
+//! let a = tokio::spawn(completion.clone().into_future());
+//! completer.complete(7);
+//! assert_eq!(completion.await, 7);
+//! ```
+//!
+//! The underlying future is polled by whichever waiter gets there first, and
+//! the others are woken once it resolved.
+use std::collections::HashMap;
+use std::future::{Future, IntoFuture};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use crate::man::{Completer, ManualFuture};
+
+pub struct Completion<T> {
+    shared: Arc<Mutex<Shared<T>>>,
+}
+
+struct Shared<T> {
+    // Taken once it resolved, the value is kept below then.
+    fut: Option<ManualFuture<T>>,
+    value: Option<T>,
+    // The waiters that are pending, by their id.
+    wakers: HashMap<u64, Waker>,
+    next_id: u64,
+}
+
+/// The future of a `Completion`, see `IntoFuture`.
+pub struct Wait<T> {
+    shared: Arc<Mutex<Shared<T>>>,
+    // Set while registered in `wakers`.
+    id: Option<u64>,
+}
+
+impl<T> Completion<T> {
+    /// Creates a completion whose value is provided by the `Completer`.
+    pub fn pending() -> (Self, Completer<T>) {
+        let (fut, completer) = ManualFuture::pending();
+        (fut.into(), completer)
+    }
+}
+
+impl<T> From<ManualFuture<T>> for Completion<T> {
+    fn from(fut: ManualFuture<T>) -> Self {
+        Self {
+            shared: Arc::new(Mutex::new(Shared {
+                fut: Some(fut),
+                value: None,
+                wakers: HashMap::new(),
+                next_id: 0,
+            })),
+        }
+    }
+}
+
+impl<T> Clone for Completion<T> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<T: Clone + Send + 'static> IntoFuture for Completion<T> {
+    type Output = T;
+    type IntoFuture = Wait<T>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        Wait {
+            shared: self.shared,
+            id: None,
+        }
+    }
+}
+
+impl<T: Clone + Send + 'static> IntoFuture for &Completion<T> {
+    type Output = T;
+    type IntoFuture = Wait<T>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        self.clone().into_future()
+    }
+}
+
+impl<T: Clone + Send + 'static> Future for Wait<T> {
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let shared = Arc::clone(&self.shared);
+        let mut shared = shared.lock().unwrap();
+        if let Some(val) = &shared.value {
+            let val = val.clone();
+            self.unregister(&mut shared);
+            return Poll::Ready(val);
+        }
+
+        // The future only keeps the waker of the last poll, which makes us
+        // responsible for waking the others.
+        let fut = shared.fut.as_mut().expect("no value and no future");
+        match Pin::new(fut).poll(cx) {
+            Poll::Ready(val) => {
+                shared.fut = None;
+                shared.value = Some(val.clone());
+                self.unregister(&mut shared);
+                for (_, waker) in shared.wakers.drain() {
+                    waker.wake();
+                }
+                Poll::Ready(val)
+            }
+            Poll::Pending => {
+                let id = *self.id.get_or_insert_with(|| {
+                    shared.next_id += 1;
+                    shared.next_id
+                });
+                shared.wakers.insert(id, cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<T> Wait<T> {
+    fn unregister(&mut self, shared: &mut Shared<T>) {
+        if let Some(id) = self.id.take() {
+            shared.wakers.remove(&id);
+        }
+    }
+}
+
+impl<T> Drop for Wait<T> {
+    fn drop(&mut self) {
+        let Some(id) = self.id else {
+            return;
+        };
+        let mut shared = self.shared.lock().unwrap();
+        shared.wakers.remove(&id);
+        // We may have been the one the future would wake, so the others poll
+        // it again to register themselves.
+        for waker in shared.wakers.values() {
+            waker.wake_by_ref();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::time;
+
+    #[tokio::test]
+    async fn every_clone_gets_the_value() {
+        let (completion, completer) = Completion::pending();
+
+        let waiters: Vec<_> = (0..4)
+            .map(|_| tokio::spawn(completion.clone().into_future()))
+            .collect();
+        time::sleep(Duration::from_millis(10)).await;
+        assert!(completer.complete("value".to_owned()));
+
+        for waiter in waiters {
+            assert_eq!(waiter.await.unwrap(), "value");
+        }
+        // Also after the fact, and through a reference.
+        assert_eq!((&completion).await, "value");
+        assert_eq!(completion.await, "value");
+    }
+
+    #[tokio::test]
+    async fn dropped_waiters_dont_strand_the_others() {
+        let (completion, completer) = Completion::pending();
+
+        let first = tokio::spawn(completion.clone().into_future());
+        time::sleep(Duration::from_millis(10)).await;
+        let second = tokio::spawn(completion.clone().into_future());
+        time::sleep(Duration::from_millis(10)).await;
+        // The future would wake the second one.
+        second.abort();
+        let _ = second.await;
+
+        completer.complete(7);
+        let val = time::timeout(Duration::from_secs(5), first).await;
+        assert_eq!(val.unwrap().unwrap(), 7);
+    }
+}
@@ -0,0 +1,5 @@
+pub mod completion;
+pub mod man;
+mod timer;
+#[cfg(feature = "trace")]
+pub mod trace;
@@ -1,9 +1,6 @@
 use tokio::time::{self, Duration};
 
-use tokio;
-
-mod man;
-use man::ManualFuture;
+use manfut::man::ManualFuture;
 
 #[tokio::main]
 async fn main() {
@@ -1,56 +1,210 @@
-use std::future::Future;
+use std::future::{self, Future};
 use std::pin::Pin;
 use std::sync::{mpsc, Arc, Mutex};
 use std::task::{Context, Poll, Waker};
 use std::thread;
+use std::time::{Duration, Instant};
+
+use cancel_token::CancellationToken;
+
+use crate::timer::{self, EntryId};
+#[cfg(feature = "trace")]
+use crate::trace::{Event, StateName, Trace};
 
 pub struct ManualFuture<T> {
-    val: Option<T>,
-    inner: Arc<Mutex<ManualFutureInner>>,
-    // Receive halve is given to the thread to wait for ready signal.
-    ready_rx: Option<mpsc::Receiver<()>>,
+    inner: Arc<Mutex<ManualFutureInner<T>>>,
+    // Receive halve is given to the thread to wait for the resolved value.
+    ready_rx: Option<mpsc::Receiver<T>>,
+    // Cancelled along with the on_cancel cleanups, see `cancellation_token`.
+    cancel: CancellationToken,
 }
 
-struct ManualFutureInner {
-    state: State,
+struct ManualFutureInner<T> {
+    state: State<T>,
     waker: Option<Waker>,
+    // Set when completion was scheduled on the shared timer, so that dropping
+    // the future can cancel it instead of leaving it around until the deadline.
+    timer: Option<EntryId>,
+    dropped: bool,
+    // Set by the Completer that provided the value. Checked along with
+    // `dropped`, so either the value is provided or on_cancel runs.
+    completed: bool,
+    on_cancel: Vec<Box<dyn FnOnce() + Send>>,
+    #[cfg(feature = "trace")]
+    trace: Trace,
 }
 
 // impl<T> Unpin for ManualFuture<T> {}
 
-enum State {
+enum State<T> {
     NotReady,
-    Ready,
+    Ready(T),
     Consumed,
 }
 
-// TODO: allow determinning the final resolved value to be sent via ready.
+/// The sending half of a `ManualFuture` created with `ManualFuture::pending`.
+/// It decides both when the future resolves and the value it resolves to.
+pub struct Completer<T> {
+    tx: mpsc::Sender<T>,
+    inner: Arc<Mutex<ManualFutureInner<T>>>,
+}
 
 impl<T> ManualFuture<T> {
     pub fn new(val: T) -> (Self, impl FnOnce()) {
+        let (fut, completer) = Self::pending();
+
+        let ready = move || match completer.complete(val) {
+            true => println!("successfully sent ready signal"),
+            false => println!("ERROR failed to send ready signal ERROR"),
+        };
+
+        (fut, ready)
+    }
+
+    /// Creates a future whose value is only provided later via the `Completer`.
+    pub fn pending() -> (Self, Completer<T>) {
         let (tx, rx) = mpsc::channel();
 
-        let inner = ManualFutureInner {
+        let inner = Arc::new(Mutex::new(ManualFutureInner {
             state: State::NotReady,
             waker: None,
-        };
+            timer: None,
+            dropped: false,
+            completed: false,
+            on_cancel: vec![],
+            #[cfg(feature = "trace")]
+            trace: Trace::new(),
+        }));
 
         let fut = ManualFuture {
-            val: Some(val),
-            inner: Arc::new(Mutex::new(inner)),
+            inner: inner.clone(),
             ready_rx: Some(rx),
+            cancel: CancellationToken::new(),
         };
 
-        let ready = move || match tx.send(()) {
-            Ok(_) => println!("successfully sent ready signal"),
-            Err(_) => println!("ERROR failed to send ready signal ERROR"),
+        (fut, Completer { tx, inner })
+    }
+
+    /// Registers `f` to run when the future is dropped before it was
+    /// completed, e.g. because its task was aborted. It runs on the thread
+    /// that drops the future, at most once. If the future was already
+    /// completed, `f` is just dropped along with it.
+    pub fn on_cancel(&self, f: impl FnOnce() + Send + 'static) {
+        self.inner.lock().unwrap().on_cancel.push(Box::new(f));
+    }
+
+    /// Returns a token that is cancelled when the future is dropped before it
+    /// was completed, i.e. whenever the `on_cancel` cleanups run. Work feeding
+    /// the `Completer` can check it, or wait on it, to stop early.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.cancel.child_token()
+    }
+}
+
+impl<T> ManualFuture<T> {
+    /// A future that's polled by calling `f`, like `std::future::poll_fn`,
+    /// for ad hoc futures in tests.
+    pub fn from_fn<F>(f: F) -> future::PollFn<F>
+    where
+        F: FnMut(&mut Context<'_>) -> Poll<T>,
+    {
+        future::poll_fn(f)
+    }
+
+    /// A future that returns `Pending` for its first `polls` polls, waking
+    /// itself right away every time, and then resolves to `val`. Handy to
+    /// check that a combinator or executor keeps polling.
+    pub fn pending_for(
+        polls: usize,
+        val: T,
+    ) -> future::PollFn<impl FnMut(&mut Context<'_>) -> Poll<T>> {
+        let mut polls = polls;
+        let mut val = Some(val);
+        future::poll_fn(move |cx| {
+            if polls == 0 {
+                return Poll::Ready(val.take().expect("polled after completion"));
+            }
+            polls -= 1;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        })
+    }
+}
+
+#[cfg(feature = "trace")]
+impl<T> ManualFuture<T> {
+    /// Returns what has happened to the future so far. The future can be
+    /// awaited through `&mut` to get its trace afterwards.
+    pub fn trace(&self) -> Trace {
+        self.inner.lock().unwrap().trace.clone()
+    }
+}
+
+impl<T> Drop for ManualFuture<T> {
+    fn drop(&mut self) {
+        let (cancelled, on_cancel) = {
+            let mut inner = self.inner.lock().unwrap();
+            inner.dropped = true;
+
+            // Cancelling drops the scheduled sender, so the thread waiting on
+            // the receive halve (if poll was ever called) wakes up with an
+            // error and exits right away instead of at the deadline.
+            if let Some(id) = inner.timer.take() {
+                timer::cancel(id);
+            }
+
+            if inner.completed {
+                (false, vec![])
+            } else {
+                (true, std::mem::take(&mut inner.on_cancel))
+            }
         };
 
-        (fut, ready)
+        // Outside of the lock, the cleanup may do anything.
+        if cancelled {
+            self.cancel.cancel();
+        }
+        for f in on_cancel {
+            f();
+        }
     }
 }
 
-impl<T> Future for ManualFuture<T> {
+impl<T> Completer<T> {
+    /// Resolves the future with `val`. Returns false if the future was
+    /// already dropped, in which case its `on_cancel` cleanups run instead.
+    pub fn complete(self, val: T) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        // The receive halve outlives the future while a thread waits on it.
+        if inner.dropped {
+            return false;
+        }
+        inner.completed = true;
+        self.tx.send(val).is_ok()
+    }
+}
+
+impl<T: Send + 'static> Completer<T> {
+    /// Resolves the future with `val` once `dur` has passed. Completion runs on
+    /// a timer thread shared by all futures, and is cancelled if the future is
+    /// dropped before the deadline.
+    pub fn complete_after(self, dur: Duration, val: T) {
+        // Holding the lock while scheduling, so a concurrent drop either sees
+        // the timer entry and cancels it, or we see `dropped` and skip it.
+        let inner = Arc::clone(&self.inner);
+        let mut inner = inner.lock().unwrap();
+        if inner.dropped {
+            return;
+        }
+
+        let id = timer::schedule(Instant::now() + dur, move || {
+            self.complete(val);
+        });
+        inner.timer = Some(id);
+    }
+}
+
+impl<T: Send + 'static> Future for ManualFuture<T> {
     type Output = T;
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
@@ -65,17 +219,28 @@ impl<T> Future for ManualFuture<T> {
         };
 
         let mut inner = self.inner.lock().unwrap();
+        #[cfg(feature = "trace")]
+        inner.trace.record(Event::Polled);
 
         match &inner.waker {
             None => {
                 inner.waker = Some(cx.waker().clone());
+                #[cfg(feature = "trace")]
+                inner.trace.record(Event::WakerRegistered);
 
                 thread::spawn(move || match ready_rx.unwrap().recv() {
-                    Ok(_) => {
+                    Ok(val) => {
                         println!("receive on the channel was ok");
                         let mut inner = inner_cloned.lock().unwrap();
-                        inner.state = State::Ready;
+                        inner.state = State::Ready(val);
+                        #[cfg(feature = "trace")]
+                        inner.trace.record(Event::Transition {
+                            from: StateName::NotReady,
+                            to: StateName::Ready,
+                        });
                         inner.waker.as_ref().unwrap().wake_by_ref();
+                        #[cfg(feature = "trace")]
+                        inner.trace.record(Event::Woken);
                     }
                     Err(_) => println!("ERROR receive on the channel returned ERROR"),
                 });
@@ -83,34 +248,186 @@ impl<T> Future for ManualFuture<T> {
             Some(waker) => {
                 if !waker.will_wake(cx.waker()) {
                     inner.waker = Some(cx.waker().clone());
+                    #[cfg(feature = "trace")]
+                    inner.trace.record(Event::WakerReplaced);
                 }
             }
         }
 
         match inner.state {
             State::NotReady => Poll::Pending,
-            State::Ready => {
-                inner.state = State::Consumed;
-
-                // Lock is longer needed, so we release it.
-                drop(inner);
-
-                let val = unsafe {
-                    let this = self.as_mut().get_unchecked_mut();
-                    this.val.take().unwrap()
-                };
-                Poll::Ready(val)
-                // let res = unsafe {
-                //     let this = self.get_unchecked_mut();
-                //     let state = std::mem::replace(&mut this.state, State::Consumed);
-                //     match state {
-                //         State::Ready(res) => res,
-                //         _ => unreachable!(),
-                //     }
-                // };
-                // Poll::Ready(res)
+            State::Ready(_) => {
+                let state = std::mem::replace(&mut inner.state, State::Consumed);
+                #[cfg(feature = "trace")]
+                inner.trace.record(Event::Transition {
+                    from: StateName::Ready,
+                    to: StateName::Consumed,
+                });
+                match state {
+                    State::Ready(val) => Poll::Ready(val),
+                    _ => unreachable!(),
+                }
             }
             State::Consumed => unreachable!("Consumed Future polled again!"),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::time;
+
+    #[tokio::test]
+    async fn completer_provides_the_value() {
+        let (fut, completer) = ManualFuture::pending();
+
+        let handle = tokio::spawn(fut);
+        assert!(completer.complete("value".to_owned()));
+
+        assert_eq!(handle.await.unwrap(), "value");
+    }
+
+    #[tokio::test]
+    async fn complete_after_resolves_after_deadline() {
+        let (fut, completer) = ManualFuture::pending();
+        let now = Instant::now();
+
+        completer.complete_after(Duration::from_millis(50), 7);
+
+        assert_eq!(fut.await, 7);
+        assert!(now.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[tokio::test]
+    async fn dropping_future_cancels_scheduled_completion() {
+        let (fut, completer) = ManualFuture::pending();
+        completer.complete_after(Duration::from_secs(60), 7);
+
+        let id = fut.inner.lock().unwrap().timer.unwrap();
+        assert!(timer::is_scheduled(id));
+
+        // Poll once so the waiting thread gets spawned as well.
+        let handle = tokio::spawn(fut);
+        time::sleep(Duration::from_millis(10)).await;
+        handle.abort();
+        let _ = handle.await;
+
+        assert!(!timer::is_scheduled(id));
+    }
+
+    #[tokio::test]
+    async fn complete_after_on_dropped_future_is_noop() {
+        let (fut, completer) = ManualFuture::<u8>::pending();
+        let inner = completer.inner.clone();
+        drop(fut);
+
+        completer.complete_after(Duration::from_secs(60), 7);
+        assert!(inner.lock().unwrap().timer.is_none());
+    }
+
+    fn count_cancels<T>(fut: &ManualFuture<T>) -> Arc<AtomicUsize> {
+        let cancels = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&cancels);
+        fut.on_cancel(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        cancels
+    }
+
+    #[test]
+    fn on_cancel_runs_when_dropped_before_poll() {
+        let (fut, completer) = ManualFuture::<u8>::pending();
+        let cancels = count_cancels(&fut);
+
+        drop(fut);
+        assert_eq!(cancels.load(Ordering::SeqCst), 1);
+        assert!(!completer.complete(7));
+        assert_eq!(cancels.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn on_cancel_runs_when_aborted_after_poll() {
+        let (fut, completer) = ManualFuture::<u8>::pending();
+        let cancels = count_cancels(&fut);
+        completer.complete_after(Duration::from_secs(60), 7);
+
+        let handle = tokio::spawn(fut);
+        time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(cancels.load(Ordering::SeqCst), 0);
+        handle.abort();
+        let _ = handle.await;
+
+        assert_eq!(cancels.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn on_cancel_doesnt_run_after_completion() {
+        let (fut, completer) = ManualFuture::pending();
+        let cancels = count_cancels(&fut);
+        assert!(completer.complete(7));
+        assert_eq!(fut.await, 7);
+
+        // Completed but never polled.
+        let (fut, completer) = ManualFuture::pending();
+        let never_polled = count_cancels(&fut);
+        assert!(completer.complete(7));
+        drop(fut);
+
+        assert_eq!(cancels.load(Ordering::SeqCst), 0);
+        assert_eq!(never_polled.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn cancellation_token_follows_on_cancel() {
+        let (fut, completer) = ManualFuture::<u8>::pending();
+        let token = fut.cancellation_token();
+        assert!(!token.is_cancelled());
+        drop(fut);
+        assert!(token.is_cancelled());
+        assert!(!completer.complete(7));
+
+        let (fut, completer) = ManualFuture::pending();
+        let token = fut.cancellation_token();
+        assert!(completer.complete(7));
+        assert_eq!(fut.await, 7);
+        assert!(!token.is_cancelled());
+    }
+
+    #[tokio::test]
+    async fn ad_hoc_futures() {
+        let mut polls = 0;
+        let fut = ManualFuture::from_fn(|cx| {
+            polls += 1;
+            if polls < 3 {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            Poll::Ready(polls)
+        });
+        assert_eq!(fut.await, 3);
+
+        assert_eq!(ManualFuture::pending_for(5, "value").await, "value");
+
+        let mut fut = std::pin::pin!(ManualFuture::pending_for(1, 7));
+        let mut cx = Context::from_waker(Waker::noop());
+        assert_eq!(fut.as_mut().poll(&mut cx), Poll::Pending);
+        assert_eq!(fut.as_mut().poll(&mut cx), Poll::Ready(7));
+    }
+
+    #[test]
+    fn on_cancel_runs_unless_completion_wins_the_race() {
+        for _ in 0..1000 {
+            let (fut, completer) = ManualFuture::pending();
+            let cancels = count_cancels(&fut);
+
+            let completing = thread::spawn(move || completer.complete(7));
+            drop(fut);
+            let completed = completing.join().unwrap();
+
+            let cancelled = cancels.load(Ordering::SeqCst);
+            assert_eq!(cancelled, usize::from(!completed));
+        }
+    }
+}
@@ -0,0 +1,160 @@
+//! A single background thread that runs callbacks once their deadline has
+//! passed. It's shared by every `Completer::complete_after` call, so tests
+//! that need "resolve this future in 50ms" don't spawn a sleeping thread each.
+//!
+//! Entries can be cancelled before they fire, which drops the callback (and
+//! everything it captured) immediately instead of at the deadline.
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::sync::{Condvar, Mutex, OnceLock};
+use std::thread;
+use std::time::Instant;
+
+type Callback = Box<dyn FnOnce() + Send>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct EntryId(u64);
+
+struct Timer {
+    state: Mutex<TimerState>,
+    cond: Condvar,
+}
+
+struct TimerState {
+    next_id: u64,
+    // Deadlines are kept in a min-heap. Cancelled entries are only removed from
+    // `callbacks`, and their stale heap slots are skipped when they surface.
+    deadlines: BinaryHeap<Reverse<(Instant, EntryId)>>,
+    callbacks: HashMap<EntryId, Callback>,
+}
+
+fn timer() -> &'static Timer {
+    static TIMER: OnceLock<Timer> = OnceLock::new();
+    TIMER.get_or_init(|| {
+        thread::Builder::new()
+            .name("manfut-timer".to_owned())
+            .spawn(run)
+            .expect("failed to spawn timer thread");
+
+        Timer {
+            state: Mutex::new(TimerState {
+                next_id: 0,
+                deadlines: BinaryHeap::new(),
+                callbacks: HashMap::new(),
+            }),
+            cond: Condvar::new(),
+        }
+    })
+}
+
+/// Schedules `f` to run on the timer thread once `deadline` has passed.
+pub fn schedule(deadline: Instant, f: impl FnOnce() + Send + 'static) -> EntryId {
+    let timer = timer();
+    let mut state = timer.state.lock().unwrap();
+
+    let id = EntryId(state.next_id);
+    state.next_id += 1;
+    state.deadlines.push(Reverse((deadline, id)));
+    state.callbacks.insert(id, Box::new(f));
+
+    // The new entry might be earlier than the one the timer thread is
+    // currently sleeping on.
+    timer.cond.notify_one();
+    id
+}
+
+/// Cancels a scheduled entry. Returns false if it has already fired or was
+/// cancelled before.
+pub fn cancel(id: EntryId) -> bool {
+    let callback = timer().state.lock().unwrap().callbacks.remove(&id);
+
+    // Dropping the callback outside of the lock, its captures may do
+    // arbitrary work in their destructors.
+    callback.is_some()
+}
+
+/// Returns true if the entry is still waiting for its deadline.
+#[cfg(test)]
+pub fn is_scheduled(id: EntryId) -> bool {
+    timer().state.lock().unwrap().callbacks.contains_key(&id)
+}
+
+fn run() {
+    let timer = timer();
+    let mut state = timer.state.lock().unwrap();
+
+    loop {
+        let now = Instant::now();
+        let mut due = vec![];
+
+        while let Some(&Reverse((deadline, id))) = state.deadlines.peek() {
+            if deadline > now {
+                break;
+            }
+            state.deadlines.pop();
+            if let Some(f) = state.callbacks.remove(&id) {
+                due.push(f);
+            }
+        }
+
+        if !due.is_empty() {
+            // Callbacks are run without holding the lock so they can schedule
+            // or cancel other entries.
+            drop(state);
+            for f in due {
+                f();
+            }
+            state = timer.state.lock().unwrap();
+            continue;
+        }
+
+        state = match state.deadlines.peek() {
+            None => timer.cond.wait(state).unwrap(),
+            Some(&Reverse((deadline, _))) => {
+                timer
+                    .cond
+                    .wait_timeout(state, deadline.saturating_duration_since(now))
+                    .unwrap()
+                    .0
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    #[test]
+    fn fires_in_deadline_order() {
+        let (tx, rx) = mpsc::channel();
+        let now = Instant::now();
+
+        for (i, ms) in [30, 10, 20].into_iter().enumerate() {
+            let tx = tx.clone();
+            schedule(now + Duration::from_millis(ms), move || tx.send(i).unwrap());
+        }
+
+        let fired: Vec<usize> = rx.iter().take(3).collect();
+        assert_eq!(fired, vec![1, 2, 0]);
+        assert!(now.elapsed() >= Duration::from_millis(30));
+    }
+
+    #[test]
+    fn cancelled_entry_never_fires() {
+        let (tx, rx) = mpsc::channel::<()>();
+        let id = schedule(Instant::now() + Duration::from_millis(20), move || {
+            tx.send(()).unwrap()
+        });
+
+        assert!(is_scheduled(id));
+        assert!(cancel(id));
+        assert!(!is_scheduled(id));
+        assert!(!cancel(id));
+
+        // The callback was dropped along with the sender it captured.
+        assert_eq!(rx.recv(), Err(mpsc::RecvError));
+    }
+}
@@ -0,0 +1,167 @@
+//! A record of everything that happened to a `ManualFuture`: polls, waker
+//! registrations, wake calls and state transitions, each with the time it
+//! happened at relative to the creation of the future. Reading one next to
+//! the code shows the future/waker protocol in action, e.g. that the future
+//! is polled once, returns Pending, and isn't polled again until it's woken.
+//!
+//! Only compiled with the `trace` feature.
+use std::fmt;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    Polled,
+    /// The first poll stored the waker.
+    WakerRegistered,
+    /// A later poll came with a waker that wouldn't wake the same task.
+    WakerReplaced,
+    Woken,
+    Transition {
+        from: StateName,
+        to: StateName,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateName {
+    NotReady,
+    Ready,
+    Consumed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry {
+    pub at: Duration,
+    pub event: Event,
+}
+
+#[derive(Debug, Clone)]
+pub struct Trace {
+    start: Instant,
+    entries: Vec<Entry>,
+}
+
+impl Trace {
+    pub(crate) fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            entries: vec![],
+        }
+    }
+
+    pub(crate) fn record(&mut self, event: Event) {
+        self.entries.push(Entry {
+            at: self.start.elapsed(),
+            event,
+        });
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// The events without their timestamps, which is easier to compare.
+    pub fn events(&self) -> Vec<Event> {
+        self.entries.iter().map(|e| e.event).collect()
+    }
+}
+
+impl fmt::Display for StateName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::Polled => write!(f, "polled"),
+            Event::WakerRegistered => write!(f, "waker registered"),
+            Event::WakerReplaced => write!(f, "waker replaced"),
+            Event::Woken => write!(f, "woken"),
+            Event::Transition { from, to } => write!(f, "{} -> {}", from, to),
+        }
+    }
+}
+
+/// One event per line, e.g. `  0.052ms  NotReady -> Ready`.
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for entry in &self.entries {
+            let ms = entry.at.as_secs_f64() * 1000.0;
+            writeln!(f, "{:>9.3}ms  {}", ms, entry.event)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::man::ManualFuture;
+    use std::future::{poll_fn, Future};
+    use std::pin::Pin;
+    use std::task::Poll;
+    use Event::*;
+    use StateName::*;
+
+    #[tokio::test]
+    async fn records_the_protocol() {
+        let (mut fut, completer) = ManualFuture::pending();
+
+        // The first poll finds it not ready and registers the waker.
+        let pending = poll_fn(|cx| Poll::Ready(Pin::new(&mut fut).poll(cx).is_pending())).await;
+        assert!(pending);
+
+        // The value arrives on another thread, which wakes the task.
+        completer.complete(7);
+        while !fut.trace().events().contains(&Woken) {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!((&mut fut).await, 7);
+
+        assert_eq!(
+            fut.trace().events(),
+            [
+                Polled,
+                WakerRegistered,
+                Transition {
+                    from: NotReady,
+                    to: Ready
+                },
+                Woken,
+                Polled,
+                Transition {
+                    from: Ready,
+                    to: Consumed
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn timestamps_are_in_order() {
+        let mut trace = Trace::new();
+        trace.record(Polled);
+        trace.record(Woken);
+
+        let entries = trace.entries();
+        assert!(entries[0].at <= entries[1].at);
+    }
+
+    #[test]
+    fn renders_one_line_per_event() {
+        let mut trace = Trace::new();
+        trace.record(WakerRegistered);
+        trace.record(Transition {
+            from: NotReady,
+            to: Ready,
+        });
+
+        let rendered = trace.to_string();
+        let lines: Vec<_> = rendered.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with("ms  waker registered"));
+        assert!(lines[1].ends_with("ms  NotReady -> Ready"));
+    }
+}
@@ -1,80 +1,421 @@
-use crate::db::Db;
+use std::fmt;
+
+use crate::csv;
+use crate::db::{AddEmplResult, Db, MoveEmplResult, RemoveEmplResult, RenameDptResult};
+use crate::history::{Change, History};
+use crate::plugin::{PluginId, Registry};
+use crate::query::{edit_distance, Query, QueryError};
+
+// How many matches `Find` shows at a time.
+const PAGE_SIZE: usize = 10;
 
 pub enum Cmd {
-    Add { dpt: String, empl: String },
+    Add {
+        dpt: String,
+        empl: String,
+    },
+    Remove {
+        dpt: String,
+        empl: String,
+    },
+    Move {
+        empl: String,
+        from: String,
+        to: String,
+    },
+    /// `merge` merges `dpt` into `new_name` if it's taken.
+    Rename {
+        dpt: String,
+        new_name: String,
+        merge: bool,
+    },
+    Import(String),
+    Export(String),
+    Undo,
+    Redo,
     ListAll,
     ListDepartment(String),
+    /// Pages start at 1.
+    Find {
+        query: Query,
+        page: usize,
+    },
     Close,
-    Unknown(String),
+    // A command parsed by one of the plugins.
+    Plugin {
+        id: PluginId,
+        args: Vec<String>,
+    },
 }
 
+/// Why a line isn't a command. The messages are shown to the user as they
+/// are.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    Empty,
+    UnterminatedQuote,
+    UnknownCommand {
+        input: String,
+        /// The built-in verb `input` is closest to, if any is close.
+        suggestion: Option<&'static str>,
+    },
+    MissingEmployee {
+        verb: &'static str,
+    },
+    MissingPreposition {
+        expected: &'static str,
+    },
+    MissingDepartment {
+        verb: &'static str,
+    },
+    /// The arguments don't fit the command, which is shown as it's used.
+    Usage(&'static str),
+    InvalidPage,
+    Query(QueryError),
+    /// A plugin's reason, as it gave it.
+    Plugin(String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Empty => write!(f, "enter a command, like `Add Sally to Engineering`"),
+            ParseError::UnterminatedQuote => write!(f, "unterminated quote"),
+            ParseError::UnknownCommand { suggestion, .. } => {
+                write!(f, "unknown command")?;
+                match suggestion {
+                    Some(verb) => write!(f, ", did you mean `{}`?", verb),
+                    None => Ok(()),
+                }
+            }
+            ParseError::MissingEmployee { verb } => {
+                write!(f, "`{}` command needs employee name", verb)
+            }
+            ParseError::MissingPreposition { expected } => write!(
+                f,
+                "employee name should be followed by `{}` preposition",
+                expected
+            ),
+            ParseError::MissingDepartment { verb: "List" } => {
+                write!(f, "`List` command requires department as argument")
+            }
+            ParseError::MissingDepartment { verb } => {
+                write!(f, "`{}` command needs department", verb)
+            }
+            ParseError::Usage(usage) => write!(f, "usage: {}", usage),
+            ParseError::InvalidPage => write!(f, "`PAGE` needs a number from 1 on"),
+            ParseError::Query(e) => write!(f, "{}", e),
+            ParseError::Plugin(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl From<QueryError> for ParseError {
+    fn from(e: QueryError) -> Self {
+        ParseError::Query(e)
+    }
+}
+
+// The verbs `parse` knows of, for suggestions.
+const VERBS: [&str; 11] = [
+    "Add", "Remove", "Move", "Rename", "Import", "Export", "List", "Find", "Undo", "Redo", "Close",
+];
+
 /// (1) Read on custom erros and state machines.
 /// (2) how to keep using different part os the input string without
 ///     cloning them? below we're using a ton of to_owned! what's the more
 ///     performant way for achieving the same thing?
 /// (3) How to not perform heap allocations for fixed strings?
-pub fn parse(ss: &str) -> Cmd {
-    let mut parts = ss.split_whitespace();
+pub fn parse(ss: &str, plugins: &Registry) -> Result<Cmd, ParseError> {
+    // The query has a syntax of its own, quotes included.
+    if ss.split_whitespace().next() == Some("Find") {
+        return parse_find(&ss.trim_start()["Find".len()..]);
+    }
+    let words = split_words(ss)?;
+    let mut parts = words.iter().map(String::as_str);
 
-    let p = match parts.next() {
-        Some(p) => p,
-        None => return Cmd::Unknown("not enough parts".to_owned()),
-    };
+    let p = parts.next().ok_or(ParseError::Empty)?;
 
     match p {
         "Add" => parse_add(parts),
+        "Remove" => parse_remove(parts),
+        "Move" => parse_move(parts),
+        "Rename" => parse_rename(parts),
+        "Import" => parse_file("Import FILE", parts).map(Cmd::Import),
+        "Export" => parse_file("Export FILE", parts).map(Cmd::Export),
         "List" => parse_list(parts),
-        "Close" => Cmd::Close,
-        _ => Cmd::Unknown("unknown command".to_owned()),
+        "Undo" => Ok(Cmd::Undo),
+        "Redo" => Ok(Cmd::Redo),
+        "Close" => Ok(Cmd::Close),
+        verb => parse_plugin(plugins, verb, parts),
+    }
+}
+
+// Splits a line into words at whitespace, except for the whitespace between
+// double quotes, so that `"Mary Jane"` is a single word. Quotes can start in
+// the middle of a word, and `\"` and `\\` stand for a quote and a backslash
+// between them.
+fn split_words(ss: &str) -> Result<Vec<String>, ParseError> {
+    let mut words = Vec::new();
+    let mut chars = ss.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            return Ok(words);
+        }
+        let mut word = String::new();
+        while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+            if c != '"' {
+                word.push(c);
+                continue;
+            }
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => match chars.next_if(|&c| c == '"' || c == '\\') {
+                        Some(c) => word.push(c),
+                        None => word.push('\\'),
+                    },
+                    Some(c) => word.push(c),
+                    None => return Err(ParseError::UnterminatedQuote),
+                }
+            }
+        }
+        words.push(word);
     }
 }
 
-fn parse_add<'a, T>(mut parts: T) -> Cmd
+fn parse_plugin<'a, T>(plugins: &Registry, verb: &str, parts: T) -> Result<Cmd, ParseError>
 where
     T: Iterator<Item = &'a str>,
 {
-    let empl = match parts.next() {
-        Some(e) => e,
-        None => return Cmd::Unknown("`Add` command needs employee name".to_owned()),
-    };
+    let args: Vec<&str> = parts.collect();
+    match plugins.parse(verb, &args) {
+        Some(Ok((id, args))) => Ok(Cmd::Plugin { id, args }),
+        Some(Err(reason)) => Err(ParseError::Plugin(reason)),
+        None => Err(ParseError::UnknownCommand {
+            input: verb.to_owned(),
+            suggestion: suggest(verb),
+        }),
+    }
+}
+
+// The closest built-in verb to `verb`, ignoring case, if it's a typo away
+// from it, or one for every four characters of longer verbs.
+fn suggest(verb: &str) -> Option<&'static str> {
+    let verb = verb.to_lowercase();
+    VERBS
+        .iter()
+        .map(|&v| (edit_distance(&verb, &v.to_lowercase()), v))
+        .filter(|&(d, v)| d <= (v.len() / 4).max(1))
+        .min()
+        .map(|(_, v)| v)
+}
+
+// `NAME to DEPT`, and `NAME from DEPT` for `Remove`.
+fn parse_empl_dpt<'a, T>(
+    verb: &'static str,
+    preposition: &'static str,
+    mut parts: T,
+) -> Result<(String, String), ParseError>
+where
+    T: Iterator<Item = &'a str>,
+{
+    let empl = parts.next().ok_or(ParseError::MissingEmployee { verb })?;
 
     match parts.next() {
-        Some(to) if to == "to" => (),
+        Some(p) if p == preposition => (),
         _ => {
-            return Cmd::Unknown("employee name should be followed by `to` preposition".to_owned())
+            return Err(ParseError::MissingPreposition {
+                expected: preposition,
+            })
         }
     }
 
-    let dpt = match parts.next() {
-        Some(d) => d,
-        None => return Cmd::Unknown("`Add` command needs department".to_owned()),
-    };
+    let dpt = parts.next().ok_or(ParseError::MissingDepartment { verb })?;
+    Ok((empl.to_owned(), dpt.to_owned()))
+}
+
+fn parse_add<'a, T>(parts: T) -> Result<Cmd, ParseError>
+where
+    T: Iterator<Item = &'a str>,
+{
+    let (empl, dpt) = parse_empl_dpt("Add", "to", parts)?;
+    Ok(Cmd::Add { empl, dpt })
+}
 
-    Cmd::Add {
-        empl: empl.to_owned(),
+// `NAME from DEPT`
+fn parse_remove<'a, T>(parts: T) -> Result<Cmd, ParseError>
+where
+    T: Iterator<Item = &'a str>,
+{
+    let (empl, dpt) = parse_empl_dpt("Remove", "from", parts)?;
+    Ok(Cmd::Remove { empl, dpt })
+}
+
+// `NAME from DEPT to DEPT`
+fn parse_move<'a, T>(parts: T) -> Result<Cmd, ParseError>
+where
+    T: Iterator<Item = &'a str>,
+{
+    match parts.collect::<Vec<_>>().as_slice() {
+        [empl, "from", from, "to", to] => Ok(Cmd::Move {
+            empl: empl.to_string(),
+            from: from.to_string(),
+            to: to.to_string(),
+        }),
+        _ => Err(ParseError::Usage("Move NAME from DEPT to DEPT")),
+    }
+}
+
+// `DEPT to NEW_NAME [MERGE]`
+fn parse_rename<'a, T>(parts: T) -> Result<Cmd, ParseError>
+where
+    T: Iterator<Item = &'a str>,
+{
+    let (dpt, new_name, merge) = match parts.collect::<Vec<_>>().as_slice() {
+        [dpt, "to", new_name] => (*dpt, *new_name, false),
+        [dpt, "to", new_name, "MERGE"] => (*dpt, *new_name, true),
+        _ => return Err(ParseError::Usage("Rename DEPT to NEW_NAME [MERGE]")),
+    };
+    Ok(Cmd::Rename {
         dpt: dpt.to_owned(),
+        new_name: new_name.to_owned(),
+        merge,
+    })
+}
+
+// `FILE`
+fn parse_file<'a, T>(usage: &'static str, mut parts: T) -> Result<String, ParseError>
+where
+    T: Iterator<Item = &'a str>,
+{
+    match (parts.next(), parts.next()) {
+        (Some(path), None) => Ok(path.to_owned()),
+        _ => Err(ParseError::Usage(usage)),
+    }
+}
+
+// `QUERY [PAGE n]`
+fn parse_find(args: &str) -> Result<Cmd, ParseError> {
+    let mut page = 1;
+    let mut query = args.trim();
+    if let Some((rest, n)) = query.rsplit_once(char::is_whitespace) {
+        if let Some(rest) = rest.trim_end().strip_suffix("PAGE") {
+            page = match n.parse() {
+                Ok(n) if n > 0 => n,
+                _ => return Err(ParseError::InvalidPage),
+            };
+            query = rest;
+        }
     }
+    let query = Query::parse(query)?;
+    Ok(Cmd::Find { query, page })
 }
 
-fn parse_list<'a, T>(mut parts: T) -> Cmd
+fn parse_list<'a, T>(mut parts: T) -> Result<Cmd, ParseError>
 where
     T: Iterator<Item = &'a str>,
 {
     match parts.next() {
-        Some(dpt) if dpt == "All" => Cmd::ListAll,
-        Some(dpt) => Cmd::ListDepartment(dpt.into()),
-        None => return Cmd::Unknown("`List` command requires department as argument".to_owned()),
+        Some("All") => Ok(Cmd::ListAll),
+        Some(dpt) => Ok(Cmd::ListDepartment(dpt.into())),
+        None => Err(ParseError::MissingDepartment { verb: "List" }),
     }
 }
 
 impl Cmd {
-    pub fn exec(self, db: &mut Db) -> bool {
+    pub fn exec(self, db: &mut Db, plugins: &Registry, history: &mut History) -> bool {
         match self {
             Cmd::Add { dpt, empl } => {
-                db.add_empl(dpt, empl);
+                if let AddEmplResult::Added = db.add_empl(dpt.clone(), empl.clone()) {
+                    history.record(Change::Added { dpt, empl });
+                }
                 println!("success\n");
                 true
             }
+            Cmd::Remove { dpt, empl } => {
+                match db.remove_empl(&dpt, &empl) {
+                    RemoveEmplResult::Removed => {
+                        println!("removed\n");
+                        history.record(Change::Removed { dpt, empl });
+                    }
+                    RemoveEmplResult::NoSuchDepartment => println!("no such department: {}\n", dpt),
+                    RemoveEmplResult::NoSuchEmployee => println!("{} isn't in {}\n", empl, dpt),
+                }
+                true
+            }
+            Cmd::Move { empl, from, to } => {
+                match db.move_empl(&empl, &from, &to) {
+                    MoveEmplResult::Moved => {
+                        println!("moved\n");
+                        history.record(Change::Moved { empl, from, to });
+                    }
+                    MoveEmplResult::NotInSource => println!("{} isn't in {}\n", empl, from),
+                    MoveEmplResult::AlreadyInTarget => {
+                        println!("{} is already in {}\n", empl, to)
+                    }
+                }
+                true
+            }
+            Cmd::Rename {
+                dpt,
+                new_name,
+                merge,
+            } => {
+                match db.rename_dpt(&dpt, new_name.clone()) {
+                    RenameDptResult::Renamed(n) => {
+                        println!("renamed {} to {}, {} employees\n", dpt, new_name, n);
+                        history.record(Change::Renamed {
+                            from: dpt,
+                            to: new_name,
+                        });
+                    }
+                    RenameDptResult::NoSuchDepartment => println!("no such department: {}\n", dpt),
+                    // Merges aren't journaled, see history.
+                    RenameDptResult::AlreadyExists if merge => {
+                        let added = db.merge_dpts(&dpt, new_name.clone()).unwrap_or(0);
+                        history.clear();
+                        println!(
+                            "merged {} into {}, {} employees added\n",
+                            dpt, new_name, added
+                        );
+                    }
+                    RenameDptResult::AlreadyExists => println!(
+                        "{} already exists, add MERGE to merge {} into it\n",
+                        new_name, dpt
+                    ),
+                }
+                true
+            }
+            Cmd::Import(path) => {
+                let src = match std::fs::read_to_string(&path) {
+                    Ok(src) => src,
+                    Err(e) => {
+                        println!("couldn't read {}: {}\n", path, e);
+                        return true;
+                    }
+                };
+                let (added, errors) = csv::import(db, &src);
+                if added > 0 {
+                    history.clear();
+                }
+                for e in &errors {
+                    println!("{}", e);
+                }
+                println!("imported {} rows, skipped {}\n", added, errors.len());
+                true
+            }
+            Cmd::Export(path) => {
+                match std::fs::write(&path, csv::export(db)) {
+                    Ok(()) => println!("exported {} rows\n", db.len()),
+                    Err(e) => println!("couldn't write {}: {}\n", path, e),
+                }
+                true
+            }
             Cmd::ListAll => {
                 for (dpt, empl) in db.get_all_dpt_empls() {
                     println!("{} => {}", dpt, empl);
@@ -89,11 +430,293 @@ impl Cmd {
                 println!("");
                 true
             }
+            Cmd::Find { query, page } => {
+                let found = db.find(&query);
+                if found.is_empty() {
+                    println!("no matches\n");
+                    return true;
+                }
+                let pages = found.len().div_ceil(PAGE_SIZE);
+                for (dpt, empl) in found.iter().skip((page - 1) * PAGE_SIZE).take(PAGE_SIZE) {
+                    println!("{} => {}", dpt, empl);
+                }
+                println!("page {} of {}\n", page, pages);
+                true
+            }
+            Cmd::Undo => {
+                match history.undo(db) {
+                    Ok(change) => println!("undid `{}`\n", change),
+                    Err(e) => println!("couldn't undo: {}\n", e),
+                }
+                true
+            }
+            Cmd::Redo => {
+                match history.redo(db) {
+                    Ok(change) => println!("redid `{}`\n", change),
+                    Err(e) => println!("couldn't redo: {}\n", e),
+                }
+                true
+            }
             Cmd::Close => false,
-            Cmd::Unknown(reason) => {
-                println!("{}\n", reason);
+            Cmd::Plugin { id, args } => {
+                // Plugins can only add employees.
+                let len = db.len();
+                println!("{}\n", plugins.exec(id, args, db));
+                if db.len() != len {
+                    history.clear();
+                }
                 true
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(line: &str) -> Result<(Query, usize), ParseError> {
+        match parse(line, &Registry::new())? {
+            Cmd::Find { query, page } => Ok((query, page)),
+            _ => panic!("expected a find command"),
+        }
+    }
+
+    fn err(line: &str) -> ParseError {
+        match parse(line, &Registry::new()) {
+            Err(e) => e,
+            Ok(_) => panic!("expected {} to fail", line),
+        }
+    }
+
+    #[test]
+    fn remove() {
+        match parse("Remove Sally from Engineering\n", &Registry::new()) {
+            Ok(Cmd::Remove { dpt, empl }) => assert_eq!((&*empl, &*dpt), ("Sally", "Engineering")),
+            _ => panic!("expected a remove command"),
+        }
+        assert_eq!(
+            err("Remove"),
+            ParseError::MissingEmployee { verb: "Remove" }
+        );
+        assert_eq!(
+            err("Remove Sally to Engineering"),
+            ParseError::MissingPreposition { expected: "from" }
+        );
+        assert_eq!(
+            err("Remove Sally from"),
+            ParseError::MissingDepartment { verb: "Remove" }
+        );
+    }
+
+    #[test]
+    fn move_() {
+        match parse("Move Sally from Sales to Engineering", &Registry::new()) {
+            Ok(Cmd::Move { empl, from, to }) => assert_eq!(
+                (empl.as_str(), from.as_str(), to.as_str()),
+                ("Sally", "Sales", "Engineering")
+            ),
+            _ => panic!("expected a move command"),
+        }
+        for line in [
+            "Move Sally",
+            "Move Sally to Sales from Eng",
+            "Move a from b to c d",
+        ] {
+            assert_eq!(err(line), ParseError::Usage("Move NAME from DEPT to DEPT"));
+        }
+    }
+
+    #[test]
+    fn rename() {
+        let rename = |line| match parse(line, &Registry::new())? {
+            Cmd::Rename {
+                dpt,
+                new_name,
+                merge,
+            } => Ok((dpt, new_name, merge)),
+            _ => panic!("expected a rename command"),
+        };
+        assert_eq!(
+            rename("Rename Eng to Dev"),
+            Ok(("Eng".to_owned(), "Dev".to_owned(), false))
+        );
+        assert_eq!(
+            rename("Rename Eng to Dev MERGE"),
+            Ok(("Eng".to_owned(), "Dev".to_owned(), true))
+        );
+        for line in ["Rename Eng", "Rename Eng Dev", "Rename Eng to Dev merge"] {
+            assert_eq!(
+                rename(line),
+                Err(ParseError::Usage("Rename DEPT to NEW_NAME [MERGE]"))
+            );
+        }
+    }
+
+    #[test]
+    fn import_and_export_take_a_file() {
+        match parse("Import rows.csv", &Registry::new()) {
+            Ok(Cmd::Import(path)) => assert_eq!(path, "rows.csv"),
+            _ => panic!("expected an import command"),
+        }
+        assert_eq!(err("Export"), ParseError::Usage("Export FILE"));
+    }
+
+    // Runs the commands the way the main loop does.
+    fn exec_all(db: &mut Db, history: &mut History, lines: &[&str]) {
+        let plugins = Registry::new();
+        for line in lines {
+            parse(line, &plugins).unwrap().exec(db, &plugins, history);
+        }
+    }
+
+    #[test]
+    fn imports_clear_the_history() {
+        use crate::history::UndoError;
+
+        let path = std::env::temp_dir().join("memanager-cmd-import-test.csv");
+        std::fs::write(&path, "Eng,Bob\n").unwrap();
+        let mut db = Db::new();
+        let mut history = History::new();
+
+        let import = format!("Import {}", path.display());
+        exec_all(&mut db, &mut history, &["Add Sally to Eng", &import]);
+        // The add came before the import, so it's forgotten.
+        assert_eq!(history.undo(&mut db), Err(UndoError::Empty));
+
+        // Importing nothing new keeps it.
+        exec_all(&mut db, &mut history, &["Add Eve to Ops", &import]);
+        assert!(history.undo(&mut db).is_ok());
+    }
+
+    #[test]
+    fn merges_clear_the_history() {
+        use crate::history::UndoError;
+
+        let mut db = Db::new();
+        let mut history = History::new();
+        exec_all(
+            &mut db,
+            &mut history,
+            &[
+                "Add Sally to Eng",
+                "Rename Eng to Dev",
+                "Add Bob to Ops",
+                "Rename Ops to Dev MERGE",
+            ],
+        );
+        // Undoing the rename would take Bob back to Eng.
+        assert_eq!(history.undo(&mut db), Err(UndoError::Empty));
+        assert_eq!(db.get_empls("Dev").collect::<Vec<_>>(), ["Bob", "Sally"]);
+    }
+
+    #[test]
+    fn find_takes_the_rest_of_the_line() {
+        let (query, page) = find("  Find name~\"al i\"  AND dept=Eng*\n").unwrap();
+        assert_eq!(page, 1);
+        assert!(query.matches("Eng", "Sal Ivy"));
+
+        let (query, page) = find("Find dept=PAGE PAGE 3").unwrap();
+        assert_eq!(page, 3);
+        assert!(query.matches("PAGE", "Sally"));
+    }
+
+    #[test]
+    fn find_errors() {
+        assert_eq!(
+            find("Find").err().unwrap(),
+            ParseError::Query(QueryError::Empty)
+        );
+        assert_eq!(
+            find("Find dept=Eng PAGE 0").err().unwrap(),
+            ParseError::InvalidPage
+        );
+        assert_eq!(
+            find("Find dept=Eng PAGE").err().unwrap(),
+            ParseError::Query(QueryError::Unexpected("PAGE".to_owned()))
+        );
+    }
+
+    #[test]
+    fn quoted_words() {
+        let words = |line| split_words(line).unwrap();
+        assert_eq!(
+            words(r#"  Add "Mary Jane" to "Customer Success"  "#),
+            ["Add", "Mary Jane", "to", "Customer Success"]
+        );
+        assert_eq!(
+            words(r#"a"b c"d "" "\"q\" \\ \n" x\y"#),
+            ["ab cd", "", r#""q" \ \n"#, r"x\y"]
+        );
+        assert_eq!(
+            split_words(r#"Add "Mary to Eng"#),
+            Err(ParseError::UnterminatedQuote)
+        );
+
+        match parse(
+            r#"Move "Mary Jane" from Sales to "Customer Success""#,
+            &Registry::new(),
+        ) {
+            Ok(Cmd::Move { empl, from, to }) => assert_eq!(
+                (empl.as_str(), from.as_str(), to.as_str()),
+                ("Mary Jane", "Sales", "Customer Success")
+            ),
+            _ => panic!("expected a move command"),
+        }
+        match parse(r#"List "Customer Success""#, &Registry::new()) {
+            Ok(Cmd::ListDepartment(dpt)) => assert_eq!(dpt, "Customer Success"),
+            _ => panic!("expected a list command"),
+        }
+        match parse(r#"Remove "Mary \"MJ\" Jane" from Sales"#, &Registry::new()) {
+            Ok(Cmd::Remove { empl, .. }) => assert_eq!(empl, r#"Mary "MJ" Jane"#),
+            _ => panic!("expected a remove command"),
+        }
+        // Find's quotes are its own.
+        assert!(find(r#"Find name~"ali"#).is_err());
+        assert!(find(r#"Find name="Mary Jane""#).is_ok());
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(err(" \n"), ParseError::Empty);
+        assert_eq!(
+            err("Add Sally"),
+            ParseError::MissingPreposition { expected: "to" }
+        );
+        assert_eq!(err("List"), ParseError::MissingDepartment { verb: "List" });
+        assert_eq!(
+            err("List").to_string(),
+            "`List` command requires department as argument"
+        );
+        assert_eq!(
+            err("Fire Sally"),
+            ParseError::UnknownCommand {
+                input: "Fire".to_owned(),
+                suggestion: None
+            }
+        );
+    }
+
+    #[test]
+    fn unknown_commands_get_suggestions() {
+        let suggestion = |line| match err(line) {
+            ParseError::UnknownCommand { suggestion, .. } => suggestion,
+            e => panic!("unexpected error: {}", e),
+        };
+        assert_eq!(suggestion("add Sally to Eng"), Some("Add"));
+        assert_eq!(suggestion("Lsit All"), Some("List"));
+        assert_eq!(suggestion("REMOVE"), Some("Remove"));
+        assert_eq!(suggestion("Undoo"), Some("Undo"));
+        assert_eq!(suggestion("Delete"), None);
+
+        assert_eq!(
+            err("Lsit All").to_string(),
+            "unknown command, did you mean `List`?"
+        );
+        assert_eq!(err("Delete").to_string(), "unknown command");
+        assert_eq!(
+            err("Remove").to_string(),
+            "`Remove` command needs employee name"
+        );
+    }
+}
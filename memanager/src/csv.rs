@@ -0,0 +1,172 @@
+//! `Import FILE` and `Export FILE`, which move `department,employee` rows in
+//! and out of the database. Fields with commas or quotes in them are quoted,
+//! with the quotes doubled, and a row can't span lines.
+use std::fmt;
+
+use crate::db::{AddEmplResult, Db};
+
+const HEADER: &str = "department,employee";
+
+/// A row of an import that wasn't added. Lines start at 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RowError {
+    pub line: usize,
+    pub reason: String,
+}
+
+impl fmt::Display for RowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.reason)
+    }
+}
+
+/// adds the rows of `src` to `db`, and returns how many were added and why
+/// the others weren't. The rows are independent: a bad one doesn't keep the
+/// ones after it out. An optional header comes first, and empty lines are
+/// skipped.
+pub fn import(db: &mut Db, src: &str) -> (usize, Vec<RowError>) {
+    let mut added = 0;
+    let mut errors = Vec::new();
+    for (i, row) in src.lines().enumerate() {
+        if row.trim().is_empty() || (i == 0 && row.trim() == HEADER) {
+            continue;
+        }
+        let reason = match parse_row(row) {
+            Ok((dpt, empl)) => match db.add_empl(dpt.clone(), empl.clone()) {
+                AddEmplResult::Added => {
+                    added += 1;
+                    continue;
+                }
+                AddEmplResult::AlreadyExists => format!("{} is already in {}", empl, dpt),
+            },
+            Err(reason) => reason,
+        };
+        errors.push(RowError {
+            line: i + 1,
+            reason,
+        });
+    }
+    (added, errors)
+}
+
+/// the rows of `db` under a header, sorted by department and then name.
+pub fn export(db: &Db) -> String {
+    let mut rows: Vec<_> = db.get_all_dpt_empls().collect();
+    rows.sort_unstable();
+
+    let mut out = format!("{}\n", HEADER);
+    for (dpt, empl) in rows {
+        out.push_str(&format!("{},{}\n", quote(dpt), quote(empl)));
+    }
+    out
+}
+
+fn parse_row(row: &str) -> Result<(String, String), String> {
+    let mut fields = Vec::new();
+    let mut rest = row;
+    loop {
+        let (field, after) = parse_field(rest)?;
+        fields.push(field);
+        match after.strip_prefix(',') {
+            Some(after) => rest = after,
+            None => break,
+        }
+    }
+    match <[String; 2]>::try_from(fields) {
+        Ok([dpt, _]) if dpt.is_empty() => Err("missing department".to_owned()),
+        Ok([_, empl]) if empl.is_empty() => Err("missing employee".to_owned()),
+        Ok([dpt, empl]) => Ok((dpt, empl)),
+        Err(fields) => Err(format!("expected 2 fields, found {}", fields.len())),
+    }
+}
+
+// Parses the field at the start of `s`, returning it and what follows it.
+fn parse_field(s: &str) -> Result<(String, &str), String> {
+    let trimmed = s.trim_start();
+    let Some(quoted) = trimmed.strip_prefix('"') else {
+        let end = s.find(',').unwrap_or(s.len());
+        return Ok((s[..end].trim().to_owned(), &s[end..]));
+    };
+
+    let mut field = String::new();
+    let mut chars = quoted.char_indices();
+    while let Some((i, c)) = chars.next() {
+        if c != '"' {
+            field.push(c);
+            continue;
+        }
+        if quoted[i + 1..].starts_with('"') {
+            field.push('"');
+            chars.next();
+            continue;
+        }
+        let after = quoted[i + 1..].trim_start();
+        if !after.is_empty() && !after.starts_with(',') {
+            return Err("unexpected text after a quoted field".to_owned());
+        }
+        return Ok((field, after));
+    }
+    Err("unterminated quote".to_owned())
+}
+
+fn quote(field: &str) -> String {
+    if field.contains([',', '"']) || field.trim() != field {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn import_reports_bad_rows() {
+        let mut db = Db::new();
+        let src = "department,employee\n\
+                   Eng,Sally\n\
+                   \n\
+                   \"Sales, EU\" , \"Amir \"\"A\"\"\"\n\
+                   Eng\n\
+                   Eng,Sally\n\
+                   ,Bob\n\
+                   Ops,\"Eve\n\
+                   Ops,\"Eve\" x\n\
+                   Ops,Eve,Kim\n\
+                   Ops, Kim \n";
+        let (added, errors) = import(&mut db, src);
+        assert_eq!(added, 3);
+        let errors: Vec<_> = errors.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            errors,
+            [
+                "line 5: expected 2 fields, found 1",
+                "line 6: Sally is already in Eng",
+                "line 7: missing department",
+                "line 8: unterminated quote",
+                "line 9: unexpected text after a quoted field",
+                "line 10: expected 2 fields, found 3",
+            ]
+        );
+        assert!(db.get_empls("Sales, EU").eq(["Amir \"A\""]));
+        assert!(db.get_empls("Ops").eq(["Kim"]));
+    }
+
+    #[test]
+    fn export_round_trips() {
+        let mut db = Db::new();
+        for (dpt, empl) in [("Sales", "Amir"), ("Eng", "Sally"), ("R,D", " \"Bob\"")] {
+            db.add_empl(dpt.to_owned(), empl.to_owned());
+        }
+        let exported = export(&db);
+        assert_eq!(
+            exported,
+            "department,employee\nEng,Sally\n\"R,D\",\" \"\"Bob\"\"\"\nSales,Amir\n"
+        );
+
+        let mut imported = Db::new();
+        assert_eq!(import(&mut imported, &exported), (3, vec![]));
+        assert_eq!(export(&imported), exported);
+    }
+}
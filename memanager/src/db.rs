@@ -1,7 +1,16 @@
-use std::collections::{hash_map::Entry, HashMap};
+use std::collections::{btree_map::Entry, BTreeMap};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::ops::Bound;
+use std::path::Path;
 
+use crate::query::{DptLookup, Query};
+
+/// Departments and their employees, both kept sorted, so that everything
+/// is listed in alphabetical order.
 pub struct Db {
-    db: HashMap<String, Vec<String>>,
+    // The employees are sorted as well, and a department has at least one.
+    db: BTreeMap<String, Vec<String>>,
 }
 
 pub enum AddEmplResult {
@@ -9,9 +18,56 @@ pub enum AddEmplResult {
     AlreadyExists,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemoveEmplResult {
+    Removed,
+    NoSuchDepartment,
+    NoSuchEmployee,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveEmplResult {
+    Moved,
+    NotInSource,
+    AlreadyInTarget,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenameDptResult {
+    /// the number of employees in the renamed department.
+    Renamed(usize),
+    NoSuchDepartment,
+    AlreadyExists,
+}
+
 impl Db {
     pub fn new() -> Self {
-        Self { db: HashMap::new() }
+        Self {
+            db: BTreeMap::new(),
+        }
+    }
+
+    /// loads a database written by `save`.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = BufReader::new(File::open(path)?);
+        let saved: BTreeMap<String, Vec<String>> = serde_json::from_reader(file)?;
+        // Adding them one by one drops the duplicates and the empty
+        // departments of a file that was edited by hand.
+        let mut db = Self::new();
+        for (dpt, empls) in saved {
+            for empl in empls {
+                db.add_empl(dpt.clone(), empl);
+            }
+        }
+        Ok(db)
+    }
+
+    /// writes the departments and their employees to `path` as a JSON
+    /// object, replacing whatever is there.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut file, &self.db)?;
+        file.flush()
     }
 
     /// adds an employee to a new department.
@@ -19,11 +75,12 @@ impl Db {
         match self.db.entry(dpt) {
             Entry::Occupied(mut o) => {
                 let empls = o.get_mut();
-                if empls.contains(&empl) {
-                    AddEmplResult::AlreadyExists
-                } else {
-                    empls.push(empl);
-                    AddEmplResult::Added
+                match empls.binary_search(&empl) {
+                    Ok(_) => AddEmplResult::AlreadyExists,
+                    Err(i) => {
+                        empls.insert(i, empl);
+                        AddEmplResult::Added
+                    }
                 }
             }
             Entry::Vacant(v) => {
@@ -33,21 +90,122 @@ impl Db {
         }
     }
 
-    // get all employees.
+    /// removes an employee from a department. a department without
+    /// employees is removed as well.
+    pub fn remove_empl(&mut self, dpt: &str, empl: &str) -> RemoveEmplResult {
+        let empls = match self.db.get_mut(dpt) {
+            Some(empls) => empls,
+            None => return RemoveEmplResult::NoSuchDepartment,
+        };
+        match empls.binary_search_by(|e| e.as_str().cmp(empl)) {
+            Ok(i) => {
+                empls.remove(i);
+                if empls.is_empty() {
+                    self.db.remove(dpt);
+                }
+                RemoveEmplResult::Removed
+            }
+            Err(_) => RemoveEmplResult::NoSuchEmployee,
+        }
+    }
+
+    /// moves an employee to another department, or leaves both departments
+    /// alone if it can't.
+    pub fn move_empl(&mut self, empl: &str, from: &str, to: &str) -> MoveEmplResult {
+        if !self.get_empls(from).any(|e| e == empl) {
+            return MoveEmplResult::NotInSource;
+        }
+        if self.get_empls(to).any(|e| e == empl) {
+            return MoveEmplResult::AlreadyInTarget;
+        }
+        self.remove_empl(from, empl);
+        self.add_empl(to.to_owned(), empl.to_owned());
+        MoveEmplResult::Moved
+    }
+
+    /// gives a department a name that isn't taken yet.
+    pub fn rename_dpt(&mut self, dpt: &str, new_name: String) -> RenameDptResult {
+        if self.db.contains_key(&new_name) {
+            return RenameDptResult::AlreadyExists;
+        }
+        match self.db.remove(dpt) {
+            Some(empls) => {
+                let count = empls.len();
+                self.db.insert(new_name, empls);
+                RenameDptResult::Renamed(count)
+            }
+            None => RenameDptResult::NoSuchDepartment,
+        }
+    }
+
+    /// moves all employees of a department to another one, which is created
+    /// if it doesn't exist. returns how many employees were added to `into`,
+    /// which doesn't count the ones that were already there, or None if
+    /// there's no such department as `from`.
+    pub fn merge_dpts(&mut self, from: &str, into: String) -> Option<usize> {
+        if from == into {
+            return self.db.contains_key(from).then_some(0);
+        }
+        let empls = self.db.remove(from)?;
+        let mut added = 0;
+        for empl in empls {
+            if let AddEmplResult::Added = self.add_empl(into.clone(), empl) {
+                added += 1;
+            }
+        }
+        Some(added)
+    }
+
+    /// the number of employees, counting the ones in several departments
+    /// once per department.
+    pub fn len(&self) -> usize {
+        self.db.values().map(Vec::len).sum()
+    }
+
+    #[cfg(test)]
+    pub fn is_empty(&self) -> bool {
+        self.db.is_empty()
+    }
+
+    // get all departments, none of which is empty, sorted.
+    #[cfg(test)]
+    pub fn get_dpts(&self) -> impl Iterator<Item = &str> {
+        self.db.keys().map(|d| &**d)
+    }
+
+    // get all employees, sorted by department and then name.
     pub fn get_all_empls(&self) -> impl Iterator<Item = &str> {
         self.db
             .iter()
             .flat_map(|(_dpt, empls)| empls.iter().map(|e| &**e))
     }
 
-    // get all employees with their department.
+    // get all employees with their department, sorted by department and
+    // then name.
     pub fn get_all_dpt_empls(&self) -> impl Iterator<Item = (&str, &str)> {
         self.db
             .iter()
             .flat_map(|(dpt, empls)| empls.iter().map(|e| (&**dpt, &**e)))
     }
 
-    // get employees of a particular department.
+    /// the employees that match `query`, with their department, sorted by
+    /// department and then name.
+    pub fn find(&self, query: &Query) -> Vec<(&str, &str)> {
+        let dpts: Box<dyn Iterator<Item = (&String, &Vec<String>)>> = match query.dpt_lookup() {
+            DptLookup::Exact(dpt) => Box::new(self.db.get_key_value(dpt).into_iter()),
+            DptLookup::Prefix(prefix) => Box::new(
+                self.db
+                    .range::<str, _>((Bound::Included(prefix), Bound::Unbounded))
+                    .take_while(move |(dpt, _)| dpt.starts_with(prefix)),
+            ),
+            DptLookup::All => Box::new(self.db.iter()),
+        };
+        dpts.flat_map(|(dpt, empls)| empls.iter().map(move |empl| (&**dpt, &**empl)))
+            .filter(|(dpt, empl)| query.matches(dpt, empl))
+            .collect()
+    }
+
+    // get employees of a particular department, sorted.
     pub fn get_empls(&self, dpt: &str) -> Box<dyn Iterator<Item = &str> + '_> {
         match self.db.get(dpt) {
             Some(empls) => Box::new(empls.iter().map(|e| &**e)),
@@ -55,3 +213,302 @@ impl Db {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use std::collections::{HashMap, HashSet};
+
+    // What Db is expected to do, without caring about the order of anything.
+    #[derive(Default)]
+    struct Model {
+        dpts: HashMap<String, HashSet<String>>,
+    }
+
+    #[derive(Debug, Clone)]
+    enum Op {
+        Add(String, String),
+        Remove(String, String),
+        Move(String, String, String),
+        Rename(String, String),
+        Merge(String, String),
+    }
+
+    impl Model {
+        fn apply(&mut self, op: &Op) {
+            match op {
+                Op::Add(dpt, empl) => {
+                    self.dpts
+                        .entry(dpt.clone())
+                        .or_default()
+                        .insert(empl.clone());
+                }
+                Op::Remove(dpt, empl) => self.remove(dpt, empl),
+                Op::Move(empl, from, to) => {
+                    let in_from = self.dpts.get(from).is_some_and(|e| e.contains(empl));
+                    let in_to = self.dpts.get(to).is_some_and(|e| e.contains(empl));
+                    if in_from && !in_to {
+                        self.remove(from, empl);
+                        self.dpts
+                            .entry(to.clone())
+                            .or_default()
+                            .insert(empl.clone());
+                    }
+                }
+                Op::Rename(dpt, new_name) => {
+                    if !self.dpts.contains_key(new_name) {
+                        if let Some(empls) = self.dpts.remove(dpt) {
+                            self.dpts.insert(new_name.clone(), empls);
+                        }
+                    }
+                }
+                Op::Merge(from, into) if from != into => {
+                    if let Some(empls) = self.dpts.remove(from) {
+                        self.dpts.entry(into.clone()).or_default().extend(empls);
+                    }
+                }
+                Op::Merge(..) => (),
+            }
+        }
+
+        fn remove(&mut self, dpt: &str, empl: &str) {
+            if let Some(empls) = self.dpts.get_mut(dpt) {
+                empls.remove(empl);
+                if empls.is_empty() {
+                    self.dpts.remove(dpt);
+                }
+            }
+        }
+    }
+
+    fn apply(db: &mut Db, op: &Op) {
+        match op.clone() {
+            Op::Add(dpt, empl) => {
+                db.add_empl(dpt, empl);
+            }
+            Op::Remove(dpt, empl) => {
+                db.remove_empl(&dpt, &empl);
+            }
+            Op::Move(empl, from, to) => {
+                db.move_empl(&empl, &from, &to);
+            }
+            Op::Rename(dpt, new_name) => {
+                db.rename_dpt(&dpt, new_name);
+            }
+            Op::Merge(from, into) => {
+                db.merge_dpts(&from, into);
+            }
+        }
+    }
+
+    fn sorted<'a>(it: impl Iterator<Item = &'a str>) -> Vec<&'a str> {
+        let mut v: Vec<_> = it.collect();
+        v.sort_unstable();
+        v
+    }
+
+    // Db lists everything sorted, so its output isn't sorted here.
+    fn assert_matches(db: &Db, model: &Model) {
+        let dpts: Vec<_> = db.get_dpts().collect();
+        assert_eq!(dpts, sorted(model.dpts.keys().map(|d| &**d)));
+        assert_eq!(
+            db.len(),
+            model.dpts.values().map(HashSet::len).sum::<usize>()
+        );
+        assert_eq!(db.is_empty(), model.dpts.is_empty());
+
+        for dpt in dpts {
+            let empls: Vec<_> = db.get_empls(dpt).collect();
+            assert_eq!(empls, sorted(model.dpts[dpt].iter().map(|e| &**e)));
+        }
+
+        let all: Vec<_> = db.get_all_dpt_empls().collect();
+        let mut expected: Vec<_> = model
+            .dpts
+            .iter()
+            .flat_map(|(d, empls)| empls.iter().map(move |e| (&**d, &**e)))
+            .collect();
+        expected.sort_unstable();
+        assert_eq!(all, expected);
+        assert!(db.get_all_empls().eq(all.iter().map(|(_, e)| *e)));
+        assert_eq!(db.find(&Query::parse("name=*").unwrap()), all);
+    }
+
+    // Few names, so that operations often hit existing employees and
+    // departments.
+    fn dpt() -> impl Strategy<Value = String> {
+        prop::sample::select(vec!["Eng", "Sales", "Ops", "Hr"]).prop_map(String::from)
+    }
+
+    fn empl() -> impl Strategy<Value = String> {
+        prop::sample::select(vec!["Amir", "Sally", "Bob", "Eve", "Kim"]).prop_map(String::from)
+    }
+
+    fn op() -> impl Strategy<Value = Op> {
+        prop_oneof![
+            3 => (dpt(), empl()).prop_map(|(d, e)| Op::Add(d, e)),
+            2 => (dpt(), empl()).prop_map(|(d, e)| Op::Remove(d, e)),
+            2 => (empl(), dpt(), dpt()).prop_map(|(e, from, to)| Op::Move(e, from, to)),
+            1 => (dpt(), dpt()).prop_map(|(d, new_name)| Op::Rename(d, new_name)),
+            1 => (dpt(), dpt()).prop_map(|(from, into)| Op::Merge(from, into)),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn db_matches_model(ops in prop::collection::vec(op(), 0..64)) {
+            let mut db = Db::new();
+            let mut model = Model::default();
+            for op in &ops {
+                apply(&mut db, op);
+                model.apply(op);
+                assert_matches(&db, &model);
+            }
+        }
+    }
+
+    #[test]
+    fn results_tell_what_happened() {
+        let mut db = Db::new();
+        db.add_empl("Eng".to_owned(), "Sally".to_owned());
+        db.add_empl("Sales".to_owned(), "Sally".to_owned());
+        db.add_empl("Sales".to_owned(), "Amir".to_owned());
+
+        assert_eq!(
+            db.remove_empl("Ops", "Sally"),
+            RemoveEmplResult::NoSuchDepartment
+        );
+        assert_eq!(
+            db.remove_empl("Eng", "Amir"),
+            RemoveEmplResult::NoSuchEmployee
+        );
+        assert_eq!(
+            db.move_empl("Amir", "Eng", "Ops"),
+            MoveEmplResult::NotInSource
+        );
+        assert_eq!(
+            db.move_empl("Sally", "Eng", "Sales"),
+            MoveEmplResult::AlreadyInTarget
+        );
+        assert_eq!(db.move_empl("Amir", "Sales", "Eng"), MoveEmplResult::Moved);
+
+        assert_eq!(
+            db.rename_dpt("Eng", "Sales".to_owned()),
+            RenameDptResult::AlreadyExists
+        );
+        assert_eq!(
+            db.rename_dpt("Ops", "Hr".to_owned()),
+            RenameDptResult::NoSuchDepartment
+        );
+        assert_eq!(
+            db.rename_dpt("Eng", "Dev".to_owned()),
+            RenameDptResult::Renamed(2)
+        );
+
+        // Sally is in both.
+        assert_eq!(db.merge_dpts("Sales", "Dev".to_owned()), Some(0));
+        assert_eq!(db.merge_dpts("Sales", "Dev".to_owned()), None);
+        assert_eq!(sorted(db.get_dpts()), ["Dev"]);
+        assert_eq!(db.len(), 2);
+    }
+
+    #[test]
+    fn lists_are_sorted() {
+        let mut db = Db::new();
+        for (dpt, empl) in [
+            ("Sales", "Zoe"),
+            ("Eng", "Sally"),
+            ("Sales", "Amir"),
+            ("Eng", "Bob"),
+            ("Eng", "alice"),
+            ("Ops", "Eve"),
+        ] {
+            db.add_empl(dpt.to_owned(), empl.to_owned());
+        }
+        db.move_empl("Eve", "Ops", "Eng");
+        db.rename_dpt("Sales", "Accounts".to_owned());
+
+        assert!(db.get_dpts().eq(["Accounts", "Eng"]));
+        // Uppercase letters sort before lowercase ones.
+        assert!(db.get_empls("Eng").eq(["Bob", "Eve", "Sally", "alice"]));
+        assert!(db.get_all_dpt_empls().eq([
+            ("Accounts", "Amir"),
+            ("Accounts", "Zoe"),
+            ("Eng", "Bob"),
+            ("Eng", "Eve"),
+            ("Eng", "Sally"),
+            ("Eng", "alice"),
+        ]));
+    }
+
+    #[test]
+    fn find_uses_the_dpt_index() {
+        let mut db = Db::new();
+        for (dpt, empl) in [
+            ("Engineering", "Sally"),
+            ("Engineering", "Alice"),
+            ("Eng", "Bob"),
+            ("Sales", "Ali"),
+            ("Ops", "Eve"),
+        ] {
+            db.add_empl(dpt.to_owned(), empl.to_owned());
+        }
+
+        let find = |query: &str| db.find(&Query::parse(query).unwrap());
+        assert_eq!(
+            find(r#"dept=Eng* AND name~"ali""#),
+            [("Engineering", "Alice")]
+        );
+        assert_eq!(
+            find("dept=Eng*"),
+            [
+                ("Eng", "Bob"),
+                ("Engineering", "Alice"),
+                ("Engineering", "Sally")
+            ]
+        );
+        assert_eq!(find("dept=Eng"), [("Eng", "Bob")]);
+        assert_eq!(find("dept=En"), []);
+        assert_eq!(
+            find("name~ali OR dept=Ops"),
+            [("Engineering", "Alice"), ("Ops", "Eve"), ("Sales", "Ali")]
+        );
+    }
+
+    #[test]
+    fn save_and_load() {
+        let path = std::env::temp_dir().join(format!("memanager-{}.json", std::process::id()));
+        let mut db = Db::new();
+        db.add_empl("Eng".to_owned(), "Sally".to_owned());
+        db.add_empl("Eng".to_owned(), "Bob".to_owned());
+        db.add_empl("Sales".to_owned(), "Amir".to_owned());
+
+        db.save(&path).unwrap();
+        let loaded = Db::load(&path).unwrap();
+        assert_eq!(
+            loaded.get_empls("Eng").collect::<Vec<_>>(),
+            ["Bob", "Sally"]
+        );
+        assert_eq!(
+            loaded.find(&Query::parse("dept=*").unwrap()),
+            db.find(&Query::parse("dept=*").unwrap())
+        );
+
+        std::fs::write(&path, r#"{"Eng": ["Sally", "Sally"], "Ops": []}"#).unwrap();
+        let loaded = Db::load(&path).unwrap();
+        assert_eq!(sorted(loaded.get_dpts()), ["Eng"]);
+        assert_eq!(loaded.len(), 1);
+
+        std::fs::write(&path, "[]").unwrap();
+        assert_eq!(
+            Db::load(&path).err().unwrap().kind(),
+            io::ErrorKind::InvalidData
+        );
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            Db::load(&path).err().unwrap().kind(),
+            io::ErrorKind::NotFound
+        );
+    }
+}
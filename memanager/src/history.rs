@@ -0,0 +1,271 @@
+//! The journal behind `Undo` and `Redo`. The commands that change the
+//! database record what they changed, and undoing a change applies its
+//! inverse. Making a new change after undoing some forgets the undone ones,
+//! as they can't be redone on top of it.
+//!
+//! `Import`, the merges of `Rename ... MERGE` and the plugins aren't
+//! journaled. When they change the database, they clear the journal: the
+//! changes before them might not undo the same way anymore, e.g. undoing a
+//! rename would carry the imported employees along.
+use std::fmt;
+
+use crate::db::{AddEmplResult, Db, MoveEmplResult, RemoveEmplResult, RenameDptResult};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    Added {
+        dpt: String,
+        empl: String,
+    },
+    Removed {
+        dpt: String,
+        empl: String,
+    },
+    Moved {
+        empl: String,
+        from: String,
+        to: String,
+    },
+    Renamed {
+        from: String,
+        to: String,
+    },
+}
+
+impl Change {
+    // Makes the change, returning whether it could. It can't once the
+    // database has changed in ways the journal doesn't know of.
+    fn apply(&self, db: &mut Db) -> bool {
+        match self {
+            Change::Added { dpt, empl } => {
+                matches!(db.add_empl(dpt.clone(), empl.clone()), AddEmplResult::Added)
+            }
+            Change::Removed { dpt, empl } => db.remove_empl(dpt, empl) == RemoveEmplResult::Removed,
+            Change::Moved { empl, from, to } => {
+                db.move_empl(empl, from, to) == MoveEmplResult::Moved
+            }
+            Change::Renamed { from, to } => {
+                matches!(db.rename_dpt(from, to.clone()), RenameDptResult::Renamed(_))
+            }
+        }
+    }
+
+    fn inverse(&self) -> Change {
+        match self.clone() {
+            Change::Added { dpt, empl } => Change::Removed { dpt, empl },
+            Change::Removed { dpt, empl } => Change::Added { dpt, empl },
+            Change::Moved { empl, from, to } => Change::Moved {
+                empl,
+                from: to,
+                to: from,
+            },
+            Change::Renamed { from, to } => Change::Renamed { from: to, to: from },
+        }
+    }
+}
+
+/// The change as the command that made it.
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Added { dpt, empl } => write!(f, "Add {} to {}", empl, dpt),
+            Change::Removed { dpt, empl } => write!(f, "Remove {} from {}", empl, dpt),
+            Change::Moved { empl, from, to } => write!(f, "Move {} from {} to {}", empl, from, to),
+            Change::Renamed { from, to } => write!(f, "Rename {} to {}", from, to),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum UndoError {
+    /// There's no change to undo, or to redo.
+    Empty,
+    /// The change doesn't apply anymore, and is dropped from the journal.
+    Conflict(Change),
+}
+
+impl fmt::Display for UndoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UndoError::Empty => write!(f, "nothing to do"),
+            UndoError::Conflict(change) => {
+                write!(f, "`{}` doesn't apply to the data anymore", change)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct History {
+    undo: Vec<Change>,
+    redo: Vec<Change>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// records a change that was just made to the database.
+    pub fn record(&mut self, change: Change) {
+        self.undo.push(change);
+        self.redo.clear();
+    }
+
+    /// forgets every change, after the database was changed behind the
+    /// journal's back.
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+
+    /// reverts the latest change, and returns it.
+    pub fn undo(&mut self, db: &mut Db) -> Result<&Change, UndoError> {
+        let change = self.undo.pop().ok_or(UndoError::Empty)?;
+        if !change.inverse().apply(db) {
+            return Err(UndoError::Conflict(change));
+        }
+        self.redo.push(change);
+        Ok(self.redo.last().unwrap())
+    }
+
+    /// makes the latest undone change again, and returns it.
+    pub fn redo(&mut self, db: &mut Db) -> Result<&Change, UndoError> {
+        let change = self.redo.pop().ok_or(UndoError::Empty)?;
+        if !change.apply(db) {
+            return Err(UndoError::Conflict(change));
+        }
+        self.undo.push(change);
+        Ok(self.undo.last().unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::Query;
+
+    fn added(dpt: &str, empl: &str) -> Change {
+        Change::Added {
+            dpt: dpt.to_owned(),
+            empl: empl.to_owned(),
+        }
+    }
+
+    // Makes a change through the history, like the commands do.
+    fn make(db: &mut Db, history: &mut History, change: Change) {
+        assert!(change.apply(db), "{}", change);
+        history.record(change);
+    }
+
+    fn rows(db: &Db) -> Vec<(String, String)> {
+        db.find(&Query::parse("dept=*").unwrap())
+            .into_iter()
+            .map(|(d, e)| (d.to_owned(), e.to_owned()))
+            .collect()
+    }
+
+    fn row(dpt: &str, empl: &str) -> (String, String) {
+        (dpt.to_owned(), empl.to_owned())
+    }
+
+    #[test]
+    fn undo_and_redo_every_change() {
+        let mut db = Db::new();
+        let mut history = History::new();
+        make(&mut db, &mut history, added("Eng", "Sally"));
+        make(&mut db, &mut history, added("Eng", "Bob"));
+        make(
+            &mut db,
+            &mut history,
+            Change::Moved {
+                empl: "Sally".to_owned(),
+                from: "Eng".to_owned(),
+                to: "Sales".to_owned(),
+            },
+        );
+        make(
+            &mut db,
+            &mut history,
+            Change::Removed {
+                dpt: "Eng".to_owned(),
+                empl: "Bob".to_owned(),
+            },
+        );
+        make(
+            &mut db,
+            &mut history,
+            Change::Renamed {
+                from: "Sales".to_owned(),
+                to: "Ops".to_owned(),
+            },
+        );
+        let end = rows(&db);
+        assert_eq!(end, [row("Ops", "Sally")]);
+
+        let mut states = vec![];
+        while let Ok(change) = history.undo(&mut db) {
+            states.push((change.to_string(), rows(&db)));
+        }
+        assert_eq!(
+            states,
+            [
+                (
+                    "Rename Sales to Ops".to_owned(),
+                    vec![row("Sales", "Sally")]
+                ),
+                (
+                    "Remove Bob from Eng".to_owned(),
+                    vec![row("Eng", "Bob"), row("Sales", "Sally")]
+                ),
+                (
+                    "Move Sally from Eng to Sales".to_owned(),
+                    vec![row("Eng", "Bob"), row("Eng", "Sally")]
+                ),
+                ("Add Bob to Eng".to_owned(), vec![row("Eng", "Sally")]),
+                ("Add Sally to Eng".to_owned(), vec![]),
+            ]
+        );
+        assert_eq!(history.undo(&mut db), Err(UndoError::Empty));
+
+        while history.redo(&mut db).is_ok() {}
+        assert_eq!(rows(&db), end);
+    }
+
+    #[test]
+    fn new_changes_forget_the_undone_ones() {
+        let mut db = Db::new();
+        let mut history = History::new();
+        make(&mut db, &mut history, added("Eng", "Sally"));
+        make(&mut db, &mut history, added("Eng", "Bob"));
+
+        assert_eq!(history.undo(&mut db), Ok(&added("Eng", "Bob")));
+        make(&mut db, &mut history, added("Ops", "Eve"));
+        assert_eq!(history.redo(&mut db), Err(UndoError::Empty));
+        assert_eq!(rows(&db), [row("Eng", "Sally"), row("Ops", "Eve")]);
+
+        assert_eq!(history.undo(&mut db), Ok(&added("Ops", "Eve")));
+        assert_eq!(history.undo(&mut db), Ok(&added("Eng", "Sally")));
+        assert_eq!(history.redo(&mut db), Ok(&added("Eng", "Sally")));
+        assert_eq!(rows(&db), [row("Eng", "Sally")]);
+        assert_eq!(history.redo(&mut db), Ok(&added("Ops", "Eve")));
+        assert_eq!(rows(&db), [row("Eng", "Sally"), row("Ops", "Eve")]);
+    }
+
+    #[test]
+    fn changes_that_no_longer_apply_are_dropped() {
+        let mut db = Db::new();
+        let mut history = History::new();
+        make(&mut db, &mut history, added("Eng", "Sally"));
+        make(&mut db, &mut history, added("Eng", "Bob"));
+        // Behind the journal's back, like an import.
+        db.remove_empl("Eng", "Bob");
+
+        assert_eq!(
+            history.undo(&mut db),
+            Err(UndoError::Conflict(added("Eng", "Bob")))
+        );
+        assert_eq!(history.undo(&mut db), Ok(&added("Eng", "Sally")));
+        assert!(db.is_empty());
+    }
+}
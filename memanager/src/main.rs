@@ -7,34 +7,86 @@
 //! For example:
 //! `Add Sally to Engineering`
 //! `Add Amir to Sales`
+//! `Add "Mary Jane" to "Customer Success"`
+//! `Remove Amir from Sales`
+//! `Move Sally from Engineering to Sales`
+//! `Rename Sales to Accounts`
+//! `Rename Engineering to Accounts MERGE`
 //! `List All`
 //! `List Engineering`
+//! `Promote Sally in Engineering`
+//! `Find dept=Eng* AND name~"ali" PAGE 2`
+//! `Find name%salyl`
+//! `Import employees.csv`
+//! `Export employees.csv`
+//! `Undo`
+//! `Redo`
 //! `Close`
+//!
+//! The database is saved to `memanager.json`, or to the file given as the
+//! only argument, on `Close`, and loaded from it at startup if it exists.
 
 use std::error::Error;
 use std::io;
+use std::path::Path;
 
 mod cmd;
 
+mod csv;
+
 mod db;
 use db::Db;
 
+mod history;
+use history::History;
+
+mod plugin;
+use plugin::Registry;
+
+mod promote;
+use promote::Promote;
+
+mod query;
+
 // Employee, Department => HashMap<Department, Employee>
 
+const DB_PATH: &str = "memanager.json";
+
 fn main() -> Result<(), Box<dyn Error>> {
-    let mut db = Db::new();
+    let path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| DB_PATH.to_owned());
+    let mut db = if Path::new(&path).exists() {
+        Db::load(&path).map_err(|e| format!("couldn't load {}: {}", path, e))?
+    } else {
+        Db::new()
+    };
+    let mut plugins = Registry::new();
+    plugins.register(Promote);
+    let mut history = History::new();
 
     loop {
         let mut buffer = String::new();
 
         println!("Enter your command =>");
-        io::stdin().read_line(&mut buffer)?;
+        // The input ended, e.g. on Ctrl-D or a piped script without close,
+        // which is a close too.
+        if io::stdin().read_line(&mut buffer)? == 0 {
+            break;
+        }
 
         // parse a command out of string.
-        if !cmd::parse(&buffer).exec(&mut db) {
-            break;
+        match cmd::parse(&buffer, &plugins) {
+            Ok(cmd) => {
+                if !cmd.exec(&mut db, &plugins, &mut history) {
+                    break;
+                }
+            }
+            Err(e) => println!("{}\n", e),
         }
     }
 
+    db.save(&path)
+        .map_err(|e| format!("couldn't save {}: {}", path, e))?;
     Ok(())
 }
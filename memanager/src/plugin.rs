@@ -0,0 +1,155 @@
+//! Commands that aren't built into the parser. A plugin is asked to parse
+//! every command whose verb isn't one of the built-in ones, and executes
+//! the commands it parsed.
+use crate::db::{AddEmplResult, Db};
+
+pub trait CommandPlugin {
+    /// Parses the words following `verb`, or returns None if the verb isn't
+    /// handled by this plugin. An error is shown to the user as is.
+    fn parse(&self, verb: &str, args: &[&str]) -> Option<Result<Vec<String>, String>>;
+
+    /// Executes a command with the arguments returned by `parse`, and
+    /// returns the message to show to the user.
+    fn exec(&self, args: Vec<String>, db: &mut Employees<'_>) -> String;
+}
+
+/// What plugins get to do with the database. Employees can be added and
+/// listed, but nothing a plugin does can lose data.
+pub struct Employees<'a> {
+    db: &'a mut Db,
+}
+
+impl Employees<'_> {
+    pub fn add_empl(&mut self, dpt: &str, empl: &str) -> AddEmplResult {
+        self.db.add_empl(dpt.to_owned(), empl.to_owned())
+    }
+
+    pub fn get_empls(&self, dpt: &str) -> impl Iterator<Item = &str> {
+        self.db.get_empls(dpt)
+    }
+}
+
+/// Plugins are asked to parse a command in the order they were registered,
+/// and the first one that recognizes the verb gets it.
+#[derive(Default)]
+pub struct Registry {
+    plugins: Vec<Box<dyn CommandPlugin>>,
+}
+
+/// Identifies the plugin that parsed a command, and executes it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PluginId(usize);
+
+impl Registry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&mut self, plugin: impl CommandPlugin + 'static) {
+        self.plugins.push(Box::new(plugin));
+    }
+
+    pub(crate) fn parse(
+        &self,
+        verb: &str,
+        args: &[&str],
+    ) -> Option<Result<(PluginId, Vec<String>), String>> {
+        self.plugins.iter().enumerate().find_map(|(i, plugin)| {
+            plugin
+                .parse(verb, args)
+                .map(|parsed| parsed.map(|args| (PluginId(i), args)))
+        })
+    }
+
+    pub(crate) fn exec(&self, id: PluginId, args: Vec<String>, db: &mut Db) -> String {
+        self.plugins[id.0].exec(args, &mut Employees { db })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmd::{self, Cmd, ParseError};
+    use crate::promote::Promote;
+
+    // Claims every verb, to check the order plugins are asked in.
+    struct Anything;
+
+    impl CommandPlugin for Anything {
+        fn parse(&self, verb: &str, _args: &[&str]) -> Option<Result<Vec<String>, String>> {
+            Some(Ok(vec![verb.to_owned()]))
+        }
+
+        fn exec(&self, args: Vec<String>, _db: &mut Employees<'_>) -> String {
+            args.join(" ")
+        }
+    }
+
+    fn registry() -> Registry {
+        let mut plugins = Registry::new();
+        plugins.register(Promote);
+        plugins.register(Anything);
+        plugins
+    }
+
+    #[test]
+    fn unknown_verbs_go_to_the_first_plugin_that_parses_them() {
+        let plugins = registry();
+
+        match cmd::parse("Promote Sally in Engineering", &plugins) {
+            Ok(Cmd::Plugin { id, args }) => {
+                assert_eq!(id, PluginId(0));
+                assert_eq!(args, ["Sally", "Engineering"]);
+            }
+            _ => panic!("expected a plugin command"),
+        }
+        match cmd::parse("Fire Sally", &plugins) {
+            Ok(Cmd::Plugin { id, .. }) => assert_eq!(id, PluginId(1)),
+            _ => panic!("expected a plugin command"),
+        }
+    }
+
+    #[test]
+    fn builtin_verbs_take_precedence() {
+        assert!(matches!(
+            cmd::parse("List All", &registry()),
+            Ok(Cmd::ListAll)
+        ));
+    }
+
+    #[test]
+    fn parse_errors_are_reported_like_builtin_ones() {
+        match cmd::parse("Promote Sally", &registry()) {
+            Err(e) => assert_eq!(e.to_string(), "usage: Promote NAME in DEPT"),
+            _ => panic!("expected a parse error"),
+        }
+        assert!(matches!(
+            cmd::parse("Promote Sally", &Registry::new()),
+            Err(ParseError::UnknownCommand { .. })
+        ));
+    }
+
+    #[test]
+    fn exec_has_access_to_the_db() {
+        let plugins = registry();
+        let mut db = Db::new();
+        db.add_empl("Engineering".to_owned(), "Sally".to_owned());
+
+        let (id, args) = plugins
+            .parse("Promote", &["Sally", "in", "Engineering"])
+            .unwrap()
+            .unwrap();
+        assert_eq!(plugins.exec(id, args.clone(), &mut db), "promoted");
+        assert_eq!(plugins.exec(id, args, &mut db), "already a lead");
+        assert_eq!(
+            db.get_empls("Engineering-leads").collect::<Vec<_>>(),
+            ["Sally"]
+        );
+
+        let (id, args) = plugins
+            .parse("Promote", &["Amir", "in", "Engineering"])
+            .unwrap()
+            .unwrap();
+        assert_eq!(plugins.exec(id, args, &mut db), "Amir isn't in Engineering");
+    }
+}
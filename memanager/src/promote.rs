@@ -0,0 +1,30 @@
+//! An example of a command added through a plugin.
+use crate::db::AddEmplResult;
+use crate::plugin::{CommandPlugin, Employees};
+
+/// `Promote NAME in DEPT` adds NAME to the leads of DEPT, which is just
+/// another department, if they're already in DEPT.
+pub struct Promote;
+
+impl CommandPlugin for Promote {
+    fn parse(&self, verb: &str, args: &[&str]) -> Option<Result<Vec<String>, String>> {
+        if verb != "Promote" {
+            return None;
+        }
+        Some(match args {
+            [empl, "in", dpt] => Ok(vec![empl.to_string(), dpt.to_string()]),
+            _ => Err("usage: Promote NAME in DEPT".to_owned()),
+        })
+    }
+
+    fn exec(&self, args: Vec<String>, db: &mut Employees<'_>) -> String {
+        let (empl, dpt) = (&args[0], &args[1]);
+        if !db.get_empls(dpt).any(|e| e == empl) {
+            return format!("{} isn't in {}", empl, dpt);
+        }
+        match db.add_empl(&format!("{}-leads", dpt), empl) {
+            AddEmplResult::Added => "promoted".to_owned(),
+            AddEmplResult::AlreadyExists => "already a lead".to_owned(),
+        }
+    }
+}
@@ -0,0 +1,471 @@
+//! The query language of `Find`. A query is made of terms on the department
+//! or the name of an employee, combined with `AND`, `OR`, `NOT` and
+//! parentheses, like `dept=Eng* AND name~"ali"`.
+//!
+//! `=` matches a glob, in which `*` stands for any number of characters and
+//! `?` for a single one. `~` matches a substring, ignoring case, or a regex
+//! between slashes: `name~/^a.*i$/`. `%` matches fuzzily, ignoring case and
+//! allowing a typo, like a missing, extra, wrong or swapped character, for
+//! every four characters of the value, and at least one: `name%salyl`.
+//! Values with spaces or any of `=~%()"/` in them can be quoted. `NOT` binds
+//! tighter than `AND`, which binds tighter than `OR`.
+use std::fmt;
+
+use regex::Regex;
+
+#[derive(Debug)]
+pub struct Query(Expr);
+
+#[derive(Debug)]
+enum Expr {
+    Term(Field, Pattern),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Dept,
+    Name,
+}
+
+#[derive(Debug)]
+enum Pattern {
+    Glob(String),
+    // Lowercased.
+    Contains(String),
+    Regex(Regex),
+    // Lowercased.
+    Fuzzy(String),
+}
+
+/// The departments a query can match at all, so that `Db::find` doesn't
+/// have to look at the others.
+#[derive(Debug, PartialEq, Eq)]
+pub enum DptLookup<'q> {
+    Exact(&'q str),
+    Prefix(&'q str),
+    All,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueryError {
+    Empty,
+    UnexpectedEnd,
+    Unexpected(String),
+    UnknownField(String),
+    UnterminatedString,
+    InvalidRegex(String),
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryError::Empty => write!(f, "`Find` command needs a query"),
+            QueryError::UnexpectedEnd => write!(f, "query ends too early"),
+            QueryError::Unexpected(t) => write!(f, "unexpected `{}` in query", t),
+            QueryError::UnknownField(t) => {
+                write!(f, "unknown field `{}`, expected `dept` or `name`", t)
+            }
+            QueryError::UnterminatedString => write!(f, "unterminated string in query"),
+            QueryError::InvalidRegex(e) => write!(f, "invalid regex in query: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+impl Query {
+    pub fn parse(s: &str) -> Result<Query, QueryError> {
+        let tokens = tokenize(s)?;
+        if tokens.is_empty() {
+            return Err(QueryError::Empty);
+        }
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.or()?;
+        match parser.next() {
+            None => Ok(Query(expr)),
+            Some(t) => Err(QueryError::Unexpected(t.to_string())),
+        }
+    }
+
+    pub fn matches(&self, dpt: &str, empl: &str) -> bool {
+        self.0.matches(dpt, empl)
+    }
+
+    pub fn dpt_lookup(&self) -> DptLookup<'_> {
+        self.0.dpt_lookup()
+    }
+}
+
+impl Expr {
+    fn matches(&self, dpt: &str, empl: &str) -> bool {
+        match self {
+            Expr::Term(field, pattern) => pattern.matches(match field {
+                Field::Dept => dpt,
+                Field::Name => empl,
+            }),
+            Expr::Not(e) => !e.matches(dpt, empl),
+            Expr::And(a, b) => a.matches(dpt, empl) && b.matches(dpt, empl),
+            Expr::Or(a, b) => a.matches(dpt, empl) || b.matches(dpt, empl),
+        }
+    }
+
+    fn dpt_lookup(&self) -> DptLookup<'_> {
+        match self {
+            Expr::Term(Field::Dept, Pattern::Glob(glob)) => match glob.find(['*', '?']) {
+                None => DptLookup::Exact(glob),
+                Some(i) if i == glob.len() - 1 && glob.ends_with('*') => {
+                    DptLookup::Prefix(&glob[..i])
+                }
+                Some(_) => DptLookup::All,
+            },
+            // Either side narrows it down, the narrower one wins.
+            Expr::And(a, b) => match (a.dpt_lookup(), b.dpt_lookup()) {
+                (exact @ DptLookup::Exact(_), _) | (_, exact @ DptLookup::Exact(_)) => exact,
+                (DptLookup::Prefix(a), DptLookup::Prefix(b)) => {
+                    DptLookup::Prefix(if a.len() >= b.len() { a } else { b })
+                }
+                (DptLookup::All, lookup) | (lookup, DptLookup::All) => lookup,
+            },
+            _ => DptLookup::All,
+        }
+    }
+}
+
+impl Pattern {
+    fn matches(&self, s: &str) -> bool {
+        match self {
+            Pattern::Glob(glob) => glob_matches(glob, s),
+            Pattern::Contains(sub) => s.to_lowercase().contains(sub.as_str()),
+            Pattern::Regex(re) => re.is_match(s),
+            Pattern::Fuzzy(v) => {
+                let typos = (v.chars().count() / 4).max(1);
+                edit_distance(v, &s.to_lowercase()) <= typos
+            }
+        }
+    }
+}
+
+// The Levenshtein distance between a and b in characters, in which swapping
+// two neighbouring characters is a single edit as well.
+pub(crate) fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // d[i][j] is the distance between the first i characters of a and the
+    // first j of b.
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    d[0] = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let substituted = d[i - 1][j - 1] + usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = substituted.min(d[i - 1][j] + 1).min(d[i][j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+// Backtracks to the last `*` only, which keeps it linear in practice.
+fn glob_matches(glob: &str, s: &str) -> bool {
+    let glob: Vec<char> = glob.chars().collect();
+    let s: Vec<char> = s.chars().collect();
+    let (mut g, mut i) = (0, 0);
+    // The position after the last `*`, and where in s it started matching.
+    let mut star = None;
+
+    while i < s.len() {
+        match glob.get(g) {
+            Some('*') => {
+                star = Some((g + 1, i));
+                g += 1;
+            }
+            Some(&c) if c == '?' || c == s[i] => {
+                g += 1;
+                i += 1;
+            }
+            _ => match star {
+                Some((after, from)) => {
+                    g = after;
+                    i = from + 1;
+                    star = Some((after, from + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    glob[g..].iter().all(|&c| c == '*')
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Regex(String),
+    Eq,
+    Tilde,
+    Percent,
+    Open,
+    Close,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Word(w) => write!(f, "{}", w),
+            Token::Quoted(q) => write!(f, "\"{}\"", q),
+            Token::Regex(r) => write!(f, "/{}/", r),
+            Token::Eq => write!(f, "="),
+            Token::Tilde => write!(f, "~"),
+            Token::Percent => write!(f, "%"),
+            Token::Open => write!(f, "("),
+            Token::Close => write!(f, ")"),
+        }
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, QueryError> {
+    let mut tokens = Vec::new();
+    let mut chars = s.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '=' => Token::Eq,
+            '~' => Token::Tilde,
+            '%' => Token::Percent,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '"' | '/' => {
+                let rest = &s[i + 1..];
+                let len = rest.find(c).ok_or(QueryError::UnterminatedString)?;
+                // Skips the contents and the closing quote or slash.
+                for _ in rest[..=len].chars() {
+                    chars.next();
+                }
+                let contents = rest[..len].to_owned();
+                if c == '"' {
+                    Token::Quoted(contents)
+                } else {
+                    Token::Regex(contents)
+                }
+            }
+            _ => {
+                let mut end = s.len();
+                while let Some(&(j, c)) = chars.peek() {
+                    if c.is_whitespace() || "=~%()\"/".contains(c) {
+                        end = j;
+                        break;
+                    }
+                    chars.next();
+                }
+                Token::Word(s[i..end].to_owned())
+            }
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        match self.tokens.get(self.pos) {
+            Some(Token::Word(w)) if w == keyword => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, QueryError> {
+        let mut expr = self.and()?;
+        while self.keyword("OR") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, QueryError> {
+        let mut expr = self.not()?;
+        while self.keyword("AND") {
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr, QueryError> {
+        if self.keyword("NOT") {
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        match self.next().ok_or(QueryError::UnexpectedEnd)? {
+            Token::Open => {
+                let expr = self.or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(expr),
+                    Some(t) => Err(QueryError::Unexpected(t.to_string())),
+                    None => Err(QueryError::UnexpectedEnd),
+                }
+            }
+            Token::Word(w) => self.term(&w),
+            t => Err(QueryError::Unexpected(t.to_string())),
+        }
+    }
+
+    fn term(&mut self, field: &str) -> Result<Expr, QueryError> {
+        let field = match field {
+            "dept" => Field::Dept,
+            "name" => Field::Name,
+            _ => return Err(QueryError::UnknownField(field.to_owned())),
+        };
+        let op = self.next().ok_or(QueryError::UnexpectedEnd)?;
+        let value = self.next().ok_or(QueryError::UnexpectedEnd)?;
+        let pattern = match (op, value) {
+            (Token::Eq, Token::Word(v) | Token::Quoted(v)) => Pattern::Glob(v),
+            (Token::Tilde, Token::Word(v) | Token::Quoted(v)) => {
+                Pattern::Contains(v.to_lowercase())
+            }
+            (Token::Percent, Token::Word(v) | Token::Quoted(v)) => Pattern::Fuzzy(v.to_lowercase()),
+            (Token::Tilde, Token::Regex(re)) => Pattern::Regex(
+                Regex::new(&re).map_err(|e| QueryError::InvalidRegex(e.to_string()))?,
+            ),
+            (Token::Eq | Token::Tilde | Token::Percent, t) | (t, _) => {
+                return Err(QueryError::Unexpected(t.to_string()))
+            }
+        };
+        Ok(Expr::Term(field, pattern))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(query: &str, dpt: &str, empl: &str) -> bool {
+        Query::parse(query).unwrap().matches(dpt, empl)
+    }
+
+    #[test]
+    fn globs() {
+        assert!(glob_matches("Eng*", "Engineering"));
+        assert!(glob_matches("Eng*", "Eng"));
+        assert!(glob_matches("*ing", "Engineering"));
+        assert!(glob_matches("E?g*r*g", "Engineering"));
+        assert!(glob_matches("*", ""));
+        assert!(!glob_matches("Eng", "Engineering"));
+        assert!(!glob_matches("?ng", "ng"));
+        assert!(!glob_matches("*x*", "Engineering"));
+    }
+
+    #[test]
+    fn terms() {
+        assert!(matches("dept=Eng*", "Engineering", "Sally"));
+        assert!(!matches("dept=eng*", "Engineering", "Sally"));
+        assert!(matches(r#"name~"ALI""#, "Sales", "Alice"));
+        assert!(matches("name~ice", "Sales", "Alice"));
+        assert!(matches("name~/^A.*e$/", "Sales", "Alice"));
+        assert!(!matches("name~/^a/", "Sales", "Alice"));
+        assert!(matches(
+            r#"dept="Human Resources""#,
+            "Human Resources",
+            "Bob"
+        ));
+    }
+
+    #[test]
+    fn fuzzy() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("zoë", "zoe"), 1);
+        assert_eq!(edit_distance("salyl", "sally"), 1);
+
+        assert!(matches("name%salyl", "Sales", "Sally"));
+        assert!(matches("name%sally", "Sales", "SALLY"));
+        assert!(matches("name%bo", "Sales", "Bob"));
+        assert!(!matches("name%bo", "Sales", "Rob"));
+        assert!(matches(r#"name%"mary jnae""#, "Sales", "Mary Jane"));
+        assert!(!matches("name%sal", "Sales", "Sally"));
+        assert!(matches(
+            "dept%egnineering AND name~al",
+            "Engineering",
+            "Alice"
+        ));
+    }
+
+    #[test]
+    fn operators() {
+        let query = r#"dept=Eng* AND name~"ali""#;
+        assert!(matches(query, "Engineering", "Alice"));
+        assert!(!matches(query, "Sales", "Alice"));
+        assert!(!matches(query, "Engineering", "Bob"));
+
+        // NOT, then AND, then OR.
+        let query = "NOT dept=Sales AND name=Bob OR name=Eve";
+        assert!(matches(query, "Eng", "Bob"));
+        assert!(!matches(query, "Sales", "Bob"));
+        assert!(matches(query, "Sales", "Eve"));
+
+        let query = "NOT (dept=Sales AND name=Bob OR name=Eve)";
+        assert!(!matches(query, "Sales", "Eve"));
+        assert!(matches(query, "Sales", "Sally"));
+    }
+
+    #[test]
+    fn errors() {
+        let err = |query: &str| Query::parse(query).unwrap_err().to_string();
+        assert_eq!(err("  "), "`Find` command needs a query");
+        assert_eq!(err("dept="), "query ends too early");
+        assert_eq!(err("(dept=Eng"), "query ends too early");
+        assert_eq!(err("dept=Eng name=Bob"), "unexpected `name` in query");
+        assert_eq!(err("dept=Eng)"), "unexpected `)` in query");
+        assert_eq!(err("dept=/x/"), "unexpected `/x/` in query");
+        assert_eq!(err("name%/x/"), "unexpected `/x/` in query");
+        assert_eq!(err("dept Eng OR name=Bob"), "unexpected `Eng` in query");
+        assert_eq!(
+            err("age=3"),
+            "unknown field `age`, expected `dept` or `name`"
+        );
+        assert_eq!(err(r#"name~"ali"#), "unterminated string in query");
+        assert!(err("name~/(/").starts_with("invalid regex in query: "));
+    }
+
+    #[test]
+    fn dpt_lookups() {
+        let lookup = |query: &str| Query::parse(query).unwrap().0;
+        assert_eq!(lookup("dept=Eng").dpt_lookup(), DptLookup::Exact("Eng"));
+        assert_eq!(lookup("dept=Eng*").dpt_lookup(), DptLookup::Prefix("Eng"));
+        assert_eq!(lookup("dept=*ing").dpt_lookup(), DptLookup::All);
+        assert_eq!(lookup("dept=E?g*").dpt_lookup(), DptLookup::All);
+        assert_eq!(
+            lookup("name=Bob AND dept=Eng*").dpt_lookup(),
+            DptLookup::Prefix("Eng")
+        );
+        assert_eq!(
+            lookup("dept=E* AND dept=Eng*").dpt_lookup(),
+            DptLookup::Prefix("Eng")
+        );
+        assert_eq!(
+            lookup("dept=E* AND dept=Ops").dpt_lookup(),
+            DptLookup::Exact("Ops")
+        );
+        assert_eq!(lookup("dept=Eng OR dept=Ops").dpt_lookup(), DptLookup::All);
+        assert_eq!(lookup("NOT dept=Eng").dpt_lookup(), DptLookup::All);
+    }
+}
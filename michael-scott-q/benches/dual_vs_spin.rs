@@ -0,0 +1,81 @@
+use std::thread;
+
+use cancel_token::CancellationToken;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use michael_scott_q::{DualQueue, Queue};
+
+const ELEMENTS: u64 = 20_000;
+const PRODUCERS: u64 = 2;
+
+// Consumers outnumber producers, so most pops find the queue empty and have
+// to wait, which is where the queues differ.
+fn handoff(consumers: u64, push: impl Fn(u64) + Sync, pop: impl Fn() -> u64 + Sync) {
+    thread::scope(|s| {
+        for p in 0..PRODUCERS {
+            let push = &push;
+            s.spawn(move || {
+                for i in (p..ELEMENTS).step_by(PRODUCERS as usize) {
+                    push(i);
+                }
+            });
+        }
+        for c in 0..consumers {
+            let pop = &pop;
+            s.spawn(move || {
+                // Every consumer pops its share, the first one the rest.
+                let share = ELEMENTS / consumers + if c == 0 { ELEMENTS % consumers } else { 0 };
+                for _ in 0..share {
+                    black_box(pop());
+                }
+            });
+        }
+    });
+}
+
+pub fn dual_vs_spin_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("handoff");
+    group.sample_size(20);
+    for consumers in [1, 4, 8] {
+        group.bench_with_input(
+            BenchmarkId::new("DualQueue::pop", consumers),
+            &consumers,
+            |b, &consumers| {
+                b.iter(|| {
+                    let q = DualQueue::new();
+                    handoff(consumers, |v| q.push(v), || q.pop());
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("Queue::pop", consumers),
+            &consumers,
+            |b, &consumers| {
+                b.iter(|| {
+                    let q = Queue::new();
+                    handoff(consumers, |v| q.push(v), || q.pop());
+                })
+            },
+        );
+        // A token that's never cancelled, so pop_until_cancelled parks like
+        // pop, and pays for registering its callback on every call.
+        let token = CancellationToken::new();
+        group.bench_with_input(
+            BenchmarkId::new("Queue::pop_until_cancelled", consumers),
+            &consumers,
+            |b, &consumers| {
+                b.iter(|| {
+                    let q = Queue::new();
+                    handoff(
+                        consumers,
+                        |v| q.push(v),
+                        || q.pop_until_cancelled(&token).unwrap(),
+                    );
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, dual_vs_spin_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,620 @@
+//! Dual queue: the Michael-Scott queue extended by Scherer and Scott, so that
+//! pop on an empty queue doesn't spin but enqueues a reservation.
+//!
+//! The queue holds either elements or reservations, never both, and its tail
+//! tells which. A push on a queue of reservations fulfills the one at the
+//! head instead of enqueuing its element, which hands out elements to waiting
+//! poppers in the order they started waiting. A pop on a queue of elements
+//! dequeues the head like the plain queue does.
+//!
+//! Fulfilling a reservation is a CAS of its slot from null to the element, so
+//! a reservation can also be cancelled by CASing its slot to a marker first
+//! (when a pop times out, or its future is dropped). Nodes of fulfilled and
+//! cancelled reservations stay in the queue until a push or pop comes across
+//! them at the head and dequeues them.
+//!
+//! The popper only holds on to the slot of its reservation, not the node, so
+//! it doesn't stay pinned while it waits.
+use std::future::Future;
+use std::pin::Pin;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+use crossbeam_epoch::{self, Atomic, Guard, Owned, Shared};
+use crossbeam_utils::CachePadded;
+use parking_lot::{Notifier, ParkResult};
+
+// How often a popper checks its slot before parking, since the element is
+// often already on its way.
+const SPINS: usize = 64;
+
+// The address of this static marks a slot that can't be fulfilled anymore,
+// because it was cancelled or its element was taken. No Box can live there,
+// not even one of a zero-sized type, whose address is just its alignment.
+static CLOSED_MARKER: u8 = 0;
+
+fn closed<T>() -> *mut T {
+    &CLOSED_MARKER as *const u8 as *mut T
+}
+
+pub struct DualQueue<T> {
+    head: CachePadded<Atomic<Node<T>>>,
+    tail: CachePadded<Atomic<Node<T>>>,
+}
+
+struct Node<T> {
+    kind: Kind<T>,
+    next: Atomic<Node<T>>,
+}
+
+enum Kind<T> {
+    // Null once the element was popped.
+    Element(AtomicPtr<T>),
+    Reservation(Arc<Slot<T>>),
+}
+
+// Shared between a reservation and its popper.
+struct Slot<T> {
+    // Null until fulfilled with a boxed element or closed.
+    item: AtomicPtr<T>,
+    // For poppers that block.
+    notifier: Notifier,
+    // For poppers that await.
+    waker: Mutex<Option<Waker>>,
+}
+
+unsafe impl<T: Send> Send for DualQueue<T> {}
+unsafe impl<T: Send> Sync for DualQueue<T> {}
+
+impl<T> Node<T> {
+    fn element(item: *mut T) -> Self {
+        Self {
+            kind: Kind::Element(AtomicPtr::new(item)),
+            next: Atomic::null(),
+        }
+    }
+
+    fn reservation(slot: Arc<Slot<T>>) -> Self {
+        Self {
+            kind: Kind::Reservation(slot),
+            next: Atomic::null(),
+        }
+    }
+
+    fn is_reservation(&self) -> bool {
+        matches!(self.kind, Kind::Reservation(_))
+    }
+}
+
+impl<T> Drop for Node<T> {
+    fn drop(&mut self) {
+        if let Kind::Element(item) = &self.kind {
+            let item = item.load(Ordering::Relaxed);
+            if !item.is_null() {
+                drop(unsafe { Box::from_raw(item) });
+            }
+        }
+    }
+}
+
+impl<T> Slot<T> {
+    fn new() -> Self {
+        Self {
+            item: AtomicPtr::new(ptr::null_mut()),
+            notifier: Notifier::new(),
+            waker: Mutex::new(None),
+        }
+    }
+
+    fn fulfill(&self, item: *mut T) -> bool {
+        if self
+            .item
+            .compare_exchange(ptr::null_mut(), item, Ordering::AcqRel, Ordering::Acquire)
+            .is_err()
+        {
+            return false;
+        }
+        self.notifier.notify_all();
+        if let Some(waker) = self.waker.lock().unwrap().take() {
+            waker.wake();
+        }
+        true
+    }
+
+    // Only called by the popper, which is the only one reading the slot once
+    // it's fulfilled.
+    fn take(&self) -> Option<T> {
+        let item = self.item.load(Ordering::Acquire);
+        if item.is_null() || item == closed() {
+            return None;
+        }
+        // Not back to null: a pusher that found the reservation before it
+        // was fulfilled may still try to fulfill it.
+        self.item.store(closed(), Ordering::Relaxed);
+        Some(*unsafe { Box::from_raw(item) })
+    }
+
+    // Returns the element instead if the reservation was fulfilled first.
+    fn cancel(&self) -> Option<T> {
+        match self.item.compare_exchange(
+            ptr::null_mut(),
+            closed(),
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => None,
+            Err(_) => self.take(),
+        }
+    }
+}
+
+impl<T> Drop for Slot<T> {
+    fn drop(&mut self) {
+        let item = *self.item.get_mut();
+        if !item.is_null() && item != closed() {
+            drop(unsafe { Box::from_raw(item) });
+        }
+    }
+}
+
+impl<T> Drop for DualQueue<T> {
+    fn drop(&mut self) {
+        let guard = unsafe { crossbeam_epoch::unprotected() };
+        let mut node = self.head.load(Ordering::Relaxed, guard);
+        while !node.is_null() {
+            let owned = unsafe { node.into_owned() };
+            node = owned.next.load(Ordering::Relaxed, guard);
+            drop(owned);
+        }
+    }
+}
+
+impl<T> DualQueue<T> {
+    pub fn new() -> Self {
+        let dummy = Owned::new(Node::element(ptr::null_mut()))
+            .into_shared(unsafe { crossbeam_epoch::unprotected() });
+
+        Self {
+            head: CachePadded::new(dummy.into()),
+            tail: CachePadded::new(dummy.into()),
+        }
+    }
+
+    /// Returns true if there's no element to pop, even if poppers are
+    /// waiting.
+    pub fn is_empty(&self) -> bool {
+        let guard = &crossbeam_epoch::pin();
+        let head = self.head.load(Ordering::Acquire, guard);
+        let next = unsafe { head.deref() }.next.load(Ordering::Acquire, guard);
+        unsafe { next.as_ref() }.is_none_or(Node::is_reservation)
+    }
+
+    /// Hands the element to the popper that has been waiting the longest, or
+    /// enqueues it if nobody is waiting.
+    pub fn push(&self, val: T) {
+        let item = Box::into_raw(Box::new(val));
+        let guard = &crossbeam_epoch::pin();
+        let mut node = None;
+
+        loop {
+            let head = self.head.load(Ordering::Acquire, guard);
+            let tail = self.tail.load(Ordering::Acquire, guard);
+            let tail_ref = unsafe { tail.deref() };
+
+            if head == tail || !tail_ref.is_reservation() {
+                let new = node.unwrap_or_else(|| Owned::new(Node::element(item)));
+                match self.append(tail, new, guard) {
+                    Ok(()) => return,
+                    Err(new) => node = Some(new),
+                }
+                continue;
+            }
+
+            let Some((next, slot)) = self.next_reservation(head, tail, guard) else {
+                continue;
+            };
+            let fulfilled = slot.fulfill(item);
+            // Fulfilled or cancelled, the reservation is done with.
+            self.advance_head(head, next, guard);
+            if fulfilled {
+                // The node isn't needed anymore, but still owns the element.
+                if let Some(node) = node {
+                    if let Kind::Element(item) = &node.kind {
+                        item.store(ptr::null_mut(), Ordering::Relaxed);
+                    }
+                }
+                return;
+            }
+        }
+    }
+
+    /// Pops an element if there is one, without waiting.
+    pub fn try_pop(&self) -> Option<T> {
+        let guard = &crossbeam_epoch::pin();
+        loop {
+            let head = self.head.load(Ordering::Acquire, guard);
+            let next = unsafe { head.deref() }.next.load(Ordering::Acquire, guard);
+            if unsafe { next.as_ref() }.is_none_or(Node::is_reservation) {
+                return None;
+            }
+            if let Some(val) = self.pop_element(head, guard) {
+                return Some(val);
+            }
+        }
+    }
+
+    /// Blocks until there's an element to pop. Poppers get elements in the
+    /// order they started waiting.
+    pub fn pop(&self) -> T {
+        match self.pop_or_reserve() {
+            Ok(val) => val,
+            Err(slot) => loop {
+                if let Some(val) = Self::wait(&slot, None) {
+                    return val;
+                }
+            },
+        }
+    }
+
+    /// Like `pop`, but gives up after `timeout`.
+    pub fn pop_timeout(&self, timeout: Duration) -> Option<T> {
+        let deadline = Instant::now() + timeout;
+        let slot = match self.pop_or_reserve() {
+            Ok(val) => return Some(val),
+            Err(slot) => slot,
+        };
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                return slot.cancel();
+            }
+            if let Some(val) = Self::wait(&slot, Some(deadline - now)) {
+                return Some(val);
+            }
+        }
+    }
+
+    /// Like `pop`, but waits by returning a future. Dropping the future gives
+    /// up the reservation: an element that was already handed to it is
+    /// pushed again.
+    pub fn pop_async(&self) -> Pop<'_, T> {
+        Pop {
+            queue: self,
+            slot: None,
+        }
+    }
+
+    // Pops an element, or enqueues a reservation if there's none.
+    fn pop_or_reserve(&self) -> Result<T, Arc<Slot<T>>> {
+        let guard = &crossbeam_epoch::pin();
+        let mut node = None;
+
+        loop {
+            let head = self.head.load(Ordering::Acquire, guard);
+            let tail = self.tail.load(Ordering::Acquire, guard);
+
+            if head == tail || unsafe { tail.deref() }.is_reservation() {
+                let new =
+                    node.unwrap_or_else(|| Owned::new(Node::reservation(Arc::new(Slot::new()))));
+                let slot = match &new.kind {
+                    Kind::Reservation(slot) => Arc::clone(slot),
+                    Kind::Element(_) => unreachable!(),
+                };
+                match self.append(tail, new, guard) {
+                    Ok(()) => return Err(slot),
+                    Err(new) => node = Some(new),
+                }
+                continue;
+            }
+
+            if let Some(val) = self.pop_element(head, guard) {
+                return Ok(val);
+            }
+        }
+    }
+
+    // Waits for the slot to be fulfilled, returns None on timeout or when
+    // woken spuriously.
+    fn wait(slot: &Slot<T>, timeout: Option<Duration>) -> Option<T> {
+        for _ in 0..SPINS {
+            if let Some(val) = slot.take() {
+                return Some(val);
+            }
+            std::hint::spin_loop();
+        }
+        let blocked = || slot.item.load(Ordering::Acquire).is_null();
+        if slot.notifier.wait(blocked, timeout) == ParkResult::TimedOut {
+            return None;
+        }
+        slot.take()
+    }
+
+    // Links `new` after `tail`, like push of the plain queue. Returns the
+    // node back if tail wasn't the last node anymore.
+    fn append<'g>(
+        &self,
+        tail: Shared<'g, Node<T>>,
+        new: Owned<Node<T>>,
+        guard: &'g Guard,
+    ) -> Result<(), Owned<Node<T>>> {
+        let tail_ref = unsafe { tail.deref() };
+        let next = tail_ref.next.load(Ordering::Acquire, guard);
+        if !next.is_null() {
+            // Help with moving the lagging tail.
+            let _ =
+                self.tail
+                    .compare_exchange(tail, next, Ordering::Release, Ordering::Relaxed, guard);
+            return Err(new);
+        }
+
+        match tail_ref.next.compare_exchange(
+            Shared::null(),
+            new,
+            Ordering::Release,
+            Ordering::Relaxed,
+            guard,
+        ) {
+            Ok(new) => {
+                let _ = self.tail.compare_exchange(
+                    tail,
+                    new,
+                    Ordering::Release,
+                    Ordering::Relaxed,
+                    guard,
+                );
+                Ok(())
+            }
+            Err(e) => Err(e.new),
+        }
+    }
+
+    // The reservation after head, if head and tail are still the ones the
+    // caller saw, which means the queue is still one of reservations.
+    fn next_reservation<'g>(
+        &self,
+        head: Shared<'g, Node<T>>,
+        tail: Shared<'g, Node<T>>,
+        guard: &'g Guard,
+    ) -> Option<(Shared<'g, Node<T>>, &'g Slot<T>)> {
+        let next = unsafe { head.deref() }.next.load(Ordering::Acquire, guard);
+        if self.tail.load(Ordering::Acquire, guard) != tail
+            || self.head.load(Ordering::Acquire, guard) != head
+        {
+            return None;
+        }
+        match &unsafe { next.as_ref() }?.kind {
+            Kind::Reservation(slot) => Some((next, slot)),
+            Kind::Element(_) => None,
+        }
+    }
+
+    // Dequeues the element after head, just like the plain queue: whoever
+    // moves head onto a node owns its element.
+    fn pop_element(&self, head: Shared<'_, Node<T>>, guard: &Guard) -> Option<T> {
+        let next = unsafe { head.deref() }.next.load(Ordering::Acquire, guard);
+        let Kind::Element(item) = &unsafe { next.as_ref() }?.kind else {
+            return None;
+        };
+        if !self.advance_head(head, next, guard) {
+            return None;
+        }
+        let item = item.swap(ptr::null_mut(), Ordering::Acquire);
+        Some(*unsafe { Box::from_raw(item) })
+    }
+
+    fn advance_head<'g>(
+        &self,
+        head: Shared<'g, Node<T>>,
+        next: Shared<'g, Node<T>>,
+        guard: &'g Guard,
+    ) -> bool {
+        if self
+            .head
+            .compare_exchange(head, next, Ordering::Release, Ordering::Relaxed, guard)
+            .is_err()
+        {
+            return false;
+        }
+        // Head never passes tail: the queue isn't empty while there's a next.
+        let tail = self.tail.load(Ordering::Acquire, guard);
+        if tail == head {
+            let _ =
+                self.tail
+                    .compare_exchange(tail, next, Ordering::Release, Ordering::Relaxed, guard);
+        }
+        unsafe { guard.defer_destroy(head) };
+        true
+    }
+}
+
+impl<T> Default for DualQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The future returned by `DualQueue::pop_async`.
+pub struct Pop<'a, T> {
+    queue: &'a DualQueue<T>,
+    // The reservation, once the first poll found the queue empty.
+    slot: Option<Arc<Slot<T>>>,
+}
+
+impl<T> Future for Pop<'_, T> {
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let slot = match &self.slot {
+            Some(slot) => Arc::clone(slot),
+            None => match self.queue.pop_or_reserve() {
+                Ok(val) => return Poll::Ready(val),
+                Err(slot) => {
+                    self.slot = Some(Arc::clone(&slot));
+                    slot
+                }
+            },
+        };
+
+        // Registered before checking the slot, so a fulfill that the check
+        // misses finds the waker.
+        *slot.waker.lock().unwrap() = Some(cx.waker().clone());
+        match slot.take() {
+            Some(val) => {
+                self.slot = None;
+                Poll::Ready(val)
+            }
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl<T> Drop for Pop<'_, T> {
+    fn drop(&mut self) {
+        if let Some(val) = self.slot.take().and_then(|slot| slot.cancel()) {
+            self.queue.push(val);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::thread;
+
+    #[test]
+    fn push_pop_in_order() {
+        let q = DualQueue::new();
+        assert!(q.is_empty());
+        assert_eq!(q.try_pop(), None);
+
+        for i in 0..100 {
+            q.push(i);
+        }
+        assert!(!q.is_empty());
+        for i in 0..50 {
+            assert_eq!(q.try_pop(), Some(i));
+        }
+        for i in 50..100 {
+            assert_eq!(q.pop(), i);
+        }
+        assert!(q.is_empty());
+    }
+
+    #[test]
+    fn waiting_poppers_are_served_in_order() {
+        const POPPERS: usize = 4;
+        let q = DualQueue::new();
+
+        thread::scope(|s| {
+            let poppers: Vec<_> = (0..POPPERS)
+                .map(|_| {
+                    let h = s.spawn(|| q.pop());
+                    // Gives the popper time to enqueue its reservation.
+                    thread::sleep(Duration::from_millis(20));
+                    h
+                })
+                .collect();
+            assert!(q.is_empty());
+
+            for i in 0..POPPERS {
+                q.push(i);
+            }
+            let popped: Vec<_> = poppers.into_iter().map(|h| h.join().unwrap()).collect();
+            assert_eq!(popped, (0..POPPERS).collect::<Vec<_>>());
+        });
+        assert!(q.is_empty());
+    }
+
+    #[test]
+    fn timed_out_reservations_are_skipped() {
+        let q = DualQueue::new();
+        assert_eq!(q.pop_timeout(Duration::from_millis(10)), None);
+        assert_eq!(q.pop_timeout(Duration::from_millis(10)), None);
+
+        q.push(1);
+        q.push(2);
+        assert_eq!(q.pop_timeout(Duration::from_millis(10)), Some(1));
+        assert_eq!(q.try_pop(), Some(2));
+    }
+
+    #[tokio::test]
+    async fn pop_async_waits_for_push() {
+        let q = Arc::new(DualQueue::new());
+        let popper = tokio::spawn({
+            let q = Arc::clone(&q);
+            async move { q.pop_async().await }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        q.push(37);
+        assert_eq!(popper.await.unwrap(), 37);
+
+        q.push(48);
+        assert_eq!(q.pop_async().await, 48);
+    }
+
+    #[tokio::test]
+    async fn dropped_pop_async_gives_back_its_element() {
+        let q = DualQueue::new();
+        let mut pop = Box::pin(q.pop_async());
+        let waker = std::task::Waker::noop();
+        let mut cx = Context::from_waker(waker);
+        assert!(pop.as_mut().poll(&mut cx).is_pending());
+
+        // Fulfills the reservation of the future, which is never polled
+        // again.
+        q.push(1);
+        drop(pop);
+        assert_eq!(q.try_pop(), Some(1));
+
+        // Without a reservation nothing is lost either way.
+        drop(q.pop_async());
+        q.push(2);
+        assert_eq!(q.try_pop(), Some(2));
+    }
+
+    #[test]
+    fn every_element_is_popped_once() {
+        const THREADS: usize = 4;
+        const PER_THREAD: usize = 10_000;
+        let q = DualQueue::new();
+        let sum = AtomicUsize::new(0);
+
+        thread::scope(|s| {
+            for t in 0..THREADS {
+                let q = &q;
+                s.spawn(move || {
+                    for i in 0..PER_THREAD {
+                        q.push(t * PER_THREAD + i);
+                    }
+                });
+            }
+            for _ in 0..THREADS {
+                s.spawn(|| {
+                    for _ in 0..PER_THREAD {
+                        sum.fetch_add(q.pop(), Ordering::Relaxed);
+                    }
+                });
+            }
+        });
+
+        let n = THREADS * PER_THREAD;
+        assert_eq!(sum.into_inner(), n * (n - 1) / 2);
+        assert!(q.is_empty());
+    }
+
+    #[test]
+    fn drops_elements_left_in_the_queue() {
+        let val = Arc::new(());
+        let q = DualQueue::new();
+        for _ in 0..3 {
+            q.push(Arc::clone(&val));
+        }
+        q.try_pop();
+        drop(q);
+        assert_eq!(Arc::strong_count(&val), 1);
+    }
+}
@@ -26,12 +26,24 @@ use std::fmt::Debug;
 use std::mem::MaybeUninit;
 use std::sync::atomic::Ordering;
 
+use cancel_token::CancellationToken;
 use crossbeam_epoch::{self, Atomic, Guard, Owned, Shared};
 use crossbeam_utils::CachePadded;
+use parking_lot::Notifier;
+
+pub use dual::{DualQueue, Pop};
+#[cfg(feature = "shm")]
+pub use shm::ShmQueue;
+
+mod dual;
+#[cfg(feature = "shm")]
+mod shm;
 
 pub struct Queue<T: Debug> {
     head: CachePadded<Atomic<Node<T>>>,
     tail: CachePadded<Atomic<Node<T>>>,
+    // Wakes the threads blocked in pop.
+    notifier: Notifier,
 }
 pub struct Node<T> {
     data: MaybeUninit<T>,
@@ -78,6 +90,7 @@ impl<T: Debug> Queue<T> {
         Self {
             head: CachePadded::new(dummy.into()),
             tail: CachePadded::new(dummy.into()),
+            notifier: Notifier::new(),
         }
     }
 
@@ -148,6 +161,7 @@ impl<T: Debug> Queue<T> {
                     .compare_exchange(tail, new, Ordering::Release, Ordering::Relaxed, guard);
             break;
         }
+        self.notifier.notify_one();
     }
 
     fn try_pop(&self, guard: &Guard) -> Option<T> {
@@ -198,12 +212,36 @@ impl<T: Debug> Queue<T> {
         }
     }
 
+    /// Blocks until there's an element to pop.
     pub fn pop(&self) -> T {
-        let guard = &crossbeam_epoch::pin();
         loop {
-            if let Some(data) = self.try_pop(guard) {
+            if let Some(data) = self.try_pop(&crossbeam_epoch::pin()) {
                 return data;
             }
+            // Parks unpinned, so that a blocked pop doesn't hold back the
+            // reclamation of every other thread.
+            self.notifier.wait(|| self.is_empty(), None);
+        }
+    }
+
+    /// Like `pop`, but gives up and returns None once `token` is cancelled.
+    /// An element that is already in the queue is still returned even if the
+    /// token has been cancelled.
+    pub fn pop_until_cancelled(&self, token: &CancellationToken) -> Option<T> {
+        let notifier = self.notifier.handle();
+        let _on_cancel = token.on_cancel(move || {
+            notifier.notify_all();
+        });
+        loop {
+            if let Some(data) = self.try_pop(&crossbeam_epoch::pin()) {
+                return Some(data);
+            }
+            if token.is_cancelled() {
+                return None;
+            }
+            // Parks unpinned like pop, until a push or the cancellation.
+            self.notifier
+                .wait(|| self.is_empty() && !token.is_cancelled(), None);
         }
     }
 }
@@ -418,6 +456,23 @@ mod tests {
         assert!(q.is_empty());
     }
 
+    #[test]
+    fn blocked_pops_are_woken_by_push() {
+        let q: Queue<i64> = Queue::new();
+
+        thread::scope(|s| {
+            let poppers: Vec<_> = (0..4).map(|_| s.spawn(|| q.pop())).collect();
+            thread::sleep(std::time::Duration::from_millis(20));
+
+            for i in 0..4 {
+                q.push(i);
+            }
+            let mut popped: Vec<_> = poppers.into_iter().map(|h| h.join().unwrap()).collect();
+            popped.sort();
+            assert_eq!(popped, [0, 1, 2, 3]);
+        });
+    }
+
     #[test]
     fn is_empty_dont_pop() {
         let q: Queue<i64> = Queue::new();
@@ -428,6 +483,37 @@ mod tests {
         assert!(try_pop(&q).is_some());
     }
 
+    #[test]
+    fn pop_until_cancelled_returns_none_on_cancel() {
+        let q: Queue<i64> = Queue::new();
+        let token = CancellationToken::new();
+
+        thread::scope(|s| {
+            let h = s.spawn(|| q.pop_until_cancelled(&token));
+            thread::sleep(std::time::Duration::from_millis(20));
+            token.cancel();
+            assert_eq!(h.join().unwrap(), None);
+        });
+    }
+
+    #[test]
+    fn pop_until_cancelled_prefers_available_elements() {
+        let q: Queue<i64> = Queue::new();
+        let token = CancellationToken::new();
+
+        thread::scope(|s| {
+            let h = s.spawn(|| q.pop_until_cancelled(&token));
+            thread::sleep(std::time::Duration::from_millis(20));
+            q.push(37);
+            assert_eq!(h.join().unwrap(), Some(37));
+        });
+
+        q.push(48);
+        token.cancel();
+        assert_eq!(q.pop_until_cancelled(&token), Some(48));
+        assert_eq!(q.pop_until_cancelled(&token), None);
+    }
+
     // try_pop makes calling try_pop on the Queue convenient.
     // Because it expected a &Guard and this function takes
     // care of providing that.
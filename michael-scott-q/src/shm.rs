@@ -0,0 +1,482 @@
+//! A Michael-Scott queue that lives in shared memory, so that processes that
+//! map the same file can exchange items through it.
+//!
+//! Nothing in the region may depend on where it's mapped, so:
+//!
+//! - Nodes are linked by their index into a fixed array of nodes that follows
+//!   the header, not by pointers. Which makes the queue bounded.
+//! - Nodes are never freed, but put on a free list (a Treiber stack) to be
+//!   reused by the next push. Epoch based reclamation would need the epochs
+//!   of all processes.
+//! - Items are copied in and out, they can't own anything outside of the
+//!   region, hence `T: Copy`. Pointers and references in them are only
+//!   meaningful in the process that pushed them.
+//!
+//! Reusing nodes brings back the ABA problem: a CAS could succeed on a link
+//! that was changed and changed back in the meantime. So every link is a
+//! tagged index, the upper half counts the changes of the link, like in the
+//! original paper. The tag acts as a reference count of sorts: an operation
+//! that read a link before its node was recycled holds on to an old count,
+//! and its CAS fails. It takes 2^32 changes of the same link while a thread
+//! is between its read and its CAS for a CAS to succeed wrongly.
+//!
+//! A pop reads the item before it swings the head, since the node can be
+//! recycled right after. If the node was recycled before, the item may be
+//! torn, but then the CAS fails and the item is thrown away without being
+//! looked at.
+//!
+//! A process that dies in the middle of an operation may leave the node it
+//! was pushing or popping unreachable, the queue stays usable otherwise.
+//! Only the atomics of the region are shared, so there's no blocking pop.
+use std::cell::UnsafeCell;
+use std::fs::{File, OpenOptions};
+use std::io::{self, ErrorKind};
+use std::marker::PhantomData;
+use std::mem::{self, MaybeUninit};
+use std::path::Path;
+use std::ptr;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crossbeam_utils::CachePadded;
+use memmap2::MmapMut;
+
+// Tells regions that were initialized apart from those that weren't, or are
+// from another version of this layout.
+const MAGIC: u64 = 0x6d73_712d_7368_6d31;
+
+// The index of no node.
+const NIL: u32 = u32::MAX;
+
+pub struct ShmQueue<T> {
+    map: MmapMut,
+    _marker: PhantomData<T>,
+}
+
+#[repr(C)]
+struct Header {
+    // Written last by create, and only then.
+    magic: AtomicU64,
+    capacity: u64,
+    // The layout of T, to refuse mapping a queue of other items.
+    item_size: u64,
+    item_align: u64,
+    head: CachePadded<AtomicU64>,
+    tail: CachePadded<AtomicU64>,
+    free: CachePadded<AtomicU64>,
+}
+
+#[repr(C)]
+struct Node<T> {
+    // In the queue, or on the free list.
+    next: AtomicU64,
+    item: UnsafeCell<MaybeUninit<T>>,
+}
+
+// Links are tagged indices, the tag in the upper half.
+fn link(idx: u32, tag: u32) -> u64 {
+    (tag as u64) << 32 | idx as u64
+}
+
+fn index(link: u64) -> u32 {
+    link as u32
+}
+
+fn tag(link: u64) -> u32 {
+    (link >> 32) as u32
+}
+
+// The link that follows `old` and points to idx.
+fn next_link(old: u64, idx: u32) -> u64 {
+    link(idx, tag(old).wrapping_add(1))
+}
+
+unsafe impl<T: Copy + Send> Send for ShmQueue<T> {}
+unsafe impl<T: Copy + Send> Sync for ShmQueue<T> {}
+
+impl<T: Copy> ShmQueue<T> {
+    /// The size of the region a queue of `capacity` items takes.
+    pub fn region_size(capacity: usize) -> usize {
+        nodes_offset::<T>() + (capacity + 1) * mem::size_of::<Node<T>>()
+    }
+
+    /// Creates a queue that holds up to `capacity` items in the file at
+    /// `path`, replacing what the file held. On Linux a path in `/dev/shm`
+    /// keeps it in memory.
+    pub fn create(path: impl AsRef<Path>, capacity: usize) -> io::Result<Self> {
+        // One node is always the dummy.
+        if capacity == 0 || capacity >= NIL as usize {
+            let msg = format!("capacity out of range: {}", capacity);
+            return Err(io::Error::new(ErrorKind::InvalidInput, msg));
+        }
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        file.set_len(Self::region_size(capacity) as u64)?;
+        let mut q = Self::map(&file)?;
+
+        // SAFETY: The region is large enough and still private to us, the
+        // file was just truncated. The zeroed magic keeps others out.
+        unsafe {
+            let header = q.map.as_mut_ptr() as *mut Header;
+            ptr::addr_of_mut!((*header).capacity).write(capacity as u64);
+            ptr::addr_of_mut!((*header).item_size).write(mem::size_of::<T>() as u64);
+            ptr::addr_of_mut!((*header).item_align).write(mem::align_of::<T>() as u64);
+        }
+        let header = q.header();
+        // Node 0 is the first dummy, the rest are free.
+        header.head.store(link(0, 0), Ordering::Relaxed);
+        header.tail.store(link(0, 0), Ordering::Relaxed);
+        q.node(0).next.store(link(NIL, 0), Ordering::Relaxed);
+        for i in 1..=capacity as u32 {
+            let next = if i as usize == capacity { NIL } else { i + 1 };
+            q.node(i).next.store(link(next, 0), Ordering::Relaxed);
+        }
+        header.free.store(link(1, 0), Ordering::Relaxed);
+        header.magic.store(MAGIC, Ordering::Release);
+        Ok(q)
+    }
+
+    /// Maps the queue that was created at `path`, by this or another
+    /// process. Fails if it wasn't created for items of the layout of `T`.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        if (file.metadata()?.len() as usize) < mem::size_of::<Header>() {
+            return Err(invalid("file is too small for a queue"));
+        }
+        let q = Self::map(&file)?;
+
+        let header = q.header();
+        if header.magic.load(Ordering::Acquire) != MAGIC {
+            return Err(invalid("file doesn't hold a queue"));
+        }
+        if header.item_size != mem::size_of::<T>() as u64
+            || header.item_align != mem::align_of::<T>() as u64
+        {
+            return Err(invalid("queue holds items of another layout"));
+        }
+        if q.map.len() < Self::region_size(header.capacity as usize) {
+            return Err(invalid("file is too small for the capacity of the queue"));
+        }
+        Ok(q)
+    }
+
+    fn map(file: &File) -> io::Result<Self> {
+        // SAFETY: The other processes only access the file through the
+        // atomics of the region, and the nodes they own.
+        let map = unsafe { MmapMut::map_mut(file)? };
+        // Mappings are page aligned, which covers the alignment of the
+        // header, and of the nodes if T's isn't extraordinary.
+        assert!(mem::align_of::<Node<T>>() <= 4096);
+        Ok(Self {
+            map,
+            _marker: PhantomData,
+        })
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.header().capacity as usize
+    }
+
+    fn header(&self) -> &Header {
+        // SAFETY: create and open made sure the region holds a header.
+        unsafe { &*(self.map.as_ptr() as *const Header) }
+    }
+
+    fn node(&self, idx: u32) -> &Node<T> {
+        assert!(idx as u64 <= self.header().capacity, "index out of range");
+        // SAFETY: The region holds capacity + 1 nodes.
+        unsafe {
+            let nodes = self.map.as_ptr().add(nodes_offset::<T>()) as *const Node<T>;
+            &*nodes.add(idx as usize)
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        let head = self.header().head.load(Ordering::Acquire);
+        index(self.node(index(head)).next.load(Ordering::Acquire)) == NIL
+    }
+
+    /// Appends `item`, or hands it back if the queue is full.
+    pub fn push(&self, item: T) -> Result<(), T> {
+        let Some(idx) = self.alloc() else {
+            return Err(item);
+        };
+        let node = self.node(idx);
+        // SAFETY: The node is off the free list and not in the queue yet, so
+        // it's ours.
+        unsafe { (*node.item.get()).write(item) };
+        let next = node.next.load(Ordering::Relaxed);
+        node.next.store(next_link(next, NIL), Ordering::Relaxed);
+
+        let header = self.header();
+        loop {
+            let tail = header.tail.load(Ordering::Acquire);
+            let next = self.node(index(tail)).next.load(Ordering::Acquire);
+            if tail != header.tail.load(Ordering::Acquire) {
+                continue;
+            }
+
+            if index(next) != NIL {
+                // Help with the cleanup when tail is lagging behind.
+                let _ = header.tail.compare_exchange(
+                    tail,
+                    next_link(tail, index(next)),
+                    Ordering::Release,
+                    Ordering::Relaxed,
+                );
+                continue;
+            }
+            if self
+                .node(index(tail))
+                .next
+                .compare_exchange(
+                    next,
+                    next_link(next, idx),
+                    Ordering::Release,
+                    Ordering::Relaxed,
+                )
+                .is_ok()
+            {
+                // Someone else may have moved the tail already.
+                let _ = header.tail.compare_exchange(
+                    tail,
+                    next_link(tail, idx),
+                    Ordering::Release,
+                    Ordering::Relaxed,
+                );
+                return Ok(());
+            }
+        }
+    }
+
+    pub fn try_pop(&self) -> Option<T> {
+        let header = self.header();
+        loop {
+            let head = header.head.load(Ordering::Acquire);
+            let tail = header.tail.load(Ordering::Acquire);
+            let next = self.node(index(head)).next.load(Ordering::Acquire);
+            if head != header.head.load(Ordering::Acquire) {
+                continue;
+            }
+
+            if index(head) == index(tail) {
+                if index(next) == NIL {
+                    return None;
+                }
+                // The tail is lagging behind.
+                let _ = header.tail.compare_exchange(
+                    tail,
+                    next_link(tail, index(next)),
+                    Ordering::Release,
+                    Ordering::Relaxed,
+                );
+                continue;
+            }
+            if index(next) == NIL {
+                // The head node was recycled after we read the head.
+                continue;
+            }
+
+            // SAFETY: Read as MaybeUninit, as it may be torn, and only
+            // assumed to be initialized if the head didn't change.
+            let item = unsafe { ptr::read_volatile(self.node(index(next)).item.get()) };
+            if header
+                .head
+                .compare_exchange(
+                    head,
+                    next_link(head, index(next)),
+                    Ordering::AcqRel,
+                    Ordering::Relaxed,
+                )
+                .is_ok()
+            {
+                // The old dummy is ours now, next is the new dummy.
+                self.free(index(head));
+                return Some(unsafe { item.assume_init() });
+            }
+        }
+    }
+
+    // Takes a node off the free list.
+    fn alloc(&self) -> Option<u32> {
+        let free = &self.header().free;
+        loop {
+            let top = free.load(Ordering::Acquire);
+            if index(top) == NIL {
+                return None;
+            }
+            // May be stale if the node was taken meanwhile, but then the tag
+            // of top changed as well.
+            let next = self.node(index(top)).next.load(Ordering::Acquire);
+            if free
+                .compare_exchange(
+                    top,
+                    next_link(top, index(next)),
+                    Ordering::AcqRel,
+                    Ordering::Relaxed,
+                )
+                .is_ok()
+            {
+                return Some(index(top));
+            }
+        }
+    }
+
+    fn free(&self, idx: u32) {
+        let free = &self.header().free;
+        let node = self.node(idx);
+        loop {
+            let top = free.load(Ordering::Acquire);
+            let next = node.next.load(Ordering::Relaxed);
+            node.next
+                .store(next_link(next, index(top)), Ordering::Relaxed);
+            if free
+                .compare_exchange(
+                    top,
+                    next_link(top, idx),
+                    Ordering::Release,
+                    Ordering::Relaxed,
+                )
+                .is_ok()
+            {
+                return;
+            }
+        }
+    }
+}
+
+// Where the nodes start, the header is padded to their alignment.
+fn nodes_offset<T>() -> usize {
+    let align = mem::align_of::<Node<T>>();
+    mem::size_of::<Header>().div_ceil(align) * align
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::thread;
+
+    // A file in the temp dir that is removed at the end of the test.
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str) -> Self {
+            let name = format!("msq-{}-{}", std::process::id(), name);
+            Self(std::env::temp_dir().join(name))
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn fifo_up_to_capacity() {
+        let file = TempFile::new("fifo");
+        let q = ShmQueue::<u64>::create(&file.0, 3).unwrap();
+        assert_eq!(q.capacity(), 3);
+        assert!(q.is_empty());
+
+        // Goes round the node array a few times.
+        for round in 0..4 {
+            for i in 0..3 {
+                q.push(round * 10 + i).unwrap();
+            }
+            assert_eq!(q.push(99), Err(99));
+            for i in 0..3 {
+                assert_eq!(q.try_pop(), Some(round * 10 + i));
+            }
+            assert_eq!(q.try_pop(), None);
+        }
+    }
+
+    #[test]
+    fn mappings_at_different_addresses_share_the_queue() {
+        let file = TempFile::new("two-maps");
+        let a = ShmQueue::<(u32, u16)>::create(&file.0, 8).unwrap();
+        let b = ShmQueue::<(u32, u16)>::open(&file.0).unwrap();
+        assert_ne!(a.map.as_ptr(), b.map.as_ptr());
+
+        a.push((1, 2)).unwrap();
+        b.push((3, 4)).unwrap();
+        assert_eq!(b.try_pop(), Some((1, 2)));
+        assert_eq!(a.try_pop(), Some((3, 4)));
+        assert!(b.is_empty());
+    }
+
+    #[test]
+    fn open_checks_the_region() {
+        let file = TempFile::new("checks");
+        fs::write(&file.0, [0; 8]).unwrap();
+        let err = ShmQueue::<u64>::open(&file.0).err().unwrap();
+        assert_eq!(err.to_string(), "file is too small for a queue");
+
+        fs::write(&file.0, vec![0; 4096]).unwrap();
+        let err = ShmQueue::<u64>::open(&file.0).err().unwrap();
+        assert_eq!(err.to_string(), "file doesn't hold a queue");
+
+        ShmQueue::<u64>::create(&file.0, 4).unwrap();
+        let err = ShmQueue::<u8>::open(&file.0).err().unwrap();
+        assert_eq!(err.to_string(), "queue holds items of another layout");
+
+        let err = ShmQueue::<u8>::create(&file.0, 0).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn concurrent_mappings_mpmc() {
+        const COUNT: u64 = 100_000;
+        let file = TempFile::new("mpmc");
+        ShmQueue::<u64>::create(&file.0, 64).unwrap();
+
+        let sums: Vec<u64> = thread::scope(|s| {
+            for p in 0..2 {
+                let q = ShmQueue::<u64>::open(&file.0).unwrap();
+                s.spawn(move || {
+                    for i in 0..COUNT {
+                        let mut item = p * COUNT + i;
+                        while let Err(back) = q.push(item) {
+                            item = back;
+                            thread::yield_now();
+                        }
+                    }
+                });
+            }
+            let poppers: Vec<_> = (0..2)
+                .map(|_| {
+                    let q = ShmQueue::<u64>::open(&file.0).unwrap();
+                    s.spawn(move || {
+                        let (mut sum, mut last) = (0, [None, None]);
+                        for _ in 0..COUNT {
+                            let item = loop {
+                                match q.try_pop() {
+                                    Some(item) => break item,
+                                    None => thread::yield_now(),
+                                }
+                            };
+                            // The items of each pusher come out in order.
+                            let p = (item / COUNT) as usize;
+                            assert!(last[p] < Some(item));
+                            last[p] = Some(item);
+                            sum += item;
+                        }
+                        sum
+                    })
+                })
+                .collect();
+            poppers.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        assert_eq!(sums.iter().sum::<u64>(), (0..2 * COUNT).sum::<u64>());
+    }
+}
@@ -0,0 +1,100 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::thread;
+
+use michael_scott_q::ShmQueue;
+
+// Set for the child processes, to the path of the queue.
+const QUEUE_ENV: &str = "MSQ_SHM_TEST_QUEUE";
+const COUNT: u64 = 50_000;
+
+// The queues of both directions, removed at the end of the test.
+struct Queues(PathBuf, PathBuf);
+
+impl Drop for Queues {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+        let _ = fs::remove_file(&self.1);
+    }
+}
+
+fn pop(q: &ShmQueue<u64>) -> u64 {
+    loop {
+        match q.try_pop() {
+            Some(item) => return item,
+            None => thread::yield_now(),
+        }
+    }
+}
+
+fn push(q: &ShmQueue<u64>, mut item: u64) {
+    while let Err(back) = q.push(item) {
+        item = back;
+        thread::yield_now();
+    }
+}
+
+// Runs in the child processes only: doubles what it gets on the first queue
+// and pushes it onto the second one.
+#[test]
+#[ignore]
+fn child() {
+    let Ok(paths) = env::var(QUEUE_ENV) else {
+        return;
+    };
+    let (from, to) = paths.split_once(';').unwrap();
+    let from = ShmQueue::<u64>::open(from).unwrap();
+    let to = ShmQueue::<u64>::open(to).unwrap();
+    for _ in 0..COUNT {
+        push(&to, pop(&from) * 2);
+    }
+}
+
+#[test]
+fn processes_exchange_items() {
+    let dir = env::temp_dir();
+    let id = std::process::id();
+    let queues = Queues(
+        dir.join(format!("msq-shm-{}-requests", id)),
+        dir.join(format!("msq-shm-{}-responses", id)),
+    );
+    // Small enough that both sides fill them up now and then.
+    let requests = ShmQueue::<u64>::create(&queues.0, 16).unwrap();
+    let responses = ShmQueue::<u64>::create(&queues.1, 16).unwrap();
+
+    // Two children compete for the requests.
+    let children: Vec<_> = (0..2)
+        .map(|_| {
+            Command::new(env::current_exe().unwrap())
+                .args(["child", "--exact", "--ignored", "--quiet"])
+                .env(
+                    QUEUE_ENV,
+                    format!("{};{}", queues.0.display(), queues.1.display()),
+                )
+                .stdout(Stdio::null())
+                .spawn()
+                .unwrap()
+        })
+        .collect();
+
+    let mut sum = 0;
+    thread::scope(|s| {
+        s.spawn(|| {
+            for i in 0..2 * COUNT {
+                push(&requests, i);
+            }
+        });
+        for _ in 0..2 * COUNT {
+            sum += pop(&responses);
+        }
+    });
+
+    for mut child in children {
+        assert!(child.wait().unwrap().success());
+    }
+    assert_eq!(sum, (0..2 * COUNT).map(|i| i * 2).sum::<u64>());
+    assert!(requests.is_empty());
+    assert!(responses.is_empty());
+}
@@ -0,0 +1,250 @@
+//! A minimal userspace parking lot, in the spirit of futexes.
+//!
+//! Threads park on an address: any `usize` that identifies whatever they're
+//! waiting for, usually the address of the structure itself. The wait queues
+//! live in one global hash table of buckets, so a structure doesn't need to
+//! carry a Mutex and a Condvar around to let threads block on it. It only
+//! pays for the bucket lock when somebody actually parks or unparks.
+//!
+//! `park` checks its `validate` callback under the bucket lock before queueing
+//! the thread, and `unpark_*` take the same lock. So if the state a thread is
+//! waiting for is changed before calling `unpark_*`, the thread either sees
+//! the change in `validate` or is already queued and gets unparked: no wakeup
+//! is lost in between.
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, Thread};
+use std::time::{Duration, Instant};
+
+mod notifier;
+mod semaphore;
+
+pub use notifier::{Notifier, NotifyHandle};
+pub use semaphore::Semaphore;
+
+// Enough to make collisions between unrelated addresses rare. Colliding
+// addresses only share a lock, the queue entries still carry their address.
+const BUCKETS: usize = 256;
+
+static TABLE: [Bucket; BUCKETS] = [const { Bucket::new() }; BUCKETS];
+
+struct Bucket {
+    queue: Mutex<VecDeque<Arc<Waiter>>>,
+}
+
+struct Waiter {
+    addr: usize,
+    thread: Thread,
+    // Only set by unpark_*, under the bucket lock, after taking the waiter out
+    // of the queue. thread::park can return spuriously, so this is what tells
+    // the parked thread that it was actually unparked.
+    unparked: AtomicBool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParkResult {
+    Unparked,
+    /// `validate` returned false, so the thread didn't park at all.
+    Invalid,
+    TimedOut,
+}
+
+impl Bucket {
+    const fn new() -> Self {
+        Self {
+            queue: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, VecDeque<Arc<Waiter>>> {
+        self.queue.lock().unwrap()
+    }
+}
+
+fn bucket(addr: usize) -> &'static Bucket {
+    // Fibonacci hashing. Addresses are aligned, so their low bits are mostly
+    // zero and can't be used directly.
+    let hash = (addr as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    &TABLE[(hash >> (64 - BUCKETS.trailing_zeros())) as usize]
+}
+
+/// Parks the current thread on `addr` if `validate` returns true, until
+/// another thread unparks it or `timeout` elapses.
+///
+/// `validate` runs while the bucket of `addr` is locked, so it must not call
+/// `park` or `unpark_*` itself.
+pub fn park(addr: usize, validate: impl FnOnce() -> bool, timeout: Option<Duration>) -> ParkResult {
+    let deadline = timeout.map(|t| Instant::now() + t);
+    let waiter = Arc::new(Waiter {
+        addr,
+        thread: thread::current(),
+        unparked: AtomicBool::new(false),
+    });
+
+    let bucket = bucket(addr);
+    {
+        let mut queue = bucket.lock();
+        if !validate() {
+            return ParkResult::Invalid;
+        }
+        queue.push_back(Arc::clone(&waiter));
+    }
+
+    while !waiter.unparked.load(Ordering::Acquire) {
+        match deadline {
+            None => thread::park(),
+            Some(deadline) => {
+                let now = Instant::now();
+                if now >= deadline {
+                    return timed_out(bucket, &waiter);
+                }
+                thread::park_timeout(deadline - now);
+            }
+        }
+    }
+    ParkResult::Unparked
+}
+
+// An unpark might have raced with the timeout, in which case it counts.
+fn timed_out(bucket: &Bucket, waiter: &Arc<Waiter>) -> ParkResult {
+    let mut queue = bucket.lock();
+    if waiter.unparked.load(Ordering::Acquire) {
+        return ParkResult::Unparked;
+    }
+    queue.retain(|w| !Arc::ptr_eq(w, waiter));
+    ParkResult::TimedOut
+}
+
+/// Unparks the thread that has been parked on `addr` the longest. Returns
+/// false if there was none.
+pub fn unpark_one(addr: usize) -> bool {
+    let waiter = {
+        let mut queue = bucket(addr).lock();
+        let Some(i) = queue.iter().position(|w| w.addr == addr) else {
+            return false;
+        };
+        let waiter = queue.remove(i).unwrap();
+        waiter.unparked.store(true, Ordering::Release);
+        waiter
+    };
+    waiter.thread.unpark();
+    true
+}
+
+/// Unparks every thread parked on `addr`. Returns how many there were.
+pub fn unpark_all(addr: usize) -> usize {
+    let mut unparked = Vec::new();
+    {
+        let mut queue = bucket(addr).lock();
+        queue.retain(|w| {
+            if w.addr != addr {
+                return true;
+            }
+            w.unparked.store(true, Ordering::Release);
+            unparked.push(Arc::clone(w));
+            false
+        });
+    }
+
+    // Without holding the lock, so the unparked threads don't run into it
+    // right away.
+    for waiter in &unparked {
+        waiter.thread.unpark();
+    }
+    unparked.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    // Waits until `n` threads are parked on `addr`.
+    fn wait_for_parked(addr: usize, n: usize) {
+        while bucket(addr)
+            .lock()
+            .iter()
+            .filter(|w| w.addr == addr)
+            .count()
+            < n
+        {
+            thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn invalid_does_not_park() {
+        let result = park(1, || false, None);
+        assert_eq!(result, ParkResult::Invalid);
+        assert!(!unpark_one(1));
+    }
+
+    #[test]
+    fn times_out() {
+        let start = Instant::now();
+        let result = park(2, || true, Some(Duration::from_millis(20)));
+        assert_eq!(result, ParkResult::TimedOut);
+        assert!(start.elapsed() >= Duration::from_millis(20));
+
+        // It doesn't stay queued.
+        assert!(!unpark_one(2));
+    }
+
+    #[test]
+    fn unpark_one_in_fifo_order() {
+        let woken = Mutex::new(Vec::new());
+        thread::scope(|s| {
+            for i in 0..3 {
+                let woken = &woken;
+                s.spawn(move || {
+                    assert_eq!(park(3, || true, None), ParkResult::Unparked);
+                    woken.lock().unwrap().push(i);
+                });
+                // Parks them one after the other.
+                wait_for_parked(3, i + 1);
+            }
+
+            for i in 0..3 {
+                assert!(unpark_one(3));
+                while woken.lock().unwrap().len() <= i {
+                    thread::yield_now();
+                }
+            }
+        });
+        assert_eq!(*woken.lock().unwrap(), [0, 1, 2]);
+    }
+
+    #[test]
+    fn unpark_all_only_wakes_its_address() {
+        let count = AtomicUsize::new(0);
+        thread::scope(|s| {
+            for addr in [4, 4, 4, 5] {
+                let count = &count;
+                s.spawn(move || {
+                    park(addr, || true, None);
+                    count.fetch_add(1, Ordering::Relaxed);
+                });
+            }
+            wait_for_parked(4, 3);
+            wait_for_parked(5, 1);
+
+            assert_eq!(unpark_all(4), 3);
+            while count.load(Ordering::Relaxed) < 3 {
+                thread::yield_now();
+            }
+            assert!(unpark_one(5));
+        });
+        assert_eq!(count.load(Ordering::Relaxed), 4);
+    }
+
+    #[test]
+    fn unpark_before_timeout_counts() {
+        thread::scope(|s| {
+            let h = s.spawn(|| park(6, || true, Some(Duration::from_secs(10))));
+            wait_for_parked(6, 1);
+            assert!(unpark_one(6));
+            assert_eq!(h.join().unwrap(), ParkResult::Unparked);
+        });
+    }
+}
@@ -0,0 +1,146 @@
+// Lets threads block until some lock-free state changes, e.g. until a queue
+// isn't empty anymore. Notifying only costs a fence and a load while nobody
+// is waiting, so it can sit on the hot path of push.
+use std::sync::atomic::{fence, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use crate::ParkResult;
+
+#[derive(Debug, Default)]
+pub struct Notifier {
+    waiting: AtomicUsize,
+}
+
+impl Notifier {
+    pub const fn new() -> Self {
+        Self {
+            waiting: AtomicUsize::new(0),
+        }
+    }
+
+    fn addr(&self) -> usize {
+        self as *const Self as usize
+    }
+
+    /// Blocks while `blocked` returns true, until a notification or the
+    /// timeout. `blocked` is checked under the bucket lock (see `park`), and
+    /// after this thread is counted as waiting, so a change that's followed
+    /// by `notify_*` is never missed.
+    ///
+    /// Being notified doesn't mean the state is as expected: another thread
+    /// may have gotten to it first. Callers check again and wait in a loop.
+    pub fn wait(&self, blocked: impl FnOnce() -> bool, timeout: Option<Duration>) -> ParkResult {
+        self.waiting.fetch_add(1, Ordering::SeqCst);
+        let result = crate::park(self.addr(), blocked, timeout);
+        self.waiting.fetch_sub(1, Ordering::Relaxed);
+        result
+    }
+
+    /// Wakes one waiting thread, if any. Must be called after the state
+    /// change the waiters are interested in.
+    pub fn notify_one(&self) -> bool {
+        self.has_waiters() && crate::unpark_one(self.addr())
+    }
+
+    pub fn notify_all(&self) -> usize {
+        if !self.has_waiters() {
+            return 0;
+        }
+        crate::unpark_all(self.addr())
+    }
+
+    /// A handle that can wake the waiting threads without borrowing the
+    /// notifier, e.g. from a callback that has to be `'static`. A handle
+    /// that outlives its notifier only causes spurious wakeups.
+    pub fn handle(&self) -> NotifyHandle {
+        NotifyHandle { addr: self.addr() }
+    }
+
+    // Together with the SeqCst increment in wait, either we see the waiter,
+    // or the waiter's `blocked` sees the change made before notifying.
+    fn has_waiters(&self) -> bool {
+        fence(Ordering::SeqCst);
+        self.waiting.load(Ordering::Relaxed) > 0
+    }
+}
+
+/// See `Notifier::handle`.
+#[derive(Debug, Clone, Copy)]
+pub struct NotifyHandle {
+    addr: usize,
+}
+
+impl NotifyHandle {
+    /// Like `Notifier::notify_all`, but always takes the bucket lock, since
+    /// the notifier's count of waiters may be gone.
+    pub fn notify_all(&self) -> usize {
+        crate::unpark_all(self.addr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+    use std::thread;
+
+    #[test]
+    fn notify_without_waiters() {
+        let notifier = Notifier::new();
+        assert!(!notifier.notify_one());
+        assert_eq!(notifier.notify_all(), 0);
+    }
+
+    #[test]
+    fn wakes_waiters_after_state_change() {
+        let notifier = Notifier::new();
+        let ready = AtomicBool::new(false);
+
+        thread::scope(|s| {
+            let waiters: Vec<_> = (0..4)
+                .map(|_| {
+                    s.spawn(|| {
+                        while !ready.load(Ordering::Acquire) {
+                            notifier.wait(|| !ready.load(Ordering::Acquire), None);
+                        }
+                    })
+                })
+                .collect();
+
+            thread::sleep(Duration::from_millis(10));
+            ready.store(true, Ordering::Release);
+            notifier.notify_all();
+
+            for w in waiters {
+                w.join().unwrap();
+            }
+        });
+    }
+
+    #[test]
+    fn handles_wake_waiters() {
+        let notifier = Notifier::new();
+        let ready = AtomicBool::new(false);
+        let handle = notifier.handle();
+
+        thread::scope(|s| {
+            let waiter = s.spawn(|| {
+                while !ready.load(Ordering::Acquire) {
+                    notifier.wait(|| !ready.load(Ordering::Acquire), None);
+                }
+            });
+
+            thread::sleep(Duration::from_millis(10));
+            ready.store(true, Ordering::Release);
+            handle.notify_all();
+            waiter.join().unwrap();
+        });
+    }
+
+    #[test]
+    fn wait_after_change_returns_right_away() {
+        let notifier = Notifier::new();
+        let result = notifier.wait(|| false, None);
+        assert_eq!(result, ParkResult::Invalid);
+    }
+}
@@ -0,0 +1,113 @@
+// A counting semaphore. Acquiring a permit is a CAS while there are permits
+// left; threads only park when there are none.
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::{Notifier, ParkResult};
+
+#[derive(Debug)]
+pub struct Semaphore {
+    permits: AtomicUsize,
+    notifier: Notifier,
+}
+
+impl Semaphore {
+    pub const fn new(permits: usize) -> Self {
+        Self {
+            permits: AtomicUsize::new(permits),
+            notifier: Notifier::new(),
+        }
+    }
+
+    pub fn available_permits(&self) -> usize {
+        self.permits.load(Ordering::Relaxed)
+    }
+
+    pub fn try_acquire(&self) -> bool {
+        self.permits
+            .fetch_update(Ordering::Acquire, Ordering::Relaxed, |p| p.checked_sub(1))
+            .is_ok()
+    }
+
+    /// Blocks until a permit is available and takes it.
+    pub fn acquire(&self) {
+        while !self.try_acquire() {
+            self.notifier.wait(|| self.available_permits() == 0, None);
+        }
+    }
+
+    /// Like `acquire`, but gives up after `timeout`. Returns true if it took
+    /// a permit.
+    pub fn acquire_timeout(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        while !self.try_acquire() {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            let blocked = || self.available_permits() == 0;
+            if self.notifier.wait(blocked, Some(timeout)) == ParkResult::TimedOut {
+                // A permit might have been released right at the deadline.
+                return self.try_acquire();
+            }
+        }
+        true
+    }
+
+    /// Returns a permit, and wakes a thread waiting for one.
+    pub fn release(&self) {
+        self.permits.fetch_add(1, Ordering::Release);
+        self.notifier.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::thread;
+
+    #[test]
+    fn try_acquire_takes_permits() {
+        let sem = Semaphore::new(2);
+        assert!(sem.try_acquire());
+        assert!(sem.try_acquire());
+        assert!(!sem.try_acquire());
+
+        sem.release();
+        assert_eq!(sem.available_permits(), 1);
+        assert!(sem.try_acquire());
+    }
+
+    #[test]
+    fn acquire_timeout_gives_up() {
+        let sem = Semaphore::new(0);
+        assert!(!sem.acquire_timeout(Duration::from_millis(10)));
+
+        sem.release();
+        assert!(sem.acquire_timeout(Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn limits_concurrency() {
+        const PERMITS: usize = 3;
+        let sem = Semaphore::new(PERMITS);
+        let inside = AtomicUsize::new(0);
+        let max_inside = AtomicUsize::new(0);
+
+        thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    for _ in 0..200 {
+                        sem.acquire();
+                        let n = inside.fetch_add(1, Ordering::SeqCst) + 1;
+                        max_inside.fetch_max(n, Ordering::SeqCst);
+                        thread::yield_now();
+                        inside.fetch_sub(1, Ordering::SeqCst);
+                        sem.release();
+                    }
+                });
+            }
+        });
+
+        assert!(max_inside.load(Ordering::SeqCst) <= PERMITS);
+        assert_eq!(sem.available_permits(), PERMITS);
+    }
+}
@@ -0,0 +1,48 @@
+// Counts the bytes the whole process has allocated and not freed yet, and
+// the most there have been at once. The nodes a data structure has unlinked
+// but not reclaimed yet are counted too, which is what the workloads are
+// after: the backends differ in how long they hold on to them.
+//
+// Every allocation updates the shared counters, which slows down every
+// backend alike.
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+pub struct Counting;
+
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let live = LIVE.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK.fetch_max(live, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        LIVE.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+/// The bytes allocated and not freed yet.
+pub fn live() -> usize {
+    LIVE.load(Ordering::Relaxed)
+}
+
+/// The most bytes there were at once since the last `reset_peak`.
+pub fn peak() -> usize {
+    PEAK.load(Ordering::Relaxed)
+}
+
+/// Starts tracking the peak from the current number of bytes, which it also
+/// returns.
+pub fn reset_peak() -> usize {
+    let live = live();
+    PEAK.store(live, Ordering::Relaxed);
+    live
+}
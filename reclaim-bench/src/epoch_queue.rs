@@ -0,0 +1,194 @@
+// The Michael-Scott queue of michael-scott-q, reclaimed by crossbeam-epoch
+// like it, but without its notifier: michael_scott_q::Queue::push wakes
+// blocked pops, which costs a SeqCst fence that SeizeQueue and HazardQueue
+// don't pay. See that crate for how the algorithm works.
+use std::mem::MaybeUninit;
+use std::sync::atomic::Ordering;
+
+use crossbeam_epoch::{Atomic, Owned, Shared};
+
+pub struct EpochQueue<T> {
+    head: Atomic<Node<T>>,
+    tail: Atomic<Node<T>>,
+}
+
+struct Node<T> {
+    // Uninitialized in the dummy node the head points to. A pop moves the
+    // data out of the node that becomes the new dummy.
+    data: MaybeUninit<T>,
+    next: Atomic<Node<T>>,
+}
+
+unsafe impl<T: Send> Send for EpochQueue<T> {}
+unsafe impl<T: Send> Sync for EpochQueue<T> {}
+
+impl<T> EpochQueue<T> {
+    pub fn new() -> Self {
+        let dummy = Owned::new(Node {
+            data: MaybeUninit::uninit(),
+            next: Atomic::null(),
+        })
+        .into_shared(unsafe { crossbeam_epoch::unprotected() });
+        Self {
+            head: Atomic::from(dummy),
+            tail: Atomic::from(dummy),
+        }
+    }
+
+    pub fn push(&self, data: T) {
+        let guard = &crossbeam_epoch::pin();
+        let node = Owned::new(Node {
+            data: MaybeUninit::new(data),
+            next: Atomic::null(),
+        })
+        .into_shared(guard);
+
+        loop {
+            let tail = self.tail.load(Ordering::Acquire, guard);
+            let tail_ref = unsafe { tail.deref() };
+            let next = tail_ref.next.load(Ordering::Acquire, guard);
+            if !next.is_null() {
+                // The tail is lagging behind, help moving it.
+                let _ = self.tail.compare_exchange(
+                    tail,
+                    next,
+                    Ordering::Release,
+                    Ordering::Relaxed,
+                    guard,
+                );
+                continue;
+            }
+            if tail_ref
+                .next
+                .compare_exchange(
+                    Shared::null(),
+                    node,
+                    Ordering::Release,
+                    Ordering::Relaxed,
+                    guard,
+                )
+                .is_ok()
+            {
+                // Whoever comes along next fixes the tail if this fails.
+                let _ = self.tail.compare_exchange(
+                    tail,
+                    node,
+                    Ordering::Release,
+                    Ordering::Relaxed,
+                    guard,
+                );
+                return;
+            }
+        }
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        let guard = &crossbeam_epoch::pin();
+
+        loop {
+            let head = self.head.load(Ordering::Acquire, guard);
+            let next = unsafe { head.deref() }.next.load(Ordering::Acquire, guard);
+            let next_ref = unsafe { next.as_ref() }?;
+            // The head can't move past the tail, or the tail would point to
+            // a retired node.
+            let tail = self.tail.load(Ordering::Acquire, guard);
+            if tail == head {
+                let _ = self.tail.compare_exchange(
+                    tail,
+                    next,
+                    Ordering::Release,
+                    Ordering::Relaxed,
+                    guard,
+                );
+                continue;
+            }
+            if self
+                .head
+                .compare_exchange(head, next, Ordering::Release, Ordering::Relaxed, guard)
+                .is_ok()
+            {
+                unsafe {
+                    // next is the new dummy, and pinned until we're done.
+                    let data = next_ref.data.assume_init_read();
+                    guard.defer_destroy(head);
+                    return Some(data);
+                }
+            }
+        }
+    }
+}
+
+impl<T> Default for EpochQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for EpochQueue<T> {
+    fn drop(&mut self) {
+        unsafe {
+            let guard = crossbeam_epoch::unprotected();
+            let dummy = self.head.load(Ordering::Relaxed, guard);
+            let mut node = dummy.deref().next.load(Ordering::Relaxed, guard);
+            drop(dummy.into_owned());
+            while !node.is_null() {
+                let mut owned = node.into_owned();
+                node = owned.next.load(Ordering::Relaxed, guard);
+                owned.data.assume_init_drop();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn first_in_first_out() {
+        let queue = EpochQueue::new();
+        assert_eq!(queue.pop(), None);
+        for i in 0..10 {
+            queue.push(i);
+        }
+        for i in 0..10 {
+            assert_eq!(queue.pop(), Some(i));
+        }
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[test]
+    fn concurrent_pushes_and_pops_lose_nothing() {
+        let queue = EpochQueue::new();
+        let popped: Vec<Vec<u64>> = thread::scope(|s| {
+            let handles: Vec<_> = (0..4)
+                .map(|t| {
+                    let queue = &queue;
+                    s.spawn(move || {
+                        (0..1000)
+                            .filter_map(|i| {
+                                queue.push(t * 1000 + i);
+                                queue.pop()
+                            })
+                            .collect()
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        let mut all: Vec<u64> = popped.into_iter().flatten().collect();
+        all.sort();
+        assert_eq!(all, (0..4000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn drop_frees_what_is_left() {
+        let queue = EpochQueue::new();
+        for i in 0..10 {
+            queue.push(i.to_string());
+        }
+        queue.pop();
+    }
+}
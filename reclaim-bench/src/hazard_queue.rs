@@ -0,0 +1,187 @@
+// The Michael-Scott queue of michael-scott-q, with nodes reclaimed by hazard
+// pointers (haphazard's global domain) instead of crossbeam-epoch. See that
+// crate for how the algorithm works.
+use std::mem::MaybeUninit;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+use haphazard::{Domain, HazardPointer};
+
+pub struct HazardQueue<T> {
+    head: AtomicPtr<Node<T>>,
+    tail: AtomicPtr<Node<T>>,
+}
+
+struct Node<T> {
+    // Uninitialized in the dummy node the head points to. A pop moves the
+    // data out of the node that becomes the new dummy.
+    data: MaybeUninit<T>,
+    next: AtomicPtr<Node<T>>,
+}
+
+// Retired nodes are dropped by whichever thread reclaims them.
+unsafe impl<T: Send> Send for Node<T> {}
+
+unsafe impl<T: Send> Send for HazardQueue<T> {}
+unsafe impl<T: Send> Sync for HazardQueue<T> {}
+
+impl<T> HazardQueue<T> {
+    pub fn new() -> Self {
+        let dummy = Box::into_raw(Box::new(Node {
+            data: MaybeUninit::uninit(),
+            next: AtomicPtr::new(ptr::null_mut()),
+        }));
+        Self {
+            head: AtomicPtr::new(dummy),
+            tail: AtomicPtr::new(dummy),
+        }
+    }
+
+    pub fn push(&self, data: T) {
+        let node = Box::into_raw(Box::new(Node {
+            data: MaybeUninit::new(data),
+            next: AtomicPtr::new(ptr::null_mut()),
+        }));
+        let mut hp = HazardPointer::new();
+
+        loop {
+            // The tail is never null, there's always at least the dummy.
+            let (tail, _) = hp.protect_ptr(&self.tail).unwrap();
+            let tail = tail.as_ptr();
+            let tail_ref = unsafe { &*tail };
+            // Only compared, never read through, so it needs no protection.
+            let next = tail_ref.next.load(Ordering::Acquire);
+            if !next.is_null() {
+                // The tail is lagging behind, help moving it.
+                let _ =
+                    self.tail
+                        .compare_exchange(tail, next, Ordering::Release, Ordering::Relaxed);
+                continue;
+            }
+            if tail_ref
+                .next
+                .compare_exchange(next, node, Ordering::Release, Ordering::Relaxed)
+                .is_ok()
+            {
+                // Whoever comes along next fixes the tail if this fails.
+                let _ =
+                    self.tail
+                        .compare_exchange(tail, node, Ordering::Release, Ordering::Relaxed);
+                return;
+            }
+        }
+    }
+
+    pub fn pop(&self) -> Option<T>
+    where
+        T: Send,
+    {
+        let mut hps = HazardPointer::many::<2>();
+        let [hp_head, hp_next] = hps.as_refs();
+
+        loop {
+            let (head, _) = hp_head.protect_ptr(&self.head).unwrap();
+            let head = head.as_ptr();
+            let (next, _) = hp_next.protect_ptr(unsafe { &(&*head).next })?;
+            let next = next.as_ptr();
+            // A head that moved on may have been popped along with its next,
+            // which then wasn't protected in time.
+            if self.head.load(Ordering::Acquire) != head {
+                continue;
+            }
+            // The head can't move past the tail, or the tail would point to
+            // a retired node.
+            let tail = self.tail.load(Ordering::Acquire);
+            if tail == head {
+                let _ =
+                    self.tail
+                        .compare_exchange(tail, next, Ordering::Release, Ordering::Relaxed);
+                continue;
+            }
+            if self
+                .head
+                .compare_exchange(head, next, Ordering::Release, Ordering::Relaxed)
+                .is_ok()
+            {
+                // next is the new dummy, and protected until we're done.
+                let data = unsafe { (&*next).data.assume_init_read() };
+                hp_head.reset_protection();
+                hp_next.reset_protection();
+                // SAFETY: the old dummy is unlinked, and retired only by us.
+                unsafe { Domain::global().retire_ptr::<Node<T>, Box<Node<T>>>(head) };
+                return Some(data);
+            }
+        }
+    }
+}
+
+impl<T> Default for HazardQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for HazardQueue<T> {
+    fn drop(&mut self) {
+        let dummy = unsafe { Box::from_raw(*self.head.get_mut()) };
+        let mut node = dummy.next.load(Ordering::Relaxed);
+        while !node.is_null() {
+            let mut owned = unsafe { Box::from_raw(node) };
+            node = *owned.next.get_mut();
+            unsafe { owned.data.assume_init_drop() };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn first_in_first_out() {
+        let queue = HazardQueue::new();
+        assert_eq!(queue.pop(), None);
+        for i in 0..10 {
+            queue.push(i);
+        }
+        for i in 0..10 {
+            assert_eq!(queue.pop(), Some(i));
+        }
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[test]
+    fn concurrent_pushes_and_pops_lose_nothing() {
+        let queue = HazardQueue::new();
+        let popped: Vec<Vec<u64>> = thread::scope(|s| {
+            let handles: Vec<_> = (0..4)
+                .map(|t| {
+                    let queue = &queue;
+                    s.spawn(move || {
+                        (0..1000)
+                            .filter_map(|i| {
+                                queue.push(t * 1000 + i);
+                                queue.pop()
+                            })
+                            .collect()
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        let mut all: Vec<u64> = popped.into_iter().flatten().collect();
+        all.sort();
+        assert_eq!(all, (0..4000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn drop_frees_what_is_left() {
+        let queue = HazardQueue::new();
+        for i in 0..10 {
+            queue.push(i.to_string());
+        }
+        queue.pop();
+    }
+}
@@ -0,0 +1,149 @@
+// The Treiber stack of treiber-stack, with nodes reclaimed by hazard pointers
+// (haphazard's global domain) instead of crossbeam-epoch. A pop protects the
+// head it's about to read, which also keeps the head from being reused while
+// it's protected, so the CAS can't suffer from ABA.
+use std::mem::ManuallyDrop;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+use haphazard::{Domain, HazardPointer};
+
+pub struct HazardStack<T> {
+    head: AtomicPtr<Node<T>>,
+}
+
+struct Node<T> {
+    // Moved out by the pop that unlinks the node, before the node is retired.
+    data: ManuallyDrop<T>,
+    prev: *mut Node<T>,
+}
+
+// Retired nodes are dropped by whichever thread reclaims them.
+unsafe impl<T: Send> Send for Node<T> {}
+
+unsafe impl<T: Send> Send for HazardStack<T> {}
+unsafe impl<T: Send> Sync for HazardStack<T> {}
+
+impl<T> HazardStack<T> {
+    pub fn new() -> Self {
+        Self {
+            head: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    pub fn push(&self, data: T) {
+        let node = Box::into_raw(Box::new(Node {
+            data: ManuallyDrop::new(data),
+            prev: ptr::null_mut(),
+        }));
+        // Not shared until the CAS succeeds.
+        let node_ref = unsafe { &mut *node };
+
+        loop {
+            // Push never reads through the head, so it needs no protection.
+            let head = self.head.load(Ordering::Acquire);
+            node_ref.prev = head;
+            if self
+                .head
+                .compare_exchange(head, node, Ordering::Release, Ordering::Relaxed)
+                .is_ok()
+            {
+                return;
+            }
+        }
+    }
+
+    pub fn pop(&self) -> Option<T>
+    where
+        T: Send,
+    {
+        let mut hp = HazardPointer::new();
+
+        loop {
+            let (head, _) = hp.protect_ptr(&self.head)?;
+            let head = head.as_ptr();
+            let node = unsafe { &*head };
+            if self
+                .head
+                .compare_exchange(head, node.prev, Ordering::Release, Ordering::Relaxed)
+                .is_ok()
+            {
+                let data = unsafe { ptr::read(&node.data) };
+                hp.reset_protection();
+                // SAFETY: the node is unlinked, and retired only by us.
+                unsafe { Domain::global().retire_ptr::<Node<T>, Box<Node<T>>>(head) };
+                return Some(ManuallyDrop::into_inner(data));
+            }
+        }
+    }
+}
+
+impl<T> Default for HazardStack<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for HazardStack<T> {
+    fn drop(&mut self) {
+        let mut node = *self.head.get_mut();
+        while !node.is_null() {
+            let mut owned = unsafe { Box::from_raw(node) };
+            node = owned.prev;
+            unsafe { ManuallyDrop::drop(&mut owned.data) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn last_in_first_out() {
+        let stack = HazardStack::new();
+        assert_eq!(stack.pop(), None);
+        for i in 0..10 {
+            stack.push(i);
+        }
+        for i in (0..10).rev() {
+            assert_eq!(stack.pop(), Some(i));
+        }
+        assert_eq!(stack.pop(), None);
+    }
+
+    #[test]
+    fn concurrent_pushes_and_pops_lose_nothing() {
+        let stack = HazardStack::new();
+        let popped: Vec<Vec<u64>> = thread::scope(|s| {
+            let handles: Vec<_> = (0..4)
+                .map(|t| {
+                    let stack = &stack;
+                    s.spawn(move || {
+                        (0..1000)
+                            .filter_map(|i| {
+                                stack.push(t * 1000 + i);
+                                stack.pop()
+                            })
+                            .collect()
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        let mut all: Vec<u64> = popped.into_iter().flatten().collect();
+        all.sort();
+        assert_eq!(all, (0..4000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn drop_frees_what_is_left() {
+        let stack = HazardStack::new();
+        for i in 0..10 {
+            stack.push(i.to_string());
+        }
+        stack.pop();
+    }
+}
@@ -0,0 +1,239 @@
+//! Runs the same stack and queue workloads against every memory reclamation
+//! backend in the workspace, and compares them with bench-report.
+//!
+//! The epoch runs use the workspace's own `treiber_stack::Stack`, which
+//! reclaims with crossbeam-epoch, and `EpochQueue`, the algorithm of
+//! `michael_scott_q::Queue` without the notifier that wakes its blocked pops,
+//! so that no backend pays for a fence the others don't. The seize runs use
+//! `SeizeStack` and `SeizeQueue`, and the hazard pointer runs use
+//! `HazardStack` and `HazardQueue`: the same algorithms with the other
+//! backend swapped in. Another backend takes part by implementing
+//! `Container` for its stack and queue and adding them to `compare`.
+//!
+//! Besides throughput and latencies, every run reports how much memory it
+//! held on to, measured by a counting global allocator:
+//!
+//! - `peak_bytes`: the most bytes allocated at once during the run, above
+//!   what was allocated before it. The workloads keep the containers nearly
+//!   empty, so this is mostly nodes that were popped but not reclaimed yet.
+//! - `end_bytes`: what's still allocated once the threads are done and the
+//!   container is dropped, i.e. retired nodes the backend hasn't freed yet.
+//!
+//! Both include the latency recorders of the threads, which take about the
+//! same for every backend.
+mod alloc;
+
+mod epoch_queue;
+pub use epoch_queue::EpochQueue;
+
+mod hazard_queue;
+pub use hazard_queue::HazardQueue;
+
+mod hazard_stack;
+pub use hazard_stack::HazardStack;
+
+mod seize_queue;
+pub use seize_queue::SeizeQueue;
+
+mod seize_stack;
+pub use seize_stack::SeizeStack;
+
+use std::sync::{Arc, Barrier};
+use std::thread;
+use std::time::Instant;
+
+use bench_report::{Comparison, Recorder, Report};
+
+#[global_allocator]
+static ALLOC: alloc::Counting = alloc::Counting;
+
+/// A stack or queue that a workload runs against.
+pub trait Container: Send + Sync {
+    fn push(&self, val: u64);
+    fn pop(&self) -> Option<u64>;
+}
+
+impl Container for treiber_stack::Stack<u64> {
+    fn push(&self, val: u64) {
+        self.push(val)
+    }
+
+    fn pop(&self) -> Option<u64> {
+        self.pop()
+    }
+}
+
+impl Container for EpochQueue<u64> {
+    fn push(&self, val: u64) {
+        self.push(val)
+    }
+
+    fn pop(&self) -> Option<u64> {
+        self.pop()
+    }
+}
+
+impl Container for SeizeStack<u64> {
+    fn push(&self, val: u64) {
+        self.push(val)
+    }
+
+    fn pop(&self) -> Option<u64> {
+        self.pop()
+    }
+}
+
+impl Container for SeizeQueue<u64> {
+    fn push(&self, val: u64) {
+        self.push(val)
+    }
+
+    fn pop(&self) -> Option<u64> {
+        self.pop()
+    }
+}
+
+impl Container for HazardStack<u64> {
+    fn push(&self, val: u64) {
+        self.push(val)
+    }
+
+    fn pop(&self) -> Option<u64> {
+        self.pop()
+    }
+}
+
+impl Container for HazardQueue<u64> {
+    fn push(&self, val: u64) {
+        self.push(val)
+    }
+
+    fn pop(&self) -> Option<u64> {
+        self.pop()
+    }
+}
+
+/// Every thread pushes and then pops, `ops` times. So a pop never finds the
+/// container empty, every pop retires a node, and the container never holds
+/// more than one element per thread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Workload {
+    pub threads: usize,
+    pub ops: usize,
+}
+
+/// How much memory a run held on to, in bytes, see the crate docs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Memory {
+    pub peak: usize,
+    pub end: usize,
+}
+
+impl Workload {
+    pub fn run<C: Container + 'static>(&self, title: &str, container: C) -> (Report, Memory) {
+        let baseline = alloc::reset_peak();
+        let container = Arc::new(container);
+        // Counted in, so the threads start together once they're spawned.
+        let barrier = Arc::new(Barrier::new(self.threads + 1));
+
+        let handles: Vec<_> = (0..self.threads)
+            .map(|t| {
+                let container = Arc::clone(&container);
+                let barrier = Arc::clone(&barrier);
+                let ops = self.ops;
+                thread::spawn(move || {
+                    let mut recorder = Recorder::new(format!("thread-{}", t));
+                    barrier.wait();
+                    for i in 0..ops {
+                        let val = (t * ops + i) as u64;
+                        recorder.time("push", || container.push(val));
+                        let popped = recorder.time("pop", || container.pop());
+                        assert!(popped.is_some(), "popped from an empty container");
+                    }
+                    recorder
+                })
+            })
+            .collect();
+
+        barrier.wait();
+        let start = Instant::now();
+        let recorders = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        let elapsed = start.elapsed();
+
+        drop(container);
+        let memory = Memory {
+            peak: alloc::peak().saturating_sub(baseline),
+            end: alloc::live().saturating_sub(baseline),
+        };
+        (Report::new(title, elapsed, recorders), memory)
+    }
+}
+
+/// Runs `workload` against the stack and queue of every backend, one after
+/// the other.
+pub fn compare(workload: Workload) -> Comparison {
+    let runs = [
+        workload.run("stack/epoch", treiber_stack::Stack::<u64>::new()),
+        workload.run("stack/seize", SeizeStack::<u64>::new()),
+        workload.run("stack/hazard", HazardStack::<u64>::new()),
+        workload.run("queue/epoch", EpochQueue::<u64>::new()),
+        workload.run("queue/seize", SeizeQueue::<u64>::new()),
+        workload.run("queue/hazard", HazardQueue::<u64>::new()),
+    ];
+
+    let mut comparison = Comparison::new(format!(
+        "reclamation backends ({} threads, {} push/pop pairs each)",
+        workload.threads, workload.ops
+    ));
+    for (report, memory) in runs {
+        let metrics = [
+            ("peak_bytes", memory.peak as u64),
+            ("end_bytes", memory.end as u64),
+        ];
+        comparison.add(report, &metrics);
+    }
+    comparison
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bench_report::Format;
+
+    #[test]
+    fn every_backend_runs_every_op() {
+        let workload = Workload {
+            threads: 2,
+            ops: 500,
+        };
+        let csv = compare(workload).render(Format::Csv);
+        let rows: Vec<&str> = csv.lines().skip(1).collect();
+
+        assert_eq!(rows.len(), 6 * 2);
+        let runs = [
+            "stack/epoch",
+            "stack/seize",
+            "stack/hazard",
+            "queue/epoch",
+            "queue/seize",
+            "queue/hazard",
+        ];
+        for run in runs {
+            for op in ["pop", "push"] {
+                let prefix = format!("{},{},1000,", run, op);
+                assert!(rows.iter().any(|r| r.starts_with(&prefix)), "{}", prefix);
+            }
+        }
+    }
+
+    #[test]
+    fn popped_nodes_show_up_in_the_peak() {
+        let workload = Workload {
+            threads: 1,
+            ops: 1000,
+        };
+        let (_, memory) = workload.run("stack/seize", SeizeStack::<u64>::new());
+        // At least the node of the first push was allocated.
+        assert!(memory.peak >= std::mem::size_of::<u64>());
+    }
+}
@@ -0,0 +1,21 @@
+use bench_report::Format;
+use reclaim_bench::{compare, Workload};
+
+// Usage: reclaim-bench [text|csv|json] [threads] [ops per thread]
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let format = match args.next() {
+        None => Format::Text,
+        Some(f) => f.parse().unwrap_or_else(|e| panic!("{}", e)),
+    };
+    let mut number = |default: usize| match args.next() {
+        None => default,
+        Some(n) => n.parse().unwrap_or_else(|_| panic!("not a number: {}", n)),
+    };
+    let workload = Workload {
+        threads: number(4),
+        ops: number(100_000),
+    };
+
+    print!("{}", compare(workload).render(format));
+}
@@ -0,0 +1,174 @@
+// The Michael-Scott queue of michael-scott-q, with nodes reclaimed by seize
+// instead of crossbeam-epoch. See that crate for how the algorithm works.
+use std::mem::MaybeUninit;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+use seize::{reclaim, Collector, Linked};
+
+pub struct SeizeQueue<T> {
+    head: AtomicPtr<Linked<Node<T>>>,
+    tail: AtomicPtr<Linked<Node<T>>>,
+    collector: Collector,
+}
+
+struct Node<T> {
+    // Uninitialized in the dummy node the head points to. A pop moves the
+    // data out of the node that becomes the new dummy.
+    data: MaybeUninit<T>,
+    next: AtomicPtr<Linked<Node<T>>>,
+}
+
+unsafe impl<T: Send> Send for SeizeQueue<T> {}
+unsafe impl<T: Send> Sync for SeizeQueue<T> {}
+
+impl<T> SeizeQueue<T> {
+    pub fn new() -> Self {
+        let collector = Collector::new();
+        let dummy = collector.link_boxed(Node {
+            data: MaybeUninit::uninit(),
+            next: AtomicPtr::new(ptr::null_mut()),
+        });
+        Self {
+            head: AtomicPtr::new(dummy),
+            tail: AtomicPtr::new(dummy),
+            collector,
+        }
+    }
+
+    pub fn push(&self, data: T) {
+        let node = self.collector.link_boxed(Node {
+            data: MaybeUninit::new(data),
+            next: AtomicPtr::new(ptr::null_mut()),
+        });
+        let guard = self.collector.enter();
+
+        loop {
+            let tail = guard.protect(&self.tail, Ordering::Acquire);
+            let tail_ref = unsafe { &*tail };
+            let next = guard.protect(&tail_ref.next, Ordering::Acquire);
+            if !next.is_null() {
+                // The tail is lagging behind, help moving it.
+                let _ =
+                    self.tail
+                        .compare_exchange(tail, next, Ordering::Release, Ordering::Relaxed);
+                continue;
+            }
+            if tail_ref
+                .next
+                .compare_exchange(next, node, Ordering::Release, Ordering::Relaxed)
+                .is_ok()
+            {
+                // Whoever comes along next fixes the tail if this fails.
+                let _ =
+                    self.tail
+                        .compare_exchange(tail, node, Ordering::Release, Ordering::Relaxed);
+                return;
+            }
+        }
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        let guard = self.collector.enter();
+
+        loop {
+            let head = guard.protect(&self.head, Ordering::Acquire);
+            let next = guard.protect(unsafe { &(&*head).next }, Ordering::Acquire);
+            if next.is_null() {
+                return None;
+            }
+            // The head can't move past the tail, or the tail would point to
+            // a retired node.
+            let tail = self.tail.load(Ordering::Acquire);
+            if tail == head {
+                let _ =
+                    self.tail
+                        .compare_exchange(tail, next, Ordering::Release, Ordering::Relaxed);
+                continue;
+            }
+            if self
+                .head
+                .compare_exchange(head, next, Ordering::Release, Ordering::Relaxed)
+                .is_ok()
+            {
+                unsafe {
+                    // next is the new dummy, and protected until we're done.
+                    let data = (&*next).data.assume_init_read();
+                    guard.retire(head, reclaim::boxed::<Node<T>>);
+                    return Some(data);
+                }
+            }
+        }
+    }
+}
+
+impl<T> Default for SeizeQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for SeizeQueue<T> {
+    fn drop(&mut self) {
+        let dummy = unsafe { Box::from_raw(*self.head.get_mut()) };
+        let mut node = dummy.next.load(Ordering::Relaxed);
+        while !node.is_null() {
+            let mut owned = unsafe { Box::from_raw(node) };
+            node = *owned.next.get_mut();
+            unsafe { owned.data.assume_init_drop() };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn first_in_first_out() {
+        let queue = SeizeQueue::new();
+        assert_eq!(queue.pop(), None);
+        for i in 0..10 {
+            queue.push(i);
+        }
+        for i in 0..10 {
+            assert_eq!(queue.pop(), Some(i));
+        }
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[test]
+    fn concurrent_pushes_and_pops_lose_nothing() {
+        let queue = SeizeQueue::new();
+        let popped: Vec<Vec<u64>> = thread::scope(|s| {
+            let handles: Vec<_> = (0..4)
+                .map(|t| {
+                    let queue = &queue;
+                    s.spawn(move || {
+                        (0..1000)
+                            .filter_map(|i| {
+                                queue.push(t * 1000 + i);
+                                queue.pop()
+                            })
+                            .collect()
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        let mut all: Vec<u64> = popped.into_iter().flatten().collect();
+        all.sort();
+        assert_eq!(all, (0..4000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn drop_frees_what_is_left() {
+        let queue = SeizeQueue::new();
+        for i in 0..10 {
+            queue.push(i.to_string());
+        }
+        queue.pop();
+    }
+}
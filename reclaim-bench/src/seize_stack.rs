@@ -0,0 +1,142 @@
+// The Treiber stack of treiber-stack, with nodes reclaimed by seize instead
+// of crossbeam-epoch, so the two backends can be compared on the same
+// algorithm.
+use std::mem::ManuallyDrop;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+use seize::{reclaim, Collector, Linked};
+
+pub struct SeizeStack<T> {
+    head: AtomicPtr<Linked<Node<T>>>,
+    collector: Collector,
+}
+
+struct Node<T> {
+    // Moved out by the pop that unlinks the node, before the node is retired.
+    data: ManuallyDrop<T>,
+    prev: *mut Linked<Node<T>>,
+}
+
+unsafe impl<T: Send> Send for SeizeStack<T> {}
+unsafe impl<T: Send> Sync for SeizeStack<T> {}
+
+impl<T> SeizeStack<T> {
+    pub fn new() -> Self {
+        Self {
+            head: AtomicPtr::new(ptr::null_mut()),
+            collector: Collector::new(),
+        }
+    }
+
+    pub fn push(&self, data: T) {
+        let node = self.collector.link_boxed(Node {
+            data: ManuallyDrop::new(data),
+            prev: ptr::null_mut(),
+        });
+        let guard = self.collector.enter();
+
+        loop {
+            let head = guard.protect(&self.head, Ordering::Acquire);
+            // Not shared until the CAS succeeds.
+            unsafe { (&mut *node).prev = head };
+            if self
+                .head
+                .compare_exchange(head, node, Ordering::Release, Ordering::Relaxed)
+                .is_ok()
+            {
+                return;
+            }
+        }
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        let guard = self.collector.enter();
+
+        loop {
+            let head = guard.protect(&self.head, Ordering::Acquire);
+            let node = unsafe { head.as_ref()? };
+            if self
+                .head
+                .compare_exchange(head, node.prev, Ordering::Release, Ordering::Relaxed)
+                .is_ok()
+            {
+                unsafe {
+                    let data = ptr::read(&node.data);
+                    guard.retire(head, reclaim::boxed::<Node<T>>);
+                    return Some(ManuallyDrop::into_inner(data));
+                }
+            }
+        }
+    }
+}
+
+impl<T> Default for SeizeStack<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for SeizeStack<T> {
+    fn drop(&mut self) {
+        let mut node = *self.head.get_mut();
+        while !node.is_null() {
+            let mut owned = unsafe { Box::from_raw(node) };
+            node = owned.prev;
+            unsafe { ManuallyDrop::drop(&mut owned.data) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn last_in_first_out() {
+        let stack = SeizeStack::new();
+        assert_eq!(stack.pop(), None);
+        for i in 0..10 {
+            stack.push(i);
+        }
+        for i in (0..10).rev() {
+            assert_eq!(stack.pop(), Some(i));
+        }
+        assert_eq!(stack.pop(), None);
+    }
+
+    #[test]
+    fn concurrent_pushes_and_pops_lose_nothing() {
+        let stack = SeizeStack::new();
+        let popped: Vec<Vec<u64>> = thread::scope(|s| {
+            let handles: Vec<_> = (0..4)
+                .map(|t| {
+                    let stack = &stack;
+                    s.spawn(move || {
+                        (0..1000)
+                            .filter_map(|i| {
+                                stack.push(t * 1000 + i);
+                                stack.pop()
+                            })
+                            .collect()
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        let mut all: Vec<u64> = popped.into_iter().flatten().collect();
+        all.sort();
+        assert_eq!(all, (0..4000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn drop_frees_what_is_left() {
+        let stack = SeizeStack::new();
+        for i in 0..10 {
+            stack.push(i.to_string());
+        }
+        stack.pop();
+    }
+}
@@ -0,0 +1,297 @@
+//! Waits on several sources at once and returns whichever is ready first.
+//!
+//! ```ignore
+//! let msg = Select::new()
+//!     .recv(jobs.pop_async(), Msg::Job)
+//!     .recv(urgent.pop(), Msg::Urgent)
+//!     .recv(token.cancelled(), |_| Msg::Cancelled)
+//!     .wait();
+//! ```
+//!
+//! A source is any future, so the waiting futures of the workspace's
+//! primitives take part as they are: `DualQueue::pop_async`, `AsyncStack`'s
+//! `pop` and `push`, `CancellationToken::cancelled` and manfut's
+//! `Completion`. Their wakers are the registration: every branch is polled,
+//! which registers the select's waker with each source, and whichever source
+//! changes first wakes it.
+//!
+//! The protocol has one more rule, which is what the futures above guarantee:
+//! dropping a future that wasn't ready gives up its registration without
+//! losing anything. The branches that didn't win are dropped as soon as one
+//! did, and a `DualQueue` reservation that was fulfilled in the meantime
+//! pushes its element again, while an `AsyncStack` waiter passes its wakeup
+//! on. Sources that can only block a thread, like `parking_lot::Notifier`,
+//! can't take part.
+//!
+//! `wait` and `wait_timeout` block the thread until a branch is ready, and
+//! awaiting the `Select` does the same in async code.
+use std::cell::Cell;
+use std::future::{Future, IntoFuture};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+use std::time::{Duration, Instant};
+
+type Branch<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+pub struct Select<'a, T> {
+    branches: Vec<Branch<'a, T>>,
+}
+
+thread_local! {
+    // Where the next select of this thread starts polling, see `Selected`.
+    static NEXT: Cell<usize> = const { Cell::new(0) };
+}
+
+impl<'a, T> Select<'a, T> {
+    pub fn new() -> Self {
+        Self { branches: vec![] }
+    }
+
+    /// Adds a branch that waits for `source`, and turns its output into the
+    /// output of the select with `f`.
+    pub fn recv<F, M>(mut self, source: F, f: M) -> Self
+    where
+        F: Future + Send + 'a,
+        M: FnOnce(F::Output) -> T + Send + 'a,
+    {
+        self.branches.push(Box::pin(async move { f(source.await) }));
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.branches.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.branches.is_empty()
+    }
+
+    /// Blocks the thread until a branch is ready.
+    ///
+    /// # Panics
+    ///
+    /// If there are no branches, which would block forever.
+    pub fn wait(self) -> T {
+        block_on(self.into_future(), None).expect("no deadline")
+    }
+
+    /// Like `wait`, but gives up after `timeout`. Every branch is dropped
+    /// then, so no source loses anything to a select that timed out.
+    pub fn wait_timeout(self, timeout: Duration) -> Option<T> {
+        block_on(self.into_future(), Some(Instant::now() + timeout))
+    }
+}
+
+impl<T> Default for Select<'_, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, T> IntoFuture for Select<'a, T> {
+    type Output = T;
+    type IntoFuture = Selected<'a, T>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        assert!(!self.is_empty(), "select without branches");
+        // Consecutive selects start at consecutive branches, so a source
+        // that's always ready can't starve the ones after it.
+        let start = NEXT.with(|next| {
+            let start = next.get();
+            next.set(start.wrapping_add(1));
+            start
+        });
+        Selected {
+            start: start % self.branches.len(),
+            branches: self.branches,
+        }
+    }
+}
+
+/// The future of a `Select`, which resolves to the output of the first
+/// branch that's ready.
+pub struct Selected<'a, T> {
+    branches: Vec<Branch<'a, T>>,
+    // The branch polled first, for as long as the select lasts.
+    start: usize,
+}
+
+impl<T> Future for Selected<'_, T> {
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let len = self.branches.len();
+        let start = self.start;
+        for i in (start..len).chain(0..start) {
+            if let Poll::Ready(val) = self.branches[i].as_mut().poll(cx) {
+                // The others give up their registrations.
+                self.branches.clear();
+                return Poll::Ready(val);
+            }
+        }
+        Poll::Pending
+    }
+}
+
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+// Polls `fut` on this thread, parking in between, until it's ready or the
+// deadline passes.
+fn block_on<F: Future>(fut: F, deadline: Option<Instant>) -> Option<F::Output> {
+    let mut fut = std::pin::pin!(fut);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+
+    loop {
+        if let Poll::Ready(val) = fut.as_mut().poll(&mut cx) {
+            return Some(val);
+        }
+        // Parking can return spuriously, polling again is harmless.
+        match deadline {
+            None => thread::park(),
+            Some(deadline) => {
+                let now = Instant::now();
+                if now >= deadline {
+                    return None;
+                }
+                thread::park_timeout(deadline - now);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cancel_token::CancellationToken;
+    use manfut::completion::Completion;
+    use michael_scott_q::DualQueue;
+    use treiber_stack::AsyncStack;
+
+    #[derive(Debug, PartialEq)]
+    enum Msg {
+        A(u32),
+        B(u32),
+        Cancelled,
+    }
+
+    #[test]
+    fn first_ready_wins() {
+        let (a, b) = (DualQueue::new(), DualQueue::new());
+        b.push(7);
+
+        let msg = Select::new()
+            .recv(a.pop_async(), Msg::A)
+            .recv(b.pop_async(), Msg::B)
+            .wait();
+        assert_eq!(msg, Msg::B(7));
+
+        // The reservation on a is gone with the losing branch.
+        a.push(1);
+        assert_eq!(a.try_pop(), Some(1));
+    }
+
+    #[test]
+    fn blocks_until_a_push() {
+        let (a, b) = (DualQueue::new(), DualQueue::new());
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(20));
+                a.push(3);
+            });
+            let msg = Select::new()
+                .recv(a.pop_async(), Msg::A)
+                .recv(b.pop_async(), Msg::B)
+                .wait();
+            assert_eq!(msg, Msg::A(3));
+        });
+    }
+
+    #[test]
+    fn timed_out_selects_lose_nothing() {
+        let q = DualQueue::new();
+        let token = CancellationToken::new();
+
+        let msg = Select::new()
+            .recv(q.pop_async(), Msg::A)
+            .recv(token.cancelled(), |_| Msg::Cancelled)
+            .wait_timeout(Duration::from_millis(10));
+        assert_eq!(msg, None);
+
+        q.push(1);
+        assert_eq!(q.try_pop(), Some(1));
+
+        token.cancel();
+        let msg = Select::new()
+            .recv(q.pop_async(), Msg::A)
+            .recv(token.cancelled(), |_| Msg::Cancelled)
+            .wait_timeout(Duration::from_secs(5));
+        assert_eq!(msg, Some(Msg::Cancelled));
+    }
+
+    #[test]
+    fn ready_branches_take_turns() {
+        let (a, b) = (DualQueue::new(), DualQueue::new());
+        for i in 0..100 {
+            a.push(i);
+            b.push(i);
+        }
+
+        let mut from_a = 0;
+        for _ in 0..100 {
+            let msg = Select::new()
+                .recv(a.pop_async(), Msg::A)
+                .recv(b.pop_async(), Msg::B)
+                .wait();
+            if let Msg::A(_) = msg {
+                from_a += 1;
+            }
+        }
+        assert_eq!(from_a, 50);
+        // Popping from the losing queue and pushing the element back would
+        // reorder it, so nothing may have been popped there.
+        assert_eq!(a.try_pop(), Some(50));
+        assert_eq!(b.try_pop(), Some(50));
+    }
+
+    #[tokio::test]
+    async fn awaits_stacks_and_completions() {
+        let stack = Arc::new(AsyncStack::new());
+        let (completion, completer) = Completion::pending();
+
+        let select = Select::new()
+            .recv(stack.pop(), Msg::A)
+            .recv(completion.clone().into_future(), Msg::B);
+        let waiter = tokio::spawn({
+            let stack = Arc::clone(&stack);
+            async move {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                stack.push(4).await;
+            }
+        });
+        assert_eq!(select.await, Msg::A(4));
+        waiter.await.unwrap();
+
+        assert!(completer.complete(5));
+        let msg = Select::new()
+            .recv(stack.pop(), Msg::A)
+            .recv(completion.into_future(), Msg::B)
+            .await;
+        assert_eq!(msg, Msg::B(5));
+    }
+
+    #[test]
+    #[should_panic(expected = "select without branches")]
+    fn empty_select() {
+        Select::<()>::new().wait();
+    }
+}
@@ -0,0 +1,82 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::sync::Mutex;
+use std::thread;
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use skiplist_pq::PriorityQueue;
+
+const PREFILL: u64 = 1_000;
+const OPS_PER_THREAD: u64 = 10_000;
+
+fn priority(thread: u64, i: u64) -> u64 {
+    (i * 7919 + thread * 104_729) % 100_000
+}
+
+// Every thread alternates between pushes and pops, so the size stays around
+// PREFILL.
+fn skiplist(threads: u64) {
+    let pq = PriorityQueue::new(usize::MAX);
+    for i in 0..PREFILL {
+        pq.push(priority(threads, i), i).unwrap();
+    }
+    thread::scope(|s| {
+        for t in 0..threads {
+            let pq = &pq;
+            s.spawn(move || {
+                for i in 0..OPS_PER_THREAD {
+                    if i % 2 == 0 {
+                        pq.push(priority(t, i), i).unwrap();
+                    } else {
+                        black_box(pq.pop_min());
+                    }
+                }
+            });
+        }
+    });
+}
+
+fn mutex_binary_heap(threads: u64) {
+    let heap = Mutex::new(BinaryHeap::new());
+    for i in 0..PREFILL {
+        heap.lock()
+            .unwrap()
+            .push(Reverse((priority(threads, i), i)));
+    }
+    thread::scope(|s| {
+        for t in 0..threads {
+            let heap = &heap;
+            s.spawn(move || {
+                for i in 0..OPS_PER_THREAD {
+                    let mut heap = heap.lock().unwrap();
+                    if i % 2 == 0 {
+                        heap.push(Reverse((priority(t, i), i)));
+                    } else {
+                        black_box(heap.pop());
+                    }
+                }
+            });
+        }
+    });
+}
+
+pub fn vs_binary_heap_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("push_pop");
+    group.sample_size(20);
+    for threads in [1, 2, 4, 8] {
+        group.bench_with_input(
+            BenchmarkId::new("PriorityQueue", threads),
+            &threads,
+            |b, &t| b.iter(|| skiplist(t)),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("Mutex<BinaryHeap>", threads),
+            &threads,
+            |b, &t| b.iter(|| mutex_binary_heap(t)),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, vs_binary_heap_benchmark);
+criterion_main!(benches);
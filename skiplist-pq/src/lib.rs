@@ -0,0 +1,404 @@
+//! A bounded priority queue on top of a lock-free skip list.
+//!
+//! Entries are kept sorted by priority in a skip list like the one of
+//! cskiplist: a node is removed by marking its next pointers (the lowest
+//! bit), and marking level 0 is what removes it logically. `pop_min` follows
+//! Lotan and Shavit: it walks level 0 from the front and claims the first
+//! node that isn't marked yet by marking it. Whoever sets the mark owns the
+//! entry, and anyone who runs into a marked node on the way helps unlinking
+//! it. Entries with the same priority are popped in the order they were
+//! pushed, since every entry is keyed by its priority and a ticket.
+//!
+//! A pop that has already walked past the front of the list can miss an
+//! entry pushed there in the meantime, and return the next one instead. So
+//! pops are only ordered among themselves while no push is in progress:
+//! every thread then pops the entries in order, and no entry is popped
+//! while a smaller one is left.
+//!
+//! Nodes are reclaimed with crossbeam-epoch. Like in cskiplist, they count
+//! the levels they're linked into, plus one while their push is in progress,
+//! and whoever drops the count to zero destroys the node.
+use std::cell::Cell;
+use std::cmp;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::mem::ManuallyDrop;
+use std::ptr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use crossbeam_epoch::{self as epoch, Atomic, Guard, Owned, Shared};
+
+const MAX_HEIGHT: usize = 16;
+
+type Next<P, T> = Atomic<Node<P, T>>;
+
+pub struct PriorityQueue<P, T> {
+    // The next pointers of the head, one per level.
+    head: Box<[Next<P, T>]>,
+    capacity: usize,
+    // Counts the entries from before they're linked until they're claimed,
+    // so it never exceeds the capacity.
+    len: AtomicUsize,
+    next_ticket: AtomicU64,
+}
+
+struct Node<P, T> {
+    priority: P,
+    // Orders the entries with the same priority.
+    ticket: u64,
+    // Moved out by the pop that claims the node. The node itself stays
+    // around until it's unlinked, so its priority is still compared.
+    item: ManuallyDrop<T>,
+    // The levels the node is linked into, plus one while it's being pushed.
+    links: AtomicUsize,
+    next: Box<[Next<P, T>]>,
+}
+
+// Where a key is, or would be, on every level. A pred is represented by its
+// next pointers, so the head and the nodes can be treated the same.
+struct Position<'g, P, T> {
+    preds: [&'g [Next<P, T>]; MAX_HEIGHT],
+    succs: [Shared<'g, Node<P, T>>; MAX_HEIGHT],
+}
+
+// Items are only ever moved between threads, priorities are shared.
+unsafe impl<P: Send + Sync, T: Send> Send for PriorityQueue<P, T> {}
+unsafe impl<P: Send + Sync, T: Send> Sync for PriorityQueue<P, T> {}
+
+// A geometric distribution: height h has a probability of 1/2^h.
+fn random_height() -> usize {
+    thread_local! {
+        static STATE: Cell<u64> = Cell::new(RandomState::new().build_hasher().finish() | 1);
+    }
+
+    // xorshift64, which is plenty for deciding heights.
+    let x = STATE.with(|state| {
+        let mut x = state.get();
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        state.set(x);
+        x
+    });
+    (x.trailing_zeros() as usize + 1).min(MAX_HEIGHT)
+}
+
+impl<P: Ord, T> Node<P, T> {
+    fn cmp_key(&self, priority: &P, ticket: u64) -> cmp::Ordering {
+        self.priority.cmp(priority).then(self.ticket.cmp(&ticket))
+    }
+}
+
+impl<P, T> PriorityQueue<P, T> {
+    /// Creates a queue that holds at most `capacity` entries.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            head: (0..MAX_HEIGHT).map(|_| Atomic::null()).collect(),
+            capacity,
+            len: AtomicUsize::new(0),
+            next_ticket: AtomicU64::new(0),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The number of entries. It's only a snapshot while other threads are
+    /// pushing or popping.
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Drops a link of the node, and destroys it if it was the last one.
+    unsafe fn release(&self, node: Shared<'_, Node<P, T>>, guard: &Guard) {
+        if node.deref().links.fetch_sub(1, Ordering::AcqRel) == 1 {
+            guard.defer_destroy(node);
+        }
+    }
+}
+
+impl<P: Ord, T> PriorityQueue<P, T> {
+    /// Adds an entry, unless the queue is full. Then the arguments are handed
+    /// back.
+    pub fn push(&self, priority: P, item: T) -> Result<(), (P, T)> {
+        let reserved = self
+            .len
+            .fetch_update(Ordering::AcqRel, Ordering::Relaxed, |len| {
+                (len < self.capacity).then_some(len + 1)
+            })
+            .is_ok();
+        if !reserved {
+            return Err((priority, item));
+        }
+
+        let guard = &epoch::pin();
+        let ticket = self.next_ticket.fetch_add(1, Ordering::Relaxed);
+        let height = random_height();
+        let node = Owned::new(Node {
+            priority,
+            ticket,
+            item: ManuallyDrop::new(item),
+            // The push's own link, dropped at the end.
+            links: AtomicUsize::new(1),
+            next: (0..height).map(|_| Atomic::null()).collect(),
+        })
+        .into_shared(guard);
+        // SAFETY: the node isn't destroyed before we drop our link.
+        let n = unsafe { node.deref() };
+
+        // Linking level 0 pushes the entry. Tickets are unique, so there's
+        // never a node with the same key.
+        let mut pos = loop {
+            let pos = self.find(&n.priority, ticket, guard);
+            for (level, next) in n.next.iter().enumerate() {
+                next.store(pos.succs[level], Ordering::Relaxed);
+            }
+            n.links.fetch_add(1, Ordering::Relaxed);
+            let linked = pos.preds[0][0]
+                .compare_exchange(
+                    pos.succs[0],
+                    node,
+                    Ordering::AcqRel,
+                    Ordering::Acquire,
+                    guard,
+                )
+                .is_ok();
+            if linked {
+                break pos;
+            }
+            n.links.fetch_sub(1, Ordering::Relaxed);
+        };
+
+        // The upper levels only speed up searches, so we give up on them as
+        // soon as the node is popped.
+        'levels: for level in 1..height {
+            loop {
+                let succ = pos.succs[level];
+                let next = n.next[level].load(Ordering::Acquire, guard);
+                if next.tag() == 1 {
+                    break 'levels;
+                }
+                if next != succ
+                    && n.next[level]
+                        .compare_exchange(next, succ, Ordering::AcqRel, Ordering::Acquire, guard)
+                        .is_err()
+                {
+                    // It can only have been marked in the meantime.
+                    break 'levels;
+                }
+
+                // Counted before it's linked, as it can be unlinked right away.
+                n.links.fetch_add(1, Ordering::Relaxed);
+                let linked = pos.preds[level][level]
+                    .compare_exchange(succ, node, Ordering::AcqRel, Ordering::Acquire, guard)
+                    .is_ok();
+                if linked {
+                    break;
+                }
+                n.links.fetch_sub(1, Ordering::Relaxed);
+
+                pos = self.find(&n.priority, ticket, guard);
+                if pos.succs[0] != node {
+                    break 'levels;
+                }
+            }
+        }
+
+        unsafe { self.release(node, guard) };
+        Ok(())
+    }
+
+    /// Removes an entry with the lowest priority, the one pushed first if
+    /// there are several.
+    pub fn pop_min(&self) -> Option<(P, T)>
+    where
+        P: Clone,
+    {
+        let guard = &epoch::pin();
+        let mut curr = self.head[0].load(Ordering::Acquire, guard);
+        let node = loop {
+            let node = unsafe { curr.as_ref() }?;
+            // Claims the node, unless someone else did.
+            let next = node.next[0].fetch_or(1, Ordering::AcqRel, guard);
+            if next.tag() == 0 {
+                break node;
+            }
+            curr = next.with_tag(0);
+        };
+        self.len.fetch_sub(1, Ordering::Release);
+
+        // SAFETY: we claimed the node, so no one else reads its item.
+        let item = ManuallyDrop::into_inner(unsafe { ptr::read(&node.item) });
+        let priority = node.priority.clone();
+
+        // Top-down, so an insert still linking the node notices.
+        for next in node.next[1..].iter().rev() {
+            next.fetch_or(1, Ordering::AcqRel, guard);
+        }
+        // Unlinks the node from every level it's linked into by now.
+        self.find(&node.priority, node.ticket, guard);
+        Some((priority, item))
+    }
+
+    // Finds the preds and succs of the key on every level, where succs[0]
+    // is the first node with a key that isn't less than it. Marked nodes on
+    // the way are unlinked.
+    fn find<'g>(&'g self, priority: &P, ticket: u64, guard: &'g Guard) -> Position<'g, P, T> {
+        'retry: loop {
+            let mut pos = Position {
+                preds: [&*self.head; MAX_HEIGHT],
+                succs: [Shared::null(); MAX_HEIGHT],
+            };
+            let mut pred: &[Next<P, T>] = &self.head;
+
+            for level in (0..MAX_HEIGHT).rev() {
+                let mut curr = pred[level].load(Ordering::Acquire, guard);
+                if curr.tag() == 1 {
+                    // pred was popped since we got to it.
+                    continue 'retry;
+                }
+
+                while let Some(node) = unsafe { curr.as_ref() } {
+                    let succ = node.next[level].load(Ordering::Acquire, guard);
+                    if succ.tag() == 1 {
+                        // Help unlinking the popped node on this level.
+                        let unlinked = pred[level]
+                            .compare_exchange(
+                                curr,
+                                succ.with_tag(0),
+                                Ordering::AcqRel,
+                                Ordering::Acquire,
+                                guard,
+                            )
+                            .is_ok();
+                        if !unlinked {
+                            continue 'retry;
+                        }
+                        unsafe { self.release(curr, guard) };
+                        curr = succ.with_tag(0);
+                        continue;
+                    }
+
+                    if node.cmp_key(priority, ticket) != cmp::Ordering::Less {
+                        break;
+                    }
+                    pred = &node.next;
+                    curr = succ;
+                }
+
+                pos.preds[level] = pred;
+                pos.succs[level] = curr;
+            }
+            return pos;
+        }
+    }
+}
+
+impl<P, T> Drop for PriorityQueue<P, T> {
+    fn drop(&mut self) {
+        // No push is in progress, so every node has exactly as many links as
+        // levels it's linked into, and the ones that weren't popped are all
+        // on level 0, which comes last.
+        let guard = unsafe { epoch::unprotected() };
+        for level in (0..MAX_HEIGHT).rev() {
+            let mut curr = self.head[level].load(Ordering::Relaxed, guard);
+            while !curr.is_null() {
+                unsafe {
+                    let node = curr.deref_mut();
+                    let next = node.next[level].load(Ordering::Relaxed, guard);
+                    if level == 0 && next.tag() == 0 {
+                        ManuallyDrop::drop(&mut node.item);
+                    }
+                    if node.links.fetch_sub(1, Ordering::Relaxed) == 1 {
+                        drop(curr.into_owned());
+                    }
+                    curr = next.with_tag(0);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn pops_in_priority_order() {
+        let pq = PriorityQueue::new(16);
+        assert_eq!(pq.pop_min(), None);
+
+        for p in [5, 3, 9, 1, 7] {
+            pq.push(p, p * 10).unwrap();
+        }
+        assert_eq!(pq.len(), 5);
+
+        let popped: Vec<_> = std::iter::from_fn(|| pq.pop_min()).collect();
+        assert_eq!(popped, [(1, 10), (3, 30), (5, 50), (7, 70), (9, 90)]);
+        assert!(pq.is_empty());
+    }
+
+    #[test]
+    fn equal_priorities_pop_in_push_order() {
+        let pq = PriorityQueue::new(16);
+        pq.push(2, "b1").unwrap();
+        pq.push(1, "a").unwrap();
+        pq.push(2, "b2").unwrap();
+        pq.push(2, "b3").unwrap();
+
+        let popped: Vec<_> = std::iter::from_fn(|| pq.pop_min())
+            .map(|(_, i)| i)
+            .collect();
+        assert_eq!(popped, ["a", "b1", "b2", "b3"]);
+    }
+
+    #[test]
+    fn full_queue_hands_entries_back() {
+        let pq = PriorityQueue::new(2);
+        pq.push(1, 'a').unwrap();
+        pq.push(2, 'b').unwrap();
+        assert_eq!(pq.push(0, 'c'), Err((0, 'c')));
+
+        assert_eq!(pq.pop_min(), Some((1, 'a')));
+        pq.push(0, 'c').unwrap();
+        assert_eq!(pq.pop_min(), Some((0, 'c')));
+        assert_eq!(pq.capacity(), 2);
+    }
+
+    #[test]
+    fn many_entries_get_tall_towers() {
+        let pq = PriorityQueue::new(10_000);
+        for i in (0..10_000).rev() {
+            pq.push(i, ()).unwrap();
+        }
+        let guard = &epoch::pin();
+        assert!(!pq.head[8].load(Ordering::Relaxed, guard).is_null());
+
+        for i in 0..10_000 {
+            assert_eq!(pq.pop_min(), Some((i, ())));
+        }
+    }
+
+    #[test]
+    fn drops_every_item_once() {
+        let counter = Arc::new(());
+        let pq = PriorityQueue::new(1000);
+        for i in 0..1000 {
+            pq.push(i, counter.clone()).unwrap();
+        }
+        // Popped items belong to the caller, whenever their nodes are freed.
+        for _ in 0..300 {
+            pq.pop_min();
+        }
+        assert_eq!(Arc::strong_count(&counter), 701);
+
+        drop(pq);
+        assert_eq!(Arc::strong_count(&counter), 1);
+    }
+}
@@ -0,0 +1,108 @@
+//! Several threads push and pop at once. Every item is unique, so the items
+//! that were popped plus the ones left at the end must be exactly the ones
+//! that were pushed.
+use std::collections::BTreeSet;
+use std::sync::Mutex;
+use std::thread;
+
+use skiplist_pq::PriorityQueue;
+
+const THREADS: u64 = 4;
+const ITEMS_PER_THREAD: u64 = 5_000;
+
+#[test]
+fn no_items_are_lost() {
+    let pq = PriorityQueue::new(usize::MAX);
+    let popped = Mutex::new(vec![]);
+
+    thread::scope(|s| {
+        for t in 0..THREADS {
+            let (pq, popped) = (&pq, &popped);
+            s.spawn(move || {
+                let mut mine = vec![];
+                for i in 0..ITEMS_PER_THREAD {
+                    let item = t * ITEMS_PER_THREAD + i;
+                    // Priorities collide across threads.
+                    pq.push(item % 100, item).unwrap();
+                    if i % 3 == 0 {
+                        mine.extend(pq.pop_min().map(|(_, item)| item));
+                    }
+                }
+                popped.lock().unwrap().extend(mine);
+            });
+        }
+    });
+
+    let mut popped = popped.into_inner().unwrap();
+    let popped_during = popped.len();
+    let mut last = 0;
+    while let Some((p, item)) = pq.pop_min() {
+        assert!(p >= last, "{} popped after {}", p, last);
+        last = p;
+        popped.push(item);
+    }
+    assert!(pq.is_empty());
+
+    let unique: BTreeSet<u64> = popped.iter().copied().collect();
+    assert_eq!(unique.len(), popped.len(), "an item was popped twice");
+    assert!(unique.iter().copied().eq(0..THREADS * ITEMS_PER_THREAD));
+    assert!(popped_during > 0);
+}
+
+#[test]
+fn pops_are_ordered_without_concurrent_pushes() {
+    let pq = PriorityQueue::new(usize::MAX);
+    for i in 0..THREADS * ITEMS_PER_THREAD {
+        // Pushed out of order.
+        let p = i * 7919 % (THREADS * ITEMS_PER_THREAD);
+        pq.push(p, ()).unwrap();
+    }
+
+    let popped: Vec<Vec<u64>> = thread::scope(|s| {
+        let poppers: Vec<_> = (0..THREADS)
+            .map(|_| {
+                s.spawn(|| {
+                    let mut mine = vec![];
+                    while let Some((p, ())) = pq.pop_min() {
+                        mine.push(p);
+                    }
+                    mine
+                })
+            })
+            .collect();
+        poppers.into_iter().map(|p| p.join().unwrap()).collect()
+    });
+
+    for mine in &popped {
+        assert!(mine.windows(2).all(|w| w[0] < w[1]), "pops out of order");
+    }
+    let all: BTreeSet<u64> = popped.into_iter().flatten().collect();
+    assert!(all.into_iter().eq(0..THREADS * ITEMS_PER_THREAD));
+}
+
+#[test]
+fn capacity_holds_under_contention() {
+    const CAPACITY: usize = 64;
+    let pq = PriorityQueue::new(CAPACITY);
+
+    thread::scope(|s| {
+        for t in 0..THREADS {
+            let pq = &pq;
+            s.spawn(move || {
+                for i in 0..ITEMS_PER_THREAD {
+                    if pq.push(i, t).is_err() {
+                        assert_eq!(pq.len(), CAPACITY);
+                        pq.pop_min().unwrap();
+                    }
+                    assert!(pq.len() <= CAPACITY);
+                }
+            });
+        }
+    });
+
+    let mut left = 0;
+    while pq.pop_min().is_some() {
+        left += 1;
+    }
+    assert!(left <= CAPACITY);
+}
@@ -0,0 +1,70 @@
+//! The time source of the wrappers. `TokioClock`, tokio's clock, is the
+//! default, and `VirtualClock` is the one `DeterministicIo` moves forward
+//! itself, so delays take no wall-clock time at all.
+use std::future::Future;
+use std::pin::{pin, Pin};
+use std::task::Poll;
+
+use tokio::time::{Duration, Instant};
+
+/// A pending delay of a `Clock`.
+pub type Sleep = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+pub trait Clock: Clone + Unpin {
+    /// The time since the clock was created.
+    fn now(&self) -> Duration;
+
+    /// Completes once `now` has reached `deadline`.
+    fn sleep_until(&self, deadline: Duration) -> Sleep;
+
+    fn sleep(&self, dur: Duration) -> Sleep {
+        self.sleep_until(self.now() + dur)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TokioClock {
+    start: Instant,
+}
+
+impl TokioClock {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Default for TokioClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for TokioClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    fn sleep_until(&self, deadline: Duration) -> Sleep {
+        Box::pin(tokio::time::sleep_until(self.start + deadline))
+    }
+}
+
+/// Runs `fut` for at most `dur` of `clock`'s time, and returns None if it
+/// didn't complete by then.
+pub async fn timeout<C, F>(clock: &C, dur: Duration, fut: F) -> Option<F::Output>
+where
+    C: Clock,
+    F: Future,
+{
+    let mut sleep = clock.sleep(dur);
+    let mut fut = pin!(fut);
+    std::future::poll_fn(|cx| {
+        if let Poll::Ready(out) = fut.as_mut().poll(cx) {
+            return Poll::Ready(Some(out));
+        }
+        sleep.as_mut().poll(cx).map(|()| None)
+    })
+    .await
+}
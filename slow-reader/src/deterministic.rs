@@ -0,0 +1,376 @@
+//! A minimal executor for fully deterministic IO tests.
+//!
+//! `DeterministicIo` runs futures on the current thread, on a `VirtualClock`
+//! of its own. Whenever no task can make progress, it moves the clock
+//! straight to the next deadline instead of waiting for it, so a test full of
+//! delays and timeouts takes no wall-clock time. Which of the ready tasks
+//! runs next is picked by a generator seeded by the test, so a seed always
+//! reproduces the same interleaving of reads and writes.
+//!
+//! ```ignore
+//! let mut io = DeterministicIo::new(7);
+//! let clock = io.clock();
+//! let n = io.block_on(async {
+//!     let mut reader = SlowReader::with_clock(&data[..], strategy, clock.clone());
+//!     reader.read(&mut buf).await
+//! });
+//! assert_eq!(clock.now(), strategy.initial);
+//! ```
+//!
+//! Only the wrappers built `with_clock(io.clock())` and the `clock::timeout`s
+//! on that clock use the virtual time. Tokio's timers need tokio's runtime,
+//! and don't work here.
+use std::cmp::{Ordering, Reverse};
+use std::collections::binary_heap::PeekMut;
+use std::collections::{BinaryHeap, HashMap};
+use std::fmt;
+use std::future::Future;
+use std::pin::{pin, Pin};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+
+use tokio::time::Duration;
+
+use crate::clock::{Clock, Sleep};
+
+type Task = Pin<Box<dyn Future<Output = ()>>>;
+
+// The id of the future passed to block_on, the others are indices of tasks.
+const MAIN: usize = usize::MAX;
+
+pub struct DeterministicIo {
+    clock: VirtualClock,
+    rng: u64,
+    // None once the task completed.
+    tasks: Vec<Option<Task>>,
+    // The ids of the woken tasks, without duplicates.
+    ready: Arc<Mutex<Vec<usize>>>,
+}
+
+/// A clock that only moves when its `DeterministicIo` moves it. Clones share
+/// the time.
+#[derive(Clone, Default)]
+pub struct VirtualClock {
+    state: Arc<Mutex<ClockState>>,
+}
+
+#[derive(Default)]
+struct ClockState {
+    now: Duration,
+    timers: BinaryHeap<Reverse<Timer>>,
+    // The wakers of the pending timers by seq. A timer whose seq isn't in
+    // here belongs to a sleep that was dropped, and is skipped.
+    wakers: HashMap<u64, Waker>,
+    // Orders the timers of the same deadline by when they were set.
+    seq: u64,
+}
+
+struct Timer {
+    deadline: Duration,
+    seq: u64,
+}
+
+// The Sleep of a VirtualClock. It sets one timer however often it's polled,
+// and removes it when it's dropped.
+struct VirtualSleep {
+    clock: VirtualClock,
+    deadline: Duration,
+    // The seq of its timer, once it has set one.
+    timer: Option<u64>,
+}
+
+struct TaskWaker {
+    id: usize,
+    ready: Arc<Mutex<Vec<usize>>>,
+}
+
+impl DeterministicIo {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            clock: VirtualClock::default(),
+            // xorshift gets stuck at 0.
+            rng: seed.max(1),
+            tasks: Vec::new(),
+            ready: Arc::default(),
+        }
+    }
+
+    pub fn clock(&self) -> VirtualClock {
+        self.clock.clone()
+    }
+
+    /// Runs `fut` alongside the future of the next `block_on`, and of every
+    /// one after it until `fut` completes.
+    pub fn spawn(&mut self, fut: impl Future<Output = ()> + 'static) {
+        let id = self.tasks.len();
+        self.tasks.push(Some(Box::pin(fut)));
+        self.wake(id);
+    }
+
+    /// Runs `fut` and the spawned tasks until `fut` completes.
+    ///
+    /// # Panics
+    ///
+    /// If `fut` can't complete: no task is ready and there's no timer left
+    /// to wake one up.
+    pub fn block_on<F: Future>(&mut self, fut: F) -> F::Output {
+        let mut fut = pin!(fut);
+        self.wake(MAIN);
+
+        loop {
+            let Some(id) = self.next_ready() else {
+                assert!(
+                    self.clock.advance(),
+                    "deadlock: no task is ready and no timer is pending"
+                );
+                continue;
+            };
+            let waker = Waker::from(Arc::new(TaskWaker {
+                id,
+                ready: Arc::clone(&self.ready),
+            }));
+            let mut cx = Context::from_waker(&waker);
+
+            if id == MAIN {
+                if let Poll::Ready(out) = fut.as_mut().poll(&mut cx) {
+                    return out;
+                }
+            } else if let Some(task) = &mut self.tasks[id] {
+                if task.as_mut().poll(&mut cx).is_ready() {
+                    self.tasks[id] = None;
+                }
+            }
+        }
+    }
+
+    fn wake(&self, id: usize) {
+        wake(&self.ready, id);
+    }
+
+    // Picks one of the ready tasks at random.
+    fn next_ready(&mut self) -> Option<usize> {
+        let len = self.ready.lock().unwrap().len();
+        if len == 0 {
+            return None;
+        }
+        let idx = (self.next_random() % len as u64) as usize;
+        Some(self.ready.lock().unwrap().remove(idx))
+    }
+
+    // xorshift64, enough to shuffle the tasks.
+    fn next_random(&mut self) -> u64 {
+        let mut x = self.rng;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.rng = x;
+        x
+    }
+}
+
+impl fmt::Debug for DeterministicIo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DeterministicIo")
+            .field("clock", &self.clock)
+            .field("tasks", &self.tasks.iter().flatten().count())
+            .finish()
+    }
+}
+
+fn wake(ready: &Mutex<Vec<usize>>, id: usize) {
+    let mut ready = ready.lock().unwrap();
+    if !ready.contains(&id) {
+        ready.push(id);
+    }
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        wake(&self.ready, self.id);
+    }
+}
+
+impl VirtualClock {
+    // Moves the time to the earliest deadline, and wakes up every timer that
+    // is due then. Returns false if there's no timer.
+    fn advance(&self) -> bool {
+        let mut guard = self.state.lock().unwrap();
+        let state = &mut *guard;
+        let mut due = vec![];
+        while let Some(timer) = state.timers.peek_mut() {
+            if !due.is_empty() && timer.0.deadline > state.now {
+                break;
+            }
+            let Reverse(timer) = PeekMut::pop(timer);
+            if let Some(waker) = state.wakers.remove(&timer.seq) {
+                state.now = state.now.max(timer.deadline);
+                due.push(waker);
+            }
+        }
+        if due.is_empty() {
+            return false;
+        }
+        // The wakers don't run with the state locked.
+        drop(guard);
+        due.into_iter().for_each(Waker::wake);
+        true
+    }
+}
+
+impl Future for VirtualSleep {
+    type Output = ();
+
+    // Ready once the deadline has passed, otherwise wakes up cx then.
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.clock.state.lock().unwrap();
+        if state.now >= self.deadline {
+            return Poll::Ready(());
+        }
+        // Polled again before the deadline, only the waker may be new.
+        if let Some(waker) = self.timer.and_then(|seq| state.wakers.get_mut(&seq)) {
+            waker.clone_from(cx.waker());
+            return Poll::Pending;
+        }
+
+        state.seq += 1;
+        let seq = state.seq;
+        state.timers.push(Reverse(Timer {
+            deadline: self.deadline,
+            seq,
+        }));
+        state.wakers.insert(seq, cx.waker().clone());
+        drop(state);
+        self.timer = Some(seq);
+        Poll::Pending
+    }
+}
+
+impl Drop for VirtualSleep {
+    fn drop(&mut self) {
+        if let Some(seq) = self.timer {
+            // Its entry in the heap is skipped once it's due.
+            self.clock.state.lock().unwrap().wakers.remove(&seq);
+        }
+    }
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> Duration {
+        self.state.lock().unwrap().now
+    }
+
+    fn sleep_until(&self, deadline: Duration) -> Sleep {
+        Box::pin(VirtualSleep {
+            clock: self.clone(),
+            deadline,
+            timer: None,
+        })
+    }
+}
+
+impl fmt::Debug for VirtualClock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.state.lock().unwrap();
+        f.debug_struct("VirtualClock")
+            .field("now", &state.now)
+            .field("timers", &state.wakers.len())
+            .finish()
+    }
+}
+
+impl PartialEq for Timer {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Timer {}
+
+impl PartialOrd for Timer {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Timer {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.deadline, self.seq).cmp(&(other.deadline, other.seq))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn sleeps_advance_the_clock_without_waiting() {
+        let mut io = DeterministicIo::new(1);
+        let clock = io.clock();
+        let start = std::time::Instant::now();
+        io.block_on(clock.sleep(Duration::from_secs(3600)));
+        assert_eq!(clock.now(), Duration::from_secs(3600));
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn timers_fire_in_deadline_order() {
+        let mut io = DeterministicIo::new(1);
+        let clock = io.clock();
+        let order = Rc::new(RefCell::new(Vec::new()));
+        for ms in [30, 10, 20] {
+            let (clock, order) = (clock.clone(), Rc::clone(&order));
+            io.spawn(async move {
+                clock.sleep(Duration::from_millis(ms)).await;
+                order.borrow_mut().push((ms, clock.now()));
+            });
+        }
+        io.block_on(clock.sleep(Duration::from_millis(40)));
+
+        let ms = Duration::from_millis;
+        assert_eq!(*order.borrow(), [(10, ms(10)), (20, ms(20)), (30, ms(30))]);
+    }
+
+    #[test]
+    fn completed_timeouts_leave_no_timer() {
+        let mut io = DeterministicIo::new(1);
+        let clock = io.clock();
+        let secs = Duration::from_secs;
+        io.block_on(async {
+            let slept = clock::timeout(&clock, secs(10), clock.sleep(secs(1))).await;
+            assert_eq!(slept, Some(()));
+            clock.sleep(secs(2)).await;
+        });
+
+        assert_eq!(clock.now(), secs(3));
+        assert_eq!(clock.state.lock().unwrap().wakers.len(), 0);
+        // The timeout's timer doesn't move the clock to its deadline.
+        let stuck = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            io.block_on(std::future::pending::<()>())
+        }));
+        assert!(stuck.is_err());
+        assert_eq!(clock.now(), secs(3));
+    }
+
+    #[test]
+    fn repolled_sleeps_set_one_timer() {
+        let mut io = DeterministicIo::new(1);
+        let clock = io.clock();
+        let mut sleep = clock.sleep(Duration::from_secs(1));
+        io.block_on(async {
+            for _ in 0..10 {
+                let polled = std::future::poll_fn(|cx| Poll::Ready(sleep.as_mut().poll(cx))).await;
+                assert!(polled.is_pending());
+            }
+        });
+        assert_eq!(clock.state.lock().unwrap().timers.len(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "deadlock")]
+    fn futures_that_cant_complete_panic() {
+        DeterministicIo::new(1).block_on(std::future::pending::<()>());
+    }
+}
@@ -0,0 +1,488 @@
+//! Simulated IO conditions stacked around any AsyncRead/AsyncWrite.
+//!
+//! ```ignore
+//! let io = IoSim::builder(stream)
+//!     .timeout(Duration::from_secs(1))
+//!     .delay(DelayStrategy::default())
+//!     .chunks(64)
+//!     .build();
+//! ```
+//!
+//! Layers are applied in the order they're declared, the first one being the
+//! closest to the caller and the last one the closest to the inner stream.
+//! Every read or write passes through them from the outside in, and its
+//! result travels back from the inside out. So above, the delays count
+//! towards the timeout, while declaring the timeout last would only time the
+//! inner stream.
+//!
+//! Reads and writes go through separate copies of each layer's state, e.g.
+//! the initial delay is paid once before the first read and once before the
+//! first write. Flushes and shutdowns go straight to the inner stream.
+use std::future::Future;
+use std::io::{self, ErrorKind};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::{self, Duration, Instant, Sleep};
+
+use crate::io_trace::{Dir, IoEvent, IoRecorder, IoTrace, Outcome};
+use crate::DelayStrategy;
+
+pub struct IoSim<T> {
+    inner: T,
+    layers: Vec<Layer>,
+}
+
+pub struct IoSimBuilder<T> {
+    inner: T,
+    layers: Vec<Layer>,
+}
+
+/// Errors injected by the `faults` layer, by the index of the read or write
+/// operation that reaches the layer (counting from 0).
+#[derive(Debug, Clone, Default)]
+pub struct Faults {
+    reads: Vec<(u64, ErrorKind)>,
+    writes: Vec<(u64, ErrorKind)>,
+}
+
+/// Counts what passes through the `metrics` layer. Clones share the
+/// counters, so a clone kept by the test can be read after the IoSim is
+/// handed over.
+#[derive(Debug, Clone, Default)]
+pub struct IoMetrics {
+    counters: Arc<Counters>,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    read_ops: AtomicU64,
+    bytes_read: AtomicU64,
+    write_ops: AtomicU64,
+    bytes_written: AtomicU64,
+    pending: AtomicU64,
+    errors: AtomicU64,
+}
+
+/// A snapshot of `IoMetrics`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IoCounts {
+    /// Reads that completed successfully, including the ones at EOF.
+    pub read_ops: u64,
+    pub bytes_read: u64,
+    /// Writes that completed successfully.
+    pub write_ops: u64,
+    pub bytes_written: u64,
+    /// Times a read or write wasn't ready.
+    pub pending: u64,
+    /// Reads and writes that failed.
+    pub errors: u64,
+}
+
+struct Layer {
+    kind: Kind,
+    read: State,
+    write: State,
+}
+
+enum Kind {
+    Delay(DelayStrategy),
+    Throttle { bytes_per_sec: u64 },
+    Chunks(usize),
+    Faults(Faults),
+    Timeout(Duration),
+    Metrics(IoMetrics),
+    Record(IoRecorder),
+    Replay(Replay),
+}
+
+struct Replay {
+    // Shared by reads and writes, which are timed against the same clock.
+    start: Option<Instant>,
+    reads: Vec<IoEvent>,
+    writes: Vec<IoEvent>,
+}
+
+// Every kind of layer uses some of these, separately for reads and writes.
+#[derive(Default)]
+struct State {
+    // Boxed so layers are Unpin and only the inner stream needs projecting.
+    sleep: Option<Pin<Box<Sleep>>>,
+    started: bool,
+    // True while an operation that returned Pending is polled again, so it
+    // isn't mistaken for a new one.
+    in_op: bool,
+    ops: u64,
+}
+
+impl<T> IoSim<T> {
+    pub fn builder(inner: T) -> IoSimBuilder<T> {
+        IoSimBuilder {
+            inner,
+            layers: vec![],
+        }
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    // SAFETY: the inner stream is never moved out of a pinned IoSim, and the
+    // layers are Unpin so handing out a &mut to them is fine.
+    fn project(self: Pin<&mut Self>) -> (Pin<&mut T>, &mut [Layer]) {
+        unsafe {
+            let this = self.get_unchecked_mut();
+            (Pin::new_unchecked(&mut this.inner), &mut this.layers)
+        }
+    }
+}
+
+impl<T> IoSimBuilder<T> {
+    /// Stalls before the first operation and whenever the layers below
+    /// aren't ready, like `SlowReader`.
+    pub fn delay(self, strategy: DelayStrategy) -> Self {
+        self.layer(Kind::Delay(strategy))
+    }
+
+    /// Limits the rate to `bytes_per_sec`, by stalling the next operation for
+    /// as long as the bytes of the previous one should have taken.
+    ///
+    /// # Panics
+    ///
+    /// If `bytes_per_sec` is 0.
+    pub fn throttle(self, bytes_per_sec: u64) -> Self {
+        assert!(bytes_per_sec > 0, "throttle rate must be positive");
+        self.layer(Kind::Throttle { bytes_per_sec })
+    }
+
+    /// Passes at most `max` bytes per operation to the layers below.
+    ///
+    /// # Panics
+    ///
+    /// If `max` is 0.
+    pub fn chunks(self, max: usize) -> Self {
+        assert!(max > 0, "chunk size must be positive");
+        self.layer(Kind::Chunks(max))
+    }
+
+    pub fn faults(self, faults: Faults) -> Self {
+        self.layer(Kind::Faults(faults))
+    }
+
+    /// Fails an operation with `TimedOut` if the layers below haven't
+    /// completed it within `dur`.
+    pub fn timeout(self, dur: Duration) -> Self {
+        self.layer(Kind::Timeout(dur))
+    }
+
+    pub fn metrics(self, metrics: &IoMetrics) -> Self {
+        self.layer(Kind::Metrics(metrics.clone()))
+    }
+
+    /// Records every operation that completes in the layers below, see
+    /// `io_trace`.
+    pub fn record(self, recorder: &IoRecorder) -> Self {
+        self.layer(Kind::Record(recorder.clone()))
+    }
+
+    /// Makes the layers below repeat `trace`: every operation is held back
+    /// until the time of its recorded counterpart, passed at most as many
+    /// bytes, or failed with its error. Operations past the end of the trace
+    /// pass through unchanged.
+    pub fn replay(self, trace: IoTrace) -> Self {
+        self.layer(Kind::Replay(Replay {
+            start: None,
+            reads: trace.split(Dir::Read),
+            writes: trace.split(Dir::Write),
+        }))
+    }
+
+    pub fn build(self) -> IoSim<T> {
+        IoSim {
+            inner: self.inner,
+            layers: self.layers,
+        }
+    }
+
+    fn layer(mut self, kind: Kind) -> Self {
+        self.layers.push(Layer {
+            kind,
+            read: State::default(),
+            write: State::default(),
+        });
+        self
+    }
+}
+
+impl Faults {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fails the read with index `op`.
+    pub fn read(mut self, op: u64, kind: ErrorKind) -> Self {
+        self.reads.push((op, kind));
+        self
+    }
+
+    /// Fails the write with index `op`.
+    pub fn write(mut self, op: u64, kind: ErrorKind) -> Self {
+        self.writes.push((op, kind));
+        self
+    }
+
+    fn get(&self, dir: Dir, op: u64) -> Option<ErrorKind> {
+        let faults = match dir {
+            Dir::Read => &self.reads,
+            Dir::Write => &self.writes,
+        };
+        faults.iter().find(|(i, _)| *i == op).map(|(_, kind)| *kind)
+    }
+}
+
+impl IoMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn snapshot(&self) -> IoCounts {
+        let c = &self.counters;
+        IoCounts {
+            read_ops: c.read_ops.load(Ordering::Relaxed),
+            bytes_read: c.bytes_read.load(Ordering::Relaxed),
+            write_ops: c.write_ops.load(Ordering::Relaxed),
+            bytes_written: c.bytes_written.load(Ordering::Relaxed),
+            pending: c.pending.load(Ordering::Relaxed),
+            errors: c.errors.load(Ordering::Relaxed),
+        }
+    }
+
+    fn record(&self, dir: Dir, res: &Poll<io::Result<usize>>) {
+        let c = &self.counters;
+        let (ops, bytes) = match dir {
+            Dir::Read => (&c.read_ops, &c.bytes_read),
+            Dir::Write => (&c.write_ops, &c.bytes_written),
+        };
+        match res {
+            Poll::Ready(Ok(n)) => {
+                ops.fetch_add(1, Ordering::Relaxed);
+                bytes.fetch_add(*n as u64, Ordering::Relaxed);
+            }
+            Poll::Ready(Err(_)) => {
+                c.errors.fetch_add(1, Ordering::Relaxed);
+            }
+            Poll::Pending => {
+                c.pending.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+// An operation as seen by a layer: `next` runs it through the layers below
+// with at most the given number of bytes, and returns how many were read or
+// written.
+type Next<'a> = dyn FnMut(&mut Context<'_>, usize) -> Poll<io::Result<usize>> + 'a;
+
+impl Layer {
+    fn poll(
+        &mut self,
+        dir: Dir,
+        cx: &mut Context<'_>,
+        max: usize,
+        next: &mut Next<'_>,
+    ) -> Poll<io::Result<usize>> {
+        let state = match dir {
+            Dir::Read => &mut self.read,
+            Dir::Write => &mut self.write,
+        };
+
+        match &mut self.kind {
+            Kind::Delay(strategy) => {
+                if !state.started {
+                    state.started = true;
+                    state.sleep = Some(Box::pin(time::sleep(strategy.initial)));
+                }
+                if state.poll_sleep(cx).is_pending() {
+                    return Poll::Pending;
+                }
+                let res = next(cx, max);
+                if res.is_pending() {
+                    // Not polled until the layers below wake us up.
+                    state.sleep = Some(Box::pin(time::sleep(strategy.on_pending)));
+                }
+                res
+            }
+            Kind::Throttle { bytes_per_sec } => {
+                if state.poll_sleep(cx).is_pending() {
+                    return Poll::Pending;
+                }
+                let res = next(cx, max);
+                if let Poll::Ready(Ok(n)) = res {
+                    if n > 0 {
+                        let secs = n as f64 / *bytes_per_sec as f64;
+                        let dur = Duration::from_secs_f64(secs);
+                        state.sleep = Some(Box::pin(time::sleep(dur)));
+                    }
+                }
+                res
+            }
+            Kind::Chunks(size) => next(cx, max.min(*size)),
+            Kind::Faults(faults) => {
+                if !state.in_op {
+                    let op = state.ops;
+                    state.ops += 1;
+                    if let Some(kind) = faults.get(dir, op) {
+                        return Poll::Ready(Err(io::Error::new(kind, "injected fault")));
+                    }
+                }
+                let res = next(cx, max);
+                state.in_op = res.is_pending();
+                res
+            }
+            Kind::Timeout(dur) => {
+                if !state.in_op {
+                    state.sleep = Some(Box::pin(time::sleep(*dur)));
+                }
+                let res = next(cx, max);
+                if res.is_ready() {
+                    state.in_op = false;
+                    state.sleep = None;
+                    return res;
+                }
+                if state.poll_sleep(cx).is_ready() {
+                    state.in_op = false;
+                    return Poll::Ready(Err(io::Error::new(
+                        ErrorKind::TimedOut,
+                        "simulated timeout",
+                    )));
+                }
+                state.in_op = true;
+                Poll::Pending
+            }
+            Kind::Metrics(metrics) => {
+                let res = next(cx, max);
+                metrics.record(dir, &res);
+                res
+            }
+            Kind::Record(recorder) => {
+                recorder.start();
+                let res = next(cx, max);
+                if let Poll::Ready(res) = &res {
+                    recorder.record(dir, res);
+                }
+                res
+            }
+            Kind::Replay(replay) => {
+                let start = *replay.start.get_or_insert_with(Instant::now);
+                let events = match dir {
+                    Dir::Read => &replay.reads,
+                    Dir::Write => &replay.writes,
+                };
+                if !state.in_op {
+                    state.in_op = true;
+                    state.ops += 1;
+                    if let Some(event) = events.get(state.ops as usize - 1) {
+                        state.sleep = Some(Box::pin(time::sleep_until(start + event.at)));
+                    }
+                }
+                if state.poll_sleep(cx).is_pending() {
+                    return Poll::Pending;
+                }
+
+                let res = match events.get(state.ops as usize - 1) {
+                    None => next(cx, max),
+                    Some(event) => match event.outcome {
+                        Outcome::Bytes(n) => next(cx, max.min(n)),
+                        Outcome::Error(kind) => {
+                            Poll::Ready(Err(io::Error::new(kind, "replayed fault")))
+                        }
+                    },
+                };
+                state.in_op = res.is_pending();
+                res
+            }
+        }
+    }
+}
+
+impl State {
+    // Ready if there's no sleep, or once it's over.
+    fn poll_sleep(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if let Some(sleep) = &mut self.sleep {
+            if sleep.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+            self.sleep = None;
+        }
+        Poll::Ready(())
+    }
+}
+
+fn poll_layers(
+    layers: &mut [Layer],
+    dir: Dir,
+    cx: &mut Context<'_>,
+    max: usize,
+    op: &mut Next<'_>,
+) -> Poll<io::Result<usize>> {
+    match layers.split_first_mut() {
+        None => op(cx, max),
+        Some((layer, rest)) => layer.poll(dir, cx, max, &mut |cx, max| {
+            poll_layers(rest, dir, cx, max, op)
+        }),
+    }
+}
+
+impl<T: AsyncRead> AsyncRead for IoSim<T> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let (mut inner, layers) = self.project();
+        let remaining = buf.remaining();
+
+        // The layers below only see the first `max` bytes of buf.
+        let mut read = |cx: &mut Context<'_>, max: usize| {
+            let mut limited = ReadBuf::new(buf.initialize_unfilled_to(max));
+            match inner.as_mut().poll_read(cx, &mut limited) {
+                Poll::Ready(Ok(())) => {
+                    let n = limited.filled().len();
+                    buf.advance(n);
+                    Poll::Ready(Ok(n))
+                }
+                Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+                Poll::Pending => Poll::Pending,
+            }
+        };
+        poll_layers(layers, Dir::Read, cx, remaining, &mut read).map_ok(|_| ())
+    }
+}
+
+impl<T: AsyncWrite> AsyncWrite for IoSim<T> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        data: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let (mut inner, layers) = self.project();
+        let mut write =
+            |cx: &mut Context<'_>, max: usize| inner.as_mut().poll_write(cx, &data[..max]);
+        poll_layers(layers, Dir::Write, cx, data.len(), &mut write)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().0.poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().0.poll_shutdown(cx)
+    }
+}
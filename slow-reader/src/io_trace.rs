@@ -0,0 +1,289 @@
+//! Recording the reads and writes of a real stream, and replaying them
+//! against another one.
+//!
+//! ```ignore
+//! let recorder = IoRecorder::new();
+//! let io = IoSim::builder(stream).record(&recorder).build();
+//! // ... run the workload ...
+//! recorder.trace().save("capture.trace")?;
+//!
+//! let trace = IoTrace::load("capture.trace")?;
+//! let io = IoSim::builder(fresh_stream).replay(trace).build();
+//! ```
+//!
+//! A trace holds every completed operation in the order it completed: its
+//! direction, how many bytes it moved or the kind of error it failed with,
+//! and when it completed relative to the start of the first operation.
+//! Replaying it holds every operation back until it's as late as its
+//! recorded counterpart, passes it at most as many bytes, and fails it with
+//! the recorded error instead of running it. Faults injected by layers below
+//! the recorder are part of the trace, so they're replayed as well.
+//!
+//! Traces are saved as text, one operation per line: the time in
+//! microseconds, `read` or `write`, and the byte count or `err` followed by
+//! the error kind, e.g. `1500 write err BrokenPipe`. Lines starting with `#`
+//! are comments.
+use std::fmt;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use tokio::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dir {
+    Read,
+    Write,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// Bytes read or written, 0 being EOF for reads.
+    Bytes(usize),
+    Error(ErrorKind),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoEvent {
+    /// Since the start of the first operation.
+    pub at: Duration,
+    pub dir: Dir,
+    pub outcome: Outcome,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IoTrace {
+    events: Vec<IoEvent>,
+}
+
+/// Collects the trace of the `record` layer. Clones share it, like the ones
+/// of `IoMetrics`.
+#[derive(Debug, Clone, Default)]
+pub struct IoRecorder {
+    recording: Arc<Mutex<Recording>>,
+}
+
+#[derive(Debug, Default)]
+struct Recording {
+    start: Option<Instant>,
+    trace: IoTrace,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseTraceError {
+    /// Starts at 1.
+    pub line: usize,
+    pub reason: String,
+}
+
+// The kinds that are written by name. Others are written by their Debug
+// name too, but read back as Other.
+const ERROR_KINDS: [ErrorKind; 19] = [
+    ErrorKind::NotFound,
+    ErrorKind::PermissionDenied,
+    ErrorKind::ConnectionRefused,
+    ErrorKind::ConnectionReset,
+    ErrorKind::ConnectionAborted,
+    ErrorKind::NotConnected,
+    ErrorKind::AddrInUse,
+    ErrorKind::AddrNotAvailable,
+    ErrorKind::BrokenPipe,
+    ErrorKind::AlreadyExists,
+    ErrorKind::WouldBlock,
+    ErrorKind::InvalidInput,
+    ErrorKind::InvalidData,
+    ErrorKind::TimedOut,
+    ErrorKind::WriteZero,
+    ErrorKind::Interrupted,
+    ErrorKind::Unsupported,
+    ErrorKind::UnexpectedEof,
+    ErrorKind::OutOfMemory,
+];
+
+impl IoTrace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn events(&self) -> &[IoEvent] {
+        &self.events
+    }
+
+    pub fn push(&mut self, event: IoEvent) {
+        self.events.push(event);
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        fs::read_to_string(path)?
+            .parse()
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+    }
+
+    // The events in `dir`, in order.
+    pub(crate) fn split(&self, dir: Dir) -> Vec<IoEvent> {
+        self.events
+            .iter()
+            .filter(|e| e.dir == dir)
+            .copied()
+            .collect()
+    }
+}
+
+impl IoRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// What was recorded so far.
+    pub fn trace(&self) -> IoTrace {
+        self.recording.lock().unwrap().trace.clone()
+    }
+
+    // Called whenever an operation is polled, the first call starts the clock.
+    pub(crate) fn start(&self) {
+        self.recording
+            .lock()
+            .unwrap()
+            .start
+            .get_or_insert_with(Instant::now);
+    }
+
+    pub(crate) fn record(&self, dir: Dir, res: &io::Result<usize>) {
+        let mut recording = self.recording.lock().unwrap();
+        let start = *recording.start.get_or_insert_with(Instant::now);
+        let outcome = match res {
+            Ok(n) => Outcome::Bytes(*n),
+            Err(e) => Outcome::Error(e.kind()),
+        };
+        recording.trace.push(IoEvent {
+            at: start.elapsed(),
+            dir,
+            outcome,
+        });
+    }
+}
+
+impl fmt::Display for IoTrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for event in &self.events {
+            let dir = match event.dir {
+                Dir::Read => "read",
+                Dir::Write => "write",
+            };
+            write!(f, "{} {} ", event.at.as_micros(), dir)?;
+            match event.outcome {
+                Outcome::Bytes(n) => writeln!(f, "{}", n)?,
+                Outcome::Error(kind) => writeln!(f, "err {:?}", kind)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for IoTrace {
+    type Err = ParseTraceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut trace = IoTrace::new();
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let event = parse_event(line).map_err(|reason| ParseTraceError {
+                line: i + 1,
+                reason,
+            })?;
+            trace.push(event);
+        }
+        Ok(trace)
+    }
+}
+
+fn parse_event(line: &str) -> Result<IoEvent, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let (at, dir, outcome) = match words[..] {
+        [at, dir, "err", kind] => (at, dir, Outcome::Error(parse_error_kind(kind))),
+        [at, dir, n] => {
+            let n = n
+                .parse()
+                .map_err(|_| format!("invalid byte count: {}", n))?;
+            (at, dir, Outcome::Bytes(n))
+        }
+        _ => return Err("expected `TIME DIR BYTES` or `TIME DIR err KIND`".to_owned()),
+    };
+
+    let at = at.parse().map_err(|_| format!("invalid time: {}", at))?;
+    let dir = match dir {
+        "read" => Dir::Read,
+        "write" => Dir::Write,
+        _ => return Err(format!("invalid direction: {}", dir)),
+    };
+    Ok(IoEvent {
+        at: Duration::from_micros(at),
+        dir,
+        outcome,
+    })
+}
+
+fn parse_error_kind(name: &str) -> ErrorKind {
+    ERROR_KINDS
+        .into_iter()
+        .find(|kind| format!("{:?}", kind) == name)
+        .unwrap_or(ErrorKind::Other)
+}
+
+impl fmt::Display for ParseTraceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.reason)
+    }
+}
+
+impl std::error::Error for ParseTraceError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_format_round_trip() {
+        let mut trace = IoTrace::new();
+        trace.push(IoEvent {
+            at: Duration::from_micros(1500),
+            dir: Dir::Write,
+            outcome: Outcome::Error(ErrorKind::BrokenPipe),
+        });
+        trace.push(IoEvent {
+            at: Duration::from_millis(20),
+            dir: Dir::Read,
+            outcome: Outcome::Bytes(64),
+        });
+
+        let text = trace.to_string();
+        assert_eq!(text, "1500 write err BrokenPipe\n20000 read 64\n");
+        assert_eq!(text.parse(), Ok(trace));
+    }
+
+    #[test]
+    fn comments_and_unknown_error_kinds() {
+        let trace: IoTrace = "# captured by hand\n\n0 read err SomethingElse\n"
+            .parse()
+            .unwrap();
+        assert_eq!(trace.events()[0].outcome, Outcome::Error(ErrorKind::Other));
+    }
+
+    #[test]
+    fn parse_errors_have_line_numbers() {
+        let err = "0 read 1\n5 sideways 1\n".parse::<IoTrace>().unwrap_err();
+        assert_eq!(err.to_string(), "line 2: invalid direction: sideways");
+
+        let err = "0 read many".parse::<IoTrace>().unwrap_err();
+        assert_eq!(err.to_string(), "line 1: invalid byte count: many");
+    }
+}
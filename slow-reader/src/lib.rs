@@ -0,0 +1,16 @@
+pub mod clock;
+pub use clock::{Clock, TokioClock};
+
+mod deterministic;
+pub use deterministic::{DeterministicIo, VirtualClock};
+
+mod io_sim;
+pub use io_sim::{Faults, IoCounts, IoMetrics, IoSim, IoSimBuilder};
+
+pub mod io_trace;
+pub use io_trace::{IoRecorder, IoTrace};
+
+mod slow_reader;
+pub use slow_reader::{DelayStrategy, SlowReader, SlowWriter};
+
+pub mod test_support;
@@ -4,7 +4,6 @@ use std::time::Instant;
 use tokio::fs::File;
 use tokio::io::AsyncReadExt;
 
-mod slow_reader;
 use slow_reader::SlowReader;
 
 #[tokio::main]
@@ -1,49 +1,158 @@
-use std::future::{Future, Pending};
 use std::io::Result;
 use std::pin::Pin;
 use std::task::{self, Context, Poll};
-use tokio::io::{AsyncRead, ReadBuf};
-use tokio::time::{self, Duration, Instant, Sleep};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::Duration;
 
-pub struct SlowReader<R> {
-    sleep: Sleep,
+use crate::clock::{Clock, Sleep, TokioClock};
+
+/// Decides how long a `SlowReader` stalls: once before the first read, and
+/// again every time the inner reader isn't ready.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DelayStrategy {
+    pub initial: Duration,
+    pub on_pending: Duration,
+}
+
+impl Default for DelayStrategy {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_millis(200),
+            on_pending: Duration::from_millis(25),
+        }
+    }
+}
+
+impl DelayStrategy {
+    /// The total delay added to a workload during which the inner reader
+    /// returned `Pending` `pending_reads` times.
+    pub fn predict(&self, pending_reads: u32) -> Duration {
+        self.initial + self.on_pending * pending_reads
+    }
+}
+
+pub struct SlowReader<R, C = TokioClock> {
+    delay: Delay<C>,
     reader: R,
 }
 
+/// The `SlowReader` of writes: stalls before the first write, and again
+/// every time the inner writer isn't ready. Flushes and shutdowns go straight
+/// to the inner writer.
+pub struct SlowWriter<W, C = TokioClock> {
+    delay: Delay<C>,
+    writer: W,
+}
+
+// The stalls of both wrappers, on the time of `clock`.
+struct Delay<C> {
+    sleep: Sleep,
+    strategy: DelayStrategy,
+    clock: C,
+}
+
 impl<R> SlowReader<R> {
     pub fn new(reader: R) -> Self {
+        Self::with_strategy(reader, DelayStrategy::default())
+    }
+
+    pub fn with_strategy(reader: R, strategy: DelayStrategy) -> Self {
+        Self::with_clock(reader, strategy, TokioClock::new())
+    }
+}
+
+impl<R, C: Clock> SlowReader<R, C> {
+    /// Stalls on the time of `clock`, e.g. the `VirtualClock` of a
+    /// `DeterministicIo`.
+    pub fn with_clock(reader: R, strategy: DelayStrategy, clock: C) -> Self {
         Self {
-            sleep: time::sleep(Duration::from_millis(200)),
+            delay: Delay::new(strategy, clock),
             reader,
         }
     }
 }
 
-impl<R> AsyncRead for SlowReader<R>
+impl<W> SlowWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self::with_strategy(writer, DelayStrategy::default())
+    }
+
+    pub fn with_strategy(writer: W, strategy: DelayStrategy) -> Self {
+        Self::with_clock(writer, strategy, TokioClock::new())
+    }
+}
+
+impl<W, C: Clock> SlowWriter<W, C> {
+    /// Stalls on the time of `clock`, like `SlowReader::with_clock`.
+    pub fn with_clock(writer: W, strategy: DelayStrategy, clock: C) -> Self {
+        Self {
+            delay: Delay::new(strategy, clock),
+            writer,
+        }
+    }
+}
+
+impl<C: Clock> Delay<C> {
+    fn new(strategy: DelayStrategy, clock: C) -> Self {
+        Self {
+            sleep: clock.sleep(strategy.initial),
+            strategy,
+            clock,
+        }
+    }
+
+    // Polls `op` once the current stall is over, and stalls again if it
+    // isn't ready.
+    fn poll<T>(
+        &mut self,
+        cx: &mut Context<'_>,
+        op: impl FnOnce(&mut Context<'_>) -> Poll<T>,
+    ) -> Poll<T> {
+        if self.sleep.as_mut().poll(cx).is_pending() {
+            return Poll::Pending;
+        }
+        let res = op(cx);
+        if res.is_pending() {
+            self.sleep = self.clock.sleep(self.strategy.on_pending);
+        }
+        res
+    }
+}
+
+impl<R, C> AsyncRead for SlowReader<R, C>
 where
     R: AsyncRead + Unpin,
+    C: Clock,
 {
     fn poll_read(
         self: Pin<&mut Self>,
         cx: &mut Context<'_>,
         buf: &mut ReadBuf<'_>,
     ) -> task::Poll<Result<()>> {
-        let (mut sleep, reader) = unsafe {
-            let this = self.get_unchecked_mut();
-            (Pin::new_unchecked(&mut this.sleep), &mut this.reader)
-        };
-
-        match sleep.as_mut().poll(cx) {
-            Poll::Pending => Poll::Pending,
-            Poll::Ready(_) => {
-                let reader = Pin::new(reader);
-                if let Poll::Ready(res) = reader.poll_read(cx, buf) {
-                    Poll::Ready(res)
-                } else {
-                    sleep.reset(Instant::now() + Duration::from_millis(25));
-                    Poll::Pending
-                }
-            }
-        }
+        let this = self.get_mut();
+        let reader = &mut this.reader;
+        this.delay
+            .poll(cx, |cx| Pin::new(reader).poll_read(cx, buf))
+    }
+}
+
+impl<W, C> AsyncWrite for SlowWriter<W, C>
+where
+    W: AsyncWrite + Unpin,
+    C: Clock,
+{
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, data: &[u8]) -> Poll<Result<usize>> {
+        let this = self.get_mut();
+        let writer = &mut this.writer;
+        this.delay
+            .poll(cx, |cx| Pin::new(writer).poll_write(cx, data))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Pin::new(&mut self.get_mut().writer).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Pin::new(&mut self.get_mut().writer).poll_shutdown(cx)
     }
 }
@@ -0,0 +1,60 @@
+//! Helpers for asserting the timing behavior of the wrappers in tests.
+use std::fmt::Debug;
+use std::future::Future;
+use std::ops::RangeBounds;
+
+use tokio::time::{Duration, Instant};
+
+/// Runs `workload` to completion and panics unless the elapsed time falls
+/// within `range`.
+///
+/// Time is measured with tokio's clock, so under a paused runtime
+/// (`#[tokio::test(start_paused = true)]`) it's the virtual time and the
+/// envelope can be as tight as the exact value `DelayStrategy::predict`
+/// returns.
+pub async fn expect_duration<F, R>(range: R, workload: F) -> F::Output
+where
+    F: Future,
+    R: RangeBounds<Duration> + Debug,
+{
+    let start = Instant::now();
+    let output = workload.await;
+    let elapsed = start.elapsed();
+
+    assert!(
+        range.contains(&elapsed),
+        "workload took {:?} which is outside of the expected envelope {:?}",
+        elapsed,
+        range
+    );
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::time;
+
+    #[tokio::test(start_paused = true)]
+    async fn within_envelope() {
+        let out = expect_duration(
+            Duration::from_millis(100)..=Duration::from_millis(100),
+            async {
+                time::sleep(Duration::from_millis(100)).await;
+                7
+            },
+        )
+        .await;
+        assert_eq!(out, 7);
+    }
+
+    #[tokio::test(start_paused = true)]
+    #[should_panic(expected = "outside of the expected envelope")]
+    async fn outside_envelope() {
+        expect_duration(
+            ..Duration::from_millis(50),
+            time::sleep(Duration::from_millis(100)),
+        )
+        .await;
+    }
+}
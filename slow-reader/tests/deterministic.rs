@@ -0,0 +1,112 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use slow_reader::clock::timeout;
+use slow_reader::{Clock, DelayStrategy, DeterministicIo, SlowReader, SlowWriter};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::time::Duration;
+
+const DELAY: DelayStrategy = DelayStrategy {
+    initial: Duration::from_millis(100),
+    on_pending: Duration::from_millis(10),
+};
+
+// What was read or written, how many bytes, and when.
+type Event = (&'static str, usize, Duration);
+
+// A writer and a reader talking through a pipe too small for all the data,
+// so the writer keeps stalling on the reader. Returns what the reader got,
+// the virtual time every read and write completed at, and the final time.
+fn pipe(seed: u64) -> (Vec<u8>, Vec<Event>, Duration) {
+    let mut io = DeterministicIo::new(seed);
+    let clock = io.clock();
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let (tx, rx) = tokio::io::duplex(8);
+
+    let (writer_clock, writer_log) = (clock.clone(), Rc::clone(&log));
+    io.spawn(async move {
+        let mut writer = SlowWriter::with_clock(tx, DELAY, writer_clock.clone());
+        let data: Vec<u8> = (0..64).collect();
+        for chunk in data.chunks(16) {
+            let n = writer.write(chunk).await.unwrap();
+            writer_log
+                .borrow_mut()
+                .push(("write", n, writer_clock.now()));
+            writer.write_all(&chunk[n..]).await.unwrap();
+        }
+    });
+
+    let received = io.block_on(async {
+        let mut reader = SlowReader::with_clock(rx, DELAY, clock.clone());
+        let mut received = Vec::new();
+        let mut buf = [0; 5];
+        loop {
+            let n = reader.read(&mut buf).await.unwrap();
+            log.borrow_mut().push(("read", n, clock.now()));
+            if n == 0 {
+                return received;
+            }
+            received.extend_from_slice(&buf[..n]);
+        }
+    });
+    let log = log.borrow().clone();
+    (received, log, clock.now())
+}
+
+#[test]
+fn delays_take_no_wall_clock_time() {
+    let strategy = DelayStrategy {
+        initial: Duration::from_secs(3600),
+        on_pending: Duration::from_secs(60),
+    };
+    let mut io = DeterministicIo::new(1);
+    let clock = io.clock();
+    let start = std::time::Instant::now();
+
+    let data = [7u8; 16];
+    let mut buf = [0; 16];
+    let n = io.block_on(async {
+        let mut reader = SlowReader::with_clock(&data[..], strategy, clock.clone());
+        reader.read(&mut buf).await.unwrap()
+    });
+
+    assert_eq!(n, 16);
+    assert_eq!(clock.now(), strategy.initial);
+    assert!(start.elapsed() < Duration::from_secs(1));
+}
+
+#[test]
+fn a_seed_reproduces_the_interleaving() {
+    for seed in [1, 7, 42] {
+        let (received, log, end) = pipe(seed);
+        assert_eq!(received, (0..64).collect::<Vec<u8>>(), "seed {}", seed);
+        assert_eq!(pipe(seed), (received, log, end), "seed {}", seed);
+    }
+}
+
+#[test]
+fn timeouts_and_retries_run_on_the_virtual_clock() {
+    let mut io = DeterministicIo::new(3);
+    let clock = io.clock();
+    let data = [1u8; 4];
+
+    let attempts = io.block_on(async {
+        let mut reader = SlowReader::with_clock(&data[..], DELAY, clock.clone());
+        let mut buf = [0; 4];
+        let mut attempts = vec![];
+        loop {
+            let res = timeout(&clock, Duration::from_millis(40), reader.read(&mut buf)).await;
+            attempts.push((res.is_some(), clock.now()));
+            if res.is_some() {
+                return attempts;
+            }
+        }
+    });
+
+    let ms = Duration::from_millis;
+    // The initial delay of the reader outlasts two attempts.
+    assert_eq!(
+        attempts,
+        [(false, ms(40)), (false, ms(80)), (true, ms(100))]
+    );
+}
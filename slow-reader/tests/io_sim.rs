@@ -0,0 +1,112 @@
+use std::io::ErrorKind;
+
+use slow_reader::test_support::expect_duration;
+use slow_reader::{DelayStrategy, Faults, IoMetrics, IoSim};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::time::Duration;
+
+const DELAY: DelayStrategy = DelayStrategy {
+    initial: Duration::from_millis(100),
+    on_pending: Duration::from_millis(10),
+};
+
+#[tokio::test(start_paused = true)]
+async fn delay_inside_timeout_counts_towards_it() {
+    let data = [1u8; 16];
+    let mut io = IoSim::builder(&data[..])
+        .timeout(Duration::from_millis(50))
+        .delay(DELAY)
+        .build();
+
+    let mut buf = [0; 16];
+    let err = io.read(&mut buf).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+}
+
+#[tokio::test(start_paused = true)]
+async fn delay_outside_timeout_does_not() {
+    let data = [1u8; 16];
+    let mut io = IoSim::builder(&data[..])
+        .delay(DELAY)
+        .timeout(Duration::from_millis(50))
+        .build();
+
+    let mut buf = [0; 16];
+    let n = expect_duration(DELAY.initial..=DELAY.initial, io.read(&mut buf))
+        .await
+        .unwrap();
+    assert_eq!(n, 16);
+}
+
+#[tokio::test]
+async fn faults_outside_metrics_are_not_counted() {
+    let data = [1u8; 16];
+    let metrics = IoMetrics::new();
+    let mut io = IoSim::builder(&data[..])
+        .faults(Faults::new().read(0, ErrorKind::ConnectionReset))
+        .metrics(&metrics)
+        .build();
+
+    let mut buf = [0; 16];
+    let err = io.read(&mut buf).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::ConnectionReset);
+    assert_eq!(io.read(&mut buf).await.unwrap(), 16);
+
+    let counts = metrics.snapshot();
+    assert_eq!(counts.errors, 0);
+    assert_eq!(counts.read_ops, 1);
+}
+
+#[tokio::test]
+async fn faults_inside_metrics_are_counted() {
+    let data = [1u8; 16];
+    let metrics = IoMetrics::new();
+    let mut io = IoSim::builder(&data[..])
+        .metrics(&metrics)
+        .faults(Faults::new().read(0, ErrorKind::ConnectionReset))
+        .build();
+
+    let mut buf = [0; 16];
+    assert!(io.read(&mut buf).await.is_err());
+    assert_eq!(io.read(&mut buf).await.unwrap(), 16);
+
+    let counts = metrics.snapshot();
+    assert_eq!(counts.errors, 1);
+    assert_eq!(counts.read_ops, 1);
+    assert_eq!(counts.bytes_read, 16);
+}
+
+#[tokio::test(start_paused = true)]
+async fn throttled_chunks() {
+    let data: Vec<u8> = (0..=255).collect();
+    let mut io = IoSim::builder(&data[..]).throttle(1280).chunks(64).build();
+
+    // 4 reads of 64 bytes, each of the last 3 waits for the 50ms the
+    // previous one should have taken.
+    let expected = Duration::from_millis(150);
+    let mut buf = [0; 256];
+    expect_duration(expected..=expected, io.read_exact(&mut buf))
+        .await
+        .unwrap();
+    assert_eq!(&buf[..], &data[..]);
+}
+
+#[tokio::test]
+async fn chunked_writes() {
+    let metrics = IoMetrics::new();
+    let mut io = IoSim::builder(Vec::new())
+        .metrics(&metrics)
+        .chunks(30)
+        .faults(Faults::new().write(4, ErrorKind::BrokenPipe))
+        .build();
+
+    let data = [7u8; 100];
+    io.write_all(&data).await.unwrap();
+    let counts = metrics.snapshot();
+    assert_eq!(counts.write_ops, 4);
+    assert_eq!(counts.bytes_written, 100);
+
+    let err = io.write_all(&data).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::BrokenPipe);
+    assert_eq!(io.into_inner(), data);
+}
@@ -0,0 +1,108 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use pin_utils::pin_mut;
+use slow_reader::test_support::expect_duration;
+use slow_reader::{DelayStrategy, SlowReader};
+use tokio::io::{AsyncRead, AsyncReadExt, ReadBuf};
+use tokio::time::Duration;
+
+// Serves `data` in chunks of `chunk` bytes, and is not ready before every
+// chunk but the first one. It wakes itself up right away, so any extra time
+// spent reading comes from the SlowReader.
+struct Chunked<'a> {
+    data: &'a [u8],
+    chunk: usize,
+    ready: bool,
+}
+
+impl<'a> Chunked<'a> {
+    fn new(data: &'a [u8], chunk: usize) -> Self {
+        Self {
+            data,
+            chunk,
+            ready: true,
+        }
+    }
+}
+
+impl AsyncRead for Chunked<'_> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        if !self.ready {
+            self.ready = true;
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+
+        let n = self.chunk.min(self.data.len()).min(buf.remaining());
+        let (head, tail) = self.data.split_at(n);
+        buf.put_slice(head);
+        self.data = tail;
+        self.ready = false;
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn read_exact_always_ready_pays_initial_delay_only() {
+    let data = [7u8; 1024];
+    let strategy = DelayStrategy::default();
+    let expected = strategy.predict(0);
+
+    let sr = SlowReader::with_strategy(&data[..], strategy);
+    pin_mut!(sr);
+    let mut buf = [0; 1024];
+
+    let n = expect_duration(expected..=expected, sr.read_exact(&mut buf))
+        .await
+        .unwrap();
+    assert_eq!(n, 1024);
+    assert_eq!(buf, data);
+}
+
+#[tokio::test(start_paused = true)]
+async fn read_exact_pays_delay_for_every_pending_read() {
+    let data: Vec<u8> = (0..=255).collect();
+    let strategy = DelayStrategy {
+        initial: Duration::from_millis(100),
+        on_pending: Duration::from_millis(10),
+    };
+
+    // 256 bytes in chunks of 64: the inner reader is pending before each of
+    // the last 3 chunks.
+    let expected = strategy.predict(3);
+    assert_eq!(expected, Duration::from_millis(130));
+
+    let sr = SlowReader::with_strategy(Chunked::new(&data, 64), strategy);
+    pin_mut!(sr);
+    let mut buf = [0; 256];
+
+    expect_duration(expected..=expected, sr.read_exact(&mut buf))
+        .await
+        .unwrap();
+    assert_eq!(&buf[..], &data[..]);
+}
+
+#[tokio::test]
+async fn read_exact_wall_clock_envelope() {
+    let data = [1u8; 64];
+    let strategy = DelayStrategy {
+        initial: Duration::from_millis(50),
+        on_pending: Duration::from_millis(5),
+    };
+
+    // On a real clock timers are only guaranteed not to fire early.
+    let min = strategy.predict(0);
+    let sr = SlowReader::with_strategy(&data[..], strategy);
+    pin_mut!(sr);
+    let mut buf = [0; 64];
+
+    expect_duration(min..min + Duration::from_secs(1), sr.read_exact(&mut buf))
+        .await
+        .unwrap();
+}
@@ -0,0 +1,84 @@
+use std::io::ErrorKind;
+
+use slow_reader::io_trace::{Dir, Outcome};
+use slow_reader::test_support::expect_duration;
+use slow_reader::{Faults, IoMetrics, IoRecorder, IoSim, IoTrace};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::time::{Duration, Instant};
+
+// Reads until EOF, and returns what every read returned along with the time
+// it took.
+async fn read_all(mut io: impl AsyncRead + Unpin) -> (Vec<Result<usize, ErrorKind>>, Duration) {
+    let start = Instant::now();
+    let mut results = vec![];
+    let mut buf = [0; 256];
+    loop {
+        let res = io.read(&mut buf).await.map_err(|e| e.kind());
+        results.push(res);
+        if res == Ok(0) {
+            return (results, start.elapsed());
+        }
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn replay_reproduces_sizes_timing_and_faults() {
+    let data: Vec<u8> = (0..=255).collect();
+    let recorder = IoRecorder::new();
+    let io = IoSim::builder(&data[..])
+        .record(&recorder)
+        .throttle(1280)
+        .chunks(64)
+        .faults(Faults::new().read(2, ErrorKind::ConnectionReset))
+        .build();
+    let (recorded, took) = read_all(io).await;
+    let trace = recorder.trace();
+    assert_eq!(trace.events().len(), recorded.len());
+    assert_eq!(
+        trace.events()[2].outcome,
+        Outcome::Error(ErrorKind::ConnectionReset)
+    );
+
+    // A plain stream, which would return all of it at once.
+    let replayed = IoRecorder::new();
+    let io = IoSim::builder(&data[..])
+        .record(&replayed)
+        .replay(trace.clone())
+        .build();
+    let (results, _) = expect_duration(took..=took, read_all(io)).await;
+
+    assert_eq!(results, recorded);
+    assert_eq!(replayed.trace(), trace);
+}
+
+#[tokio::test]
+async fn replay_of_writes_from_a_saved_trace() {
+    let recorder = IoRecorder::new();
+    let mut io = IoSim::builder(Vec::new())
+        .record(&recorder)
+        .chunks(30)
+        .faults(Faults::new().write(1, ErrorKind::BrokenPipe))
+        .build();
+    let data = [7u8; 100];
+    assert!(io.write_all(&data).await.is_err());
+    io.write_all(&data).await.unwrap();
+
+    let path = std::env::temp_dir().join(format!("slow-reader-{}.trace", std::process::id()));
+    recorder.trace().save(&path).unwrap();
+    let trace = IoTrace::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(trace.events().iter().all(|e| e.dir == Dir::Write));
+
+    let metrics = IoMetrics::new();
+    let mut io = IoSim::builder(Vec::new())
+        .replay(trace)
+        .metrics(&metrics)
+        .build();
+    let err = io.write_all(&data).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::BrokenPipe);
+    io.write_all(&data).await.unwrap();
+
+    // The first write, then 4 chunks of the second one.
+    assert_eq!(metrics.snapshot().write_ops, 5);
+    assert_eq!(io.into_inner().len(), 130);
+}
@@ -0,0 +1,27 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+// Usage: criterion-compare [DIR]
+//
+// Compares the last two runs of every benchmark in DIR (target/criterion by
+// default) and fails if any of them regressed.
+fn main() -> ExitCode {
+    let root = std::env::args()
+        .nth(1)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("target/criterion"));
+
+    let report = match statistics::criterion::compare(&root) {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("couldn't read {}: {}", root.display(), e);
+            return ExitCode::from(2);
+        }
+    };
+
+    print!("{}", report);
+    if report.regressions().next().is_some() {
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,363 @@
+//! Compares the last two runs of criterion benchmarks with the statistics of
+//! this crate.
+//!
+//! Criterion keeps the samples of the latest run of every benchmark in
+//! `target/criterion/<benchmark>/new/raw.csv`, and the ones of the run before
+//! in `base/raw.csv`. Each row is one sample: a number of iterations and the
+//! time they took together. The per-iteration times of both runs are tested
+//! for a difference with Welch's t-test.
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::summary::{welch_t_test, Summary, TTest};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Samples {
+    /// The group, function and value of the benchmark, joined with '/'.
+    pub name: String,
+    pub unit: String,
+    /// The time of a single iteration in every sample.
+    pub times: Vec<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Regressed,
+    Improved,
+    NoChange,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparison {
+    pub name: String,
+    pub unit: String,
+    pub base: Summary,
+    pub new: Summary,
+    /// None when either run has less than two samples.
+    pub test: Option<TTest>,
+}
+
+impl Comparison {
+    /// The relative change of the mean, e.g. 0.1 when the new run is 10%
+    /// slower.
+    pub fn change(&self) -> f64 {
+        (self.new.mean - self.base.mean) / self.base.mean
+    }
+
+    /// A change counts if it's statistically significant at `alpha`, and
+    /// larger than `noise` (relative), like criterion's own noise threshold.
+    pub fn verdict(&self, alpha: f64, noise: f64) -> Verdict {
+        let significant = self.test.is_some_and(|t| t.p_value < alpha);
+        let change = self.change();
+        if !significant || change.abs() <= noise {
+            Verdict::NoChange
+        } else if change > 0.0 {
+            Verdict::Regressed
+        } else {
+            Verdict::Improved
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub comparisons: Vec<Comparison>,
+    alpha: f64,
+    noise: f64,
+}
+
+impl Report {
+    /// The significance level of the t-test, 0.05 by default.
+    pub fn alpha(mut self, alpha: f64) -> Self {
+        self.alpha = alpha;
+        self
+    }
+
+    /// Relative changes up to this are ignored, 0.02 by default.
+    pub fn noise_threshold(mut self, noise: f64) -> Self {
+        self.noise = noise;
+        self
+    }
+
+    pub fn verdict(&self, comparison: &Comparison) -> Verdict {
+        comparison.verdict(self.alpha, self.noise)
+    }
+
+    pub fn regressions(&self) -> impl Iterator<Item = &Comparison> {
+        self.comparisons
+            .iter()
+            .filter(|c| self.verdict(c) == Verdict::Regressed)
+    }
+}
+
+/// Compares every benchmark under `root` (usually `target/criterion`) that
+/// has been run at least twice. Benchmarks are sorted by name.
+pub fn compare(root: &Path) -> io::Result<Report> {
+    let mut comparisons = Vec::new();
+    visit(root, &mut comparisons)?;
+    comparisons.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(Report {
+        comparisons,
+        alpha: 0.05,
+        noise: 0.02,
+    })
+}
+
+fn visit(dir: &Path, comparisons: &mut Vec<Comparison>) -> io::Result<()> {
+    let (base, new) = (dir.join("base/raw.csv"), dir.join("new/raw.csv"));
+    if base.is_file() && new.is_file() {
+        let base = read_raw_csv(&base)?;
+        let new = read_raw_csv(&new)?;
+        if let Some(comparison) = compare_samples(&base, &new) {
+            comparisons.push(comparison);
+        }
+        return Ok(());
+    }
+
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        // Criterion's HTML reports live next to the benchmarks.
+        if entry.file_type()?.is_dir() && entry.file_name() != "report" {
+            visit(&entry.path(), comparisons)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn compare_samples(base: &Samples, new: &Samples) -> Option<Comparison> {
+    let base_summary = Summary::of(&base.times)?;
+    let new_summary = Summary::of(&new.times)?;
+    Some(Comparison {
+        name: new.name.clone(),
+        unit: new.unit.clone(),
+        test: welch_t_test(&new_summary, &base_summary),
+        base: base_summary,
+        new: new_summary,
+    })
+}
+
+pub fn read_raw_csv(path: &Path) -> io::Result<Samples> {
+    parse_raw_csv(&fs::read_to_string(path)?).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", path.display(), e),
+        )
+    })
+}
+
+// The columns are: group, function, value, throughput_num, throughput_type,
+// sample_measured_value, unit, iteration_count.
+fn parse_raw_csv(csv: &str) -> Result<Samples, String> {
+    let mut lines = csv.lines().filter(|l| !l.is_empty());
+    let header = lines.next().ok_or("empty file")?;
+    let columns = split_row(header);
+    let column = |name: &str| {
+        columns
+            .iter()
+            .position(|c| c == name)
+            .ok_or(format!("missing column: {}", name))
+    };
+    let (group, function, value) = (column("group")?, column("function")?, column("value")?);
+    let (measured, unit, iterations) = (
+        column("sample_measured_value")?,
+        column("unit")?,
+        column("iteration_count")?,
+    );
+
+    let mut samples = Samples {
+        name: String::new(),
+        unit: String::new(),
+        times: Vec::new(),
+    };
+    for (i, line) in lines.enumerate() {
+        let row = split_row(line);
+        let field = |col: usize| {
+            row.get(col)
+                .map(String::as_str)
+                .ok_or(format!("row {} has too few fields", i + 1))
+        };
+        let number = |col: usize| {
+            field(col)?
+                .parse::<f64>()
+                .map_err(|e| format!("row {}: {}", i + 1, e))
+        };
+
+        if samples.name.is_empty() {
+            let parts = [field(group)?, field(function)?, field(value)?];
+            samples.name = parts
+                .iter()
+                .filter(|p| !p.is_empty())
+                .copied()
+                .collect::<Vec<_>>()
+                .join("/");
+            samples.unit = field(unit)?.to_owned();
+        }
+        samples.times.push(number(measured)? / number(iterations)?);
+    }
+    Ok(samples)
+}
+
+// Splits a CSV row, handling quoted fields (which criterion writes for names
+// containing commas or quotes).
+fn split_row(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                fields.last_mut().unwrap().push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(String::new()),
+            c => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields
+}
+
+/// One line per benchmark, e.g.
+/// `read_mostly/get/last_read  12.410 ns -> 13.902 ns  +12.02%  p=0.000  regressed`.
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self
+            .comparisons
+            .iter()
+            .map(|c| c.name.len())
+            .max()
+            .unwrap_or(0);
+        for c in &self.comparisons {
+            let p_value = match c.test {
+                Some(t) => format!("p={:.3}", t.p_value),
+                None => "p=n/a".to_owned(),
+            };
+            let verdict = match self.verdict(c) {
+                Verdict::Regressed => "regressed",
+                Verdict::Improved => "improved",
+                Verdict::NoChange => "no change",
+            };
+            writeln!(
+                f,
+                "{:<width$}  {:.3} {unit} -> {:.3} {unit}  {:+.2}%  {}  {}",
+                c.name,
+                c.base.mean,
+                c.new.mean,
+                c.change() * 100.0,
+                p_value,
+                verdict,
+                width = width,
+                unit = c.unit,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "group,function,value,throughput_num,throughput_type,sample_measured_value,unit,iteration_count";
+
+    fn raw_csv(group: &str, function: &str, per_iter: &[f64]) -> String {
+        let mut csv = format!("{}\n", HEADER);
+        for (i, t) in per_iter.iter().enumerate() {
+            let iters = (i + 1) * 10;
+            csv += &format!(
+                "{},{},,,,{},ns,{}\n",
+                group,
+                function,
+                t * iters as f64,
+                iters
+            );
+        }
+        csv
+    }
+
+    #[test]
+    fn parses_per_iteration_times() {
+        let csv = raw_csv("queue", "push", &[10.0, 12.0, 11.0]);
+        let samples = parse_raw_csv(&csv).unwrap();
+        assert_eq!(samples.name, "queue/push");
+        assert_eq!(samples.unit, "ns");
+        assert_eq!(samples.times, [10.0, 12.0, 11.0]);
+    }
+
+    #[test]
+    fn parses_quoted_fields() {
+        assert_eq!(
+            split_row(r#"a,"b,c","say ""hi""",d"#),
+            ["a", "b,c", r#"say "hi""#, "d"]
+        );
+    }
+
+    #[test]
+    fn reports_malformed_files() {
+        assert_eq!(parse_raw_csv(""), Err("empty file".to_owned()));
+        assert_eq!(
+            parse_raw_csv("group,function\n"),
+            Err("missing column: value".to_owned())
+        );
+        let csv = format!("{}\nq,push,,,,abc,ns,10\n", HEADER);
+        assert!(parse_raw_csv(&csv).unwrap_err().starts_with("row 1: "));
+    }
+
+    #[test]
+    fn compares_runs_in_a_criterion_dir() {
+        let root =
+            std::env::temp_dir().join(format!("statistics-criterion-{}", std::process::id()));
+        let write = |bench: &str, run: &str, csv: String| {
+            let dir = root.join(bench).join(run);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("raw.csv"), csv).unwrap();
+        };
+
+        let steady = [10.0, 10.2, 9.8, 10.1, 9.9, 10.0];
+        let slower: Vec<f64> = steady.iter().map(|t| t * 1.5).collect();
+        write("stack/push", "base", raw_csv("stack", "push", &steady));
+        write("stack/push", "new", raw_csv("stack", "push", &slower));
+        write("stack/pop", "base", raw_csv("stack", "pop", &steady));
+        write("stack/pop", "new", raw_csv("stack", "pop", &steady));
+        // Only run once, so there's nothing to compare yet.
+        write("queue/push", "new", raw_csv("queue", "push", &steady));
+        fs::create_dir_all(root.join("report")).unwrap();
+
+        let report = compare(&root).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        let names: Vec<_> = report.comparisons.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["stack/pop", "stack/push"]);
+        let regressions: Vec<_> = report.regressions().map(|c| c.name.as_str()).collect();
+        assert_eq!(regressions, ["stack/push"]);
+        assert_eq!(report.verdict(&report.comparisons[0]), Verdict::NoChange);
+
+        let rendered = report.to_string();
+        let lines: Vec<_> = rendered.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].starts_with("stack/push  10.000 ns -> 15.000 ns  +50.00%  p=0.000"));
+        assert!(lines[1].ends_with("regressed"));
+    }
+
+    #[test]
+    fn noise_threshold_hides_small_changes() {
+        let base = Samples {
+            name: "b".to_owned(),
+            unit: "ns".to_owned(),
+            times: vec![100.0, 100.1, 99.9, 100.0],
+        };
+        let new = Samples {
+            times: vec![101.0, 101.1, 100.9, 101.0],
+            ..base.clone()
+        };
+        let comparison = compare_samples(&base, &new).unwrap();
+
+        // Significant, but only a 1% change.
+        assert!(comparison.test.unwrap().p_value < 0.05);
+        assert_eq!(comparison.verdict(0.05, 0.02), Verdict::NoChange);
+        assert_eq!(comparison.verdict(0.05, 0.005), Verdict::Regressed);
+    }
+}
@@ -4,6 +4,9 @@
 /// a hash map will be helpful here) of the list.
 use std::collections::HashMap;
 
+pub mod criterion;
+pub mod summary;
+
 enum MiddleIndex {
     Even(usize, usize),
     Odd(usize),
@@ -0,0 +1,242 @@
+//! Descriptive statistics and Welch's t-test over samples of measurements.
+use std::f64::consts::PI;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Summary {
+    pub n: usize,
+    pub mean: f64,
+    /// The sample standard deviation (divided by n - 1).
+    pub std_dev: f64,
+    pub min: f64,
+    pub max: f64,
+    pub median: f64,
+}
+
+impl Summary {
+    /// Returns None for an empty sample.
+    pub fn of(samples: &[f64]) -> Option<Summary> {
+        if samples.is_empty() {
+            return None;
+        }
+
+        let n = samples.len();
+        let mean = samples.iter().sum::<f64>() / n as f64;
+        let variance = if n > 1 {
+            samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1) as f64
+        } else {
+            0.0
+        };
+
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        let median = if n.is_multiple_of(2) {
+            (sorted[n / 2 - 1] + sorted[n / 2]) / 2.0
+        } else {
+            sorted[n / 2]
+        };
+
+        Some(Summary {
+            n,
+            mean,
+            std_dev: variance.sqrt(),
+            min: sorted[0],
+            max: sorted[n - 1],
+            median,
+        })
+    }
+
+    fn variance(&self) -> f64 {
+        self.std_dev * self.std_dev
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TTest {
+    pub t: f64,
+    /// Degrees of freedom from the Welch-Satterthwaite equation.
+    pub df: f64,
+    /// Two-sided: the probability of a difference at least this large if
+    /// both samples had the same mean.
+    pub p_value: f64,
+}
+
+/// Welch's t-test, which unlike Student's doesn't assume that both samples
+/// have the same variance. Both samples need at least two measurements.
+pub fn welch_t_test(a: &Summary, b: &Summary) -> Option<TTest> {
+    if a.n < 2 || b.n < 2 {
+        return None;
+    }
+
+    let va = a.variance() / a.n as f64;
+    let vb = b.variance() / b.n as f64;
+    let se = (va + vb).sqrt();
+    if se == 0.0 {
+        // Both samples are constant, so any difference is certain.
+        let (t, p_value) = if a.mean == b.mean {
+            (0.0, 1.0)
+        } else {
+            (f64::INFINITY.copysign(a.mean - b.mean), 0.0)
+        };
+        return Some(TTest {
+            t,
+            df: (a.n + b.n - 2) as f64,
+            p_value,
+        });
+    }
+
+    let t = (a.mean - b.mean) / se;
+    let df = (va + vb).powi(2) / (va.powi(2) / (a.n - 1) as f64 + vb.powi(2) / (b.n - 1) as f64);
+    let p_value = inc_beta(df / 2.0, 0.5, df / (df + t * t));
+    Some(TTest { t, df, p_value })
+}
+
+// The regularized incomplete beta function I_x(a, b), which gives the tail
+// probability of Student's t-distribution. Evaluated with its continued
+// fraction (Numerical Recipes, 6.4), which converges quickly on the side of
+// x < (a + 1) / (a + b + 2), and through the symmetry I_x(a, b) =
+// 1 - I_(1-x)(b, a) on the other.
+fn inc_beta(a: f64, b: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+
+    let front =
+        (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln()).exp();
+    if x < (a + 1.0) / (a + b + 2.0) {
+        front * beta_cont_frac(a, b, x) / a
+    } else {
+        1.0 - front * beta_cont_frac(b, a, 1.0 - x) / b
+    }
+}
+
+fn beta_cont_frac(a: f64, b: f64, x: f64) -> f64 {
+    const MAX_ITERATIONS: usize = 300;
+    const EPSILON: f64 = 1e-14;
+    const TINY: f64 = 1e-300;
+
+    let clamp = |v: f64| if v.abs() < TINY { TINY } else { v };
+
+    let mut c = 1.0;
+    let mut d = 1.0 / clamp(1.0 - (a + b) * x / (a + 1.0));
+    let mut h = d;
+    for m in 1..=MAX_ITERATIONS {
+        let m = m as f64;
+
+        // Even step.
+        let num = m * (b - m) * x / ((a + 2.0 * m - 1.0) * (a + 2.0 * m));
+        d = 1.0 / clamp(1.0 + num * d);
+        c = clamp(1.0 + num / c);
+        h *= d * c;
+
+        // Odd step.
+        let num = -(a + m) * (a + b + m) * x / ((a + 2.0 * m) * (a + 2.0 * m + 1.0));
+        d = 1.0 / clamp(1.0 + num * d);
+        c = clamp(1.0 + num / c);
+        let delta = d * c;
+        h *= delta;
+
+        if (delta - 1.0).abs() < EPSILON {
+            break;
+        }
+    }
+    h
+}
+
+// Lanczos approximation (g = 7, n = 9), accurate to about 15 digits.
+fn ln_gamma(x: f64) -> f64 {
+    const G: f64 = 7.0;
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+
+    if x < 0.5 {
+        // Reflection formula.
+        return (PI / (PI * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+
+    let x = x - 1.0;
+    let mut sum = COEFFICIENTS[0];
+    for (i, c) in COEFFICIENTS.iter().enumerate().skip(1) {
+        sum += c / (x + i as f64);
+    }
+    let t = x + G + 0.5;
+    0.5 * (2.0 * PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() < tolerance,
+            "{} is not within {} of {}",
+            actual,
+            tolerance,
+            expected
+        );
+    }
+
+    #[test]
+    fn summary_of_samples() {
+        let summary = Summary::of(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]).unwrap();
+        assert_eq!(summary.n, 8);
+        assert_eq!(summary.mean, 5.0);
+        assert_close(summary.std_dev, 2.138_089_935, 1e-9);
+        assert_eq!((summary.min, summary.max, summary.median), (2.0, 9.0, 4.5));
+
+        assert_eq!(Summary::of(&[]), None);
+        assert_eq!(Summary::of(&[3.0]).unwrap().std_dev, 0.0);
+    }
+
+    #[test]
+    fn ln_gamma_of_known_values() {
+        assert_close(ln_gamma(1.0), 0.0, 1e-12);
+        assert_close(ln_gamma(5.0), 24f64.ln(), 1e-12);
+        assert_close(ln_gamma(0.5), PI.sqrt().ln(), 1e-12);
+    }
+
+    #[test]
+    fn t_distribution_tail() {
+        // Two-sided p-values of Student's t-distribution from tables.
+        let p = |t: f64, df: f64| inc_beta(df / 2.0, 0.5, df / (df + t * t));
+        assert_close(p(2.0, 10.0), 0.073_388, 1e-5);
+        assert_close(p(2.228, 10.0), 0.05, 1e-4);
+        assert_close(p(1.96, 1e6), 0.05, 1e-4);
+        assert_close(p(0.0, 5.0), 1.0, 1e-12);
+    }
+
+    #[test]
+    fn welch_t_test_detects_shift() {
+        let a = Summary::of(&[10.0, 10.2, 9.9, 10.1, 10.0, 9.8]).unwrap();
+        let shifted = Summary::of(&[11.0, 11.2, 10.9, 11.1, 11.0, 10.8]).unwrap();
+        let noisy = Summary::of(&[10.1, 9.7, 10.3, 10.0, 9.9, 10.2]).unwrap();
+
+        let test = welch_t_test(&shifted, &a).unwrap();
+        assert!(test.t > 0.0);
+        assert!(test.p_value < 1e-6);
+
+        let test = welch_t_test(&noisy, &a).unwrap();
+        assert!(test.p_value > 0.5);
+    }
+
+    #[test]
+    fn welch_t_test_of_constant_samples() {
+        let a = Summary::of(&[1.0, 1.0]).unwrap();
+        let b = Summary::of(&[2.0, 2.0]).unwrap();
+        assert_eq!(welch_t_test(&a, &a).unwrap().p_value, 1.0);
+        assert_eq!(welch_t_test(&a, &b).unwrap().p_value, 0.0);
+        assert!(welch_t_test(&a, &Summary::of(&[1.0]).unwrap()).is_none());
+    }
+}
@@ -0,0 +1,209 @@
+//! Following a growing file, like `tail -f`.
+//!
+//! ```ignore
+//! let mut tail = Tail::builder("app.log")
+//!     .poll_interval(Duration::from_millis(250))
+//!     .open()?;
+//! while let Some(line) = tail.next().await {
+//!     println!("{}", line?);
+//! }
+//! ```
+//!
+//! A `Tail` polls the file the way a `SlowReader` stalls: whenever there's
+//! nothing new to read it sleeps for the poll interval before looking again.
+//! Regular files are always ready to be read, so the reads themselves are
+//! plain blocking reads that return right away.
+//!
+//! When the file shrinks below what was read so far it was truncated, and
+//! it's followed from its start again. When the path names another file it
+//! was rotated: the rest of the old file is read first, then the new one is
+//! followed from its start. A truncation that is followed by enough writes to
+//! grow the file past the old position before the next poll goes unnoticed,
+//! like it does for `tail -f`.
+use std::fs::{self, File, Metadata};
+use std::future::Future;
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::time::{self, Duration, Instant, Sleep};
+use tokio_stream::Stream;
+
+/// A stream of the lines appended to a file, without their line endings.
+/// Must be created within a tokio runtime.
+#[derive(Debug)]
+pub struct Tail {
+    path: PathBuf,
+    file: File,
+    id: Option<FileId>,
+    // How much of the file was read.
+    pos: u64,
+    // Read, but not returned as a line yet.
+    buf: Vec<u8>,
+    interval: Duration,
+    sleep: Pin<Box<Sleep>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TailBuilder {
+    path: PathBuf,
+    interval: Duration,
+    from_start: bool,
+}
+
+// Tells files apart across renames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FileId {
+    dev: u64,
+    ino: u64,
+}
+
+impl TailBuilder {
+    /// How long to wait before looking at the file again when there was
+    /// nothing new. 100ms by default.
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Returns the lines that are already in the file as well, instead of
+    /// starting at its end.
+    pub fn from_start(mut self) -> Self {
+        self.from_start = true;
+        self
+    }
+
+    pub fn open(self) -> io::Result<Tail> {
+        let mut file = File::open(&self.path)?;
+        let id = file_id(&file.metadata()?);
+        let pos = if self.from_start {
+            0
+        } else {
+            file.seek(SeekFrom::End(0))?
+        };
+
+        Ok(Tail {
+            path: self.path,
+            file,
+            id,
+            pos,
+            buf: vec![],
+            interval: self.interval,
+            // The first poll looks at the file right away.
+            sleep: Box::pin(time::sleep(Duration::ZERO)),
+        })
+    }
+}
+
+impl Tail {
+    pub fn builder(path: impl AsRef<Path>) -> TailBuilder {
+        TailBuilder {
+            path: path.as_ref().to_owned(),
+            interval: Duration::from_millis(100),
+            from_start: false,
+        }
+    }
+
+    /// Follows `path` from its current end, polling every 100ms.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::builder(path).open()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Removes the first complete line from buf.
+    fn take_line(&mut self) -> Option<io::Result<String>> {
+        let end = self.buf.iter().position(|&b| b == b'\n')?;
+        let mut line: Vec<u8> = self.buf.drain(..=end).collect();
+        line.pop();
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        Some(String::from_utf8(line).map_err(|e| io::Error::new(ErrorKind::InvalidData, e)))
+    }
+
+    // Reads what was appended since the last call, following truncation and
+    // rotation. Returns whether anything changed.
+    fn poll_file(&mut self) -> io::Result<bool> {
+        let n = self.file.read_to_end(&mut self.buf)?;
+        if n > 0 {
+            self.pos += n as u64;
+            return Ok(true);
+        }
+
+        if self.file.metadata()?.len() < self.pos {
+            // What's left of a line from before the truncation isn't
+            // continued by what's written after it.
+            self.buf.clear();
+            self.pos = self.file.seek(SeekFrom::Start(0))?;
+            return Ok(true);
+        }
+
+        let meta = match fs::metadata(&self.path) {
+            Ok(meta) => meta,
+            // Rotated, but the new file isn't there yet.
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e),
+        };
+        if file_id(&meta) == self.id {
+            return Ok(false);
+        }
+
+        // The old file was read to its end, an unterminated last line is
+        // still a line.
+        if !self.buf.is_empty() {
+            self.buf.push(b'\n');
+        }
+        self.file = File::open(&self.path)?;
+        self.id = file_id(&self.file.metadata()?);
+        self.pos = 0;
+        Ok(true)
+    }
+}
+
+impl Stream for Tail {
+    type Item = io::Result<String>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(line) = self.take_line() {
+                return Poll::Ready(Some(line));
+            }
+            if self.sleep.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+
+            match self.poll_file() {
+                // The sleep has elapsed, so the file is read again right away.
+                Ok(true) => continue,
+                Ok(false) => {
+                    let deadline = Instant::now() + self.interval;
+                    self.sleep.as_mut().reset(deadline);
+                }
+                Err(e) => {
+                    let deadline = Instant::now() + self.interval;
+                    self.sleep.as_mut().reset(deadline);
+                    return Poll::Ready(Some(Err(e)));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn file_id(meta: &Metadata) -> Option<FileId> {
+    use std::os::unix::fs::MetadataExt;
+    Some(FileId {
+        dev: meta.dev(),
+        ino: meta.ino(),
+    })
+}
+
+// Rotation isn't noticed without file ids, only truncation.
+#[cfg(not(unix))]
+fn file_id(_: &Metadata) -> Option<FileId> {
+    None
+}
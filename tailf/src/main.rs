@@ -0,0 +1,19 @@
+use std::env;
+use std::io;
+
+use tokio_stream::StreamExt;
+
+use tailf::Tail;
+
+#[tokio::main]
+async fn main() -> io::Result<()> {
+    let path = env::args()
+        .nth(1)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "usage: tailf PATH"))?;
+
+    let mut tail = Tail::open(path)?;
+    while let Some(line) = tail.next().await {
+        println!("{}", line?);
+    }
+    Ok(())
+}
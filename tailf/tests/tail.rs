@@ -0,0 +1,120 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use tailf::Tail;
+use tokio::time::{self, Duration, Instant};
+use tokio_stream::StreamExt;
+
+// A file in the temp dir that is removed at the end of the test.
+struct TempFile(PathBuf);
+
+impl TempFile {
+    fn new(name: &str, content: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("tailf-{}-{}", std::process::id(), name));
+        fs::write(&path, content).unwrap();
+        Self(path)
+    }
+
+    fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+fn append(path: &Path, s: &str) {
+    let mut f = OpenOptions::new().append(true).open(path).unwrap();
+    f.write_all(s.as_bytes()).unwrap();
+}
+
+async fn next_line(tail: &mut Tail) -> String {
+    tail.next().await.unwrap().unwrap()
+}
+
+#[tokio::test(start_paused = true)]
+async fn follows_appended_lines() {
+    let file = TempFile::new("append", "old\n");
+    let mut tail = Tail::builder(file.path())
+        .poll_interval(Duration::from_millis(10))
+        .open()
+        .unwrap();
+
+    let path = file.path().to_owned();
+    let writer = tokio::spawn(async move {
+        time::sleep(Duration::from_millis(50)).await;
+        append(&path, "a\nb");
+        // The rest of the line comes with a later poll.
+        time::sleep(Duration::from_millis(50)).await;
+        append(&path, "c\r\n");
+    });
+
+    assert_eq!(next_line(&mut tail).await, "a");
+    assert_eq!(next_line(&mut tail).await, "bc");
+    writer.await.unwrap();
+}
+
+#[tokio::test(start_paused = true)]
+async fn from_start_returns_existing_lines() {
+    let file = TempFile::new("from-start", "one\ntwo\n");
+    let mut tail = Tail::builder(file.path()).from_start().open().unwrap();
+
+    assert_eq!(next_line(&mut tail).await, "one");
+    assert_eq!(next_line(&mut tail).await, "two");
+    append(file.path(), "three\n");
+    assert_eq!(next_line(&mut tail).await, "three");
+}
+
+#[tokio::test(start_paused = true)]
+async fn new_lines_wait_for_the_next_poll() {
+    let file = TempFile::new("interval", "");
+    let mut tail = Tail::builder(file.path())
+        .poll_interval(Duration::from_secs(1))
+        .open()
+        .unwrap();
+
+    let path = file.path().to_owned();
+    tokio::spawn(async move {
+        time::sleep(Duration::from_millis(10)).await;
+        append(&path, "line\n");
+    });
+
+    let start = Instant::now();
+    assert_eq!(next_line(&mut tail).await, "line");
+    assert_eq!(start.elapsed(), Duration::from_secs(1));
+}
+
+#[tokio::test(start_paused = true)]
+async fn truncated_file_is_followed_from_its_start() {
+    let file = TempFile::new("truncate", "first line\nsecond line\n");
+    let mut tail = Tail::builder(file.path()).from_start().open().unwrap();
+    assert_eq!(next_line(&mut tail).await, "first line");
+    assert_eq!(next_line(&mut tail).await, "second line");
+
+    fs::write(file.path(), "short\n").unwrap();
+    assert_eq!(next_line(&mut tail).await, "short");
+}
+
+#[tokio::test(start_paused = true)]
+async fn rotated_file_is_read_to_its_end_first() {
+    let file = TempFile::new("rotate", "");
+    let rotated = TempFile::new("rotate.1", "");
+    let mut tail = Tail::open(file.path()).unwrap();
+
+    append(file.path(), "before\nlast");
+    fs::rename(file.path(), rotated.path()).unwrap();
+    let path = file.path().to_owned();
+    tokio::spawn(async move {
+        // The path is missing for a few polls.
+        time::sleep(Duration::from_millis(500)).await;
+        fs::write(&path, "new\n").unwrap();
+    });
+
+    assert_eq!(next_line(&mut tail).await, "before");
+    assert_eq!(next_line(&mut tail).await, "last");
+    assert_eq!(next_line(&mut tail).await, "new");
+}
@@ -0,0 +1,58 @@
+use std::thread;
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use treiber_stack::{Stack, StampedStack};
+
+const OPS_PER_THREAD: u64 = 10_000;
+
+// Every pusher pushes its share while as many poppers pop it, so the stack
+// is contended from both ends.
+fn push_pop(pairs: u64, push: impl Fn(u64) + Sync, pop: impl Fn() -> Option<u64> + Sync) {
+    thread::scope(|s| {
+        for p in 0..pairs {
+            let push = &push;
+            s.spawn(move || {
+                for i in 0..OPS_PER_THREAD {
+                    push(p * OPS_PER_THREAD + i);
+                }
+            });
+        }
+        for _ in 0..pairs {
+            let pop = &pop;
+            s.spawn(move || {
+                let mut popped = 0;
+                while popped < OPS_PER_THREAD {
+                    if let Some(v) = pop() {
+                        black_box(v);
+                        popped += 1;
+                    }
+                }
+            });
+        }
+    });
+}
+
+pub fn stamped_vs_epoch_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("push_pop");
+    group.sample_size(20);
+    for pairs in [1, 2, 4] {
+        // The stacks are drained by every iteration, so they're reused.
+        let stack = Stack::new();
+        group.bench_with_input(
+            BenchmarkId::new("Stack (epoch)", pairs),
+            &pairs,
+            |b, &pairs| b.iter(|| push_pop(pairs, |v| stack.push(v), || stack.pop())),
+        );
+        // Enough slots for every push, so none of them fail.
+        let stamped = StampedStack::with_capacity((pairs * OPS_PER_THREAD) as usize);
+        group.bench_with_input(
+            BenchmarkId::new("StampedStack", pairs),
+            &pairs,
+            |b, &pairs| b.iter(|| push_pop(pairs, |v| stamped.push(v).unwrap(), || stamped.pop())),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, stamped_vs_epoch_benchmark);
+criterion_main!(benches);
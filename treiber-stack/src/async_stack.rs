@@ -0,0 +1,480 @@
+// A Stack for async code: `pop().await` waits for a push, and with a
+// capacity `push(item).await` waits for a pop while the stack is full.
+//
+// Waiting tasks queue up in a wait list and are woken one at a time, in the
+// order they started waiting. A woken task that finds nothing (another
+// caller was faster) goes back to the front of the list, and a task that is
+// dropped after it was woken passes the wakeup on to the next one. So
+// dropping the futures at any point neither loses elements nor wakeups.
+//
+// The capacity is soft: it's only enforced by pushes through this type, and
+// the count of elements it's checked against is updated around the pushes
+// and pops of the stack, not together with them.
+use core::fmt::Debug;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::task::{Context, Poll, Waker};
+
+use crate::Stack;
+
+pub struct AsyncStack<T: Debug> {
+    stack: Stack<T>,
+    // Counts the elements, and those that are about to be pushed.
+    len: AtomicUsize,
+    capacity: Option<usize>,
+    poppers: WaitList,
+    pushers: WaitList,
+}
+
+impl<T: Debug> AsyncStack<T> {
+    /// A stack whose pushes never wait.
+    pub fn new() -> Self {
+        Self::with_capacity_opt(None)
+    }
+
+    /// A stack whose pushes wait while it holds `capacity` elements.
+    pub fn with_capacity(capacity: usize) -> Self {
+        assert!(capacity > 0, "capacity must be positive");
+        Self::with_capacity_opt(Some(capacity))
+    }
+
+    fn with_capacity_opt(capacity: Option<usize>) -> Self {
+        Self {
+            stack: Stack::new(),
+            len: AtomicUsize::new(0),
+            capacity,
+            poppers: WaitList::default(),
+            pushers: WaitList::default(),
+        }
+    }
+
+    pub fn capacity(&self) -> Option<usize> {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Acquire)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stack.is_empty()
+    }
+
+    /// Pushes `data` unless the stack is full, in which case it's handed
+    /// back.
+    pub fn try_push(&self, data: T) -> Result<(), T> {
+        let reserved = self
+            .len
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |len| {
+                match self.capacity {
+                    Some(capacity) if len >= capacity => None,
+                    _ => Some(len + 1),
+                }
+            });
+        if reserved.is_err() {
+            return Err(data);
+        }
+        self.stack.push(data);
+        self.poppers.notify_one();
+        Ok(())
+    }
+
+    /// Waits until there's room for `data` and pushes it. Dropping the
+    /// future before it completes drops `data`.
+    pub fn push(&self, data: T) -> Push<'_, T> {
+        Push {
+            stack: self,
+            data: Some(data),
+            wait: Wait::default(),
+        }
+    }
+
+    pub fn try_pop(&self) -> Option<T> {
+        let data = self.stack.pop()?;
+        self.len.fetch_sub(1, Ordering::AcqRel);
+        self.pushers.notify_one();
+        Some(data)
+    }
+
+    /// Waits until there's an element to pop.
+    pub fn pop(&self) -> Pop<'_, T> {
+        Pop {
+            stack: self,
+            wait: Wait::default(),
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.capacity
+            .is_some_and(|capacity| self.len.load(Ordering::Acquire) >= capacity)
+    }
+}
+
+impl<T: Debug> Default for AsyncStack<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct Pop<'a, T: Debug> {
+    stack: &'a AsyncStack<T>,
+    wait: Wait,
+}
+
+impl<T: Debug> Future for Pop<'_, T> {
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let this = &mut *self;
+        loop {
+            if let Some(data) = this.stack.try_pop() {
+                this.stack.poppers.leave(&mut this.wait);
+                return Poll::Ready(data);
+            }
+            let ready = || !this.stack.stack.is_empty();
+            if !this
+                .stack
+                .poppers
+                .register(&mut this.wait, cx.waker(), ready)
+            {
+                return Poll::Pending;
+            }
+        }
+    }
+}
+
+impl<T: Debug> Drop for Pop<'_, T> {
+    fn drop(&mut self) {
+        self.stack.poppers.cancel(&mut self.wait);
+    }
+}
+
+pub struct Push<'a, T: Debug> {
+    stack: &'a AsyncStack<T>,
+    // Taken once it's pushed.
+    data: Option<T>,
+    wait: Wait,
+}
+
+// The data is never pinned.
+impl<T: Debug> Unpin for Push<'_, T> {}
+
+impl<T: Debug> Future for Push<'_, T> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = &mut *self;
+        let mut data = this.data.take().expect("polled after completion");
+        loop {
+            match this.stack.try_push(data) {
+                Ok(()) => {
+                    this.stack.pushers.leave(&mut this.wait);
+                    return Poll::Ready(());
+                }
+                Err(back) => data = back,
+            }
+            let ready = || !this.stack.is_full();
+            if !this
+                .stack
+                .pushers
+                .register(&mut this.wait, cx.waker(), ready)
+            {
+                this.data = Some(data);
+                return Poll::Pending;
+            }
+        }
+    }
+}
+
+impl<T: Debug> Drop for Push<'_, T> {
+    fn drop(&mut self) {
+        self.stack.pushers.cancel(&mut self.wait);
+    }
+}
+
+// The tasks waiting for the same thing, oldest first.
+#[derive(Default)]
+struct WaitList {
+    inner: Mutex<Waiters>,
+}
+
+#[derive(Default)]
+struct Waiters {
+    queue: VecDeque<(u64, Waker)>,
+    next_id: u64,
+}
+
+// Where a future stands in a wait list. A queued future that's not in the
+// list anymore was notified.
+#[derive(Default)]
+enum Wait {
+    #[default]
+    Idle,
+    Queued(u64),
+}
+
+impl WaitList {
+    // Queues the task of `waker` unless `ready` says it doesn't have to wait
+    // after all, in which case it returns true. `ready` is checked under the
+    // lock that notify_one takes, so a notification either comes before and
+    // is seen by `ready`, or after and wakes the task.
+    fn register(&self, wait: &mut Wait, waker: &Waker, ready: impl FnOnce() -> bool) -> bool {
+        let mut waiters = self.inner.lock().unwrap();
+        if ready() {
+            return true;
+        }
+        match *wait {
+            Wait::Queued(id) => match waiters.queue.iter_mut().find(|(i, _)| *i == id) {
+                Some((_, w)) => w.clone_from(waker),
+                // It was the first in line when it was notified, and stays
+                // there.
+                None => {
+                    let id = waiters.new_id();
+                    waiters.queue.push_front((id, waker.clone()));
+                    *wait = Wait::Queued(id);
+                }
+            },
+            Wait::Idle => {
+                let id = waiters.new_id();
+                waiters.queue.push_back((id, waker.clone()));
+                *wait = Wait::Queued(id);
+            }
+        }
+        false
+    }
+
+    fn notify_one(&self) {
+        let mut waiters = self.inner.lock().unwrap();
+        if let Some((_, waker)) = waiters.queue.pop_front() {
+            drop(waiters);
+            waker.wake();
+        }
+    }
+
+    // Leaves the list, for a future that completed.
+    fn leave(&self, wait: &mut Wait) {
+        if let Wait::Queued(id) = std::mem::take(wait) {
+            self.remove(id);
+        }
+    }
+
+    // Leaves the list, for a future that's dropped before it completed. A
+    // notification it got is passed on.
+    fn cancel(&self, wait: &mut Wait) {
+        if let Wait::Queued(id) = std::mem::take(wait) {
+            if !self.remove(id) {
+                self.notify_one();
+            }
+        }
+    }
+
+    // Returns false if the waiter wasn't in the list anymore.
+    fn remove(&self, id: u64) -> bool {
+        let mut waiters = self.inner.lock().unwrap();
+        match waiters.queue.iter().position(|(i, _)| *i == id) {
+            Some(pos) => waiters.queue.remove(pos).is_some(),
+            None => false,
+        }
+    }
+}
+
+impl Waiters {
+    fn new_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+    use std::task::Wake;
+    use std::time::Duration;
+
+    // Remembers being woken.
+    #[derive(Default)]
+    struct Flag(AtomicBool);
+
+    impl Wake for Flag {
+        fn wake(self: Arc<Self>) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    impl Flag {
+        fn take(&self) -> bool {
+            self.0.swap(false, Ordering::SeqCst)
+        }
+    }
+
+    fn poll<F: Future + Unpin>(fut: &mut F, flag: &Arc<Flag>) -> Poll<F::Output> {
+        let waker = Waker::from(Arc::clone(flag));
+        Pin::new(fut).poll(&mut Context::from_waker(&waker))
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn pop_waits_for_push() {
+        let stack = Arc::new(AsyncStack::new());
+        let poppers: Vec<_> = (0..4)
+            .map(|_| {
+                let stack = Arc::clone(&stack);
+                tokio::spawn(async move { stack.pop().await })
+            })
+            .collect();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        for i in 0..4 {
+            stack.push(i).await;
+        }
+        let mut popped = vec![];
+        for h in poppers {
+            popped.push(h.await.unwrap());
+        }
+        popped.sort();
+        assert_eq!(popped, [0, 1, 2, 3]);
+        assert!(stack.is_empty());
+    }
+
+    #[test]
+    fn poppers_are_woken_in_the_order_they_waited() {
+        let stack = AsyncStack::new();
+        let flags: Vec<Arc<Flag>> = (0..3).map(|_| Arc::default()).collect();
+        let mut pops: Vec<_> = (0..3).map(|_| stack.pop()).collect();
+        for (pop, flag) in pops.iter_mut().zip(&flags) {
+            assert!(poll(pop, flag).is_pending());
+        }
+
+        stack.try_push(1).unwrap();
+        let woken: Vec<_> = flags.iter().map(|f| f.take()).collect();
+        assert_eq!(woken, [true, false, false]);
+
+        // Someone else takes the element, so the woken popper waits again,
+        // but stays first in line.
+        assert_eq!(stack.try_pop(), Some(1));
+        assert!(poll(&mut pops[0], &flags[0]).is_pending());
+        stack.try_push(2).unwrap();
+        assert!(flags[0].take());
+        assert!(!flags[1].take());
+        assert_eq!(poll(&mut pops[0], &flags[0]), Poll::Ready(2));
+
+        stack.try_push(3).unwrap();
+        assert!(flags[1].take());
+        assert_eq!(poll(&mut pops[1], &flags[1]), Poll::Ready(3));
+        assert!(!flags[2].take());
+    }
+
+    #[test]
+    fn dropped_poppers_pass_their_wakeup_on() {
+        let stack = AsyncStack::new();
+        let (a, b) = (Arc::default(), Arc::default());
+        let mut pop_a = stack.pop();
+        let mut pop_b = stack.pop();
+        assert!(poll(&mut pop_a, &a).is_pending());
+        assert!(poll(&mut pop_b, &b).is_pending());
+
+        stack.try_push(1).unwrap();
+        assert!(a.take());
+        drop(pop_a);
+        assert!(b.take());
+        assert_eq!(poll(&mut pop_b, &b), Poll::Ready(1));
+
+        // A popper that's dropped before it's woken just leaves.
+        let mut pop_c = stack.pop();
+        assert!(poll(&mut pop_c, &a).is_pending());
+        drop(pop_c);
+        stack.try_push(2).unwrap();
+        assert!(!a.take());
+        assert_eq!(stack.try_pop(), Some(2));
+    }
+
+    #[tokio::test]
+    async fn pop_with_timeout() {
+        let stack = AsyncStack::new();
+        let result = tokio::time::timeout(Duration::from_millis(10), stack.pop()).await;
+        assert!(result.is_err());
+
+        stack.push(1).await;
+        assert_eq!(stack.pop().await, 1);
+        assert!(stack.poppers.inner.lock().unwrap().queue.is_empty());
+    }
+
+    #[test]
+    fn push_waits_for_room() {
+        let stack = AsyncStack::with_capacity(2);
+        assert_eq!(stack.capacity(), Some(2));
+        stack.try_push(1).unwrap();
+        stack.try_push(2).unwrap();
+        assert_eq!(stack.try_push(3), Err(3));
+
+        let (a, b) = (Arc::default(), Arc::default());
+        let mut push_a = stack.push(3);
+        let mut push_b = stack.push(4);
+        assert!(poll(&mut push_a, &a).is_pending());
+        assert!(poll(&mut push_b, &b).is_pending());
+
+        assert_eq!(stack.try_pop(), Some(2));
+        assert!(a.take());
+        assert!(!b.take());
+        assert_eq!(poll(&mut push_a, &a), Poll::Ready(()));
+        assert_eq!(stack.len(), 2);
+
+        // Dropping the woken pusher drops its element, and wakes the next.
+        let c = Arc::default();
+        let mut push_c = stack.push(5);
+        assert!(poll(&mut push_c, &c).is_pending());
+        assert_eq!(stack.try_pop(), Some(3));
+        assert!(b.take());
+        assert!(!c.take());
+        drop(push_b);
+        assert!(c.take());
+        assert_eq!(poll(&mut push_c, &c), Poll::Ready(()));
+        assert_eq!(stack.try_pop(), Some(5));
+        assert_eq!(stack.try_pop(), Some(1));
+        assert_eq!(stack.try_pop(), None);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn bounded_producers_and_consumers() {
+        const COUNT: u64 = 10_000;
+        let stack = Arc::new(AsyncStack::with_capacity(4));
+
+        let producers: Vec<_> = (0..4)
+            .map(|p| {
+                let stack = Arc::clone(&stack);
+                tokio::spawn(async move {
+                    for i in 0..COUNT {
+                        stack.push(p * COUNT + i).await;
+                        assert!(stack.len() <= 4);
+                    }
+                })
+            })
+            .collect();
+        let consumers: Vec<_> = (0..4)
+            .map(|_| {
+                let stack = Arc::clone(&stack);
+                tokio::spawn(async move {
+                    let mut sum = 0;
+                    for _ in 0..COUNT {
+                        sum += stack.pop().await;
+                    }
+                    sum
+                })
+            })
+            .collect();
+
+        for h in producers {
+            h.await.unwrap();
+        }
+        let mut sum = 0;
+        for h in consumers {
+            sum += h.await.unwrap();
+        }
+        assert_eq!(sum, (0..4 * COUNT).sum::<u64>());
+        assert!(stack.is_empty());
+    }
+}
@@ -0,0 +1,95 @@
+// A Stack whose pop can wait for a push, parking the thread instead of
+// spinning. Only available with std, which the parking lot needs.
+use core::fmt::Debug;
+use std::time::{Duration, Instant};
+
+use parking_lot::{Notifier, ParkResult};
+
+use crate::Stack;
+
+pub struct BlockingStack<T: Debug> {
+    stack: Stack<T>,
+    notifier: Notifier,
+}
+
+impl<T: Debug> BlockingStack<T> {
+    pub fn new() -> Self {
+        Self {
+            stack: Stack::new(),
+            notifier: Notifier::new(),
+        }
+    }
+
+    pub fn push(&self, data: T) {
+        self.stack.push(data);
+        self.notifier.notify_one();
+    }
+
+    pub fn try_pop(&self) -> Option<T> {
+        self.stack.pop()
+    }
+
+    /// Blocks until there's an element to pop.
+    pub fn pop(&self) -> T {
+        loop {
+            if let Some(data) = self.stack.pop() {
+                return data;
+            }
+            self.notifier.wait(|| self.stack.is_empty(), None);
+        }
+    }
+
+    /// Like `pop`, but gives up after `timeout`.
+    pub fn pop_timeout(&self, timeout: Duration) -> Option<T> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(data) = self.stack.pop() {
+                return Some(data);
+            }
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            let result = self.notifier.wait(|| self.stack.is_empty(), Some(timeout));
+            if result == ParkResult::TimedOut {
+                return self.stack.pop();
+            }
+        }
+    }
+}
+
+impl<T: Debug> Default for BlockingStack<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn pop_waits_for_push() {
+        let stack = BlockingStack::new();
+
+        thread::scope(|s| {
+            let poppers: Vec<_> = (0..4).map(|_| s.spawn(|| stack.pop())).collect();
+            thread::sleep(Duration::from_millis(20));
+
+            for i in 0..4 {
+                stack.push(i);
+            }
+            let mut popped: Vec<_> = poppers.into_iter().map(|h| h.join().unwrap()).collect();
+            popped.sort();
+            assert_eq!(popped, [0, 1, 2, 3]);
+        });
+    }
+
+    #[test]
+    fn pop_timeout() {
+        let stack = BlockingStack::new();
+        assert_eq!(stack.pop_timeout(Duration::from_millis(10)), None);
+
+        stack.push(1);
+        assert_eq!(stack.pop_timeout(Duration::from_millis(10)), Some(1));
+        assert_eq!(stack.try_pop(), None);
+    }
+}
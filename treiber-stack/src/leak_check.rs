@@ -0,0 +1,87 @@
+//! Counts the nodes of a Stack that haven't been freed yet, so tests can
+//! check that every node is eventually reclaimed, whether it was popped or
+//! dropped with the stack.
+//!
+//! Every node holds a `Counted`, which increments the stack's counter when
+//! the node is allocated and decrements it when the node is freed. The
+//! counter is shared with the nodes instead of living in the stack itself,
+//! because popped nodes are only freed once crossbeam-epoch runs their
+//! deferred destruction, which can be long after the stack is gone.
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crossbeam_epoch as epoch;
+
+// How often `leaked_nodes` flushes the collector before giving up. Deferred
+// destructions only run two epochs after they were deferred, and an epoch
+// can only advance when every pinned thread has caught up with it.
+const FLUSHES: usize = 256;
+
+pub(crate) struct Counted {
+    live: Arc<AtomicUsize>,
+}
+
+impl Counted {
+    pub(crate) fn new(live: &Arc<AtomicUsize>) -> Self {
+        live.fetch_add(1, Ordering::Relaxed);
+        Self {
+            live: Arc::clone(live),
+        }
+    }
+}
+
+impl Drop for Counted {
+    fn drop(&mut self) {
+        self.live.fetch_sub(1, Ordering::Release);
+    }
+}
+
+/// Returned by `Stack::leak_check`. It stays usable after the stack is
+/// dropped.
+pub struct LeakCheck {
+    live: Arc<AtomicUsize>,
+    #[cfg(not(feature = "std"))]
+    collector: epoch::Collector,
+}
+
+impl LeakCheck {
+    pub(crate) fn new(
+        live: &Arc<AtomicUsize>,
+        #[cfg(not(feature = "std"))] collector: &epoch::Collector,
+    ) -> Self {
+        Self {
+            live: Arc::clone(live),
+            #[cfg(not(feature = "std"))]
+            collector: collector.clone(),
+        }
+    }
+
+    /// Nodes that were allocated but not freed yet, including popped nodes
+    /// whose destruction is still deferred.
+    pub fn live_nodes(&self) -> usize {
+        self.live.load(Ordering::Acquire)
+    }
+
+    /// Like `live_nodes`, but first gives the deferred destructions a chance
+    /// to run by flushing the collector. Once every thread that used the
+    /// stack is done with it, anything left is a leak.
+    pub fn leaked_nodes(&self) -> usize {
+        for _ in 0..FLUSHES {
+            if self.live_nodes() == 0 {
+                break;
+            }
+            self.pin().flush();
+        }
+        self.live_nodes()
+    }
+
+    #[cfg(feature = "std")]
+    fn pin(&self) -> epoch::Guard {
+        epoch::pin()
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn pin(&self) -> epoch::Guard {
+        self.collector.register().pin()
+    }
+}
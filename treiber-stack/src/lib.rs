@@ -1,13 +1,43 @@
-use std::fmt::Debug;
-use std::mem::{self, ManuallyDrop};
-use std::ptr;
-use std::sync::atomic::Ordering;
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 
-use crossbeam_epoch::{self as epoch, Atomic};
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::fmt::Debug;
+use core::mem::{self, ManuallyDrop};
+use core::ptr;
+use core::sync::atomic::Ordering;
+
+use crossbeam_epoch::{self as epoch, Atomic, Guard};
 use epoch::Owned;
 
+#[cfg(feature = "std")]
+pub use async_stack::{AsyncStack, Pop, Push};
+#[cfg(feature = "std")]
+pub use blocking::BlockingStack;
+#[cfg(feature = "leak-check")]
+pub use leak_check::LeakCheck;
+#[cfg(target_has_atomic = "64")]
+pub use stamped::StampedStack;
+
+#[cfg(feature = "std")]
+mod async_stack;
+#[cfg(feature = "std")]
+mod blocking;
+#[cfg(feature = "leak-check")]
+mod leak_check;
+#[cfg(target_has_atomic = "64")]
+mod stamped;
+
 pub struct Stack<T: Debug> {
     head: Atomic<Node<T>>,
+    // Without std there's neither crossbeam's global collector nor thread
+    // locals to cache a handle in, so every stack owns its collector.
+    #[cfg(not(feature = "std"))]
+    collector: epoch::Collector,
+    // Shared with every node, see leak_check.
+    #[cfg(feature = "leak-check")]
+    live: alloc::sync::Arc<core::sync::atomic::AtomicUsize>,
 }
 
 // TODO: should T be Send as well?
@@ -16,8 +46,9 @@ unsafe impl<T: Debug> Sync for Stack<T> {}
 
 impl<T: Debug> Drop for Stack<T> {
     fn drop(&mut self) {
+        #[cfg(feature = "std")]
         println!("inside drop");
-        let guard = &epoch::pin();
+        let guard = &self.pin();
 
         let mut current = mem::replace(&mut self.head, Atomic::null());
         unsafe {
@@ -28,6 +59,7 @@ impl<T: Debug> Drop for Stack<T> {
                 // drop(ManuallyDrop::into_inner(data));
 
                 let node = node.into_box();
+                #[cfg(feature = "std")]
                 println!("dropping {:?}", node.data);
                 drop(ManuallyDrop::into_inner(node.data));
 
@@ -46,29 +78,63 @@ struct Node<T> {
     // use ManuallyDrop, it will result in double-free error.
     data: ManuallyDrop<T>,
     prev: Atomic<Node<T>>,
-}
-
-impl<T> Node<T> {
-    fn new(data: T, prev: Atomic<Node<T>>) -> Self {
-        Self {
-            data: ManuallyDrop::new(data),
-            prev,
-        }
-    }
+    #[cfg(feature = "leak-check")]
+    _counted: leak_check::Counted,
 }
 
 impl<T: Debug> Stack<T> {
     pub fn new() -> Self {
         Self {
             head: Atomic::null(),
+            #[cfg(not(feature = "std"))]
+            collector: epoch::Collector::new(),
+            #[cfg(feature = "leak-check")]
+            live: Default::default(),
         }
     }
 
+    /// Returns a handle that tells how many nodes of this stack haven't been
+    /// freed yet, for tests that check for leaks.
+    #[cfg(feature = "leak-check")]
+    pub fn leak_check(&self) -> LeakCheck {
+        LeakCheck::new(
+            &self.live,
+            #[cfg(not(feature = "std"))]
+            &self.collector,
+        )
+    }
+
+    fn new_node(&self, data: T) -> Node<T> {
+        Node {
+            data: ManuallyDrop::new(data),
+            prev: Atomic::null(),
+            #[cfg(feature = "leak-check")]
+            _counted: leak_check::Counted::new(&self.live),
+        }
+    }
+
+    #[cfg(feature = "std")]
+    fn pin(&self) -> Guard {
+        epoch::pin()
+    }
+
+    // Registering on every call is slower than the cached thread-local handle
+    // that `epoch::pin` uses, but it's the only option without std. The guard
+    // keeps the participant alive even after the handle is dropped.
+    #[cfg(not(feature = "std"))]
+    fn pin(&self) -> Guard {
+        self.collector.register().pin()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        let guard = &self.pin();
+        self.head.load(Ordering::Acquire, guard).is_null()
+    }
+
     pub fn push(&self, data: T) {
-        let node = Node::new(data, Atomic::null());
-        let mut node = Owned::new(node);
+        let mut node = Owned::new(self.new_node(data));
 
-        let guard = epoch::pin();
+        let guard = self.pin();
 
         loop {
             let old_head = self.head.load(Ordering::Acquire, &guard);
@@ -96,7 +162,7 @@ impl<T: Debug> Stack<T> {
     }
 
     pub fn pop(&self) -> Option<T> {
-        let guard = &epoch::pin();
+        let guard = &self.pin();
 
         loop {
             let old_head = self.head.load(Ordering::Acquire, guard);
@@ -135,4 +201,172 @@ impl<T: Debug> Stack<T> {
             }
         }
     }
+
+    /// Pops up to `n` elements with a single CAS on head, returned in the
+    /// order `pop` would have returned them (most recently pushed first).
+    pub fn pop_n(&self, n: usize) -> Vec<T> {
+        if n == 0 {
+            return Vec::new();
+        }
+
+        let guard = &self.pin();
+
+        loop {
+            let old_head = self.head.load(Ordering::Acquire, guard);
+
+            // Walk down at most n nodes. Because we're pinned, none of them can
+            // be freed while we're looking at them. And since a node's prev is
+            // never changed after it's pushed, the chain below old_head is the
+            // same one we'll detach if the CAS below succeeds.
+            let mut new_head = old_head;
+            let mut count = 0;
+            while count < n {
+                match unsafe { new_head.as_ref() } {
+                    None => break,
+                    Some(node) => {
+                        new_head = node.prev.load(Ordering::Acquire, guard);
+                        count += 1;
+                    }
+                }
+            }
+
+            if count == 0 {
+                return Vec::new();
+            }
+
+            let result = self.head.compare_exchange(
+                old_head,
+                new_head,
+                Ordering::Release,
+                Ordering::Relaxed,
+                guard,
+            );
+            if result.is_err() {
+                continue;
+            }
+
+            // The detached nodes are now only reachable by us (and by threads
+            // that loaded them before our CAS, which will fail their own CAS).
+            let mut popped = Vec::with_capacity(count);
+            let mut current = old_head;
+            while current != new_head {
+                unsafe {
+                    let node = current.deref();
+                    let next = node.prev.load(Ordering::Relaxed, guard);
+                    popped.push(ManuallyDrop::into_inner(ptr::read(&node.data)));
+                    guard.defer_destroy(current);
+                    current = next;
+                }
+            }
+            return popped;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn push_pop_last_in_first_out() {
+        let stack = Stack::new();
+        assert_eq!(stack.pop(), None);
+
+        stack.push(1);
+        stack.push(2);
+        assert_eq!(stack.pop(), Some(2));
+        stack.push(3);
+        assert_eq!(stack.pop(), Some(3));
+        assert_eq!(stack.pop(), Some(1));
+        assert_eq!(stack.pop(), None);
+    }
+
+    #[test]
+    fn pop_n_returns_lifo_batches() {
+        let stack = Stack::new();
+        assert!(stack.pop_n(3).is_empty());
+
+        for i in 0..5 {
+            stack.push(i);
+        }
+        assert!(stack.pop_n(0).is_empty());
+        assert_eq!(stack.pop_n(2), vec![4, 3]);
+        assert_eq!(stack.pop_n(10), vec![2, 1, 0]);
+        assert_eq!(stack.pop(), None);
+    }
+
+    #[test]
+    fn pop_n_many_concurrent() {
+        let stack = Stack::new();
+
+        let popped: Vec<usize> = thread::scope(|s| {
+            for t in 0..4 {
+                let stack = &stack;
+                s.spawn(move || {
+                    for i in 0..10_000 {
+                        stack.push(t * 10_000 + i);
+                    }
+                });
+            }
+
+            let handles: Vec<_> = (1..=4)
+                .map(|n| {
+                    let stack = &stack;
+                    s.spawn(move || {
+                        let mut popped = vec![];
+                        for _ in 0..5_000 {
+                            popped.extend(stack.pop_n(n));
+                        }
+                        popped
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|h| h.join().unwrap())
+                .collect()
+        });
+
+        let mut all: Vec<usize> = popped.into_iter().chain(stack.pop_n(usize::MAX)).collect();
+        all.sort();
+        assert_eq!(all, (0..40_000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn drop_releases_remaining_elements() {
+        let stack = Stack::new();
+        for i in 0..100 {
+            stack.push(format!("elem-{}", i));
+        }
+        assert_eq!(stack.pop(), Some("elem-99".to_owned()));
+        drop(stack);
+    }
+
+    #[test]
+    fn push_pop_many_concurrent() {
+        let stack = Stack::new();
+
+        let popped: usize = thread::scope(|s| {
+            for t in 0..4 {
+                let stack = &stack;
+                s.spawn(move || {
+                    for i in 0..10_000 {
+                        stack.push(t * 10_000 + i);
+                    }
+                });
+            }
+
+            let handles: Vec<_> = (0..4)
+                .map(|_| s.spawn(|| (0..10_000).filter(|_| stack.pop().is_some()).count()))
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).sum()
+        });
+
+        let mut remaining = 0;
+        while stack.pop().is_some() {
+            remaining += 1;
+        }
+        assert_eq!(popped + remaining, 40_000);
+    }
 }
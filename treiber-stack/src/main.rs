@@ -1,61 +1,91 @@
 use std::sync::Arc;
 use std::thread;
+use std::time::Instant;
 
-use crossbeam_channel;
+use bench_report::{Format, Recorder, Report};
 
-use treiber_stack::Stack;
+use treiber_stack::{Stack, StampedStack};
 
+const THREAD_PAIRS: usize = 3;
+const OPS_PER_THREAD: usize = 100;
+
+// Usage: treiber-stack [text|csv|json] [epoch|stamped]
 fn main() {
-    let stack = Arc::new(Stack::<String>::new());
+    let mut args = std::env::args().skip(1);
+    let format = match args.next() {
+        None => Format::Text,
+        Some(f) => f.parse().unwrap_or_else(|e| panic!("{}", e)),
+    };
+
+    let report = match args.next().as_deref() {
+        None | Some("epoch") => run(
+            "treiber-stack",
+            Stack::<String>::new(),
+            |stack, data| stack.push(data),
+            |stack| stack.pop(),
+        ),
+        Some("stamped") => run(
+            "treiber-stack (stamped)",
+            // Enough slots for every push, so none of them fail.
+            StampedStack::<String>::with_capacity(THREAD_PAIRS * OPS_PER_THREAD),
+            |stack, data| stack.push(data).unwrap(),
+            |stack| stack.pop(),
+        ),
+        Some(other) => panic!("unknown stack implementation: {}", other),
+    };
+    print!("{}", report.render(format));
+}
+
+// Runs pushers and poppers concurrently against `stack`, push and pop adapt
+// the different stack APIs.
+fn run<S: Send + Sync + 'static>(
+    title: &str,
+    stack: S,
+    push: fn(&S, String),
+    pop: fn(&S) -> Option<String>,
+) -> Report {
+    let stack = Arc::new(stack);
     let (start_tx, start_rx) = crossbeam_channel::unbounded::<()>();
 
-    let mut push_handles = vec![];
-    let mut pop_handles = vec![];
-    for _ in 0..3 {
+    let mut handles = vec![];
+    for _ in 0..THREAD_PAIRS {
         let pusher_start_rx = start_rx.clone();
         let pusher_stack = stack.clone();
         let h = thread::spawn(move || {
             let _ = pusher_start_rx.recv();
             let id = thread::current().id();
-            for j in 0..100 {
-                pusher_stack.push(format!("pusher-{:?}-{}", id, j));
+            let mut recorder = Recorder::new(format!("pusher-{:?}", id));
+            for j in 0..OPS_PER_THREAD {
+                let data = format!("pusher-{:?}-{}", id, j);
+                recorder.time("push", || push(&pusher_stack, data));
             }
+            recorder
         });
-        push_handles.push(h);
+        handles.push(h);
 
         let popper_start_rx = start_rx.clone();
         let popper_stack = stack.clone();
         let h = thread::spawn(move || {
-            let mut stolen = vec![];
             let _ = popper_start_rx.recv();
             let id = thread::current().id();
-            for j in 0..100 {
-                if let Some(popped) = popper_stack.pop() {
-                    stolen.push(format!("popper-{:?} iteration {} => {:?}", id, j, popped));
-                } else {
-                    println!("popper-{:?} popped None", id);
-                }
+            let mut recorder = Recorder::new(format!("popper-{:?}", id));
+            for _ in 0..OPS_PER_THREAD {
+                // Pops that find the stack empty are recorded separately, as
+                // they're much cheaper than successful ones.
+                let start = Instant::now();
+                let popped = pop(&popper_stack);
+                let op = if popped.is_some() { "pop" } else { "pop_none" };
+                recorder.record(op, start.elapsed());
             }
-            stolen
+            recorder
         });
-        pop_handles.push(h);
+        handles.push(h);
     }
 
     // Signal the start to other threads.
+    let start = Instant::now();
     drop(start_tx);
 
-    let mut results = vec![];
-    for h in pop_handles {
-        results.push(h.join().unwrap());
-    }
-
-    for r in results {
-        for s in r {
-            println!("{s}");
-        }
-    }
-
-    for h in push_handles {
-        h.join().unwrap();
-    }
+    let recorders = handles.into_iter().map(|h| h.join().unwrap()).collect();
+    Report::new(title, start.elapsed(), recorders)
 }
@@ -0,0 +1,276 @@
+//! A Treiber stack that doesn't need a garbage collector.
+//!
+//! Nodes live in a fixed pool of slots allocated up front, and a slot is
+//! never freed while the stack is alive. Popped slots go to a freelist (which
+//! is itself a stamped stack) and get reused by later pushes. Since the memory
+//! stays valid, reading `next` from a slot that was popped concurrently is
+//! harmless: it's at worst a stale value.
+//!
+//! What's left is the ABA problem: a pop can read head A and its next B, then
+//! another thread pops A and B and pushes A again. Head is A once more, but
+//! the next it read is outdated. To catch that, head packs the slot index
+//! together with a version that's incremented on every change, so the CAS
+//! fails if anything happened in between. The version is 32 bits, so it'd
+//! take 2^32 changes in the window between the load and the CAS to fool it.
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+const NIL: u32 = u32::MAX;
+
+pub struct StampedStack<T> {
+    head: AtomicU64,
+    // Unused slots, linked through the same `next` fields as the stack. A
+    // slot is always in exactly one of the two lists, or owned by the thread
+    // that's in the middle of pushing or popping it.
+    free: AtomicU64,
+    slots: Box<[Slot<T>]>,
+}
+
+struct Slot<T> {
+    next: AtomicU32,
+    data: UnsafeCell<MaybeUninit<T>>,
+}
+
+unsafe impl<T: Send> Send for StampedStack<T> {}
+unsafe impl<T: Send> Sync for StampedStack<T> {}
+
+fn pack(index: u32, version: u32) -> u64 {
+    ((version as u64) << 32) | index as u64
+}
+
+fn unpack(stamped: u64) -> (u32, u32) {
+    (stamped as u32, (stamped >> 32) as u32)
+}
+
+impl<T> StampedStack<T> {
+    /// Creates a stack that can hold up to `capacity` elements.
+    ///
+    /// # Panics
+    ///
+    /// If `capacity` doesn't fit in the 32 bit slot indices.
+    pub fn with_capacity(capacity: usize) -> Self {
+        assert!(capacity < NIL as usize, "capacity is too large");
+
+        // Initially, every slot is on the freelist in order.
+        let slots: Vec<Slot<T>> = (0..capacity)
+            .map(|i| Slot {
+                next: AtomicU32::new(if i + 1 < capacity { i as u32 + 1 } else { NIL }),
+                data: UnsafeCell::new(MaybeUninit::uninit()),
+            })
+            .collect();
+        let first_free = if capacity > 0 { 0 } else { NIL };
+
+        Self {
+            head: AtomicU64::new(pack(NIL, 0)),
+            free: AtomicU64::new(pack(first_free, 0)),
+            slots: slots.into_boxed_slice(),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        unpack(self.head.load(Ordering::Acquire)).0 == NIL
+    }
+
+    /// Pushes `data` on the stack, or gives it back if the stack is full.
+    pub fn push(&self, data: T) -> Result<(), T> {
+        let index = match self.pop_index(&self.free) {
+            None => return Err(data),
+            Some(index) => index,
+        };
+
+        // SAFETY: the slot was just taken off the freelist, so no one else
+        // will touch its data until it's pushed on the stack.
+        unsafe { (*self.slot(index).data.get()).write(data) };
+        self.push_index(&self.head, index);
+        Ok(())
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        let index = self.pop_index(&self.head)?;
+
+        // SAFETY: winning the CAS in pop_index gives us sole ownership of the
+        // slot, and the Acquire synchronizes with the push that wrote it.
+        let data = unsafe { (*self.slot(index).data.get()).assume_init_read() };
+        self.push_index(&self.free, index);
+        Some(data)
+    }
+
+    fn slot(&self, index: u32) -> &Slot<T> {
+        &self.slots[index as usize]
+    }
+
+    fn push_index(&self, list: &AtomicU64, index: u32) {
+        let mut cur = list.load(Ordering::Relaxed);
+        loop {
+            let (head, version) = unpack(cur);
+            self.slot(index).next.store(head, Ordering::Relaxed);
+
+            // Release publishes both the data and next of the slot.
+            match list.compare_exchange_weak(
+                cur,
+                pack(index, version.wrapping_add(1)),
+                Ordering::Release,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return,
+                Err(actual) => cur = actual,
+            }
+        }
+    }
+
+    fn pop_index(&self, list: &AtomicU64) -> Option<u32> {
+        let mut cur = list.load(Ordering::Acquire);
+        loop {
+            let (head, version) = unpack(cur);
+            if head == NIL {
+                return None;
+            }
+
+            // If the slot was popped (and maybe pushed again) since we loaded
+            // cur, this can be garbage. But then version has changed as well
+            // and the CAS below fails.
+            let next = self.slot(head).next.load(Ordering::Relaxed);
+            match list.compare_exchange_weak(
+                cur,
+                pack(next, version.wrapping_add(1)),
+                Ordering::Acquire,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => return Some(head),
+                Err(actual) => cur = actual,
+            }
+        }
+    }
+}
+
+impl<T> Drop for StampedStack<T> {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn last_in_first_out() {
+        let stack = StampedStack::with_capacity(3);
+        assert!(stack.is_empty());
+
+        stack.push(1).unwrap();
+        stack.push(2).unwrap();
+        stack.push(3).unwrap();
+        assert!(!stack.is_empty());
+
+        assert_eq!(stack.pop(), Some(3));
+        assert_eq!(stack.pop(), Some(2));
+        assert_eq!(stack.pop(), Some(1));
+        assert_eq!(stack.pop(), None);
+        assert!(stack.is_empty());
+    }
+
+    #[test]
+    fn push_to_full_stack_gives_data_back() {
+        let stack = StampedStack::with_capacity(1);
+        stack.push("a").unwrap();
+        assert_eq!(stack.push("b"), Err("b"));
+
+        // Popping frees the slot for the next push.
+        assert_eq!(stack.pop(), Some("a"));
+        stack.push("b").unwrap();
+        assert_eq!(stack.pop(), Some("b"));
+    }
+
+    #[test]
+    fn zero_capacity() {
+        let stack = StampedStack::with_capacity(0);
+        assert_eq!(stack.push(1), Err(1));
+        assert_eq!(stack.pop(), None);
+    }
+
+    #[test]
+    fn drop_drops_remaining_elements() {
+        struct Counted(Arc<AtomicUsize>);
+        impl Drop for Counted {
+            fn drop(&mut self) {
+                self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        let drops = Arc::new(AtomicUsize::new(0));
+
+        let stack = StampedStack::with_capacity(4);
+        for _ in 0..3 {
+            assert!(stack.push(Counted(drops.clone())).is_ok());
+        }
+        drop(stack.pop());
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+
+        drop(stack);
+        assert_eq!(drops.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn version_detects_aba() {
+        let stack = StampedStack::with_capacity(2);
+        stack.push('a').unwrap();
+        stack.push('b').unwrap();
+
+        // A pop that gets preempted right after loading head.
+        let stale = stack.head.load(Ordering::Acquire);
+
+        // Meanwhile, both are popped and two new elements are pushed, the
+        // second of which ends up in b's slot.
+        assert_eq!(stack.pop(), Some('b'));
+        assert_eq!(stack.pop(), Some('a'));
+        stack.push('c').unwrap();
+        stack.push('d').unwrap();
+
+        let cur = stack.head.load(Ordering::Acquire);
+        assert_eq!(unpack(stale).0, unpack(cur).0);
+        assert_ne!(stale, cur);
+    }
+
+    #[test]
+    fn concurrent_push_pop_conserves_elements() {
+        let stack = StampedStack::with_capacity(64);
+        let popped_sum = AtomicUsize::new(0);
+
+        thread::scope(|s| {
+            for t in 0..4 {
+                let stack = &stack;
+                let popped_sum = &popped_sum;
+                s.spawn(move || {
+                    for i in 0..10_000 {
+                        let mut val = t * 10_000 + i;
+                        // Spin while full, other threads are popping.
+                        while let Err(v) = stack.push(val) {
+                            val = v;
+                            if let Some(v) = stack.pop() {
+                                popped_sum.fetch_add(v, Ordering::Relaxed);
+                            }
+                        }
+                        if let Some(v) = stack.pop() {
+                            popped_sum.fetch_add(v, Ordering::Relaxed);
+                        }
+                    }
+                });
+            }
+        });
+
+        while let Some(v) = stack.pop() {
+            popped_sum.fetch_add(v, Ordering::Relaxed);
+        }
+        assert_eq!(popped_sum.into_inner(), (0..40_000).sum());
+    }
+}
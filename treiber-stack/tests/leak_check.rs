@@ -0,0 +1,75 @@
+//! Checks that every node of a Stack is freed, no matter whether it was
+//! popped, popped in a batch, or still on the stack when it was dropped.
+//!
+//! Needs the `leak-check` feature: `cargo test --features leak-check`.
+use std::sync::Barrier;
+use std::thread;
+
+use treiber_stack::Stack;
+
+const THREADS: usize = 4;
+const OPS_PER_THREAD: usize = 20_000;
+
+#[test]
+fn drop_frees_every_node() {
+    let stack = Stack::new();
+    let check = stack.leak_check();
+    for i in 0..100 {
+        stack.push(i);
+    }
+    assert_eq!(check.live_nodes(), 100);
+
+    // Dropping the stack frees its nodes right away, without deferring.
+    drop(stack);
+    assert_eq!(check.live_nodes(), 0);
+}
+
+#[test]
+fn popped_nodes_are_freed_after_concurrent_workload() {
+    let stack = Stack::new();
+    let check = stack.leak_check();
+    let barrier = Barrier::new(2 * THREADS);
+
+    thread::scope(|s| {
+        for t in 0..THREADS {
+            let (stack, barrier) = (&stack, &barrier);
+            s.spawn(move || {
+                barrier.wait();
+                for i in 0..OPS_PER_THREAD {
+                    stack.push(t * OPS_PER_THREAD + i);
+                }
+            });
+        }
+        for t in 0..THREADS {
+            let (stack, barrier) = (&stack, &barrier);
+            s.spawn(move || {
+                barrier.wait();
+                for _ in 0..OPS_PER_THREAD {
+                    if t % 2 == 0 {
+                        stack.pop();
+                    } else {
+                        stack.pop_n(t);
+                    }
+                }
+            });
+        }
+    });
+
+    // Whatever is left is still allocated, and so are popped nodes whose
+    // destruction is deferred.
+    let remaining = stack.pop_n(usize::MAX).len();
+    assert!(check.live_nodes() >= remaining);
+    drop(stack);
+    assert_eq!(check.leaked_nodes(), 0);
+}
+
+#[test]
+fn stacks_are_counted_separately() {
+    let (first, second) = (Stack::new(), Stack::new());
+    first.push("a");
+    second.push("b");
+    second.push("c");
+
+    assert_eq!(first.leak_check().live_nodes(), 1);
+    assert_eq!(second.leak_check().live_nodes(), 2);
+}
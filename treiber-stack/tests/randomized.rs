@@ -0,0 +1,136 @@
+//! Runs small concurrent workloads over thousands of randomized schedules.
+//!
+//! crossbeam-epoch uses std atomics and thread locals directly, so model
+//! checkers like shuttle or loom can't take over its scheduling. Instead,
+//! every thread gets a seeded RNG and randomly yields or spins between
+//! operations, which shakes out many different interleavings of the CAS loops.
+//! The seed of a failing schedule is part of the panic message.
+use std::collections::HashSet;
+use std::sync::Barrier;
+use std::thread;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use treiber_stack::Stack;
+
+const SCHEDULES: u64 = 2_000;
+const PUSHERS: u64 = 2;
+const POPPERS: u64 = 2;
+const OPS_PER_THREAD: u64 = 16;
+
+fn jitter(rng: &mut StdRng) {
+    match rng.gen_range(0..4) {
+        0 => thread::yield_now(),
+        1 => {
+            for _ in 0..rng.gen_range(0..64) {
+                std::hint::spin_loop();
+            }
+        }
+        _ => (),
+    }
+}
+
+// Every pushed element must be popped exactly once: either by a concurrent
+// popper or when draining the stack after all threads are done.
+fn run_schedule(seed: u64) {
+    let stack = Stack::new();
+    let barrier = Barrier::new((PUSHERS + POPPERS) as usize);
+
+    let popped: Vec<u64> = thread::scope(|s| {
+        for p in 0..PUSHERS {
+            let (stack, barrier) = (&stack, &barrier);
+            s.spawn(move || {
+                let mut rng = StdRng::seed_from_u64(seed * 100 + p);
+                barrier.wait();
+                for i in 0..OPS_PER_THREAD {
+                    jitter(&mut rng);
+                    stack.push(p * OPS_PER_THREAD + i);
+                }
+            });
+        }
+
+        let poppers: Vec<_> = (0..POPPERS)
+            .map(|p| {
+                let (stack, barrier) = (&stack, &barrier);
+                s.spawn(move || {
+                    let mut rng = StdRng::seed_from_u64(seed * 100 + PUSHERS + p);
+                    let mut popped = vec![];
+                    barrier.wait();
+                    for _ in 0..OPS_PER_THREAD {
+                        jitter(&mut rng);
+                        if let Some(v) = stack.pop() {
+                            popped.push(v);
+                        }
+                    }
+                    popped
+                })
+            })
+            .collect();
+
+        poppers
+            .into_iter()
+            .flat_map(|h| h.join().unwrap())
+            .collect()
+    });
+
+    let mut seen = HashSet::new();
+    for v in popped.into_iter().chain(std::iter::from_fn(|| stack.pop())) {
+        assert!(
+            v < PUSHERS * OPS_PER_THREAD,
+            "seed {}: phantom value {}",
+            seed,
+            v
+        );
+        assert!(seen.insert(v), "seed {}: {} popped twice", seed, v);
+    }
+    assert_eq!(
+        seen.len() as u64,
+        PUSHERS * OPS_PER_THREAD,
+        "seed {}: elements were lost",
+        seed
+    );
+}
+
+#[test]
+fn every_pushed_element_is_popped_exactly_once() {
+    for seed in 0..SCHEDULES {
+        run_schedule(seed);
+    }
+}
+
+// A single thread popping everything it pushed, while others interfere, must
+// still see its own pushes in LIFO order relative to each other.
+#[test]
+fn own_pushes_are_popped_in_lifo_order() {
+    for seed in 0..SCHEDULES / 4 {
+        let stack = Stack::new();
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                let mut rng = StdRng::seed_from_u64(seed);
+                for i in 0..OPS_PER_THREAD {
+                    jitter(&mut rng);
+                    stack.push(1_000 + i);
+                    jitter(&mut rng);
+                    stack.pop();
+                }
+            });
+
+            let mut rng = StdRng::seed_from_u64(seed + SCHEDULES);
+            for i in 0..OPS_PER_THREAD {
+                jitter(&mut rng);
+                stack.push(i);
+            }
+        });
+
+        // Whatever is left of the main thread's values must come out in
+        // descending order.
+        let mine: Vec<u64> = std::iter::from_fn(|| stack.pop())
+            .filter(|v| *v < 1_000)
+            .collect();
+        let mut sorted = mine.clone();
+        sorted.sort_by(|a, b| b.cmp(a));
+        assert_eq!(mine, sorted, "seed {}", seed);
+    }
+}
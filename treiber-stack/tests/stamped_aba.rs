@@ -0,0 +1,95 @@
+//! Stress tests aimed at the ABA problem of `StampedStack`.
+//!
+//! With only a handful of slots shared by many threads, the same slot index
+//! is at the top of the stack over and over again, so a pop that gets
+//! preempted between loading head and its CAS regularly finds the same index
+//! there again. Without the version stamp such a pop would install a stale
+//! next, which shows up as elements that are popped twice or lost.
+use std::collections::HashSet;
+use std::sync::Barrier;
+use std::thread;
+
+use treiber_stack::StampedStack;
+
+const THREADS: usize = 8;
+const OPS_PER_THREAD: usize = 50_000;
+
+#[test]
+fn every_element_is_popped_exactly_once() {
+    for capacity in [1, 2, 4] {
+        let stack = StampedStack::with_capacity(capacity);
+        let barrier = Barrier::new(THREADS);
+
+        let popped: Vec<usize> = thread::scope(|s| {
+            let handles: Vec<_> = (0..THREADS)
+                .map(|t| {
+                    let (stack, barrier) = (&stack, &barrier);
+                    s.spawn(move || {
+                        let mut popped = vec![];
+                        barrier.wait();
+                        for i in 0..OPS_PER_THREAD {
+                            let mut val = t * OPS_PER_THREAD + i;
+                            while let Err(v) = stack.push(val) {
+                                val = v;
+                                popped.extend(stack.pop());
+                            }
+                            popped.extend(stack.pop());
+                        }
+                        popped
+                    })
+                })
+                .collect();
+
+            handles
+                .into_iter()
+                .flat_map(|h| h.join().unwrap())
+                .collect()
+        });
+
+        let mut seen = HashSet::new();
+        for v in popped.into_iter().chain(std::iter::from_fn(|| stack.pop())) {
+            assert!(
+                seen.insert(v),
+                "{} was popped twice (capacity {})",
+                v,
+                capacity
+            );
+        }
+        assert_eq!(
+            seen.len(),
+            THREADS * OPS_PER_THREAD,
+            "elements were lost (capacity {})",
+            capacity
+        );
+    }
+}
+
+#[test]
+fn full_and_empty_are_respected_under_contention() {
+    let capacity = 3;
+    let stack = StampedStack::with_capacity(capacity);
+
+    thread::scope(|s| {
+        for _ in 0..THREADS {
+            s.spawn(|| {
+                // Pushes twice as often as it pops, so the stack is full
+                // most of the time.
+                for i in 0..OPS_PER_THREAD {
+                    if i % 3 == 0 {
+                        stack.pop();
+                    } else {
+                        let _ = stack.push(i);
+                    }
+                }
+            });
+        }
+    });
+
+    // Never more elements than slots, no matter how the pushes raced.
+    let remaining = std::iter::from_fn(|| stack.pop()).count();
+    assert!(remaining <= capacity);
+    for i in 0..capacity {
+        stack.push(i).unwrap();
+    }
+    assert_eq!(stack.push(capacity), Err(capacity));
+}